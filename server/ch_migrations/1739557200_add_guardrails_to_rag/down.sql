@@ -0,0 +1,5 @@
+ALTER TABLE rag_queries
+DROP COLUMN guardrail_actions;
+
+ALTER TABLE rag_queries
+DROP COLUMN guardrail_violations;
@@ -0,0 +1,2 @@
+ALTER TABLE rag_queries ADD COLUMN IF NOT EXISTS guardrail_actions Array(String) DEFAULT [];
+ALTER TABLE rag_queries ADD COLUMN IF NOT EXISTS guardrail_violations Array(String) DEFAULT [];
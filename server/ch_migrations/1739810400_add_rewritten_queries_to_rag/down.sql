@@ -0,0 +1,2 @@
+ALTER TABLE rag_queries
+DROP COLUMN rewritten_queries;
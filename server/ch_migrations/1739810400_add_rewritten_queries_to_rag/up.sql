@@ -0,0 +1 @@
+ALTER TABLE rag_queries ADD COLUMN IF NOT EXISTS rewritten_queries Array(String) DEFAULT [];
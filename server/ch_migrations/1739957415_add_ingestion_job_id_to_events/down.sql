@@ -0,0 +1,2 @@
+ALTER TABLE dataset_events
+DROP COLUMN ingestion_job_id;
@@ -0,0 +1 @@
+ALTER TABLE dataset_events ADD COLUMN IF NOT EXISTS ingestion_job_id UUID DEFAULT toUUID('00000000-0000-0000-0000-000000000000');
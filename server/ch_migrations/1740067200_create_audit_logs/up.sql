@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS audit_logs (
+    id UUID,
+    organization_id UUID,
+    dataset_id UUID,
+    actor_id UUID,
+    actor_email String,
+    api_key_id UUID,
+    action String,
+    target_type String,
+    target_id String,
+    before String,
+    after String,
+    source_ip String,
+    created_at DateTime
+) ENGINE = MergeTree()
+ORDER BY (organization_id, created_at, action, id)
+PARTITION BY
+    (toYYYYMM(created_at),
+    organization_id);
@@ -0,0 +1,6 @@
+ALTER TABLE search_queries
+DROP COLUMN IF EXISTS experiment_id,
+DROP COLUMN IF EXISTS experiment_variant;
+ALTER TABLE rag_queries
+DROP COLUMN IF EXISTS experiment_id,
+DROP COLUMN IF EXISTS experiment_variant;
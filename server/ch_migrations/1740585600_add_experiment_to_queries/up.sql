@@ -0,0 +1,4 @@
+ALTER TABLE search_queries ADD COLUMN IF NOT EXISTS experiment_id String DEFAULT '';
+ALTER TABLE search_queries ADD COLUMN IF NOT EXISTS experiment_variant String DEFAULT '';
+ALTER TABLE rag_queries ADD COLUMN IF NOT EXISTS experiment_id String DEFAULT '';
+ALTER TABLE rag_queries ADD COLUMN IF NOT EXISTS experiment_variant String DEFAULT '';
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE topics DROP COLUMN IF EXISTS rag_preset;
+
+DROP TABLE IF EXISTS rag_presets;
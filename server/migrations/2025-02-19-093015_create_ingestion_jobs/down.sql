@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS ingestion_job_batches;
+DROP TABLE IF EXISTS ingestion_jobs;
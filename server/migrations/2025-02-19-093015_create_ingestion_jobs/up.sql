@@ -0,0 +1,30 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS ingestion_jobs (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    dataset_id UUID NOT NULL,
+    source TEXT NOT NULL,
+    queuing_complete BOOLEAN NOT NULL DEFAULT false,
+    webhook_url TEXT,
+    webhook_sent_at TIMESTAMP,
+    webhook_attempts INTEGER NOT NULL DEFAULT 0,
+    webhook_next_attempt_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS ingestion_job_batches (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    ingestion_job_id UUID NOT NULL,
+    chunk_count INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued',
+    error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (ingestion_job_id) REFERENCES ingestion_jobs(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_ingestion_job_batches_ingestion_job_id ON ingestion_job_batches(ingestion_job_id);
+CREATE INDEX IF NOT EXISTS idx_ingestion_jobs_webhook_next_attempt_at ON ingestion_jobs(webhook_next_attempt_at) WHERE webhook_sent_at IS NULL;
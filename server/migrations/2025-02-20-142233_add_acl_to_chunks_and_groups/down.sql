@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE chunk_metadata DROP COLUMN acl;
+ALTER TABLE chunk_group DROP COLUMN acl;
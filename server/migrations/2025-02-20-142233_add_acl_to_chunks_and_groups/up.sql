@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE chunk_metadata ADD COLUMN acl TEXT[];
+ALTER TABLE chunk_group ADD COLUMN acl TEXT[];
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE organizations DROP COLUMN IF EXISTS trash_retention_days;
+
+DROP TABLE IF EXISTS trash;
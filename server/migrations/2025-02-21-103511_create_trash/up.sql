@@ -0,0 +1,22 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS trash (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id UUID NOT NULL,
+    dataset_id UUID NOT NULL,
+    item_type TEXT NOT NULL,
+    item_id UUID NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    tracking_id TEXT,
+    delete_chunks BOOLEAN NOT NULL DEFAULT false,
+    deleted_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    purge_at TIMESTAMP NOT NULL,
+
+    FOREIGN KEY (organization_id) REFERENCES organizations(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_trash_organization_id ON trash(organization_id);
+CREATE INDEX IF NOT EXISTS idx_trash_dataset_id ON trash(dataset_id);
+CREATE INDEX IF NOT EXISTS idx_trash_purge_at ON trash(purge_at);
+
+ALTER TABLE organizations ADD COLUMN IF NOT EXISTS trash_retention_days INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,35 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS dataset_webhooks (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    dataset_id UUID NOT NULL,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    event_types TEXT[] NOT NULL DEFAULT '{}',
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_dataset_webhooks_dataset_id ON dataset_webhooks(dataset_id);
+
+CREATE TABLE IF NOT EXISTS dataset_webhook_deliveries (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    webhook_id UUID NOT NULL,
+    dataset_id UUID NOT NULL,
+    event_type TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempt_number INTEGER NOT NULL DEFAULT 0,
+    response_status INTEGER,
+    error TEXT,
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (webhook_id) REFERENCES dataset_webhooks(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_dataset_webhook_deliveries_webhook_id ON dataset_webhook_deliveries(webhook_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_dataset_webhook_deliveries_pending ON dataset_webhook_deliveries(next_attempt_at) WHERE status = 'pending';
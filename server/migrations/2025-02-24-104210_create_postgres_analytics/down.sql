@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS analytics_audit_logs;
+DROP TABLE IF EXISTS analytics_worker_events;
+DROP TABLE IF EXISTS analytics_recommendations;
+DROP TABLE IF EXISTS analytics_events;
+DROP TABLE IF EXISTS analytics_rag_queries;
+DROP TABLE IF EXISTS analytics_search_queries;
@@ -0,0 +1,100 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS analytics_search_queries (
+    id UUID NOT NULL PRIMARY KEY,
+    dataset_id UUID NOT NULL,
+    search_type TEXT NOT NULL,
+    search_method TEXT,
+    query TEXT NOT NULL,
+    request_params JSONB NOT NULL DEFAULT '{}',
+    latency REAL NOT NULL DEFAULT 0,
+    top_score REAL NOT NULL DEFAULT 0,
+    results JSONB NOT NULL DEFAULT '[]',
+    user_id TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_analytics_search_queries_dataset_id ON analytics_search_queries(dataset_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_analytics_search_queries_created_at ON analytics_search_queries(created_at);
+
+CREATE TABLE IF NOT EXISTS analytics_rag_queries (
+    id UUID NOT NULL PRIMARY KEY,
+    dataset_id UUID NOT NULL,
+    rag_type TEXT NOT NULL,
+    search_id UUID NOT NULL,
+    user_message TEXT NOT NULL,
+    llm_response TEXT NOT NULL DEFAULT '',
+    top_score REAL NOT NULL DEFAULT 0,
+    user_id TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_analytics_rag_queries_dataset_id ON analytics_rag_queries(dataset_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_analytics_rag_queries_created_at ON analytics_rag_queries(created_at);
+
+CREATE TABLE IF NOT EXISTS analytics_events (
+    id UUID NOT NULL PRIMARY KEY,
+    dataset_id UUID NOT NULL,
+    event_type TEXT NOT NULL,
+    event_name TEXT NOT NULL,
+    request_id TEXT NOT NULL DEFAULT '',
+    request_type TEXT NOT NULL DEFAULT '',
+    items TEXT[] NOT NULL DEFAULT '{}',
+    metadata JSONB NOT NULL DEFAULT '{}',
+    user_id TEXT NOT NULL DEFAULT '',
+    is_conversion BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_analytics_events_dataset_id ON analytics_events(dataset_id, event_type);
+CREATE INDEX IF NOT EXISTS idx_analytics_events_request_id ON analytics_events(request_id);
+CREATE INDEX IF NOT EXISTS idx_analytics_events_created_at ON analytics_events(created_at);
+
+CREATE TABLE IF NOT EXISTS analytics_recommendations (
+    id UUID NOT NULL PRIMARY KEY,
+    dataset_id UUID NOT NULL,
+    recommendation_type TEXT NOT NULL,
+    positive_ids TEXT[] NOT NULL DEFAULT '{}',
+    negative_ids TEXT[] NOT NULL DEFAULT '{}',
+    positive_tracking_ids TEXT[] NOT NULL DEFAULT '{}',
+    negative_tracking_ids TEXT[] NOT NULL DEFAULT '{}',
+    request_params JSONB NOT NULL DEFAULT '{}',
+    results JSONB NOT NULL DEFAULT '[]',
+    top_score REAL NOT NULL DEFAULT 0,
+    user_id TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_analytics_recommendations_dataset_id ON analytics_recommendations(dataset_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_analytics_recommendations_created_at ON analytics_recommendations(created_at);
+
+CREATE TABLE IF NOT EXISTS analytics_worker_events (
+    id UUID NOT NULL PRIMARY KEY,
+    dataset_id UUID NOT NULL,
+    event_type TEXT NOT NULL,
+    event_data TEXT NOT NULL DEFAULT '',
+    ingestion_job_id UUID,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_analytics_worker_events_dataset_id ON analytics_worker_events(dataset_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_analytics_worker_events_ingestion_job_id ON analytics_worker_events(ingestion_job_id) WHERE ingestion_job_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_analytics_worker_events_created_at ON analytics_worker_events(created_at);
+
+CREATE TABLE IF NOT EXISTS analytics_audit_logs (
+    id UUID NOT NULL PRIMARY KEY,
+    organization_id UUID NOT NULL,
+    dataset_id UUID,
+    actor_id UUID NOT NULL,
+    actor_email TEXT NOT NULL,
+    api_key_id UUID,
+    action TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    before JSONB NOT NULL DEFAULT '{}',
+    after JSONB NOT NULL DEFAULT '{}',
+    source_ip TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_analytics_audit_logs_organization_id ON analytics_audit_logs(organization_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_analytics_audit_logs_created_at ON analytics_audit_logs(created_at);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS analytics_export_runs;
+DROP TABLE IF EXISTS analytics_export_configs;
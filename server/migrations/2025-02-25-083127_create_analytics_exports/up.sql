@@ -0,0 +1,38 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS analytics_export_configs (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    dataset_id UUID NOT NULL UNIQUE,
+    format TEXT NOT NULL DEFAULT 'jsonl',
+    event_types TEXT[] NOT NULL DEFAULT '{}',
+    interval_minutes INTEGER NOT NULL DEFAULT 60,
+    forward_url TEXT,
+    forward_sink TEXT NOT NULL DEFAULT 'http',
+    forward_auth_header TEXT,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    last_exported_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    next_export_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_analytics_export_configs_next_export_at ON analytics_export_configs(next_export_at) WHERE active;
+
+CREATE TABLE IF NOT EXISTS analytics_export_runs (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    config_id UUID NOT NULL,
+    dataset_id UUID NOT NULL,
+    status TEXT NOT NULL DEFAULT 'running',
+    window_start TIMESTAMP NOT NULL,
+    window_end TIMESTAMP NOT NULL,
+    files TEXT[] NOT NULL DEFAULT '{}',
+    row_count BIGINT NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (config_id) REFERENCES analytics_export_configs(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_analytics_export_runs_dataset_id ON analytics_export_runs(dataset_id, created_at);
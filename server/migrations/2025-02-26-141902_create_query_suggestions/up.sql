@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS query_suggestions (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    dataset_id UUID NOT NULL,
+    suggestion_type TEXT NOT NULL,
+    query TEXT NOT NULL,
+    suggestion TEXT NOT NULL DEFAULT '',
+    occurrences BIGINT NOT NULL DEFAULT 0,
+    status TEXT NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    UNIQUE (dataset_id, suggestion_type, query, suggestion)
+);
+
+CREATE INDEX IF NOT EXISTS idx_query_suggestions_dataset_id_status ON query_suggestions(dataset_id, status, occurrences DESC);
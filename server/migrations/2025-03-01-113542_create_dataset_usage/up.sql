@@ -0,0 +1,23 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS dataset_usage (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    dataset_id UUID NOT NULL,
+    api_key_id UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000',
+    usage_date DATE NOT NULL,
+    search_calls BIGINT NOT NULL DEFAULT 0,
+    rag_calls BIGINT NOT NULL DEFAULT 0,
+    prompt_tokens BIGINT NOT NULL DEFAULT 0,
+    completion_tokens BIGINT NOT NULL DEFAULT 0,
+    embedding_tokens BIGINT NOT NULL DEFAULT 0,
+    file_pages BIGINT NOT NULL DEFAULT 0,
+    storage_bytes BIGINT NOT NULL DEFAULT 0,
+    stripe_reported_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    UNIQUE (dataset_id, api_key_id, usage_date),
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_dataset_usage_usage_date ON dataset_usage(usage_date);
+CREATE INDEX IF NOT EXISTS idx_dataset_usage_unreported ON dataset_usage(usage_date) WHERE stripe_reported_at IS NULL;
@@ -0,0 +1,477 @@
+use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
+use signal_hook::consts::SIGTERM;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use trieve_server::{
+    data::models::{
+        self, AnalyticsExportConfig, AnalyticsForwardMessage, AnalyticsForwardSink, DeadLetterQueue,
+    },
+    errors::ServiceError,
+    establish_connection, get_env,
+    operators::{
+        analytics_export_operator::{
+            claim_due_analytics_exports_query, get_analytics_export_config_query,
+            get_analytics_forward_body, get_analytics_forward_retry_delay,
+            run_analytics_export_query, ANALYTICS_FORWARD_PROCESSING_QUEUE,
+            ANALYTICS_FORWARD_QUEUE, ANALYTICS_FORWARD_RETRY_SET, MAX_ANALYTICS_FORWARD_ATTEMPTS,
+        },
+        analytics_store_operator::{
+            AnalyticsBackend, ClickHouseAnalyticsStore, PostgresAnalyticsStore,
+        },
+        dataset_webhook_operator::{build_webhook_http_client, validate_webhook_url},
+        dead_letter_operator::add_dead_letter,
+        worker_metrics_operator::observe_worker_job,
+    },
+};
+
+fn main() {
+    dotenvy::dotenv().ok();
+    env_logger::builder()
+        .target(env_logger::Target::Stdout)
+        .filter_level(log::LevelFilter::Info)
+        .init();
+
+    let database_url = get_env!("DATABASE_URL", "DATABASE_URL is not set");
+
+    let mut config = ManagerConfig::default();
+    config.custom_setup = Box::new(establish_connection);
+
+    let mgr = AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new_with_config(
+        database_url,
+        config,
+    );
+
+    let pool = diesel_async::pooled_connection::deadpool::Pool::builder(mgr)
+        .max_size(3)
+        .build()
+        .expect("Failed to create diesel_async pool");
+
+    let web_pool = actix_web::web::Data::new(pool.clone());
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to create tokio runtime")
+        .block_on(async move {
+            let redis_url = get_env!("REDIS_URL", "REDIS_URL is not set");
+            let redis_connections: u32 = std::env::var("REDIS_CONNECTIONS")
+                .unwrap_or("2".to_string())
+                .parse()
+                .unwrap_or(2);
+
+            let redis_manager = bb8_redis::RedisConnectionManager::new(redis_url)
+                .expect("Failed to connect to redis");
+
+            let redis_pool = bb8_redis::bb8::Pool::builder()
+                .max_size(redis_connections)
+                .connection_timeout(std::time::Duration::from_secs(2))
+                .build(redis_manager)
+                .await
+                .expect("Failed to create redis pool");
+
+            let web_redis_pool = actix_web::web::Data::new(redis_pool);
+
+            let should_terminate = Arc::new(AtomicBool::new(false));
+            signal_hook::flag::register(SIGTERM, Arc::clone(&should_terminate))
+                .expect("Failed to register shutdown hook");
+
+            let analytics_backend = if !std::env::var("USE_ANALYTICS")
+                .unwrap_or("false".to_string())
+                .parse()
+                .unwrap_or(false)
+            {
+                log::info!("Analytics disabled, only forwarding events");
+                AnalyticsBackend::Disabled
+            } else if AnalyticsBackend::is_postgres_configured() {
+                log::info!("Exporting analytics from the postgres backend");
+                AnalyticsBackend::Postgres(PostgresAnalyticsStore {
+                    pool: web_pool.clone(),
+                })
+            } else {
+                log::info!("Exporting analytics from the clickhouse backend");
+                AnalyticsBackend::ClickHouse(ClickHouseAnalyticsStore {
+                    clickhouse_client: clickhouse::Client::default()
+                        .with_url(
+                            std::env::var("CLICKHOUSE_URL")
+                                .unwrap_or("http://localhost:8123".to_string()),
+                        )
+                        .with_user(
+                            std::env::var("CLICKHOUSE_USER").unwrap_or("default".to_string()),
+                        )
+                        .with_password(
+                            std::env::var("CLICKHOUSE_PASSWORD").unwrap_or("".to_string()),
+                        )
+                        .with_database(
+                            std::env::var("CLICKHOUSE_DATABASE").unwrap_or("default".to_string()),
+                        ),
+                })
+            };
+
+            analytics_export_worker(
+                should_terminate,
+                web_redis_pool,
+                web_pool,
+                analytics_backend,
+            )
+            .await
+        });
+}
+
+/// Forward configs are looked up for every event, so they are cached for a minute.
+struct ForwardConfigCache {
+    configs: HashMap<uuid::Uuid, (std::time::Instant, Option<AnalyticsExportConfig>)>,
+}
+
+impl ForwardConfigCache {
+    async fn get(
+        &mut self,
+        dataset_id: uuid::Uuid,
+        web_pool: &actix_web::web::Data<models::Pool>,
+    ) -> Option<AnalyticsExportConfig> {
+        if let Some((fetched_at, config)) = self.configs.get(&dataset_id) {
+            if fetched_at.elapsed() < std::time::Duration::from_secs(60) {
+                return config.clone();
+            }
+        }
+
+        let config = get_analytics_export_config_query(dataset_id, web_pool)
+            .await
+            .ok()
+            .filter(|config| config.active && config.forward_url.is_some());
+        self.configs
+            .insert(dataset_id, (std::time::Instant::now(), config.clone()));
+
+        config
+    }
+}
+
+async fn analytics_export_worker(
+    should_terminate: Arc<AtomicBool>,
+    redis_pool: actix_web::web::Data<models::RedisPool>,
+    web_pool: actix_web::web::Data<models::Pool>,
+    analytics_backend: AnalyticsBackend,
+) {
+    log::info!("Starting analytics export worker service thread");
+
+    let mut redis_conn_sleep = std::time::Duration::from_secs(1);
+
+    #[allow(unused_assignments)]
+    let mut opt_redis_connection = None;
+
+    loop {
+        let borrowed_redis_connection = match redis_pool.get().await {
+            Ok(redis_connection) => Some(redis_connection),
+            Err(err) => {
+                log::error!("Failed to get redis connection outside of loop: {:?}", err);
+                None
+            }
+        };
+
+        if borrowed_redis_connection.is_some() {
+            opt_redis_connection = borrowed_redis_connection;
+            break;
+        }
+
+        log::info!(
+            "Retrying to get redis connection out of loop after {:?} secs",
+            redis_conn_sleep
+        );
+        tokio::time::sleep(redis_conn_sleep).await;
+        redis_conn_sleep = std::cmp::min(redis_conn_sleep * 2, std::time::Duration::from_secs(300));
+    }
+
+    let mut redis_connection =
+        opt_redis_connection.expect("Failed to get redis connection outside of loop");
+
+    // Events left in the processing list by a worker which died mid request are sent again
+    loop {
+        let requeued: Option<String> = redis::cmd("RPOPLPUSH")
+            .arg(ANALYTICS_FORWARD_PROCESSING_QUEUE)
+            .arg(ANALYTICS_FORWARD_QUEUE)
+            .query_async(&mut *redis_connection)
+            .await
+            .unwrap_or(None);
+
+        if requeued.is_none() {
+            break;
+        }
+    }
+
+    let http_client = build_webhook_http_client().expect("Failed to create http client");
+
+    let mut forward_configs = ForwardConfigCache {
+        configs: HashMap::new(),
+    };
+
+    let mut broken_pipe_sleep = std::time::Duration::from_secs(10);
+    let export_interval = std::time::Duration::from_secs(60);
+    let mut last_export = std::time::Instant::now() - export_interval;
+    let retry_interval = std::time::Duration::from_secs(5);
+    let mut last_retry = std::time::Instant::now() - retry_interval;
+
+    loop {
+        if should_terminate.load(Ordering::Relaxed) {
+            log::info!("Shutting down");
+            break;
+        }
+
+        if analytics_backend.is_enabled() && last_export.elapsed() >= export_interval {
+            last_export = std::time::Instant::now();
+            run_due_exports(&analytics_backend, &web_pool, &redis_pool).await;
+        }
+
+        if last_retry.elapsed() >= retry_interval {
+            last_retry = std::time::Instant::now();
+            requeue_due_retries(&redis_pool).await;
+        }
+
+        let payload_result: Result<Option<String>, redis::RedisError> = redis::cmd("brpoplpush")
+            .arg(ANALYTICS_FORWARD_QUEUE)
+            .arg(ANALYTICS_FORWARD_PROCESSING_QUEUE)
+            .arg(1.0)
+            .query_async(&mut redis_connection.clone())
+            .await;
+
+        let serialized_message = match payload_result {
+            Ok(Some(serialized_message)) => {
+                broken_pipe_sleep = std::time::Duration::from_secs(10);
+                serialized_message
+            }
+            Ok(None) => {
+                broken_pipe_sleep = std::time::Duration::from_secs(10);
+                continue;
+            }
+            Err(err) => {
+                log::error!("Unable to process {:?}", err);
+
+                if err.is_io_error() {
+                    log::error!("IO broken pipe error, trying to acquire new connection");
+                    match redis_pool.get().await {
+                        Ok(redis_conn) => {
+                            log::info!(
+                                "Got new redis connection after broken pipe! Resuming polling"
+                            );
+                            redis_connection = redis_conn;
+                        }
+                        Err(err) => {
+                            log::error!(
+                                "Failed to get redis connection after broken pipe, will try again after {broken_pipe_sleep:?} secs, err: {:?}",
+                                err
+                            );
+                        }
+                    }
+
+                    tokio::time::sleep(broken_pipe_sleep).await;
+                    broken_pipe_sleep =
+                        std::cmp::min(broken_pipe_sleep * 2, std::time::Duration::from_secs(300));
+                }
+
+                continue;
+            }
+        };
+
+        match serde_json::from_str::<AnalyticsForwardMessage>(&serialized_message) {
+            Ok(message) => {
+                if let Some(config) = forward_configs.get(message.dataset_id, &web_pool).await {
+                    if let Err(err) = observe_worker_job(
+                        "analytics-export-worker",
+                        Some(message.dataset_id),
+                        &redis_pool,
+                        forward_event(&message, &config, &http_client),
+                    )
+                    .await
+                    {
+                        retry_forward(message, err, &redis_pool).await;
+                    }
+                }
+            }
+            Err(err) => log::error!("Failed to parse analytics forward message {:?}", err),
+        }
+
+        let _ = redis::cmd("LREM")
+            .arg(ANALYTICS_FORWARD_PROCESSING_QUEUE)
+            .arg(1)
+            .arg(serialized_message)
+            .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_connection)
+            .await;
+    }
+}
+
+async fn run_due_exports(
+    analytics_backend: &AnalyticsBackend,
+    web_pool: &actix_web::web::Data<models::Pool>,
+    redis_pool: &models::RedisPool,
+) {
+    let due_exports = match claim_due_analytics_exports_query(
+        10,
+        chrono::Duration::minutes(30),
+        web_pool,
+    )
+    .await
+    {
+        Ok(due_exports) => due_exports,
+        Err(err) => {
+            log::error!("Failed to claim analytics exports {:?}", err);
+            return;
+        }
+    };
+
+    for config in due_exports {
+        let dataset_id = config.dataset_id;
+        match observe_worker_job(
+            "analytics-export-worker",
+            Some(dataset_id),
+            redis_pool,
+            run_analytics_export_query(config, analytics_backend, web_pool),
+        )
+        .await
+        {
+            Ok(run) => log::info!(
+                "Analytics export for dataset {} finished with status {} and {} events",
+                dataset_id,
+                run.status,
+                run.row_count
+            ),
+            Err(err) => log::error!(
+                "Failed to run analytics export for dataset {} {:?}",
+                dataset_id,
+                err
+            ),
+        }
+    }
+}
+
+async fn forward_event(
+    message: &AnalyticsForwardMessage,
+    config: &AnalyticsExportConfig,
+    http_client: &reqwest::Client,
+) -> Result<(), ServiceError> {
+    let Some(forward_url) = &config.forward_url else {
+        return Ok(());
+    };
+
+    // The url's DNS may have been changed since the config was saved
+    validate_webhook_url(forward_url).await?;
+
+    let sink = AnalyticsForwardSink::from_name(&config.forward_sink);
+    let content_type = match sink {
+        AnalyticsForwardSink::Http => "application/json",
+        AnalyticsForwardSink::KafkaRest => "application/vnd.kafka.json.v2+json",
+    };
+
+    let mut request = http_client
+        .post(forward_url)
+        .header("Content-Type", content_type)
+        .body(get_analytics_forward_body(message, sink).to_string());
+    if let Some(auth_header) = &config.forward_auth_header {
+        request = request.header("Authorization", auth_header);
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|err| ServiceError::BadRequest(format!("Failed to forward event: {}", err)))?;
+
+    if !response.status().is_success() {
+        return Err(ServiceError::BadRequest(format!(
+            "Forward url responded with {}",
+            response.status()
+        )));
+    }
+
+    Ok(())
+}
+
+async fn retry_forward(
+    mut message: AnalyticsForwardMessage,
+    error: ServiceError,
+    redis_pool: &actix_web::web::Data<models::RedisPool>,
+) {
+    message.attempt_number += 1;
+
+    if message.attempt_number >= MAX_ANALYTICS_FORWARD_ATTEMPTS {
+        log::error!(
+            "Failed to forward analytics event {} {} times {:?}",
+            message.id,
+            message.attempt_number,
+            error
+        );
+
+        let _ = add_dead_letter(
+            DeadLetterQueue::AnalyticsForward,
+            ANALYTICS_FORWARD_QUEUE,
+            message.dataset_id,
+            &message,
+            error.to_string(),
+            redis_pool,
+        )
+        .await
+        .map_err(|err| log::error!("Failed to add dead letter {:?}", err));
+
+        return;
+    }
+
+    let Ok(serialized_message) = serde_json::to_string(&message) else {
+        return;
+    };
+
+    let next_attempt_at = chrono::Utc::now().timestamp()
+        + get_analytics_forward_retry_delay(message.attempt_number).as_secs() as i64;
+
+    let Ok(mut redis_conn) = redis_pool.get().await else {
+        log::error!("Failed to get redis connection to retry analytics event");
+        return;
+    };
+
+    let _ = redis::cmd("ZADD")
+        .arg(ANALYTICS_FORWARD_RETRY_SET)
+        .arg(next_attempt_at)
+        .arg(serialized_message)
+        .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
+        .await
+        .map_err(|err| log::error!("Failed to queue analytics event retry {:?}", err));
+}
+
+async fn requeue_due_retries(redis_pool: &actix_web::web::Data<models::RedisPool>) {
+    let Ok(mut redis_conn) = redis_pool.get().await else {
+        log::error!("Failed to get redis connection to requeue analytics event retries");
+        return;
+    };
+
+    let due_messages: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+        .arg(ANALYTICS_FORWARD_RETRY_SET)
+        .arg("-inf")
+        .arg(chrono::Utc::now().timestamp())
+        .arg("LIMIT")
+        .arg(0)
+        .arg(100)
+        .query_async(&mut *redis_conn)
+        .await
+        .unwrap_or_default();
+
+    for due_message in due_messages {
+        // Only the worker which removes the message from the retry set requeues it
+        let removed: usize = redis::cmd("ZREM")
+            .arg(ANALYTICS_FORWARD_RETRY_SET)
+            .arg(&due_message)
+            .query_async(&mut *redis_conn)
+            .await
+            .unwrap_or(0);
+
+        if removed == 0 {
+            continue;
+        }
+
+        let _ = redis::cmd("lpush")
+            .arg(ANALYTICS_FORWARD_QUEUE)
+            .arg(due_message)
+            .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+            .await
+            .map_err(|err| log::error!("Failed to requeue analytics event {:?}", err));
+    }
+}
@@ -0,0 +1,165 @@
+use chm::tools::migrations::SetupArgs;
+use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
+use trieve_server::{
+    errors::ServiceError,
+    establish_connection, get_env,
+    operators::{
+        analytics_report_operator::{
+            claim_due_analytics_reports_query, deactivate_analytics_report_subscription_query,
+            send_analytics_report, set_analytics_report_sent_query,
+        },
+        analytics_store_operator::AnalyticsBackend,
+        dataset_operator::get_dataset_by_id_query,
+        user_operator::get_user_by_id_query,
+        worker_metrics_operator::observe_worker_job,
+    },
+};
+
+#[tokio::main]
+async fn main() -> Result<(), ServiceError> {
+    dotenvy::dotenv().ok();
+    env_logger::builder()
+        .target(env_logger::Target::Stdout)
+        .filter_level(log::LevelFilter::Info)
+        .init();
+    log::info!("Starting analytics report cronjob");
+    AnalyticsBackend::require_clickhouse("Building analytics reports")?;
+
+    let redis_url = get_env!("REDIS_URL", "REDIS_URL is not set");
+    let redis_connections: u32 = std::env::var("REDIS_CONNECTIONS")
+        .unwrap_or("2".to_string())
+        .parse()
+        .unwrap_or(2);
+
+    let redis_manager =
+        bb8_redis::RedisConnectionManager::new(redis_url).expect("Failed to connect to redis");
+
+    let redis_pool = bb8_redis::bb8::Pool::builder()
+        .max_size(redis_connections)
+        .connection_timeout(std::time::Duration::from_secs(2))
+        .build(redis_manager)
+        .await
+        .expect("Failed to create redis pool");
+
+    let database_url = get_env!("DATABASE_URL", "DATABASE_URL is not set");
+
+    let mut config = ManagerConfig::default();
+    config.custom_setup = Box::new(establish_connection);
+
+    let mgr = AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new_with_config(
+        database_url,
+        config,
+    );
+
+    let pool = diesel_async::pooled_connection::deadpool::Pool::builder(mgr)
+        .max_size(3)
+        .build()
+        .expect("Failed to create diesel_async pool");
+
+    let pool = actix_web::web::Data::new(pool.clone());
+
+    let args = SetupArgs {
+        url: Some(get_env!("CLICKHOUSE_URL", "CLICKHOUSE_URL is not set").to_string()),
+        user: Some(get_env!("CLICKHOUSE_USER", "CLICKHOUSE_USER is not set").to_string()),
+        password: Some(
+            get_env!("CLICKHOUSE_PASSWORD", "CLICKHOUSE_PASSWORD is not set").to_string(),
+        ),
+        database: Some(get_env!("CLICKHOUSE_DB", "CLICKHOUSE_DB is not set").to_string()),
+    };
+
+    let clickhouse_client = clickhouse::Client::default()
+        .with_url(args.url.as_ref().unwrap())
+        .with_user(args.user.as_ref().unwrap())
+        .with_password(args.password.as_ref().unwrap())
+        .with_database(args.database.as_ref().unwrap())
+        .with_option("async_insert", "1")
+        .with_option("wait_for_async_insert", "0");
+
+    let mut env = minijinja::Environment::new();
+    minijinja_embed::load_templates!(&mut env);
+
+    loop {
+        // Claimed subscriptions are leased for an hour so a crashed run retries them later
+        let subscriptions =
+            claim_due_analytics_reports_query(50, chrono::Duration::hours(1), &pool).await?;
+        if subscriptions.is_empty() {
+            break;
+        }
+
+        for subscription in subscriptions {
+            let dataset = match get_dataset_by_id_query(subscription.dataset_id, pool.clone()).await
+            {
+                Ok(dataset) => dataset,
+                Err(err) => {
+                    log::error!(
+                        "Failed to get dataset {} for analytics report: {:?}",
+                        subscription.dataset_id,
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            let (user, user_orgs, _) =
+                match get_user_by_id_query(&subscription.user_id, pool.clone()).await {
+                    Ok(user) => user,
+                    Err(err) => {
+                        log::error!(
+                            "Failed to get user {} for analytics report: {:?}",
+                            subscription.user_id,
+                            err
+                        );
+                        continue;
+                    }
+                };
+
+            let is_admin = user_orgs.iter().any(|user_org| {
+                user_org.organization_id == dataset.organization_id && user_org.role >= 1
+            });
+            if !is_admin {
+                log::info!(
+                    "User {} is no longer an admin of dataset {}, deactivating their analytics report",
+                    subscription.user_id,
+                    subscription.dataset_id
+                );
+                deactivate_analytics_report_subscription_query(subscription.id, &pool).await?;
+                continue;
+            }
+
+            match observe_worker_job(
+                "analytics-report-cronjob",
+                Some(subscription.dataset_id),
+                &redis_pool,
+                send_analytics_report(
+                    &subscription,
+                    &dataset,
+                    user.email,
+                    &env,
+                    &clickhouse_client,
+                    &pool,
+                ),
+            )
+            .await
+            {
+                Ok(sent_at) => {
+                    set_analytics_report_sent_query(&subscription, sent_at, &pool).await?;
+                    log::info!(
+                        "Sent analytics report of dataset {} to user {}",
+                        subscription.dataset_id,
+                        subscription.user_id
+                    );
+                }
+                Err(err) => {
+                    log::error!(
+                        "Failed to send analytics report of dataset {} to user {}: {:?}",
+                        subscription.dataset_id,
+                        subscription.user_id,
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
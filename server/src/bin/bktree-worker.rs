@@ -6,13 +6,15 @@ use std::sync::{
     Arc,
 };
 use trieve_server::{
-    data::models::RedisPool,
+    data::models::{DeadLetterQueue, RedisPool},
     errors::ServiceError,
     get_env,
     operators::{
         chunk_operator::get_last_processed_from_clickhouse,
         dataset_operator::{scroll_words_from_dataset, update_dataset_last_processed_query},
+        dead_letter_operator::add_dead_letter,
         typo_operator::{BkTree, CreateBkTreeMessage},
+        worker_metrics_operator::record_worker_job,
     },
 };
 
@@ -180,6 +182,7 @@ async fn bktree_worker(
             }
         };
 
+        let job_started_at = std::time::Instant::now();
         let mut id_offset = uuid::Uuid::nil();
         log::info!("Processing dataset {}", create_tree_msg.dataset_id);
 
@@ -205,6 +208,14 @@ async fn bktree_worker(
                     .map_err(|e| {
                         eprintln!("Failed to readd error to queue: {:?}", e);
                     });
+                record_worker_job(
+                    "bktree-worker",
+                    Some(create_tree_msg.dataset_id),
+                    job_started_at.elapsed(),
+                    false,
+                    &redis_pool,
+                )
+                .await;
                 continue;
             }
         };
@@ -244,13 +255,31 @@ async fn bktree_worker(
         }
 
         if failed {
+            record_worker_job(
+                "bktree-worker",
+                Some(create_tree_msg.dataset_id),
+                job_started_at.elapsed(),
+                false,
+                &redis_pool,
+            )
+            .await;
             continue;
         }
 
-        match bk_tree
+        let save_result = bk_tree
             .save(create_tree_msg.dataset_id, redis_pool.clone())
-            .await
-        {
+            .await;
+
+        record_worker_job(
+            "bktree-worker",
+            Some(create_tree_msg.dataset_id),
+            job_started_at.elapsed(),
+            save_result.is_ok(),
+            &redis_pool,
+        )
+        .await;
+
+        match save_result {
             Ok(()) => {
                 let _ = redis::cmd("LREM")
                     .arg("bktree_processing")
@@ -309,7 +338,7 @@ pub async fn readd_error_to_queue(
     let _ = redis::cmd("SREM")
         .arg("bktree_processing")
         .arg(1)
-        .arg(old_payload_message.clone())
+        .arg(old_payload_message)
         .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
         .await;
 
@@ -317,17 +346,16 @@ pub async fn readd_error_to_queue(
 
     if message.attempt_number == 3 {
         log::error!("Failed to construct bktree 3 times {:?}", error);
-        let mut redis_conn = redis_pool
-            .get()
-            .await
-            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
 
-        redis::cmd("SADD")
-            .arg("bktree_dead_letters")
-            .arg(old_payload_message)
-            .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
-            .await
-            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+        add_dead_letter(
+            DeadLetterQueue::BkTree,
+            "bktree_creation",
+            message.dataset_id,
+            &message,
+            error.to_string(),
+            &redis_pool,
+        )
+        .await?;
 
         return Err(ServiceError::InternalServerError(format!(
             "Failed to construct bktree {:?}",
@@ -2,7 +2,10 @@ use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfi
 use trieve_server::{
     errors::ServiceError,
     establish_connection, get_env,
-    operators::crawl_operator::{crawl_site, get_crawl_requests_to_rerun, update_scrape_id},
+    operators::{
+        crawl_operator::{crawl_site, get_crawl_requests_to_rerun, update_scrape_id},
+        worker_metrics_operator::observe_worker_job,
+    },
 };
 
 #[allow(clippy::print_stdout)]
@@ -52,9 +55,14 @@ async fn main() -> Result<(), ServiceError> {
 
     for request in new_requests {
         log::info!("Re-crawling site: {}", request.url);
-        let new_scrape_id = crawl_site(request.crawl_options.clone())
-            .await
-            .expect("Failed to crawl site");
+        let new_scrape_id = observe_worker_job(
+            "crawl-cron-job",
+            Some(request.dataset_id),
+            &redis_pool,
+            crawl_site(request.crawl_options.clone()),
+        )
+        .await
+        .expect("Failed to crawl site");
 
         let updated_request = update_scrape_id(request.scrape_id, new_scrape_id, pool.clone())
             .await
@@ -1,5 +1,5 @@
 use actix_web::web;
-use broccoli_queue::{error::BroccoliError, queue::BroccoliQueue};
+use broccoli_queue::{brokers::broker::BrokerMessage, error::BroccoliError, queue::BroccoliQueue};
 use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
 use serde::{Deserialize, Serialize};
 use signal_hook::consts::SIGTERM;
@@ -8,16 +8,21 @@ use std::{
     error::Error,
     sync::{atomic::AtomicBool, Arc},
 };
+use trieve_server::operators::analytics_store_operator::AnalyticsBackend;
 use trieve_server::{
-    data::models::{self, ChunkGroup, UnifiedId, WorkerEvent},
+    data::models::{self, ChunkGroup, DeadLetterQueue, UnifiedId, WorkerEvent},
     operators::{
         chunk_operator::get_row_count_for_organization_id_query,
         clickhouse_operator::{ClickHouseEvent, EventQueue},
         crawl_operator::IngestResult,
         dataset_operator::get_dataset_and_organization_from_dataset_id_query,
+        dead_letter_operator::{
+            add_dead_letter, broccoli_retry_strategy, is_last_broccoli_attempt,
+        },
         group_operator::create_groups_query,
         organization_operator::hash_function,
         video_operator::{get_channel_id, get_channel_video_ids, get_transcript},
+        worker_metrics_operator::observe_worker_job,
     },
 };
 use trieve_server::{
@@ -822,6 +827,7 @@ async fn parse_youtube_chunks(
                         fulltext_boost: None,
                         semantic_boost: None,
                         high_priority: None,
+                        acl: None,
                     };
 
                     chunks.push(create_chunk_data);
@@ -1090,6 +1096,16 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .parse()
         .unwrap_or(2);
 
+    let redis_manager =
+        bb8_redis::RedisConnectionManager::new(redis_url).expect("Failed to connect to redis");
+
+    let redis_pool = bb8_redis::bb8::Pool::builder()
+        .max_size(redis_connections)
+        .connection_timeout(std::time::Duration::from_secs(2))
+        .build(redis_manager)
+        .await
+        .expect("Failed to create redis pool");
+
     let should_terminate = Arc::new(AtomicBool::new(false));
     signal_hook::flag::register(SIGTERM, Arc::clone(&should_terminate))
         .expect("Failed to register shutdown hook");
@@ -1111,33 +1127,82 @@ async fn main() -> Result<(), Box<dyn Error>> {
             .with_option("async_insert", "1")
             .with_option("wait_for_async_insert", "0");
 
-        let mut event_queue = EventQueue::new(clickhouse_client.clone());
+        let mut event_queue = EventQueue::from_analytics_backend(AnalyticsBackend::from_env(
+            clickhouse_client.clone(),
+            web_pool.clone(),
+        ));
         event_queue.start_service();
         event_queue
     } else {
         log::info!("Analytics disabled");
         EventQueue::default()
     };
-    let web_event_queue = actix_web::web::Data::new(event_queue);
+    let web_event_queue = actix_web::web::Data::new(event_queue.with_webhooks(redis_pool.clone()));
 
     let broccoli_queue = BroccoliQueue::builder(redis_url)
         .pool_connections(redis_connections.try_into().unwrap())
-        .failed_message_retry_strategy(Default::default())
+        .failed_message_retry_strategy(broccoli_retry_strategy())
         .build()
         .await
         .expect("Failed to create broccoli queue");
 
     let web_broccoli_queue = actix_web::web::Data::new(broccoli_queue.clone());
+    let scrape_redis_pool = redis_pool.clone();
 
     broccoli_queue
-        .process_messages("crawl_queue", None, None, move |msg| {
-            scrape_worker(
-                msg.payload,
-                web_pool.clone(),
-                web_event_queue.clone(),
-                web_broccoli_queue.clone(),
-            )
-        })
+        .process_messages_with_handlers(
+            "crawl_queue",
+            None,
+            None,
+            move |msg| {
+                let web_pool = web_pool.clone();
+                let web_event_queue = web_event_queue.clone();
+                let web_broccoli_queue = web_broccoli_queue.clone();
+                let redis_pool = scrape_redis_pool.clone();
+
+                async move {
+                    observe_worker_job(
+                        "crawl-worker",
+                        Some(msg.payload.dataset_id),
+                        &redis_pool,
+                        scrape_worker(msg.payload, web_pool, web_event_queue, web_broccoli_queue),
+                    )
+                    .await
+                }
+            },
+            |_msg| async move { Ok(()) },
+            move |msg: BrokerMessage<CrawlRequest>, err| {
+                let redis_pool = redis_pool.clone();
+
+                async move {
+                    if !is_last_broccoli_attempt(msg.attempts) {
+                        return Ok(());
+                    }
+
+                    log::error!(
+                        "Failed to crawl {} {} times: {:?}",
+                        msg.payload.url,
+                        msg.attempts as usize + 1,
+                        err
+                    );
+
+                    if let Err(dead_letter_err) = add_dead_letter(
+                        DeadLetterQueue::Crawl,
+                        "crawl_queue",
+                        msg.payload.dataset_id,
+                        &msg.payload,
+                        err.to_string(),
+                        &redis_pool,
+                    )
+                    .await
+                    {
+                        log::error!("Failed to add dead letter: {:?}", dead_letter_err);
+                    }
+
+                    Ok(())
+                }
+            },
+        )
         .await?;
 
     Ok(())
@@ -1162,24 +1227,22 @@ pub async fn readd_error_to_queue(
     let _ = redis::cmd("LREM")
         .arg("scrape_processing")
         .arg(1)
-        .arg(old_payload_message.clone())
+        .arg(old_payload_message)
         .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
         .await;
 
     if payload.attempt_number == 3 {
         log::error!("Failed to insert data 3 times quitting {:?}", error);
 
-        let mut redis_conn = redis_pool
-            .get()
-            .await
-            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
-
-        redis::cmd("lpush")
-            .arg("dead_letters_scrape")
-            .arg(old_payload_message)
-            .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
-            .await
-            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+        add_dead_letter(
+            DeadLetterQueue::Crawl,
+            "crawl_queue",
+            payload.dataset_id,
+            &payload,
+            error.to_string(),
+            &redis_pool,
+        )
+        .await?;
 
         return Err(ServiceError::InternalServerError(format!(
             "Failed to create new qdrant point: {:?}",
@@ -1,16 +1,30 @@
 use broccoli_queue::queue::BroccoliQueue;
-use chrono::{DateTime, NaiveDateTime};
+use bytes::Bytes;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
 use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
+use futures::future::BoxFuture;
+use once_cell::sync::Lazy;
+use parquet::arrow::async_reader::{
+    fetch_parquet_metadata, AsyncFileReader, ParquetRecordBatchStreamBuilder,
+};
+use parquet::errors::ParquetError;
+use parquet::file::metadata::ParquetMetaData;
+use regex::Regex;
+use s3::Bucket;
 use signal_hook::consts::SIGTERM;
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Range;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
 use tokio_stream::StreamExt;
+use trieve_server::operators::analytics_store_operator::AnalyticsBackend;
 use trieve_server::{
     data::models::{
         self, ChunkGroup, ChunkReqPayloadFields, ChunkReqPayloadMapping, CsvJsonlWorkerMessage,
-        GeoInfo, GeoTypes,
+        GeoInfo, GeoTypes, MappingValueType,
     },
     errors::ServiceError,
     establish_connection, get_env,
@@ -21,8 +35,12 @@ use trieve_server::{
     operators::{
         chunk_operator::create_chunk_metadata,
         clickhouse_operator::{ClickHouseEvent, EventQueue},
-        file_operator::{create_file_query, get_csvjsonl_aws_bucket},
+        file_operator::{create_file_query, csv_jsonl_error_file_key, get_csvjsonl_aws_bucket},
         group_operator::{create_group_from_file_query, create_groups_query},
+        ingestion_job_operator::{
+            complete_ingestion_job_queuing, create_tracked_ingestion_message,
+        },
+        worker_metrics_operator::observe_worker_job,
     },
 };
 
@@ -93,7 +111,9 @@ fn main() {
                     .with_option("async_insert", "1")
                     .with_option("wait_for_async_insert", "0");
 
-                let mut event_queue = EventQueue::new(clickhouse_client.clone());
+                let mut event_queue = EventQueue::from_analytics_backend(
+                    AnalyticsBackend::from_env(clickhouse_client.clone(), web_pool.clone()),
+                );
                 event_queue.start_service();
                 event_queue
             } else {
@@ -264,6 +284,18 @@ async fn file_worker(
                         )
                         .await;
 
+                    if let Some(ingestion_job_id) = csv_jsonl_worker_message.ingestion_job_id {
+                        if let Err(err) = complete_ingestion_job_queuing(
+                            ingestion_job_id,
+                            csv_jsonl_worker_message.dataset_id,
+                            web_pool.clone(),
+                        )
+                        .await
+                        {
+                            log::error!("Failed to complete ingestion job queuing: {:?}", err);
+                        }
+                    }
+
                     continue;
                 }
 
@@ -279,39 +311,44 @@ async fn file_worker(
             }
         };
 
-        let _ = process_csv_jsonl_file(
-            csv_jsonl_worker_message,
-            web_pool.clone(),
-            broccoli_queue.clone(),
+        let ingestion_job_id = csv_jsonl_worker_message.ingestion_job_id;
+        let dataset_id = csv_jsonl_worker_message.dataset_id;
+
+        let _ = observe_worker_job(
+            "csv-jsonl-worker",
+            Some(dataset_id),
+            &redis_pool,
+            process_csv_jsonl_file(
+                csv_jsonl_worker_message,
+                web_pool.clone(),
+                event_queue.clone(),
+                broccoli_queue.clone(),
+            ),
         )
         .await;
+
+        // Rows queued before a failure are still ingested, so the job finishes with whatever made it into the queue
+        if let Some(ingestion_job_id) = ingestion_job_id {
+            if let Err(err) =
+                complete_ingestion_job_queuing(ingestion_job_id, dataset_id, web_pool.clone()).await
+            {
+                log::error!("Failed to complete ingestion job queuing: {:?}", err);
+            }
+        }
     }
 }
 
 async fn process_csv_jsonl_file(
     csv_jsonl_worker_message: CsvJsonlWorkerMessage,
     web_pool: actix_web::web::Data<models::Pool>,
+    event_queue: actix_web::web::Data<EventQueue>,
     broccoli_queue: BroccoliQueue,
 ) -> Result<Option<uuid::Uuid>, ServiceError> {
-    // get_object_stream from s3
     let bucket = get_csvjsonl_aws_bucket().map_err(|err| {
         log::error!("Failed to get aws bucket: {:?}", err);
         ServiceError::InternalServerError("Failed to get aws bucket".to_string())
     })?;
 
-    log::info!(
-        "Getting object stream for file id: {}",
-        csv_jsonl_worker_message.file_id
-    );
-
-    let mut response_data_stream = bucket
-        .get_object_stream(csv_jsonl_worker_message.file_id.to_string())
-        .await
-        .map_err(|err| {
-            log::error!("Failed to get object stream: {:?}", err);
-            ServiceError::InternalServerError("Failed to get object stream".to_string())
-        })?;
-
     let chunk_group = ChunkGroup::from_details(
         Some(
             csv_jsonl_worker_message
@@ -350,166 +387,106 @@ async fn process_csv_jsonl_file(
 
     log::info!("Group created with id: {:?}", group_id);
 
-    let mut columns = vec![];
-    let mut line = String::new();
-    let mut bytes: bytes::BytesMut = bytes::BytesMut::new();
-    let mut byte_count = 0;
-    let mut chunk_req_payloads: Vec<ChunkReqPayload> = vec![];
-    while let Some(chunk) = response_data_stream.bytes().next().await {
-        let chunk_bytes = chunk.map_err(|err| {
-            log::error!("Failed to get chunk from stream: {:?}", err);
-            ServiceError::InternalServerError("Failed to get chunk from stream".to_string())
-        })?;
-        bytes.extend_from_slice(&chunk_bytes);
-        let chunk = match String::from_utf8(bytes.to_vec()) {
-            Ok(chunk) => {
-                bytes.clear();
-                chunk
-            }
-            Err(_) => {
-                log::info!(
-                    "Failed to convert bytes chunk to utf8, continuing with bytes append..."
-                );
-                continue;
-            }
-        };
+    let mut row_importer = RowImporter {
+        dataset_id: csv_jsonl_worker_message.dataset_id,
+        group_id,
+        mappings: csv_jsonl_worker_message
+            .create_presigned_put_url_data
+            .mappings
+            .clone()
+            .map(|mappings| mappings.0)
+            .unwrap_or_default(),
+        chunk_html_template: csv_jsonl_worker_message
+            .create_presigned_put_url_data
+            .chunk_html_template
+            .clone(),
+        fulltext_boost_factor: csv_jsonl_worker_message
+            .create_presigned_put_url_data
+            .fulltext_boost_factor,
+        semantic_boost_factor: csv_jsonl_worker_message
+            .create_presigned_put_url_data
+            .semantic_boost_factor,
+        columns: vec![],
+        row_number: 0,
+        chunk_req_payloads: vec![],
+        group_metadatas: HashMap::new(),
+        row_errors: vec![],
+        row_error_count: 0,
+        max_row_errors: std::env::var("CSV_JSONL_MAX_ROW_ERRORS")
+            .ok()
+            .and_then(|val| val.parse().ok())
+            .unwrap_or(DEFAULT_MAX_ROW_ERRORS),
+        ingestion_job_id: csv_jsonl_worker_message.ingestion_job_id,
+        web_pool: web_pool.clone(),
+        broccoli_queue: broccoli_queue.clone(),
+    };
 
-        byte_count += chunk.len();
+    let file_key = csv_jsonl_worker_message.file_id.to_string();
+    let import_format = BulkImportFormat::from_file_name(
+        &csv_jsonl_worker_message
+            .create_presigned_put_url_data
+            .file_name,
+    );
+    log::info!(
+        "Importing file id {} as {:?}",
+        csv_jsonl_worker_message.file_id,
+        import_format
+    );
 
-        let chunk_lines = chunk.split_inclusive('\n');
-        for chunk_line in chunk_lines {
-            if chunk_line.is_empty() {
-                continue;
-            }
+    let byte_count = match import_format {
+        BulkImportFormat::Parquet => {
+            import_parquet_rows(&bucket, &file_key, &mut row_importer).await?
+        }
+        text_format => import_text_rows(&bucket, &file_key, text_format, &mut row_importer).await?,
+    };
 
-            if chunk_line.ends_with('\n') {
-                line.push_str(chunk_line.trim_end_matches('\n'));
-                let object = match serde_json::from_str::<serde_json::Value>(&line) {
-                    Ok(object) => Some(object),
-                    Err(_) => {
-                        if columns.is_empty() {
-                            columns = parse_csv_line(&line);
-                            None
-                        } else {
-                            let mut new_object = serde_json::Map::new();
-                            let mut line_vals_iter = parse_csv_line(&line).into_iter();
-                            for column in columns.iter() {
-                                let key = column.trim();
-                                let value: serde_json::Value = match line_vals_iter.next() {
-                                    Some(val) => match val.trim() {
-                                        "null" => serde_json::Value::Null,
-                                        "None" => serde_json::Value::Null,
-                                        val => {
-                                            let number_value =
-                                                if let Ok(int_val) = val.parse::<i64>() {
-                                                    serde_json::Number::from_f64(int_val as f64)
-                                                        .map(serde_json::Value::Number)
-                                                } else if let Ok(float_val) = val.parse::<f64>() {
-                                                    serde_json::Number::from_f64(float_val)
-                                                        .map(serde_json::Value::Number)
-                                                } else {
-                                                    None
-                                                };
-
-                                            let bool_value = match val.to_lowercase().as_str() {
-                                                "true" => Some(serde_json::Value::Bool(true)),
-                                                "false" => Some(serde_json::Value::Bool(false)),
-                                                _ => None,
-                                            };
-
-                                            if let Some(int_value) = number_value {
-                                                int_value
-                                            } else if let Some(bool_value) = bool_value {
-                                                bool_value
-                                            } else {
-                                                serde_json::Value::String(val.to_string())
-                                            }
-                                        }
-                                    },
-                                    None => serde_json::Value::Null,
-                                };
-                                new_object.insert(key.to_string(), value);
-                            }
-
-                            Some(serde_json::Value::Object(new_object))
-                        }
-                    }
-                };
-
-                if let Some(object) = object {
-                    let chunk_req_payload = convert_value_to_chunkreqpayload(
-                        object,
-                        Some(group_id),
-                        csv_jsonl_worker_message
-                            .create_presigned_put_url_data
-                            .mappings
-                            .clone()
-                            .map(|mappings| mappings.0.clone())
-                            .unwrap_or_default(),
-                        csv_jsonl_worker_message
-                            .create_presigned_put_url_data
-                            .fulltext_boost_factor,
-                        csv_jsonl_worker_message
-                            .create_presigned_put_url_data
-                            .semantic_boost_factor,
-                    );
-                    chunk_req_payloads.push(chunk_req_payload);
+    row_importer.flush().await?;
+    let row_error_count = row_importer.row_error_count;
+    let mut row_errors = row_importer.row_errors;
 
-                    if chunk_req_payloads.len() >= 120 {
-                        let (upsert_chunk_ingestion_message, upsert_chunk_metadatas) =
-                            create_chunk_metadata(
-                                chunk_req_payloads.clone(),
-                                csv_jsonl_worker_message.dataset_id,
-                            )
-                            .await?;
+    if row_error_count > 0 {
+        log::info!(
+            "{} rows of file {} failed validation",
+            row_error_count,
+            csv_jsonl_worker_message.file_id
+        );
 
-                        if !upsert_chunk_metadatas.is_empty() {
-                            log::info!(
-                                "Pushing chunk ingestion message to redis {:?}",
-                                upsert_chunk_metadatas.len()
-                            );
-                            broccoli_queue
-                                .publish(
-                                    "ingestion",
-                                    Some(csv_jsonl_worker_message.dataset_id.to_string()),
-                                    &upsert_chunk_ingestion_message,
-                                    None,
-                                )
-                                .await
-                                .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
-                        }
-                        chunk_req_payloads.clear();
-                    }
-                }
-                line.clear();
-            } else {
-                line.push_str(chunk_line);
-            }
+        if row_error_count > row_errors.len() {
+            row_errors.push(
+                serde_json::json!({
+                    "error": format!(
+                        "{} more rows failed validation and were omitted",
+                        row_error_count - row_errors.len()
+                    ),
+                })
+                .to_string(),
+            );
         }
-    }
-
-    if !chunk_req_payloads.is_empty() {
-        let (upsert_chunk_ingestion_message, upsert_chunk_metadatas) = create_chunk_metadata(
-            chunk_req_payloads.clone(),
-            csv_jsonl_worker_message.dataset_id,
-        )
-        .await?;
 
-        if !upsert_chunk_metadatas.is_empty() {
-            log::info!(
-                "Pushing chunk ingestion message to redis after while {:?}",
-                upsert_chunk_metadatas.len()
-            );
-            broccoli_queue
-                .publish(
-                    "ingestion",
-                    Some(csv_jsonl_worker_message.dataset_id.to_string()),
-                    &upsert_chunk_ingestion_message,
-                    None,
+        bucket
+            .put_object_with_content_type(
+                csv_jsonl_error_file_key(csv_jsonl_worker_message.file_id),
+                row_errors.join("\n").as_bytes(),
+                "application/jsonl",
+            )
+            .await
+            .map_err(|err| {
+                log::error!("Failed to upload row error file: {:?}", err);
+                ServiceError::InternalServerError("Failed to upload row error file".to_string())
+            })?;
+
+        event_queue
+            .send(ClickHouseEvent::WorkerEvent(
+                models::WorkerEvent::from_details(
+                    csv_jsonl_worker_message.dataset_id,
+                    models::EventType::CsvJsonlRowErrors {
+                        file_id: csv_jsonl_worker_message.file_id,
+                        error_count: row_error_count,
+                    },
                 )
-                .await
-                .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
-        }
+                .into(),
+            ))
+            .await;
     }
 
     let file_size_mb = (byte_count as f64 / 1024.0 / 1024.0).round() as i64;
@@ -559,6 +536,481 @@ async fn process_csv_jsonl_file(
     Ok(None)
 }
 
+const CHUNK_BATCH_SIZE: usize = 120;
+
+const PARQUET_BATCH_SIZE: usize = 1024;
+
+/// Default number of failing rows written to the error file, overridable with CSV_JSONL_MAX_ROW_ERRORS.
+const DEFAULT_MAX_ROW_ERRORS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BulkImportFormat {
+    Text,
+    Gzip,
+    Zstd,
+    Parquet,
+}
+
+impl BulkImportFormat {
+    fn from_file_name(file_name: &str) -> Self {
+        let file_name = file_name.to_lowercase();
+        if file_name.ends_with(".parquet") {
+            BulkImportFormat::Parquet
+        } else if file_name.ends_with(".gz") {
+            BulkImportFormat::Gzip
+        } else if file_name.ends_with(".zst") || file_name.ends_with(".zstd") {
+            BulkImportFormat::Zstd
+        } else {
+            BulkImportFormat::Text
+        }
+    }
+}
+
+/// Incrementally decompresses the object stream so compressed files never need to be held in memory.
+enum StreamDecoder {
+    Plain,
+    Gzip(flate2::write::GzDecoder<Vec<u8>>),
+    Zstd(zstd::stream::write::Decoder<'static, Vec<u8>>),
+}
+
+impl StreamDecoder {
+    fn new(format: BulkImportFormat) -> std::io::Result<Self> {
+        match format {
+            BulkImportFormat::Gzip => Ok(StreamDecoder::Gzip(flate2::write::GzDecoder::new(
+                Vec::new(),
+            ))),
+            BulkImportFormat::Zstd => Ok(StreamDecoder::Zstd(zstd::stream::write::Decoder::new(
+                Vec::new(),
+            )?)),
+            _ => Ok(StreamDecoder::Plain),
+        }
+    }
+
+    fn decode(&mut self, input: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            StreamDecoder::Plain => Ok(input.to_vec()),
+            StreamDecoder::Gzip(decoder) => {
+                decoder.write_all(input)?;
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+            StreamDecoder::Zstd(decoder) => {
+                decoder.write_all(input)?;
+                decoder.flush()?;
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+        }
+    }
+
+    fn finish(self) -> std::io::Result<Vec<u8>> {
+        match self {
+            StreamDecoder::Plain => Ok(vec![]),
+            StreamDecoder::Gzip(decoder) => decoder.finish(),
+            StreamDecoder::Zstd(mut decoder) => {
+                decoder.flush()?;
+                Ok(decoder.into_inner())
+            }
+        }
+    }
+}
+
+/// Converts rows of a bulk import into chunks and publishes them to the ingestion queue in batches.
+struct RowImporter {
+    dataset_id: uuid::Uuid,
+    group_id: uuid::Uuid,
+    mappings: Vec<ChunkReqPayloadMapping>,
+    chunk_html_template: Option<String>,
+    fulltext_boost_factor: Option<f64>,
+    semantic_boost_factor: Option<f64>,
+    columns: Vec<String>,
+    row_number: usize,
+    chunk_req_payloads: Vec<ChunkReqPayload>,
+    group_metadatas: HashMap<String, serde_json::Value>,
+    row_errors: Vec<String>,
+    row_error_count: usize,
+    max_row_errors: usize,
+    ingestion_job_id: Option<uuid::Uuid>,
+    web_pool: actix_web::web::Data<models::Pool>,
+    broccoli_queue: BroccoliQueue,
+}
+
+impl RowImporter {
+    /// Parses a line of a CSV or JSONL file. The first line which is not valid JSON is treated as the CSV header.
+    async fn process_line(&mut self, line: &str) -> Result<(), ServiceError> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+
+        let object = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(object) => object,
+            Err(_) => {
+                if self.columns.is_empty() {
+                    self.columns = parse_csv_line(line);
+                    return Ok(());
+                }
+
+                let mut new_object = serde_json::Map::new();
+                let mut line_vals_iter = parse_csv_line(line).into_iter();
+                for column in self.columns.iter() {
+                    let key = column.trim();
+                    let value: serde_json::Value = match line_vals_iter.next() {
+                        Some(val) => parse_csv_value(val.trim()),
+                        None => serde_json::Value::Null,
+                    };
+                    new_object.insert(key.to_string(), value);
+                }
+
+                serde_json::Value::Object(new_object)
+            }
+        };
+
+        self.process_row(object).await
+    }
+
+    async fn process_row(&mut self, object: serde_json::Value) -> Result<(), ServiceError> {
+        self.row_number += 1;
+        match convert_value_to_chunkreqpayload(
+            object.clone(),
+            Some(self.group_id),
+            &self.mappings,
+            self.chunk_html_template.as_deref(),
+            self.fulltext_boost_factor,
+            self.semantic_boost_factor,
+        ) {
+            Ok(converted_row) => {
+                if let Some(group_metadata) = converted_row.group_metadata {
+                    for group_tracking_id in converted_row
+                        .chunk_req_payload
+                        .group_tracking_ids
+                        .clone()
+                        .unwrap_or_default()
+                    {
+                        self.group_metadatas
+                            .insert(group_tracking_id, group_metadata.clone());
+                    }
+                }
+                self.chunk_req_payloads
+                    .push(converted_row.chunk_req_payload);
+            }
+            Err(error) => {
+                self.row_error_count += 1;
+                if self.row_errors.len() < self.max_row_errors {
+                    self.row_errors.push(
+                        serde_json::json!({
+                            "row": self.row_number,
+                            "error": error,
+                            "value": object,
+                        })
+                        .to_string(),
+                    );
+                }
+            }
+        }
+
+        if self.chunk_req_payloads.len() >= CHUNK_BATCH_SIZE {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), ServiceError> {
+        if self.chunk_req_payloads.is_empty() {
+            return Ok(());
+        }
+
+        upsert_groups_with_metadata(
+            &mut self.group_metadatas,
+            self.dataset_id,
+            self.web_pool.clone(),
+        )
+        .await?;
+
+        let (upsert_chunk_ingestion_message, upsert_chunk_metadatas) = create_chunk_metadata(
+            std::mem::take(&mut self.chunk_req_payloads),
+            self.dataset_id,
+        )
+        .await?;
+
+        if !upsert_chunk_metadatas.is_empty() {
+            log::info!(
+                "Pushing chunk ingestion message to redis {:?}",
+                upsert_chunk_metadatas.len()
+            );
+
+            let upsert_chunk_ingestion_message = match self.ingestion_job_id {
+                Some(ingestion_job_id) => {
+                    create_tracked_ingestion_message(
+                        ingestion_job_id,
+                        self.dataset_id,
+                        upsert_chunk_ingestion_message.ingestion_messages,
+                        self.web_pool.clone(),
+                    )
+                    .await?
+                }
+                None => upsert_chunk_ingestion_message,
+            };
+
+            self.broccoli_queue
+                .publish(
+                    "ingestion",
+                    Some(self.dataset_id.to_string()),
+                    &upsert_chunk_ingestion_message,
+                    None,
+                )
+                .await
+                .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_csv_value(val: &str) -> serde_json::Value {
+    match val {
+        "null" => serde_json::Value::Null,
+        "None" => serde_json::Value::Null,
+        val => {
+            let number_value = if let Ok(int_val) = val.parse::<i64>() {
+                serde_json::Number::from_f64(int_val as f64).map(serde_json::Value::Number)
+            } else if let Ok(float_val) = val.parse::<f64>() {
+                serde_json::Number::from_f64(float_val).map(serde_json::Value::Number)
+            } else {
+                None
+            };
+
+            let bool_value = match val.to_lowercase().as_str() {
+                "true" => Some(serde_json::Value::Bool(true)),
+                "false" => Some(serde_json::Value::Bool(false)),
+                _ => None,
+            };
+
+            if let Some(int_value) = number_value {
+                int_value
+            } else if let Some(bool_value) = bool_value {
+                bool_value
+            } else {
+                serde_json::Value::String(val.to_string())
+            }
+        }
+    }
+}
+
+/// Streams a CSV or JSONL file, optionally gzip or zstd compressed, line by line into the row importer. Returns the number of bytes read from S3.
+async fn import_text_rows(
+    bucket: &Bucket,
+    file_key: &str,
+    format: BulkImportFormat,
+    row_importer: &mut RowImporter,
+) -> Result<usize, ServiceError> {
+    let mut response_data_stream = bucket.get_object_stream(file_key).await.map_err(|err| {
+        log::error!("Failed to get object stream: {:?}", err);
+        ServiceError::InternalServerError("Failed to get object stream".to_string())
+    })?;
+
+    let mut decoder = StreamDecoder::new(format).map_err(|err| {
+        log::error!("Failed to create decoder: {:?}", err);
+        ServiceError::InternalServerError("Failed to create decoder".to_string())
+    })?;
+    let mut line = String::new();
+    let mut bytes: bytes::BytesMut = bytes::BytesMut::new();
+    let mut byte_count = 0;
+    let mut finished = false;
+
+    while !finished {
+        let decoded_bytes = match response_data_stream.bytes().next().await {
+            Some(chunk) => {
+                let chunk_bytes = chunk.map_err(|err| {
+                    log::error!("Failed to get chunk from stream: {:?}", err);
+                    ServiceError::InternalServerError("Failed to get chunk from stream".to_string())
+                })?;
+                byte_count += chunk_bytes.len();
+                decoder.decode(&chunk_bytes)
+            }
+            None => {
+                finished = true;
+                std::mem::replace(&mut decoder, StreamDecoder::Plain).finish()
+            }
+        }
+        .map_err(|err| {
+            log::error!("Failed to decompress file: {:?}", err);
+            ServiceError::BadRequest("Failed to decompress file".to_string())
+        })?;
+
+        bytes.extend_from_slice(&decoded_bytes);
+        let chunk = match String::from_utf8(bytes.to_vec()) {
+            Ok(chunk) => {
+                bytes.clear();
+                chunk
+            }
+            Err(_) => {
+                log::info!(
+                    "Failed to convert bytes chunk to utf8, continuing with bytes append..."
+                );
+                continue;
+            }
+        };
+
+        for chunk_line in chunk.split_inclusive('\n') {
+            if chunk_line.ends_with('\n') {
+                line.push_str(chunk_line.trim_end_matches('\n'));
+                row_importer.process_line(&line).await?;
+                line.clear();
+            } else {
+                line.push_str(chunk_line);
+            }
+        }
+    }
+
+    if !line.is_empty() {
+        row_importer.process_line(&line).await?;
+    }
+
+    Ok(byte_count)
+}
+
+/// Reads a byte range of an object in S3 for the parquet reader.
+async fn get_object_range_bytes(
+    bucket: &Bucket,
+    file_key: &str,
+    range: Range<usize>,
+) -> parquet::errors::Result<Bytes> {
+    if range.is_empty() {
+        return Ok(Bytes::new());
+    }
+
+    let response = bucket
+        .get_object_range(file_key, range.start as u64, Some(range.end as u64 - 1))
+        .await
+        .map_err(|err| ParquetError::External(Box::new(err)))?;
+
+    Ok(response.bytes().clone())
+}
+
+/// Parquet reader which fetches the footer and row groups with ranged S3 GETs so only the row groups being read are held in memory.
+struct S3ParquetReader {
+    bucket: Bucket,
+    file_key: String,
+    file_size: usize,
+}
+
+impl AsyncFileReader for S3ParquetReader {
+    fn get_bytes(&mut self, range: Range<usize>) -> BoxFuture<'_, parquet::errors::Result<Bytes>> {
+        Box::pin(get_object_range_bytes(&self.bucket, &self.file_key, range))
+    }
+
+    fn get_metadata(&mut self) -> BoxFuture<'_, parquet::errors::Result<Arc<ParquetMetaData>>> {
+        Box::pin(async move {
+            let bucket = &self.bucket;
+            let file_key = &self.file_key;
+            let metadata = fetch_parquet_metadata(
+                move |range| get_object_range_bytes(bucket, file_key, range),
+                self.file_size,
+                Some(64 * 1024),
+            )
+            .await?;
+
+            Ok(Arc::new(metadata))
+        })
+    }
+}
+
+/// Streams the rows of a parquet file into the row importer one record batch at a time. Returns the size of the file.
+async fn import_parquet_rows(
+    bucket: &Bucket,
+    file_key: &str,
+    row_importer: &mut RowImporter,
+) -> Result<usize, ServiceError> {
+    let (head_object, _) = bucket.head_object(file_key).await.map_err(|err| {
+        log::error!("Failed to get parquet file size: {:?}", err);
+        ServiceError::InternalServerError("Failed to get parquet file size".to_string())
+    })?;
+    let file_size = head_object.content_length.unwrap_or_default().max(0) as usize;
+
+    let reader = S3ParquetReader {
+        bucket: bucket.clone(),
+        file_key: file_key.to_string(),
+        file_size,
+    };
+
+    let mut record_batch_stream = ParquetRecordBatchStreamBuilder::new(reader)
+        .await
+        .map_err(|err| {
+            log::error!("Failed to read parquet metadata: {:?}", err);
+            ServiceError::BadRequest("Failed to read parquet metadata".to_string())
+        })?
+        .with_batch_size(PARQUET_BATCH_SIZE)
+        .build()
+        .map_err(|err| {
+            log::error!("Failed to create parquet stream: {:?}", err);
+            ServiceError::BadRequest("Failed to create parquet stream".to_string())
+        })?;
+
+    while let Some(record_batch) = record_batch_stream.next().await {
+        let record_batch = record_batch.map_err(|err| {
+            log::error!("Failed to read parquet record batch: {:?}", err);
+            ServiceError::BadRequest("Failed to read parquet record batch".to_string())
+        })?;
+
+        let mut json_writer = arrow_json::ArrayWriter::new(Vec::new());
+        json_writer
+            .write(&record_batch)
+            .and_then(|_| json_writer.finish())
+            .map_err(|err| {
+                log::error!("Failed to convert parquet rows to json: {:?}", err);
+                ServiceError::BadRequest("Failed to convert parquet rows to json".to_string())
+            })?;
+        let json_rows = json_writer.into_inner();
+        if json_rows.is_empty() {
+            continue;
+        }
+
+        let rows: Vec<serde_json::Value> = serde_json::from_slice(&json_rows).map_err(|err| {
+            log::error!("Failed to parse parquet rows: {:?}", err);
+            ServiceError::BadRequest("Failed to parse parquet rows".to_string())
+        })?;
+
+        for row in rows {
+            row_importer.process_row(row).await?;
+        }
+    }
+
+    Ok(file_size)
+}
+
+/// Creates or updates the groups referenced by group_tracking_ids mappings with the group metadata mapped from their rows so they exist before the chunks are ingested.
+async fn upsert_groups_with_metadata(
+    group_metadatas: &mut HashMap<String, serde_json::Value>,
+    dataset_id: uuid::Uuid,
+    web_pool: actix_web::web::Data<models::Pool>,
+) -> Result<(), ServiceError> {
+    if group_metadatas.is_empty() {
+        return Ok(());
+    }
+
+    let groups = group_metadatas
+        .drain()
+        .map(|(group_tracking_id, metadata)| {
+            ChunkGroup::from_details(
+                Some(group_tracking_id.clone()),
+                None,
+                dataset_id,
+                Some(group_tracking_id),
+                Some(metadata),
+                None,
+            )
+        })
+        .collect::<Vec<ChunkGroup>>();
+
+    create_groups_query(groups, true, web_pool)
+        .await
+        .map_err(|err| {
+            log::error!("Could not upsert groups with metadata {:?}", err);
+            ServiceError::BadRequest("Could not upsert groups with metadata".to_string())
+        })?;
+
+    Ok(())
+}
+
 fn parse_csv_line(line: &str) -> Vec<String> {
     let mut fields = Vec::new();
     let mut current_field = String::new();
@@ -598,13 +1050,160 @@ fn parse_csv_line(line: &str) -> Vec<String> {
     fields
 }
 
+static TEMPLATE_FIELD_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{\{\s*([^}]+?)\s*\}\}").expect("Valid template regex"));
+
+fn value_to_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(val) => val.to_string(),
+        serde_json::Value::Null => String::new(),
+        val => val.to_string(),
+    }
+}
+
+fn get_strings_from_value(value: &serde_json::Value) -> Vec<String> {
+    if let Some(arr) = value.as_array() {
+        arr.iter()
+            .filter_map(|val| val.as_str().map(|val| val.to_string()))
+            .collect()
+    } else if let Some(arr) = get_array_from_string(value.as_str().unwrap_or("")) {
+        arr
+    } else if let Some(val) = value.as_str() {
+        vec![val.to_string()]
+    } else {
+        vec![]
+    }
+}
+
+fn render_chunk_html_template(template: &str, value: &serde_json::Value) -> String {
+    TEMPLATE_FIELD_REGEX
+        .replace_all(template, |captures: &regex::Captures| {
+            value
+                .get(&captures[1])
+                .map(value_to_text)
+                .unwrap_or_default()
+        })
+        .to_string()
+}
+
+fn insert_at_path(
+    target: &mut serde_json::Map<String, serde_json::Value>,
+    path: &str,
+    value: serde_json::Value,
+) {
+    let mut keys = path.split('.').peekable();
+    let mut current = target;
+    while let Some(key) = keys.next() {
+        if keys.peek().is_none() {
+            current.insert(key.to_string(), value);
+            return;
+        }
+
+        let entry = current
+            .entry(key.to_string())
+            .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+        if !entry.is_object() {
+            *entry = serde_json::Value::Object(serde_json::Map::new());
+        }
+        current = entry
+            .as_object_mut()
+            .expect("Entry must be an object after being replaced");
+    }
+}
+
+fn parse_date(value: &serde_json::Value, date_format: Option<&str>) -> Option<NaiveDateTime> {
+    match value {
+        serde_json::Value::Number(val) => val
+            .as_i64()
+            .and_then(|val| DateTime::from_timestamp(val, 0))
+            .map(|val| val.naive_utc()),
+        serde_json::Value::String(val) => {
+            let val = val.trim();
+            match date_format {
+                Some(date_format) => NaiveDateTime::parse_from_str(val, date_format)
+                    .ok()
+                    .or_else(|| {
+                        NaiveDate::parse_from_str(val, date_format)
+                            .ok()
+                            .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    }),
+                None => val
+                    .parse::<NaiveDateTime>()
+                    .ok()
+                    .or_else(|| {
+                        DateTime::parse_from_rfc3339(val)
+                            .ok()
+                            .map(|val| val.naive_utc())
+                    })
+                    .or_else(|| {
+                        val.parse::<NaiveDate>()
+                            .ok()
+                            .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    })
+                    .or_else(|| {
+                        val.parse::<i64>()
+                            .ok()
+                            .and_then(|val| DateTime::from_timestamp(val, 0))
+                            .map(|val| val.naive_utc())
+                    }),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn coerce_value(
+    value: &serde_json::Value,
+    value_type: MappingValueType,
+    date_format: Option<&str>,
+) -> Option<serde_json::Value> {
+    match value_type {
+        MappingValueType::String => Some(serde_json::Value::String(value_to_text(value))),
+        MappingValueType::Number => match value {
+            serde_json::Value::Number(_) => Some(value.clone()),
+            serde_json::Value::String(val) => val
+                .trim()
+                .replace(',', "")
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(serde_json::Value::Number),
+            serde_json::Value::Bool(val) => Some(serde_json::json!(if *val { 1 } else { 0 })),
+            _ => None,
+        },
+        MappingValueType::Bool => match value {
+            serde_json::Value::Bool(_) => Some(value.clone()),
+            serde_json::Value::Number(val) => val.as_f64().map(|val| (val != 0.0).into()),
+            serde_json::Value::String(val) => match val.trim().to_lowercase().as_str() {
+                "true" | "yes" | "y" | "1" => Some(true.into()),
+                "false" | "no" | "n" | "0" => Some(false.into()),
+                _ => None,
+            },
+            _ => None,
+        },
+        MappingValueType::Date => parse_date(value, date_format)
+            .map(|val| serde_json::Value::String(val.format("%Y-%m-%dT%H:%M:%S").to_string())),
+    }
+}
+
+/// A row which was converted into a ChunkReqPayload along with the group metadata mapped from it.
+struct ConvertedRow {
+    chunk_req_payload: ChunkReqPayload,
+    group_metadata: Option<serde_json::Value>,
+}
+
 fn convert_value_to_chunkreqpayload(
     value: serde_json::Value,
     group_id: Option<uuid::Uuid>,
-    mappings: Vec<ChunkReqPayloadMapping>,
+    mappings: &[ChunkReqPayloadMapping],
+    chunk_html_template: Option<&str>,
     fulltext_boost_factor: Option<f64>,
     semantic_boost_factor: Option<f64>,
-) -> ChunkReqPayload {
+) -> Result<ConvertedRow, String> {
+    if !value.is_object() {
+        return Err("Row must be a JSON object".to_string());
+    }
+
     let cleaned_value = match value.clone() {
         serde_json::Value::Object(obj) => {
             let mut new_obj = serde_json::Map::new();
@@ -642,77 +1241,88 @@ fn convert_value_to_chunkreqpayload(
         fulltext_boost: None,
         semantic_boost: None,
         high_priority: None,
+        acl: None,
     };
 
     let mut boost_phrase = String::new();
     let mut lat: Option<GeoTypes> = None;
     let mut lon: Option<GeoTypes> = None;
+    let mut chunk_html_parts: Vec<String> = vec![];
+    let mut semantic_content_parts: Vec<String> = vec![];
+    let mut group_metadata: Option<serde_json::Map<String, serde_json::Value>> = None;
 
     for mapping in mappings {
-        match mapping.chunk_req_payload_field {
+        let mapped_value = match value.get(&mapping.csv_jsonl_field) {
+            Some(val) if !val.is_null() => match mapping.value_type {
+                Some(value_type) => Some(
+                    coerce_value(val, value_type, mapping.date_format.as_deref()).ok_or_else(
+                        || {
+                            format!(
+                                "Could not convert value {} of field {} to {}",
+                                val, mapping.csv_jsonl_field, value_type
+                            )
+                        },
+                    )?,
+                ),
+                None => Some(val.clone()),
+            },
+            _ => None,
+        };
+        let mapped_value = mapped_value.as_ref();
+
+        match &mapping.chunk_req_payload_field {
             ChunkReqPayloadFields::Link => {
-                let _ = value
-                    .get(mapping.csv_jsonl_field)
+                let _ = mapped_value
                     .and_then(|val| val.as_str())
                     .map(|val| val.to_string())
                     .map(|val| chunk_req_payload.link = Some(val));
             }
             ChunkReqPayloadFields::TagSet => {
                 let mut cur_tag_set = chunk_req_payload.tag_set.clone().unwrap_or_default();
-                if let Some(val) = value.get(mapping.csv_jsonl_field) {
-                    if let Some(arr) = val.as_array() {
-                        for tag in arr {
-                            if let Some(tag) = tag.as_str() {
-                                cur_tag_set.push(tag.to_string());
-                            }
-                        }
-                    } else if let Some(arr) = get_array_from_string(val.as_str().unwrap_or("")) {
-                        for tag in arr {
-                            cur_tag_set.push(tag);
-                        }
-                    } else if let Some(val) = val.as_str() {
-                        cur_tag_set.push(val.to_string());
-                    }
+                if let Some(val) = mapped_value {
+                    cur_tag_set.extend(get_strings_from_value(val));
                 }
 
                 chunk_req_payload.tag_set = Some(cur_tag_set);
             }
             ChunkReqPayloadFields::NumValue => {
-                let _ = value
-                    .get(mapping.csv_jsonl_field)
+                let _ = mapped_value
                     .and_then(|val| val.as_f64())
                     .map(|val| chunk_req_payload.num_value = Some(val));
             }
             ChunkReqPayloadFields::TrackingId => {
-                let _ = value
-                    .get(mapping.csv_jsonl_field)
-                    .map(|val| chunk_req_payload.tracking_id = Some(val.to_string()));
+                let _ =
+                    mapped_value.map(|val| chunk_req_payload.tracking_id = Some(val.to_string()));
             }
             ChunkReqPayloadFields::GroupTrackingIds => {
                 let mut cur_group_tracking_ids = chunk_req_payload
                     .group_tracking_ids
                     .clone()
                     .unwrap_or_default();
-                if let Some(val) = value.get(mapping.csv_jsonl_field) {
-                    if let Some(arr) = val.as_array() {
-                        for url in arr {
-                            if let Some(url) = url.as_str() {
-                                cur_group_tracking_ids.push(url.to_string());
-                            }
-                        }
-                    } else if let Some(arr) = get_array_from_string(val.as_str().unwrap_or("")) {
-                        for url in arr {
-                            cur_group_tracking_ids.push(url);
-                        }
-                    } else if let Some(val) = val.as_str() {
-                        cur_group_tracking_ids.push(val.to_string());
-                    }
+                if let Some(val) = mapped_value {
+                    cur_group_tracking_ids.extend(get_strings_from_value(val));
                 }
 
                 chunk_req_payload.group_tracking_ids = Some(cur_group_tracking_ids);
             }
+            ChunkReqPayloadFields::GroupIds => {
+                let mut cur_group_ids = chunk_req_payload.group_ids.clone().unwrap_or_default();
+                if let Some(val) = mapped_value {
+                    for group_id in get_strings_from_value(val) {
+                        let group_id = group_id.trim().parse::<uuid::Uuid>().map_err(|_| {
+                            format!(
+                                "Invalid group id {} in field {}",
+                                group_id, mapping.csv_jsonl_field
+                            )
+                        })?;
+                        cur_group_ids.push(group_id);
+                    }
+                }
+
+                chunk_req_payload.group_ids = Some(cur_group_ids);
+            }
             ChunkReqPayloadFields::TimeStamp => {
-                if let Some(val) = value.get(mapping.csv_jsonl_field) {
+                if let Some(val) = mapped_value {
                     match val {
                         serde_json::Value::String(val) => {
                             let _ = val
@@ -732,56 +1342,79 @@ fn convert_value_to_chunkreqpayload(
                 }
             }
             ChunkReqPayloadFields::Lat => {
-                let _ = value
-                    .get(mapping.csv_jsonl_field)
+                let _ = mapped_value
                     .and_then(|val| val.as_f64())
                     .map(|val| lat = Some(GeoTypes::Float(val)));
             }
             ChunkReqPayloadFields::Lon => {
-                let _ = value
-                    .get(mapping.csv_jsonl_field)
+                let _ = mapped_value
                     .and_then(|val| val.as_f64())
                     .map(|val| lon = Some(GeoTypes::Float(val)));
             }
             ChunkReqPayloadFields::ImageUrls => {
                 let mut cur_image_urls = chunk_req_payload.image_urls.clone().unwrap_or_default();
-                if let Some(val) = value.get(mapping.csv_jsonl_field) {
-                    if let Some(arr) = val.as_array() {
-                        for url in arr {
-                            if let Some(url) = url.as_str() {
-                                cur_image_urls.push(url.to_string());
-                            }
-                        }
-                    } else if let Some(arr) = get_array_from_string(val.as_str().unwrap_or("")) {
-                        for url in arr {
-                            cur_image_urls.push(url);
-                        }
-                    } else if let Some(val) = val.as_str() {
-                        cur_image_urls.push(val.to_string());
-                    }
+                if let Some(val) = mapped_value {
+                    cur_image_urls.extend(get_strings_from_value(val));
                 }
 
                 chunk_req_payload.image_urls = Some(cur_image_urls);
             }
             ChunkReqPayloadFields::Weight => {
-                let _ = value
-                    .get(mapping.csv_jsonl_field)
+                let _ = mapped_value
                     .and_then(|val| val.as_f64())
                     .map(|val| chunk_req_payload.weight = Some(val));
             }
             ChunkReqPayloadFields::BoostPhrase => {
-                let mut cur_boost_phrase = boost_phrase.clone();
-                if let Some(val) = value.get(mapping.csv_jsonl_field) {
-                    if let Some(val) = val.as_str() {
-                        cur_boost_phrase.push_str(format!(" {}", val).as_str());
-                    }
+                if let Some(val) = mapped_value.and_then(|val| val.as_str()) {
+                    boost_phrase.push_str(format!(" {}", val).as_str());
+                }
+            }
+            ChunkReqPayloadFields::ChunkHtml => {
+                if let Some(val) = mapped_value {
+                    chunk_html_parts.push(value_to_text(val));
+                }
+            }
+            ChunkReqPayloadFields::SemanticContent => {
+                if let Some(val) = mapped_value {
+                    semantic_content_parts.push(value_to_text(val));
+                }
+            }
+            ChunkReqPayloadFields::Metadata(path) => {
+                if let (Some(val), Some(serde_json::Value::Object(metadata))) =
+                    (mapped_value, chunk_req_payload.metadata.as_mut())
+                {
+                    insert_at_path(metadata, path, val.clone());
+                }
+            }
+            ChunkReqPayloadFields::GroupMetadata(path) => {
+                if let Some(val) = mapped_value {
+                    insert_at_path(
+                        group_metadata.get_or_insert_with(serde_json::Map::new),
+                        path,
+                        val.clone(),
+                    );
                 }
-
-                boost_phrase = cur_boost_phrase;
             }
         }
     }
 
+    if let Some(chunk_html_template) = chunk_html_template {
+        chunk_req_payload.chunk_html =
+            Some(render_chunk_html_template(chunk_html_template, &value));
+    } else if !chunk_html_parts.is_empty() {
+        chunk_req_payload.chunk_html = Some(chunk_html_parts.join("\n"));
+    }
+    if chunk_req_payload
+        .chunk_html
+        .as_ref()
+        .map_or(true, |chunk_html| chunk_html.trim().is_empty())
+    {
+        return Err("Row produced an empty chunk_html".to_string());
+    }
+    if !semantic_content_parts.is_empty() {
+        chunk_req_payload.semantic_content = Some(semantic_content_parts.join("\n"));
+    }
+
     if let Some(fulltext_boost_factor) = fulltext_boost_factor {
         chunk_req_payload.fulltext_boost = Some(FullTextBoost {
             phrase: boost_phrase.clone(),
@@ -800,7 +1433,10 @@ fn convert_value_to_chunkreqpayload(
         }
     }
 
-    chunk_req_payload
+    Ok(ConvertedRow {
+        chunk_req_payload,
+        group_metadata: group_metadata.map(serde_json::Value::Object),
+    })
 }
 
 fn get_array_from_string(string: &str) -> Option<Vec<String>> {
@@ -812,3 +1448,75 @@ fn get_array_from_string(string: &str) -> Option<Vec<String>> {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_parse_csv_value() {
+        assert_eq!(parse_csv_value("42"), serde_json::json!(42.0));
+        assert_eq!(parse_csv_value("None"), serde_json::Value::Null);
+        assert_eq!(parse_csv_value("true"), serde_json::json!(true));
+        assert_eq!(parse_csv_value("hello"), serde_json::json!("hello"));
+    }
+
+    #[test]
+    pub fn test_coerce_value() {
+        assert_eq!(
+            coerce_value(
+                &serde_json::json!("1,234.5"),
+                MappingValueType::Number,
+                None
+            ),
+            Some(serde_json::json!(1234.5))
+        );
+        assert_eq!(
+            coerce_value(&serde_json::json!("abc"), MappingValueType::Number, None),
+            None
+        );
+        assert_eq!(
+            coerce_value(&serde_json::json!(" Yes "), MappingValueType::Bool, None),
+            Some(serde_json::json!(true))
+        );
+        assert_eq!(
+            coerce_value(&serde_json::json!(0), MappingValueType::Bool, None),
+            Some(serde_json::json!(false))
+        );
+        assert_eq!(
+            coerce_value(&serde_json::json!(12), MappingValueType::String, None),
+            Some(serde_json::json!("12"))
+        );
+        assert_eq!(
+            coerce_value(
+                &serde_json::json!("03/15/2024"),
+                MappingValueType::Date,
+                Some("%m/%d/%Y")
+            ),
+            Some(serde_json::json!("2024-03-15T00:00:00"))
+        );
+        assert_eq!(
+            coerce_value(
+                &serde_json::json!("2024-03-15"),
+                MappingValueType::Date,
+                None
+            ),
+            Some(serde_json::json!("2024-03-15T00:00:00"))
+        );
+    }
+
+    #[test]
+    pub fn test_insert_at_path() {
+        let mut target = serde_json::Map::new();
+        target.insert("price".to_string(), serde_json::json!(10));
+        target.insert("a".to_string(), serde_json::json!("scalar"));
+
+        insert_at_path(&mut target, "a.b.c", serde_json::json!(1));
+        insert_at_path(&mut target, "a.b.d", serde_json::json!(2));
+
+        assert_eq!(
+            serde_json::Value::Object(target),
+            serde_json::json!({ "price": 10, "a": { "b": { "c": 1, "d": 2 } } })
+        );
+    }
+}
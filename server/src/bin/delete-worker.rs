@@ -5,8 +5,9 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
+use trieve_server::operators::analytics_store_operator::AnalyticsBackend;
 use trieve_server::{
-    data::models::{self, DatasetConfiguration},
+    data::models::{self, DatasetConfiguration, DeadLetterQueue},
     errors::ServiceError,
     establish_connection, get_env,
     operators::{
@@ -17,9 +18,12 @@ use trieve_server::{
             get_deleted_dataset_by_id_query, ChunkDeleteMessage, DatasetDeleteMessage,
             DeleteMessage,
         },
+        dead_letter_operator::add_dead_letter,
         organization_operator::{
             delete_actual_organization_query, get_soft_deleted_datasets_for_organization,
         },
+        trash_operator::purge_expired_trash_query,
+        worker_metrics_operator::observe_worker_job,
     },
 };
 
@@ -94,7 +98,9 @@ fn main() {
                     .with_option("async_insert", "1")
                     .with_option("wait_for_async_insert", "0");
 
-                let mut event_queue = EventQueue::new(clickhouse_client.clone());
+                let mut event_queue = EventQueue::from_analytics_backend(
+                    AnalyticsBackend::from_env(clickhouse_client.clone(), web_pool.clone()),
+                );
                 event_queue.start_service();
                 event_queue
             } else {
@@ -102,7 +108,9 @@ fn main() {
                 EventQueue::default()
             };
 
-            let web_event_queue = actix_web::web::Data::new(event_queue);
+            let web_event_queue = actix_web::web::Data::new(
+                event_queue.with_webhooks(web_redis_pool.get_ref().clone()),
+            );
 
             delete_worker(should_terminate, web_redis_pool, web_pool, web_event_queue).await
         });
@@ -147,6 +155,8 @@ async fn delete_worker(
         opt_redis_connection.expect("Failed to get redis connection outside of loop");
 
     let mut broken_pipe_sleep = std::time::Duration::from_secs(10);
+    let trash_purge_interval = std::time::Duration::from_secs(60);
+    let mut last_trash_purge = std::time::Instant::now() - trash_purge_interval;
 
     loop {
         if should_terminate.load(Ordering::Relaxed) {
@@ -154,6 +164,15 @@ async fn delete_worker(
             break;
         }
 
+        if last_trash_purge.elapsed() >= trash_purge_interval {
+            last_trash_purge = std::time::Instant::now();
+            match purge_expired_trash_query(web_pool.clone(), redis_pool.clone()).await {
+                Ok(0) => {}
+                Ok(purged) => log::info!("Purged {} expired trash items", purged),
+                Err(err) => log::error!("Failed to purge expired trash: {:?}", err),
+            }
+        }
+
         let payload_result: Result<Vec<String>, redis::RedisError> = redis::cmd("brpoplpush")
             .arg("delete_dataset_queue")
             .arg("delete_dataset_processing")
@@ -203,11 +222,16 @@ async fn delete_worker(
 
         match delete_worker_message {
             DeleteMessage::DatasetDelete(delete_worker_message) => {
-                if let Err(err) = delete_or_clear_dataset(
-                    web_pool.clone(),
-                    redis_pool.clone(),
-                    delete_worker_message.clone(),
-                    event_queue.clone(),
+                if let Err(err) = observe_worker_job(
+                    "delete-worker",
+                    Some(delete_worker_message.dataset_id),
+                    &redis_pool,
+                    delete_or_clear_dataset(
+                        web_pool.clone(),
+                        redis_pool.clone(),
+                        delete_worker_message.clone(),
+                        event_queue.clone(),
+                    ),
                 )
                 .await
                 {
@@ -230,8 +254,13 @@ async fn delete_worker(
                 }
             }
             DeleteMessage::ChunkDelete(chunk_delete_message) => {
-                if let Err(err) =
-                    bulk_delete_chunks(web_pool.clone(), chunk_delete_message.clone()).await
+                if let Err(err) = observe_worker_job(
+                    "delete-worker",
+                    Some(chunk_delete_message.dataset_id),
+                    &redis_pool,
+                    bulk_delete_chunks(web_pool.clone(), chunk_delete_message.clone()),
+                )
+                .await
                 {
                     let _ = readd_error_to_queue(
                         DeleteMessage::ChunkDelete(chunk_delete_message),
@@ -294,6 +323,16 @@ pub async fn delete_or_clear_dataset(
                 delete_worker_message.dataset_id
             );
 
+            event_queue
+                .send(ClickHouseEvent::WorkerEvent(
+                    models::WorkerEvent::from_details(
+                        delete_worker_message.dataset_id,
+                        models::EventType::DatasetCleared,
+                    )
+                    .into(),
+                ))
+                .await;
+
             return Ok(());
         }
 
@@ -315,6 +354,16 @@ pub async fn delete_or_clear_dataset(
             delete_worker_message.dataset_id
         );
 
+        event_queue
+            .send(ClickHouseEvent::WorkerEvent(
+                models::WorkerEvent::from_details(
+                    delete_worker_message.dataset_id,
+                    models::EventType::DatasetCleared,
+                )
+                .into(),
+            ))
+            .await;
+
         return Ok(());
     }
 
@@ -431,24 +480,22 @@ pub async fn readd_error_to_queue(
     let _ = redis::cmd("LREM")
         .arg("delete_dataset_processing")
         .arg(1)
-        .arg(old_payload_message.clone())
+        .arg(old_payload_message)
         .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
         .await;
 
     if payload.attempt_number() == 3 {
         log::error!("Failed to insert data 3 times quitting {:?}", error);
 
-        let mut redis_conn = redis_pool
-            .get()
-            .await
-            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
-
-        redis::cmd("lpush")
-            .arg("dead_letters_delete")
-            .arg(old_payload_message)
-            .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
-            .await
-            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+        add_dead_letter(
+            DeadLetterQueue::Delete,
+            "delete_dataset_queue",
+            payload.dataset_id(),
+            &payload,
+            error.to_string(),
+            &redis_pool,
+        )
+        .await?;
 
         event_queue
             .send(ClickHouseEvent::WorkerEvent(
@@ -6,6 +6,7 @@ use trieve_server::{
     operators::{
         dittofeed_operator::{get_user_ditto_identity, send_user_ditto_identity},
         user_operator::get_all_users_query,
+        worker_metrics_operator::observe_worker_job,
     },
 };
 
@@ -19,6 +20,22 @@ async fn main() -> Result<(), ServiceError> {
         .filter_level(log::LevelFilter::Info)
         .init();
 
+    let redis_url = get_env!("REDIS_URL", "REDIS_URL is not set");
+    let redis_connections: u32 = std::env::var("REDIS_CONNECTIONS")
+        .unwrap_or("2".to_string())
+        .parse()
+        .unwrap_or(2);
+
+    let redis_manager =
+        bb8_redis::RedisConnectionManager::new(redis_url).expect("Failed to connect to redis");
+
+    let redis_pool = bb8_redis::bb8::Pool::builder()
+        .max_size(redis_connections)
+        .connection_timeout(std::time::Duration::from_secs(2))
+        .build(redis_manager)
+        .await
+        .expect("Failed to create redis pool");
+
     let database_url = get_env!("DATABASE_URL", "DATABASE_URL is not set");
 
     let mut config = ManagerConfig::default();
@@ -60,7 +77,14 @@ async fn main() -> Result<(), ServiceError> {
     for user in users {
         match get_user_ditto_identity(user.clone(), pool.clone(), &clickhouse_client).await {
             Ok(identify_request) => {
-                match send_user_ditto_identity(identify_request).await {
+                match observe_worker_job(
+                    "dittofeed-sync-worker",
+                    None,
+                    &redis_pool,
+                    send_user_ditto_identity(identify_request),
+                )
+                .await
+                {
                     Ok(_) => {
                         log::info!("Sent ditto identity for user {}", user.email);
                     }
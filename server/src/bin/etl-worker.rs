@@ -10,11 +10,13 @@ use trieve_server::handlers::chunk_handler::{
     FullTextBoost, SemanticBoost, UpdateIngestionMessage,
 };
 use trieve_server::handlers::etl_handler::{EtlJobMessage, EtlJobRequest, EtlWebhookResponse};
+use trieve_server::operators::analytics_store_operator::AnalyticsBackend;
 use trieve_server::operators::chunk_operator::{get_chunk_boost_query, get_metadata_from_id_query};
 use trieve_server::operators::clickhouse_operator::ClickHouseEvent;
 use trieve_server::operators::dataset_operator::get_dataset_config_query;
 use trieve_server::operators::etl_operator::get_all_chunks_for_dataset_id;
 use trieve_server::operators::group_operator::get_groups_for_bookmark_query;
+use trieve_server::operators::worker_metrics_operator::observe_worker_job;
 use trieve_server::{
     data::models::Pool, establish_connection, get_env, operators::clickhouse_operator::EventQueue,
 };
@@ -287,7 +289,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
             .with_option("async_insert", "1")
             .with_option("wait_for_async_insert", "0");
 
-        let mut event_queue = EventQueue::new(clickhouse_client.clone());
+        let mut event_queue = EventQueue::from_analytics_backend(AnalyticsBackend::from_env(
+            clickhouse_client.clone(),
+            web_pool.clone(),
+        ));
         event_queue.start_service();
         event_queue
     } else {
@@ -308,6 +313,16 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .build()
         .await?;
 
+    let redis_manager =
+        bb8_redis::RedisConnectionManager::new(redis_url).expect("Failed to connect to redis");
+
+    let redis_pool = bb8_redis::bb8::Pool::builder()
+        .max_size(redis_connections)
+        .connection_timeout(std::time::Duration::from_secs(2))
+        .build(redis_manager)
+        .await
+        .expect("Failed to create redis pool");
+
     queue
         .clone()
         .process_messages_with_handlers(
@@ -317,7 +332,21 @@ async fn main() -> Result<(), Box<dyn Error>> {
             move |msg: BrokerMessage<EtlJobMessage>| {
                 let pool = web_pool.clone();
                 let broccoli_queue = queue.clone();
-                async move { etl_worker(msg.payload, pool.clone(), broccoli_queue.clone()).await }
+                let redis_pool = redis_pool.clone();
+                async move {
+                    let dataset_id = match &msg.payload {
+                        EtlJobMessage::CreateJob(job) => Some(job.dataset_id),
+                        EtlJobMessage::WebhookResponse(job) => job.job_id.parse().ok(),
+                    };
+
+                    observe_worker_job(
+                        "etl-worker",
+                        dataset_id,
+                        &redis_pool,
+                        etl_worker(msg.payload, pool.clone(), broccoli_queue.clone()),
+                    )
+                    .await
+                }
             },
             {
                 let event_queue = event_queue.clone();
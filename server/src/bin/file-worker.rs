@@ -9,6 +9,7 @@ use std::{
     error::Error,
     sync::{atomic::AtomicBool, Arc},
 };
+use trieve_server::operators::analytics_store_operator::AnalyticsBackend;
 use trieve_server::{
     data::models::{self, ChunkGroup, FileWorkerMessage},
     establish_connection, get_env,
@@ -18,6 +19,9 @@ use trieve_server::{
         dataset_operator::get_dataset_and_organization_from_dataset_id_query,
         file_operator::{create_file_chunks, get_aws_bucket, preprocess_file_to_chunks},
         group_operator::{create_group_from_file_query, create_groups_query},
+        ingestion_job_operator::complete_ingestion_job_queuing,
+        usage_operator::{meter_usage, with_usage_meter},
+        worker_metrics_operator::observe_worker_job,
     },
 };
 
@@ -82,7 +86,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
             .with_option("async_insert", "1")
             .with_option("wait_for_async_insert", "0");
 
-        let mut event_queue = EventQueue::new(clickhouse_client.clone());
+        let mut event_queue = EventQueue::from_analytics_backend(AnalyticsBackend::from_env(
+            clickhouse_client.clone(),
+            web_pool.clone(),
+        ));
         event_queue.start_service();
         event_queue
     } else {
@@ -90,7 +97,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
         EventQueue::default()
     };
 
-    let web_event_queue = actix_web::web::Data::new(event_queue);
+    let redis_manager =
+        bb8_redis::RedisConnectionManager::new(redis_url).expect("Failed to connect to redis");
+
+    let redis_pool = bb8_redis::bb8::Pool::builder()
+        .max_size(redis_connections)
+        .connection_timeout(std::time::Duration::from_secs(2))
+        .build(redis_manager)
+        .await
+        .expect("Failed to create redis pool");
+
+    let web_event_queue = actix_web::web::Data::new(event_queue.with_webhooks(redis_pool.clone()));
 
     let should_terminate = Arc::new(AtomicBool::new(false));
     signal_hook::flag::register(SIGTERM, Arc::clone(&should_terminate))
@@ -115,22 +132,44 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 let queue = queue.clone();
                 let web_pool = web_pool.clone();
                 let web_event_queue = web_event_queue.clone();
+                let redis_pool = redis_pool.clone();
                 move |msg| {
-                    file_worker(
-                        msg.payload,
-                        web_pool.clone(),
-                        web_event_queue.clone(),
-                        (*queue).clone(),
-                    )
+                    let queue = queue.clone();
+                    let web_pool = web_pool.clone();
+                    let web_event_queue = web_event_queue.clone();
+                    let redis_pool = redis_pool.clone();
+                    async move {
+                        observe_worker_job(
+                            "file-worker",
+                            Some(msg.payload.dataset_id),
+                            &redis_pool,
+                            file_worker(msg.payload, web_pool, web_event_queue, (*queue).clone()),
+                        )
+                        .await
+                    }
                 }
             },
             {
+                let web_pool = web_pool.clone();
                 move |msg| {
                     let event_queue = web_event_queue.clone();
+                    let web_pool = web_pool.clone();
                     async move {
                         let message = msg.payload;
                         log::info!("Uploaded file: {:?}", message.file_id);
 
+                        if let Some(ingestion_job_id) = message.ingestion_job_id {
+                            if let Err(err) = complete_ingestion_job_queuing(
+                                ingestion_job_id,
+                                message.dataset_id,
+                                web_pool.clone(),
+                            )
+                            .await
+                            {
+                                log::error!("Failed to complete ingestion job queuing: {:?}", err);
+                            }
+                        }
+
                         event_queue
                             .send(ClickHouseEvent::WorkerEvent(
                                 models::WorkerEvent::from_details(
@@ -149,10 +188,27 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 }
             },
             {
-                move |msg, err| async move {
-                    log::error!("Failed to upload file {:?}: {:?}", msg.payload.file_id, err);
+                let web_pool = web_pool.clone();
+                move |msg, err| {
+                    let web_pool = web_pool.clone();
+                    async move {
+                        log::error!("Failed to upload file {:?}: {:?}", msg.payload.file_id, err);
+
+                        // Chunks queued before the failure are still ingested, so the job finishes with whatever made it into the queue
+                        if let Some(ingestion_job_id) = msg.payload.ingestion_job_id {
+                            if let Err(err) = complete_ingestion_job_queuing(
+                                ingestion_job_id,
+                                msg.payload.dataset_id,
+                                web_pool,
+                            )
+                            .await
+                            {
+                                log::error!("Failed to complete ingestion job queuing: {:?}", err);
+                            }
+                        }
 
-                    Ok(())
+                        Ok(())
+                    }
                 }
             },
         )
@@ -166,11 +222,16 @@ async fn file_worker(
     event_queue: actix_web::web::Data<EventQueue>,
     broccoli_queue: BroccoliQueue,
 ) -> Result<(), BroccoliError> {
-    upload_file(
-        message.clone(),
-        web_pool.clone(),
-        event_queue.clone(),
-        broccoli_queue.clone(),
+    with_usage_meter(
+        message.dataset_id,
+        message.api_key_id,
+        &web_pool,
+        upload_file(
+            message.clone(),
+            web_pool.clone(),
+            event_queue.clone(),
+            broccoli_queue.clone(),
+        ),
     )
     .await
 }
@@ -480,6 +541,7 @@ async fn upload_file(
                             fulltext_boost: None,
                             semantic_boost: None,
                             high_priority: None,
+                            acl: None,
                         };
                         new_chunks.push(create_chunk_data);
                     }
@@ -495,6 +557,7 @@ async fn upload_file(
                         web_pool.clone(),
                         event_queue.clone(),
                         broccoli_queue.clone(),
+                        file_worker_message.ingestion_job_id,
                     )
                     .await?;
                 }
@@ -520,6 +583,8 @@ async fn upload_file(
             }
         }
 
+        meter_usage(|usage| usage.file_pages += processed_pages.len() as i64);
+
         return Ok(());
     }
 
@@ -595,6 +660,7 @@ async fn upload_file(
             fulltext_boost: None,
             semantic_boost: None,
             high_priority: None,
+            acl: None,
         };
 
         create_file_chunks(
@@ -606,6 +672,7 @@ async fn upload_file(
             web_pool.clone(),
             event_queue.clone(),
             broccoli_queue.clone(),
+            file_worker_message.ingestion_job_id,
         )
         .await?;
         return Ok(());
@@ -645,6 +712,7 @@ async fn upload_file(
             fulltext_boost: None,
             semantic_boost: None,
             high_priority: None,
+            acl: None,
         })
         .collect::<Vec<_>>();
 
@@ -657,6 +725,7 @@ async fn upload_file(
         web_pool.clone(),
         event_queue.clone(),
         broccoli_queue.clone(),
+        file_worker_message.ingestion_job_id,
     )
     .await?;
 
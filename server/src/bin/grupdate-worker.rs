@@ -4,6 +4,7 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
+use trieve_server::operators::analytics_store_operator::AnalyticsBackend;
 use trieve_server::{
     data::models,
     errors::ServiceError,
@@ -11,6 +12,7 @@ use trieve_server::{
     operators::{
         clickhouse_operator::{ClickHouseEvent, EventQueue},
         group_operator::{update_grouped_chunks_query, GroupUpdateMessage},
+        worker_metrics_operator::observe_worker_job,
     },
 };
 use trieve_server::{
@@ -85,7 +87,9 @@ fn main() {
                     .with_option("async_insert", "1")
                     .with_option("wait_for_async_insert", "0");
 
-                let mut event_queue = EventQueue::new(clickhouse_client.clone());
+                let mut event_queue = EventQueue::from_analytics_backend(
+                    AnalyticsBackend::from_env(clickhouse_client.clone(), web_pool.clone()),
+                );
                 event_queue.start_service();
                 event_queue
             } else {
@@ -211,11 +215,16 @@ async fn grupdate_worker(
         };
         let service_config = DatasetConfiguration::from_json(dataset.server_configuration);
 
-        match update_grouped_chunks_query(
-            group_update_msg.prev_group.clone(),
-            group_update_msg.group.clone(),
-            web_pool.clone(),
-            service_config.clone(),
+        match observe_worker_job(
+            "grupdate-worker",
+            Some(group_update_msg.dataset_id),
+            &redis_pool,
+            update_grouped_chunks_query(
+                group_update_msg.prev_group.clone(),
+                group_update_msg.group.clone(),
+                web_pool.clone(),
+                service_config.clone(),
+            ),
         )
         .await
         {
@@ -12,13 +12,14 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::sync::{atomic::AtomicBool, Arc};
 use trieve_server::data::models::{
-    self, ChunkBoost, ChunkData, ChunkGroup, ChunkMetadata, DatasetConfiguration,
-    PagefindIndexWorkerMessage, QdrantPayload, WorkerEvent,
+    self, ChunkBoost, ChunkData, ChunkGroup, ChunkMetadata, DatasetConfiguration, DeadLetterQueue,
+    IngestionBatchStatus, PagefindIndexWorkerMessage, QdrantPayload, WorkerEvent,
 };
 use trieve_server::errors::ServiceError;
 use trieve_server::handlers::chunk_handler::{
     BulkUploadIngestionMessage, FullTextBoost, SemanticBoost, UploadIngestionMessage,
 };
+use trieve_server::operators::analytics_store_operator::AnalyticsBackend;
 use trieve_server::operators::chunk_operator::{
     bulk_insert_chunk_metadata_query, bulk_revert_insert_chunk_metadata_query,
     get_row_count_for_organization_id_query, insert_chunk_boost, insert_chunk_metadata_query,
@@ -28,9 +29,15 @@ use trieve_server::operators::clickhouse_operator::{ClickHouseEvent, EventQueue}
 use trieve_server::operators::dataset_operator::{
     get_dataset_and_organization_from_dataset_id_query, get_dataset_by_id_query,
 };
+use trieve_server::operators::dead_letter_operator::{
+    add_dead_letter, broccoli_retry_strategy, is_last_broccoli_attempt,
+};
 use trieve_server::operators::group_operator::{
     create_groups_query, get_group_ids_from_tracking_ids_query, get_groups_from_group_ids_query,
 };
+use trieve_server::operators::ingestion_job_operator::{
+    queue_ingestion_job_webhook_if_complete, set_ingestion_batch_status_query,
+};
 use trieve_server::operators::model_operator::{
     get_bm25_embeddings, get_dense_vectors, get_sparse_vectors,
 };
@@ -38,6 +45,8 @@ use trieve_server::operators::parse_operator::{
     average_embeddings, coarse_doc_chunker, convert_html_to_text,
 };
 use trieve_server::operators::qdrant_operator::bulk_upsert_qdrant_points_query;
+use trieve_server::operators::usage_operator::with_usage_meter;
+use trieve_server::operators::worker_metrics_operator::observe_worker_job;
 use trieve_server::{establish_connection, get_env};
 
 #[tokio::main]
@@ -83,7 +92,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let queue = BroccoliQueue::builder(redis_url)
         .pool_connections(redis_connections.try_into().unwrap())
-        .failed_message_retry_strategy(Default::default())
+        .failed_message_retry_strategy(broccoli_retry_strategy())
         .build()
         .await?;
 
@@ -104,7 +113,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
             .with_option("async_insert", "1")
             .with_option("wait_for_async_insert", "0");
 
-        let mut event_queue = EventQueue::new(clickhouse_client.clone());
+        let mut event_queue = EventQueue::from_analytics_backend(AnalyticsBackend::from_env(
+            clickhouse_client.clone(),
+            web_pool.clone(),
+        ));
         event_queue.start_service();
         event_queue
     } else {
@@ -112,7 +124,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
         EventQueue::default()
     };
 
-    let web_event_queue = actix_web::web::Data::new(event_queue);
+    let web_event_queue = actix_web::web::Data::new(event_queue.with_webhooks(redis_pool.clone()));
+    let failed_web_event_queue = web_event_queue.clone();
     let queue_name = std::env::var("INGESTION_QUEUE_NAME").unwrap_or("ingestion".to_string());
 
     let should_terminate = Arc::new(AtomicBool::new(false));
@@ -120,6 +133,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .expect("Failed to register shutdown hook");
 
     let ingestion_web_pool = web_pool.clone();
+    let ingestion_redis_pool = redis_pool.clone();
 
     log::info!("Starting ingestion service thread");
 
@@ -130,7 +144,16 @@ async fn main() -> Result<(), Box<dyn Error>> {
             Some(ConsumeOptionsBuilder::new().fairness(true).build()),
             move |msg| {
                 let pool = ingestion_web_pool.clone();
-                async move { ingestion_worker(msg.payload, pool.clone()).await }
+                let redis_pool = ingestion_redis_pool.clone();
+                async move {
+                    observe_worker_job(
+                        "ingestion-worker",
+                        Some(msg.payload.dataset_id),
+                        &redis_pool,
+                        ingestion_worker(msg.payload, pool.clone()),
+                    )
+                    .await
+                }
             },
             {
                 let web_pool = web_pool.clone();
@@ -207,17 +230,84 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                     msg.payload.dataset_id,
                                     models::EventType::ChunksUploaded { chunk_ids },
                                 )
+                                .with_ingestion_job_id(msg.payload.ingestion_job_id)
                                 .into(),
                             ))
                             .await;
 
+                        update_ingestion_job_batch(
+                            &msg.payload,
+                            IngestionBatchStatus::Done,
+                            None,
+                            web_pool.clone(),
+                        )
+                        .await;
+
                         Ok(())
                     }
                 }
             },
-            |_msg, err| async move {
-                log::error!("Failed to upload chunks: {:?}", err);
-                Ok(())
+            {
+                let web_pool = web_pool.clone();
+                let event_queue = failed_web_event_queue.clone();
+                let redis_pool = redis_pool.clone();
+                let queue_name = queue_name.clone();
+                move |msg: BrokerMessage<BulkUploadIngestionMessage>, err| {
+                    let web_pool = web_pool.clone();
+                    let event_queue = event_queue.clone();
+                    let redis_pool = redis_pool.clone();
+                    let queue_name = queue_name.clone();
+
+                    async move {
+                        log::error!("Failed to upload chunks: {:?}", err);
+
+                        if is_last_broccoli_attempt(msg.attempts) {
+                            if let Err(dead_letter_err) = add_dead_letter(
+                                DeadLetterQueue::Ingestion,
+                                &queue_name,
+                                msg.payload.dataset_id,
+                                &msg.payload,
+                                err.to_string(),
+                                &redis_pool,
+                            )
+                            .await
+                            {
+                                log::error!("Failed to add dead letter: {:?}", dead_letter_err);
+                            }
+                        }
+
+                        let chunk_ids = msg
+                            .payload
+                            .ingestion_messages
+                            .iter()
+                            .map(|message| message.ingest_specific_chunk_metadata.id)
+                            .collect::<Vec<uuid::Uuid>>();
+
+                        event_queue
+                            .send(ClickHouseEvent::WorkerEvent(
+                                WorkerEvent::from_details(
+                                    msg.payload.dataset_id,
+                                    models::EventType::BulkChunkUploadFailed {
+                                        chunk_ids,
+                                        error: err.to_string(),
+                                    },
+                                )
+                                .with_ingestion_job_id(msg.payload.ingestion_job_id)
+                                .into(),
+                            ))
+                            .await;
+
+                        update_ingestion_job_batch(
+                            &msg.payload,
+                            IngestionBatchStatus::Failed,
+                            Some(err.to_string()),
+                            web_pool,
+                        )
+                        .await;
+
+                        Ok(())
+                    }
+                }
             },
         )
         .await?;
@@ -242,6 +332,14 @@ async fn ingestion_worker(
     };
     let dataset_config = DatasetConfiguration::from_json(dataset.server_configuration);
 
+    update_ingestion_job_batch(
+        &ingestion_message,
+        IngestionBatchStatus::Processing,
+        None,
+        web_pool.clone(),
+    )
+    .await;
+
     log::info!(
         "Starting bulk upload of {} chunks for dataset_id: {:?}",
         ingestion_message.ingestion_messages.len(),
@@ -250,15 +348,52 @@ async fn ingestion_worker(
 
     let reqwest_client = reqwest::Client::new();
 
-    bulk_upload_chunks(
-        ingestion_message.clone(),
-        dataset_config.clone(),
-        web_pool.clone(),
-        reqwest_client.clone(),
+    with_usage_meter(
+        ingestion_message.dataset_id,
+        ingestion_message.api_key_id,
+        &web_pool,
+        bulk_upload_chunks(
+            ingestion_message.clone(),
+            dataset_config.clone(),
+            web_pool.clone(),
+            reqwest_client.clone(),
+        ),
     )
     .await
 }
 
+/// Records the status of the message's ingestion job batch and sends the job's webhook once it has finished. Failures are only logged so they never fail the ingestion itself.
+async fn update_ingestion_job_batch(
+    payload: &BulkUploadIngestionMessage,
+    status: IngestionBatchStatus,
+    error: Option<String>,
+    web_pool: actix_web::web::Data<models::Pool>,
+) {
+    let (Some(ingestion_job_id), Some(ingestion_batch_id)) =
+        (payload.ingestion_job_id, payload.ingestion_batch_id)
+    else {
+        return;
+    };
+
+    if let Err(err) =
+        set_ingestion_batch_status_query(ingestion_batch_id, status, error, web_pool.clone()).await
+    {
+        log::error!("Failed to update ingestion job batch status: {:?}", err);
+        return;
+    }
+
+    if status == IngestionBatchStatus::Processing {
+        return;
+    }
+
+    if let Err(err) =
+        queue_ingestion_job_webhook_if_complete(ingestion_job_id, payload.dataset_id, web_pool)
+            .await
+    {
+        log::error!("Failed to queue ingestion job webhook: {:?}", err);
+    }
+}
+
 pub async fn bulk_upload_chunks(
     payload: BulkUploadIngestionMessage,
     dataset_config: DatasetConfiguration,
@@ -430,6 +565,11 @@ pub async fn bulk_upload_chunks(
                     .map(|urls| urls.into_iter().map(Some).collect()),
                 tag_set: chunk_tag_set,
                 num_value: message.chunk.num_value,
+                acl: message
+                    .chunk
+                    .acl
+                    .clone()
+                    .map(|acl| acl.into_iter().map(Some).collect()),
             };
 
             let group_ids_from_group_tracking_ids: Vec<uuid::Uuid> =
@@ -855,6 +995,10 @@ async fn upload_chunk(
             .map(|urls| urls.into_iter().map(Some).collect()),
         tag_set: chunk_tag_set,
         num_value: payload.chunk.num_value,
+        acl: payload
+            .chunk
+            .acl
+            .map(|acl| acl.into_iter().map(Some).collect()),
     };
 
     if content.is_empty() {
@@ -4,6 +4,7 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
+use trieve_server::operators::analytics_store_operator::AnalyticsBackend;
 use trieve_server::{
     data::models::{self, DatasetConfiguration, PagefindIndexWorkerMessage, WorkerEvent},
     errors::ServiceError,
@@ -12,6 +13,7 @@ use trieve_server::{
         clickhouse_operator::{ClickHouseEvent, EventQueue},
         dataset_operator::get_dataset_by_id_query,
         pagefind_operator,
+        worker_metrics_operator::observe_worker_job,
     },
 };
 
@@ -82,7 +84,9 @@ fn main() {
                     .with_option("async_insert", "1")
                     .with_option("wait_for_async_insert", "0");
 
-                let mut event_queue = EventQueue::new(clickhouse_client.clone());
+                let mut event_queue = EventQueue::from_analytics_backend(
+                    AnalyticsBackend::from_env(clickhouse_client.clone(), web_pool.clone()),
+                );
                 event_queue.start_service();
                 event_queue
             } else {
@@ -193,7 +197,14 @@ async fn pagefind_indexer(
         let message: PagefindIndexWorkerMessage =
             serde_json::from_str(&serialized_message).expect("Failed to parse file message");
 
-        match process_pagefind_index(message.clone(), web_pool.clone(), &event_queue).await {
+        match observe_worker_job(
+            "pagefind-worker",
+            Some(message.dataset_id),
+            &redis_pool,
+            process_pagefind_index(message.clone(), web_pool.clone(), &event_queue),
+        )
+        .await
+        {
             Ok(_) => {
                 log::info!("Successfully processed pagefind index");
             }
@@ -0,0 +1,117 @@
+use chm::tools::migrations::SetupArgs;
+use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
+use trieve_server::{
+    errors::ServiceError,
+    establish_connection, get_env,
+    operators::{
+        analytics_store_operator::AnalyticsBackend,
+        dataset_operator::{get_all_dataset_ids, get_dataset_by_id_query},
+        query_clustering_operator::cluster_dataset_queries,
+        worker_metrics_operator::observe_worker_job,
+    },
+};
+
+#[tokio::main]
+async fn main() -> Result<(), ServiceError> {
+    dotenvy::dotenv().ok();
+    env_logger::builder()
+        .target(env_logger::Target::Stdout)
+        .filter_level(log::LevelFilter::Info)
+        .init();
+    log::info!("Starting query clustering cronjob");
+    AnalyticsBackend::require_clickhouse("Clustering search queries")?;
+
+    let window_hours: i64 = std::env::var("QUERY_CLUSTERING_WINDOW_HOURS")
+        .unwrap_or("168".to_string())
+        .parse()
+        .unwrap_or(168);
+
+    let redis_url = get_env!("REDIS_URL", "REDIS_URL is not set");
+    let redis_connections: u32 = std::env::var("REDIS_CONNECTIONS")
+        .unwrap_or("2".to_string())
+        .parse()
+        .unwrap_or(2);
+
+    let redis_manager =
+        bb8_redis::RedisConnectionManager::new(redis_url).expect("Failed to connect to redis");
+
+    let redis_pool = bb8_redis::bb8::Pool::builder()
+        .max_size(redis_connections)
+        .connection_timeout(std::time::Duration::from_secs(2))
+        .build(redis_manager)
+        .await
+        .expect("Failed to create redis pool");
+
+    let database_url = get_env!("DATABASE_URL", "DATABASE_URL is not set");
+
+    let mut config = ManagerConfig::default();
+    config.custom_setup = Box::new(establish_connection);
+
+    let mgr = AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new_with_config(
+        database_url,
+        config,
+    );
+
+    let pool = diesel_async::pooled_connection::deadpool::Pool::builder(mgr)
+        .max_size(3)
+        .build()
+        .expect("Failed to create diesel_async pool");
+
+    let pool = actix_web::web::Data::new(pool.clone());
+
+    let args = SetupArgs {
+        url: Some(get_env!("CLICKHOUSE_URL", "CLICKHOUSE_URL is not set").to_string()),
+        user: Some(get_env!("CLICKHOUSE_USER", "CLICKHOUSE_USER is not set").to_string()),
+        password: Some(
+            get_env!("CLICKHOUSE_PASSWORD", "CLICKHOUSE_PASSWORD is not set").to_string(),
+        ),
+        database: Some(get_env!("CLICKHOUSE_DB", "CLICKHOUSE_DB is not set").to_string()),
+    };
+
+    let clickhouse_client = clickhouse::Client::default()
+        .with_url(args.url.as_ref().unwrap())
+        .with_user(args.user.as_ref().unwrap())
+        .with_password(args.password.as_ref().unwrap())
+        .with_database(args.database.as_ref().unwrap())
+        .with_option("async_insert", "1")
+        .with_option("wait_for_async_insert", "0");
+
+    let since = chrono::Utc::now().naive_utc() - chrono::Duration::hours(window_hours);
+
+    for dataset_id in get_all_dataset_ids(pool.clone()).await? {
+        let dataset = match get_dataset_by_id_query(dataset_id, pool.clone()).await {
+            Ok(dataset) => dataset,
+            Err(err) => {
+                log::error!("Failed to get dataset {}: {:?}", dataset_id, err);
+                continue;
+            }
+        };
+
+        match observe_worker_job(
+            "query-clustering-cronjob",
+            Some(dataset_id),
+            &redis_pool,
+            cluster_dataset_queries(&dataset, since, &clickhouse_client),
+        )
+        .await
+        {
+            Ok(0) => {}
+            Ok(clusters) => {
+                log::info!(
+                    "Clustered the queries of dataset {} into {} topics",
+                    dataset_id,
+                    clusters
+                );
+            }
+            Err(err) => {
+                log::error!(
+                    "Failed to cluster the queries of dataset {}: {:?}",
+                    dataset_id,
+                    err
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
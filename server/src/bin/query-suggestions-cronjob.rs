@@ -0,0 +1,125 @@
+use chm::tools::migrations::SetupArgs;
+use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
+use trieve_server::{
+    errors::ServiceError,
+    establish_connection, get_env,
+    operators::{
+        analytics_store_operator::AnalyticsBackend,
+        dataset_operator::get_all_dataset_ids,
+        query_suggestion_operator::{mine_query_suggestions, upsert_query_suggestions_query},
+        worker_metrics_operator::observe_worker_job,
+    },
+};
+
+#[tokio::main]
+async fn main() -> Result<(), ServiceError> {
+    dotenvy::dotenv().ok();
+    env_logger::builder()
+        .target(env_logger::Target::Stdout)
+        .filter_level(log::LevelFilter::Info)
+        .init();
+    log::info!("Starting query suggestions cronjob");
+    AnalyticsBackend::require_clickhouse("Mining query suggestions")?;
+
+    let window_hours: i64 = std::env::var("QUERY_SUGGESTIONS_WINDOW_HOURS")
+        .unwrap_or("24".to_string())
+        .parse()
+        .unwrap_or(24);
+    let min_occurrences: i64 = std::env::var("QUERY_SUGGESTIONS_MIN_OCCURRENCES")
+        .unwrap_or("3".to_string())
+        .parse()
+        .unwrap_or(3);
+
+    let redis_url = get_env!("REDIS_URL", "REDIS_URL is not set");
+    let redis_connections: u32 = std::env::var("REDIS_CONNECTIONS")
+        .unwrap_or("2".to_string())
+        .parse()
+        .unwrap_or(2);
+
+    let redis_manager =
+        bb8_redis::RedisConnectionManager::new(redis_url).expect("Failed to connect to redis");
+
+    let redis_pool = bb8_redis::bb8::Pool::builder()
+        .max_size(redis_connections)
+        .connection_timeout(std::time::Duration::from_secs(2))
+        .build(redis_manager)
+        .await
+        .expect("Failed to create redis pool");
+
+    let database_url = get_env!("DATABASE_URL", "DATABASE_URL is not set");
+
+    let mut config = ManagerConfig::default();
+    config.custom_setup = Box::new(establish_connection);
+
+    let mgr = AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new_with_config(
+        database_url,
+        config,
+    );
+
+    let pool = diesel_async::pooled_connection::deadpool::Pool::builder(mgr)
+        .max_size(3)
+        .build()
+        .expect("Failed to create diesel_async pool");
+
+    let pool = actix_web::web::Data::new(pool.clone());
+
+    let args = SetupArgs {
+        url: Some(get_env!("CLICKHOUSE_URL", "CLICKHOUSE_URL is not set").to_string()),
+        user: Some(get_env!("CLICKHOUSE_USER", "CLICKHOUSE_USER is not set").to_string()),
+        password: Some(
+            get_env!("CLICKHOUSE_PASSWORD", "CLICKHOUSE_PASSWORD is not set").to_string(),
+        ),
+        database: Some(get_env!("CLICKHOUSE_DB", "CLICKHOUSE_DB is not set").to_string()),
+    };
+
+    let clickhouse_client = clickhouse::Client::default()
+        .with_url(args.url.as_ref().unwrap())
+        .with_user(args.user.as_ref().unwrap())
+        .with_password(args.password.as_ref().unwrap())
+        .with_database(args.database.as_ref().unwrap())
+        .with_option("async_insert", "1")
+        .with_option("wait_for_async_insert", "0");
+
+    let since = chrono::Utc::now().naive_utc() - chrono::Duration::hours(window_hours);
+
+    for dataset_id in get_all_dataset_ids(pool.clone()).await? {
+        let suggestions = match observe_worker_job(
+            "query-suggestions-cronjob",
+            Some(dataset_id),
+            &redis_pool,
+            mine_query_suggestions(dataset_id, since, min_occurrences, &clickhouse_client),
+        )
+        .await
+        {
+            Ok(suggestions) => suggestions,
+            Err(err) => {
+                log::error!(
+                    "Failed to mine query suggestions for dataset {}: {:?}",
+                    dataset_id,
+                    err
+                );
+                continue;
+            }
+        };
+
+        if suggestions.is_empty() {
+            continue;
+        }
+
+        log::info!(
+            "Mined {} query suggestions for dataset {}",
+            suggestions.len(),
+            dataset_id
+        );
+
+        if let Err(err) = upsert_query_suggestions_query(suggestions, &pool).await {
+            log::error!(
+                "Failed to save query suggestions for dataset {}: {:?}",
+                dataset_id,
+                err
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -8,7 +8,10 @@ use trieve_server::{
     data::models::{MigratePointMessage, MigrationMode},
     errors::ServiceError,
     get_env,
-    operators::{model_operator::get_bm25_embeddings, qdrant_operator::get_qdrant_connection},
+    operators::{
+        model_operator::get_bm25_embeddings, qdrant_operator::get_qdrant_connection,
+        worker_metrics_operator::observe_worker_job,
+    },
 };
 
 #[allow(clippy::print_stdout)]
@@ -135,13 +138,18 @@ async fn main() -> Result<(), ServiceError> {
 
         let result = match migration_message.mode {
             MigrationMode::BM25 { average_len, k, b } => {
-                migrate_bm25(
-                    qdrant_client,
-                    points,
-                    migration_message.to_collection,
-                    average_len,
-                    b,
-                    k,
+                observe_worker_job(
+                    "reindex-worker",
+                    None,
+                    &redis_pool,
+                    migrate_bm25(
+                        qdrant_client,
+                        points,
+                        migration_message.to_collection,
+                        average_len,
+                        b,
+                        k,
+                    ),
                 )
                 .await
             }
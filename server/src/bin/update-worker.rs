@@ -5,6 +5,7 @@ use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfi
 use trieve_server::data::models::{ChunkBoost, EventType, WorkerEvent};
 use trieve_server::errors::ServiceError;
 use trieve_server::handlers::group_handler::dataset_owns_group;
+use trieve_server::operators::analytics_store_operator::AnalyticsBackend;
 use trieve_server::operators::chunk_operator::{
     update_chunk_boost_query, update_chunk_metadata_query,
 };
@@ -15,6 +16,7 @@ use trieve_server::operators::model_operator::{
 };
 use trieve_server::operators::parse_operator::convert_html_to_text;
 use trieve_server::operators::qdrant_operator::update_qdrant_point_query;
+use trieve_server::operators::worker_metrics_operator::observe_worker_job;
 
 use std::error::Error;
 use trieve_server::{
@@ -64,7 +66,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
             .with_option("async_insert", "1")
             .with_option("wait_for_async_insert", "0");
 
-        let mut event_queue = EventQueue::new(clickhouse_client.clone());
+        let mut event_queue = EventQueue::from_analytics_backend(AnalyticsBackend::from_env(
+            clickhouse_client.clone(),
+            web_pool.clone(),
+        ));
         event_queue.start_service();
         event_queue
     } else {
@@ -85,6 +90,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .build()
         .await?;
 
+    let redis_manager =
+        bb8_redis::RedisConnectionManager::new(redis_url).expect("Failed to connect to redis");
+
+    let redis_pool = bb8_redis::bb8::Pool::builder()
+        .max_size(redis_connections)
+        .connection_timeout(std::time::Duration::from_secs(2))
+        .build(redis_manager)
+        .await
+        .expect("Failed to create redis pool");
+
+    let event_queue = event_queue.with_webhooks(redis_pool.clone());
+
     queue
         .process_messages_with_handlers(
             "update_chunk_queue",
@@ -93,7 +110,16 @@ async fn main() -> Result<(), Box<dyn Error>> {
             {
                 move |msg| {
                     let pool = web_pool.clone();
-                    async move { update_chunk(msg.payload, pool.clone()).await }
+                    let redis_pool = redis_pool.clone();
+                    async move {
+                        observe_worker_job(
+                            "update-worker",
+                            Some(msg.payload.dataset_id),
+                            &redis_pool,
+                            update_chunk(msg.payload, pool.clone()),
+                        )
+                        .await
+                    }
                 }
             },
             {
@@ -0,0 +1,423 @@
+use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
+use signal_hook::consts::SIGTERM;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use trieve_server::{
+    data::models::{self, WebhookDeliveryStatus, WebhookEventType, WorkerEvent},
+    establish_connection, get_env,
+    operators::{
+        analytics_store_operator::AnalyticsBackend,
+        clickhouse_operator::WEBHOOK_EVENT_QUEUE,
+        dataset_webhook_operator::{
+            attempt_webhook_delivery_query, build_webhook_http_client,
+            claim_due_webhook_deliveries_query, create_webhook_deliveries_query,
+            get_datasets_subscribed_to_query, get_low_confidence_search_spikes_query,
+        },
+        ingestion_job_operator::{
+            attempt_ingestion_job_webhook_query, claim_due_ingestion_job_webhooks_query,
+        },
+        worker_metrics_operator::observe_worker_job,
+    },
+};
+
+fn main() {
+    dotenvy::dotenv().ok();
+    env_logger::builder()
+        .target(env_logger::Target::Stdout)
+        .filter_level(log::LevelFilter::Info)
+        .init();
+
+    let database_url = get_env!("DATABASE_URL", "DATABASE_URL is not set");
+
+    let mut config = ManagerConfig::default();
+    config.custom_setup = Box::new(establish_connection);
+
+    let mgr = AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new_with_config(
+        database_url,
+        config,
+    );
+
+    let pool = diesel_async::pooled_connection::deadpool::Pool::builder(mgr)
+        .max_size(3)
+        .build()
+        .expect("Failed to create diesel_async pool");
+
+    let web_pool = actix_web::web::Data::new(pool.clone());
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to create tokio runtime")
+        .block_on(async move {
+            let redis_url = get_env!("REDIS_URL", "REDIS_URL is not set");
+            let redis_connections: u32 = std::env::var("REDIS_CONNECTIONS")
+                .unwrap_or("2".to_string())
+                .parse()
+                .unwrap_or(2);
+
+            let redis_manager = bb8_redis::RedisConnectionManager::new(redis_url)
+                .expect("Failed to connect to redis");
+
+            let redis_pool = bb8_redis::bb8::Pool::builder()
+                .max_size(redis_connections)
+                .connection_timeout(std::time::Duration::from_secs(2))
+                .build(redis_manager)
+                .await
+                .expect("Failed to create redis pool");
+
+            let web_redis_pool = actix_web::web::Data::new(redis_pool);
+
+            let should_terminate = Arc::new(AtomicBool::new(false));
+            signal_hook::flag::register(SIGTERM, Arc::clone(&should_terminate))
+                .expect("Failed to register shutdown hook");
+
+            let use_analytics = std::env::var("USE_ANALYTICS")
+                .unwrap_or("false".to_string())
+                .parse()
+                .unwrap_or(false);
+            let clickhouse_client = if use_analytics && AnalyticsBackend::is_postgres_configured()
+            {
+                log::info!("Low confidence search spikes need the ClickHouse analytics backend and will not be sent");
+                None
+            } else if use_analytics {
+                log::info!("Analytics enabled, watching for low confidence search spikes");
+
+                Some(
+                    clickhouse::Client::default()
+                        .with_url(
+                            std::env::var("CLICKHOUSE_URL")
+                                .unwrap_or("http://localhost:8123".to_string()),
+                        )
+                        .with_user(
+                            std::env::var("CLICKHOUSE_USER").unwrap_or("default".to_string()),
+                        )
+                        .with_password(
+                            std::env::var("CLICKHOUSE_PASSWORD").unwrap_or("".to_string()),
+                        )
+                        .with_database(
+                            std::env::var("CLICKHOUSE_DATABASE").unwrap_or("default".to_string()),
+                        ),
+                )
+            } else {
+                log::info!("Analytics disabled, low confidence search spikes will not be sent");
+                None
+            };
+
+            webhook_worker(
+                should_terminate,
+                web_redis_pool,
+                web_pool,
+                clickhouse_client,
+            )
+            .await
+        });
+}
+
+async fn webhook_worker(
+    should_terminate: Arc<AtomicBool>,
+    redis_pool: actix_web::web::Data<models::RedisPool>,
+    web_pool: actix_web::web::Data<models::Pool>,
+    clickhouse_client: Option<clickhouse::Client>,
+) {
+    log::info!("Starting webhook worker service thread");
+
+    let mut redis_conn_sleep = std::time::Duration::from_secs(1);
+
+    #[allow(unused_assignments)]
+    let mut opt_redis_connection = None;
+
+    loop {
+        let borrowed_redis_connection = match redis_pool.get().await {
+            Ok(redis_connection) => Some(redis_connection),
+            Err(err) => {
+                log::error!("Failed to get redis connection outside of loop: {:?}", err);
+                None
+            }
+        };
+
+        if borrowed_redis_connection.is_some() {
+            opt_redis_connection = borrowed_redis_connection;
+            break;
+        }
+
+        log::info!(
+            "Retrying to get redis connection out of loop after {:?} secs",
+            redis_conn_sleep
+        );
+        tokio::time::sleep(redis_conn_sleep).await;
+        redis_conn_sleep = std::cmp::min(redis_conn_sleep * 2, std::time::Duration::from_secs(300));
+    }
+
+    let mut redis_connection =
+        opt_redis_connection.expect("Failed to get redis connection outside of loop");
+
+    let http_client = build_webhook_http_client().expect("Failed to create http client");
+
+    let mut broken_pipe_sleep = std::time::Duration::from_secs(10);
+    let delivery_interval = std::time::Duration::from_secs(5);
+    let mut last_delivery = std::time::Instant::now() - delivery_interval;
+    let spike_interval = std::time::Duration::from_secs(300);
+    let mut last_spike_check = std::time::Instant::now() - spike_interval;
+
+    loop {
+        if should_terminate.load(Ordering::Relaxed) {
+            log::info!("Shutting down");
+            break;
+        }
+
+        if last_delivery.elapsed() >= delivery_interval {
+            last_delivery = std::time::Instant::now();
+            send_due_deliveries(&http_client, &web_pool, &redis_pool).await;
+            send_due_ingestion_job_webhooks(&http_client, &web_pool, &redis_pool).await;
+        }
+
+        if let Some(clickhouse_client) = &clickhouse_client {
+            if last_spike_check.elapsed() >= spike_interval {
+                last_spike_check = std::time::Instant::now();
+                queue_low_confidence_search_spikes(
+                    clickhouse_client,
+                    redis_pool.clone(),
+                    &web_pool,
+                )
+                .await;
+            }
+        }
+
+        let payload_result: Result<Vec<String>, redis::RedisError> = redis::cmd("brpop")
+            .arg(WEBHOOK_EVENT_QUEUE)
+            .arg(1.0)
+            .query_async(&mut redis_connection.clone())
+            .await;
+
+        let serialized_event = if let Ok(payload) = payload_result {
+            broken_pipe_sleep = std::time::Duration::from_secs(10);
+
+            // brpop replies with the key followed by the value
+            match payload.get(1) {
+                Some(serialized_event) => serialized_event.clone(),
+                None => continue,
+            }
+        } else {
+            log::error!("Unable to process {:?}", payload_result);
+
+            if payload_result.is_err_and(|err| err.is_io_error()) {
+                log::error!("IO broken pipe error, trying to acquire new connection");
+                match redis_pool.get().await {
+                    Ok(redis_conn) => {
+                        log::info!("Got new redis connection after broken pipe! Resuming polling");
+                        redis_connection = redis_conn;
+                    }
+                    Err(err) => {
+                        log::error!(
+                                "Failed to get redis connection after broken pipe, will try again after {broken_pipe_sleep:?} secs, err: {:?}",
+                                err
+                            );
+                    }
+                }
+
+                tokio::time::sleep(broken_pipe_sleep).await;
+                broken_pipe_sleep =
+                    std::cmp::min(broken_pipe_sleep * 2, std::time::Duration::from_secs(300));
+            }
+
+            continue;
+        };
+
+        let event: WorkerEvent = match serde_json::from_str(&serialized_event) {
+            Ok(event) => event,
+            Err(err) => {
+                log::error!("Failed to parse webhook event {:?}", err);
+                continue;
+            }
+        };
+
+        let Some(event_type) = WebhookEventType::from_worker_event_type(&event.event_type) else {
+            continue;
+        };
+
+        let data = serde_json::from_str::<serde_json::Value>(&event.event_data)
+            .unwrap_or(serde_json::Value::String(event.event_data.clone()));
+
+        if let Err(err) = observe_worker_job(
+            "webhook-worker",
+            Some(event.dataset_id),
+            &redis_pool,
+            create_webhook_deliveries_query(event.dataset_id, event_type, data, &web_pool),
+        )
+        .await
+        {
+            log::error!("Failed to create webhook deliveries {:?}", err);
+        }
+    }
+}
+
+async fn send_due_deliveries(
+    http_client: &reqwest::Client,
+    web_pool: &actix_web::web::Data<models::Pool>,
+    redis_pool: &models::RedisPool,
+) {
+    let due_deliveries = match claim_due_webhook_deliveries_query(
+        50,
+        chrono::Duration::minutes(5),
+        web_pool,
+    )
+    .await
+    {
+        Ok(due_deliveries) => due_deliveries,
+        Err(err) => {
+            log::error!("Failed to claim webhook deliveries {:?}", err);
+            return;
+        }
+    };
+
+    for (delivery, webhook) in due_deliveries {
+        let delivery_id = delivery.id;
+        let dataset_id = webhook.dataset_id;
+        match observe_worker_job(
+            "webhook-worker",
+            Some(dataset_id),
+            redis_pool,
+            attempt_webhook_delivery_query(delivery, webhook, http_client, web_pool),
+        )
+        .await
+        {
+            Ok(WebhookDeliveryStatus::Failed) => {
+                log::warn!("Webhook delivery {} failed permanently", delivery_id)
+            }
+            Ok(_) => {}
+            Err(err) => log::error!(
+                "Failed to attempt webhook delivery {} {:?}",
+                delivery_id,
+                err
+            ),
+        }
+    }
+}
+
+async fn send_due_ingestion_job_webhooks(
+    http_client: &reqwest::Client,
+    web_pool: &actix_web::web::Data<models::Pool>,
+    redis_pool: &models::RedisPool,
+) {
+    let due_ingestion_jobs =
+        match claim_due_ingestion_job_webhooks_query(50, chrono::Duration::minutes(5), web_pool)
+            .await
+        {
+            Ok(due_ingestion_jobs) => due_ingestion_jobs,
+            Err(err) => {
+                log::error!("Failed to claim ingestion job webhooks {:?}", err);
+                return;
+            }
+        };
+
+    for ingestion_job in due_ingestion_jobs {
+        let ingestion_job_id = ingestion_job.id;
+        if let Err(err) = observe_worker_job(
+            "webhook-worker",
+            Some(ingestion_job.dataset_id),
+            redis_pool,
+            attempt_ingestion_job_webhook_query(ingestion_job, http_client, web_pool),
+        )
+        .await
+        {
+            log::error!(
+                "Failed to attempt ingestion job webhook {} {:?}",
+                ingestion_job_id,
+                err
+            );
+        }
+    }
+}
+
+async fn queue_low_confidence_search_spikes(
+    clickhouse_client: &clickhouse::Client,
+    redis_pool: actix_web::web::Data<models::RedisPool>,
+    web_pool: &actix_web::web::Data<models::Pool>,
+) {
+    let window_minutes: u32 = std::env::var("WEBHOOK_LOW_CONFIDENCE_WINDOW_MINUTES")
+        .unwrap_or("15".to_string())
+        .parse()
+        .unwrap_or(15);
+    let score_threshold: f32 = std::env::var("WEBHOOK_LOW_CONFIDENCE_SCORE")
+        .unwrap_or("0.3".to_string())
+        .parse()
+        .unwrap_or(0.3);
+    let min_searches: u64 = std::env::var("WEBHOOK_LOW_CONFIDENCE_MIN_SEARCHES")
+        .unwrap_or("20".to_string())
+        .parse()
+        .unwrap_or(20);
+    let min_ratio: f64 = std::env::var("WEBHOOK_LOW_CONFIDENCE_RATIO")
+        .unwrap_or("0.5".to_string())
+        .parse()
+        .unwrap_or(0.5);
+
+    let dataset_ids = match get_datasets_subscribed_to_query(
+        WebhookEventType::LowConfidenceSearchSpike,
+        web_pool,
+    )
+    .await
+    {
+        Ok(dataset_ids) => dataset_ids,
+        Err(err) => {
+            log::error!("Failed to get datasets subscribed to spikes {:?}", err);
+            return;
+        }
+    };
+
+    let spikes = match get_low_confidence_search_spikes_query(
+        dataset_ids,
+        window_minutes,
+        score_threshold,
+        min_searches,
+        min_ratio,
+        clickhouse_client,
+    )
+    .await
+    {
+        Ok(spikes) => spikes,
+        Err(err) => {
+            log::error!("Failed to get low confidence search spikes {:?}", err);
+            return;
+        }
+    };
+
+    let Ok(mut redis_conn) = redis_pool.get().await else {
+        log::error!("Failed to get redis connection for low confidence search spikes");
+        return;
+    };
+
+    for spike in spikes {
+        // Only notify once per hour for a dataset, a spike usually lasts several checks
+        let newly_set: Option<String> = redis::cmd("SET")
+            .arg(format!("webhook_low_confidence_spike:{}", spike.dataset_id))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(3600)
+            .query_async(&mut *redis_conn)
+            .await
+            .unwrap_or(None);
+
+        if newly_set.is_none() {
+            continue;
+        }
+
+        if let Err(err) = create_webhook_deliveries_query(
+            spike.dataset_id,
+            WebhookEventType::LowConfidenceSearchSpike,
+            serde_json::json!({
+                "window_minutes": window_minutes,
+                "score_threshold": score_threshold,
+                "total_searches": spike.total_searches,
+                "low_confidence_searches": spike.low_confidence_searches,
+            }),
+            web_pool,
+        )
+        .await
+        {
+            log::error!("Failed to create low confidence spike deliveries {:?}", err);
+        }
+    }
+}
@@ -11,6 +11,7 @@ use trieve_server::{
         },
         dataset_operator::get_all_dataset_ids,
         typo_operator::ProcessWordsFromDatasetMessage,
+        worker_metrics_operator::record_worker_job,
     },
 };
 
@@ -91,6 +92,7 @@ async fn main() -> Result<(), ServiceError> {
     let dataset_ids_and_processed = join_all(dataset_ids_and_processed).await;
 
     for (dataset_id, last_processed) in dataset_ids_and_processed {
+        let started_at = std::time::Instant::now();
         let mut chunk_id_offset = uuid::Uuid::nil();
 
         let last_processed = last_processed.map_err(|_| {
@@ -153,6 +155,15 @@ async fn main() -> Result<(), ServiceError> {
                 .collect::<Result<Vec<bool>, ServiceError>>()?;
             log::info!("Scrolled {} chunks", chunk_id_dataset_id_list.len());
         }
+
+        record_worker_job(
+            "word-id-cronjob",
+            Some(dataset_id),
+            started_at.elapsed(),
+            true,
+            &redis_pool,
+        )
+        .await;
     }
 
     Ok(())
@@ -13,14 +13,16 @@ use std::{
     },
 };
 use trieve_server::{
-    data::models,
+    data::models::{self, DeadLetterQueue},
     errors::ServiceError,
     establish_connection, get_env,
     operators::{
         chunk_operator::get_chunk_html_from_ids_query,
         dataset_operator::add_words_to_dataset,
+        dead_letter_operator::add_dead_letter,
         parse_operator::convert_html_to_text,
         typo_operator::{CreateBkTreeMessage, ProcessWordsFromDatasetMessage},
+        worker_metrics_operator::observe_worker_job,
     },
 };
 
@@ -199,11 +201,22 @@ async fn word_worker(
             }
         };
 
-        match process_chunks(
-            msg.clone(),
-            web_pool.clone(),
-            redis_pool.clone(),
-            clickhouse_client.clone(),
+        // Messages are queued per dataset, so the first chunk's dataset labels the whole job
+        let dataset_id = msg
+            .chunks_to_process
+            .first()
+            .map(|(_, dataset_id)| *dataset_id);
+
+        match observe_worker_job(
+            "word-worker",
+            dataset_id,
+            &redis_pool,
+            process_chunks(
+                msg.clone(),
+                web_pool.clone(),
+                redis_pool.clone(),
+                clickhouse_client.clone(),
+            ),
         )
         .await
         {
@@ -356,7 +369,7 @@ pub async fn readd_error_to_queue(
     let _ = redis::cmd("lrem")
         .arg("process_dictionary")
         .arg(1)
-        .arg(old_payload_message.clone())
+        .arg(old_payload_message)
         .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
         .await;
 
@@ -364,12 +377,29 @@ pub async fn readd_error_to_queue(
 
     if message.attempt_number == 3 {
         log::error!("Failed to process dataset 3 times: {:?}", error);
-        redis::cmd("lpush")
-            .arg("dictionary_dead_letters")
-            .arg(old_payload_message)
-            .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
-            .await
-            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+        drop(redis_conn);
+
+        // Messages can hold chunks from several datasets, so they are dead lettered per dataset
+        let chunks_by_dataset = message
+            .chunks_to_process
+            .iter()
+            .copied()
+            .into_group_map_by(|(_, dataset_id)| *dataset_id);
+
+        for (dataset_id, chunks_to_process) in chunks_by_dataset {
+            add_dead_letter(
+                DeadLetterQueue::Dictionary,
+                "create_dictionary",
+                dataset_id,
+                &ProcessWordsFromDatasetMessage {
+                    chunks_to_process,
+                    attempt_number: message.attempt_number,
+                },
+                error.to_string(),
+                &redis_pool,
+            )
+            .await?;
+        }
         return Err(ServiceError::InternalServerError(format!(
             "Failed to create new qdrant point: {:?}",
             error
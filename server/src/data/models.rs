@@ -18,6 +18,7 @@ use crate::handlers::message_handler::{
 };
 
 use crate::handlers::page_handler::PublicPageParameters;
+use crate::operators::acl_operator::{get_acl_filter_condition, get_qdrant_acl};
 use crate::operators::analytics_operator::{
     CTRRecommendationsWithClicksResponse, CTRRecommendationsWithoutClicksResponse,
     CTRSearchQueryWithClicksResponse, CTRSearchQueryWithoutClicksResponse, HeadQueryResponse,
@@ -144,10 +145,16 @@ pub struct Topic {
     pub updated_at: chrono::NaiveDateTime,
     pub dataset_id: uuid::Uuid,
     pub owner_id: String,
+    pub rag_preset: Option<String>,
 }
 
 impl Topic {
-    pub fn from_details<S: Into<String>>(name: S, owner_id: S, dataset_id: uuid::Uuid) -> Self {
+    pub fn from_details<S: Into<String>>(
+        name: S,
+        owner_id: S,
+        dataset_id: uuid::Uuid,
+        rag_preset: Option<String>,
+    ) -> Self {
         Topic {
             id: uuid::Uuid::new_v4(),
             name: name.into(),
@@ -156,10 +163,374 @@ impl Topic {
             updated_at: chrono::Utc::now().naive_local(),
             dataset_id,
             owner_id: owner_id.into(),
+            rag_preset,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+#[schema(example = json!({
+    "system_prompt": "You are a helpful support assistant for Trieve.",
+    "rag_prompt": "Use the following retrieved documents to answer the user's question: ",
+    "model": "gpt-4o-mini",
+    "temperature": 0.2,
+    "search_type": "hybrid",
+    "page_size": 5,
+    "filters": {
+        "must": [
+            {
+                "field": "tag_set",
+                "match_all": ["docs"]
+            }
+        ]
+    },
+    "context_options": {
+        "include_links": true
+    }
+}))]
+pub struct RagPresetOptions {
+    /// System prompt to use for completions made with this preset. Overrides the dataset's SYSTEM_PROMPT.
+    pub system_prompt: Option<String>,
+    /// RAG prompt to use for completions made with this preset. Overrides the dataset's RAG_PROMPT.
+    pub rag_prompt: Option<String>,
+    /// Model to use for completions made with this preset. Overrides the dataset's LLM_DEFAULT_MODEL.
+    pub model: Option<String>,
+    /// Sampling temperature to use for completions made with this preset. Overrides the dataset's TEMPERATURE.
+    pub temperature: Option<f64>,
+    /// Filters to apply to the retrieval step. Their must and must_not conditions are ANDed with the request's filters, and their should conditions are used when the request has none of its own.
+    pub filters: Option<ChunkFilter>,
+    /// Search type to use for the retrieval step when the request does not specify its own.
+    pub search_type: Option<SearchMethod>,
+    /// Number of chunks to retrieve when the request does not specify its own page_size.
+    pub page_size: Option<u64>,
+    /// Highlight options to use when the request does not specify its own.
+    pub highlight_options: Option<HighlightOptions>,
+    /// Context options to use when the request does not specify its own.
+    pub context_options: Option<ContextOptions>,
+}
+
+impl RagPresetOptions {
+    /// Layers the preset between the request and the dataset defaults. Values explicitly set on the request always win, then the preset, then the dataset configuration. Filters are the exception and are combined with the request's.
+    pub fn apply(
+        &self,
+        dataset_config: &mut DatasetConfiguration,
+        create_message_data: &mut CreateMessageReqPayload,
+    ) {
+        if let Some(system_prompt) = &self.system_prompt {
+            dataset_config.SYSTEM_PROMPT.clone_from(system_prompt);
+        }
+        if let Some(rag_prompt) = &self.rag_prompt {
+            dataset_config.RAG_PROMPT.clone_from(rag_prompt);
+        }
+        if let Some(model) = &self.model {
+            dataset_config.LLM_DEFAULT_MODEL.clone_from(model);
+        }
+        if self.temperature.is_some() {
+            dataset_config.TEMPERATURE = self.temperature;
+        }
+
+        // The request's filters already carry the access control and api key required filters, so the preset's are added to them rather than replacing them
+        if let Some(preset_filters) = &self.filters {
+            let mut filters = with_required_filters(
+                create_message_data.filters.take(),
+                Some(preset_filters.clone()),
+            );
+            if let Some(filters) = filters.as_mut() {
+                if !filters
+                    .should
+                    .as_ref()
+                    .is_some_and(|should| !should.is_empty())
+                {
+                    filters.should.clone_from(&preset_filters.should);
+                }
+            }
+            create_message_data.filters = filters;
+        }
+        if create_message_data.search_type.is_none() {
+            create_message_data
+                .search_type
+                .clone_from(&self.search_type);
+        }
+        if create_message_data.page_size.is_none() {
+            create_message_data.page_size = self.page_size;
+        }
+        if create_message_data.highlight_options.is_none() {
+            create_message_data
+                .highlight_options
+                .clone_from(&self.highlight_options);
+        }
+        if create_message_data.context_options.is_none() {
+            create_message_data
+                .context_options
+                .clone_from(&self.context_options);
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[schema(example = json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "name": "support",
+    "options": {
+        "system_prompt": "You are a helpful support assistant for Trieve.",
+        "model": "gpt-4o-mini",
+        "temperature": 0.2,
+        "search_type": "hybrid"
+    },
+    "created_at": "2021-01-01 00:00:00.000",
+    "updated_at": "2021-01-01 00:00:00.000",
+}))]
+#[diesel(table_name = rag_presets)]
+pub struct RagPresetPG {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub name: String,
+    pub options: serde_json::Value,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct RagPreset {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub name: String,
+    pub options: RagPresetOptions,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl RagPreset {
+    pub fn from_details(dataset_id: uuid::Uuid, name: String, options: RagPresetOptions) -> Self {
+        RagPreset {
+            id: uuid::Uuid::new_v4(),
+            dataset_id,
+            name,
+            options,
+            created_at: chrono::Utc::now().naive_local(),
+            updated_at: chrono::Utc::now().naive_local(),
+        }
+    }
+}
+
+impl From<RagPresetPG> for RagPreset {
+    fn from(preset: RagPresetPG) -> Self {
+        RagPreset {
+            id: preset.id,
+            dataset_id: preset.dataset_id,
+            name: preset.name,
+            options: serde_json::from_value(preset.options).unwrap_or_default(),
+            created_at: preset.created_at,
+            updated_at: preset.updated_at,
+        }
+    }
+}
+
+impl From<RagPreset> for RagPresetPG {
+    fn from(preset: RagPreset) -> Self {
+        RagPresetPG {
+            id: preset.id,
+            dataset_id: preset.dataset_id,
+            name: preset.name,
+            options: serde_json::to_value(preset.options).unwrap_or_default(),
+            created_at: preset.created_at,
+            updated_at: preset.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Display, ToSchema)]
+#[serde(rename_all = "snake_case")]
+/// Where the chunks of an ingestion job came from.
+pub enum IngestionJobSource {
+    #[display(fmt = "chunks")]
+    Chunks,
+    #[display(fmt = "file")]
+    File,
+    #[display(fmt = "csv_jsonl")]
+    CsvJsonl,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Display, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestionBatchStatus {
+    #[display(fmt = "queued")]
+    Queued,
+    #[display(fmt = "processing")]
+    Processing,
+    #[display(fmt = "done")]
+    Done,
+    #[display(fmt = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = ingestion_jobs)]
+pub struct IngestionJob {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub source: String,
+    pub queuing_complete: bool,
+    pub webhook_url: Option<String>,
+    /// When the webhook receiver acknowledged the job's completion with a 2xx response.
+    pub webhook_sent_at: Option<chrono::NaiveDateTime>,
+    pub webhook_attempts: i32,
+    /// When the webhook worker should next try to send the job's webhook. Set once every batch of the job has finished.
+    pub webhook_next_attempt_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl IngestionJob {
+    pub fn from_details(
+        dataset_id: uuid::Uuid,
+        source: IngestionJobSource,
+        queuing_complete: bool,
+        webhook_url: Option<String>,
+    ) -> Self {
+        IngestionJob {
+            id: uuid::Uuid::new_v4(),
+            dataset_id,
+            source: source.to_string(),
+            queuing_complete,
+            webhook_url,
+            webhook_sent_at: None,
+            webhook_attempts: 0,
+            webhook_next_attempt_at: None,
+            created_at: chrono::Utc::now().naive_local(),
+            updated_at: chrono::Utc::now().naive_local(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = ingestion_job_batches)]
+pub struct IngestionJobBatch {
+    pub id: uuid::Uuid,
+    pub ingestion_job_id: uuid::Uuid,
+    pub chunk_count: i32,
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl IngestionJobBatch {
+    pub fn from_details(ingestion_job_id: uuid::Uuid, chunk_count: usize) -> Self {
+        IngestionJobBatch {
+            id: uuid::Uuid::new_v4(),
+            ingestion_job_id,
+            chunk_count: chunk_count as i32,
+            status: IngestionBatchStatus::Queued.to_string(),
+            error: None,
+            created_at: chrono::Utc::now().naive_local(),
+            updated_at: chrono::Utc::now().naive_local(),
         }
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Display, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestionJobState {
+    #[display(fmt = "queued")]
+    Queued,
+    #[display(fmt = "processing")]
+    Processing,
+    #[display(fmt = "completed")]
+    Completed,
+    #[display(fmt = "completed_with_failures")]
+    CompletedWithFailures,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct FailedIngestionChunk {
+    /// Id of the chunk which failed to be ingested.
+    pub chunk_id: uuid::Uuid,
+    /// Error message from the ingestion worker.
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[schema(example = json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "source": "chunks",
+    "state": "completed_with_failures",
+    "queued": 0,
+    "processing": 0,
+    "done": 119,
+    "failed": 1,
+    "total": 120,
+    "failed_chunks": [{
+        "chunk_id": "d290f1ee-6c54-4b01-90e6-d701748f0851",
+        "error": "Failed to create embeddings"
+    }],
+    "created_at": "2021-01-01 00:00:00.000",
+}))]
+pub struct IngestionJobStatus {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub source: String,
+    /// Overall state of the job. A job is only completed once all of its chunks have been queued and each one is done or failed.
+    pub state: IngestionJobState,
+    /// Number of chunks waiting in the ingestion queue.
+    pub queued: i64,
+    /// Number of chunks currently being embedded and indexed.
+    pub processing: i64,
+    /// Number of chunks which are searchable.
+    pub done: i64,
+    /// Number of chunks whose latest ingestion attempt failed. These may still be retried by the worker.
+    pub failed: i64,
+    /// Total number of chunks queued for the job so far.
+    pub total: i64,
+    /// Chunks whose latest ingestion attempt failed along with the error. Requires analytics to be enabled.
+    pub failed_chunks: Vec<FailedIngestionChunk>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Display, ToSchema)]
+#[serde(rename_all = "snake_case")]
+/// The worker queue a dead letter came from and is replayed to.
+pub enum DeadLetterQueue {
+    #[display(fmt = "ingestion")]
+    Ingestion,
+    #[display(fmt = "crawl")]
+    Crawl,
+    #[display(fmt = "delete")]
+    Delete,
+    #[display(fmt = "dictionary")]
+    Dictionary,
+    #[display(fmt = "bktree")]
+    BkTree,
+    #[display(fmt = "analytics_forward")]
+    AnalyticsForward,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[schema(example = json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "queue": "delete",
+    "queue_name": "delete_dataset_queue",
+    "payload": {"DatasetDelete": {"dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3", "attempt_number": 2, "deleted_at": "2021-01-01T00:00:00", "empty_dataset": false}},
+    "error": "Failed to delete qdrant points",
+    "created_at": "2021-01-01T00:00:00",
+}))]
+/// A worker message which failed on every retry attempt.
+pub struct DeadLetter {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub queue: DeadLetterQueue,
+    /// Name of the redis queue the message is pushed back onto when replayed.
+    pub queue_name: String,
+    /// The message as it was on its last attempt.
+    pub payload: serde_json::Value,
+    /// Error from the last attempt.
+    pub error: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
 #[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Clone, ToSchema)]
 #[schema(example = json!({
     "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
@@ -173,6 +544,7 @@ impl Topic {
     "created_at": "2021-01-01 00:00:00.000",
     "updated_at": "2021-01-01 00:00:00.000",
     "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "follow_up_questions": null,
 }))]
 #[diesel(table_name = messages)]
 pub struct Message {
@@ -187,6 +559,7 @@ pub struct Message {
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
     pub dataset_id: uuid::Uuid,
+    pub follow_up_questions: Option<Vec<Option<String>>>,
 }
 
 impl From<Message> for ChatMessage {
@@ -273,6 +646,7 @@ impl Message {
             created_at: chrono::Utc::now().naive_local(),
             updated_at: chrono::Utc::now().naive_local(),
             dataset_id: dataset_id.into(),
+            follow_up_questions: None,
         }
     }
 }
@@ -376,6 +750,7 @@ pub struct UpdateSpecificChunkMetadata {
     pub image_urls: Option<Vec<Option<String>>>,
     pub tag_set: Option<Vec<Option<String>>>,
     pub num_value: Option<f64>,
+    pub acl: Option<Vec<Option<String>>>,
 }
 
 impl From<UpdateSpecificChunkMetadata> for ChunkMetadata {
@@ -396,6 +771,7 @@ impl From<UpdateSpecificChunkMetadata> for ChunkMetadata {
             image_urls: update_specific_chunk_metadata.image_urls,
             tag_set: update_specific_chunk_metadata.tag_set,
             num_value: update_specific_chunk_metadata.num_value,
+            acl: update_specific_chunk_metadata.acl,
         }
     }
 }
@@ -418,6 +794,7 @@ impl From<ChunkMetadata> for UpdateSpecificChunkMetadata {
             image_urls: chunk_metadata.image_urls,
             tag_set: chunk_metadata.tag_set,
             num_value: chunk_metadata.num_value,
+            acl: chunk_metadata.acl,
         }
     }
 }
@@ -467,6 +844,9 @@ pub struct ChunkMetadata {
     pub tag_set: Option<Vec<Option<String>>>,
     /// Numeric value of the chunk, can be any float. Can represent the most relevant numeric value of the chunk, such as a price, quantity in stock, rating, etc.
     pub num_value: Option<f64>,
+    /// Principals which can access the chunk when access control is enabled for the dataset, in the form `user:<user_id>` or `group:<group_name>`. Chunks without an ACL follow the dataset's default access control policy.
+    #[serde(default)]
+    pub acl: Option<Vec<Option<String>>>,
 }
 
 impl Default for ChunkMetadata {
@@ -487,6 +867,7 @@ impl Default for ChunkMetadata {
             image_urls: None,
             tag_set: None,
             num_value: None,
+            acl: None,
         }
     }
 }
@@ -508,6 +889,7 @@ pub struct ChunkMetadataTable {
     pub location: Option<GeoInfo>,
     pub image_urls: Option<Vec<Option<String>>>,
     pub num_value: Option<f64>,
+    pub acl: Option<Vec<Option<String>>>,
 }
 
 impl From<ChunkMetadata> for ChunkMetadataTable {
@@ -527,6 +909,7 @@ impl From<ChunkMetadata> for ChunkMetadataTable {
             location: chunk_metadata.location,
             image_urls: chunk_metadata.image_urls,
             num_value: chunk_metadata.num_value,
+            acl: chunk_metadata.acl,
         }
     }
 }
@@ -563,6 +946,7 @@ impl ChunkMetadata {
             weight,
             image_urls: image_urls.map(|urls| urls.into_iter().map(Some).collect()),
             num_value,
+            acl: None,
         }
     }
 
@@ -586,6 +970,7 @@ impl ChunkMetadata {
             weight: chunk_metadata_table.weight,
             image_urls: chunk_metadata_table.image_urls,
             num_value: chunk_metadata_table.num_value,
+            acl: chunk_metadata_table.acl,
         }
     }
 
@@ -609,6 +994,7 @@ impl ChunkMetadata {
             weight: chunk_metadata_table.weight,
             image_urls: chunk_metadata_table.image_urls,
             num_value: chunk_metadata_table.num_value,
+            acl: chunk_metadata_table.acl,
         }
     }
 }
@@ -646,6 +1032,7 @@ impl ChunkMetadata {
             weight,
             image_urls: image_urls.map(|urls| urls.into_iter().map(Some).collect()),
             num_value,
+            acl: None,
         }
     }
 }
@@ -676,6 +1063,7 @@ impl From<ChunkMetadataStringTagSet> for ChunkMetadata {
                 }
             }),
             num_value: chunk_metadata_string_tag_set.num_value,
+            acl: None,
         }
     }
 }
@@ -700,6 +1088,7 @@ impl From<SlimChunkMetadata> for ChunkMetadata {
             weight: slim_chunk.weight,
             image_urls: slim_chunk.image_urls,
             num_value: slim_chunk.num_value,
+            acl: None,
         }
     }
 }
@@ -722,6 +1111,7 @@ impl From<ContentChunkMetadata> for ChunkMetadata {
             weight: content_chunk.weight,
             image_urls: content_chunk.image_urls,
             num_value: content_chunk.num_value,
+            acl: None,
         }
     }
 }
@@ -774,6 +1164,7 @@ impl From<ChunkMetadataWithScore> for ScoreChunk {
             chunk: NewChunkMetadataTypes::Metadata(val.into()),
             highlights: None,
             score,
+            explain: None,
         }
     }
 }
@@ -823,6 +1214,7 @@ impl From<ChunkMetadataWithScore> for ChunkMetadata {
             location: None,
             image_urls: None,
             num_value: None,
+            acl: None,
         }
     }
 }
@@ -871,6 +1263,30 @@ pub struct ScoreChunkDTO {
     pub metadata: Vec<ChunkMetadataTypes>,
     pub highlights: Option<Vec<String>>,
     pub score: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explain: Option<ScoreExplanation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+#[schema(example = json!({
+    "score_before_engagement": 0.5,
+    "query_engagement_boost": 0.8,
+    "chunk_engagement_boost": 0.4,
+    "engagement_boost": 0.68,
+    "engagement_weight": 0.3
+}))]
+/// How reranking adjusted the score of a chunk. Only returned when `sort_options.explain` is true.
+pub struct ScoreExplanation {
+    /// Score of the chunk after the other reranking stages and before engagement boosting.
+    pub score_before_engagement: f64,
+    /// Boost from engagement with the chunk on previous searches for the same query, between 0 and 1.
+    pub query_engagement_boost: f64,
+    /// Boost from engagement with the chunk across all searches, between 0 and 1.
+    pub chunk_engagement_boost: f64,
+    /// Combined engagement boost the score was multiplied by 1 + engagement_weight times.
+    pub engagement_boost: f64,
+    /// Weight of the engagement stage used for the request.
+    pub engagement_weight: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
@@ -892,6 +1308,8 @@ pub struct ScoreChunk {
     pub chunk: NewChunkMetadataTypes,
     pub highlights: Option<Vec<String>>,
     pub score: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explain: Option<ScoreExplanation>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
@@ -945,6 +1363,16 @@ pub enum NewChunkMetadataTypes {
     Content(ContentChunkMetadata),
 }
 
+impl NewChunkMetadataTypes {
+    pub fn id(&self) -> uuid::Uuid {
+        match self {
+            NewChunkMetadataTypes::ID(slim_metadata) => slim_metadata.id,
+            NewChunkMetadataTypes::Metadata(metadata) => metadata.id,
+            NewChunkMetadataTypes::Content(content_metadata) => content_metadata.id,
+        }
+    }
+}
+
 impl From<ChunkMetadataTypes> for NewChunkMetadataTypes {
     fn from(val: ChunkMetadataTypes) -> Self {
         match val {
@@ -989,6 +1417,7 @@ impl From<ScoreChunkDTO> for ScoreChunk {
             chunk: score_chunk_dto.metadata[0].clone().into(),
             highlights: score_chunk_dto.highlights,
             score: score_chunk_dto.score as f32,
+            explain: score_chunk_dto.explain,
         }
     }
 }
@@ -1831,6 +2260,9 @@ pub struct ChunkGroup {
     pub tracking_id: Option<String>,
     pub metadata: Option<serde_json::Value>,
     pub tag_set: Option<Vec<Option<String>>>,
+    /// Principals which are allowed to see the group when access control is enabled for the dataset. The chunks in the group are governed by their own acl.
+    #[serde(default)]
+    pub acl: Option<Vec<Option<String>>>,
 }
 
 impl ChunkGroup {
@@ -1852,6 +2284,7 @@ impl ChunkGroup {
             tracking_id,
             metadata,
             tag_set,
+            acl: None,
         }
     }
 
@@ -1874,6 +2307,7 @@ impl ChunkGroup {
             tracking_id,
             metadata,
             tag_set,
+            acl: None,
         }
     }
 }
@@ -1900,6 +2334,8 @@ pub struct ChunkGroupAndFileId {
     pub file_id: Option<uuid::Uuid>,
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
+    #[serde(default)]
+    pub acl: Option<Vec<Option<String>>>,
 }
 
 impl ChunkGroupAndFileId {
@@ -1915,6 +2351,7 @@ impl ChunkGroupAndFileId {
             file_id,
             created_at: group.created_at,
             updated_at: group.updated_at,
+            acl: group.acl,
         }
     }
 
@@ -1929,6 +2366,7 @@ impl ChunkGroupAndFileId {
             metadata: self.metadata.clone(),
             created_at: self.created_at,
             updated_at: self.updated_at,
+            acl: self.acl.clone(),
         }
     }
 }
@@ -2004,44 +2442,123 @@ pub struct UserDTOWithChunks {
     pub chunks: Vec<ChunkMetadata>,
 }
 
-#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
-/// The key in the ChunkReqPayload which you can map a column or field from the CSV or JSONL file to.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+/// The key in the ChunkReqPayload which you can map a column or field from the CSV or JSONL file to. Use `metadata.<path>` to set a nested key on the chunk's metadata, which otherwise contains every field of the row, and `group_metadata.<path>` to set a nested key on the metadata of the row's groups.
 pub enum ChunkReqPayloadFields {
-    #[serde(rename = "link")]
     Link,
-    #[serde(rename = "tag_set")]
     TagSet,
-    #[serde(rename = "num_value")]
     NumValue,
-    #[serde(rename = "tracking_id")]
     TrackingId,
-    #[serde(rename = "group_tracking_ids")]
     GroupTrackingIds,
-    #[serde(rename = "time_stamp")]
     TimeStamp,
-    #[serde(rename = "lat")]
     Lat,
-    #[serde(rename = "lon")]
     Lon,
-    #[serde(rename = "image_urls")]
     ImageUrls,
-    #[serde(rename = "weight")]
     Weight,
-    #[serde(rename = "boost_phrase")]
     BoostPhrase,
+    ChunkHtml,
+    SemanticContent,
+    GroupIds,
+    Metadata(String),
+    GroupMetadata(String),
+}
+
+impl TryFrom<String> for ChunkReqPayloadFields {
+    type Error = String;
+
+    fn try_from(field: String) -> Result<Self, Self::Error> {
+        if let Some(path) = field.strip_prefix("metadata.") {
+            if path.is_empty() || path.split('.').any(|key| key.is_empty()) {
+                return Err(format!("Invalid metadata path: {}", field));
+            }
+            return Ok(ChunkReqPayloadFields::Metadata(path.to_string()));
+        }
+        if let Some(path) = field.strip_prefix("group_metadata.") {
+            if path.is_empty() || path.split('.').any(|key| key.is_empty()) {
+                return Err(format!("Invalid group metadata path: {}", field));
+            }
+            return Ok(ChunkReqPayloadFields::GroupMetadata(path.to_string()));
+        }
+
+        match field.as_str() {
+            "link" => Ok(ChunkReqPayloadFields::Link),
+            "tag_set" => Ok(ChunkReqPayloadFields::TagSet),
+            "num_value" => Ok(ChunkReqPayloadFields::NumValue),
+            "tracking_id" => Ok(ChunkReqPayloadFields::TrackingId),
+            "group_tracking_ids" => Ok(ChunkReqPayloadFields::GroupTrackingIds),
+            "time_stamp" => Ok(ChunkReqPayloadFields::TimeStamp),
+            "lat" => Ok(ChunkReqPayloadFields::Lat),
+            "lon" => Ok(ChunkReqPayloadFields::Lon),
+            "image_urls" => Ok(ChunkReqPayloadFields::ImageUrls),
+            "weight" => Ok(ChunkReqPayloadFields::Weight),
+            "boost_phrase" => Ok(ChunkReqPayloadFields::BoostPhrase),
+            "chunk_html" => Ok(ChunkReqPayloadFields::ChunkHtml),
+            "semantic_content" => Ok(ChunkReqPayloadFields::SemanticContent),
+            "group_ids" => Ok(ChunkReqPayloadFields::GroupIds),
+            _ => Err(format!("Unknown chunk_req_payload_field: {}", field)),
+        }
+    }
+}
+
+impl From<ChunkReqPayloadFields> for String {
+    fn from(field: ChunkReqPayloadFields) -> Self {
+        match field {
+            ChunkReqPayloadFields::Link => "link".to_string(),
+            ChunkReqPayloadFields::TagSet => "tag_set".to_string(),
+            ChunkReqPayloadFields::NumValue => "num_value".to_string(),
+            ChunkReqPayloadFields::TrackingId => "tracking_id".to_string(),
+            ChunkReqPayloadFields::GroupTrackingIds => "group_tracking_ids".to_string(),
+            ChunkReqPayloadFields::TimeStamp => "time_stamp".to_string(),
+            ChunkReqPayloadFields::Lat => "lat".to_string(),
+            ChunkReqPayloadFields::Lon => "lon".to_string(),
+            ChunkReqPayloadFields::ImageUrls => "image_urls".to_string(),
+            ChunkReqPayloadFields::Weight => "weight".to_string(),
+            ChunkReqPayloadFields::BoostPhrase => "boost_phrase".to_string(),
+            ChunkReqPayloadFields::ChunkHtml => "chunk_html".to_string(),
+            ChunkReqPayloadFields::SemanticContent => "semantic_content".to_string(),
+            ChunkReqPayloadFields::GroupIds => "group_ids".to_string(),
+            ChunkReqPayloadFields::Metadata(path) => format!("metadata.{}", path),
+            ChunkReqPayloadFields::GroupMetadata(path) => format!("group_metadata.{}", path),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Display)]
+#[serde(rename_all = "lowercase")]
+/// The type a mapped value should be converted to before it is set on the ChunkReqPayload.
+pub enum MappingValueType {
+    #[display(fmt = "string")]
+    String,
+    #[display(fmt = "number")]
+    Number,
+    #[display(fmt = "bool")]
+    Bool,
+    #[display(fmt = "date")]
+    Date,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[schema(example = json!({
+    "csv_jsonl_field": "price",
+    "chunk_req_payload_field": "metadata.product.price",
+    "value_type": "number",
+}))]
 /// Express a mapping between a column or field in a CSV or JSONL field and a key in the ChunkReqPayload created for each row or object.
 pub struct ChunkReqPayloadMapping {
     /// The column or field in the CSV or JSONL file that you want to map to a key in the ChunkReqPayload
     pub csv_jsonl_field: String,
-    /// The key in the ChunkReqPayload that you want to map the column or field to.
+    /// The key in the ChunkReqPayload that you want to map the column or field to. Use `metadata.<path>` or `group_metadata.<path>` for nested metadata keys, e.g. `metadata.product.price`.
+    #[schema(value_type = String)]
     pub chunk_req_payload_field: ChunkReqPayloadFields,
+    /// The type to convert the value to before mapping it. Rows with a value which can not be converted are skipped and reported in the file's error file. If not specified, the value is used as it was parsed.
+    pub value_type: Option<MappingValueType>,
+    /// The chrono strftime format used to parse the value when value_type is `date`, e.g. `%m/%d/%Y`. If not specified, ISO 8601 date times, `YYYY-MM-DD` dates, and unix timestamps are accepted.
+    pub date_format: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
-/// Specify all of the mappings between columns or fields in a CSV or JSONL file and keys in the ChunkReqPayload. Array fields like tag_set, image_urls, and group_tracking_ids can have multiple mappings. Boost phrase can also have multiple mappings which get concatenated. Other fields can only have one mapping and only the last mapping will be used.
+/// Specify all of the mappings between columns or fields in a CSV or JSONL file and keys in the ChunkReqPayload. Array fields like tag_set, image_urls, group_ids, and group_tracking_ids can have multiple mappings. Boost phrase, chunk_html, and semantic_content can also have multiple mappings which get concatenated. Metadata paths are merged into a single object. Other fields can only have one mapping and only the last mapping will be used.
 pub struct ChunkReqPayloadMappings(pub Vec<ChunkReqPayloadMapping>);
 
 #[derive(
@@ -2159,6 +2676,8 @@ pub struct WorkerEventClickhouse {
     pub event_data: String,
     #[serde(with = "clickhouse::serde::time::datetime")]
     pub created_at: OffsetDateTime,
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub ingestion_job_id: uuid::Uuid,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
@@ -2176,6 +2695,9 @@ pub struct WorkerEvent {
     pub dataset_id: uuid::Uuid,
     pub event_type: String,
     pub event_data: String,
+    /// The ingestion job the event belongs to, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingestion_job_id: Option<uuid::Uuid>,
 }
 
 impl From<WorkerEventClickhouse> for WorkerEvent {
@@ -2186,6 +2708,8 @@ impl From<WorkerEventClickhouse> for WorkerEvent {
             dataset_id: uuid::Uuid::from_bytes(*clickhouse_event.dataset_id.as_bytes()),
             event_type: clickhouse_event.event_type,
             event_data: clickhouse_event.event_data,
+            ingestion_job_id: Some(clickhouse_event.ingestion_job_id)
+                .filter(|ingestion_job_id| !ingestion_job_id.is_nil()),
         }
     }
 }
@@ -2198,10 +2722,175 @@ impl From<WorkerEvent> for WorkerEventClickhouse {
             dataset_id: event.dataset_id,
             event_type: event.event_type,
             event_data: event.event_data,
+            ingestion_job_id: event.ingestion_job_id.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    #[display(fmt = "dataset_created")]
+    DatasetCreated,
+    #[display(fmt = "dataset_updated")]
+    DatasetUpdated,
+    #[display(fmt = "dataset_deleted")]
+    DatasetDeleted,
+    #[display(fmt = "dataset_cleared")]
+    DatasetCleared,
+    #[display(fmt = "dataset_configs_updated")]
+    DatasetConfigsUpdated,
+    #[display(fmt = "organization_updated")]
+    OrganizationUpdated,
+    #[display(fmt = "organization_deleted")]
+    OrganizationDeleted,
+    #[display(fmt = "api_key_created")]
+    ApiKeyCreated,
+    #[display(fmt = "api_key_deleted")]
+    ApiKeyDeleted,
+    #[display(fmt = "user_role_updated")]
+    UserRoleUpdated,
+    #[display(fmt = "user_removed_from_organization")]
+    UserRemovedFromOrganization,
+    #[display(fmt = "invitation_created")]
+    InvitationCreated,
+    #[display(fmt = "invitation_deleted")]
+    InvitationDeleted,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditTargetType {
+    #[display(fmt = "dataset")]
+    Dataset,
+    #[display(fmt = "organization")]
+    Organization,
+    #[display(fmt = "organization_api_key")]
+    OrganizationApiKey,
+    #[display(fmt = "user_api_key")]
+    UserApiKey,
+    #[display(fmt = "user")]
+    User,
+    #[display(fmt = "invitation")]
+    Invitation,
+}
+
+/// The user or api key which made a request along with the ip it was made from. Api keys act as a generated user so `api_key_id` should be used to identify them.
+#[derive(Debug, Clone)]
+pub struct AuditActor {
+    pub user_id: uuid::Uuid,
+    pub email: String,
+    pub api_key_id: Option<uuid::Uuid>,
+    pub source_ip: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Row)]
+pub struct AuditLogClickhouse {
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub id: uuid::Uuid,
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub organization_id: uuid::Uuid,
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub dataset_id: uuid::Uuid,
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub actor_id: uuid::Uuid,
+    pub actor_email: String,
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub api_key_id: uuid::Uuid,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub before: String,
+    pub after: String,
+    pub source_ip: String,
+    #[serde(with = "clickhouse::serde::time::datetime")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[schema(example=json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "organization_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "actor_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "actor_email": "developer@trieve.ai",
+    "action": "dataset_updated",
+    "target_type": "dataset",
+    "target_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "before": {"server_configuration": {"TEMPERATURE": 0.5}},
+    "after": {"server_configuration": {"TEMPERATURE": 0.2}},
+    "source_ip": "127.0.0.1",
+    "created_at": "2021-01-01 00:00:00.000",
+}))]
+pub struct AuditLog {
+    pub id: uuid::Uuid,
+    pub organization_id: uuid::Uuid,
+    /// The dataset the change was made to, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dataset_id: Option<uuid::Uuid>,
+    /// Id of the user who made the change. Requests made with an organization api key have a generated id, use `api_key_id` to identify them instead.
+    pub actor_id: uuid::Uuid,
+    pub actor_email: String,
+    /// The api key the change was made with, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_id: Option<uuid::Uuid>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    /// The fields of the target which were changed, as they were before the change.
+    pub before: serde_json::Value,
+    /// The fields of the target which were changed, as they are after the change.
+    pub after: serde_json::Value,
+    pub source_ip: String,
+    pub created_at: String,
+}
+
+impl From<AuditLogClickhouse> for AuditLog {
+    fn from(audit_log: AuditLogClickhouse) -> Self {
+        AuditLog {
+            id: uuid::Uuid::from_bytes(*audit_log.id.as_bytes()),
+            organization_id: uuid::Uuid::from_bytes(*audit_log.organization_id.as_bytes()),
+            dataset_id: Some(audit_log.dataset_id).filter(|dataset_id| !dataset_id.is_nil()),
+            actor_id: uuid::Uuid::from_bytes(*audit_log.actor_id.as_bytes()),
+            actor_email: audit_log.actor_email,
+            api_key_id: Some(audit_log.api_key_id).filter(|api_key_id| !api_key_id.is_nil()),
+            action: audit_log.action,
+            target_type: audit_log.target_type,
+            target_id: audit_log.target_id,
+            before: serde_json::from_str(&audit_log.before).unwrap_or_default(),
+            after: serde_json::from_str(&audit_log.after).unwrap_or_default(),
+            source_ip: audit_log.source_ip,
+            created_at: audit_log.created_at.to_string(),
         }
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+#[schema(example=json!({
+    "actions": ["dataset_updated", "api_key_deleted"],
+    "target_type": "dataset",
+    "date_range": {
+        "gte": "2021-01-01 00:00:00.000",
+        "lt": "2021-02-01 00:00:00.000"
+    }
+}))]
+pub struct AuditLogFilter {
+    /// Only return audit logs with one of these actions. Leave undefined to get all actions.
+    pub actions: Option<Vec<AuditAction>>,
+    /// Only return audit logs for changes made by this user.
+    pub actor_id: Option<uuid::Uuid>,
+    /// Only return audit logs for changes made with this api key.
+    pub api_key_id: Option<uuid::Uuid>,
+    /// Only return audit logs for changes made to this dataset.
+    pub dataset_id: Option<uuid::Uuid>,
+    /// Only return audit logs for changes made to this type of target.
+    pub target_type: Option<AuditTargetType>,
+    /// Only return audit logs for changes made to the target with this id.
+    pub target_id: Option<String>,
+    /// Only return audit logs created within this date range.
+    pub date_range: Option<DateRange>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Display, ToSchema)]
 #[serde(untagged)]
 pub enum EventType {
@@ -2264,6 +2953,11 @@ pub enum EventType {
         file_id: uuid::Uuid,
         chunks_created: usize,
     },
+    #[display(fmt = "csv_jsonl_row_errors")]
+    CsvJsonlRowErrors {
+        file_id: uuid::Uuid,
+        error_count: usize,
+    },
     #[display(fmt = "video_uploaded")]
     VideoUploaded {
         video_id: String,
@@ -2284,6 +2978,8 @@ pub enum EventType {
     EtlCompleted,
     #[display(fmt = "etl_failed")]
     EtlFailed { error: String },
+    #[display(fmt = "dataset_cleared")]
+    DatasetCleared,
 }
 
 impl EventType {
@@ -2305,6 +3001,7 @@ impl EventType {
             EventTypeRequest::CsvJsonlProcessingFailed,
             EventTypeRequest::CsvJsonlProcessingCheckpoint,
             EventTypeRequest::CsvJsonlProcessingCompleted,
+            EventTypeRequest::CsvJsonlRowErrors,
             EventTypeRequest::VideoUploaded,
             EventTypeRequest::PagefindIndexingStarted,
             EventTypeRequest::PagefindIndexingFinished,
@@ -2312,6 +3009,7 @@ impl EventType {
             EventTypeRequest::EtlCompleted,
             EventTypeRequest::EtlFailed,
             EventTypeRequest::ChunkUpdateFailed,
+            EventTypeRequest::DatasetCleared,
         ]
     }
 }
@@ -2324,52 +3022,911 @@ impl WorkerEvent {
             dataset_id,
             event_type: event_type.to_string(),
             event_data: serde_json::to_value(event_type).unwrap().to_string(),
+            ingestion_job_id: None,
         }
     }
-}
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, ValidGrouping)]
-#[diesel(table_name = dataset_group_counts)]
-pub struct DatasetGroupCount {
-    pub id: uuid::Uuid,
-    pub group_count: i32,
-    pub dataset_id: uuid::Uuid,
+    pub fn with_ingestion_job_id(mut self, ingestion_job_id: Option<uuid::Uuid>) -> Self {
+        self.ingestion_job_id = ingestion_job_id;
+        self
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, ValidGrouping)]
-#[diesel(table_name = dataset_event_counts)]
-pub struct DatasetEventCount {
-    pub id: uuid::Uuid,
-    pub dataset_uuid: uuid::Uuid,
-    pub notification_count: i32,
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventType {
+    #[display(fmt = "chunks_created")]
+    ChunksCreated,
+    #[display(fmt = "chunk_updated")]
+    ChunkUpdated,
+    #[display(fmt = "chunks_failed")]
+    ChunksFailed,
+    #[display(fmt = "file_processed")]
+    FileProcessed,
+    #[display(fmt = "crawl_finished")]
+    CrawlFinished,
+    #[display(fmt = "dataset_cleared")]
+    DatasetCleared,
+    #[display(fmt = "low_confidence_search_spike")]
+    LowConfidenceSearchSpike,
+}
+
+impl WebhookEventType {
+    /// Webhook event type a worker event is delivered as. Worker events without one are not sent to webhooks.
+    pub fn from_worker_event_type(event_type: &str) -> Option<Self> {
+        match event_type {
+            "chunks_uploaded" => Some(WebhookEventType::ChunksCreated),
+            "chunk_updated" => Some(WebhookEventType::ChunkUpdated),
+            "bulk_chunk_upload_failed" | "chunk_update_failed" | "chunk_action_failed" => {
+                Some(WebhookEventType::ChunksFailed)
+            }
+            "file_uploaded" => Some(WebhookEventType::FileProcessed),
+            "crawl_completed" => Some(WebhookEventType::CrawlFinished),
+            "dataset_cleared" => Some(WebhookEventType::DatasetCleared),
+            _ => None,
+        }
+    }
 }
 
-#[derive(
-    Debug,
-    Serialize,
-    Deserialize,
-    Queryable,
-    Insertable,
-    Selectable,
-    Clone,
-    ToSchema,
-    QueryableByName,
-    AsChangeset,
-)]
-#[schema(example=json!({
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[schema(example = json!({
     "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
-    "name": "Trieve",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "url": "https://example.com/trieve/webhook",
+    "event_types": ["chunks_created", "file_processed"],
+    "active": true,
     "created_at": "2021-01-01 00:00:00.000",
     "updated_at": "2021-01-01 00:00:00.000",
-    "organization_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
-    "tracking_id": "foobar-dataset",
-    "server_configuration": {
-        "LLM_BASE_URL": "https://api.openai.com/v1",
-        "EMBEDDING_BASE_URL": "https://api.openai.com/v1",
-        "EMBEDDING_MODEL_NAME": "text-embedding-3-small",
-        "MESSAGE_TO_QUERY_PROMPT": "Write a 1-2 sentence semantic search query along the lines of a hypothetical response to: \n\n",
-        "RAG_PROMPT": "Use the following retrieved documents to respond briefly and accurately:",
-        "N_RETRIEVALS_TO_INCLUDE": 8,
+}))]
+#[diesel(table_name = dataset_webhooks)]
+pub struct DatasetWebhook {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    /// The url deliveries are POSTed to.
+    pub url: String,
+    /// Secret the deliveries are signed with. It is only returned when the webhook is created.
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// The event types delivered to the webhook.
+    pub event_types: Vec<String>,
+    /// Inactive webhooks do not receive deliveries.
+    pub active: bool,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl DatasetWebhook {
+    pub fn from_details(
+        dataset_id: uuid::Uuid,
+        url: String,
+        secret: String,
+        event_types: Vec<WebhookEventType>,
+    ) -> Self {
+        DatasetWebhook {
+            id: uuid::Uuid::new_v4(),
+            dataset_id,
+            url,
+            secret,
+            event_types: event_types
+                .iter()
+                .map(|event_type| event_type.to_string())
+                .collect(),
+            active: true,
+            created_at: chrono::Utc::now().naive_local(),
+            updated_at: chrono::Utc::now().naive_local(),
+        }
+    }
+
+    pub fn subscribes_to(&self, event_type: WebhookEventType) -> bool {
+        self.active
+            && self
+                .event_types
+                .iter()
+                .any(|subscribed| *subscribed == event_type.to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    #[display(fmt = "pending")]
+    Pending,
+    #[display(fmt = "succeeded")]
+    Succeeded,
+    #[display(fmt = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[schema(example = json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "webhook_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "event_type": "chunks_created",
+    "payload": {
+        "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+        "event_type": "chunks_created",
+        "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+        "created_at": "2021-01-01 00:00:00.000",
+        "data": {"chunk_ids": ["e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3"]}
+    },
+    "status": "succeeded",
+    "attempt_number": 1,
+    "response_status": 200,
+    "error": null,
+    "next_attempt_at": "2021-01-01 00:00:00.000",
+    "created_at": "2021-01-01 00:00:00.000",
+    "updated_at": "2021-01-01 00:00:00.000",
+}))]
+#[diesel(table_name = dataset_webhook_deliveries)]
+pub struct DatasetWebhookDelivery {
+    pub id: uuid::Uuid,
+    pub webhook_id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub event_type: String,
+    /// The JSON body which is POSTed to the webhook.
+    pub payload: serde_json::Value,
+    /// Either pending, succeeded or failed. Failed deliveries exhausted their retries and can be redelivered by hand.
+    pub status: String,
+    /// Number of delivery attempts made so far.
+    pub attempt_number: i32,
+    /// HTTP status code of the last attempt, if the webhook responded.
+    pub response_status: Option<i32>,
+    /// Error of the last failed attempt.
+    pub error: Option<String>,
+    /// When the next attempt of a pending delivery is made.
+    pub next_attempt_at: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl DatasetWebhookDelivery {
+    pub fn from_details(
+        webhook_id: uuid::Uuid,
+        dataset_id: uuid::Uuid,
+        event_type: WebhookEventType,
+        data: serde_json::Value,
+    ) -> Self {
+        let id = uuid::Uuid::new_v4();
+        let created_at = chrono::Utc::now().naive_local();
+
+        DatasetWebhookDelivery {
+            id,
+            webhook_id,
+            dataset_id,
+            event_type: event_type.to_string(),
+            payload: json!({
+                "id": id,
+                "event_type": event_type,
+                "dataset_id": dataset_id,
+                "created_at": created_at,
+                "data": data,
+            }),
+            status: WebhookDeliveryStatus::Pending.to_string(),
+            attempt_number: 0,
+            response_status: None,
+            error: None,
+            next_attempt_at: created_at,
+            created_at,
+            updated_at: created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+/// An analytics table which can be exported. Each export run writes one file per table.
+pub enum AnalyticsExportEventType {
+    #[display(fmt = "search_queries")]
+    SearchQueries,
+    #[display(fmt = "rag_queries")]
+    RagQueries,
+    #[display(fmt = "recommendations")]
+    Recommendations,
+    #[display(fmt = "events")]
+    Events,
+}
+
+impl AnalyticsExportEventType {
+    pub fn from_name(event_type: &str) -> Option<Self> {
+        match event_type {
+            "search_queries" => Some(AnalyticsExportEventType::SearchQueries),
+            "rag_queries" => Some(AnalyticsExportEventType::RagQueries),
+            "recommendations" => Some(AnalyticsExportEventType::Recommendations),
+            "events" => Some(AnalyticsExportEventType::Events),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalyticsExportFormat {
+    #[display(fmt = "jsonl")]
+    #[default]
+    Jsonl,
+    #[display(fmt = "parquet")]
+    Parquet,
+}
+
+impl AnalyticsExportFormat {
+    pub fn from_name(format: &str) -> Self {
+        match format {
+            "parquet" => AnalyticsExportFormat::Parquet,
+            _ => AnalyticsExportFormat::Jsonl,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            AnalyticsExportFormat::Jsonl => "application/x-ndjson",
+            AnalyticsExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+/// How forwarded events are POSTed. `http` sends each event as a JSON body. `kafka_rest` wraps the event in the `{"records": [{"key": ..., "value": ...}]}` envelope accepted by Kafka REST proxies such as Confluent REST Proxy and Redpanda, so `forward_url` should point at a topic, e.g. `https://proxy:8082/topics/trieve-analytics`.
+pub enum AnalyticsForwardSink {
+    #[display(fmt = "http")]
+    #[default]
+    Http,
+    #[display(fmt = "kafka_rest")]
+    KafkaRest,
+}
+
+impl AnalyticsForwardSink {
+    pub fn from_name(sink: &str) -> Self {
+        match sink {
+            "kafka_rest" => AnalyticsForwardSink::KafkaRest,
+            _ => AnalyticsForwardSink::Http,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[schema(example = json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "format": "parquet",
+    "event_types": ["search_queries", "events"],
+    "interval_minutes": 60,
+    "forward_url": "https://example.com/trieve/analytics",
+    "forward_sink": "http",
+    "active": true,
+    "last_exported_at": "2021-01-01 00:00:00.000",
+    "next_export_at": "2021-01-01 01:00:00.000",
+    "created_at": "2021-01-01 00:00:00.000",
+    "updated_at": "2021-01-01 00:00:00.000",
+}))]
+#[diesel(table_name = analytics_export_configs)]
+pub struct AnalyticsExportConfig {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    /// Either jsonl or parquet.
+    pub format: String,
+    /// The analytics tables which are exported.
+    pub event_types: Vec<String>,
+    /// Minutes between scheduled exports.
+    pub interval_minutes: i32,
+    /// Url every analytics event of the dataset is POSTed to as it happens. Null disables forwarding.
+    pub forward_url: Option<String>,
+    /// Either http or kafka_rest.
+    pub forward_sink: String,
+    /// Authorization header sent with forwarded events. It is never returned.
+    #[serde(skip_serializing)]
+    pub forward_auth_header: Option<String>,
+    /// Inactive configs neither export nor forward.
+    pub active: bool,
+    /// Events created before this time have been exported.
+    pub last_exported_at: chrono::NaiveDateTime,
+    /// When the next scheduled export runs.
+    pub next_export_at: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl AnalyticsExportConfig {
+    pub fn from_details(
+        dataset_id: uuid::Uuid,
+        format: AnalyticsExportFormat,
+        event_types: Vec<AnalyticsExportEventType>,
+        interval_minutes: i32,
+        forward_url: Option<String>,
+        forward_sink: AnalyticsForwardSink,
+        forward_auth_header: Option<String>,
+    ) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+
+        AnalyticsExportConfig {
+            id: uuid::Uuid::new_v4(),
+            dataset_id,
+            format: format.to_string(),
+            event_types: event_types
+                .iter()
+                .map(|event_type| event_type.to_string())
+                .collect(),
+            interval_minutes,
+            forward_url,
+            forward_sink: forward_sink.to_string(),
+            forward_auth_header,
+            active: true,
+            last_exported_at: now,
+            next_export_at: now + chrono::Duration::minutes(interval_minutes as i64),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn exports(&self, event_type: AnalyticsExportEventType) -> bool {
+        self.event_types
+            .iter()
+            .any(|exported| *exported == event_type.to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalyticsExportRunStatus {
+    #[display(fmt = "running")]
+    Running,
+    #[display(fmt = "succeeded")]
+    Succeeded,
+    #[display(fmt = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[schema(example = json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "config_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "status": "succeeded",
+    "window_start": "2021-01-01 00:00:00.000",
+    "window_end": "2021-01-01 01:00:00.000",
+    "files": ["analytics-exports/e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3/search_queries/1609459200_1609462800.parquet"],
+    "row_count": 1200,
+    "error": null,
+    "created_at": "2021-01-01 01:00:00.000",
+    "updated_at": "2021-01-01 01:00:05.000",
+}))]
+#[diesel(table_name = analytics_export_runs)]
+pub struct AnalyticsExportRun {
+    pub id: uuid::Uuid,
+    pub config_id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    /// Either running, succeeded or failed. A failed window is exported again on the next run.
+    pub status: String,
+    /// Events created at or after this time are included.
+    pub window_start: chrono::NaiveDateTime,
+    /// Events created before this time are included.
+    pub window_end: chrono::NaiveDateTime,
+    /// Object storage keys of the files written by the run.
+    pub files: Vec<String>,
+    /// Number of events written across all files.
+    pub row_count: i64,
+    /// Error of a failed run.
+    pub error: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl AnalyticsExportRun {
+    pub fn from_details(
+        config_id: uuid::Uuid,
+        dataset_id: uuid::Uuid,
+        window_start: chrono::NaiveDateTime,
+        window_end: chrono::NaiveDateTime,
+    ) -> Self {
+        AnalyticsExportRun {
+            id: uuid::Uuid::new_v4(),
+            config_id,
+            dataset_id,
+            status: AnalyticsExportRunStatus::Running.to_string(),
+            window_start,
+            window_end,
+            files: vec![],
+            row_count: 0,
+            error: None,
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// An analytics event on its way to the forward url of its dataset.
+pub struct AnalyticsForwardMessage {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub event_type: AnalyticsExportEventType,
+    pub event: serde_json::Value,
+    pub attempt_number: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+/// How often an analytics report is emailed. Each report covers the period since the previous one.
+pub enum AnalyticsReportSchedule {
+    #[display(fmt = "daily")]
+    Daily,
+    #[display(fmt = "weekly")]
+    #[default]
+    Weekly,
+    #[display(fmt = "monthly")]
+    Monthly,
+}
+
+impl AnalyticsReportSchedule {
+    pub fn from_name(schedule: &str) -> Self {
+        match schedule {
+            "daily" => AnalyticsReportSchedule::Daily,
+            "monthly" => AnalyticsReportSchedule::Monthly,
+            _ => AnalyticsReportSchedule::Weekly,
+        }
+    }
+
+    pub fn period(&self) -> chrono::Duration {
+        match self {
+            AnalyticsReportSchedule::Daily => chrono::Duration::days(1),
+            AnalyticsReportSchedule::Weekly => chrono::Duration::weeks(1),
+            AnalyticsReportSchedule::Monthly => chrono::Duration::days(30),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[schema(example = json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "user_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "schedule": "weekly",
+    "active": true,
+    "last_sent_at": "2021-01-01 00:00:00.000",
+    "next_send_at": "2021-01-08 00:00:00.000",
+    "created_at": "2021-01-01 00:00:00.000",
+    "updated_at": "2021-01-01 00:00:00.000",
+}))]
+#[diesel(table_name = analytics_report_subscriptions)]
+/// A user's subscription to emailed analytics reports of a dataset.
+pub struct AnalyticsReportSubscription {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    /// Either daily, weekly or monthly.
+    pub schedule: String,
+    /// Inactive subscriptions are kept but not sent.
+    pub active: bool,
+    /// When the last report was sent.
+    pub last_sent_at: Option<chrono::NaiveDateTime>,
+    /// When the next report is sent.
+    pub next_send_at: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl AnalyticsReportSubscription {
+    pub fn from_details(
+        user_id: uuid::Uuid,
+        dataset_id: uuid::Uuid,
+        schedule: AnalyticsReportSchedule,
+        active: bool,
+    ) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+
+        AnalyticsReportSubscription {
+            id: uuid::Uuid::new_v4(),
+            user_id,
+            dataset_id,
+            schedule: schedule.to_string(),
+            active,
+            last_sent_at: None,
+            next_send_at: now + schedule.period(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn schedule(&self) -> AnalyticsReportSchedule {
+        AnalyticsReportSchedule::from_name(&self.schedule)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Default)]
+#[schema(example = json!({
+    "search_calls": 1200,
+    "rag_calls": 85,
+    "prompt_tokens": 240000,
+    "completion_tokens": 31000,
+    "embedding_tokens": 56000,
+    "file_pages": 40,
+    "storage_bytes": 5242880,
+}))]
+/// Metered usage of a dataset. Token counts are those reported by the LLM provider where available and are estimated at 4 characters per token otherwise.
+pub struct UsageCounts {
+    /// Number of successful search, autocomplete, recommendation, count and scroll requests.
+    pub search_calls: i64,
+    /// Number of successful requests which generate a completion.
+    pub rag_calls: i64,
+    /// Tokens sent to the LLM.
+    pub prompt_tokens: i64,
+    /// Tokens generated by the LLM.
+    pub completion_tokens: i64,
+    /// Tokens embedded for searches and ingested chunks.
+    pub embedding_tokens: i64,
+    /// Pages of files converted with pdf2md.
+    pub file_pages: i64,
+    /// Bytes of files uploaded.
+    pub storage_bytes: i64,
+}
+
+impl UsageCounts {
+    pub fn is_empty(&self) -> bool {
+        *self == UsageCounts::default()
+    }
+
+    pub fn add(&mut self, other: &UsageCounts) {
+        self.search_calls += other.search_calls;
+        self.rag_calls += other.rag_calls;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.embedding_tokens += other.embedding_tokens;
+        self.file_pages += other.file_pages;
+        self.storage_bytes += other.storage_bytes;
+    }
+
+    /// The counts paired with their metric names, in the order of the usage CSV columns.
+    pub fn metrics(&self) -> [(&'static str, i64); 7] {
+        [
+            ("search_calls", self.search_calls),
+            ("rag_calls", self.rag_calls),
+            ("prompt_tokens", self.prompt_tokens),
+            ("completion_tokens", self.completion_tokens),
+            ("embedding_tokens", self.embedding_tokens),
+            ("file_pages", self.file_pages),
+            ("storage_bytes", self.storage_bytes),
+        ]
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[schema(example = json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "api_key_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "usage_date": "2021-01-01",
+    "search_calls": 1200,
+    "rag_calls": 85,
+    "prompt_tokens": 240000,
+    "completion_tokens": 31000,
+    "embedding_tokens": 56000,
+    "file_pages": 40,
+    "storage_bytes": 5242880,
+    "stripe_reported_at": null,
+    "created_at": "2021-01-01 00:00:00.000",
+    "updated_at": "2021-01-01 00:00:00.000",
+}))]
+#[diesel(table_name = dataset_usage)]
+/// Usage of a dataset by an api key over a day. Usage which was not made with an api key, such as from the dashboard, is recorded under the nil uuid.
+pub struct DatasetUsage {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub api_key_id: uuid::Uuid,
+    /// The UTC day the usage was made on.
+    pub usage_date: chrono::NaiveDate,
+    pub search_calls: i64,
+    pub rag_calls: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub embedding_tokens: i64,
+    pub file_pages: i64,
+    pub storage_bytes: i64,
+    /// When the usage was pushed to Stripe metered billing.
+    pub stripe_reported_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl DatasetUsage {
+    pub fn from_counts(
+        dataset_id: uuid::Uuid,
+        api_key_id: Option<uuid::Uuid>,
+        usage_date: chrono::NaiveDate,
+        counts: UsageCounts,
+    ) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+
+        DatasetUsage {
+            id: uuid::Uuid::new_v4(),
+            dataset_id,
+            api_key_id: api_key_id.unwrap_or_default(),
+            usage_date,
+            search_calls: counts.search_calls,
+            rag_calls: counts.rag_calls,
+            prompt_tokens: counts.prompt_tokens,
+            completion_tokens: counts.completion_tokens,
+            embedding_tokens: counts.embedding_tokens,
+            file_pages: counts.file_pages,
+            storage_bytes: counts.storage_bytes,
+            stripe_reported_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn counts(&self) -> UsageCounts {
+        UsageCounts {
+            search_calls: self.search_calls,
+            rag_calls: self.rag_calls,
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: self.completion_tokens,
+            embedding_tokens: self.embedding_tokens,
+            file_pages: self.file_pages,
+            storage_bytes: self.storage_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+/// Kind of change proposed by a query suggestion. Synonyms and redirects are accepted into the dataset's QUERY_REWRITES, typos into its typo dictionary and content gaps only record that they were reviewed.
+pub enum QuerySuggestionType {
+    #[display(fmt = "synonym")]
+    Synonym,
+    #[display(fmt = "redirect")]
+    Redirect,
+    #[display(fmt = "typo")]
+    Typo,
+    #[display(fmt = "content_gap")]
+    ContentGap,
+}
+
+impl QuerySuggestionType {
+    pub fn from_name(suggestion_type: &str) -> Option<Self> {
+        match suggestion_type {
+            "synonym" => Some(QuerySuggestionType::Synonym),
+            "redirect" => Some(QuerySuggestionType::Redirect),
+            "typo" => Some(QuerySuggestionType::Typo),
+            "content_gap" => Some(QuerySuggestionType::ContentGap),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuerySuggestionStatus {
+    #[display(fmt = "pending")]
+    Pending,
+    #[display(fmt = "accepted")]
+    Accepted,
+    #[display(fmt = "dismissed")]
+    Dismissed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[schema(example = json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "suggestion_type": "synonym",
+    "query": "laptop",
+    "suggestion": "notebook",
+    "occurrences": 42,
+    "status": "pending",
+    "created_at": "2021-01-01 00:00:00.000",
+    "updated_at": "2021-01-02 00:00:00.000",
+}))]
+#[diesel(table_name = query_suggestions)]
+/// Change to the dataset's query handling proposed from its search analytics, waiting in the review queue until it is accepted or dismissed.
+pub struct QuerySuggestion {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    /// Either synonym, redirect, typo or content_gap.
+    pub suggestion_type: String,
+    /// The query, or word for synonyms and typos, users searched for.
+    pub query: String,
+    /// The query or word users reformulated into which found results. Empty for content gaps.
+    pub suggestion: String,
+    /// Number of searches supporting the suggestion over the mined windows.
+    pub occurrences: i64,
+    /// Either pending, accepted or dismissed.
+    pub status: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl QuerySuggestion {
+    pub fn from_details(
+        dataset_id: uuid::Uuid,
+        suggestion_type: QuerySuggestionType,
+        query: String,
+        suggestion: String,
+        occurrences: i64,
+    ) -> Self {
+        QuerySuggestion {
+            id: uuid::Uuid::new_v4(),
+            dataset_id,
+            suggestion_type: suggestion_type.to_string(),
+            query,
+            suggestion,
+            occurrences,
+            status: QuerySuggestionStatus::Pending.to_string(),
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[schema(example = json!({
+    "name": "cross_encoder",
+    "weight": 1.0,
+    "search_overrides": {"sort_options": {"sort_by": {"rerank_type": "cross_encoder"}}},
+    "message_overrides": null,
+    "config_overrides": {"RAG_PROMPT": "Answer briefly using only the retrieved documents:"}
+}))]
+/// One arm of an experiment. Users bucketed into the variant have its overrides applied to their searches and RAG completions.
+pub struct ExperimentVariant {
+    /// Name of the variant, unique within the experiment. It is recorded on every search and RAG event of the variant's users.
+    pub name: String,
+    /// Share of users bucketed into the variant relative to the weights of the other variants. Default is 1.0.
+    #[serde(default = "default_experiment_variant_weight")]
+    pub weight: f32,
+    /// Fields of the search chunks request payload to override, e.g. `sort_options` or `search_type`.
+    pub search_overrides: Option<serde_json::Value>,
+    /// Fields of the create message request payload to override, e.g. `llm_options` or `page_size`.
+    pub message_overrides: Option<serde_json::Value>,
+    /// Dataset configuration fields to override for the variant's requests, e.g. `RAG_PROMPT` or `RERANKER_MODEL_NAME`.
+    pub config_overrides: Option<DatasetConfigurationDTO>,
+}
+
+fn default_experiment_variant_weight() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[schema(example = json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "name": "Cross encoder reranking",
+    "variants": [
+        {"name": "control", "weight": 1.0},
+        {"name": "cross_encoder", "weight": 1.0, "search_overrides": {"sort_options": {"sort_by": {"rerank_type": "cross_encoder"}}}}
+    ],
+    "active": true,
+    "created_at": "2021-01-01 00:00:00.000",
+    "updated_at": "2021-01-01 00:00:00.000",
+}))]
+#[diesel(table_name = experiments)]
+/// A/B test comparing search and RAG configurations of a dataset. Users are deterministically bucketed into one of its variants by their `user_id`. A dataset can have one active experiment at a time.
+pub struct Experiment {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub name: String,
+    /// The variants of the experiment, see ExperimentVariant.
+    pub variants: serde_json::Value,
+    /// Whether users are currently bucketed into the experiment's variants.
+    pub active: bool,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl Experiment {
+    pub fn from_details(
+        dataset_id: uuid::Uuid,
+        name: String,
+        variants: Vec<ExperimentVariant>,
+    ) -> Self {
+        Experiment {
+            id: uuid::Uuid::new_v4(),
+            dataset_id,
+            name,
+            variants: serde_json::to_value(variants).unwrap_or(json!([])),
+            active: true,
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    pub fn variants(&self) -> Vec<ExperimentVariant> {
+        serde_json::from_value(self.variants.clone()).unwrap_or_default()
+    }
+
+    /// Deterministically picks the variant of a user. The same user always lands in the same variant for as long as the experiment's variants are unchanged.
+    pub fn assign_variant(&self, user_id: &str) -> Option<ExperimentVariant> {
+        let variants = self.variants();
+        let total_weight: f64 = variants
+            .iter()
+            .map(|variant| variant.weight.max(0.0) as f64)
+            .sum();
+        if total_weight <= 0.0 {
+            return None;
+        }
+
+        // FNV-1a so buckets are stable across releases and hosts
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in format!("{}:{}", self.id, user_id).bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        let bucket = (hash as f64 / u64::MAX as f64) * total_weight;
+
+        let mut cumulative_weight = 0.0;
+        for variant in variants.iter() {
+            cumulative_weight += variant.weight.max(0.0) as f64;
+            if bucket < cumulative_weight {
+                return Some(variant.clone());
+            }
+        }
+
+        variants.last().cloned()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The experiment and variant a request was bucketed into.
+pub struct ExperimentAssignment {
+    pub experiment_id: uuid::Uuid,
+    pub variant: ExperimentVariant,
+}
+
+impl ExperimentAssignment {
+    /// Values of the experiment_id and experiment_variant columns of search and RAG events.
+    pub fn event_fields(assignment: Option<&ExperimentAssignment>) -> (String, String) {
+        assignment
+            .map(|assignment| {
+                (
+                    assignment.experiment_id.to_string(),
+                    assignment.variant.name.clone(),
+                )
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, ValidGrouping)]
+#[diesel(table_name = dataset_group_counts)]
+pub struct DatasetGroupCount {
+    pub id: uuid::Uuid,
+    pub group_count: i32,
+    pub dataset_id: uuid::Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, ValidGrouping)]
+#[diesel(table_name = dataset_event_counts)]
+pub struct DatasetEventCount {
+    pub id: uuid::Uuid,
+    pub dataset_uuid: uuid::Uuid,
+    pub notification_count: i32,
+}
+
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Queryable,
+    Insertable,
+    Selectable,
+    Clone,
+    ToSchema,
+    QueryableByName,
+    AsChangeset,
+)]
+#[schema(example=json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "name": "Trieve",
+    "created_at": "2021-01-01 00:00:00.000",
+    "updated_at": "2021-01-01 00:00:00.000",
+    "organization_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "tracking_id": "foobar-dataset",
+    "server_configuration": {
+        "LLM_BASE_URL": "https://api.openai.com/v1",
+        "EMBEDDING_BASE_URL": "https://api.openai.com/v1",
+        "EMBEDDING_MODEL_NAME": "text-embedding-3-small",
+        "MESSAGE_TO_QUERY_PROMPT": "Write a 1-2 sentence semantic search query along the lines of a hypothetical response to: \n\n",
+        "RAG_PROMPT": "Use the following retrieved documents to respond briefly and accurately:",
+        "N_RETRIEVALS_TO_INCLUDE": 8,
         "EMBEDDING_SIZE": 1536,
         "DISTANCE_METRIC": "cosine",
         "LLM_DEFAULT_MODEL": "gpt-4o",
@@ -2597,6 +4154,495 @@ pub struct DatasetConfiguration {
     pub PUBLIC_DATASET: PublicDatasetOptions,
     pub DISABLE_ANALYTICS: bool,
     pub PAGEFIND_ENABLED: bool,
+    pub LLM_FALLBACK_ENDPOINTS: Vec<FallbackEndpoint>,
+    pub EMBEDDING_FALLBACK_ENDPOINTS: Vec<FallbackEndpoint>,
+    pub RERANKER_FALLBACK_ENDPOINTS: Vec<FallbackEndpoint>,
+    pub GUARDRAILS: GuardrailsConfig,
+    pub INGESTION_WEBHOOK_URL: Option<String>,
+    pub INGESTION_WEBHOOK_SECRET: Option<String>,
+    pub RATE_LIMITS: RateLimits,
+    pub ACCESS_CONTROL: AccessControlConfig,
+    pub QUERY_REWRITES: QueryRewrites,
+    pub ENGAGEMENT_RANKING: EngagementRankingConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+#[schema(example=json!({
+    "base_url": "https://openrouter.ai/api/v1",
+    "model_name": "openai/gpt-4o"
+}))]
+/// An alternate endpoint which is tried, in order, after the primary endpoint and any earlier fallbacks have failed.
+pub struct FallbackEndpoint {
+    /// The base URL of the fallback endpoint
+    pub base_url: String,
+    /// The API key for the fallback endpoint. If not specified, the key the primary endpoint would use is sent.
+    #[serde(skip_serializing)]
+    pub api_key: Option<String>,
+    /// The model name to request from the fallback endpoint. If not specified, the primary model name is used. Embedding fallbacks must produce vectors compatible with the primary embedding model.
+    pub model_name: Option<String>,
+}
+
+impl FallbackEndpoint {
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "base_url": self.base_url,
+            "api_key": self.api_key,
+            "model_name": self.model_name,
+        })
+    }
+
+    pub fn list_from_json(value: Option<&serde_json::Value>) -> Vec<FallbackEndpoint> {
+        value
+            .and_then(|v| serde_json::from_value::<Vec<FallbackEndpoint>>(v.clone()).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|endpoint| !endpoint.base_url.is_empty())
+            .collect()
+    }
+
+    /// Keeps the stored API key of an endpoint when it is re-submitted without one, since keys are never returned to clients.
+    pub fn merge_api_keys(
+        new_endpoints: Vec<FallbackEndpoint>,
+        curr_endpoints: &[FallbackEndpoint],
+    ) -> Vec<FallbackEndpoint> {
+        new_endpoints
+            .into_iter()
+            .map(|endpoint| {
+                if endpoint.api_key.is_some() {
+                    return endpoint;
+                }
+
+                let api_key = curr_endpoints
+                    .iter()
+                    .find(|curr| curr.base_url == endpoint.base_url)
+                    .and_then(|curr| curr.api_key.clone());
+
+                FallbackEndpoint {
+                    api_key,
+                    ..endpoint
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, Display, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// What to do when a guardrail check flags a message.
+pub enum GuardrailAction {
+    /// Replace the message with the refusal message.
+    #[display(fmt = "refuse")]
+    Refuse,
+    /// Replace the flagged spans with `[REDACTED]`. Violations which can not be located in the text, such as classifier verdicts, are refused instead.
+    #[display(fmt = "redact")]
+    Redact,
+    /// Let the message through unchanged and only record the violation.
+    #[display(fmt = "continue")]
+    Continue,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, Default, PartialEq)]
+#[serde(default)]
+#[schema(example=json!({
+    "enabled": true,
+    "denylist": ["ignore previous instructions"],
+    "regex_patterns": ["(?i)system prompt"],
+    "detect_pii": true,
+    "classifier_prompt": "Block questions which are not about our product documentation.",
+    "input_action": "refuse",
+    "output_action": "redact",
+    "refusal_message": "Sorry, I can't help with that."
+}))]
+/// Checks run on the user message before retrieval and on the LLM completion before it is returned. Flagged user messages are stored redacted. While enabled, streamed completions are buffered and sent as a single message once the output check has run.
+pub struct GuardrailsConfig {
+    /// Whether to run the guardrail checks. Defaults to false. Enabling them turns off streaming, since the whole completion is buffered until the output check has run.
+    pub enabled: bool,
+    /// Phrases which are flagged when they appear in a message, matched case-insensitively.
+    pub denylist: Vec<String>,
+    /// Regular expressions which are flagged when they match a message.
+    pub regex_patterns: Vec<String>,
+    /// Whether to flag email addresses, phone numbers and payment card numbers.
+    pub detect_pii: bool,
+    /// Optional policy given to the LLM to classify each message. The classifier replies ALLOW or BLOCK and a BLOCK is treated as a violation.
+    pub classifier_prompt: Option<String>,
+    /// Action to take when the user message is flagged. Defaults to refuse.
+    pub input_action: Option<GuardrailAction>,
+    /// Action to take when the completion is flagged. Defaults to redact.
+    pub output_action: Option<GuardrailAction>,
+    /// Message returned in place of a refused input or completion.
+    pub refusal_message: Option<String>,
+}
+
+impl GuardrailsConfig {
+    pub fn from_json(value: Option<&serde_json::Value>) -> Self {
+        value
+            .and_then(|v| serde_json::from_value::<GuardrailsConfig>(v.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        for pattern in self.regex_patterns.iter() {
+            regex::Regex::new(pattern).map_err(|err| {
+                ServiceError::BadRequest(format!(
+                    "Invalid guardrail regex pattern {}: {}",
+                    pattern, err
+                ))
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Eq, Display)]
+#[serde(rename_all = "snake_case")]
+/// Group of routes which share a rate limit and which an api key can be scoped to.
+pub enum ApiOperation {
+    /// Search, autocomplete, recommendation, count and scroll routes.
+    #[display(fmt = "search")]
+    Search,
+    /// Routes which generate a completion such as creating a topic or creating, editing or regenerating a message. Api keys scoped to it can also read topics and messages.
+    #[display(fmt = "rag")]
+    Rag,
+    /// Routes which create or update chunks and upload files.
+    #[display(fmt = "ingest")]
+    Ingest,
+}
+
+impl ApiOperation {
+    pub fn from_request(method: &actix_web::http::Method, path: &str) -> Option<Self> {
+        use actix_web::http::Method;
+
+        match path.trim_end_matches('/') {
+            "/api/chunk/search"
+            | "/api/chunk/autocomplete"
+            | "/api/chunk/recommend"
+            | "/api/chunk/count"
+            | "/api/chunk/suggestions"
+            | "/api/chunks/scroll"
+            | "/api/chunk_group/search"
+            | "/api/chunk_group/group_oriented_search"
+            | "/api/chunk_group/recommend"
+                if *method == Method::POST =>
+            {
+                Some(ApiOperation::Search)
+            }
+            "/api/message" if *method != Method::GET => Some(ApiOperation::Rag),
+            "/api/topic" if *method == Method::POST => Some(ApiOperation::Rag),
+            "/api/chunk/generate" | "/api/message/get_tool_function_params"
+                if *method == Method::POST =>
+            {
+                Some(ApiOperation::Rag)
+            }
+            "/api/chunk" if *method == Method::POST || *method == Method::PUT => {
+                Some(ApiOperation::Ingest)
+            }
+            "/api/chunk/tracking_id/update" if *method == Method::PUT => Some(ApiOperation::Ingest),
+            "/api/file" | "/api/file/html_page" | "/api/file/csv_or_jsonl"
+                if *method == Method::POST =>
+            {
+                Some(ApiOperation::Ingest)
+            }
+            _ => None,
+        }
+    }
+
+    /// Operation an api key must be scoped to in order to make the request. Besides the rate limited routes of `from_request`, the topic and message read routes belong to the RAG operation.
+    pub fn scope_from_request(method: &actix_web::http::Method, path: &str) -> Option<Self> {
+        ApiOperation::from_request(method, path).or_else(|| {
+            let path = path.trim_end_matches('/');
+            let is_rag_read = *method == actix_web::http::Method::GET
+                && (path.starts_with("/api/topic/owner/")
+                    || path.starts_with("/api/message/")
+                    || path.starts_with("/api/messages/"));
+
+            is_rag_read.then_some(ApiOperation::Rag)
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq)]
+#[schema(example=json!({
+    "requests": 60,
+    "per_seconds": 60
+}))]
+/// Token bucket which holds up to `requests` tokens and refills completely over `per_seconds` seconds.
+pub struct RateLimit {
+    /// Maximum number of requests allowed in a burst.
+    pub requests: u32,
+    /// Number of seconds it takes for the full number of requests to become available again.
+    pub per_seconds: u32,
+}
+
+impl RateLimit {
+    /// Parses limits in the `requests/per_seconds` format used by the server's environment variables, e.g. `600/60`.
+    pub fn from_env(key: &str) -> Option<Self> {
+        let value = std::env::var(key).ok()?;
+        let (requests, per_seconds) = value.trim().split_once('/')?;
+
+        Some(RateLimit {
+            requests: requests.trim().parse().ok()?,
+            per_seconds: per_seconds.trim().parse().ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, Default, PartialEq)]
+#[serde(default)]
+#[schema(example=json!({
+    "search": {
+        "requests": 60,
+        "per_seconds": 60
+    },
+    "rag": {
+        "requests": 10,
+        "per_seconds": 60
+    },
+    "ingest": null
+}))]
+/// Request rate limits applied separately to search, RAG and ingest routes. Routes without a limit are not rate limited.
+pub struct RateLimits {
+    /// Limit for search, autocomplete, recommendation and scroll routes.
+    pub search: Option<RateLimit>,
+    /// Limit for routes which generate a completion such as creating, editing or regenerating a message.
+    pub rag: Option<RateLimit>,
+    /// Limit for routes which create, update or upload chunks and files.
+    pub ingest: Option<RateLimit>,
+}
+
+impl RateLimits {
+    pub fn from_json(value: Option<&serde_json::Value>) -> Self {
+        value
+            .and_then(|v| serde_json::from_value::<RateLimits>(v.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn get_limit(&self, operation: ApiOperation) -> Option<RateLimit> {
+        match operation {
+            ApiOperation::Search => self.search,
+            ApiOperation::Rag => self.rag,
+            ApiOperation::Ingest => self.ingest,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        for rate_limit in [self.search, self.rag, self.ingest].iter().flatten() {
+            if rate_limit.requests == 0 || rate_limit.per_seconds == 0 {
+                return Err(ServiceError::BadRequest(
+                    "Rate limit requests and per_seconds must be greater than 0".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+/// Whether chunks and groups without an access control list are visible to every end user or to none of them.
+pub enum AclDefaultPolicy {
+    #[default]
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, Default, PartialEq)]
+#[serde(default)]
+#[schema(example=json!({
+    "enabled": true,
+    "default_policy": "deny"
+}))]
+/// Document-level access control which limits the chunks and groups an end user can retrieve to those whose `acl` contains one of their principals.
+pub struct AccessControlConfig {
+    /// Whether the access control lists of the dataset's chunks and groups are enforced. Default is false.
+    pub enabled: bool,
+    /// Policy for chunks and groups without an access control list. Default is allow.
+    pub default_policy: AclDefaultPolicy,
+}
+
+impl AccessControlConfig {
+    pub fn from_json(value: Option<&serde_json::Value>) -> Self {
+        value
+            .and_then(|v| serde_json::from_value::<AccessControlConfig>(v.clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+/// Search query which is replaced by another query before it is run.
+pub struct QueryRedirect {
+    /// The query to redirect, matched case-insensitively against the whole search query.
+    pub query: String,
+    /// The query which is run instead.
+    pub redirect_to: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+/// Single-word term whose synonyms are appended to every search query containing it.
+pub struct QuerySynonym {
+    /// The term to expand, matched case-insensitively against the words of the search query.
+    pub term: String,
+    /// Words appended to the query when it contains the term and not the synonym already.
+    pub synonyms: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, Default, PartialEq)]
+#[serde(default)]
+#[schema(example=json!({
+    "redirects": [{"query": "pricing page", "redirect_to": "plans and pricing"}],
+    "synonyms": [{"term": "laptop", "synonyms": ["notebook"]}]
+}))]
+/// Rewrites applied to every search query of the dataset before it is run. Redirects are applied first, then synonym expansion.
+pub struct QueryRewrites {
+    pub redirects: Vec<QueryRedirect>,
+    pub synonyms: Vec<QuerySynonym>,
+}
+
+impl QueryRewrites {
+    pub fn from_json(value: Option<&serde_json::Value>) -> Self {
+        value
+            .and_then(|v| serde_json::from_value::<QueryRewrites>(v.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.redirects.is_empty() && self.synonyms.is_empty()
+    }
+
+    pub fn apply(&self, query: &str) -> String {
+        let normalized_query = query.trim().to_lowercase();
+        let mut query = self
+            .redirects
+            .iter()
+            .find(|redirect| redirect.query.trim().to_lowercase() == normalized_query)
+            .map(|redirect| redirect.redirect_to.clone())
+            .unwrap_or_else(|| query.to_string());
+
+        let words: Vec<String> = query
+            .split_whitespace()
+            .map(|word| word.to_lowercase())
+            .collect();
+        let mut additions: Vec<String> = vec![];
+        for synonym in self.synonyms.iter() {
+            if !words.contains(&synonym.term.trim().to_lowercase()) {
+                continue;
+            }
+            for word in synonym.synonyms.iter() {
+                let word = word.trim().to_lowercase();
+                if !word.is_empty() && !words.contains(&word) && !additions.contains(&word) {
+                    additions.push(word);
+                }
+            }
+        }
+
+        if !additions.is_empty() {
+            query = format!("{} {}", query, additions.join(" "));
+        }
+
+        query
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq)]
+#[serde(default)]
+#[schema(example=json!({
+    "enabled": true,
+    "weight": 0.3,
+    "half_life_days": 14.0,
+    "lookback_days": 90
+}))]
+/// Click-model ranking stage which boosts chunks users engaged with. Boosts are computed periodically from the dataset's click, add to cart and purchase events, corrected for the position the chunk was shown at and decayed by age.
+pub struct EngagementRankingConfig {
+    /// Whether engagement boosts are computed and applied to the dataset's searches. Default is false.
+    pub enabled: bool,
+    /// How strongly engagement boosts a chunk's score. A chunk with the maximum boost has its score multiplied by 1 + weight. Default is 0.3.
+    pub weight: f32,
+    /// Number of days after which an event counts half as much. Default is 14.
+    pub half_life_days: f32,
+    /// Number of days of events boosts are computed from, between 1 and 365. Default is 90.
+    pub lookback_days: i64,
+}
+
+impl Default for EngagementRankingConfig {
+    fn default() -> Self {
+        EngagementRankingConfig {
+            enabled: false,
+            weight: 0.3,
+            half_life_days: 14.0,
+            lookback_days: 90,
+        }
+    }
+}
+
+impl EngagementRankingConfig {
+    pub fn from_json(value: Option<&serde_json::Value>) -> Self {
+        value
+            .and_then(|v| serde_json::from_value::<EngagementRankingConfig>(v.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if self.weight < 0.0 {
+            return Err(ServiceError::BadRequest(
+                "Engagement ranking weight must not be negative".to_string(),
+            ));
+        }
+
+        if self.half_life_days <= 0.0 {
+            return Err(ServiceError::BadRequest(
+                "Engagement ranking half_life_days must be greater than 0".to_string(),
+            ));
+        }
+
+        if !(1..=365).contains(&self.lookback_days) {
+            return Err(ServiceError::BadRequest(
+                "Engagement ranking lookback_days must be between 1 and 365".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Principals of the end user making a request, which the access control lists of the dataset's chunks and groups are enforced against. `None` means the request is not restricted, either because access control is disabled for the dataset or because an admin made it without an end-user identity.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AclPrincipals(pub Option<Vec<String>>);
+
+impl AclPrincipals {
+    /// Whether a chunk or group with the given access control list is visible to the end user.
+    pub fn can_access(&self, acl: &Option<Vec<Option<String>>>) -> bool {
+        match &self.0 {
+            Some(principals) => get_qdrant_acl(acl.clone())
+                .iter()
+                .any(|principal| principals.contains(principal)),
+            None => true,
+        }
+    }
+
+    /// Filter which limits a request to the chunks visible to the end user.
+    pub fn required_filters(&self) -> Option<ChunkFilter> {
+        self.0.as_ref().map(|principals| ChunkFilter {
+            should: None,
+            must: Some(vec![ConditionType::Field(FieldCondition {
+                field: "acl".to_string(),
+                match_any: Some(
+                    principals
+                        .iter()
+                        .map(|principal| MatchCondition::Text(principal.clone()))
+                        .collect(),
+                ),
+                match_all: None,
+                range: None,
+                boolean: None,
+                date_range: None,
+                geo_bounding_box: None,
+                geo_radius: None,
+                geo_polygon: None,
+            })]),
+            must_not: None,
+        })
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
@@ -2710,6 +4756,24 @@ pub struct DatasetConfigurationDTO {
     pub DISABLE_ANALYTICS: Option<bool>,
     /// Whether to enable pagefind indexing
     pub PAGEFIND_ENABLED: Option<bool>,
+    /// Ordered list of LLM endpoints to try when the LLM_BASE_URL endpoint is unavailable
+    pub LLM_FALLBACK_ENDPOINTS: Option<Vec<FallbackEndpoint>>,
+    /// Ordered list of embedding endpoints to try when the EMBEDDING_BASE_URL endpoint is unavailable. Each must serve a model that is vector-compatible with EMBEDDING_MODEL_NAME.
+    pub EMBEDDING_FALLBACK_ENDPOINTS: Option<Vec<FallbackEndpoint>>,
+    /// Ordered list of reranker endpoints to try when the RERANKER_BASE_URL endpoint is unavailable
+    pub RERANKER_FALLBACK_ENDPOINTS: Option<Vec<FallbackEndpoint>>,
+    /// Input and output checks for RAG completions such as denylists, PII detection and an LLM classifier
+    pub GUARDRAILS: Option<GuardrailsConfig>,
+    /// URL which receives a POST with the ingestion job's status once all of its chunks are done or failed. The POST is signed like dataset webhooks with the `INGESTION_WEBHOOK_SECRET` generated for the dataset, which can be read from its server configuration, and is retried with backoff by the webhook worker. Set to an empty string to remove it.
+    pub INGESTION_WEBHOOK_URL: Option<String>,
+    /// Request rate limits for the dataset's search, RAG and ingest routes, shared by every user and api key
+    pub RATE_LIMITS: Option<RateLimits>,
+    /// Document-level access control for the dataset's chunks and groups based on the end user's id and groups
+    pub ACCESS_CONTROL: Option<AccessControlConfig>,
+    /// Redirects and synonyms applied to every search query of the dataset before it is run
+    pub QUERY_REWRITES: Option<QueryRewrites>,
+    /// Opt-in ranking stage which boosts chunks based on the clicks, add to carts and purchases of previous searches
+    pub ENGAGEMENT_RANKING: Option<EngagementRankingConfig>,
 }
 
 impl From<DatasetConfigurationDTO> for DatasetConfiguration {
@@ -2754,6 +4818,16 @@ impl From<DatasetConfigurationDTO> for DatasetConfiguration {
             },
             DISABLE_ANALYTICS: dto.DISABLE_ANALYTICS.unwrap_or(false),
             PAGEFIND_ENABLED: dto.PAGEFIND_ENABLED.unwrap_or(false),
+            LLM_FALLBACK_ENDPOINTS: dto.LLM_FALLBACK_ENDPOINTS.unwrap_or_default(),
+            EMBEDDING_FALLBACK_ENDPOINTS: dto.EMBEDDING_FALLBACK_ENDPOINTS.unwrap_or_default(),
+            RERANKER_FALLBACK_ENDPOINTS: dto.RERANKER_FALLBACK_ENDPOINTS.unwrap_or_default(),
+            GUARDRAILS: dto.GUARDRAILS.unwrap_or_default(),
+            INGESTION_WEBHOOK_URL: dto.INGESTION_WEBHOOK_URL.filter(|url| !url.is_empty()),
+            INGESTION_WEBHOOK_SECRET: None,
+            RATE_LIMITS: dto.RATE_LIMITS.unwrap_or_default(),
+            ACCESS_CONTROL: dto.ACCESS_CONTROL.unwrap_or_default(),
+            QUERY_REWRITES: dto.QUERY_REWRITES.unwrap_or_default(),
+            ENGAGEMENT_RANKING: dto.ENGAGEMENT_RANKING.unwrap_or_default(),
         }
     }
 }
@@ -2804,6 +4878,15 @@ impl From<DatasetConfiguration> for DatasetConfigurationDTO {
             }),
             DISABLE_ANALYTICS: Some(config.DISABLE_ANALYTICS),
             PAGEFIND_ENABLED: Some(config.PAGEFIND_ENABLED),
+            LLM_FALLBACK_ENDPOINTS: Some(config.LLM_FALLBACK_ENDPOINTS),
+            EMBEDDING_FALLBACK_ENDPOINTS: Some(config.EMBEDDING_FALLBACK_ENDPOINTS),
+            RERANKER_FALLBACK_ENDPOINTS: Some(config.RERANKER_FALLBACK_ENDPOINTS),
+            GUARDRAILS: Some(config.GUARDRAILS),
+            INGESTION_WEBHOOK_URL: config.INGESTION_WEBHOOK_URL,
+            RATE_LIMITS: Some(config.RATE_LIMITS),
+            ACCESS_CONTROL: Some(config.ACCESS_CONTROL),
+            QUERY_REWRITES: Some(config.QUERY_REWRITES),
+            ENGAGEMENT_RANKING: Some(config.ENGAGEMENT_RANKING),
         }
     }
 }
@@ -2849,6 +4932,16 @@ impl Default for DatasetConfiguration {
             },
             DISABLE_ANALYTICS: false,
             PAGEFIND_ENABLED: false,
+            LLM_FALLBACK_ENDPOINTS: vec![],
+            EMBEDDING_FALLBACK_ENDPOINTS: vec![],
+            RERANKER_FALLBACK_ENDPOINTS: vec![],
+            GUARDRAILS: GuardrailsConfig::default(),
+            INGESTION_WEBHOOK_URL: None,
+            INGESTION_WEBHOOK_SECRET: None,
+            RATE_LIMITS: RateLimits::default(),
+            ACCESS_CONTROL: AccessControlConfig::default(),
+            QUERY_REWRITES: QueryRewrites::default(),
+            ENGAGEMENT_RANKING: EngagementRankingConfig::default(),
         }
     }
 }
@@ -3140,6 +5233,32 @@ impl DatasetConfiguration {
                 .unwrap_or(&json!(false))
                 .as_bool()
                 .unwrap_or(false),
+            LLM_FALLBACK_ENDPOINTS: FallbackEndpoint::list_from_json(
+                configuration.get("LLM_FALLBACK_ENDPOINTS"),
+            ),
+            EMBEDDING_FALLBACK_ENDPOINTS: FallbackEndpoint::list_from_json(
+                configuration.get("EMBEDDING_FALLBACK_ENDPOINTS"),
+            ),
+            RERANKER_FALLBACK_ENDPOINTS: FallbackEndpoint::list_from_json(
+                configuration.get("RERANKER_FALLBACK_ENDPOINTS"),
+            ),
+            GUARDRAILS: GuardrailsConfig::from_json(configuration.get("GUARDRAILS")),
+            INGESTION_WEBHOOK_URL: configuration
+                .get("INGESTION_WEBHOOK_URL")
+                .and_then(|v| v.as_str())
+                .filter(|url| !url.is_empty())
+                .map(|url| url.to_string()),
+            INGESTION_WEBHOOK_SECRET: configuration
+                .get("INGESTION_WEBHOOK_SECRET")
+                .and_then(|v| v.as_str())
+                .filter(|secret| !secret.is_empty())
+                .map(|secret| secret.to_string()),
+            RATE_LIMITS: RateLimits::from_json(configuration.get("RATE_LIMITS")),
+            ACCESS_CONTROL: AccessControlConfig::from_json(configuration.get("ACCESS_CONTROL")),
+            QUERY_REWRITES: QueryRewrites::from_json(configuration.get("QUERY_REWRITES")),
+            ENGAGEMENT_RANKING: EngagementRankingConfig::from_json(
+                configuration.get("ENGAGEMENT_RANKING"),
+            ),
         }
     }
 
@@ -3184,6 +5303,16 @@ impl DatasetConfiguration {
             },
             "DISABLE_ANALYTICS": self.DISABLE_ANALYTICS,
             "PAGEFIND_ENABLED": self.PAGEFIND_ENABLED,
+            "LLM_FALLBACK_ENDPOINTS": self.LLM_FALLBACK_ENDPOINTS.iter().map(|endpoint| endpoint.to_json()).collect::<Vec<_>>(),
+            "EMBEDDING_FALLBACK_ENDPOINTS": self.EMBEDDING_FALLBACK_ENDPOINTS.iter().map(|endpoint| endpoint.to_json()).collect::<Vec<_>>(),
+            "RERANKER_FALLBACK_ENDPOINTS": self.RERANKER_FALLBACK_ENDPOINTS.iter().map(|endpoint| endpoint.to_json()).collect::<Vec<_>>(),
+            "GUARDRAILS": self.GUARDRAILS,
+            "INGESTION_WEBHOOK_URL": self.INGESTION_WEBHOOK_URL,
+            "INGESTION_WEBHOOK_SECRET": self.INGESTION_WEBHOOK_SECRET,
+            "RATE_LIMITS": self.RATE_LIMITS,
+            "ACCESS_CONTROL": self.ACCESS_CONTROL,
+            "QUERY_REWRITES": self.QUERY_REWRITES,
+            "ENGAGEMENT_RANKING": self.ENGAGEMENT_RANKING,
         })
     }
 }
@@ -3477,6 +5606,47 @@ impl DatasetConfigurationDTO {
             PAGEFIND_ENABLED: self
                 .PAGEFIND_ENABLED
                 .unwrap_or(curr_dataset_config.PAGEFIND_ENABLED),
+            LLM_FALLBACK_ENDPOINTS: match self.LLM_FALLBACK_ENDPOINTS.clone() {
+                Some(endpoints) => FallbackEndpoint::merge_api_keys(
+                    endpoints,
+                    &curr_dataset_config.LLM_FALLBACK_ENDPOINTS,
+                ),
+                None => curr_dataset_config.LLM_FALLBACK_ENDPOINTS,
+            },
+            EMBEDDING_FALLBACK_ENDPOINTS: match self.EMBEDDING_FALLBACK_ENDPOINTS.clone() {
+                Some(endpoints) => FallbackEndpoint::merge_api_keys(
+                    endpoints,
+                    &curr_dataset_config.EMBEDDING_FALLBACK_ENDPOINTS,
+                ),
+                None => curr_dataset_config.EMBEDDING_FALLBACK_ENDPOINTS,
+            },
+            RERANKER_FALLBACK_ENDPOINTS: match self.RERANKER_FALLBACK_ENDPOINTS.clone() {
+                Some(endpoints) => FallbackEndpoint::merge_api_keys(
+                    endpoints,
+                    &curr_dataset_config.RERANKER_FALLBACK_ENDPOINTS,
+                ),
+                None => curr_dataset_config.RERANKER_FALLBACK_ENDPOINTS,
+            },
+            GUARDRAILS: self
+                .GUARDRAILS
+                .clone()
+                .unwrap_or(curr_dataset_config.GUARDRAILS),
+            INGESTION_WEBHOOK_URL: match self.INGESTION_WEBHOOK_URL.clone() {
+                Some(url) if url.is_empty() => None,
+                Some(url) => Some(url),
+                None => curr_dataset_config.INGESTION_WEBHOOK_URL,
+            },
+            INGESTION_WEBHOOK_SECRET: curr_dataset_config.INGESTION_WEBHOOK_SECRET,
+            RATE_LIMITS: self.RATE_LIMITS.unwrap_or(curr_dataset_config.RATE_LIMITS),
+            ACCESS_CONTROL: self
+                .ACCESS_CONTROL
+                .unwrap_or(curr_dataset_config.ACCESS_CONTROL),
+            QUERY_REWRITES: self
+                .QUERY_REWRITES
+                .unwrap_or(curr_dataset_config.QUERY_REWRITES),
+            ENGAGEMENT_RANKING: self
+                .ENGAGEMENT_RANKING
+                .unwrap_or(curr_dataset_config.ENGAGEMENT_RANKING),
         }
     }
 }
@@ -3633,6 +5803,8 @@ pub struct Organization {
     pub deleted: i32,
     /// Configuration of the organization for the Trieve partner program. Contact partnerships@trieve.ai for more details.
     pub partner_configuration: serde_json::Value,
+    /// Number of days deleted datasets, groups and files are kept in the trash before being purged. Default is 0, which purges them immediately and leaves the trash disabled.
+    pub trash_retention_days: i32,
 }
 
 impl Organization {
@@ -3640,24 +5812,112 @@ impl Organization {
         Organization {
             id: uuid::Uuid::new_v4(),
             name,
-            created_at: chrono::Utc::now().naive_local(),
-            updated_at: chrono::Utc::now().naive_local(),
-            registerable: Some(true),
-            deleted: 0,
-            partner_configuration: json!({}),
+            created_at: chrono::Utc::now().naive_local(),
+            updated_at: chrono::Utc::now().naive_local(),
+            registerable: Some(true),
+            deleted: 0,
+            partner_configuration: json!({}),
+            trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+        }
+    }
+
+    pub fn from_org_with_plan_sub(org_plan_sub: OrganizationWithSubAndPlan) -> Self {
+        org_plan_sub.organization.with_complete_partner_config()
+    }
+
+    pub fn with_complete_partner_config(&self) -> Self {
+        let mut cur = self.clone();
+        cur.partner_configuration =
+            serde_json::to_value(PartnerConfiguration::from_json(cur.partner_configuration))
+                .unwrap_or_default();
+        cur
+    }
+}
+
+pub const DEFAULT_TRASH_RETENTION_DAYS: i32 = 0;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Display, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashItemType {
+    #[display(fmt = "dataset")]
+    Dataset,
+    #[display(fmt = "group")]
+    Group,
+    #[display(fmt = "file")]
+    File,
+}
+
+impl TrashItemType {
+    pub fn from_db(item_type: &str) -> Option<Self> {
+        match item_type {
+            "dataset" => Some(TrashItemType::Dataset),
+            "group" => Some(TrashItemType::Group),
+            "file" => Some(TrashItemType::File),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[schema(example = json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "organization_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "item_type": "group",
+    "item_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "name": "Versions of Oversized T-Shirt",
+    "tracking_id": "SNOVERSIZEDTSHIRT",
+    "delete_chunks": false,
+    "deleted_at": "2021-01-01 00:00:00.000",
+    "purge_at": "2021-01-08 00:00:00.000",
+}))]
+#[diesel(table_name = trash)]
+pub struct TrashItem {
+    pub id: uuid::Uuid,
+    pub organization_id: uuid::Uuid,
+    /// The dataset the item belongs to. For trashed datasets this is the id of the dataset itself.
+    pub dataset_id: uuid::Uuid,
+    /// Either dataset, group or file.
+    pub item_type: String,
+    pub item_id: uuid::Uuid,
+    pub name: String,
+    /// Tracking id the item had before it was deleted. It is released while the item is in the trash and given back on restore if it has not been taken since.
+    pub tracking_id: Option<String>,
+    /// Whether the chunks of the group or file were deleted along with it. Such chunks are hidden from searches while the item is in the trash and come back when it is restored.
+    pub delete_chunks: bool,
+    pub deleted_at: chrono::NaiveDateTime,
+    /// When the item, and its chunks if delete_chunks is set, will be permanently deleted.
+    pub purge_at: chrono::NaiveDateTime,
+}
+
+impl TrashItem {
+    pub fn from_details(
+        organization_id: uuid::Uuid,
+        dataset_id: uuid::Uuid,
+        item_type: TrashItemType,
+        item_id: uuid::Uuid,
+        name: String,
+        tracking_id: Option<String>,
+        retention_days: i32,
+    ) -> Self {
+        let deleted_at = chrono::Utc::now().naive_local();
+        TrashItem {
+            id: uuid::Uuid::new_v4(),
+            organization_id,
+            dataset_id,
+            item_type: item_type.to_string(),
+            item_id,
+            name,
+            tracking_id,
+            delete_chunks: false,
+            deleted_at,
+            purge_at: deleted_at + chrono::Duration::days(retention_days.into()),
         }
     }
 
-    pub fn from_org_with_plan_sub(org_plan_sub: OrganizationWithSubAndPlan) -> Self {
-        org_plan_sub.organization.with_complete_partner_config()
-    }
-
-    pub fn with_complete_partner_config(&self) -> Self {
-        let mut cur = self.clone();
-        cur.partner_configuration =
-            serde_json::to_value(PartnerConfiguration::from_json(cur.partner_configuration))
-                .unwrap_or_default();
-        cur
+    pub fn with_delete_chunks(mut self, delete_chunks: bool) -> Self {
+        self.delete_chunks = delete_chunks;
+        self
     }
 }
 
@@ -4063,9 +6323,101 @@ pub struct ApiKeyRequestParams {
     pub typo_options: Option<TypoOptions>,
     /// Options for handling the response for the llm to return when no results are found
     pub no_result_message: Option<String>,
+    /// Request rate limits for this api key, e.g. to cap a key shared by a public search widget. These apply in addition to the dataset and organization limits.
+    pub rate_limits: Option<RateLimits>,
+    /// Operations the api key is restricted to. If provided, requests to routes outside of these operations are forbidden.
+    pub operations: Option<Vec<ApiOperation>>,
+    /// Filters which are AND-ed with the filters of every request made with the api key, e.g. to keep a per-tenant key from reading other tenants' chunks. Only `must` and `must_not` conditions are supported. If provided, the api key can only be used on the search and create message routes which accept filters.
+    pub required_filters: Option<ChunkFilter>,
+    /// User id which is recorded with the search and RAG requests made with the api key. If the dataset has access control enabled, it is also the end user whose `user:<user_id>` principal the chunks and groups are filtered by.
+    pub user_id: Option<String>,
+    /// Groups of the end user whose `group:<group_name>` principals the chunks and groups are filtered by if the dataset has access control enabled.
+    pub user_groups: Option<Vec<String>>,
+    /// Stripe customer the metered usage of the api key is billed to. If provided, the usage export pushes the api key's daily usage to Stripe as meter events for this customer.
+    pub stripe_customer_id: Option<String>,
+}
+
+/// Paths of the routes whose filters are combined with an api key's `required_filters` in `insert_api_key_payload`.
+const FILTER_ENFORCED_ROUTES: [&str; 6] = [
+    "/api/chunk/autocomplete",
+    "/api/chunk/search",
+    "/api/chunk_group/group_oriented_search",
+    "/api/chunk_group/search",
+    "/api/message",
+    "/api/chunks/scroll",
+];
+
+/// ANDs the required filters with the request's filters by adding their `must` and `must_not` conditions.
+pub fn with_required_filters(
+    filters: Option<ChunkFilter>,
+    required_filters: Option<ChunkFilter>,
+) -> Option<ChunkFilter> {
+    let required_filters = match required_filters {
+        Some(required_filters) => required_filters,
+        None => return filters,
+    };
+
+    let mut filters = filters.unwrap_or(ChunkFilter {
+        should: None,
+        must: None,
+        must_not: None,
+    });
+    if let Some(must) = required_filters.must {
+        filters.must.get_or_insert_with(Vec::new).extend(must);
+    }
+    if let Some(must_not) = required_filters.must_not {
+        filters
+            .must_not
+            .get_or_insert_with(Vec::new)
+            .extend(must_not);
+    }
+
+    Some(filters)
 }
 
 impl ApiKeyRequestParams {
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if let Some(rate_limits) = self.rate_limits.as_ref() {
+            rate_limits.validate()?;
+        }
+
+        if self.required_filters.as_ref().is_some_and(|filters| {
+            filters
+                .should
+                .as_ref()
+                .is_some_and(|should| !should.is_empty())
+        }) {
+            return Err(ServiceError::BadRequest(
+                "required_filters only supports must and must_not conditions".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a request to the given route is within the operations and filters the api key is restricted to.
+    pub fn check_route(
+        &self,
+        method: &actix_web::http::Method,
+        path: &str,
+    ) -> Result<(), ServiceError> {
+        if let Some(operations) = self.operations.as_ref() {
+            let operation = ApiOperation::scope_from_request(method, path);
+            if !operation.is_some_and(|operation| operations.contains(&operation)) {
+                return Err(ServiceError::Forbidden);
+            }
+        }
+
+        if self.required_filters.is_some()
+            && (*method != actix_web::http::Method::POST
+                || !FILTER_ENFORCED_ROUTES.contains(&path.trim_end_matches('/')))
+        {
+            return Err(ServiceError::Forbidden);
+        }
+
+        Ok(())
+    }
+
     pub fn combine_with_create_message(
         self,
         payload: CreateMessageReqPayload,
@@ -4073,7 +6425,7 @@ impl ApiKeyRequestParams {
         CreateMessageReqPayload {
             new_message_content: payload.new_message_content,
             topic_id: payload.topic_id,
-            user_id: payload.user_id,
+            user_id: self.user_id.or(payload.user_id),
             sort_options: payload.sort_options,
             highlight_options: self.highlight_options.or(payload.highlight_options),
             search_type: self.search_type.or(payload.search_type),
@@ -4081,7 +6433,7 @@ impl ApiKeyRequestParams {
             concat_user_messages_query: payload.concat_user_messages_query,
             search_query: payload.search_query,
             page_size: self.page_size.or(payload.page_size),
-            filters: self.filters.or(payload.filters),
+            filters: with_required_filters(self.filters.or(payload.filters), self.required_filters),
             score_threshold: self.score_threshold.or(payload.score_threshold),
             llm_options: payload.llm_options,
             image_urls: payload.image_urls,
@@ -4089,6 +6441,10 @@ impl ApiKeyRequestParams {
             context_options: payload.context_options,
             no_result_message: self.no_result_message.or(payload.no_result_message),
             only_include_docs_used: payload.only_include_docs_used,
+            rag_preset: payload.rag_preset,
+            suggest_follow_up_questions: payload.suggest_follow_up_questions,
+            stream_follow_up_questions: payload.stream_follow_up_questions,
+            query_rewrite: payload.query_rewrite,
         }
     }
 
@@ -4102,7 +6458,7 @@ impl ApiKeyRequestParams {
             page: payload.page,
             page_size: self.page_size.or(payload.page_size),
             get_total_pages: payload.get_total_pages,
-            filters: self.filters.or(payload.filters),
+            filters: with_required_filters(self.filters.or(payload.filters), self.required_filters),
             sort_options: payload.sort_options,
             scoring_options: payload.scoring_options,
             highlight_options: self.highlight_options.or(payload.highlight_options),
@@ -4113,7 +6469,7 @@ impl ApiKeyRequestParams {
                 .use_quote_negated_terms
                 .or(payload.use_quote_negated_terms),
             remove_stop_words: self.remove_stop_words.or(payload.remove_stop_words),
-            user_id: payload.user_id,
+            user_id: self.user_id.or(payload.user_id),
             typo_options: self.typo_options.or(payload.typo_options),
         }
     }
@@ -4124,7 +6480,7 @@ impl ApiKeyRequestParams {
     ) -> ScrollChunksReqPayload {
         ScrollChunksReqPayload {
             page_size: self.page_size.or(payload.page_size),
-            filters: self.filters.or(payload.filters),
+            filters: with_required_filters(self.filters.or(payload.filters), self.required_filters),
             offset_chunk_id: payload.offset_chunk_id,
             sort_by: payload.sort_by,
         }
@@ -4139,7 +6495,7 @@ impl ApiKeyRequestParams {
             extend_results: payload.extend_results,
             query: payload.query,
             page_size: self.page_size.or(payload.page_size),
-            filters: self.filters.or(payload.filters),
+            filters: with_required_filters(self.filters.or(payload.filters), self.required_filters),
             sort_options: payload.sort_options,
             scoring_options: payload.scoring_options,
             highlight_options: self.highlight_options.or(payload.highlight_options),
@@ -4150,7 +6506,7 @@ impl ApiKeyRequestParams {
                 .use_quote_negated_terms
                 .or(payload.use_quote_negated_terms),
             remove_stop_words: self.remove_stop_words.or(payload.remove_stop_words),
-            user_id: payload.user_id,
+            user_id: self.user_id.or(payload.user_id),
             typo_options: self.typo_options.or(payload.typo_options),
         }
     }
@@ -4165,7 +6521,7 @@ impl ApiKeyRequestParams {
             page: payload.page,
             page_size: self.page_size.or(payload.page_size),
             get_total_pages: payload.get_total_pages,
-            filters: self.filters.or(payload.filters),
+            filters: with_required_filters(self.filters.or(payload.filters), self.required_filters),
             highlight_options: self.highlight_options.or(payload.highlight_options),
             score_threshold: self.score_threshold.or(payload.score_threshold),
             group_size: payload.group_size,
@@ -4174,7 +6530,7 @@ impl ApiKeyRequestParams {
                 .use_quote_negated_terms
                 .or(payload.use_quote_negated_terms),
             remove_stop_words: self.remove_stop_words.or(payload.remove_stop_words),
-            user_id: payload.user_id,
+            user_id: self.user_id.or(payload.user_id),
             typo_options: self.typo_options.or(payload.typo_options),
             sort_options: payload.sort_options,
         }
@@ -4189,7 +6545,7 @@ impl ApiKeyRequestParams {
             page: payload.page,
             page_size: self.page_size.or(payload.page_size),
             get_total_pages: payload.get_total_pages,
-            filters: self.filters.or(payload.filters),
+            filters: with_required_filters(self.filters.or(payload.filters), self.required_filters),
             group_id: payload.group_id,
             group_tracking_id: payload.group_tracking_id,
             search_type: self.search_type.unwrap_or(payload.search_type),
@@ -4202,7 +6558,7 @@ impl ApiKeyRequestParams {
                 .use_quote_negated_terms
                 .or(payload.use_quote_negated_terms),
             remove_stop_words: self.remove_stop_words.or(payload.remove_stop_words),
-            user_id: payload.user_id,
+            user_id: self.user_id.or(payload.user_id),
             typo_options: self.typo_options.or(payload.typo_options),
         }
     }
@@ -4457,6 +6813,10 @@ pub struct QdrantPayload {
     pub content: String,
     pub group_ids: Option<Vec<uuid::Uuid>>,
     pub group_tag_set: Option<Vec<Option<String>>>,
+    pub acl: Vec<String>,
+    /// Set while the chunk is hidden by a group or file in the trash, so searches and reads can skip it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
 }
 
 impl From<QdrantPayload> for Payload {
@@ -4489,6 +6849,8 @@ impl QdrantPayload {
             content: convert_html_to_text(&chunk_metadata.chunk_html.unwrap_or_default()),
             group_ids,
             group_tag_set,
+            acl: get_qdrant_acl(chunk_metadata.acl),
+            hidden: false,
         }
     }
 
@@ -4562,6 +6924,18 @@ impl QdrantPayload {
                     .map(|value| Some(value.to_string()))
                     .collect()
             }),
+            acl: get_qdrant_acl(point.payload.get("acl").cloned().map(|x| {
+                x.as_list()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|value| value.as_str().map(|value| value.to_string()))
+                    .collect()
+            })),
+            hidden: point
+                .payload
+                .get("hidden")
+                .and_then(|x| x.as_bool())
+                .unwrap_or_default(),
         }
     }
 }
@@ -4649,6 +7023,18 @@ impl From<RetrievedPoint> for QdrantPayload {
                     .map(|value| Some(value.to_string().replace(['"', '\\'], "")))
                     .collect()
             }),
+            acl: get_qdrant_acl(point.payload.get("acl").cloned().map(|x| {
+                x.as_list()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|value| value.as_str().map(|value| value.to_string()))
+                    .collect()
+            })),
+            hidden: point
+                .payload
+                .get("hidden")
+                .and_then(|x| x.as_bool())
+                .unwrap_or_default(),
         }
     }
 }
@@ -4659,6 +7045,11 @@ pub struct FileWorkerMessage {
     pub dataset_id: uuid::Uuid,
     pub upload_file_data: UploadFileReqPayload,
     pub attempt_number: u8,
+    #[serde(default)]
+    pub ingestion_job_id: Option<uuid::Uuid>,
+    /// Api key which uploaded the file, so its processing is metered against it.
+    #[serde(default)]
+    pub api_key_id: Option<uuid::Uuid>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -4668,6 +7059,8 @@ pub struct CsvJsonlWorkerMessage {
     pub create_presigned_put_url_data: CreatePresignedUrlForCsvJsonlReqPayload,
     pub created_at: chrono::NaiveDateTime,
     pub attempt_number: u8,
+    #[serde(default)]
+    pub ingestion_job_id: Option<uuid::Uuid>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -4902,6 +7295,16 @@ impl FieldCondition {
             ));
         }
 
+        if self.field == "acl" {
+            return Ok(Some(get_acl_filter_condition(
+                self.match_any
+                    .iter()
+                    .flatten()
+                    .map(|principal| principal.to_string())
+                    .collect(),
+            )));
+        }
+
         if let Some(date_range) = self.date_range.clone() {
             let time_range = get_date_range(date_range)?;
             return Ok(Some(qdrant::Condition::range(
@@ -5361,6 +7764,8 @@ pub struct SearchQueryEventClickhouse {
     pub created_at: OffsetDateTime,
     pub query_rating: String,
     pub user_id: String,
+    pub experiment_id: String,
+    pub experiment_variant: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -5464,6 +7869,9 @@ pub struct RagQueryEvent {
     pub query_rating: Option<SearchQueryRating>,
     pub hallucination_score: f64,
     pub detected_hallucinations: Vec<String>,
+    pub guardrail_actions: Vec<String>,
+    pub guardrail_violations: Vec<String>,
+    pub rewritten_queries: Vec<String>,
     pub created_at: String,
     pub user_id: String,
 }
@@ -5523,6 +7931,9 @@ impl RagQueryEventClickhouse {
             llm_response: self.llm_response,
             hallucination_score: self.hallucination_score,
             detected_hallucinations: self.detected_hallucinations,
+            guardrail_actions: self.guardrail_actions,
+            guardrail_violations: self.guardrail_violations,
+            rewritten_queries: self.rewritten_queries,
             created_at: self.created_at.to_string(),
             user_id: self.user_id,
         }
@@ -5549,6 +7960,11 @@ pub struct RagQueryEventClickhouse {
     pub user_id: String,
     pub hallucination_score: f64,
     pub detected_hallucinations: Vec<String>,
+    pub guardrail_actions: Vec<String>,
+    pub guardrail_violations: Vec<String>,
+    pub rewritten_queries: Vec<String>,
+    pub experiment_id: String,
+    pub experiment_variant: String,
 }
 
 #[derive(Debug, Row, Serialize, Deserialize, ToSchema)]
@@ -5577,6 +7993,17 @@ impl From<ClusterTopicsClickhouse> for SearchClusterTopics {
     }
 }
 
+#[derive(Debug, Row, Serialize, Deserialize)]
+pub struct SearchClusterMembershipClickhouse {
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub id: uuid::Uuid,
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub search_id: uuid::Uuid,
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub cluster_id: uuid::Uuid,
+    pub distance_to_centroid: f32,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema, Row, Clone)]
 pub struct RecommendationEventClickhouse {
     #[serde(with = "clickhouse::serde::uuid")]
@@ -6142,7 +8569,7 @@ pub struct EventData {
     pub updated_at: String,
 }
 
-#[derive(Debug, ToSchema, Serialize, Deserialize, Row)]
+#[derive(Debug, ToSchema, Serialize, Deserialize, Row, Clone)]
 #[schema(example = json!({
     "event_type": "view",
     "event_name": "Viewed Home Page",
@@ -6175,6 +8602,283 @@ pub struct EventDataClickhouse {
     pub updated_at: OffsetDateTime,
 }
 
+fn offset_to_naive_date_time(date_time: OffsetDateTime) -> NaiveDateTime {
+    chrono::DateTime::from_timestamp(date_time.unix_timestamp(), 0)
+        .map(|date_time| date_time.naive_utc())
+        .unwrap_or_default()
+}
+
+#[derive(
+    Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, QueryableByName, Clone,
+)]
+#[diesel(table_name = analytics_search_queries)]
+pub struct SearchQueryEventPostgres {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub search_type: String,
+    pub search_method: Option<String>,
+    pub query: String,
+    pub request_params: serde_json::Value,
+    pub latency: f32,
+    pub top_score: f32,
+    pub results: serde_json::Value,
+    pub user_id: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<SearchQueryEventClickhouse> for SearchQueryEventPostgres {
+    fn from(event: SearchQueryEventClickhouse) -> Self {
+        let request_params: serde_json::Value =
+            serde_json::from_str(&event.request_params).unwrap_or_default();
+
+        SearchQueryEventPostgres {
+            id: event.id,
+            dataset_id: event.dataset_id,
+            search_type: event.search_type,
+            search_method: request_params
+                .get("search_type")
+                .and_then(|search_method| search_method.as_str())
+                .map(|search_method| search_method.to_string()),
+            query: event.query,
+            request_params,
+            latency: event.latency,
+            top_score: event.top_score,
+            results: serde_json::Value::Array(
+                event
+                    .results
+                    .iter()
+                    .map(|result| serde_json::from_str(result).unwrap_or_default())
+                    .collect(),
+            ),
+            user_id: event.user_id,
+            created_at: offset_to_naive_date_time(event.created_at),
+        }
+    }
+}
+
+impl From<SearchQueryEventPostgres> for SearchQueryEvent {
+    fn from(event: SearchQueryEventPostgres) -> Self {
+        SearchQueryEvent {
+            id: event.id,
+            search_type: event.search_type.into(),
+            query: event.query,
+            request_params: event.request_params,
+            latency: event.latency,
+            top_score: event.top_score,
+            results: match event.results {
+                serde_json::Value::Array(results) => results,
+                _ => vec![],
+            },
+            dataset_id: event.dataset_id,
+            created_at: event.created_at.to_string(),
+            query_rating: None,
+            user_id: event.user_id,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = analytics_rag_queries)]
+pub struct RagQueryEventPostgres {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub rag_type: String,
+    pub search_id: uuid::Uuid,
+    pub user_message: String,
+    pub llm_response: String,
+    pub top_score: f32,
+    pub user_id: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<RagQueryEventClickhouse> for RagQueryEventPostgres {
+    fn from(event: RagQueryEventClickhouse) -> Self {
+        RagQueryEventPostgres {
+            id: event.id,
+            dataset_id: event.dataset_id,
+            rag_type: event.rag_type,
+            search_id: event.search_id,
+            user_message: event.user_message,
+            llm_response: event.llm_response,
+            top_score: event.top_score,
+            user_id: event.user_id,
+            created_at: offset_to_naive_date_time(event.created_at),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = analytics_events)]
+pub struct EventDataPostgres {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub event_type: String,
+    pub event_name: String,
+    pub request_id: String,
+    pub request_type: String,
+    pub items: Vec<String>,
+    pub metadata: serde_json::Value,
+    pub user_id: String,
+    pub is_conversion: bool,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<EventDataClickhouse> for EventDataPostgres {
+    fn from(event: EventDataClickhouse) -> Self {
+        EventDataPostgres {
+            id: event.id,
+            dataset_id: event.dataset_id,
+            event_type: event.event_type,
+            event_name: event.event_name,
+            request_id: event.request_id,
+            request_type: event.request_type,
+            items: event.items,
+            metadata: serde_json::from_str(&event.metadata).unwrap_or_default(),
+            user_id: event.user_id,
+            is_conversion: event.is_conversion,
+            created_at: offset_to_naive_date_time(event.created_at),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = analytics_recommendations)]
+pub struct RecommendationEventPostgres {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub recommendation_type: String,
+    pub positive_ids: Vec<String>,
+    pub negative_ids: Vec<String>,
+    pub positive_tracking_ids: Vec<String>,
+    pub negative_tracking_ids: Vec<String>,
+    pub request_params: serde_json::Value,
+    pub results: serde_json::Value,
+    pub top_score: f32,
+    pub user_id: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<RecommendationEventClickhouse> for RecommendationEventPostgres {
+    fn from(event: RecommendationEventClickhouse) -> Self {
+        RecommendationEventPostgres {
+            id: event.id,
+            dataset_id: event.dataset_id,
+            recommendation_type: event.recommendation_type,
+            positive_ids: event.positive_ids,
+            negative_ids: event.negative_ids,
+            positive_tracking_ids: event.positive_tracking_ids,
+            negative_tracking_ids: event.negative_tracking_ids,
+            request_params: serde_json::from_str(&event.request_params).unwrap_or_default(),
+            results: serde_json::Value::Array(
+                event
+                    .results
+                    .iter()
+                    .map(|result| serde_json::from_str(result).unwrap_or_default())
+                    .collect(),
+            ),
+            top_score: event.top_score,
+            user_id: event.user_id,
+            created_at: offset_to_naive_date_time(event.created_at),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = analytics_worker_events)]
+pub struct WorkerEventPostgres {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub event_type: String,
+    pub event_data: String,
+    pub ingestion_job_id: Option<uuid::Uuid>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<WorkerEventClickhouse> for WorkerEventPostgres {
+    fn from(event: WorkerEventClickhouse) -> Self {
+        WorkerEventPostgres {
+            id: event.id,
+            dataset_id: event.dataset_id,
+            event_type: event.event_type,
+            event_data: event.event_data,
+            ingestion_job_id: Some(event.ingestion_job_id)
+                .filter(|ingestion_job_id| !ingestion_job_id.is_nil()),
+            created_at: offset_to_naive_date_time(event.created_at),
+        }
+    }
+}
+
+impl From<WorkerEventPostgres> for WorkerEvent {
+    fn from(event: WorkerEventPostgres) -> Self {
+        WorkerEvent {
+            id: event.id,
+            created_at: event.created_at.to_string(),
+            dataset_id: event.dataset_id,
+            event_type: event.event_type,
+            event_data: event.event_data,
+            ingestion_job_id: event.ingestion_job_id,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = analytics_audit_logs)]
+pub struct AuditLogPostgres {
+    pub id: uuid::Uuid,
+    pub organization_id: uuid::Uuid,
+    pub dataset_id: Option<uuid::Uuid>,
+    pub actor_id: uuid::Uuid,
+    pub actor_email: String,
+    pub api_key_id: Option<uuid::Uuid>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+    pub source_ip: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<AuditLogClickhouse> for AuditLogPostgres {
+    fn from(audit_log: AuditLogClickhouse) -> Self {
+        AuditLogPostgres {
+            id: audit_log.id,
+            organization_id: audit_log.organization_id,
+            dataset_id: Some(audit_log.dataset_id).filter(|dataset_id| !dataset_id.is_nil()),
+            actor_id: audit_log.actor_id,
+            actor_email: audit_log.actor_email,
+            api_key_id: Some(audit_log.api_key_id).filter(|api_key_id| !api_key_id.is_nil()),
+            action: audit_log.action,
+            target_type: audit_log.target_type,
+            target_id: audit_log.target_id,
+            before: serde_json::from_str(&audit_log.before).unwrap_or_default(),
+            after: serde_json::from_str(&audit_log.after).unwrap_or_default(),
+            source_ip: audit_log.source_ip,
+            created_at: offset_to_naive_date_time(audit_log.created_at),
+        }
+    }
+}
+
+impl From<AuditLogPostgres> for AuditLog {
+    fn from(audit_log: AuditLogPostgres) -> Self {
+        AuditLog {
+            id: audit_log.id,
+            organization_id: audit_log.organization_id,
+            dataset_id: audit_log.dataset_id,
+            actor_id: audit_log.actor_id,
+            actor_email: audit_log.actor_email,
+            api_key_id: audit_log.api_key_id,
+            action: audit_log.action,
+            target_type: audit_log.target_type,
+            target_id: audit_log.target_id,
+            before: audit_log.before,
+            after: audit_log.after,
+            source_ip: audit_log.source_ip,
+            created_at: audit_log.created_at.to_string(),
+        }
+    }
+}
+
 pub enum EventDataTypes {
     EventDataClickhouse(EventDataClickhouse),
     SearchQueryEventClickhouse(SearchQueryEventClickhouse),
@@ -6319,6 +9023,8 @@ impl EventTypes {
                 created_at: OffsetDateTime::now_utc(),
                 query_rating: serde_json::to_string(&query_rating).unwrap_or("".to_string()),
                 user_id: user_id.unwrap_or_default(),
+                experiment_id: String::new(),
+                experiment_variant: String::new(),
             }),
             EventTypes::RAG {
                 rag_type,
@@ -6351,6 +9057,11 @@ impl EventTypes {
                 user_id: user_id.unwrap_or_default(),
                 hallucination_score: hallucination_score.unwrap_or(0.0),
                 detected_hallucinations: detected_hallucinations.unwrap_or_default(),
+                guardrail_actions: vec![],
+                guardrail_violations: vec![],
+                rewritten_queries: vec![],
+                experiment_id: String::new(),
+                experiment_variant: String::new(),
             }),
             EventTypes::Recommendation {
                 recommendation_type,
@@ -7044,6 +9755,8 @@ pub enum EventTypeRequest {
     CsvJsonlProcessingCheckpoint,
     #[display(fmt = "csv_jsonl_processing_completed")]
     CsvJsonlProcessingCompleted,
+    #[display(fmt = "csv_jsonl_row_errors")]
+    CsvJsonlRowErrors,
     #[display(fmt = "video_uploaded")]
     VideoUploaded,
     #[display(fmt = "pagefind_indexing_started")]
@@ -7056,6 +9769,8 @@ pub enum EventTypeRequest {
     EtlCompleted,
     #[display(fmt = "etl_failed")]
     EtlFailed,
+    #[display(fmt = "dataset_cleared")]
+    DatasetCleared,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -7320,6 +10035,10 @@ pub struct SortOptions {
     pub tag_weights: Option<HashMap<String, f32>>,
     /// Set use_mmr to true to use the Maximal Marginal Relevance algorithm to rerank the results. If not specified, this defaults to false.
     pub mmr: Option<MmrOptions>,
+    /// Overrides the ENGAGEMENT_RANKING weight of the dataset for this request. Set to 0.0 to disable engagement boosting. Has no effect unless engagement ranking is enabled for the dataset.
+    pub engagement_weight: Option<f32>,
+    /// Set explain to true to return how each chunk's score was adjusted by engagement boosting. If not specified, this defaults to false.
+    pub explain: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema, Default)]
@@ -7406,12 +10125,23 @@ impl Default for ContextOptions {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, Default)]
+/// Query rewriting options for the retrieval step of RAG. These only apply when search_query is not specified.
+pub struct QueryRewriteOptions {
+    /// If set to true, the latest user message is rewritten into a self-contained question using the topic history before searching. This helps with follow-ups like "what about the blue one?". Default is false.
+    pub standalone_question: Option<bool>,
+    /// Number of alternative phrasings of the query to search with in addition to the original. Results of all queries are merged with reciprocal rank fusion. Capped at 5. Default is 0.
+    pub multi_query_count: Option<u32>,
+    /// Custom instructions for the standalone question rewrite. If not specified, a default prompt is used.
+    pub rewrite_prompt: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Default)]
 /// LLM options to use for the completion. If not specified, this defaults to the dataset's LLM options.
 pub struct LLMOptions {
     /// Completion first decides whether the stream should contain the stream of the completion response or the chunks first. Default is false. Keep in mind that || is used to separate the chunks from the completion response. If || is in the completion then you may want to split on ||{ instead.
     pub completion_first: Option<bool>,
-    /// Whether or not to stream the response. If this is set to true or not included, the response will be a stream. If this is set to false, the response will be a normal JSON response. Default is true.
+    /// Whether or not to stream the response. If this is set to true or not included, the response will be a stream. If this is set to false, the response will be a normal JSON response. Default is true. When the dataset has guardrails enabled, the stream is fully buffered and sent as a single chunk once the output guardrail has run.
     pub stream_response: Option<bool>,
     /// What sampling temperature to use, between 0 and 2. Higher values like 0.8 will make the output more random, while lower values like 0.2 will make it more focused and deterministic. Default is 0.5.
     pub temperature: Option<f32>,
@@ -7818,6 +10548,10 @@ impl<'de> Deserialize<'de> for CreateMessageReqPayload {
             pub context_options: Option<ContextOptions>,
             pub no_result_message: Option<String>,
             pub only_include_docs_used: Option<bool>,
+            pub rag_preset: Option<String>,
+            pub suggest_follow_up_questions: Option<bool>,
+            pub stream_follow_up_questions: Option<bool>,
+            pub query_rewrite: Option<QueryRewriteOptions>,
             #[serde(flatten)]
             other: std::collections::HashMap<String, serde_json::Value>,
         }
@@ -7854,6 +10588,10 @@ impl<'de> Deserialize<'de> for CreateMessageReqPayload {
             context_options,
             no_result_message: helper.no_result_message,
             only_include_docs_used: helper.only_include_docs_used,
+            rag_preset: helper.rag_preset,
+            suggest_follow_up_questions: helper.suggest_follow_up_questions,
+            stream_follow_up_questions: helper.stream_follow_up_questions,
+            query_rewrite: helper.query_rewrite,
         })
     }
 }
@@ -7880,6 +10618,10 @@ impl<'de> Deserialize<'de> for RegenerateMessageReqPayload {
             pub context_options: Option<ContextOptions>,
             pub no_result_message: Option<String>,
             pub only_include_docs_used: Option<bool>,
+            pub rag_preset: Option<String>,
+            pub suggest_follow_up_questions: Option<bool>,
+            pub stream_follow_up_questions: Option<bool>,
+            pub query_rewrite: Option<QueryRewriteOptions>,
             #[serde(flatten)]
             other: std::collections::HashMap<String, serde_json::Value>,
         }
@@ -7913,6 +10655,10 @@ impl<'de> Deserialize<'de> for RegenerateMessageReqPayload {
             context_options,
             no_result_message: helper.no_result_message,
             only_include_docs_used: helper.only_include_docs_used,
+            rag_preset: helper.rag_preset,
+            suggest_follow_up_questions: helper.suggest_follow_up_questions,
+            stream_follow_up_questions: helper.stream_follow_up_questions,
+            query_rewrite: helper.query_rewrite,
         })
     }
 }
@@ -7943,6 +10689,10 @@ impl<'de> Deserialize<'de> for EditMessageReqPayload {
             pub context_options: Option<ContextOptions>,
             pub no_result_message: Option<String>,
             pub only_include_docs_used: Option<bool>,
+            pub rag_preset: Option<String>,
+            pub suggest_follow_up_questions: Option<bool>,
+            pub stream_follow_up_questions: Option<bool>,
+            pub query_rewrite: Option<QueryRewriteOptions>,
             #[serde(flatten)]
             other: std::collections::HashMap<String, serde_json::Value>,
         }
@@ -7980,6 +10730,10 @@ impl<'de> Deserialize<'de> for EditMessageReqPayload {
             context_options,
             no_result_message: helper.no_result_message,
             only_include_docs_used: helper.only_include_docs_used,
+            rag_preset: helper.rag_preset,
+            suggest_follow_up_questions: helper.suggest_follow_up_questions,
+            stream_follow_up_questions: helper.stream_follow_up_questions,
+            query_rewrite: helper.query_rewrite,
         })
     }
 }
@@ -8403,3 +11157,297 @@ pub struct DummyHallucinationScore {
     pub total_score: f64,
     pub detected_hallucinations: Vec<String>,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::http::Method;
+
+    fn chunk_filter(value: serde_json::Value) -> ChunkFilter {
+        serde_json::from_value(value).expect("Valid chunk filter")
+    }
+
+    fn api_key_params(value: serde_json::Value) -> ApiKeyRequestParams {
+        serde_json::from_value(value).expect("Valid api key params")
+    }
+
+    /// Conditions as `field=values` strings so the merged filters are easy to compare.
+    fn conditions(conditions: &Option<Vec<ConditionType>>) -> Vec<String> {
+        conditions
+            .iter()
+            .flatten()
+            .map(|condition| match condition {
+                ConditionType::Field(field) => format!(
+                    "{}={}",
+                    field.field,
+                    field
+                        .match_any
+                        .iter()
+                        .chain(field.match_all.iter())
+                        .flatten()
+                        .map(|value| value.to_string())
+                        .join(",")
+                ),
+                ConditionType::HasChunkId(_) => "has_chunk_id".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn test_api_operation_from_request() {
+        assert_eq!(
+            ApiOperation::from_request(&Method::POST, "/api/chunk/search"),
+            Some(ApiOperation::Search)
+        );
+        assert_eq!(
+            ApiOperation::from_request(&Method::POST, "/api/chunk_group/recommend/"),
+            Some(ApiOperation::Search)
+        );
+        assert_eq!(
+            ApiOperation::from_request(&Method::PATCH, "/api/message"),
+            Some(ApiOperation::Rag)
+        );
+        assert_eq!(
+            ApiOperation::from_request(&Method::POST, "/api/topic"),
+            Some(ApiOperation::Rag)
+        );
+        assert_eq!(
+            ApiOperation::from_request(&Method::PUT, "/api/chunk"),
+            Some(ApiOperation::Ingest)
+        );
+        assert_eq!(
+            ApiOperation::from_request(&Method::POST, "/api/file/csv_or_jsonl"),
+            Some(ApiOperation::Ingest)
+        );
+
+        assert_eq!(
+            ApiOperation::from_request(&Method::GET, "/api/chunk/search"),
+            None
+        );
+        assert_eq!(
+            ApiOperation::from_request(&Method::GET, "/api/message"),
+            None
+        );
+        assert_eq!(
+            ApiOperation::from_request(&Method::DELETE, "/api/chunk"),
+            None
+        );
+        assert_eq!(
+            ApiOperation::from_request(&Method::POST, "/api/dataset"),
+            None
+        );
+        assert_eq!(
+            ApiOperation::from_request(&Method::POST, "/api/chunk/search/extra"),
+            None
+        );
+    }
+
+    #[test]
+    pub fn test_api_operation_scope_from_request() {
+        assert_eq!(
+            ApiOperation::scope_from_request(&Method::POST, "/api/chunk/count"),
+            Some(ApiOperation::Search)
+        );
+        assert_eq!(
+            ApiOperation::scope_from_request(&Method::GET, "/api/messages/topic_id"),
+            Some(ApiOperation::Rag)
+        );
+        assert_eq!(
+            ApiOperation::scope_from_request(&Method::GET, "/api/message/message_id"),
+            Some(ApiOperation::Rag)
+        );
+        assert_eq!(
+            ApiOperation::scope_from_request(&Method::GET, "/api/topic/owner/user_id"),
+            Some(ApiOperation::Rag)
+        );
+
+        assert_eq!(
+            ApiOperation::scope_from_request(&Method::DELETE, "/api/message/message_id"),
+            None
+        );
+        assert_eq!(
+            ApiOperation::scope_from_request(&Method::GET, "/api/topic/topic_id"),
+            None
+        );
+        assert_eq!(
+            ApiOperation::scope_from_request(&Method::GET, "/api/chunk/chunk_id"),
+            None
+        );
+    }
+
+    #[test]
+    pub fn test_check_route_operations() {
+        let params = api_key_params(serde_json::json!({ "operations": ["search", "rag"] }));
+
+        assert!(params
+            .check_route(&Method::POST, "/api/chunk/search")
+            .is_ok());
+        assert!(params.check_route(&Method::POST, "/api/message").is_ok());
+        assert!(params
+            .check_route(&Method::GET, "/api/messages/topic_id")
+            .is_ok());
+
+        assert!(matches!(
+            params.check_route(&Method::POST, "/api/chunk"),
+            Err(ServiceError::Forbidden)
+        ));
+        assert!(matches!(
+            params.check_route(&Method::DELETE, "/api/dataset/dataset_id"),
+            Err(ServiceError::Forbidden)
+        ));
+        assert!(matches!(
+            params.check_route(&Method::GET, "/api/unknown"),
+            Err(ServiceError::Forbidden)
+        ));
+
+        let unrestricted = api_key_params(serde_json::json!({}));
+        assert!(unrestricted
+            .check_route(&Method::POST, "/api/chunk")
+            .is_ok());
+        assert!(unrestricted
+            .check_route(&Method::GET, "/api/unknown")
+            .is_ok());
+    }
+
+    #[test]
+    pub fn test_check_route_required_filters() {
+        let params = api_key_params(serde_json::json!({
+            "required_filters": {
+                "must": [{ "field": "tag_set", "match_any": ["tenant-a"] }]
+            }
+        }));
+
+        for route in FILTER_ENFORCED_ROUTES {
+            assert!(
+                params.check_route(&Method::POST, route).is_ok(),
+                "{}",
+                route
+            );
+            assert!(
+                params
+                    .check_route(&Method::POST, &format!("{}/", route))
+                    .is_ok(),
+                "{}",
+                route
+            );
+            assert!(
+                matches!(
+                    params.check_route(&Method::GET, route),
+                    Err(ServiceError::Forbidden)
+                ),
+                "{}",
+                route
+            );
+        }
+
+        // Routes which do not take filters could read chunks without them
+        assert!(matches!(
+            params.check_route(&Method::POST, "/api/chunk/recommend"),
+            Err(ServiceError::Forbidden)
+        ));
+        assert!(matches!(
+            params.check_route(&Method::GET, "/api/chunk/chunk_id"),
+            Err(ServiceError::Forbidden)
+        ));
+        assert!(matches!(
+            params.check_route(&Method::POST, "/api/unknown"),
+            Err(ServiceError::Forbidden)
+        ));
+    }
+
+    #[test]
+    pub fn test_with_required_filters() {
+        let required_filters = chunk_filter(serde_json::json!({
+            "must": [{ "field": "tag_set", "match_any": ["tenant-a"] }],
+            "must_not": [{ "field": "tag_set", "match_any": ["internal"] }]
+        }));
+
+        assert!(with_required_filters(None, None).is_none());
+
+        let filters = with_required_filters(None, Some(required_filters.clone()))
+            .expect("Required filters are added");
+        assert!(filters.should.is_none());
+        assert_eq!(conditions(&filters.must), vec!["tag_set=tenant-a"]);
+        assert_eq!(conditions(&filters.must_not), vec!["tag_set=internal"]);
+
+        let filters = with_required_filters(
+            Some(chunk_filter(serde_json::json!({
+                "should": [{ "field": "link", "match_any": ["a.com", "b.com"] }],
+                "must": [{ "field": "metadata.lang", "match_any": ["en"] }],
+                "must_not": [{ "field": "tag_set", "match_any": ["draft"] }]
+            }))),
+            Some(required_filters.clone()),
+        )
+        .expect("Required filters are added");
+        assert_eq!(conditions(&filters.should), vec!["link=a.com,b.com"]);
+        assert_eq!(
+            conditions(&filters.must),
+            vec!["metadata.lang=en", "tag_set=tenant-a"]
+        );
+        assert_eq!(
+            conditions(&filters.must_not),
+            vec!["tag_set=draft", "tag_set=internal"]
+        );
+    }
+
+    #[test]
+    pub fn test_with_required_filters_cannot_be_overridden() {
+        let params = api_key_params(serde_json::json!({
+            "required_filters": {
+                "must": [{ "field": "tag_set", "match_any": ["tenant-a"] }]
+            }
+        }));
+
+        // Asking for another tenant's chunks or excluding the required tenant only narrows the results
+        let payload = params
+            .clone()
+            .combine_with_scroll_chunks(ScrollChunksReqPayload {
+                page_size: None,
+                offset_chunk_id: None,
+                filters: Some(chunk_filter(serde_json::json!({
+                    "should": [{ "field": "tag_set", "match_any": ["tenant-b"] }],
+                    "must": [{ "field": "tag_set", "match_any": ["tenant-b"] }],
+                    "must_not": [{ "field": "tag_set", "match_any": ["tenant-a"] }]
+                }))),
+                sort_by: None,
+            });
+        let filters = payload.filters.expect("Required filters are kept");
+        assert_eq!(
+            conditions(&filters.must),
+            vec!["tag_set=tenant-b", "tag_set=tenant-a"]
+        );
+        assert_eq!(conditions(&filters.must_not), vec!["tag_set=tenant-a"]);
+
+        let payload = params
+            .clone()
+            .combine_with_scroll_chunks(ScrollChunksReqPayload {
+                page_size: None,
+                offset_chunk_id: None,
+                filters: None,
+                sort_by: None,
+            });
+        let filters = payload.filters.expect("Required filters are kept");
+        assert_eq!(conditions(&filters.must), vec!["tag_set=tenant-a"]);
+
+        // The key's default filters replace the request's, but never its required filters
+        let params = ApiKeyRequestParams {
+            filters: Some(chunk_filter(serde_json::json!({
+                "must": [{ "field": "metadata.lang", "match_any": ["en"] }]
+            }))),
+            ..params
+        };
+        let payload = params.combine_with_scroll_chunks(ScrollChunksReqPayload {
+            page_size: None,
+            offset_chunk_id: None,
+            filters: Some(chunk_filter(serde_json::json!({
+                "must": [{ "field": "tag_set", "match_any": ["tenant-b"] }]
+            }))),
+            sort_by: None,
+        });
+        let filters = payload.filters.expect("Required filters are kept");
+        assert_eq!(
+            conditions(&filters.must),
+            vec!["metadata.lang=en", "tag_set=tenant-a"]
+        );
+    }
+}
@@ -1,5 +1,145 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    analytics_audit_logs (id) {
+        id -> Uuid,
+        organization_id -> Uuid,
+        dataset_id -> Nullable<Uuid>,
+        actor_id -> Uuid,
+        actor_email -> Text,
+        api_key_id -> Nullable<Uuid>,
+        action -> Text,
+        target_type -> Text,
+        target_id -> Text,
+        before -> Jsonb,
+        after -> Jsonb,
+        source_ip -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    analytics_events (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        event_type -> Text,
+        event_name -> Text,
+        request_id -> Text,
+        request_type -> Text,
+        items -> Array<Text>,
+        metadata -> Jsonb,
+        user_id -> Text,
+        is_conversion -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    analytics_export_configs (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        format -> Text,
+        event_types -> Array<Text>,
+        interval_minutes -> Int4,
+        forward_url -> Nullable<Text>,
+        forward_sink -> Text,
+        forward_auth_header -> Nullable<Text>,
+        active -> Bool,
+        last_exported_at -> Timestamp,
+        next_export_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    analytics_export_runs (id) {
+        id -> Uuid,
+        config_id -> Uuid,
+        dataset_id -> Uuid,
+        status -> Text,
+        window_start -> Timestamp,
+        window_end -> Timestamp,
+        files -> Array<Text>,
+        row_count -> Int8,
+        error -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    analytics_rag_queries (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        rag_type -> Text,
+        search_id -> Uuid,
+        user_message -> Text,
+        llm_response -> Text,
+        top_score -> Float4,
+        user_id -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    analytics_recommendations (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        recommendation_type -> Text,
+        positive_ids -> Array<Text>,
+        negative_ids -> Array<Text>,
+        positive_tracking_ids -> Array<Text>,
+        negative_tracking_ids -> Array<Text>,
+        request_params -> Jsonb,
+        results -> Jsonb,
+        top_score -> Float4,
+        user_id -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    analytics_report_subscriptions (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        dataset_id -> Uuid,
+        schedule -> Text,
+        active -> Bool,
+        last_sent_at -> Nullable<Timestamp>,
+        next_send_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    analytics_search_queries (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        search_type -> Text,
+        search_method -> Nullable<Text>,
+        query -> Text,
+        request_params -> Jsonb,
+        latency -> Float4,
+        top_score -> Float4,
+        results -> Jsonb,
+        user_id -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    analytics_worker_events (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        event_type -> Text,
+        event_data -> Text,
+        ingestion_job_id -> Nullable<Uuid>,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     chunk_boosts (chunk_id) {
         chunk_id -> Uuid,
@@ -21,6 +161,7 @@ diesel::table! {
         tracking_id -> Nullable<Text>,
         metadata -> Nullable<Jsonb>,
         tag_set -> Nullable<Array<Nullable<Text>>>,
+        acl -> Nullable<Array<Nullable<Text>>>,
     }
 }
 
@@ -50,6 +191,7 @@ diesel::table! {
         location -> Nullable<Jsonb>,
         image_urls -> Nullable<Array<Nullable<Text>>>,
         num_value -> Nullable<Float8>,
+        acl -> Nullable<Array<Nullable<Text>>>,
     }
 }
 
@@ -100,6 +242,25 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    dataset_usage (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        api_key_id -> Uuid,
+        usage_date -> Date,
+        search_calls -> Int8,
+        rag_calls -> Int8,
+        prompt_tokens -> Int8,
+        completion_tokens -> Int8,
+        embedding_tokens -> Int8,
+        file_pages -> Int8,
+        storage_bytes -> Int8,
+        stripe_reported_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     dataset_usage_counts (id) {
         id -> Uuid,
@@ -108,6 +269,36 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    dataset_webhook_deliveries (id) {
+        id -> Uuid,
+        webhook_id -> Uuid,
+        dataset_id -> Uuid,
+        event_type -> Text,
+        payload -> Jsonb,
+        status -> Text,
+        attempt_number -> Int4,
+        response_status -> Nullable<Int4>,
+        error -> Nullable<Text>,
+        next_attempt_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    dataset_webhooks (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        url -> Text,
+        secret -> Text,
+        event_types -> Array<Text>,
+        active -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     datasets (id) {
         id -> Uuid,
@@ -121,6 +312,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    experiments (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        name -> Text,
+        variants -> Jsonb,
+        active -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     files (id) {
         id -> Uuid,
@@ -146,6 +349,33 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    ingestion_job_batches (id) {
+        id -> Uuid,
+        ingestion_job_id -> Uuid,
+        chunk_count -> Int4,
+        status -> Text,
+        error -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    ingestion_jobs (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        source -> Text,
+        queuing_complete -> Bool,
+        webhook_url -> Nullable<Text>,
+        webhook_sent_at -> Nullable<Timestamp>,
+        webhook_attempts -> Int4,
+        webhook_next_attempt_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     invitations (id) {
         id -> Uuid,
@@ -173,6 +403,7 @@ diesel::table! {
         created_at -> Timestamp,
         updated_at -> Timestamp,
         dataset_id -> Uuid,
+        follow_up_questions -> Nullable<Array<Nullable<Text>>>,
     }
 }
 
@@ -213,6 +444,32 @@ diesel::table! {
         registerable -> Nullable<Bool>,
         deleted -> Int4,
         partner_configuration -> Jsonb,
+        trash_retention_days -> Int4,
+    }
+}
+
+diesel::table! {
+    query_suggestions (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        suggestion_type -> Text,
+        query -> Text,
+        suggestion -> Text,
+        occurrences -> Int8,
+        status -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    rag_presets (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        name -> Text,
+        options -> Jsonb,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -266,6 +523,22 @@ diesel::table! {
         updated_at -> Timestamp,
         dataset_id -> Uuid,
         owner_id -> Text,
+        rag_preset -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    trash (id) {
+        id -> Uuid,
+        organization_id -> Uuid,
+        dataset_id -> Uuid,
+        item_type -> Text,
+        item_id -> Uuid,
+        name -> Text,
+        tracking_id -> Nullable<Text>,
+        delete_chunks -> Bool,
+        deleted_at -> Timestamp,
+        purge_at -> Timestamp,
     }
 }
 
@@ -310,6 +583,10 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(analytics_export_configs -> datasets (dataset_id));
+diesel::joinable!(analytics_export_runs -> analytics_export_configs (config_id));
+diesel::joinable!(analytics_report_subscriptions -> datasets (dataset_id));
+diesel::joinable!(analytics_report_subscriptions -> users (user_id));
 diesel::joinable!(chunk_boosts -> chunk_metadata (chunk_id));
 diesel::joinable!(chunk_group -> datasets (dataset_id));
 diesel::joinable!(chunk_group_bookmarks -> chunk_group (group_id));
@@ -320,24 +597,43 @@ diesel::joinable!(chunk_metadata_tags -> dataset_tags (tag_id));
 diesel::joinable!(crawl_requests -> datasets (dataset_id));
 diesel::joinable!(dataset_event_counts -> datasets (dataset_uuid));
 diesel::joinable!(dataset_tags -> datasets (dataset_id));
+diesel::joinable!(dataset_usage -> datasets (dataset_id));
 diesel::joinable!(dataset_usage_counts -> datasets (dataset_id));
+diesel::joinable!(dataset_webhook_deliveries -> dataset_webhooks (webhook_id));
+diesel::joinable!(dataset_webhooks -> datasets (dataset_id));
 diesel::joinable!(datasets -> organizations (organization_id));
+diesel::joinable!(experiments -> datasets (dataset_id));
 diesel::joinable!(files -> datasets (dataset_id));
 diesel::joinable!(groups_from_files -> chunk_group (group_id));
 diesel::joinable!(groups_from_files -> files (file_id));
+diesel::joinable!(ingestion_job_batches -> ingestion_jobs (ingestion_job_id));
+diesel::joinable!(ingestion_jobs -> datasets (dataset_id));
 diesel::joinable!(messages -> datasets (dataset_id));
 diesel::joinable!(messages -> topics (topic_id));
 diesel::joinable!(organization_api_key -> organizations (organization_id));
 diesel::joinable!(organization_usage_counts -> organizations (org_id));
+diesel::joinable!(query_suggestions -> datasets (dataset_id));
+diesel::joinable!(rag_presets -> datasets (dataset_id));
 diesel::joinable!(stripe_invoices -> organizations (org_id));
 diesel::joinable!(stripe_subscriptions -> organizations (organization_id));
 diesel::joinable!(stripe_subscriptions -> stripe_plans (plan_id));
 diesel::joinable!(topics -> datasets (dataset_id));
+diesel::joinable!(trash -> datasets (dataset_id));
+diesel::joinable!(trash -> organizations (organization_id));
 diesel::joinable!(user_api_key -> users (user_id));
 diesel::joinable!(user_organizations -> organizations (organization_id));
 diesel::joinable!(user_organizations -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    analytics_audit_logs,
+    analytics_events,
+    analytics_export_configs,
+    analytics_export_runs,
+    analytics_rag_queries,
+    analytics_recommendations,
+    analytics_report_subscriptions,
+    analytics_search_queries,
+    analytics_worker_events,
     chunk_boosts,
     chunk_group,
     chunk_group_bookmarks,
@@ -347,19 +643,28 @@ diesel::allow_tables_to_appear_in_same_query!(
     dataset_event_counts,
     dataset_group_counts,
     dataset_tags,
+    dataset_usage,
     dataset_usage_counts,
+    dataset_webhook_deliveries,
+    dataset_webhooks,
     datasets,
+    experiments,
     files,
     groups_from_files,
+    ingestion_job_batches,
+    ingestion_jobs,
     invitations,
     messages,
     organization_api_key,
     organization_usage_counts,
     organizations,
+    query_suggestions,
+    rag_presets,
     stripe_invoices,
     stripe_plans,
     stripe_subscriptions,
     topics,
+    trash,
     user_api_key,
     user_organizations,
     users,
@@ -43,6 +43,9 @@ pub enum ServiceError {
     PayloadTooLarge(String),
 
     RequestTimeout(String),
+
+    #[display(fmt = "Too Many Requests: {_0}")]
+    TooManyRequests(String, u64),
 }
 
 // impl ResponseError trait allows to convert our errors into http responses with appropriate data
@@ -89,6 +92,13 @@ impl ResponseError for ServiceError {
                     message: message.to_string(),
                 })
             }
+            ServiceError::TooManyRequests(ref message, retry_after) => {
+                HttpResponse::TooManyRequests()
+                    .insert_header(("Retry-After", retry_after.to_string()))
+                    .json(ErrorResponseBody {
+                        message: message.to_string(),
+                    })
+            }
         }
     }
 }
@@ -0,0 +1,187 @@
+use super::auth_handler::AdminOnly;
+use crate::{
+    data::models::{
+        AnalyticsExportConfig, AnalyticsExportEventType, AnalyticsExportFormat,
+        AnalyticsForwardSink, DatasetAndOrgWithSubAndPlan, Pool,
+    },
+    errors::ServiceError,
+    operators::analytics_export_operator::{
+        delete_analytics_export_config_query, get_analytics_export_config_query,
+        get_analytics_export_runs_query, upsert_analytics_export_config_query,
+        validate_analytics_export_config,
+    },
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct UpsertAnalyticsExportReqPayload {
+    /// Format of the exported files. Default is jsonl.
+    pub format: Option<AnalyticsExportFormat>,
+    /// The analytics tables to export. Each run writes one file per table which had events in the window. Leave empty to only forward events.
+    pub event_types: Vec<AnalyticsExportEventType>,
+    /// Minutes between scheduled exports, between 5 and 10080. Default is 60.
+    pub interval_minutes: Option<i32>,
+    /// Url every analytics event of the dataset is POSTed to as it happens. Leave undefined to disable forwarding. Like webhook urls, it must resolve to a public address and redirects are not followed. Forwarding requires `USE_ANALYTICS_FORWARDING` to be set on the server.
+    pub forward_url: Option<String>,
+    /// How forwarded events are POSTed. Default is http.
+    pub forward_sink: Option<AnalyticsForwardSink>,
+    /// Value of the Authorization header sent with forwarded events.
+    pub forward_auth_header: Option<String>,
+    /// Set to false to pause exports and forwarding. Default is true.
+    pub active: Option<bool>,
+}
+
+/// Upsert Analytics Export
+///
+/// Configure scheduled exports of the dataset's analytics events to object storage and optional real-time forwarding of each event to an http or Kafka REST sink. Replacing an existing config keeps its watermark, so the next export continues where the last one stopped. Exports and forwarded events are delivered at least once; consumers should dedupe on the event `id`. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    put,
+    path = "/analytics/export",
+    context_path = "/api",
+    tag = "Analytics",
+    request_body(content = UpsertAnalyticsExportReqPayload, description = "JSON request payload to configure analytics exports", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The analytics export config", body = AnalyticsExportConfig),
+        (status = 400, description = "Service error relating to configuring analytics exports", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn upsert_analytics_export(
+    data: web::Json<UpsertAnalyticsExportReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let data = data.into_inner();
+
+    if data.event_types.is_empty() && data.forward_url.is_none() {
+        return Err(ServiceError::BadRequest(
+            "Analytics export must export at least one event type or set a forward_url".to_string(),
+        ));
+    }
+
+    let mut config = AnalyticsExportConfig::from_details(
+        dataset_org_plan_sub.dataset.id,
+        data.format.unwrap_or_default(),
+        data.event_types,
+        data.interval_minutes.unwrap_or(60),
+        data.forward_url,
+        data.forward_sink.unwrap_or_default(),
+        data.forward_auth_header,
+    );
+    config.active = data.active.unwrap_or(true);
+
+    validate_analytics_export_config(&config).await?;
+
+    let config = upsert_analytics_export_config_query(config, &pool).await?;
+
+    Ok(HttpResponse::Ok().json(config))
+}
+
+/// Get Analytics Export
+///
+/// Get the analytics export config of the dataset, including when events were last exported and when the next export runs. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/analytics/export",
+    context_path = "/api",
+    tag = "Analytics",
+    responses(
+        (status = 200, description = "The analytics export config", body = AnalyticsExportConfig),
+        (status = 404, description = "The dataset has no analytics export config", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_analytics_export(
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let config = get_analytics_export_config_query(dataset_org_plan_sub.dataset.id, &pool).await?;
+
+    Ok(HttpResponse::Ok().json(config))
+}
+
+/// Delete Analytics Export
+///
+/// Stop exporting and forwarding the dataset's analytics events and delete the run history. Files already exported are kept. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    delete,
+    path = "/analytics/export",
+    context_path = "/api",
+    tag = "Analytics",
+    responses(
+        (status = 204, description = "Confirmation that the analytics export config was deleted"),
+        (status = 404, description = "The dataset has no analytics export config", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn delete_analytics_export(
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    delete_analytics_export_config_query(dataset_org_plan_sub.dataset.id, &pool).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct GetAnalyticsExportRunsReqPayload {
+    /// The page number to get. Default is 1.
+    pub page: Option<u64>,
+}
+
+/// Get Analytics Export Runs
+///
+/// Get the scheduled export runs of the dataset, newest first. Each run records the window it exported, its status, the number of events written and presigned urls to download its files. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/analytics/export/runs",
+    context_path = "/api",
+    tag = "Analytics",
+    responses(
+        (status = 200, description = "Export runs of the dataset", body = AnalyticsExportRunsResponse),
+        (status = 400, description = "Service error relating to getting the export runs", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        GetAnalyticsExportRunsReqPayload,
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_analytics_export_runs(
+    params: web::Query<GetAnalyticsExportRunsReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let runs = get_analytics_export_runs_query(
+        dataset_org_plan_sub.dataset.id,
+        params.page.unwrap_or(1),
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(runs))
+}
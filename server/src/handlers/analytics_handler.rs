@@ -10,6 +10,7 @@ use crate::{
     errors::ServiceError,
     operators::{
         analytics_operator::*,
+        analytics_store_operator::{AnalyticsBackend, AnalyticsStore},
         clickhouse_operator::{ClickHouseEvent, EventQueue},
     },
 };
@@ -41,17 +42,15 @@ use utoipa::ToSchema;
 pub async fn get_cluster_analytics(
     data: web::Json<ClusterAnalytics>,
     _user: AdminOnly,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, ServiceError> {
+    let clickhouse_client = analytics_backend.clickhouse_client()?;
     let response = match data.into_inner() {
         ClusterAnalytics::ClusterTopics { filter } => {
-            let clusters = get_clusters_query(
-                dataset_org_plan_sub.dataset.id,
-                filter,
-                clickhouse_client.get_ref(),
-            )
-            .await?;
+            let clusters =
+                get_clusters_query(dataset_org_plan_sub.dataset.id, filter, clickhouse_client)
+                    .await?;
             ClusterAnalyticsResponse::ClusterTopics(clusters)
         }
         ClusterAnalytics::ClusterQueries { cluster_id, page } => {
@@ -59,7 +58,7 @@ pub async fn get_cluster_analytics(
                 dataset_org_plan_sub.dataset.id,
                 cluster_id,
                 page,
-                clickhouse_client.get_ref(),
+                clickhouse_client,
             )
             .await?;
             ClusterAnalyticsResponse::ClusterQueries(cluster_queries)
@@ -100,16 +99,12 @@ pub struct RateQueryRequest {
 pub async fn set_search_query_rating(
     data: web::Json<RateQueryRequest>,
     _user: AdminOnly,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, ServiceError> {
+    let clickhouse_client = analytics_backend.clickhouse_client()?;
     let data = data.into_inner();
-    set_search_query_rating_query(
-        data,
-        dataset_org_plan_sub.dataset.id,
-        clickhouse_client.get_ref(),
-    )
-    .await?;
+    set_search_query_rating_query(data, dataset_org_plan_sub.dataset.id, clickhouse_client).await?;
 
     Ok(HttpResponse::NoContent().finish())
 }
@@ -138,16 +133,12 @@ pub async fn set_search_query_rating(
 pub async fn set_rag_query_rating(
     data: web::Json<RateQueryRequest>,
     _user: AdminOnly,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, ServiceError> {
+    let clickhouse_client = analytics_backend.clickhouse_client()?;
     let data = data.into_inner();
-    set_rag_query_rating_query(
-        data,
-        dataset_org_plan_sub.dataset.id,
-        clickhouse_client.get_ref(),
-    )
-    .await?;
+    set_rag_query_rating_query(data, dataset_org_plan_sub.dataset.id, clickhouse_client).await?;
 
     Ok(HttpResponse::NoContent().finish())
 }
@@ -176,7 +167,7 @@ pub async fn set_rag_query_rating(
 pub async fn get_search_analytics(
     data: web::Json<SearchAnalytics>,
     _user: AdminOnly,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, ServiceError> {
     let response = match data.into_inner() {
@@ -188,7 +179,7 @@ pub async fn get_search_analytics(
                 dataset_org_plan_sub.dataset.id,
                 filter,
                 granularity,
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
@@ -198,13 +189,9 @@ pub async fn get_search_analytics(
             filter,
             granularity,
         } => {
-            let search_frequency_graph = get_search_usage_graph_query(
-                dataset_org_plan_sub.dataset.id,
-                filter,
-                granularity,
-                clickhouse_client.get_ref(),
-            )
-            .await?;
+            let search_frequency_graph = analytics_backend
+                .get_search_usage_graph(dataset_org_plan_sub.dataset.id, filter, granularity)
+                .await?;
 
             SearchAnalyticsResponse::SearchUsageGraph(search_frequency_graph)
         }
@@ -212,20 +199,16 @@ pub async fn get_search_analytics(
             let search_metrics = get_search_metrics_query(
                 dataset_org_plan_sub.dataset.id,
                 filter,
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
             SearchAnalyticsResponse::SearchMetrics(search_metrics)
         }
         SearchAnalytics::HeadQueries { filter, page } => {
-            let head_queries = get_head_queries_query(
-                dataset_org_plan_sub.dataset.id,
-                filter,
-                page,
-                clickhouse_client.get_ref(),
-            )
-            .await?;
+            let head_queries = analytics_backend
+                .get_head_queries(dataset_org_plan_sub.dataset.id, filter, page)
+                .await?;
 
             SearchAnalyticsResponse::HeadQueries(head_queries)
         }
@@ -239,20 +222,16 @@ pub async fn get_search_analytics(
                 filter,
                 threshold,
                 page,
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
             SearchAnalyticsResponse::LowConfidenceQueries(low_confidence_queries)
         }
         SearchAnalytics::NoResultQueries { filter, page } => {
-            let no_result_queries = get_no_result_queries_query(
-                dataset_org_plan_sub.dataset.id,
-                filter,
-                page,
-                clickhouse_client.get_ref(),
-            )
-            .await?;
+            let no_result_queries = analytics_backend
+                .get_no_result_queries(dataset_org_plan_sub.dataset.id, filter, page)
+                .await?;
 
             SearchAnalyticsResponse::NoResultQueries(no_result_queries)
         }
@@ -268,7 +247,7 @@ pub async fn get_search_analytics(
                 sort_by,
                 sort_order,
                 page,
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
@@ -278,7 +257,7 @@ pub async fn get_search_analytics(
             let query = get_search_query(
                 dataset_org_plan_sub.dataset.id,
                 request_id,
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
@@ -288,7 +267,7 @@ pub async fn get_search_analytics(
             let count_queries = get_query_counts_query(
                 dataset_org_plan_sub.dataset.id,
                 filter,
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
@@ -298,7 +277,7 @@ pub async fn get_search_analytics(
             let popular_filters = get_popular_filter_values_query(
                 dataset_org_plan_sub.dataset.id,
                 filter,
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
@@ -333,18 +312,15 @@ pub async fn get_search_analytics(
 pub async fn get_rag_analytics(
     data: web::Json<RAGAnalytics>,
     _user: AdminOnly,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
     pool: web::Data<Pool>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, ServiceError> {
     let response = match data.into_inner() {
         RAGAnalytics::RAGUsage { filter } => {
-            let rag_graph = get_rag_usage_query(
-                dataset_org_plan_sub.dataset.id,
-                filter,
-                clickhouse_client.get_ref(),
-            )
-            .await?;
+            let rag_graph = analytics_backend
+                .get_rag_usage(dataset_org_plan_sub.dataset.id, filter)
+                .await?;
             RAGAnalyticsResponse::RAGUsage(rag_graph)
         }
         RAGAnalytics::RAGQueries {
@@ -360,7 +336,7 @@ pub async fn get_rag_analytics(
                 sort_order,
                 page,
                 pool.clone(),
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
             RAGAnalyticsResponse::RAGQueries(rag_queries)
@@ -370,13 +346,9 @@ pub async fn get_rag_analytics(
             filter,
             granularity,
         } => {
-            let rag = get_rag_usage_graph_query(
-                dataset_org_plan_sub.dataset.id,
-                filter,
-                granularity,
-                clickhouse_client.get_ref(),
-            )
-            .await?;
+            let rag = analytics_backend
+                .get_rag_usage_graph(dataset_org_plan_sub.dataset.id, filter, granularity)
+                .await?;
             RAGAnalyticsResponse::RAGUsageGraph(rag)
         }
 
@@ -385,7 +357,7 @@ pub async fn get_rag_analytics(
                 dataset_org_plan_sub.dataset.id,
                 request_id,
                 pool.clone(),
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
             RAGAnalyticsResponse::RAGQueryDetails(Box::new(rag_query))
@@ -394,7 +366,7 @@ pub async fn get_rag_analytics(
             let rag_query_ratings = get_rag_query_ratings_query(
                 dataset_org_plan_sub.dataset.id,
                 filter,
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
             RAGAnalyticsResponse::RAGQueryRatings(rag_query_ratings)
@@ -428,9 +400,10 @@ pub async fn get_rag_analytics(
 pub async fn get_recommendation_analytics(
     data: web::Json<RecommendationAnalytics>,
     _user: AdminOnly,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, ServiceError> {
+    let clickhouse_client = analytics_backend.clickhouse_client()?;
     let response = match data.into_inner() {
         RecommendationAnalytics::LowConfidenceRecommendations {
             filter,
@@ -442,7 +415,7 @@ pub async fn get_recommendation_analytics(
                 filter,
                 threshold,
                 page,
-                clickhouse_client.get_ref(),
+                clickhouse_client,
             )
             .await?;
             RecommendationAnalyticsResponse::LowConfidenceRecommendations(
@@ -461,7 +434,7 @@ pub async fn get_recommendation_analytics(
                 sort_by,
                 sort_order,
                 page,
-                clickhouse_client.get_ref(),
+                clickhouse_client,
             )
             .await?;
             RecommendationAnalyticsResponse::RecommendationQueries(recommendation_queries)
@@ -470,7 +443,7 @@ pub async fn get_recommendation_analytics(
             let recommendation_query = get_recommendation_query(
                 dataset_org_plan_sub.dataset.id,
                 request_id,
-                clickhouse_client.get_ref(),
+                clickhouse_client,
             )
             .await?;
             RecommendationAnalyticsResponse::QueryDetails(recommendation_query)
@@ -521,14 +494,18 @@ pub struct CTRDataRequestBody {
 pub async fn send_ctr_data(
     _user: AdminOnly,
     data: web::Json<CTRDataRequestBody>,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
+    event_queue: web::Data<EventQueue>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, ServiceError> {
     let event_data =
         EventTypes::from(data.into_inner()).to_event_data(dataset_org_plan_sub.dataset.id);
 
     if let EventDataTypes::EventDataClickhouse(event_data) = event_data {
-        send_event_data_query(event_data, clickhouse_client.get_ref()).await?;
+        analytics_backend
+            .write_event_data(event_data.clone())
+            .await?;
+        event_queue.forward_event_data(&event_data).await;
     }
 
     Ok(HttpResponse::NoContent().finish())
@@ -558,7 +535,7 @@ pub async fn send_ctr_data(
 pub async fn send_event_data(
     _user: AdminOnly,
     data: web::Json<EventTypes>,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
     event_queue: web::Data<EventQueue>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, ServiceError> {
@@ -568,7 +545,10 @@ pub async fn send_event_data(
 
     match event_data {
         EventDataTypes::EventDataClickhouse(event_data) => {
-            send_event_data_query(event_data, clickhouse_client.get_ref()).await?;
+            analytics_backend
+                .write_event_data(event_data.clone())
+                .await?;
+            event_queue.forward_event_data(&event_data).await;
         }
         EventDataTypes::SearchQueryEventClickhouse(event_data) => {
             event_queue
@@ -614,18 +594,15 @@ pub async fn send_event_data(
 pub async fn get_ctr_analytics(
     _user: AdminOnly,
     data: web::Json<CTRAnalytics>,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
     pool: web::Data<Pool>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, ServiceError> {
     let response = match data.into_inner() {
         CTRAnalytics::SearchCTRMetrics { filter } => {
-            let ctr_metrics = get_search_ctr_metrics_query(
-                dataset_org_plan_sub.dataset.id,
-                filter,
-                clickhouse_client.get_ref(),
-            )
-            .await?;
+            let ctr_metrics = analytics_backend
+                .get_search_ctr_metrics(dataset_org_plan_sub.dataset.id, filter)
+                .await?;
 
             CTRAnalyticsResponse::SearchCTRMetrics(ctr_metrics)
         }
@@ -635,7 +612,7 @@ pub async fn get_ctr_analytics(
                 page,
                 filter,
                 pool.clone(),
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
@@ -646,7 +623,7 @@ pub async fn get_ctr_analytics(
                 dataset_org_plan_sub.dataset.id,
                 page,
                 filter,
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
@@ -656,7 +633,7 @@ pub async fn get_ctr_analytics(
             let ctr_metrics = get_recommendation_ctr_metrics_query(
                 dataset_org_plan_sub.dataset.id,
                 filter,
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
@@ -668,7 +645,7 @@ pub async fn get_ctr_analytics(
                 page,
                 filter,
                 pool.clone(),
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
@@ -679,7 +656,7 @@ pub async fn get_ctr_analytics(
                 dataset_org_plan_sub.dataset.id,
                 page,
                 filter,
-                clickhouse_client.get_ref(),
+                analytics_backend.clickhouse_client()?,
             )
             .await?;
 
@@ -711,15 +688,16 @@ pub async fn get_ctr_analytics(
 pub async fn get_all_events(
     _user: AdminOnly,
     data: web::Json<GetEventsRequestBody>,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, ServiceError> {
+    let clickhouse_client = analytics_backend.clickhouse_client()?;
     let dataset_id = dataset_org_plan_sub.dataset.id;
     let events = get_all_events_query(
         dataset_id,
         data.page,
         data.filter.clone(),
-        clickhouse_client.get_ref(),
+        clickhouse_client,
     )
     .await?;
 
@@ -750,12 +728,12 @@ pub async fn get_all_events(
 pub async fn get_event_by_id(
     _user: AdminOnly,
     data: web::Path<uuid::Uuid>,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, ServiceError> {
+    let clickhouse_client = analytics_backend.clickhouse_client()?;
     let dataset_id = dataset_org_plan_sub.dataset.id;
-    let events =
-        get_event_by_id_query(dataset_id, data.into_inner(), clickhouse_client.get_ref()).await?;
+    let events = get_event_by_id_query(dataset_id, data.into_inner(), clickhouse_client).await?;
 
     Ok(HttpResponse::Ok().json(events))
 }
@@ -789,14 +767,15 @@ pub struct GetTopDatasetsRequestBody {
 pub async fn get_top_datasets(
     _user: AdminOnly,
     data: web::Json<GetTopDatasetsRequestBody>,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
     org_with_plan_and_sub: OrganizationWithSubAndPlan,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse, ServiceError> {
+    let clickhouse_client = analytics_backend.clickhouse_client()?;
     let top_datasets = get_top_datasets_query(
         data.into_inner(),
         org_with_plan_and_sub.organization.id,
-        clickhouse_client.get_ref(),
+        clickhouse_client,
         pool,
     )
     .await?;
@@ -0,0 +1,184 @@
+use super::auth_handler::AdminOnly;
+use crate::{
+    data::models::{
+        AnalyticsReportSchedule, AnalyticsReportSubscription, DatasetAndOrgWithSubAndPlan, Pool,
+        Templates,
+    },
+    errors::ServiceError,
+    operators::analytics_report_operator::{
+        analytics_report_subject, build_analytics_report,
+        delete_analytics_report_subscription_query, get_analytics_report_subscription_query,
+        render_analytics_report, upsert_analytics_report_subscription_query,
+    },
+    operators::analytics_store_operator::AnalyticsBackend,
+    operators::email_operator::send_email,
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct UpsertAnalyticsReportSubscriptionReqPayload {
+    /// How often the report is emailed. Default is weekly.
+    pub schedule: Option<AnalyticsReportSchedule>,
+    /// Set to false to pause the reports without losing the subscription. Default is true.
+    pub active: Option<bool>,
+}
+
+/// Subscribe to Analytics Reports
+///
+/// Subscribe the auth'ed user to emailed digests of the dataset's search volume, top and no-result queries, click-through rate trend, RAG ratings and ingestion errors. Each report covers the period since the previous one and is sent to the user's email address. Updating an existing subscription restarts its schedule from now. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    put,
+    path = "/analytics/reports",
+    context_path = "/api",
+    tag = "Analytics",
+    request_body(content = UpsertAnalyticsReportSubscriptionReqPayload, description = "JSON request payload to subscribe to analytics reports", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The analytics report subscription", body = AnalyticsReportSubscription),
+        (status = 400, description = "Service error relating to subscribing to analytics reports", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn upsert_analytics_report_subscription(
+    data: web::Json<UpsertAnalyticsReportSubscriptionReqPayload>,
+    user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    analytics_backend: web::Data<AnalyticsBackend>,
+) -> Result<HttpResponse, ServiceError> {
+    // Reports are built from ClickHouse, refuse subscriptions which would never be sent
+    analytics_backend.clickhouse_client()?;
+    let data = data.into_inner();
+
+    let subscription = upsert_analytics_report_subscription_query(
+        AnalyticsReportSubscription::from_details(
+            user.0.id,
+            dataset_org_plan_sub.dataset.id,
+            data.schedule.unwrap_or_default(),
+            data.active.unwrap_or(true),
+        ),
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(subscription))
+}
+
+/// Get Analytics Report Subscription
+///
+/// Get the auth'ed user's analytics report subscription for the dataset, including when the next report is sent. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/analytics/reports",
+    context_path = "/api",
+    tag = "Analytics",
+    responses(
+        (status = 200, description = "The analytics report subscription", body = AnalyticsReportSubscription),
+        (status = 404, description = "The user is not subscribed to analytics reports of the dataset", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_analytics_report_subscription(
+    user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let subscription =
+        get_analytics_report_subscription_query(user.0.id, dataset_org_plan_sub.dataset.id, &pool)
+            .await?;
+
+    Ok(HttpResponse::Ok().json(subscription))
+}
+
+/// Unsubscribe from Analytics Reports
+///
+/// Stop emailing analytics reports of the dataset to the auth'ed user. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    delete,
+    path = "/analytics/reports",
+    context_path = "/api",
+    tag = "Analytics",
+    responses(
+        (status = 204, description = "Confirmation that the user was unsubscribed"),
+        (status = 404, description = "The user is not subscribed to analytics reports of the dataset", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn delete_analytics_report_subscription(
+    user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    delete_analytics_report_subscription_query(user.0.id, dataset_org_plan_sub.dataset.id, &pool)
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Send Analytics Report
+///
+/// Email the report of the last period of the auth'ed user's subscription to them right away, without changing when the next scheduled report is sent. Without a subscription the last week is reported. Useful to preview reports. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/analytics/reports/send",
+    context_path = "/api",
+    tag = "Analytics",
+    responses(
+        (status = 200, description = "The report which was sent", body = AnalyticsReport),
+        (status = 400, description = "Service error relating to sending the report", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn send_analytics_report_now(
+    user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    analytics_backend: web::Data<AnalyticsBackend>,
+    templates: Templates<'_>,
+) -> Result<HttpResponse, ServiceError> {
+    let clickhouse_client = analytics_backend.clickhouse_client()?;
+    let dataset = dataset_org_plan_sub.dataset;
+    let schedule = get_analytics_report_subscription_query(user.0.id, dataset.id, &pool)
+        .await
+        .map(|subscription| subscription.schedule())
+        .unwrap_or_default();
+
+    let window_end = chrono::Utc::now().naive_utc();
+    let report = build_analytics_report(
+        &dataset,
+        window_end - schedule.period(),
+        window_end,
+        clickhouse_client,
+        &pool,
+    )
+    .await?;
+
+    send_email(
+        render_analytics_report(&report, &templates)?,
+        user.0.email,
+        Some(analytics_report_subject(schedule, &dataset)),
+    )?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
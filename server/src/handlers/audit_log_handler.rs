@@ -0,0 +1,101 @@
+use super::auth_handler::{LoggedUser, OwnerOnly};
+use crate::{
+    data::models::{AuditActor, AuditLogFilter, OrganizationWithSubAndPlan, UserApiKey},
+    errors::ServiceError,
+    middleware::auth_middleware::verify_owner,
+    operators::{
+        analytics_store_operator::{AnalyticsBackend, AnalyticsStore},
+        audit_log_operator::{get_source_ip, get_trusted_proxies},
+    },
+};
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::future::{ready, Ready};
+use utoipa::ToSchema;
+
+impl FromRequest for AuditActor {
+    type Error = ServiceError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(
+        req: &actix_web::HttpRequest,
+        _payload: &mut actix_web::dev::Payload,
+    ) -> Self::Future {
+        let forwarded_for = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<&str>>()
+            .join(",");
+        let source_ip = get_source_ip(
+            req.peer_addr().map(|addr| addr.ip()),
+            &forwarded_for,
+            get_trusted_proxies(),
+        );
+
+        let extensions = req.extensions();
+        let Some(user) = extensions.get::<LoggedUser>() else {
+            return ready(Err(ServiceError::Unauthorized));
+        };
+
+        ready(Ok(AuditActor {
+            user_id: user.id,
+            email: user.email.clone(),
+            api_key_id: extensions.get::<UserApiKey>().map(|api_key| api_key.id),
+            source_ip,
+        }))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct GetAuditLogsReqPayload {
+    /// Filters to apply to the audit logs. Leave undefined to get all audit logs for the organization.
+    pub filter: Option<AuditLogFilter>,
+    /// The page number to get. Default is 1.
+    pub page: Option<u64>,
+    /// The number of items per page. Default is 10.
+    pub page_size: Option<u64>,
+}
+
+/// Get Audit Logs
+///
+/// Get the record of configuration, api key, membership and dataset changes made in the organization, newest first. Auth'ed user or api key must have an owner role for the specified organization.
+#[utoipa::path(
+    post,
+    path = "/organization/audit_logs",
+    context_path = "/api",
+    tag = "Organization",
+    request_body(content = GetAuditLogsReqPayload, description = "JSON request payload to filter the audit logs", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Audit logs for the organization", body = AuditLogsResponse),
+        (status = 400, description = "Service error relating to getting the audit logs", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Organization" = uuid::Uuid, Header, description = "The organization id to use for the request"),
+    ),
+    security(
+        ("ApiKey" = ["owner"]),
+    )
+)]
+pub async fn get_audit_logs(
+    data: web::Json<GetAuditLogsReqPayload>,
+    org_with_plan_and_sub: OrganizationWithSubAndPlan,
+    user: OwnerOnly,
+    analytics_backend: web::Data<AnalyticsBackend>,
+) -> Result<HttpResponse, ServiceError> {
+    if !verify_owner(&user, &org_with_plan_and_sub.organization.id) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let data = data.into_inner();
+    let audit_logs = analytics_backend
+        .get_audit_logs(
+            org_with_plan_and_sub.organization.id,
+            data.filter,
+            data.page.unwrap_or(1),
+            data.page_size.unwrap_or(10),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(audit_logs))
+}
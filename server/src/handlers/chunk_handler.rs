@@ -2,23 +2,29 @@ use super::auth_handler::{AdminOnly, LoggedUser};
 #[cfg(not(feature = "hallucination-detection"))]
 use crate::data::models::DummyHallucinationScore;
 use crate::data::models::{
-    escape_quotes, ChatMessageProxy, ChunkMetadata, ChunkMetadataStringTagSet, ChunkMetadataTypes,
-    ChunkMetadataWithScore, ConditionType, ContextOptions, CountSearchMethod,
-    DatasetAndOrgWithSubAndPlan, DatasetConfiguration, GeoInfo, HighlightOptions, ImageConfig,
-    IngestSpecificChunkMetadata, MultiQuery, Pool, QdrantChunkMetadata, QueryTypes,
-    RagQueryEventClickhouse, RecommendType, RecommendationEventClickhouse, RecommendationStrategy,
-    RedisPool, RoleProxy, ScoreChunk, ScoreChunkDTO, SearchMethod, SearchModalities,
-    SearchQueryEventClickhouse, SlimChunkMetadataWithScore, SortByField, SortOptions, TypoOptions,
-    UnifiedId, UpdateSpecificChunkMetadata,
+    escape_quotes, AclPrincipals, ChatMessageProxy, ChunkMetadata, ChunkMetadataStringTagSet,
+    ChunkMetadataTypes, ChunkMetadataWithScore, ConditionType, ContextOptions, CountSearchMethod,
+    DatasetAndOrgWithSubAndPlan, DatasetConfiguration, ExperimentAssignment, GeoInfo,
+    HighlightOptions, ImageConfig, IngestSpecificChunkMetadata, IngestionJob, IngestionJobSource,
+    MultiQuery, Pool, QdrantChunkMetadata, QueryTypes, RagQueryEventClickhouse, RecommendType,
+    RecommendationEventClickhouse, RecommendationStrategy, RedisPool, RoleProxy, ScoreChunk,
+    ScoreChunkDTO, SearchMethod, SearchModalities, SearchQueryEventClickhouse,
+    SlimChunkMetadataWithScore, SortByField, SortOptions, TypoOptions, UnifiedId,
+    UpdateSpecificChunkMetadata, UsageCounts,
 };
 use crate::errors::ServiceError;
 use crate::get_env;
 use crate::middleware::api_version::APIVersion;
+use crate::operators::acl_operator::validate_acl;
 use crate::operators::chunk_operator::get_metadata_from_id_query;
 use crate::operators::clickhouse_operator::{get_latency_from_header, ClickHouseEvent, EventQueue};
 use crate::operators::dataset_operator::{
     get_dataset_usage_query, ChunkDeleteMessage, DeleteMessage,
 };
+use crate::operators::experiment_operator::apply_experiment_to_search;
+use crate::operators::ingestion_job_operator::{
+    complete_ingestion_job_queuing, create_ingestion_job_query, create_ingestion_message,
+};
 use crate::operators::message_operator::get_text_from_audio;
 use crate::operators::parse_operator::convert_html_to_text;
 use crate::operators::qdrant_operator::{
@@ -28,6 +34,7 @@ use crate::operators::search_operator::{
     assemble_qdrant_filter, autocomplete_chunks_query, count_chunks_query, parse_query,
     search_chunks_query, search_hybrid_chunks, ParsedQuery, ParsedQueryTypes,
 };
+use crate::operators::usage_operator::{estimate_prompt_tokens, meter_chat_completion, UsageMeter};
 use crate::operators::{chunk_operator::*, crawl_operator};
 use actix::Arbiter;
 use actix_web::web::Bytes;
@@ -143,6 +150,8 @@ pub struct ChunkReqPayload {
     pub semantic_boost: Option<SemanticBoost>,
     /// High Priority allows you to place this chunk into a priority queue with its own ingestion workers. Can only be used by users with a Custom Pro plan.
     pub high_priority: Option<bool>,
+    /// Principals which are allowed to see the chunk when access control is enabled for the dataset. Each entry is either `user:<user_id>` or `group:<group_name>`. If not specified, the dataset's default access control policy applies.
+    pub acl: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, ToSchema)]
@@ -172,6 +181,7 @@ pub enum ReturnQueuedChunk {
     }],
     "pos_in_queue": 1
 }))]
+/// Single chunk creates are not tracked by an ingestion job, so unlike the batch response there is no ingestion job id.
 pub struct SingleQueuedChunkResponse {
     /// The chunk that got queue'd
     pub chunk_metadata: ChunkMetadata,
@@ -199,11 +209,14 @@ pub struct SingleQueuedChunkResponse {
         "time_stamp": "2021-01-01 00:00:00.000",
         "weight": 0.5
     }],
-    "pos_in_queue": 2
+    "pos_in_queue": 2,
+    "ingestion_job_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3"
 }))]
 pub struct BatchQueuedChunkResponse {
     // All the chunks that got queue'd
     pub chunk_metadata: Vec<ChunkMetadata>,
+    /// Id of the ingestion job tracking the chunks. Use it with the get ingestion job route to check on their progress.
+    pub ingestion_job_id: uuid::Uuid,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -219,6 +232,13 @@ pub struct BulkUploadIngestionMessage {
     pub attempt_number: usize,
     pub dataset_id: uuid::Uuid,
     pub ingestion_messages: Vec<UploadIngestionMessage>,
+    #[serde(default)]
+    pub ingestion_job_id: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub ingestion_batch_id: Option<uuid::Uuid>,
+    /// Api key which queued the chunks, so their embeddings are metered against it.
+    #[serde(default)]
+    pub api_key_id: Option<uuid::Uuid>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
@@ -288,7 +308,7 @@ pub enum CreateChunkReqPayloadEnum {
 /// Create or Upsert Chunk or Chunks
 ///
 /// Create new chunk(s). If the chunk has the same tracking_id as an existing chunk, the request will fail. Once a chunk is created, it can be searched for using the search endpoint.
-/// If uploading in bulk, the maximum amount of chunks that can be uploaded at once is 120 chunks. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+/// If uploading in bulk, the maximum amount of chunks that can be uploaded at once is 120 chunks and the response includes an ingestion job id to track their progress with. Single chunk creates are not tracked by an ingestion job and do not trigger the ingestion webhook, use the get chunk by id or tracking id routes to check when a single chunk is searchable. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
 #[utoipa::path(
     post,
     path = "/chunk",
@@ -386,6 +406,24 @@ pub async fn create_chunk(
         .dedup_by(|x, y| x.tracking_id == y.tracking_id)
         .collect::<Vec<ChunkMetadata>>();
 
+    // Only bulk creates are tracked by an ingestion job, single chunks can be checked on directly
+    let ingestion_job_id = match create_chunk_data.0 {
+        CreateChunkReqPayloadEnum::Batch(_) => Some(
+            create_ingestion_job_query(
+                IngestionJob::from_details(
+                    dataset_org_plan_sub.dataset.id,
+                    IngestionJobSource::Chunks,
+                    false,
+                    dataset_config.INGESTION_WEBHOOK_URL.clone(),
+                ),
+                pool.clone(),
+            )
+            .await?
+            .id,
+        ),
+        CreateChunkReqPayloadEnum::Single(_) => None,
+    };
+
     let is_premium = std::env::var("PREMIUM_ORGANIZATION_UUIDS")
         .unwrap_or("".to_string())
         .split(',')
@@ -414,11 +452,13 @@ pub async fn create_chunk(
                 .publish(
                     "premium_ingestion",
                     Some(dataset_org_plan_sub.dataset.id.to_string()),
-                    &BulkUploadIngestionMessage {
-                        attempt_number: 0,
-                        dataset_id: dataset_org_plan_sub.dataset.id,
-                        ingestion_messages: prio_chunks_message.clone(),
-                    },
+                    &create_ingestion_message(
+                        ingestion_job_id,
+                        dataset_org_plan_sub.dataset.id,
+                        prio_chunks_message.clone(),
+                        pool.clone(),
+                    )
+                    .await?,
                     None,
                 )
                 .await
@@ -431,11 +471,13 @@ pub async fn create_chunk(
                 .publish(
                     "openai_ingestion",
                     Some(dataset_org_plan_sub.dataset.id.to_string()),
-                    &BulkUploadIngestionMessage {
-                        attempt_number: 0,
-                        dataset_id: dataset_org_plan_sub.dataset.id,
-                        ingestion_messages: non_prio_chunks_message,
-                    },
+                    &create_ingestion_message(
+                        ingestion_job_id,
+                        dataset_org_plan_sub.dataset.id,
+                        non_prio_chunks_message,
+                        pool.clone(),
+                    )
+                    .await?,
                     None,
                 )
                 .await
@@ -448,11 +490,13 @@ pub async fn create_chunk(
                 .publish(
                     "ingestion",
                     Some(dataset_org_plan_sub.dataset.id.to_string()),
-                    &BulkUploadIngestionMessage {
-                        attempt_number: 0,
-                        dataset_id: dataset_org_plan_sub.dataset.id,
-                        ingestion_messages: non_prio_chunks_message,
-                    },
+                    &create_ingestion_message(
+                        ingestion_job_id,
+                        dataset_org_plan_sub.dataset.id,
+                        non_prio_chunks_message,
+                        pool.clone(),
+                    )
+                    .await?,
                     None,
                 )
                 .await
@@ -482,11 +526,13 @@ pub async fn create_chunk(
                 .publish(
                     "premium_ingestion",
                     Some(dataset_org_plan_sub.dataset.id.to_string()),
-                    &BulkUploadIngestionMessage {
-                        attempt_number: 0,
-                        dataset_id: dataset_org_plan_sub.dataset.id,
-                        ingestion_messages: prio_chunks_message.clone(),
-                    },
+                    &create_ingestion_message(
+                        ingestion_job_id,
+                        dataset_org_plan_sub.dataset.id,
+                        prio_chunks_message.clone(),
+                        pool.clone(),
+                    )
+                    .await?,
                     None,
                 )
                 .await
@@ -499,11 +545,13 @@ pub async fn create_chunk(
                 .publish(
                     "openai_ingestion",
                     Some(dataset_org_plan_sub.dataset.id.to_string()),
-                    &BulkUploadIngestionMessage {
-                        attempt_number: 0,
-                        dataset_id: dataset_org_plan_sub.dataset.id,
-                        ingestion_messages: non_prio_chunks_message,
-                    },
+                    &create_ingestion_message(
+                        ingestion_job_id,
+                        dataset_org_plan_sub.dataset.id,
+                        non_prio_chunks_message,
+                        pool.clone(),
+                    )
+                    .await?,
                     None,
                 )
                 .await
@@ -516,11 +564,13 @@ pub async fn create_chunk(
                 .publish(
                     "ingestion",
                     Some(dataset_org_plan_sub.dataset.id.to_string()),
-                    &BulkUploadIngestionMessage {
-                        attempt_number: 0,
-                        dataset_id: dataset_org_plan_sub.dataset.id,
-                        ingestion_messages: non_prio_chunks_message,
-                    },
+                    &create_ingestion_message(
+                        ingestion_job_id,
+                        dataset_org_plan_sub.dataset.id,
+                        non_prio_chunks_message,
+                        pool.clone(),
+                    )
+                    .await?,
                     None,
                 )
                 .await
@@ -531,6 +581,15 @@ pub async fn create_chunk(
         }
     }
 
+    if let Some(ingestion_job_id) = ingestion_job_id {
+        complete_ingestion_job_queuing(
+            ingestion_job_id,
+            dataset_org_plan_sub.dataset.id,
+            pool.clone(),
+        )
+        .await?;
+    }
+
     let response = match create_chunk_data.into_inner() {
         CreateChunkReqPayloadEnum::Single(_) => ReturnQueuedChunk::Single(SingleQueuedChunkResponse {
             chunk_metadata: chunk_metadatas
@@ -542,6 +601,9 @@ pub async fn create_chunk(
         }),
         CreateChunkReqPayloadEnum::Batch(_) => ReturnQueuedChunk::Batch(BatchQueuedChunkResponse {
             chunk_metadata: chunk_metadatas,
+            ingestion_job_id: ingestion_job_id.ok_or(ServiceError::InternalServerError(
+                "Bulk chunk create is missing its ingestion job".to_string(),
+            ))?,
         }),
     };
 
@@ -746,6 +808,8 @@ pub struct UpdateChunkReqPayload {
     /// Semantic boost is useful for moving the embedding vector of the chunk in the direction of the distance phrase. I.e. you can push a chunk with a chunk_html of "iphone" 25% closer to the term "flagship" by using the distance phrase "flagship" and a distance factor of 0.25. Conceptually it's drawing a line (euclidean/L2 distance) between the vector for the innerText of the chunk_html and distance_phrase then moving the vector of the chunk_html distance_factor*L2Distance closer to or away from the distance_phrase point along the line between the two points.
     #[serde(alias = "distance_phrase")]
     pub semantic_boost: Option<SemanticBoost>,
+    /// Principals which are allowed to see the chunk when access control is enabled for the dataset. Each entry is either `user:<user_id>` or `group:<group_name>`. If no acl is provided, the existing acl will be used. Set it to an empty array to remove the acl.
+    pub acl: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -827,7 +891,15 @@ pub async fn update_chunk(
         None => chunk_metadata.chunk_html,
     };
 
-    let chunk_metadata = ChunkMetadata::from_details_with_id(
+    let chunk_acl = match update_chunk_data.acl.clone() {
+        Some(acl) => {
+            validate_acl(&acl)?;
+            Some(acl.into_iter().map(Some).collect::<Vec<Option<String>>>())
+        }
+        None => chunk_metadata.acl.clone(),
+    };
+
+    let mut chunk_metadata = ChunkMetadata::from_details_with_id(
         chunk_metadata.id,
         chunk_html,
         &link,
@@ -858,6 +930,7 @@ pub async fn update_chunk(
         update_chunk_data.weight.unwrap_or(chunk_metadata.weight),
         update_chunk_data.num_value.or(chunk_metadata.num_value),
     );
+    chunk_metadata.acl = chunk_acl;
 
     let group_ids = if let Some(group_ids) = update_chunk_data.group_ids.clone() {
         Some(
@@ -915,6 +988,8 @@ pub struct UpdateChunkByTrackingIdData {
     group_tracking_ids: Option<Vec<String>>,
     /// Convert HTML to raw text before processing to avoid adding noise to the vector embeddings. By default this is true. If you are using HTML content that you want to be included in the vector embeddings, set this to false.
     pub convert_html_to_text: Option<bool>,
+    /// Principals which are allowed to see the chunk when access control is enabled for the dataset. Each entry is either `user:<user_id>` or `group:<group_name>`. If no acl is provided, the existing acl will be used. Set it to an empty array to remove the acl.
+    pub acl: Option<Vec<String>>,
 }
 
 /// Update Chunk By Tracking Id
@@ -969,7 +1044,15 @@ pub async fn update_chunk_by_tracking_id(
         None => chunk_metadata.chunk_html,
     };
 
-    let metadata = ChunkMetadata::from_details_with_id(
+    let chunk_acl = match update_chunk_data.acl.clone() {
+        Some(acl) => {
+            validate_acl(&acl)?;
+            Some(acl.into_iter().map(Some).collect::<Vec<Option<String>>>())
+        }
+        None => chunk_metadata.acl.clone(),
+    };
+
+    let mut metadata = ChunkMetadata::from_details_with_id(
         chunk_metadata.id,
         chunk_html,
         &Some(link),
@@ -998,6 +1081,7 @@ pub async fn update_chunk_by_tracking_id(
         update_chunk_data.weight.unwrap_or(1.0),
         None,
     );
+    metadata.acl = chunk_acl;
     let group_ids = if let Some(group_ids) = update_chunk_data.group_ids.clone() {
         Some(
             group_ids
@@ -1229,11 +1313,19 @@ pub async fn search_chunks(
     api_version: APIVersion,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let dataset_config =
+    let mut dataset_config =
         DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration.clone());
 
     let mut data = data.into_inner();
 
+    let experiment = apply_experiment_to_search(
+        &mut data,
+        &mut dataset_config,
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+
     let parsed_query = match data.query.clone() {
         QueryTypes::Single(query) => ParsedQueryTypes::Single(
             parse_query(
@@ -1317,6 +1409,8 @@ pub async fn search_chunks(
     let search_id = uuid::Uuid::new_v4();
 
     if !dataset_config.DISABLE_ANALYTICS {
+        let (experiment_id, experiment_variant) =
+            ExperimentAssignment::event_fields(experiment.as_ref());
         let clickhouse_event = SearchQueryEventClickhouse {
             id: search_id,
             search_type: String::from("search"),
@@ -1342,6 +1436,8 @@ pub async fn search_chunks(
             created_at: time::OffsetDateTime::now_utc(),
             query_rating: String::from(""),
             user_id: data.user_id.clone().unwrap_or_default(),
+            experiment_id,
+            experiment_variant,
         };
 
         event_queue
@@ -1564,6 +1660,8 @@ pub async fn autocomplete(
             created_at: time::OffsetDateTime::now_utc(),
             query_rating: String::from(""),
             user_id: data.user_id.clone().unwrap_or_default(),
+            experiment_id: String::new(),
+            experiment_variant: String::new(),
         };
 
         event_queue
@@ -1739,6 +1837,7 @@ pub async fn get_chunk_by_id(
     pool: web::Data<Pool>,
     api_version: APIVersion,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    acl_principals: AclPrincipals,
 ) -> Result<HttpResponse, ServiceError> {
     let chunk_id = chunk_id.into_inner();
 
@@ -1746,6 +1845,9 @@ pub async fn get_chunk_by_id(
         DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration.clone());
 
     let chunk = get_metadata_from_id_query(chunk_id, dataset_org_plan_sub.dataset.id, pool).await?;
+    if !acl_principals.can_access(&chunk.acl) {
+        return Err(ServiceError::NotFound("Chunk not found".to_string()));
+    }
     let chunk_string_tag_set = ChunkMetadataStringTagSet::from(chunk);
 
     let point_id = chunk_string_tag_set.qdrant_point_id;
@@ -1934,6 +2036,7 @@ pub async fn get_chunk_by_tracking_id(
     pool: web::Data<Pool>,
     api_version: APIVersion,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    acl_principals: AclPrincipals,
 ) -> Result<HttpResponse, ServiceError> {
     let dataset_configuration =
         DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration.clone());
@@ -1944,6 +2047,9 @@ pub async fn get_chunk_by_tracking_id(
         pool,
     )
     .await?;
+    if !acl_principals.can_access(&chunk.acl) {
+        return Err(ServiceError::NotFound("Chunk not found".to_string()));
+    }
     let chunk_tag_set_string = ChunkMetadataStringTagSet::from(chunk);
 
     let point_id = chunk_tag_set_string.qdrant_point_id;
@@ -1993,6 +2099,7 @@ pub async fn get_chunks_by_ids(
     pool: web::Data<Pool>,
     api_version: APIVersion,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    acl_principals: AclPrincipals,
 ) -> Result<HttpResponse, ServiceError> {
     let dataset_configuration =
         DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration.clone());
@@ -2005,6 +2112,7 @@ pub async fn get_chunks_by_ids(
     .await?;
     let chunk_string_tag_sets = chunks
         .into_iter()
+        .filter(|chunk| acl_principals.can_access(&chunk.acl))
         .map(ChunkMetadataStringTagSet::from)
         .collect::<Vec<ChunkMetadataStringTagSet>>();
 
@@ -2064,6 +2172,7 @@ pub async fn get_chunks_by_tracking_ids(
     pool: web::Data<Pool>,
     api_version: APIVersion,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    acl_principals: AclPrincipals,
 ) -> Result<HttpResponse, ServiceError> {
     let dataset_configuration =
         DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration.clone());
@@ -2076,6 +2185,7 @@ pub async fn get_chunks_by_tracking_ids(
     .await?;
     let chunk_string_tag_sets = chunks
         .into_iter()
+        .filter(|chunk| acl_principals.can_access(&chunk.acl))
         .map(ChunkMetadataStringTagSet::from)
         .collect::<Vec<ChunkMetadataStringTagSet>>();
 
@@ -2372,6 +2482,7 @@ pub async fn get_recommended_chunks(
                 chunk: chunk_metadata.into(),
                 highlights: None,
                 score,
+                explain: None,
             }
         })
         .collect::<Vec<ScoreChunk>>();
@@ -2532,6 +2643,7 @@ pub async fn generate_off_chunks(
     >,
     _user: LoggedUser,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    acl_principals: AclPrincipals,
 ) -> Result<HttpResponse, actix_web::Error> {
     let prev_messages = data.prev_messages.clone();
 
@@ -2546,7 +2658,9 @@ pub async fn generate_off_chunks(
     let context_options = data.context_options.clone();
 
     let mut chunks =
-        get_metadata_from_ids_query(chunk_ids, dataset_org_plan_sub.dataset.id, pool).await?;
+        get_metadata_from_ids_query(chunk_ids, dataset_org_plan_sub.dataset.id, pool.clone())
+            .await?;
+    chunks.retain(|chunk| acl_principals.can_access(&chunk.acl));
 
     let dataset_config =
         DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
@@ -2751,6 +2865,8 @@ pub async fn generate_off_chunks(
                     ))
                 })?;
 
+        meter_chat_completion(&assistant_completion);
+
         let completion_content = match assistant_completion.choices.get(0) {
             Some(choice) => match &choice.message {
                 ChatMessage::Assistant {
@@ -2828,6 +2944,11 @@ pub async fn generate_off_chunks(
                 user_id: data.user_id.clone().unwrap_or_default(),
                 hallucination_score: score.total_score,
                 detected_hallucinations: score.detected_hallucinations,
+                guardrail_actions: vec![],
+                guardrail_violations: vec![],
+                rewritten_queries: vec![],
+                experiment_id: String::new(),
+                experiment_variant: String::new(),
             };
 
             event_queue
@@ -2862,9 +2983,23 @@ pub async fn generate_off_chunks(
 
     let last_message_arb = last_prev_message.content.clone();
     let user_id = data.user_id.clone().unwrap_or_default();
+    let usage_meter = UsageMeter::current();
+    let estimated_prompt_tokens = estimate_prompt_tokens(&parameters.messages);
     Arbiter::new().spawn(async move {
         let chunk_v: Vec<String> = r.iter().collect();
         let completion = chunk_v.join("");
+        if let Some(usage_meter) = usage_meter {
+            usage_meter
+                .record(
+                    UsageCounts {
+                        prompt_tokens: estimated_prompt_tokens,
+                        completion_tokens: chunk_v.len() as i64,
+                        ..Default::default()
+                    },
+                    &pool,
+                )
+                .await;
+        }
         if !dataset_config.DISABLE_ANALYTICS {
             #[cfg(feature = "hallucination-detection")]
             let score = {
@@ -2914,6 +3049,11 @@ pub async fn generate_off_chunks(
                 user_id,
                 hallucination_score: score.total_score,
                 detected_hallucinations: score.detected_hallucinations,
+                guardrail_actions: vec![],
+                guardrail_violations: vec![],
+                rewritten_queries: vec![],
+                experiment_id: String::new(),
+                experiment_variant: String::new(),
             };
 
             event_queue
@@ -1,24 +1,28 @@
 use super::auth_handler::{AdminOnly, LoggedUser, OwnerOnly};
 use crate::{
     data::models::{
-        Dataset, DatasetAndOrgWithSubAndPlan, DatasetConfiguration, DatasetConfigurationDTO,
-        DatasetDTO, OrganizationWithSubAndPlan, PagefindIndexWorkerMessage, Pool, RedisPool,
-        StripePlan,
+        AclPrincipals, AuditAction, AuditActor, AuditTargetType, Dataset,
+        DatasetAndOrgWithSubAndPlan, DatasetConfiguration, DatasetConfigurationDTO, DatasetDTO,
+        OrganizationWithSubAndPlan, PagefindIndexWorkerMessage, Pool, RedisPool, StripePlan,
     },
     errors::ServiceError,
     get_env,
     middleware::auth_middleware::{verify_admin, verify_owner},
     operators::{
+        audit_log_operator::log_audit_event,
+        clickhouse_operator::EventQueue,
         dataset_operator::{
             clear_dataset_by_dataset_id_query, create_dataset_query, create_datasets_query,
             get_dataset_by_id_query, get_dataset_by_tracking_id_query, get_dataset_usage_query,
-            get_datasets_by_organization_id, get_tags_in_dataset_query,
-            soft_delete_dataset_by_id_query, update_dataset_query,
+            get_datasets_by_organization_id, get_tags_in_dataset_query, update_dataset_query,
         },
         dittofeed_operator::{
             send_ditto_event, DittoDatasetCreated, DittoTrackProperties, DittoTrackRequest,
         },
+        ingestion_job_operator::prepare_ingestion_webhook_config,
+        model_operator::validate_embedding_fallback_endpoints,
         organization_operator::{get_org_dataset_count, get_org_from_id_query},
+        trash_operator::trash_dataset_query,
     },
 };
 use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
@@ -46,6 +50,22 @@ impl FromRequest for DatasetAndOrgWithSubAndPlan {
     }
 }
 
+impl FromRequest for AclPrincipals {
+    type Error = ServiceError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(
+        req: &actix_web::HttpRequest,
+        _payload: &mut actix_web::dev::Payload,
+    ) -> Self::Future {
+        ready(Ok(req
+            .extensions()
+            .get::<AclPrincipals>()
+            .cloned()
+            .unwrap_or_default()))
+    }
+}
+
 impl FromRequest for OrganizationWithSubAndPlan {
     type Error = ServiceError;
     type Future = Ready<Result<Self, Self::Error>>;
@@ -132,6 +152,8 @@ pub async fn create_dataset(
     pool: web::Data<Pool>,
     org_with_sub_and_plan: OrganizationWithSubAndPlan,
     user: OwnerOnly,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, ServiceError> {
     let org_id = org_with_sub_and_plan.organization.id;
 
@@ -152,18 +174,40 @@ pub async fn create_dataset(
         }
     }
 
+    let mut dataset_config: DatasetConfiguration = data
+        .server_configuration
+        .clone()
+        .map(|c| c.into())
+        .unwrap_or_default();
+
+    prepare_ingestion_webhook_config(&mut dataset_config).await?;
+    dataset_config.GUARDRAILS.validate()?;
+    dataset_config.RATE_LIMITS.validate()?;
+    dataset_config.ENGAGEMENT_RANKING.validate()?;
+    validate_embedding_fallback_endpoints(&dataset_config).await?;
+
     let dataset = Dataset::from_details(
         data.dataset_name.clone(),
         org_id,
         data.tracking_id.clone(),
-        data.server_configuration
-            .clone()
-            .map(|c| c.into())
-            .unwrap_or_default(),
+        dataset_config,
     );
 
     let d = create_dataset_query(dataset.clone(), pool.clone()).await?;
 
+    log_audit_event(
+        &audit_actor,
+        org_id,
+        Some(d.id),
+        AuditAction::DatasetCreated,
+        AuditTargetType::Dataset,
+        d.id.to_string(),
+        None,
+        serde_json::to_value(&d).ok(),
+        &event_queue,
+    )
+    .await;
+
     let dataset_created_event = DittoTrackRequest {
         event: "DATASET_CREATED".to_string(),
         user_id: user.0.id,
@@ -255,6 +299,8 @@ pub async fn update_dataset(
     pool: web::Data<Pool>,
     user: OwnerOnly,
     org_with_plan_and_sub: OrganizationWithSubAndPlan,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, ServiceError> {
     let curr_dataset = if let Some(dataset_id) = data.dataset_id {
         get_dataset_by_id_query(dataset_id, pool.clone()).await?
@@ -275,26 +321,61 @@ pub async fn update_dataset(
         return Err(ServiceError::Forbidden);
     }
 
+    let dataset_before = serde_json::to_value(&curr_dataset).ok();
     let curr_dataset_config = DatasetConfiguration::from_json(curr_dataset.server_configuration);
 
+    let mut new_dataset_config = data
+        .server_configuration
+        .clone()
+        .map(|c| c.from_curr_dataset(curr_dataset_config.clone()))
+        .unwrap_or(curr_dataset_config.clone());
+
+    if new_dataset_config.INGESTION_WEBHOOK_URL != curr_dataset_config.INGESTION_WEBHOOK_URL
+        || new_dataset_config.INGESTION_WEBHOOK_SECRET.is_none()
+    {
+        prepare_ingestion_webhook_config(&mut new_dataset_config).await?;
+    }
+
+    if new_dataset_config.EMBEDDING_FALLBACK_ENDPOINTS
+        != curr_dataset_config.EMBEDDING_FALLBACK_ENDPOINTS
+        || new_dataset_config.EMBEDDING_BASE_URL != curr_dataset_config.EMBEDDING_BASE_URL
+        || new_dataset_config.EMBEDDING_MODEL_NAME != curr_dataset_config.EMBEDDING_MODEL_NAME
+    {
+        validate_embedding_fallback_endpoints(&new_dataset_config).await?;
+    }
+
+    new_dataset_config.GUARDRAILS.validate()?;
+    new_dataset_config.RATE_LIMITS.validate()?;
+    new_dataset_config.ENGAGEMENT_RANKING.validate()?;
+
     let d = update_dataset_query(
         curr_dataset.id,
         data.dataset_name.clone().unwrap_or(curr_dataset.name),
-        data.server_configuration
-            .clone()
-            .map(|c| c.from_curr_dataset(curr_dataset_config.clone()))
-            .unwrap_or(curr_dataset_config),
+        new_dataset_config,
         data.new_tracking_id.clone(),
         pool.clone(),
     )
     .await?;
 
+    log_audit_event(
+        &audit_actor,
+        d.organization_id,
+        Some(d.id),
+        AuditAction::DatasetUpdated,
+        AuditTargetType::Dataset,
+        d.id.to_string(),
+        dataset_before,
+        serde_json::to_value(&d).ok(),
+        &event_queue,
+    )
+    .await;
+
     Ok(HttpResponse::Ok().json(d))
 }
 
 /// Delete Dataset
 ///
-/// Auth'ed user must be an owner of the organization to delete a dataset.
+/// Moves the dataset to the trash, from where it can be restored until the organization's trash retention window passes. Organizations with a retention window of 0 days have the dataset deleted immediately. Auth'ed user must be an owner of the organization to delete a dataset.
 #[utoipa::path(
     delete,
     path = "/dataset/{dataset_id}",
@@ -320,6 +401,8 @@ pub async fn delete_dataset(
     redis_pool: web::Data<RedisPool>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
     user: OwnerOnly,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, ServiceError> {
     if dataset_org_plan_sub.dataset.id != *data {
         return Err(ServiceError::BadRequest(
@@ -331,9 +414,32 @@ pub async fn delete_dataset(
         return Err(ServiceError::Forbidden);
     }
 
-    let config = DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
+    let dataset_before = serde_json::to_value(&dataset_org_plan_sub.dataset).ok();
+
+    trash_dataset_query(
+        dataset_org_plan_sub.dataset.clone(),
+        dataset_org_plan_sub
+            .organization
+            .organization
+            .trash_retention_days,
+        pool,
+        redis_pool,
+    )
+    .await?;
+
+    log_audit_event(
+        &audit_actor,
+        dataset_org_plan_sub.organization.organization.id,
+        Some(dataset_org_plan_sub.dataset.id),
+        AuditAction::DatasetDeleted,
+        AuditTargetType::Dataset,
+        dataset_org_plan_sub.dataset.id.to_string(),
+        dataset_before,
+        None,
+        &event_queue,
+    )
+    .await;
 
-    soft_delete_dataset_by_id_query(data.into_inner(), config, pool, redis_pool).await?;
     Ok(HttpResponse::NoContent().finish())
 }
 
@@ -364,6 +470,8 @@ pub async fn clear_dataset(
     redis_pool: web::Data<RedisPool>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
     user: OwnerOnly,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, ServiceError> {
     if dataset_org_plan_sub.dataset.id != *data {
         return Err(ServiceError::BadRequest(
@@ -378,6 +486,19 @@ pub async fn clear_dataset(
 
     clear_dataset_by_dataset_id_query(data.into_inner(), config, redis_pool).await?;
 
+    log_audit_event(
+        &audit_actor,
+        dataset_org_plan_sub.organization.organization.id,
+        Some(dataset_org_plan_sub.dataset.id),
+        AuditAction::DatasetCleared,
+        AuditTargetType::Dataset,
+        dataset_org_plan_sub.dataset.id.to_string(),
+        None,
+        None,
+        &event_queue,
+    )
+    .await;
+
     Ok(HttpResponse::NoContent().finish())
 }
 
@@ -487,7 +608,7 @@ pub async fn get_pagefind_index_for_dataset(
 
 /// Delete Dataset by Tracking ID
 ///
-/// Auth'ed user must be an owner of the organization to delete a dataset.
+/// Moves the dataset to the trash, from where it can be restored until the organization's trash retention window passes. Organizations with a retention window of 0 days have the dataset deleted immediately. Auth'ed user must be an owner of the organization to delete a dataset.
 #[utoipa::path(
     delete,
     path = "/dataset/tracking_id/{tracking_id}",
@@ -512,6 +633,8 @@ pub async fn delete_dataset_by_tracking_id(
     redis_pool: web::Data<RedisPool>,
     user: OwnerOnly,
     org_with_plan_and_sub: OrganizationWithSubAndPlan,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, ServiceError> {
     let dataset = get_dataset_by_tracking_id_query(
         tracking_id.into_inner(),
@@ -524,9 +647,28 @@ pub async fn delete_dataset_by_tracking_id(
         return Err(ServiceError::Forbidden);
     }
 
-    let config = DatasetConfiguration::from_json(dataset.server_configuration);
+    let dataset_before = serde_json::to_value(&dataset).ok();
+
+    trash_dataset_query(
+        dataset.clone(),
+        org_with_plan_and_sub.organization.trash_retention_days,
+        pool,
+        redis_pool,
+    )
+    .await?;
 
-    soft_delete_dataset_by_id_query(dataset.id, config, pool, redis_pool).await?;
+    log_audit_event(
+        &audit_actor,
+        dataset.organization_id,
+        Some(dataset.id),
+        AuditAction::DatasetDeleted,
+        AuditTargetType::Dataset,
+        dataset.id.to_string(),
+        dataset_before,
+        None,
+        &event_queue,
+    )
+    .await;
 
     Ok(HttpResponse::NoContent().finish())
 }
@@ -820,19 +962,38 @@ pub async fn batch_create_datasets(
     _user: OwnerOnly,
     pool: web::Data<Pool>,
     org_with_sub_and_plan: OrganizationWithSubAndPlan,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, ServiceError> {
-    let datasets = data
+    let mut dataset_configs = data
         .datasets
         .iter()
         .map(|d| {
+            d.server_configuration
+                .clone()
+                .map(|c| c.into())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<DatasetConfiguration>>();
+
+    for dataset_config in dataset_configs.iter_mut() {
+        prepare_ingestion_webhook_config(dataset_config).await?;
+        dataset_config.GUARDRAILS.validate()?;
+        dataset_config.RATE_LIMITS.validate()?;
+        dataset_config.ENGAGEMENT_RANKING.validate()?;
+        validate_embedding_fallback_endpoints(dataset_config).await?;
+    }
+
+    let datasets = data
+        .datasets
+        .iter()
+        .zip(dataset_configs)
+        .map(|(d, dataset_config)| {
             Dataset::from_details(
                 d.dataset_name.clone(),
                 org_with_sub_and_plan.organization.id,
                 d.tracking_id.clone(),
-                d.server_configuration
-                    .clone()
-                    .map(|c| c.into())
-                    .unwrap_or_default(),
+                dataset_config,
             )
         })
         .collect::<Vec<_>>();
@@ -840,5 +1001,25 @@ pub async fn batch_create_datasets(
     let created_or_upserted_datasets =
         create_datasets_query(datasets, data.upsert, pool.clone()).await?;
 
+    let audit_action = if data.upsert.unwrap_or(false) {
+        AuditAction::DatasetUpdated
+    } else {
+        AuditAction::DatasetCreated
+    };
+    for dataset in created_or_upserted_datasets.iter() {
+        log_audit_event(
+            &audit_actor,
+            dataset.organization_id,
+            Some(dataset.id),
+            audit_action,
+            AuditTargetType::Dataset,
+            dataset.id.to_string(),
+            None,
+            serde_json::to_value(dataset).ok(),
+            &event_queue,
+        )
+        .await;
+    }
+
     Ok(HttpResponse::Ok().json(created_or_upserted_datasets))
 }
@@ -0,0 +1,298 @@
+use super::auth_handler::AdminOnly;
+use crate::{
+    data::models::{
+        DatasetAndOrgWithSubAndPlan, DatasetWebhook, Pool, WebhookDeliveryStatus, WebhookEventType,
+    },
+    errors::ServiceError,
+    operators::dataset_webhook_operator::{
+        create_dataset_webhook_query, delete_dataset_webhook_query, generate_webhook_secret,
+        get_dataset_webhook_query, get_dataset_webhooks_query, get_webhook_deliveries_query,
+        redeliver_webhook_delivery_query, update_dataset_webhook_query, validate_webhook_url,
+    },
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct CreateDatasetWebhookReqPayload {
+    /// The url deliveries are POSTed to. Must use http or https and resolve to a public address. Redirects are not followed.
+    pub url: String,
+    /// The event types to deliver to the webhook. Must not be empty.
+    pub event_types: Vec<WebhookEventType>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct CreateDatasetWebhookResponse {
+    pub webhook: DatasetWebhook,
+    /// Secret the deliveries are signed with. Store it now, it is not returned again. Each delivery carries a `X-Trieve-Signature: t=<timestamp>,v1=<signature>` header where the signature is the hex encoded HMAC-SHA256 of `<timestamp>.<body>` keyed with this secret.
+    pub secret: String,
+}
+
+/// Create Dataset Webhook
+///
+/// Subscribe a url to events of the dataset. Deliveries are signed with the secret returned in the response and retried with exponential backoff until the url responds with a 2xx status. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/dataset_webhook",
+    context_path = "/api",
+    tag = "Dataset Webhook",
+    request_body(content = CreateDatasetWebhookReqPayload, description = "JSON request payload to create a webhook", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The created webhook and its signing secret", body = CreateDatasetWebhookResponse),
+        (status = 400, description = "Service error relating to creating the webhook", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn create_dataset_webhook(
+    data: web::Json<CreateDatasetWebhookReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let data = data.into_inner();
+
+    validate_webhook_url(&data.url).await?;
+    if data.event_types.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "Webhook must subscribe to at least one event type".to_string(),
+        ));
+    }
+
+    let secret = generate_webhook_secret();
+    let webhook = create_dataset_webhook_query(
+        DatasetWebhook::from_details(
+            dataset_org_plan_sub.dataset.id,
+            data.url,
+            secret.clone(),
+            data.event_types,
+        ),
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(CreateDatasetWebhookResponse { webhook, secret }))
+}
+
+/// Get Dataset Webhooks
+///
+/// Get all of the webhooks subscribed to events of the dataset. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/dataset_webhook",
+    context_path = "/api",
+    tag = "Dataset Webhook",
+    responses(
+        (status = 200, description = "All webhooks of the dataset", body = Vec<DatasetWebhook>),
+        (status = 400, description = "Service error relating to getting the webhooks", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_dataset_webhooks(
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let webhooks = get_dataset_webhooks_query(dataset_org_plan_sub.dataset.id, &pool).await?;
+
+    Ok(HttpResponse::Ok().json(webhooks))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct UpdateDatasetWebhookReqPayload {
+    /// The id of the webhook to update.
+    pub webhook_id: uuid::Uuid,
+    /// The new url of the webhook. If not provided, the url will not be updated.
+    pub url: Option<String>,
+    /// The new event types of the webhook. If not provided, the event types will not be updated.
+    pub event_types: Option<Vec<WebhookEventType>>,
+    /// Set to false to pause deliveries. If not provided, the webhook keeps its current state.
+    pub active: Option<bool>,
+}
+
+/// Update Dataset Webhook
+///
+/// Change the url or event types of a webhook, or pause and resume its deliveries. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    put,
+    path = "/dataset_webhook",
+    context_path = "/api",
+    tag = "Dataset Webhook",
+    request_body(content = UpdateDatasetWebhookReqPayload, description = "JSON request payload to update a webhook", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The updated webhook", body = DatasetWebhook),
+        (status = 400, description = "Service error relating to updating the webhook", body = ErrorResponseBody),
+        (status = 404, description = "Webhook not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn update_dataset_webhook(
+    data: web::Json<UpdateDatasetWebhookReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let data = data.into_inner();
+    let mut webhook =
+        get_dataset_webhook_query(data.webhook_id, dataset_org_plan_sub.dataset.id, &pool).await?;
+
+    if let Some(url) = data.url {
+        validate_webhook_url(&url).await?;
+        webhook.url = url;
+    }
+    if let Some(event_types) = data.event_types {
+        if event_types.is_empty() {
+            return Err(ServiceError::BadRequest(
+                "Webhook must subscribe to at least one event type".to_string(),
+            ));
+        }
+        webhook.event_types = event_types
+            .iter()
+            .map(|event_type| event_type.to_string())
+            .collect();
+    }
+    if let Some(active) = data.active {
+        webhook.active = active;
+    }
+
+    let webhook = update_dataset_webhook_query(webhook, &pool).await?;
+
+    Ok(HttpResponse::Ok().json(webhook))
+}
+
+/// Delete Dataset Webhook
+///
+/// Delete a webhook along with its delivery log. Pending deliveries are dropped. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    delete,
+    path = "/dataset_webhook/{webhook_id}",
+    context_path = "/api",
+    tag = "Dataset Webhook",
+    responses(
+        (status = 204, description = "Confirmation that the webhook was deleted"),
+        (status = 400, description = "Service error relating to deleting the webhook", body = ErrorResponseBody),
+        (status = 404, description = "Webhook not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("webhook_id" = uuid::Uuid, Path, description = "The id of the webhook to delete."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn delete_dataset_webhook(
+    webhook_id: web::Path<uuid::Uuid>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    delete_dataset_webhook_query(
+        webhook_id.into_inner(),
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct GetWebhookDeliveriesReqPayload {
+    /// Only return deliveries with this status. Leave undefined to get all deliveries.
+    pub status: Option<WebhookDeliveryStatus>,
+    /// The page number to get. Default is 1.
+    pub page: Option<u64>,
+}
+
+/// Get Webhook Deliveries
+///
+/// Get the delivery log of a webhook, newest first. Each delivery records its payload, the number of attempts made, and the response status or error of the last attempt. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/dataset_webhook/{webhook_id}/deliveries",
+    context_path = "/api",
+    tag = "Dataset Webhook",
+    responses(
+        (status = 200, description = "Deliveries of the webhook", body = WebhookDeliveriesResponse),
+        (status = 400, description = "Service error relating to getting the deliveries", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("webhook_id" = uuid::Uuid, Path, description = "The id of the webhook to get deliveries for."),
+        GetWebhookDeliveriesReqPayload,
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_webhook_deliveries(
+    webhook_id: web::Path<uuid::Uuid>,
+    params: web::Query<GetWebhookDeliveriesReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let deliveries = get_webhook_deliveries_query(
+        webhook_id.into_inner(),
+        dataset_org_plan_sub.dataset.id,
+        params.status,
+        params.page.unwrap_or(1),
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+/// Redeliver Webhook Delivery
+///
+/// Queue a delivery to be sent again with a fresh set of retries. Use this to replay failed deliveries once the receiving endpoint is fixed. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/dataset_webhook/delivery/{delivery_id}/redeliver",
+    context_path = "/api",
+    tag = "Dataset Webhook",
+    responses(
+        (status = 200, description = "The queued delivery", body = DatasetWebhookDelivery),
+        (status = 404, description = "Webhook delivery not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("delivery_id" = uuid::Uuid, Path, description = "The id of the delivery to redeliver."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn redeliver_webhook_delivery(
+    delivery_id: web::Path<uuid::Uuid>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let delivery = redeliver_webhook_delivery_query(
+        delivery_id.into_inner(),
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(delivery))
+}
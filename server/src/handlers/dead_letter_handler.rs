@@ -0,0 +1,157 @@
+use actix_web::{web, HttpResponse};
+use broccoli_queue::queue::BroccoliQueue;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    data::models::{DatasetAndOrgWithSubAndPlan, DeadLetterQueue, RedisPool},
+    errors::ServiceError,
+    operators::dead_letter_operator::{
+        delete_dead_letter_query, get_dead_letter_query, get_dead_letters_query,
+        replay_dead_letter_query,
+    },
+};
+
+use super::auth_handler::AdminOnly;
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct GetDeadLettersReqPayload {
+    /// Only return dead letters from this queue. Leave undefined to get dead letters from all queues.
+    pub queue: Option<DeadLetterQueue>,
+    /// The page number to get. Default is 1.
+    pub page: Option<u64>,
+    /// The number of items per page. Default is 10.
+    pub page_size: Option<u64>,
+}
+
+/// Get Dead Letters
+///
+/// Get the worker messages for the dataset which failed on every retry attempt, newest first. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/dead_letter",
+    context_path = "/api",
+    tag = "Dead Letter",
+    responses(
+        (status = 200, description = "Dead letters for the dataset", body = DeadLettersResponse),
+        (status = 400, description = "Service error relating to getting the dead letters", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("queue" = Option<DeadLetterQueue>, Query, description = "Only return dead letters from this queue"),
+        ("page" = Option<u64>, Query, description = "The page number to get. Default is 1"),
+        ("page_size" = Option<u64>, Query, description = "The number of items per page. Default is 10"),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_dead_letters(
+    params: web::Query<GetDeadLettersReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<HttpResponse, ServiceError> {
+    let dead_letters = get_dead_letters_query(
+        dataset_org_plan_sub.dataset.id,
+        params.queue,
+        params.page.unwrap_or(1),
+        params.page_size.unwrap_or(10),
+        &redis_pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(dead_letters))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct ReplayDeadLetterReqPayload {
+    /// Edited message to replay instead of the stored payload. It must be a valid message for the dead letter's queue and belong to the same dataset.
+    pub payload: Option<serde_json::Value>,
+}
+
+/// Replay Dead Letter
+///
+/// Push a dead letter back onto its queue with its attempt number reset and remove it from the dead letters. Provide `payload` to replay an edited message. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/dead_letter/{dead_letter_id}/replay",
+    context_path = "/api",
+    tag = "Dead Letter",
+    request_body(content = ReplayDeadLetterReqPayload, description = "JSON request payload to replay a dead letter", content_type = "application/json"),
+    responses(
+        (status = 204, description = "Confirmation that the dead letter was replayed"),
+        (status = 400, description = "Service error relating to replaying the dead letter", body = ErrorResponseBody),
+        (status = 404, description = "Dead letter not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("dead_letter_id" = uuid::Uuid, Path, description = "The id of the dead letter to replay."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn replay_dead_letter(
+    dead_letter_id: web::Path<uuid::Uuid>,
+    data: web::Json<ReplayDeadLetterReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    redis_pool: web::Data<RedisPool>,
+    broccoli_queue: web::Data<BroccoliQueue>,
+) -> Result<HttpResponse, ServiceError> {
+    let dead_letter = get_dead_letter_query(
+        dataset_org_plan_sub.dataset.id,
+        dead_letter_id.into_inner(),
+        &redis_pool,
+    )
+    .await?;
+
+    replay_dead_letter_query(
+        dead_letter,
+        data.into_inner().payload,
+        &redis_pool,
+        &broccoli_queue,
+    )
+    .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Discard Dead Letter
+///
+/// Remove a dead letter without replaying it. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    delete,
+    path = "/dead_letter/{dead_letter_id}",
+    context_path = "/api",
+    tag = "Dead Letter",
+    responses(
+        (status = 204, description = "Confirmation that the dead letter was discarded"),
+        (status = 404, description = "Dead letter not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("dead_letter_id" = uuid::Uuid, Path, description = "The id of the dead letter to discard."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn delete_dead_letter(
+    dead_letter_id: web::Path<uuid::Uuid>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<HttpResponse, ServiceError> {
+    let dead_letter = get_dead_letter_query(
+        dataset_org_plan_sub.dataset.id,
+        dead_letter_id.into_inner(),
+        &redis_pool,
+    )
+    .await?;
+
+    delete_dead_letter_query(&dead_letter, &redis_pool).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -2,7 +2,7 @@ use super::auth_handler::LoggedUser;
 use crate::{
     data::models::{DatasetAndOrgWithSubAndPlan, EventType, EventTypeRequest},
     errors::ServiceError,
-    operators::event_operator::get_events_query,
+    operators::analytics_store_operator::{AnalyticsBackend, AnalyticsStore},
 };
 use actix_web::{web, HttpResponse};
 use serde::{Deserialize, Serialize};
@@ -47,7 +47,7 @@ pub async fn get_events(
     _user: LoggedUser,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
     get_events_data: web::Json<GetEventsData>,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    analytics_backend: web::Data<AnalyticsBackend>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let event_types = get_events_data
         .event_types
@@ -61,15 +61,15 @@ pub async fn get_events(
         })
         .unwrap_or(EventType::get_all_event_types());
 
-    let events = get_events_query(
-        dataset_org_plan_sub.dataset.id,
-        get_events_data.page.unwrap_or(1),
-        get_events_data.page_size.unwrap_or(10),
-        event_types,
-        clickhouse_client.clone(),
-    )
-    .await
-    .map_err(|e| ServiceError::BadRequest(e.to_string()))?;
+    let events = analytics_backend
+        .get_worker_events(
+            dataset_org_plan_sub.dataset.id,
+            get_events_data.page.unwrap_or(1),
+            get_events_data.page_size.unwrap_or(10),
+            event_types,
+        )
+        .await
+        .map_err(|e| ServiceError::BadRequest(e.to_string()))?;
 
     Ok(HttpResponse::Ok().json(events))
 }
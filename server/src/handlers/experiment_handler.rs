@@ -0,0 +1,279 @@
+use super::auth_handler::AdminOnly;
+use crate::{
+    data::models::{DatasetAndOrgWithSubAndPlan, Experiment, ExperimentVariant, Pool},
+    errors::ServiceError,
+    operators::{
+        analytics_store_operator::AnalyticsBackend,
+        experiment_operator::{
+            create_experiment_query, delete_experiment_query, get_experiment_by_id_query,
+            get_experiment_results_query, get_experiments_for_dataset_query,
+            update_experiment_query, validate_experiment_variants,
+        },
+    },
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[schema(example = json!({
+    "name": "Cross encoder reranking",
+    "variants": [
+        {"name": "control", "weight": 1.0},
+        {"name": "cross_encoder", "weight": 1.0, "search_overrides": {"sort_options": {"sort_by": {"rerank_type": "cross_encoder"}}}}
+    ]
+}))]
+pub struct CreateExperimentReqPayload {
+    /// Name of the experiment.
+    pub name: String,
+    /// The variants to compare. At least two are required and their names must be unique.
+    pub variants: Vec<ExperimentVariant>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[schema(example = json!({
+    "active": false
+}))]
+pub struct UpdateExperimentReqPayload {
+    /// New name of the experiment.
+    pub name: Option<String>,
+    /// New variants of the experiment. Changing the variants of a running experiment re-buckets its users.
+    pub variants: Option<Vec<ExperimentVariant>>,
+    /// Set to false to stop the experiment or true to resume it. Only one experiment can be active per dataset.
+    pub active: Option<bool>,
+}
+
+/// Create Experiment
+///
+/// Start an A/B test on the dataset. Requests with a `user_id` are deterministically bucketed into one of the experiment's variants, whose overrides are applied to their searches and RAG completions, and the variant is recorded on their search and RAG analytics events. A dataset can have one active experiment at a time. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/experiments",
+    context_path = "/api",
+    tag = "Experiment",
+    request_body(content = CreateExperimentReqPayload, description = "JSON request payload to create an experiment", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The created experiment", body = Experiment),
+        (status = 400, description = "Service error relating to creating the experiment", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn create_experiment(
+    data: web::Json<CreateExperimentReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let data = data.into_inner();
+    validate_experiment_variants(&data.variants)?;
+
+    let experiment = create_experiment_query(
+        Experiment::from_details(dataset_org_plan_sub.dataset.id, data.name, data.variants),
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(experiment))
+}
+
+/// Get Experiments
+///
+/// Get all experiments of the dataset, most recent first. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/experiments",
+    context_path = "/api",
+    tag = "Experiment",
+    responses(
+        (status = 200, description = "Experiments of the dataset", body = Vec<Experiment>),
+        (status = 400, description = "Service error relating to getting the experiments", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_experiments(
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let experiments =
+        get_experiments_for_dataset_query(dataset_org_plan_sub.dataset.id, &pool).await?;
+
+    Ok(HttpResponse::Ok().json(experiments))
+}
+
+/// Get Experiment
+///
+/// Get an experiment of the dataset by its id. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/experiments/{experiment_id}",
+    context_path = "/api",
+    tag = "Experiment",
+    responses(
+        (status = 200, description = "The experiment", body = Experiment),
+        (status = 404, description = "Experiment not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("experiment_id" = uuid::Uuid, Path, description = "The id of the experiment to get."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_experiment(
+    experiment_id: web::Path<uuid::Uuid>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let experiment = get_experiment_by_id_query(
+        experiment_id.into_inner(),
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(experiment))
+}
+
+/// Update Experiment
+///
+/// Rename an experiment, change its variants, or stop and resume it. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    put,
+    path = "/experiments/{experiment_id}",
+    context_path = "/api",
+    tag = "Experiment",
+    request_body(content = UpdateExperimentReqPayload, description = "JSON request payload to update an experiment", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The updated experiment", body = Experiment),
+        (status = 400, description = "Service error relating to updating the experiment", body = ErrorResponseBody),
+        (status = 404, description = "Experiment not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("experiment_id" = uuid::Uuid, Path, description = "The id of the experiment to update."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn update_experiment(
+    experiment_id: web::Path<uuid::Uuid>,
+    data: web::Json<UpdateExperimentReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let data = data.into_inner();
+    let mut experiment = get_experiment_by_id_query(
+        experiment_id.into_inner(),
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+
+    if let Some(name) = data.name {
+        experiment.name = name;
+    }
+    if let Some(variants) = data.variants {
+        validate_experiment_variants(&variants)?;
+        experiment.variants = serde_json::to_value(variants).map_err(|_| {
+            ServiceError::BadRequest("Failed to serialize experiment variants".to_string())
+        })?;
+    }
+    if let Some(active) = data.active {
+        experiment.active = active;
+    }
+
+    let experiment = update_experiment_query(experiment, &pool).await?;
+
+    Ok(HttpResponse::Ok().json(experiment))
+}
+
+/// Delete Experiment
+///
+/// Delete an experiment. Events already recorded for its variants are kept in analytics. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    delete,
+    path = "/experiments/{experiment_id}",
+    context_path = "/api",
+    tag = "Experiment",
+    responses(
+        (status = 204, description = "Confirmation that the experiment was deleted"),
+        (status = 404, description = "Experiment not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("experiment_id" = uuid::Uuid, Path, description = "The id of the experiment to delete."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn delete_experiment(
+    experiment_id: web::Path<uuid::Uuid>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    delete_experiment_query(
+        experiment_id.into_inner(),
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Get Experiment Results
+///
+/// Compare the variants of an experiment. For each variant the click-through rate, the conversion rate and the average search and RAG ratings are reported with 95% confidence intervals; variants whose intervals do not overlap differ significantly. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/experiments/{experiment_id}/results",
+    context_path = "/api",
+    tag = "Experiment",
+    responses(
+        (status = 200, description = "Metrics of each variant of the experiment", body = ExperimentResultsResponse),
+        (status = 404, description = "Experiment not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("experiment_id" = uuid::Uuid, Path, description = "The id of the experiment to get the results of."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_experiment_results(
+    experiment_id: web::Path<uuid::Uuid>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    analytics_backend: web::Data<AnalyticsBackend>,
+) -> Result<HttpResponse, ServiceError> {
+    let clickhouse_client = analytics_backend.clickhouse_client()?;
+    let experiment = get_experiment_by_id_query(
+        experiment_id.into_inner(),
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+
+    let results = get_experiment_results_query(experiment, clickhouse_client).await?;
+
+    Ok(HttpResponse::Ok().json(results))
+}
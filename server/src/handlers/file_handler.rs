@@ -5,17 +5,21 @@ use super::{
 use crate::{
     data::models::{
         ChunkReqPayloadMappings, CsvJsonlWorkerMessage, DatasetAndOrgWithSubAndPlan,
-        DatasetConfiguration, File, FileAndGroupId, FileWorkerMessage, Pool, RedisPool,
+        DatasetConfiguration, File, FileAndGroupId, FileWorkerMessage, IngestionJob,
+        IngestionJobSource, Pool, RedisPool,
     },
     errors::ServiceError,
     middleware::auth_middleware::verify_member,
     operators::{
         crawl_operator::{process_crawl_doc, Document},
         file_operator::{
-            create_file_query, delete_file_query, get_aws_bucket, get_csvjsonl_aws_bucket,
-            get_dataset_file_query, get_file_query,
+            create_file_query, delete_file_query, get_aws_bucket, get_csv_jsonl_error_file_query,
+            get_csvjsonl_aws_bucket, get_dataset_file_query, get_file_query,
         },
+        ingestion_job_operator::create_ingestion_job_query,
         organization_operator::{get_file_size_sum_org, hash_function},
+        trash_operator::trash_file_query,
+        usage_operator::{current_usage_api_key_id, meter_usage},
     },
 };
 use actix_web::{web, HttpResponse};
@@ -104,6 +108,8 @@ pub struct Pdf2MdOptions {
 pub struct UploadFileResponseBody {
     /// File object information. Id, name, tag_set, etc.
     pub file_metadata: File,
+    /// Id of the ingestion job tracking the chunks created from the file. Use it with the get ingestion job route to check on their progress.
+    pub ingestion_job_id: uuid::Uuid,
 }
 
 /// Upload File
@@ -212,6 +218,7 @@ pub async fn upload_file_handler(
     }
 
     let file_size_mb = (decoded_file_data.len() as f64 / 1024.0 / 1024.0).round() as i64;
+    meter_usage(|usage| usage.storage_bytes += decoded_file_data.len() as i64);
 
     create_file_query(
         file_id,
@@ -222,11 +229,27 @@ pub async fn upload_file_handler(
     )
     .await?;
 
+    let dataset_config =
+        DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration.clone());
+
+    let ingestion_job = create_ingestion_job_query(
+        IngestionJob::from_details(
+            dataset_org_plan_sub.dataset.id,
+            IngestionJobSource::File,
+            false,
+            dataset_config.INGESTION_WEBHOOK_URL,
+        ),
+        pool.clone(),
+    )
+    .await?;
+
     let message = FileWorkerMessage {
         file_id,
         dataset_id: dataset_org_plan_sub.dataset.id,
         upload_file_data: upload_file_data.clone(),
         attempt_number: 0,
+        ingestion_job_id: Some(ingestion_job.id),
+        api_key_id: current_usage_api_key_id(),
     };
 
     broccoli_queue
@@ -255,6 +278,7 @@ pub async fn upload_file_handler(
             upload_file_data.time_stamp.clone(),
             dataset_org_plan_sub.dataset.id,
         ),
+        ingestion_job_id: ingestion_job.id,
     };
 
     Ok(HttpResponse::Ok().json(result))
@@ -410,7 +434,7 @@ pub async fn get_file_handler(
     },
 }))]
 pub struct CreatePresignedUrlForCsvJsonlReqPayload {
-    /// Name of the file being uploaded, including the extension. Will be used to determine CSV or JSONL for processing.
+    /// Name of the file being uploaded, including the extension. Will be used to determine the format for processing. Files ending in `.parquet` are read as Parquet, files ending in `.gz` or `.zst` are decompressed while streaming, and all other files are read as CSV or JSONL.
     pub file_name: String,
     /// Tag set is a comma separated list of tags which will be passed down to the chunks made from the file. Each tag will be joined with what's creatd per row of the CSV or JSONL file.
     pub tag_set: Option<Vec<String>>,
@@ -424,8 +448,10 @@ pub struct CreatePresignedUrlForCsvJsonlReqPayload {
     pub metadata: Option<serde_json::Value>,
     /// Group tracking id is an optional field which allows you to specify the tracking id of the group that is created from the file. Chunks created will be created with the tracking id of `group_tracking_id|<index of chunk>`
     pub group_tracking_id: Option<String>,
-    /// Specify all of the mappings between columns or fields in a CSV or JSONL file and keys in the ChunkReqPayload. Array fields like tag_set and image_urls can have multiple mappings. Boost phrase can also have multiple mappings which get concatenated. Other fields can only have one mapping and only the last mapping will be used.
+    /// Specify all of the mappings between columns or fields in a CSV or JSONL file and keys in the ChunkReqPayload. Array fields like tag_set and image_urls can have multiple mappings. Boost phrase, chunk_html, and semantic_content can also have multiple mappings which get concatenated. Use `metadata.<path>` to build nested metadata; when any metadata path is mapped, only mapped paths end up in the chunk's metadata instead of the whole row. Other fields can only have one mapping and only the last mapping will be used.
     pub mappings: Option<ChunkReqPayloadMappings>,
+    /// Template used to build the chunk_html of each row. Use `{{field}}` to insert the value of a column or field, e.g. `<h1>{{title}}</h1><p>{{description}}</p>`. Takes precedence over chunk_html mappings. If neither are specified, chunk_html is the JSON of the row.
+    pub chunk_html_template: Option<String>,
     /// Upsert by tracking_id. If true, chunks will be upserted by tracking_id. If false, chunks with the same tracking_id as another already existing chunk will be ignored. Defaults to true.
     pub upsert_by_tracking_id: Option<bool>,
     /// Amount to multiplicatevly increase the frequency of the tokens in the boost phrase for each row's chunk by. Applies to fulltext (SPLADE) and keyword (BM25) search.
@@ -440,11 +466,13 @@ pub struct CreatePresignedUrlForCsvJsonResponseBody {
     pub file_metadata: File,
     /// Signed URL to upload the file to.
     pub presigned_put_url: String,
+    /// Id of the ingestion job tracking the chunks created from the file. Use it with the get ingestion job route to check on their progress.
+    pub ingestion_job_id: uuid::Uuid,
 }
 
 /// Create Presigned CSV/JSONL S3 PUT URL
 ///
-/// This route is useful for uploading very large CSV or JSONL files. Once you have completed the upload, chunks will be automatically created from the file for each line in the CSV or JSONL file. The chunks will be indexed and searchable. Auth'ed user must be an admin or owner of the dataset's organization to upload a file.
+/// This route is useful for uploading very large CSV, JSONL, or Parquet files. Gzip and zstd compressed CSV and JSONL files are also supported. Once you have completed the upload, chunks will be automatically created from the file for each line in the CSV or JSONL file or each row in the Parquet file. The chunks will be indexed and searchable. Auth'ed user must be an admin or owner of the dataset's organization to upload a file.
 #[utoipa::path(
     post,
     path = "/file/csv_or_jsonl",
@@ -466,6 +494,7 @@ pub async fn create_presigned_url_for_csv_jsonl(
     data: web::Json<CreatePresignedUrlForCsvJsonlReqPayload>,
     _user: AdminOnly,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
     redis_pool: web::Data<RedisPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut redis_conn = redis_pool
@@ -486,12 +515,27 @@ pub async fn create_presigned_url_for_csv_jsonl(
             ServiceError::BadRequest("Could not get presigned put url".to_string())
         })?;
 
+    let dataset_config =
+        DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration.clone());
+
+    let ingestion_job = create_ingestion_job_query(
+        IngestionJob::from_details(
+            dataset_org_plan_sub.dataset.id,
+            IngestionJobSource::CsvJsonl,
+            false,
+            dataset_config.INGESTION_WEBHOOK_URL,
+        ),
+        pool.clone(),
+    )
+    .await?;
+
     let message = CsvJsonlWorkerMessage {
         file_id,
         dataset_id: dataset_org_plan_sub.dataset.id,
         create_presigned_put_url_data: create_presigned_put_url_data.clone(),
         created_at: chrono::Utc::now().naive_utc(),
         attempt_number: 0,
+        ingestion_job_id: Some(ingestion_job.id),
     };
 
     let serialized_message = serde_json::to_string(&message).map_err(|e| {
@@ -520,11 +564,51 @@ pub async fn create_presigned_url_for_csv_jsonl(
             dataset_org_plan_sub.dataset.id,
         ),
         presigned_put_url,
+        ingestion_job_id: ingestion_job.id,
     };
 
     Ok(HttpResponse::Ok().json(result))
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct CsvJsonlErrorFileResponseBody {
+    /// Signed URL to download the error file. Each line of the file is a JSON object with the `row` number, the `error`, and the `value` of the row which was skipped.
+    pub signed_url: String,
+}
+
+/// Get CSV/JSONL Row Errors
+///
+/// Get a signed URL to download the rows of a CSV or JSONL file which failed validation and were skipped during processing. Returns a 404 if every row was processed. Auth'ed user must be an admin or owner of the dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/file/csv_or_jsonl/{file_id}/errors",
+    context_path = "/api",
+    tag = "File",
+    responses(
+        (status = 200, description = "Signed URL to download the error file", body = CsvJsonlErrorFileResponseBody),
+        (status = 404, description = "The file was not found or had no row errors", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("file_id" = uuid::Uuid, description = "The id of the CSV or JSONL file returned when creating its presigned URL."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_csv_jsonl_error_file(
+    file_id: web::Path<uuid::Uuid>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let signed_url =
+        get_csv_jsonl_error_file_query(file_id.into_inner(), dataset_org_plan_sub.dataset.id, pool)
+            .await?;
+
+    Ok(HttpResponse::Ok().json(CsvJsonlErrorFileResponseBody { signed_url }))
+}
+
 #[derive(Deserialize, Debug, Serialize, ToSchema)]
 pub struct DatasetFileQuery {
     pub dataset_id: uuid::Uuid,
@@ -595,7 +679,7 @@ pub async fn get_dataset_files_handler(
 }
 /// Delete File
 ///
-/// Delete a file from S3 attached to the server based on its id. This will disassociate chunks from the file, but only delete them all together if you specify delete_chunks to be true. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+/// Delete a file from S3 attached to the server based on its id. This will disassociate chunks from the file, but only delete them all together if you specify delete_chunks to be true. If the organization has a trash retention window, the file and any chunks being deleted with it are moved to the trash instead and can be restored until the window passes. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
 #[utoipa::path(
     delete,
     path = "/file/{file_id}",
@@ -621,11 +705,31 @@ pub async fn delete_file_handler(
     _user: AdminOnly,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let file_id = file_id.into_inner();
+    let trash_retention_days = dataset_org_plan_sub
+        .organization
+        .organization
+        .trash_retention_days;
+
+    if trash_retention_days > 0 {
+        trash_file_query(
+            file_id,
+            dataset_org_plan_sub.dataset.id,
+            dataset_org_plan_sub.organization.organization.id,
+            query.delete_chunks.unwrap_or(false),
+            trash_retention_days,
+            pool,
+        )
+        .await?;
+
+        return Ok(HttpResponse::NoContent().finish());
+    }
+
     let dataset_config =
         DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration.clone());
 
     delete_file_query(
-        file_id.into_inner(),
+        file_id,
         query.delete_chunks,
         dataset_org_plan_sub.dataset,
         pool,
@@ -5,9 +5,9 @@ use super::{
 use crate::operators::chunk_operator::get_metadata_from_tracking_ids_query;
 use crate::{
     data::models::{
-        escape_quotes, ChunkGroup, ChunkGroupAndFileId, ChunkGroupBookmark, ChunkMetadata,
-        ChunkMetadataStringTagSet, DatasetAndOrgWithSubAndPlan, DatasetConfiguration,
-        HighlightOptions, MultiQuery, Pool, QueryTypes, RecommendType,
+        escape_quotes, AclPrincipals, ChunkGroup, ChunkGroupAndFileId, ChunkGroupBookmark,
+        ChunkMetadata, ChunkMetadataStringTagSet, DatasetAndOrgWithSubAndPlan,
+        DatasetConfiguration, HighlightOptions, MultiQuery, Pool, QueryTypes, RecommendType,
         RecommendationEventClickhouse, RecommendationStrategy, RedisPool, ScoreChunk,
         ScoreChunkDTO, SearchMethod, SearchQueryEventClickhouse, SortOptions, TypoOptions,
         UnifiedId,
@@ -15,6 +15,7 @@ use crate::{
     errors::ServiceError,
     middleware::api_version::APIVersion,
     operators::{
+        acl_operator::validate_acl,
         chunk_operator::get_metadata_from_tracking_id_query,
         clickhouse_operator::{get_latency_from_header, ClickHouseEvent, EventQueue},
         group_operator::*,
@@ -27,6 +28,7 @@ use crate::{
             search_hybrid_groups, search_over_groups_query, GroupScoreChunk, ParsedQuery,
             ParsedQueryTypes, SearchOverGroupsQueryResult, SearchOverGroupsResults,
         },
+        trash_operator::trash_group_query,
     },
 };
 use actix_web::{web, HttpResponse};
@@ -81,6 +83,8 @@ pub struct CreateSingleChunkGroupReqPayload {
     pub tag_set: Option<Vec<String>>,
     /// Upsert when a chunk_group with the same tracking_id exists. By default this is false, and the request will fail if a chunk_group with the same tracking_id exists. If this is true, the chunk_group will be updated if a chunk_group with the same tracking_id exists.
     pub upsert_by_tracking_id: Option<bool>,
+    /// Principals which are allowed to see the chunk_group when access control is enabled for the dataset. Each entry is either `user:<user_id>` or `group:<group_name>`. The chunks inside of the chunk_group are governed by their own acl.
+    pub acl: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
@@ -233,16 +237,26 @@ pub async fn create_chunk_group(
                     .collect::<Vec<Option<String>>>()
             });
 
-            ChunkGroup::from_details(
-                payload.name.clone(),
-                payload.description.clone(),
-                dataset_org_plan_sub.dataset.id,
-                payload.tracking_id.clone(),
-                payload.metadata.clone(),
-                group_tag_set,
-            )
+            if let Some(acl) = payload.acl.as_ref() {
+                validate_acl(acl)?;
+            }
+
+            Ok(ChunkGroup {
+                acl: payload
+                    .acl
+                    .clone()
+                    .map(|acl| acl.into_iter().map(Some).collect()),
+                ..ChunkGroup::from_details(
+                    payload.name.clone(),
+                    payload.description.clone(),
+                    dataset_org_plan_sub.dataset.id,
+                    payload.tracking_id.clone(),
+                    payload.metadata.clone(),
+                    group_tag_set,
+                )
+            })
         })
-        .collect::<Vec<ChunkGroup>>();
+        .collect::<Result<Vec<ChunkGroup>, ServiceError>>()?;
 
     let non_upsert_groups = non_upsert_payloads
         .into_iter()
@@ -254,16 +268,26 @@ pub async fn create_chunk_group(
                     .collect::<Vec<Option<String>>>()
             });
 
-            ChunkGroup::from_details(
-                payload.name.clone(),
-                payload.description.clone(),
-                dataset_org_plan_sub.dataset.id,
-                payload.tracking_id.clone(),
-                payload.metadata.clone(),
-                group_tag_set,
-            )
+            if let Some(acl) = payload.acl.as_ref() {
+                validate_acl(acl)?;
+            }
+
+            Ok(ChunkGroup {
+                acl: payload
+                    .acl
+                    .clone()
+                    .map(|acl| acl.into_iter().map(Some).collect()),
+                ..ChunkGroup::from_details(
+                    payload.name.clone(),
+                    payload.description.clone(),
+                    dataset_org_plan_sub.dataset.id,
+                    payload.tracking_id.clone(),
+                    payload.metadata.clone(),
+                    group_tag_set,
+                )
+            })
         })
-        .collect::<Vec<ChunkGroup>>();
+        .collect::<Result<Vec<ChunkGroup>, ServiceError>>()?;
 
     let (upsert_results, non_upsert_results) = futures::future::join(
         create_groups_query(upsert_groups, true, pool.clone()),
@@ -370,6 +394,7 @@ pub async fn get_group_by_tracking_id(
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
     _user: LoggedUser,
     pool: web::Data<Pool>,
+    acl_principals: AclPrincipals,
 ) -> Result<HttpResponse, actix_web::Error> {
     let group = get_group_from_tracking_id_query(
         data.tracking_id.clone(),
@@ -377,6 +402,9 @@ pub async fn get_group_by_tracking_id(
         pool.clone(),
     )
     .await?;
+    if !acl_principals.can_access(&group.acl) {
+        return Err(ServiceError::NotFound("Group not found".to_string()).into());
+    }
 
     Ok(HttpResponse::Ok().json(group))
 }
@@ -413,6 +441,7 @@ pub async fn get_chunk_group(
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
     _user: LoggedUser,
     pool: web::Data<Pool>,
+    acl_principals: AclPrincipals,
 ) -> Result<HttpResponse, actix_web::Error> {
     let group = get_group_by_id_query(
         group_id.into_inner(),
@@ -420,6 +449,9 @@ pub async fn get_chunk_group(
         pool.clone(),
     )
     .await?;
+    if !acl_principals.can_access(&group.acl) {
+        return Err(ServiceError::NotFound("Group not found".to_string()).into());
+    }
 
     Ok(HttpResponse::Ok().json(group))
 }
@@ -559,14 +591,17 @@ pub async fn update_group_by_tracking_id(
             .collect::<Vec<Option<String>>>()
     });
 
-    let new_group = ChunkGroup::from_details(
-        data.name.clone(),
-        data.description.clone(),
-        dataset_org_plan_sub.dataset.id,
-        Some(data.tracking_id.clone()),
-        data.metadata.clone().or(group.metadata.clone()),
-        group_tag_set,
-    );
+    let new_group = ChunkGroup {
+        acl: group.acl.clone(),
+        ..ChunkGroup::from_details(
+            data.name.clone(),
+            data.description.clone(),
+            dataset_org_plan_sub.dataset.id,
+            Some(data.tracking_id.clone()),
+            data.metadata.clone().or(group.metadata.clone()),
+            group_tag_set,
+        )
+    };
 
     update_chunk_group_query(new_group, pool).await?;
 
@@ -580,7 +615,7 @@ pub struct DeleteGroupByTrackingIDData {
 
 /// Delete Group by Tracking ID
 ///
-/// Delete a chunk_group with the given tracking id. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+/// Delete a chunk_group with the given tracking id. If the organization has a trash retention window, the group and any chunks being deleted with it are moved to the trash instead and can be restored until the window passes. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
 #[utoipa::path(
     delete,
     path = "/chunk_group/tracking_id/{tracking_id}",
@@ -618,6 +653,24 @@ pub async fn delete_group_by_tracking_id(
     )
     .await?;
 
+    let trash_retention_days = dataset_org_plan_sub
+        .organization
+        .organization
+        .trash_retention_days;
+
+    if trash_retention_days > 0 {
+        trash_group_query(
+            group,
+            dataset_org_plan_sub.organization.organization.id,
+            data.delete_chunks.unwrap_or(false),
+            trash_retention_days,
+            delete_group_pool,
+        )
+        .await?;
+
+        return Ok(HttpResponse::NoContent().finish());
+    }
+
     let deleted_at = chrono::Utc::now().naive_utc();
 
     delete_group_by_id_query(
@@ -640,7 +693,7 @@ pub struct DeleteGroupData {
 
 /// Delete Group
 ///
-/// This will delete a chunk_group. If you set delete_chunks to true, it will also delete the chunks within the group. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+/// This will delete a chunk_group. If you set delete_chunks to true, it will also delete the chunks within the group. If the organization has a trash retention window, the group and any chunks being deleted with it are moved to the trash instead and can be restored until the window passes. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
 #[utoipa::path(
     delete,
     path = "/chunk_group/{group_id}",
@@ -672,13 +725,31 @@ pub async fn delete_chunk_group(
 
     let group_id = group_id.into_inner();
 
-    dataset_owns_group(
+    let group = dataset_owns_group(
         UnifiedId::TrieveUuid(group_id),
         dataset_org_plan_sub.dataset.id,
         pool.clone(),
     )
     .await?;
 
+    let trash_retention_days = dataset_org_plan_sub
+        .organization
+        .organization
+        .trash_retention_days;
+
+    if trash_retention_days > 0 {
+        trash_group_query(
+            group,
+            dataset_org_plan_sub.organization.organization.id,
+            data.delete_chunks.unwrap_or(false),
+            trash_retention_days,
+            delete_group_pool,
+        )
+        .await?;
+
+        return Ok(HttpResponse::NoContent().finish());
+    }
+
     delete_group_by_id_query(
         group_id,
         dataset_org_plan_sub.dataset,
@@ -709,6 +780,8 @@ pub struct UpdateChunkGroupReqPayload {
     /// Flag to update the chunks in the group. If true, each chunk in the group will be updated
     /// by appending the group's tags to the chunk's tags. Default is false.
     pub update_chunks: Option<bool>,
+    /// Principals which are allowed to see the chunk_group when access control is enabled for the dataset. Each entry is either `user:<user_id>` or `group:<group_name>`. If not provided, the acl will not be updated. Set it to an empty array to remove the acl.
+    pub acl: Option<Vec<String>>,
 }
 
 /// Update Group
@@ -768,15 +841,26 @@ pub async fn update_chunk_group(
         return Err(ServiceError::BadRequest("No group id or tracking id provided".into()).into());
     };
 
-    let new_chunk_group = ChunkGroup::from_details_with_id(
-        group.id,
-        name.unwrap_or(group.name.clone()),
-        description.or(Some(group.description.clone())),
-        dataset_org_plan_sub.dataset.id,
-        data.tracking_id.clone(),
-        data.metadata.clone(),
-        group_tag_set.or(group.tag_set.clone()),
-    );
+    let group_acl = match data.acl.clone() {
+        Some(acl) => {
+            validate_acl(&acl)?;
+            Some(acl.into_iter().map(Some).collect::<Vec<Option<String>>>())
+        }
+        None => group.acl.clone(),
+    };
+
+    let new_chunk_group = ChunkGroup {
+        acl: group_acl,
+        ..ChunkGroup::from_details_with_id(
+            group.id,
+            name.unwrap_or(group.name.clone()),
+            description.or(Some(group.description.clone())),
+            dataset_org_plan_sub.dataset.id,
+            data.tracking_id.clone(),
+            data.metadata.clone(),
+            group_tag_set.or(group.tag_set.clone()),
+        )
+    };
 
     update_chunk_group_query(new_chunk_group.clone(), pool).await?;
 
@@ -982,6 +1066,7 @@ pub async fn get_chunks_in_group(
     _user: LoggedUser,
     api_version: APIVersion,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    acl_principals: AclPrincipals,
 ) -> Result<HttpResponse, actix_web::Error> {
     let page = group_data.page.unwrap_or(1);
     let limit = group_data.limit.unwrap_or(10);
@@ -992,6 +1077,7 @@ pub async fn get_chunks_in_group(
         page,
         Some(limit),
         dataset_id,
+        &acl_principals,
         pool.clone(),
     )
     .await?;
@@ -1039,6 +1125,7 @@ pub async fn get_chunks_in_group_by_tracking_id(
     _user: LoggedUser,
     api_version: APIVersion,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    acl_principals: AclPrincipals,
 ) -> Result<HttpResponse, actix_web::Error> {
     let page = path_data.page.unwrap_or(1);
     let dataset_id = dataset_org_plan_sub.dataset.id;
@@ -1049,6 +1136,7 @@ pub async fn get_chunks_in_group_by_tracking_id(
             page,
             None,
             dataset_id,
+            &acl_principals,
             pool.clone(),
         )
         .await?
@@ -1256,6 +1344,7 @@ pub async fn get_recommended_groups(
     api_version: APIVersion,
     _user: LoggedUser,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    acl_principals: AclPrincipals,
 ) -> Result<HttpResponse, actix_web::Error> {
     let positive_group_ids = data.positive_group_ids.clone();
     let negative_group_ids = data.negative_group_ids.clone();
@@ -1404,6 +1493,7 @@ pub async fn get_recommended_groups(
                 .find(|metadata| metadata.group_id == group.group_id)
                 .cloned()
         })
+        .filter(|group| acl_principals.can_access(&group.group_acl))
         .collect::<Vec<GroupScoreChunk>>();
 
     timer.add("fetched metadata from ids");
@@ -1723,6 +1813,8 @@ pub async fn search_within_group(
             created_at: time::OffsetDateTime::now_utc(),
             query_rating: String::from(""),
             user_id: data.user_id.clone().unwrap_or_default(),
+            experiment_id: String::new(),
+            experiment_variant: String::new(),
         };
 
         event_queue
@@ -1811,6 +1903,7 @@ pub async fn search_over_groups(
     api_version: APIVersion,
     _required_user: LoggedUser,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    acl_principals: AclPrincipals,
 ) -> Result<HttpResponse, actix_web::Error> {
     let dataset_config =
         DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration.clone());
@@ -1866,7 +1959,7 @@ pub async fn search_over_groups(
 
     let mut timer = Timer::new();
 
-    let result_chunks = match data.search_type {
+    let mut result_chunks = match data.search_type {
         SearchMethod::Hybrid => {
             hybrid_search_over_groups(
                 data.clone(),
@@ -1892,6 +1985,9 @@ pub async fn search_over_groups(
             .await?
         }
     };
+    result_chunks
+        .group_chunks
+        .retain(|group_chunk| acl_principals.can_access(&group_chunk.group_acl));
     timer.add("search_chunks");
 
     let search_id = uuid::Uuid::new_v4();
@@ -1922,6 +2018,8 @@ pub async fn search_over_groups(
             created_at: time::OffsetDateTime::now_utc(),
             query_rating: String::from(""),
             user_id: data.user_id.clone().unwrap_or_default(),
+            experiment_id: String::new(),
+            experiment_variant: String::new(),
         };
 
         event_queue
@@ -0,0 +1,64 @@
+use actix_web::{web, HttpResponse};
+
+use crate::{
+    data::models::{DatasetAndOrgWithSubAndPlan, Pool},
+    errors::ServiceError,
+    operators::{
+        analytics_store_operator::AnalyticsBackend,
+        ingestion_job_operator::{
+            get_failed_chunks_for_ingestion_job_query, get_ingestion_job_status_query,
+        },
+    },
+};
+
+use super::auth_handler::LoggedUser;
+
+/// Get Ingestion Job
+///
+/// Get the status of an ingestion job. Ingestion job ids are returned by bulk create chunk requests and the upload file and CSV/JSONL presigned url routes. Single chunk creates are not tracked by an ingestion job. Counts are reported per chunk and failed chunks are listed with the error from the ingestion worker when analytics are enabled. Auth'ed user or api key must be a member of the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/ingestion_job/{ingestion_job_id}",
+    context_path = "/api",
+    tag = "Ingestion Job",
+    responses(
+        (status = 200, description = "The status of the ingestion job", body = IngestionJobStatus),
+        (status = 404, description = "Ingestion job not found", body = ErrorResponseBody),
+        (status = 400, description = "Service error relating to getting the ingestion job", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("ingestion_job_id" = uuid::Uuid, Path, description = "The id of the ingestion job to get."),
+    ),
+    security(
+        ("ApiKey" = ["readonly"]),
+    )
+)]
+pub async fn get_ingestion_job(
+    ingestion_job_id: web::Path<uuid::Uuid>,
+    _user: LoggedUser,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    analytics_backend: web::Data<AnalyticsBackend>,
+) -> Result<HttpResponse, ServiceError> {
+    let ingestion_job_id = ingestion_job_id.into_inner();
+
+    let mut status =
+        get_ingestion_job_status_query(ingestion_job_id, dataset_org_plan_sub.dataset.id, pool)
+            .await?;
+
+    if status.failed > 0 {
+        status.failed_chunks = get_failed_chunks_for_ingestion_job_query(
+            ingestion_job_id,
+            dataset_org_plan_sub.dataset.id,
+            analytics_backend.get_ref(),
+        )
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("Failed to get failed chunks for ingestion job: {:?}", err);
+            vec![]
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(status))
+}
@@ -1,9 +1,14 @@
 use super::auth_handler::AdminOnly;
 use crate::{
-    data::models::{Invitation, OrganizationWithSubAndPlan, Pool, RedisPool, Templates},
+    data::models::{
+        AuditAction, AuditActor, AuditTargetType, Invitation, OrganizationWithSubAndPlan, Pool,
+        RedisPool, Templates,
+    },
     errors::ServiceError,
     middleware::auth_middleware::verify_admin,
     operators::{
+        audit_log_operator::log_audit_event,
+        clickhouse_operator::EventQueue,
         invitation_operator::{
             create_invitation_query, delete_invitation_by_id_query, get_invitation_by_id_query,
             get_invitations_for_organization_query, send_invitation,
@@ -66,6 +71,8 @@ pub async fn post_invitation(
     org_with_plan_and_sub: OrganizationWithSubAndPlan,
     templates: Templates<'_>,
     user: AdminOnly,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, ServiceError> {
     let invitation_data = invitation_data.into_inner();
     let email = invitation_data.email.trim().to_string();
@@ -117,6 +124,19 @@ pub async fn post_invitation(
     .await?;
 
     if added_user_to_org {
+        log_audit_event(
+            &audit_actor,
+            existing_user_org_id,
+            None,
+            AuditAction::InvitationCreated,
+            AuditTargetType::User,
+            email.clone(),
+            None,
+            Some(serde_json::json!({ "email": email, "role": existing_user_role })),
+            &event_queue,
+        )
+        .await;
+
         send_invitation_for_existing_user(
             email.clone(),
             org_with_plan_and_sub.organization.name,
@@ -151,6 +171,22 @@ pub async fn post_invitation(
     )
     .await?;
 
+    log_audit_event(
+        &audit_actor,
+        existing_user_org_id,
+        None,
+        AuditAction::InvitationCreated,
+        AuditTargetType::Invitation,
+        invitation.invitation.id.to_string(),
+        None,
+        Some(serde_json::json!({
+            "email": invitation.invitation.email,
+            "role": invitation.invitation.role,
+        })),
+        &event_queue,
+    )
+    .await;
+
     send_invitation(
         invitation.registration_url,
         invitation.invitation,
@@ -246,6 +282,8 @@ pub async fn delete_invitation(
     user: AdminOnly,
     invitation_id: web::Path<uuid::Uuid>,
     pool: web::Data<Pool>,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, ServiceError> {
     let invite_id = invitation_id.into_inner();
     let invite = get_invitation_by_id_query(invite_id, pool.clone()).await?;
@@ -255,5 +293,18 @@ pub async fn delete_invitation(
     }
 
     delete_invitation_by_id_query(invite_id, pool).await?;
+
+    log_audit_event(
+        &audit_actor,
+        invite.organization_id,
+        None,
+        AuditAction::InvitationDeleted,
+        AuditTargetType::Invitation,
+        invite_id.to_string(),
+        Some(serde_json::json!({ "email": invite.email, "role": invite.role })),
+        None,
+        &event_queue,
+    )
+    .await;
     Ok(HttpResponse::NoContent().finish())
 }
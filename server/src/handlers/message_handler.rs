@@ -6,13 +6,15 @@ use crate::{
     data::models::{
         self, ChunkMetadata, ChunkMetadataStringTagSet, ChunkMetadataTypes, ContextOptions,
         DatasetAndOrgWithSubAndPlan, DatasetConfiguration, HighlightOptions, LLMOptions, Pool,
-        QdrantChunkMetadata, RedisPool, SearchMethod, SortOptions, SuggestType,
+        QdrantChunkMetadata, QueryRewriteOptions, RedisPool, SearchMethod, SortOptions,
+        SuggestType,
     },
     errors::ServiceError,
     get_env,
     operators::{
         chunk_operator::{get_chunk_metadatas_from_point_ids, get_random_chunk_metadatas_query},
         clickhouse_operator::EventQueue,
+        experiment_operator::apply_experiment_to_message,
         message_operator::{
             create_topic_message_query, delete_message_query, get_message_by_id_query,
             get_message_by_sort_for_topic_query, get_messages_for_topic_query, get_text_from_audio,
@@ -21,6 +23,7 @@ use crate::{
         organization_operator::get_message_org_count,
         parse_operator::convert_html_to_text,
         qdrant_operator::scroll_dataset_points,
+        rag_preset_operator::apply_rag_preset_to_message,
         search_operator::{
             assemble_qdrant_filter, search_chunks_query, search_hybrid_chunks, ParsedQuery,
             ParsedQueryTypes,
@@ -124,6 +127,14 @@ pub struct CreateMessageReqPayload {
     pub no_result_message: Option<String>,
     /// Only include docs used in the completion. If not specified, this defaults to false.
     pub only_include_docs_used: Option<bool>,
+    /// The name of a RAG preset configured on the dataset to use for this completion. Values set on the request take precedence over the preset. If not specified, the preset attached to the topic is used, if any.
+    pub rag_preset: Option<String>,
+    /// If set to true, 3 to 5 follow-up questions grounded in the answer and the retrieved chunks are generated after the completion and stored in the `follow_up_questions` of the assistant message. Unless `stream_follow_up_questions` is set, the response body is unchanged; get the message by the id in the TR-QueryID header once the completion has finished to read them. Default is false.
+    pub suggest_follow_up_questions: Option<bool>,
+    /// If set to true along with `suggest_follow_up_questions`, the questions are also sent as a final `||{"follow_up_questions": [...]}` event at the end of a streamed response. Clients which split the stream on `||` must expect the extra part. Default is false.
+    pub stream_follow_up_questions: Option<bool>,
+    /// Query rewriting options for retrieval, such as rewriting follow-ups into standalone questions and multi-query expansion. Rewritten queries are recorded on the RAG analytics event. If not specified, the query is used as-is.
+    pub query_rewrite: Option<QueryRewriteOptions>,
 }
 
 /// Create message
@@ -186,11 +197,25 @@ pub async fn create_message(
             "message": "To create more message completions, you must upgrade your plan" })));
     }
 
-    let create_message_data = data.into_inner();
+    let mut create_message_data = data.into_inner();
     let get_messages_pool = pool.clone();
     let create_message_pool = pool.clone();
     let stream_response_pool = pool.clone();
     let topic_id = create_message_data.topic_id;
+    apply_rag_preset_to_message(
+        &mut create_message_data,
+        &mut dataset_config,
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+    let experiment = apply_experiment_to_message(
+        &mut create_message_data,
+        &mut dataset_config,
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
     if let Some(llm_options) = &create_message_data.llm_options {
         if let Some(data_system_prompt) = &llm_options.system_prompt {
             dataset_config.SYSTEM_PROMPT.clone_from(data_system_prompt);
@@ -276,6 +301,7 @@ pub async fn create_message(
         redis_pool,
         dataset_config,
         create_message_data,
+        experiment,
         #[cfg(feature = "hallucination-detection")]
         hallucination_detector,
     )
@@ -399,6 +425,14 @@ pub struct RegenerateMessageReqPayload {
     pub no_result_message: Option<String>,
     /// Only include docs used in the completion. If not specified, this defaults to false.
     pub only_include_docs_used: Option<bool>,
+    /// The name of a RAG preset configured on the dataset to use for this completion. Values set on the request take precedence over the preset. If not specified, the preset attached to the topic is used, if any.
+    pub rag_preset: Option<String>,
+    /// If set to true, 3 to 5 follow-up questions grounded in the answer and the retrieved chunks are generated after the completion and stored in the `follow_up_questions` of the assistant message. Unless `stream_follow_up_questions` is set, the response body is unchanged; get the message by the id in the TR-QueryID header once the completion has finished to read them. Default is false.
+    pub suggest_follow_up_questions: Option<bool>,
+    /// If set to true along with `suggest_follow_up_questions`, the questions are also sent as a final `||{"follow_up_questions": [...]}` event at the end of a streamed response. Clients which split the stream on `||` must expect the extra part. Default is false.
+    pub stream_follow_up_questions: Option<bool>,
+    /// Query rewriting options for retrieval, such as rewriting follow-ups into standalone questions and multi-query expansion. Rewritten queries are recorded on the RAG analytics event. If not specified, the query is used as-is.
+    pub query_rewrite: Option<QueryRewriteOptions>,
 }
 
 #[derive(Serialize, Debug, ToSchema)]
@@ -441,6 +475,14 @@ pub struct EditMessageReqPayload {
     pub no_result_message: Option<String>,
     /// Only include docs used in the completion. If not specified, this defaults to false.
     pub only_include_docs_used: Option<bool>,
+    /// The name of a RAG preset configured on the dataset to use for this completion. Values set on the request take precedence over the preset. If not specified, the preset attached to the topic is used, if any.
+    pub rag_preset: Option<String>,
+    /// If set to true, 3 to 5 follow-up questions grounded in the answer and the retrieved chunks are generated after the completion and stored in the `follow_up_questions` of the assistant message. Unless `stream_follow_up_questions` is set, the response body is unchanged; get the message by the id in the TR-QueryID header once the completion has finished to read them. Default is false.
+    pub suggest_follow_up_questions: Option<bool>,
+    /// If set to true along with `suggest_follow_up_questions`, the questions are also sent as a final `||{"follow_up_questions": [...]}` event at the end of a streamed response. Clients which split the stream on `||` must expect the extra part. Default is false.
+    pub stream_follow_up_questions: Option<bool>,
+    /// Query rewriting options for retrieval, such as rewriting follow-ups into standalone questions and multi-query expansion. Rewritten queries are recorded on the RAG analytics event. If not specified, the query is used as-is.
+    pub query_rewrite: Option<QueryRewriteOptions>,
 }
 
 impl From<EditMessageReqPayload> for CreateMessageReqPayload {
@@ -464,6 +506,10 @@ impl From<EditMessageReqPayload> for CreateMessageReqPayload {
             context_options: data.context_options,
             no_result_message: data.no_result_message,
             only_include_docs_used: data.only_include_docs_used,
+            rag_preset: data.rag_preset,
+            suggest_follow_up_questions: data.suggest_follow_up_questions,
+            stream_follow_up_questions: data.stream_follow_up_questions,
+            query_rewrite: data.query_rewrite,
         }
     }
 }
@@ -489,6 +535,10 @@ impl From<RegenerateMessageReqPayload> for CreateMessageReqPayload {
             context_options: data.context_options,
             no_result_message: data.no_result_message,
             only_include_docs_used: data.only_include_docs_used,
+            rag_preset: data.rag_preset,
+            suggest_follow_up_questions: data.suggest_follow_up_questions,
+            stream_follow_up_questions: data.stream_follow_up_questions,
+            query_rewrite: data.query_rewrite,
         }
     }
 }
@@ -607,11 +657,27 @@ pub async fn regenerate_message_patch(
     >,
 ) -> Result<HttpResponse, actix_web::Error> {
     let topic_id = data.topic_id;
-    let dataset_config =
+    let mut dataset_config =
         DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration.clone());
 
     check_completion_param_validity(data.llm_options.clone())?;
 
+    let mut create_message_data: CreateMessageReqPayload = data.into_inner().into();
+    apply_rag_preset_to_message(
+        &mut create_message_data,
+        &mut dataset_config,
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+    let experiment = apply_experiment_to_message(
+        &mut create_message_data,
+        &mut dataset_config,
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+
     let get_messages_pool = pool.clone();
     let create_message_pool = pool.clone();
     let dataset_id = dataset_org_plan_sub.dataset.id;
@@ -634,7 +700,8 @@ pub async fn regenerate_message_patch(
             event_queue,
             redis_pool.clone(),
             dataset_config,
-            data.into_inner().into(),
+            create_message_data,
+            experiment,
             #[cfg(feature = "hallucination-detection")]
             hallucination_detector,
         )
@@ -709,7 +776,8 @@ pub async fn regenerate_message_patch(
         event_queue,
         redis_pool.clone(),
         dataset_config,
-        data.into_inner().into(),
+        create_message_data,
+        experiment,
         #[cfg(feature = "hallucination-detection")]
         hallucination_detector,
     )
@@ -1,6 +1,42 @@
-use crate::{data::models::RedisPool, errors::ServiceError};
+use crate::{
+    data::models::RedisPool,
+    errors::ServiceError,
+    operators::{
+        dead_letter_operator::get_dead_letter_depths_query,
+        fallback_operator::get_endpoint_health,
+        worker_metrics_operator::{
+            get_worker_metrics_query, get_worker_queue_depths_query, WORKER_DURATION_BUCKETS,
+        },
+    },
+};
 use actix_web::{web, HttpResponse};
-use prometheus::{opts, register_counter_vec, CounterVec, Encoder, Error, Gauge, Registry};
+use prometheus::{
+    histogram_opts, opts,
+    proto::{Bucket, LabelPair, Metric, MetricFamily, MetricType},
+    register_counter_vec, CounterVec, Encoder, Error, Gauge, GaugeVec, HistogramVec, Registry,
+};
+use std::sync::{Arc, Mutex};
+
+/// Upper bounds in seconds of the search stage duration histogram.
+const SEARCH_STAGE_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Maps the `Server-Timing` checkpoints of the search routes to the stage which ended at them.
+fn search_stage_from_timing(name: &str) -> Option<&'static str> {
+    match name {
+        "start correcting query" => Some("query_parse"),
+        "corrected query" => Some("typo_correction"),
+        "computed query vector"
+        | "computed dense embedding"
+        | "computed sparse and dense embeddings" => Some("embedding"),
+        "fetched from qdrant" | "fetching from qdrant" => Some("qdrant"),
+        "fetched from postgres" | "fetched metadata from postgres" => Some("postgres_hydration"),
+        "reranking" => Some("reranking"),
+        "highlight chunks" => Some("highlighting"),
+        _ => None,
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Metrics {
@@ -16,6 +52,13 @@ pub struct Metrics {
     pub pgbulk_queue_gauge: Gauge,
     pub pgbulk_processing_gauge: Gauge,
     pub api_error_gauge: CounterVec,
+    pub endpoint_circuit_state_gauge: GaugeVec,
+    pub endpoint_failures_gauge: GaugeVec,
+    pub dead_letter_gauge: GaugeVec,
+    pub search_stage_histogram: HistogramVec,
+    pub worker_queue_depth_gauge: GaugeVec,
+    /// Job counters and duration histograms reported by the workers through redis, refreshed on every scrape.
+    worker_metric_families: Arc<Mutex<Vec<MetricFamily>>>,
 }
 
 impl Metrics {
@@ -74,6 +117,52 @@ impl Metrics {
         )?;
         registry.register(Box::new(api_error_gauge.clone()))?;
 
+        let endpoint_circuit_state_gauge = GaugeVec::new(
+            opts!(
+                "tr_endpoint_circuit_state",
+                "circuit breaker state of llm, embedding and reranker endpoints (0 closed, 1 half open, 2 open)"
+            ),
+            &["endpoint_type", "base_url"],
+        )?;
+        registry.register(Box::new(endpoint_circuit_state_gauge.clone()))?;
+
+        let endpoint_failures_gauge = GaugeVec::new(
+            opts!(
+                "tr_endpoint_consecutive_failures",
+                "number of consecutive failed requests to llm, embedding and reranker endpoints"
+            ),
+            &["endpoint_type", "base_url"],
+        )?;
+        registry.register(Box::new(endpoint_failures_gauge.clone()))?;
+
+        let dead_letter_gauge = GaugeVec::new(
+            opts!(
+                "tr_dead_letters",
+                "number of worker messages which failed on every retry attempt"
+            ),
+            &["queue"],
+        )?;
+        registry.register(Box::new(dead_letter_gauge.clone()))?;
+
+        let search_stage_histogram = HistogramVec::new(
+            histogram_opts!(
+                "tr_search_stage_duration_seconds",
+                "time spent in each stage of the search pipeline",
+                SEARCH_STAGE_BUCKETS.to_vec()
+            ),
+            &["stage", "route"],
+        )?;
+        registry.register(Box::new(search_stage_histogram.clone()))?;
+
+        let worker_queue_depth_gauge = GaugeVec::new(
+            opts!(
+                "tr_worker_queue_depth",
+                "number of messages waiting in each worker queue"
+            ),
+            &["queue"],
+        )?;
+        registry.register(Box::new(worker_queue_depth_gauge.clone()))?;
+
         Ok(Metrics {
             registry,
             ingest_queue_gauge,
@@ -87,6 +176,12 @@ impl Metrics {
             ingest_processing_gauge,
             group_update_processing_gauge,
             api_error_gauge,
+            endpoint_circuit_state_gauge,
+            endpoint_failures_gauge,
+            dead_letter_gauge,
+            search_stage_histogram,
+            worker_queue_depth_gauge,
+            worker_metric_families: Arc::new(Mutex::new(vec![])),
         })
     }
 
@@ -155,13 +250,118 @@ impl Metrics {
         self.pgbulk_queue_gauge.set(pg_bulk_queue as f64);
         self.pgbulk_processing_gauge.set(pg_bulk_processing as f64);
 
+        for (queue, depth) in get_dead_letter_depths_query(&redis_pool).await? {
+            self.dead_letter_gauge
+                .with_label_values(&[queue.as_str()])
+                .set(depth as f64);
+        }
+
+        Ok(())
+    }
+
+    /// Records the duration of each search stage from the `Server-Timing` header of a response.
+    pub fn observe_search_stages(&self, server_timing: &str, route: &str) {
+        for timing in server_timing.split(", ") {
+            let Some((name, duration)) = timing.split_once(";dur=") else {
+                continue;
+            };
+            let (Some(stage), Ok(duration_ms)) =
+                (search_stage_from_timing(name), duration.parse::<f64>())
+            else {
+                continue;
+            };
+
+            self.search_stage_histogram
+                .with_label_values(&[stage, route])
+                .observe(duration_ms / 1000.0);
+        }
+    }
+
+    pub async fn update_worker_metrics(&self, redis_pool: &RedisPool) -> Result<(), ServiceError> {
+        for (queue, depth) in get_worker_queue_depths_query(redis_pool).await? {
+            self.worker_queue_depth_gauge
+                .with_label_values(&[queue.as_str()])
+                .set(depth as f64);
+        }
+
+        let worker_metrics = get_worker_metrics_query(redis_pool).await?;
+
+        let mut jobs_family = MetricFamily::default();
+        jobs_family.set_name("tr_worker_jobs_total".to_string());
+        jobs_family.set_help(
+            "number of jobs processed by each worker and cronjob, by dataset and outcome"
+                .to_string(),
+        );
+        jobs_family.set_field_type(MetricType::COUNTER);
+        for (worker, dataset, outcome, count) in worker_metrics.jobs {
+            let mut metric = Metric::default();
+            for (name, value) in [
+                ("worker", worker),
+                ("dataset_id", dataset),
+                ("outcome", outcome),
+            ] {
+                let mut label = LabelPair::default();
+                label.set_name(name.to_string());
+                label.set_value(value);
+                metric.mut_label().push(label);
+            }
+            metric.mut_counter().set_value(count as f64);
+            jobs_family.mut_metric().push(metric);
+        }
+
+        let mut durations_family = MetricFamily::default();
+        durations_family.set_name("tr_worker_job_duration_seconds".to_string());
+        durations_family
+            .set_help("time taken by each worker and cronjob to process a job".to_string());
+        durations_family.set_field_type(MetricType::HISTOGRAM);
+        for (worker, durations) in worker_metrics.durations {
+            let mut metric = Metric::default();
+            let mut label = LabelPair::default();
+            label.set_name("worker".to_string());
+            label.set_value(worker);
+            metric.mut_label().push(label);
+
+            let histogram = metric.mut_histogram();
+            histogram.set_sample_count(durations.count);
+            histogram.set_sample_sum(durations.sum);
+            for (upper_bound, cumulative_count) in
+                WORKER_DURATION_BUCKETS.iter().zip(durations.buckets)
+            {
+                let mut bucket = Bucket::default();
+                bucket.set_upper_bound(*upper_bound);
+                bucket.set_cumulative_count(cumulative_count);
+                histogram.mut_bucket().push(bucket);
+            }
+            durations_family.mut_metric().push(metric);
+        }
+
+        *self.worker_metric_families.lock().unwrap() = [jobs_family, durations_family]
+            .into_iter()
+            .filter(|family| !family.get_metric().is_empty())
+            .collect();
+
         Ok(())
     }
 
+    pub fn update_endpoint_health_gauges(&self) {
+        for endpoint in get_endpoint_health() {
+            let endpoint_type = endpoint.kind.to_string();
+            let labels = [endpoint_type.as_str(), endpoint.base_url.as_str()];
+
+            self.endpoint_circuit_state_gauge
+                .with_label_values(&labels)
+                .set(endpoint.state.as_gauge_value());
+            self.endpoint_failures_gauge
+                .with_label_values(&labels)
+                .set(endpoint.consecutive_failures as f64);
+        }
+    }
+
     pub fn get_response(&self) -> String {
         let mut buffer = vec![];
         let encoder = prometheus::TextEncoder::new();
-        let metric_families = self.registry.gather();
+        let mut metric_families = self.registry.gather();
+        metric_families.extend(self.worker_metric_families.lock().unwrap().iter().cloned());
         encoder.encode(&metric_families, &mut buffer).unwrap();
         String::from_utf8(buffer).unwrap()
     }
@@ -188,7 +388,7 @@ fn check_x_api_access(req: &actix_web::HttpRequest) -> bool {
 
 /// Get Prometheus Metrics
 ///
-/// This route allows you to view the number of items in each queue in the Prometheus format.
+/// This route allows you to view the number of items in each queue, the number of dead letters per queue, the health of the LLM, embedding and reranker endpoints, the duration of each search stage and the job counts and durations of the workers in the Prometheus format.
 #[utoipa::path(
     post,
     path = "/metrics",
@@ -211,7 +411,9 @@ pub async fn get_metrics(
         return Ok(HttpResponse::Unauthorized().finish());
     }
 
+    let _ = metrics.update_worker_metrics(&redis_pool).await;
     let _ = metrics.update_queue_gauges(redis_pool).await;
+    metrics.update_endpoint_health_gauges();
     let response = metrics.get_response();
     Ok(HttpResponse::Ok().content_type("text/plain").body(response))
 }
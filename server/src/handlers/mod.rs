@@ -1,18 +1,30 @@
+pub mod analytics_export_handler;
 pub mod analytics_handler;
+pub mod analytics_report_handler;
+pub mod audit_log_handler;
 pub mod auth_handler;
 pub mod chunk_handler;
 pub mod crawl_handler;
 pub mod dataset_handler;
+pub mod dataset_webhook_handler;
+pub mod dead_letter_handler;
 pub mod etl_handler;
 pub mod event_handler;
+pub mod experiment_handler;
 pub mod file_handler;
 pub mod group_handler;
+pub mod ingestion_job_handler;
 pub mod invitation_handler;
 pub mod message_handler;
 pub mod metrics_handler;
 pub mod organization_handler;
 pub mod page_handler;
+pub mod query_suggestion_handler;
+pub mod rag_preset_handler;
+pub mod search_token_handler;
 pub mod stripe_handler;
 pub mod topic_handler;
+pub mod trash_handler;
+pub mod usage_handler;
 pub mod user_handler;
 pub mod webhook_handler;
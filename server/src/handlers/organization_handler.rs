@@ -1,18 +1,21 @@
 use super::auth_handler::{AdminOnly, LoggedUser, OwnerOnly};
 use crate::{
     data::models::{
-        ApiKeyRequestParams, OrganizationWithSubAndPlan, Pool, RedisPool, UserOrganization,
-        UserRole,
+        ApiKeyRequestParams, AuditAction, AuditActor, AuditTargetType, OrganizationWithSubAndPlan,
+        Pool, RedisPool, UserOrganization, UserRole,
     },
     errors::ServiceError,
     middleware::auth_middleware::{get_role_for_org, verify_admin, verify_owner},
     operators::{
+        audit_log_operator::log_audit_event,
+        clickhouse_operator::EventQueue,
         organization_operator::{
             create_organization_api_key_query, create_organization_query,
             delete_organization_api_keys_query, delete_organization_query, get_org_from_id_query,
             get_org_usage_by_id_query, get_org_users_by_id_query, get_organization_api_keys_query,
             update_all_org_dataset_configs_query, update_organization_query,
         },
+        trash_operator::MAX_TRASH_RETENTION_DAYS,
         user_operator::{add_user_to_organization, remove_user_from_org_query},
     },
 };
@@ -84,6 +87,8 @@ pub async fn delete_organization(
     pool: web::Data<Pool>,
     redis_pool: web::Data<RedisPool>,
     user: OwnerOnly,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let organization_id = organization_id.into_inner();
 
@@ -91,6 +96,11 @@ pub async fn delete_organization(
         return Ok(HttpResponse::Forbidden().finish());
     }
 
+    let organization_before = get_org_from_id_query(organization_id, pool.clone())
+        .await
+        .ok()
+        .and_then(|org_plan_sub| serde_json::to_value(org_plan_sub.organization).ok());
+
     delete_organization_query(
         Some(&req),
         Some(user.0.id),
@@ -100,6 +110,19 @@ pub async fn delete_organization(
     )
     .await?;
 
+    log_audit_event(
+        &audit_actor,
+        organization_id,
+        None,
+        AuditAction::OrganizationDeleted,
+        AuditTargetType::Organization,
+        organization_id.to_string(),
+        organization_before,
+        None,
+        &event_queue,
+    )
+    .await;
+
     Ok(HttpResponse::NoContent().finish())
 }
 
@@ -109,6 +132,8 @@ pub struct UpdateOrganizationReqPayload {
     name: Option<String>,
     /// New details for the partnership configuration. If not provided, the partnership configuration will not be updated.
     partner_configuration: Option<serde_json::Value>,
+    /// Number of days deleted datasets, groups and files are kept in the trash before being purged. Set to 0 to purge immediately and disable the trash. If not provided, the retention window will not be updated.
+    trash_retention_days: Option<i32>,
 }
 
 /// Update Organization
@@ -137,13 +162,27 @@ pub async fn update_organization(
     redis_pool: web::Data<RedisPool>,
     org_with_plan_and_sub: OrganizationWithSubAndPlan,
     user: OwnerOnly,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, actix_web::Error> {
     if !verify_owner(&user, &org_with_plan_and_sub.organization.id) {
         return Ok(HttpResponse::Forbidden().finish());
     }
     let organization_update_data = organization.into_inner();
+    let trash_retention_days = match organization_update_data.trash_retention_days {
+        Some(days) if !(0..=MAX_TRASH_RETENTION_DAYS).contains(&days) => {
+            return Err(ServiceError::BadRequest(format!(
+                "trash_retention_days must be between 0 and {}",
+                MAX_TRASH_RETENTION_DAYS
+            ))
+            .into());
+        }
+        Some(days) => days,
+        None => org_with_plan_and_sub.organization.trash_retention_days,
+    };
     let old_organization =
         get_org_from_id_query(org_with_plan_and_sub.organization.id, pool.clone()).await?;
+    let organization_before = serde_json::to_value(&old_organization.organization).ok();
 
     let updated_organization = update_organization_query(
         org_with_plan_and_sub.organization.id,
@@ -160,11 +199,25 @@ pub async fn update_organization(
         organization_update_data
             .partner_configuration
             .unwrap_or(old_organization.organization.partner_configuration),
+        trash_retention_days,
         pool,
         redis_pool,
     )
     .await?;
 
+    log_audit_event(
+        &audit_actor,
+        org_with_plan_and_sub.organization.id,
+        None,
+        AuditAction::OrganizationUpdated,
+        AuditTargetType::Organization,
+        org_with_plan_and_sub.organization.id.to_string(),
+        organization_before,
+        serde_json::to_value(&updated_organization).ok(),
+        &event_queue,
+    )
+    .await;
+
     Ok(HttpResponse::Ok().json(updated_organization))
 }
 
@@ -320,6 +373,8 @@ pub async fn remove_user_from_org(
     redis_pool: web::Data<RedisPool>,
     org_with_plan_and_sub: OrganizationWithSubAndPlan,
     user: AdminOnly,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, actix_web::Error> {
     if !verify_admin(&user, &org_with_plan_and_sub.organization.id) {
         return Ok(HttpResponse::Forbidden().finish());
@@ -333,6 +388,19 @@ pub async fn remove_user_from_org(
 
     remove_user_from_org_query(data.user_id, user_role, org_id, pool, redis_pool).await?;
 
+    log_audit_event(
+        &audit_actor,
+        org_id,
+        None,
+        AuditAction::UserRemovedFromOrganization,
+        AuditTargetType::User,
+        data.user_id.to_string(),
+        None,
+        None,
+        &event_queue,
+    )
+    .await;
+
     Ok(HttpResponse::NoContent().finish())
 }
 
@@ -397,6 +465,8 @@ pub async fn update_all_org_dataset_configs(
     pool: web::Data<Pool>,
     org_with_plan_and_sub: OrganizationWithSubAndPlan,
     user: OwnerOnly,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let organization_id = org_with_plan_and_sub.organization.id;
     if !verify_owner(&user, &organization_id) {
@@ -405,7 +475,20 @@ pub async fn update_all_org_dataset_configs(
 
     let new_dataset_config = req_payload.dataset_config.clone();
 
-    update_all_org_dataset_configs_query(organization_id, new_dataset_config, pool).await?;
+    update_all_org_dataset_configs_query(organization_id, new_dataset_config.clone(), pool).await?;
+
+    log_audit_event(
+        &audit_actor,
+        organization_id,
+        None,
+        AuditAction::DatasetConfigsUpdated,
+        AuditTargetType::Organization,
+        organization_id.to_string(),
+        None,
+        Some(serde_json::json!({ "server_configuration": new_dataset_config })),
+        &event_queue,
+    )
+    .await;
 
     Ok(HttpResponse::NoContent().finish())
 }
@@ -457,9 +540,25 @@ pub async fn create_organization_api_key(
     data: web::Json<CreateApiKeyReqPayload>,
     organization: OrganizationWithSubAndPlan,
     pool: web::Data<Pool>,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let new_api_key =
-        create_organization_api_key_query(organization.organization.id, data.into_inner(), pool)
+    if let Some(default_params) = data.default_params.as_ref() {
+        default_params.validate()?;
+    }
+
+    let data = data.into_inner();
+    let api_key_details = serde_json::json!({
+        "name": data.name,
+        "role": data.role,
+        "dataset_ids": data.dataset_ids,
+        "scopes": data.scopes,
+        "expires_at": data.expires_at,
+        "default_params": data.default_params,
+    });
+
+    let (api_key_id, new_api_key) =
+        create_organization_api_key_query(organization.organization.id, data, pool)
             .await
             .map_err(|err| {
                 ServiceError::BadRequest(format!(
@@ -468,6 +567,19 @@ pub async fn create_organization_api_key(
                 ))
             })?;
 
+    log_audit_event(
+        &audit_actor,
+        organization.organization.id,
+        None,
+        AuditAction::ApiKeyCreated,
+        AuditTargetType::OrganizationApiKey,
+        api_key_id.to_string(),
+        None,
+        Some(api_key_details),
+        &event_queue,
+    )
+    .await;
+
     Ok(HttpResponse::Ok().json(CreateApiKeyResponse {
         api_key: new_api_key,
     }))
@@ -529,10 +641,27 @@ pub async fn delete_organization_api_key(
     organization: OrganizationWithSubAndPlan,
     data: web::Path<uuid::Uuid>,
     pool: web::Data<Pool>,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    delete_organization_api_keys_query(organization.organization.id, data.into_inner(), pool)
+    let api_key_id = data.into_inner();
+
+    delete_organization_api_keys_query(organization.organization.id, api_key_id, pool)
         .await
         .map_err(|_err| ServiceError::BadRequest("Failed to get API keys for user".into()))?;
 
+    log_audit_event(
+        &audit_actor,
+        organization.organization.id,
+        None,
+        AuditAction::ApiKeyDeleted,
+        AuditTargetType::OrganizationApiKey,
+        api_key_id.to_string(),
+        None,
+        None,
+        &event_queue,
+    )
+    .await;
+
     Ok(HttpResponse::NoContent().finish())
 }
@@ -0,0 +1,169 @@
+use super::auth_handler::AdminOnly;
+use crate::{
+    data::models::{
+        AuditAction, AuditActor, AuditTargetType, DatasetAndOrgWithSubAndPlan, Pool,
+        QuerySuggestionStatus, QuerySuggestionType, RedisPool,
+    },
+    errors::ServiceError,
+    operators::{
+        analytics_store_operator::AnalyticsBackend,
+        audit_log_operator::log_audit_event,
+        clickhouse_operator::EventQueue,
+        query_suggestion_operator::{
+            accept_query_suggestion_query, get_query_suggestions_query,
+            set_query_suggestion_status_query,
+        },
+    },
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct GetQuerySuggestionsReqPayload {
+    /// Only get suggestions with this status. Default is pending.
+    pub status: Option<QuerySuggestionStatus>,
+    /// Only get suggestions of this type.
+    pub suggestion_type: Option<QuerySuggestionType>,
+    /// The page number to get. Default is 1.
+    pub page: Option<u64>,
+}
+
+/// Get Query Suggestions
+///
+/// Get the review queue of suggestions mined from the dataset's search analytics, most frequent first. Synonyms and redirects come from queries users reformulated into ones which got a click, typos from reformulations which changed a single word by a few characters and content gaps from frequent queries without results that were never successfully reformulated. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/analytics/suggestions",
+    context_path = "/api",
+    tag = "Analytics",
+    responses(
+        (status = 200, description = "Query suggestions of the dataset", body = QuerySuggestionsResponse),
+        (status = 400, description = "Service error relating to getting the query suggestions", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        GetQuerySuggestionsReqPayload,
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_query_suggestions(
+    params: web::Query<GetQuerySuggestionsReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let suggestions = get_query_suggestions_query(
+        dataset_org_plan_sub.dataset.id,
+        params.status.unwrap_or(QuerySuggestionStatus::Pending),
+        params.suggestion_type,
+        params.page.unwrap_or(1),
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(suggestions))
+}
+
+/// Accept Query Suggestion
+///
+/// Apply a pending suggestion with one call. Synonyms and redirects are added to the QUERY_REWRITES of the dataset's configuration, typos add the suggested word to the dataset's typo dictionary and content gaps are only marked as accepted. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/analytics/suggestions/{suggestion_id}/accept",
+    context_path = "/api",
+    tag = "Analytics",
+    responses(
+        (status = 200, description = "The accepted query suggestion", body = QuerySuggestion),
+        (status = 400, description = "The query suggestion is not pending", body = ErrorResponseBody),
+        (status = 404, description = "Query suggestion not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("suggestion_id" = uuid::Uuid, Path, description = "The id of the query suggestion to accept."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn accept_query_suggestion(
+    suggestion_id: web::Path<uuid::Uuid>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+    analytics_backend: web::Data<AnalyticsBackend>,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
+) -> Result<HttpResponse, ServiceError> {
+    let clickhouse_client = analytics_backend.clickhouse_client()?;
+    let dataset = dataset_org_plan_sub.dataset;
+    let dataset_before = serde_json::to_value(&dataset).ok();
+
+    let (suggestion, updated_dataset) = accept_query_suggestion_query(
+        suggestion_id.into_inner(),
+        dataset,
+        &pool,
+        &redis_pool,
+        clickhouse_client,
+    )
+    .await?;
+
+    if let Some(d) = updated_dataset {
+        log_audit_event(
+            &audit_actor,
+            d.organization_id,
+            Some(d.id),
+            AuditAction::DatasetUpdated,
+            AuditTargetType::Dataset,
+            d.id.to_string(),
+            dataset_before,
+            serde_json::to_value(&d).ok(),
+            &event_queue,
+        )
+        .await;
+    }
+
+    Ok(HttpResponse::Ok().json(suggestion))
+}
+
+/// Dismiss Query Suggestion
+///
+/// Remove a suggestion from the review queue. Dismissed suggestions are not proposed again when the same pattern is mined later. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/analytics/suggestions/{suggestion_id}/dismiss",
+    context_path = "/api",
+    tag = "Analytics",
+    responses(
+        (status = 200, description = "The dismissed query suggestion", body = QuerySuggestion),
+        (status = 404, description = "Query suggestion not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("suggestion_id" = uuid::Uuid, Path, description = "The id of the query suggestion to dismiss."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn dismiss_query_suggestion(
+    suggestion_id: web::Path<uuid::Uuid>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let suggestion = set_query_suggestion_status_query(
+        suggestion_id.into_inner(),
+        dataset_org_plan_sub.dataset.id,
+        QuerySuggestionStatus::Dismissed,
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(suggestion))
+}
@@ -0,0 +1,212 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    data::models::{DatasetAndOrgWithSubAndPlan, Pool, RagPreset, RagPresetOptions},
+    errors::ServiceError,
+    operators::rag_preset_operator::{
+        create_rag_preset_query, delete_rag_preset_query, get_rag_preset_by_name_query,
+        get_rag_presets_for_dataset_query, update_rag_preset_query,
+    },
+};
+
+use super::auth_handler::AdminOnly;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateRagPresetReqPayload {
+    /// The name of the preset. Must be unique within the dataset. Topics and message requests reference the preset by this name.
+    pub name: String,
+    /// The RAG options stored in the preset. Any option left unset falls back to the request or dataset defaults.
+    pub options: RagPresetOptions,
+}
+
+/// Create RAG Preset
+///
+/// Create a named RAG preset for the dataset. Presets bundle a system prompt, RAG prompt, model, temperature, retrieval filters, search type and context options which can be referenced by name from topics and message requests. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/rag_preset",
+    context_path = "/api",
+    tag = "RAG Preset",
+    request_body(content = CreateRagPresetReqPayload, description = "JSON request payload to create a RAG preset", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The created RAG preset", body = RagPreset),
+        (status = 400, description = "Service error relating to creating the RAG preset", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn create_rag_preset(
+    data: web::Json<CreateRagPresetReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let data = data.into_inner();
+
+    if data.name.trim().is_empty() {
+        return Err(ServiceError::BadRequest(
+            "RAG preset name must not be empty".to_string(),
+        ));
+    }
+
+    let preset = create_rag_preset_query(
+        RagPreset::from_details(dataset_org_plan_sub.dataset.id, data.name, data.options),
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(preset))
+}
+
+/// Get RAG Presets for Dataset
+///
+/// Get all of the RAG presets configured for the dataset. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/rag_preset",
+    context_path = "/api",
+    tag = "RAG Preset",
+    responses(
+        (status = 200, description = "All RAG presets for the dataset", body = Vec<RagPreset>),
+        (status = 400, description = "Service error relating to getting the RAG presets", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_rag_presets_for_dataset(
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let presets = get_rag_presets_for_dataset_query(dataset_org_plan_sub.dataset.id, &pool).await?;
+
+    Ok(HttpResponse::Ok().json(presets))
+}
+
+/// Get RAG Preset
+///
+/// Get a single RAG preset by its name. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/rag_preset/{preset_name}",
+    context_path = "/api",
+    tag = "RAG Preset",
+    responses(
+        (status = 200, description = "The RAG preset with the given name", body = RagPreset),
+        (status = 404, description = "RAG preset not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("preset_name" = String, Path, description = "The name of the RAG preset to get."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_rag_preset(
+    preset_name: web::Path<String>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let preset = get_rag_preset_by_name_query(
+        preset_name.into_inner(),
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(preset))
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpdateRagPresetReqPayload {
+    /// The new RAG options for the preset. These replace the existing options in full.
+    pub options: RagPresetOptions,
+}
+
+/// Update RAG Preset
+///
+/// Replace the options of an existing RAG preset. Topics referencing the preset pick up the new options on their next message. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    put,
+    path = "/rag_preset/{preset_name}",
+    context_path = "/api",
+    tag = "RAG Preset",
+    request_body(content = UpdateRagPresetReqPayload, description = "JSON request payload to update a RAG preset", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The updated RAG preset", body = RagPreset),
+        (status = 400, description = "Service error relating to updating the RAG preset", body = ErrorResponseBody),
+        (status = 404, description = "RAG preset not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("preset_name" = String, Path, description = "The name of the RAG preset to update."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn update_rag_preset(
+    preset_name: web::Path<String>,
+    data: web::Json<UpdateRagPresetReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let preset = update_rag_preset_query(
+        preset_name.into_inner(),
+        data.into_inner().options,
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(preset))
+}
+
+/// Delete RAG Preset
+///
+/// Delete a RAG preset by its name. Topics which referenced the preset are detached from it and fall back to the dataset defaults. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    delete,
+    path = "/rag_preset/{preset_name}",
+    context_path = "/api",
+    tag = "RAG Preset",
+    responses(
+        (status = 204, description = "Confirmation that the RAG preset was deleted"),
+        (status = 404, description = "RAG preset not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("preset_name" = String, Path, description = "The name of the RAG preset to delete."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn delete_rag_preset(
+    preset_name: web::Path<String>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    delete_rag_preset_query(
+        preset_name.into_inner(),
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,128 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    data::models::{ApiKeyRequestParams, ApiOperation, DatasetAndOrgWithSubAndPlan, RateLimits},
+    errors::ServiceError,
+    operators::search_token_operator::{encode_search_token, SearchTokenClaims},
+};
+
+use super::{auth_handler::AdminOnly, chunk_handler::ChunkFilter};
+
+/// Longest lifetime a search token can be created with, one day.
+const MAX_SEARCH_TOKEN_EXPIRES_IN: u64 = 86400;
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[schema(example = json!({
+    "expires_in": 3600,
+    "user_id": "user-123",
+    "user_groups": ["support"],
+    "filters": {
+        "must": [
+            {
+                "field": "tag_set",
+                "match_all": ["tenant-a"]
+            }
+        ]
+    },
+    "rate_limits": {
+        "search": {
+            "requests": 30,
+            "per_seconds": 60
+        }
+    },
+    "operations": ["search"]
+}))]
+pub struct CreateSearchTokenReqPayload {
+    /// Number of seconds until the token expires. Default is 3600 and the maximum is 86400.
+    pub expires_in: Option<u64>,
+    /// User id which is recorded with the requests made with the token. If the dataset has access control enabled, the token can only retrieve chunks and groups visible to this user.
+    pub user_id: Option<String>,
+    /// Groups of the end user the token is created for, used along with the user_id when the dataset has access control enabled.
+    pub user_groups: Option<Vec<String>>,
+    /// Filters which are AND-ed with the filters of every request made with the token. Only `must` and `must_not` conditions are supported.
+    pub filters: Option<ChunkFilter>,
+    /// Request rate limits for the token. These apply in addition to the dataset and organization limits.
+    pub rate_limits: Option<RateLimits>,
+    /// Operations the token can be used for. Default is search only.
+    pub operations: Option<Vec<ApiOperation>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct CreateSearchTokenResponse {
+    /// The search token. It is used in the Authorization header in the same way as an api key.
+    pub token: String,
+    /// Timestamp after which the token is rejected.
+    pub expires_at: chrono::NaiveDateTime,
+}
+
+/// Create Search Token
+///
+/// Create a short-lived signed token for the dataset which can be handed to browser clients instead of an api key. The token only grants read access to the dataset, is restricted to the given operations, filters and rate limits, and cannot be revoked before it expires. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/search_token",
+    context_path = "/api",
+    tag = "Search Token",
+    request_body(content = CreateSearchTokenReqPayload, description = "JSON request payload to create a search token", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The created search token", body = CreateSearchTokenResponse),
+        (status = 400, description = "Service error relating to creating the search token", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn create_search_token(
+    data: web::Json<CreateSearchTokenReqPayload>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, ServiceError> {
+    let data = data.into_inner();
+
+    let expires_in = data.expires_in.unwrap_or(3600);
+    if expires_in == 0 || expires_in > MAX_SEARCH_TOKEN_EXPIRES_IN {
+        return Err(ServiceError::BadRequest(format!(
+            "expires_in must be between 1 and {} seconds",
+            MAX_SEARCH_TOKEN_EXPIRES_IN
+        )));
+    }
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(expires_in as i64);
+
+    let params = ApiKeyRequestParams {
+        search_type: None,
+        page_size: None,
+        filters: None,
+        highlight_options: None,
+        score_threshold: None,
+        slim_chunks: None,
+        use_quote_negated_terms: None,
+        remove_stop_words: None,
+        typo_options: None,
+        no_result_message: None,
+        rate_limits: data.rate_limits,
+        operations: Some(data.operations.unwrap_or(vec![ApiOperation::Search])),
+        required_filters: data.filters,
+        user_id: data.user_id,
+        user_groups: data.user_groups,
+        stripe_customer_id: None,
+    };
+    params.validate()?;
+
+    let token = encode_search_token(&SearchTokenClaims {
+        id: uuid::Uuid::new_v4(),
+        dataset_id: dataset_org_plan_sub.dataset.id,
+        organization_id: dataset_org_plan_sub.organization.organization.id,
+        exp: expires_at.timestamp(),
+        params,
+    })?;
+
+    Ok(HttpResponse::Ok().json(CreateSearchTokenResponse {
+        token,
+        expires_at: expires_at.naive_utc(),
+    }))
+}
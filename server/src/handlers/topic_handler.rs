@@ -4,6 +4,7 @@ use crate::{
     handlers::auth_handler::AdminOnly,
     operators::{
         message_operator::{create_messages_query, get_topic_messages_query, get_topic_string},
+        rag_preset_operator::get_rag_preset_by_name_query,
         topic_operator::{
             create_topic_query, delete_topic_query, get_all_topics_for_owner_id_query,
             get_topic_query, update_topic_query,
@@ -22,6 +23,8 @@ pub struct CreateTopicReqPayload {
     pub name: Option<String>,
     /// The owner_id of the topic. This is typically a browser fingerprint or your user's id. It is used to group topics together for a user.
     pub owner_id: String,
+    /// The name of a RAG preset configured on the dataset to use by default for messages created on this topic. Values set on individual message requests take precedence over the preset.
+    pub rag_preset: Option<String>,
 }
 
 /// Create Topic
@@ -55,6 +58,10 @@ pub async fn create_topic(
         DatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration.clone())
             .LLM_DEFAULT_MODEL;
 
+    if let Some(rag_preset) = data_inner.rag_preset.clone() {
+        get_rag_preset_by_name_query(rag_preset, dataset_org_plan_sub.dataset.id, &pool).await?;
+    }
+
     let first_message = data_inner.first_user_message;
 
     if first_message.is_none() && data_inner.name.is_none() {
@@ -80,6 +87,7 @@ pub async fn create_topic(
         topic_name,
         data_inner.owner_id,
         dataset_org_plan_sub.dataset.id,
+        data_inner.rag_preset,
     );
     let new_topic1 = new_topic.clone();
 
@@ -132,7 +140,12 @@ pub async fn clone_topic(
 
     let topic_name = data.name.unwrap_or(original_topic.name);
 
-    let new_topic = Topic::from_details(topic_name, data.owner_id, dataset_org_plan_sub.dataset.id);
+    let new_topic = Topic::from_details(
+        topic_name,
+        data.owner_id,
+        dataset_org_plan_sub.dataset.id,
+        original_topic.rag_preset,
+    );
 
     create_topic_query(new_topic.clone(), &pool).await?;
 
@@ -194,11 +207,13 @@ pub struct UpdateTopicReqPayload {
     pub topic_id: uuid::Uuid,
     /// The new name of the topic. A name is not generated from this field, it is used as-is.
     pub name: String,
+    /// The name of a RAG preset configured on the dataset to attach to the topic. Set to an empty string to detach the current preset. If not specified, the topic's preset is left unchanged.
+    pub rag_preset: Option<String>,
 }
 
 /// Update Topic
 ///
-/// Update an existing chat topic. Currently, only the name of the topic can be updated. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+/// Update an existing chat topic. Currently, only the name and RAG preset of the topic can be updated. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
 #[utoipa::path(
     put,
     path = "/topic",
@@ -230,7 +245,28 @@ pub async fn update_topic(
         return Err(ServiceError::BadRequest("Topic name must not be empty".to_string()).into());
     }
 
-    update_topic_query(topic_id, name, dataset_org_plan_sub.dataset.id, &pool).await?;
+    let rag_preset = match data_inner.rag_preset {
+        Some(rag_preset) if rag_preset.is_empty() => Some(None),
+        Some(rag_preset) => {
+            get_rag_preset_by_name_query(
+                rag_preset.clone(),
+                dataset_org_plan_sub.dataset.id,
+                &pool,
+            )
+            .await?;
+            Some(Some(rag_preset))
+        }
+        None => None,
+    };
+
+    update_topic_query(
+        topic_id,
+        name,
+        rag_preset,
+        dataset_org_plan_sub.dataset.id,
+        &pool,
+    )
+    .await?;
 
     Ok(HttpResponse::NoContent().finish())
 }
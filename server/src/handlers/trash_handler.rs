@@ -0,0 +1,170 @@
+use super::auth_handler::AdminOnly;
+use crate::{
+    data::models::{
+        OrganizationWithSubAndPlan, Pool, RedisPool, TrashItem, TrashItemType, UserRole,
+    },
+    errors::ServiceError,
+    middleware::auth_middleware::{get_role_for_org, verify_admin},
+    operators::trash_operator::{
+        get_trash_item_query, get_trash_query, purge_trash_item_query, restore_trash_item_query,
+    },
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct GetTrashReqPayload {
+    /// Only return items of this dataset. Leave undefined to get the trash for the whole organization.
+    pub dataset_id: Option<uuid::Uuid>,
+    /// Only return items of this type. Leave undefined to get all items.
+    pub item_type: Option<TrashItemType>,
+    /// The page number to get. Default is 1.
+    pub page: Option<u64>,
+}
+
+/// Datasets can only be deleted by owners, so only owners may restore or purge them.
+fn verify_trash_item_access(
+    user: &AdminOnly,
+    organization_id: &uuid::Uuid,
+    trash_item: &TrashItem,
+) -> bool {
+    if trash_item.item_type != TrashItemType::Dataset.to_string() {
+        return true;
+    }
+
+    get_role_for_org(&user.0, organization_id).is_some_and(|role| role >= UserRole::Owner)
+}
+
+/// Get Trash
+///
+/// Get the datasets, groups and files of the organization which were deleted and can still be restored, most recently deleted first. Auth'ed user or api key must have an admin or owner role for the specified organization.
+#[utoipa::path(
+    get,
+    path = "/trash",
+    context_path = "/api",
+    tag = "Trash",
+    responses(
+        (status = 200, description = "Items in the trash", body = TrashResponse),
+        (status = 400, description = "Service error relating to getting the trash", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Organization" = uuid::Uuid, Header, description = "The organization id to use for the request"),
+        GetTrashReqPayload,
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn get_trash(
+    params: web::Query<GetTrashReqPayload>,
+    org_with_plan_and_sub: OrganizationWithSubAndPlan,
+    user: AdminOnly,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    if !verify_admin(&user, &org_with_plan_and_sub.organization.id) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let params = params.into_inner();
+    let trash = get_trash_query(
+        org_with_plan_and_sub.organization.id,
+        params.dataset_id,
+        params.item_type,
+        params.page.unwrap_or(1),
+        pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(trash))
+}
+
+/// Restore Trash Item
+///
+/// Restore a deleted dataset, group or file along with its chunks. Its tracking id is given back unless it has been taken since. Auth'ed user or api key must have an admin or owner role for the specified organization, and an owner role to restore a dataset.
+#[utoipa::path(
+    post,
+    path = "/trash/{trash_id}/restore",
+    context_path = "/api",
+    tag = "Trash",
+    responses(
+        (status = 204, description = "Confirmation that the item was restored"),
+        (status = 400, description = "Service error relating to restoring the item", body = ErrorResponseBody),
+        (status = 404, description = "Trash item not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Organization" = uuid::Uuid, Header, description = "The organization id to use for the request"),
+        ("trash_id" = uuid::Uuid, Path, description = "The id of the trash item to restore."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn restore_trash_item(
+    trash_id: web::Path<uuid::Uuid>,
+    org_with_plan_and_sub: OrganizationWithSubAndPlan,
+    user: AdminOnly,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    let organization_id = org_with_plan_and_sub.organization.id;
+    if !verify_admin(&user, &organization_id) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let trash_item =
+        get_trash_item_query(trash_id.into_inner(), organization_id, pool.clone()).await?;
+
+    if !verify_trash_item_access(&user, &organization_id, &trash_item) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    restore_trash_item_query(trash_item, pool).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Purge Trash Item
+///
+/// Permanently delete an item in the trash along with its chunks without waiting for the retention window to pass. Auth'ed user or api key must have an admin or owner role for the specified organization, and an owner role to purge a dataset.
+#[utoipa::path(
+    delete,
+    path = "/trash/{trash_id}",
+    context_path = "/api",
+    tag = "Trash",
+    responses(
+        (status = 204, description = "Confirmation that the item was purged"),
+        (status = 400, description = "Service error relating to purging the item", body = ErrorResponseBody),
+        (status = 404, description = "Trash item not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Organization" = uuid::Uuid, Header, description = "The organization id to use for the request"),
+        ("trash_id" = uuid::Uuid, Path, description = "The id of the trash item to purge."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+pub async fn purge_trash_item(
+    trash_id: web::Path<uuid::Uuid>,
+    org_with_plan_and_sub: OrganizationWithSubAndPlan,
+    user: AdminOnly,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<HttpResponse, ServiceError> {
+    let organization_id = org_with_plan_and_sub.organization.id;
+    if !verify_admin(&user, &organization_id) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let trash_item =
+        get_trash_item_query(trash_id.into_inner(), organization_id, pool.clone()).await?;
+
+    if !verify_trash_item_access(&user, &organization_id, &trash_item) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    purge_trash_item_query(trash_item, pool, redis_pool).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,94 @@
+use super::auth_handler::OwnerOnly;
+use crate::{
+    data::models::{OrganizationWithSubAndPlan, Pool},
+    errors::ServiceError,
+    middleware::auth_middleware::verify_owner,
+    operators::usage_operator::{get_organization_usage_query, usage_to_csv, UsageFilter},
+};
+use actix_web::{web, HttpResponse};
+
+/// Get Metered Usage
+///
+/// Get the search calls, RAG calls, LLM and embedding tokens, file pages and storage metered for the organization's datasets per day and api key, along with their total. Auth'ed user or api key must have an owner role for the specified organization.
+#[utoipa::path(
+    get,
+    path = "/organization/metered_usage",
+    context_path = "/api",
+    tag = "Organization",
+    responses(
+        (status = 200, description = "Metered usage of the organization", body = UsageResponse),
+        (status = 400, description = "Service error relating to getting the metered usage", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Organization" = uuid::Uuid, Header, description = "The organization id to use for the request"),
+        UsageFilter,
+    ),
+    security(
+        ("ApiKey" = ["owner"]),
+    )
+)]
+pub async fn get_metered_usage(
+    filter: web::Query<UsageFilter>,
+    org_with_plan_and_sub: OrganizationWithSubAndPlan,
+    user: OwnerOnly,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    if !verify_owner(&user, &org_with_plan_and_sub.organization.id) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let usage = get_organization_usage_query(
+        org_with_plan_and_sub.organization.id,
+        filter.into_inner(),
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(usage))
+}
+
+/// Export Metered Usage as CSV
+///
+/// Get the same rows as Get Metered Usage as a CSV file with one column per metric, for reconciling against invoices. Auth'ed user or api key must have an owner role for the specified organization.
+#[utoipa::path(
+    get,
+    path = "/organization/metered_usage/csv",
+    context_path = "/api",
+    tag = "Organization",
+    responses(
+        (status = 200, description = "CSV of the organization's metered usage", body = String, content_type = "text/csv"),
+        (status = 400, description = "Service error relating to getting the metered usage", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Organization" = uuid::Uuid, Header, description = "The organization id to use for the request"),
+        UsageFilter,
+    ),
+    security(
+        ("ApiKey" = ["owner"]),
+    )
+)]
+pub async fn get_metered_usage_csv(
+    filter: web::Query<UsageFilter>,
+    org_with_plan_and_sub: OrganizationWithSubAndPlan,
+    user: OwnerOnly,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    if !verify_owner(&user, &org_with_plan_and_sub.organization.id) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let usage = get_organization_usage_query(
+        org_with_plan_and_sub.organization.id,
+        filter.into_inner(),
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header((
+            "Content-Disposition",
+            "attachment; filename=\"metered_usage.csv\"",
+        ))
+        .body(usage_to_csv(&usage.usage)))
+}
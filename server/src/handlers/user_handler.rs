@@ -1,10 +1,17 @@
 use super::auth_handler::LoggedUser;
 use crate::{
-    data::models::{OrganizationWithSubAndPlan, Pool, RedisPool, UserRole},
+    data::models::{
+        AuditAction, AuditActor, AuditTargetType, OrganizationWithSubAndPlan, Pool, RedisPool,
+        UserRole,
+    },
     errors::ServiceError,
-    operators::user_operator::{
-        delete_user_api_keys_query, get_user_api_keys_query, get_user_by_id_query,
-        update_user_org_role_query,
+    operators::{
+        audit_log_operator::log_audit_event,
+        clickhouse_operator::EventQueue,
+        user_operator::{
+            delete_user_api_keys_query, get_user_api_keys_query, get_user_by_id_query,
+            update_user_org_role_query,
+        },
     },
 };
 use actix_web::{web, HttpResponse};
@@ -45,6 +52,8 @@ pub async fn update_user(
     pool: web::Data<Pool>,
     org_with_plan_and_sub: OrganizationWithSubAndPlan,
     redis_pool: web::Data<RedisPool>,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, ServiceError> {
     let update_user_data = data.into_inner();
     let org_role = user
@@ -63,6 +72,7 @@ pub async fn update_user(
         ));
     }
 
+    let mut role_before = org_role;
     if let Some(user_id) = update_user_data.user_id {
         if org_role < 1 {
             return Err(ServiceError::BadRequest(
@@ -72,22 +82,26 @@ pub async fn update_user(
 
         let user_info = get_user_by_id_query(&user_id, pool.clone()).await?;
 
-        let already_in_org = user_info
+        let user_org = user_info
             .1
             .iter()
-            .any(|org| org.organization_id == org_with_plan_and_sub.organization.id);
+            .find(|org| org.organization_id == org_with_plan_and_sub.organization.id);
 
-        if !already_in_org {
-            return Err(ServiceError::BadRequest(
-                "The user who you would like to update the role of must be added to the specified org first before their role can be updated".to_string(),
-            ));
+        match user_org {
+            Some(user_org) => role_before = user_org.role,
+            None => {
+                return Err(ServiceError::BadRequest(
+                    "The user who you would like to update the role of must be added to the specified org first before their role can be updated".to_string(),
+                ));
+            }
         }
     }
 
+    let target_user_id = update_user_data.user_id.unwrap_or(user.id);
     let user_role = UserRole::from(update_user_data.role);
 
     update_user_org_role_query(
-        update_user_data.user_id.unwrap_or(user.id),
+        target_user_id,
         org_with_plan_and_sub.organization.id,
         user_role,
         pool,
@@ -95,6 +109,19 @@ pub async fn update_user(
     )
     .await?;
 
+    log_audit_event(
+        &audit_actor,
+        org_with_plan_and_sub.organization.id,
+        None,
+        AuditAction::UserRoleUpdated,
+        AuditTargetType::User,
+        target_user_id.to_string(),
+        Some(serde_json::json!({ "role": role_before })),
+        Some(serde_json::json!({ "role": update_user_data.role })),
+        &event_queue,
+    )
+    .await;
+
     Ok(HttpResponse::NoContent().finish())
 }
 
@@ -148,10 +175,30 @@ pub async fn delete_user_api_key(
     user: LoggedUser,
     data: web::Path<uuid::Uuid>,
     pool: web::Data<Pool>,
+    audit_actor: AuditActor,
+    event_queue: web::Data<EventQueue>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    delete_user_api_keys_query(user.id, data.into_inner(), pool)
+    let api_key_id = data.into_inner();
+
+    delete_user_api_keys_query(user.id, api_key_id, pool)
         .await
         .map_err(|_err| ServiceError::BadRequest("Failed to get API keys for user".into()))?;
 
+    // User api keys are not tied to a single organization so the deletion is recorded for each of the user's organizations
+    for user_org in user.user_orgs.iter() {
+        log_audit_event(
+            &audit_actor,
+            user_org.organization_id,
+            None,
+            AuditAction::ApiKeyDeleted,
+            AuditTargetType::UserApiKey,
+            api_key_id.to_string(),
+            None,
+            None,
+            &event_queue,
+        )
+        .await;
+    }
+
     Ok(HttpResponse::NoContent().finish())
 }
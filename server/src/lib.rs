@@ -9,8 +9,13 @@ use crate::{
     errors::{custom_json_error_handler, ServiceError},
     handlers::{auth_handler::build_oidc_client, metrics_handler::Metrics},
     operators::{
-        clickhouse_operator::EventQueue, qdrant_operator::create_new_qdrant_collection_query,
-        typo_operator::BKTreeCache, user_operator::create_default_user,
+        analytics_store_operator::{
+            AnalyticsBackend, ClickHouseAnalyticsStore, PostgresAnalyticsStore,
+        },
+        clickhouse_operator::EventQueue,
+        qdrant_operator::create_new_qdrant_collection_query,
+        typo_operator::BKTreeCache,
+        user_operator::create_default_user,
     },
 };
 use actix_cors::Cors;
@@ -174,6 +179,31 @@ impl Modify for SecurityAddon {
         handlers::topic_handler::update_topic,
         handlers::topic_handler::clone_topic,
         handlers::topic_handler::get_all_topics_for_owner_id,
+        handlers::rag_preset_handler::create_rag_preset,
+        handlers::rag_preset_handler::get_rag_presets_for_dataset,
+        handlers::rag_preset_handler::get_rag_preset,
+        handlers::rag_preset_handler::update_rag_preset,
+        handlers::rag_preset_handler::delete_rag_preset,
+        handlers::experiment_handler::create_experiment,
+        handlers::experiment_handler::get_experiments,
+        handlers::experiment_handler::get_experiment,
+        handlers::experiment_handler::update_experiment,
+        handlers::experiment_handler::delete_experiment,
+        handlers::experiment_handler::get_experiment_results,
+        handlers::ingestion_job_handler::get_ingestion_job,
+        handlers::dead_letter_handler::get_dead_letters,
+        handlers::dead_letter_handler::replay_dead_letter,
+        handlers::dead_letter_handler::delete_dead_letter,
+        handlers::trash_handler::get_trash,
+        handlers::trash_handler::restore_trash_item,
+        handlers::trash_handler::purge_trash_item,
+        handlers::dataset_webhook_handler::create_dataset_webhook,
+        handlers::dataset_webhook_handler::get_dataset_webhooks,
+        handlers::dataset_webhook_handler::update_dataset_webhook,
+        handlers::dataset_webhook_handler::delete_dataset_webhook,
+        handlers::dataset_webhook_handler::get_webhook_deliveries,
+        handlers::dataset_webhook_handler::redeliver_webhook_delivery,
+        handlers::search_token_handler::create_search_token,
         handlers::message_handler::create_message,
         handlers::message_handler::get_message_by_id,
         handlers::message_handler::get_all_topic_messages,
@@ -205,6 +235,9 @@ impl Modify for SecurityAddon {
         handlers::user_handler::delete_user_api_key,
         handlers::organization_handler::create_organization_api_key,
         handlers::organization_handler::delete_organization_api_key,
+        handlers::audit_log_handler::get_audit_logs,
+        handlers::usage_handler::get_metered_usage,
+        handlers::usage_handler::get_metered_usage_csv,
         handlers::organization_handler::get_organization_api_keys,
         handlers::group_handler::search_over_groups,
         handlers::group_handler::count_group_chunks,
@@ -228,6 +261,7 @@ impl Modify for SecurityAddon {
         handlers::file_handler::get_file_handler,
         handlers::file_handler::delete_file_handler,
         handlers::file_handler::create_presigned_url_for_csv_jsonl,
+        handlers::file_handler::get_csv_jsonl_error_file,
         handlers::file_handler::upload_html_page,
         handlers::event_handler::get_events,
         handlers::crawl_handler::create_crawl,
@@ -271,6 +305,17 @@ impl Modify for SecurityAddon {
         handlers::analytics_handler::get_top_datasets,
         handlers::analytics_handler::get_all_events,
         handlers::analytics_handler::get_event_by_id,
+        handlers::analytics_export_handler::upsert_analytics_export,
+        handlers::analytics_export_handler::get_analytics_export,
+        handlers::analytics_export_handler::delete_analytics_export,
+        handlers::analytics_export_handler::get_analytics_export_runs,
+        handlers::analytics_report_handler::upsert_analytics_report_subscription,
+        handlers::analytics_report_handler::get_analytics_report_subscription,
+        handlers::analytics_report_handler::delete_analytics_report_subscription,
+        handlers::analytics_report_handler::send_analytics_report_now,
+        handlers::query_suggestion_handler::get_query_suggestions,
+        handlers::query_suggestion_handler::accept_query_suggestion,
+        handlers::query_suggestion_handler::dismiss_query_suggestion,
         handlers::metrics_handler::get_metrics,
         handlers::page_handler::public_page,
         handlers::etl_handler::create_etl_job
@@ -282,6 +327,10 @@ impl Modify for SecurityAddon {
             handlers::topic_handler::CloneTopicReqPayload,
             handlers::topic_handler::DeleteTopicData,
             handlers::topic_handler::UpdateTopicReqPayload,
+            handlers::rag_preset_handler::CreateRagPresetReqPayload,
+            handlers::rag_preset_handler::UpdateRagPresetReqPayload,
+            handlers::experiment_handler::CreateExperimentReqPayload,
+            handlers::experiment_handler::UpdateExperimentReqPayload,
             handlers::message_handler::CreateMessageReqPayload,
             handlers::message_handler::RegenerateMessageReqPayload,
             handlers::message_handler::EditMessageReqPayload,
@@ -373,6 +422,7 @@ impl Modify for SecurityAddon {
             handlers::file_handler::UploadFileResponseBody,
             handlers::file_handler::CreatePresignedUrlForCsvJsonlReqPayload,
             handlers::file_handler::CreatePresignedUrlForCsvJsonResponseBody,
+            handlers::file_handler::CsvJsonlErrorFileResponseBody,
             handlers::file_handler::UploadHtmlPageReqPayload,
             handlers::file_handler::FileData,
             handlers::file_handler::Pdf2MdOptions,
@@ -427,6 +477,7 @@ impl Modify for SecurityAddon {
             data::models::ChunkReqPayloadFields,
             data::models::ChunkReqPayloadMapping,
             data::models::ChunkReqPayloadMappings,
+            data::models::MappingValueType,
             data::models::DatasetConfigurationDTO,
             data::models::ScrapeOptions,
             data::models::CrawlShopifyOptions,
@@ -520,6 +571,67 @@ impl Modify for SecurityAddon {
             data::models::SortBySearchType,
             data::models::ReRankOptions,
             data::models::Topic,
+            data::models::RagPreset,
+            data::models::RagPresetOptions,
+            data::models::Experiment,
+            data::models::ExperimentVariant,
+            operators::experiment_operator::MetricInterval,
+            operators::experiment_operator::ExperimentVariantResults,
+            operators::experiment_operator::ExperimentResultsResponse,
+            data::models::IngestionJobStatus,
+            data::models::IngestionJobState,
+            data::models::FailedIngestionChunk,
+            data::models::DeadLetter,
+            data::models::DeadLetterQueue,
+            handlers::dead_letter_handler::GetDeadLettersReqPayload,
+            handlers::dead_letter_handler::ReplayDeadLetterReqPayload,
+            operators::dead_letter_operator::DeadLettersResponse,
+            data::models::TrashItem,
+            data::models::TrashItemType,
+            handlers::trash_handler::GetTrashReqPayload,
+            operators::trash_operator::TrashResponse,
+            data::models::DatasetWebhook,
+            data::models::DatasetWebhookDelivery,
+            data::models::WebhookEventType,
+            data::models::WebhookDeliveryStatus,
+            handlers::dataset_webhook_handler::CreateDatasetWebhookReqPayload,
+            handlers::dataset_webhook_handler::CreateDatasetWebhookResponse,
+            handlers::dataset_webhook_handler::UpdateDatasetWebhookReqPayload,
+            handlers::dataset_webhook_handler::GetWebhookDeliveriesReqPayload,
+            operators::dataset_webhook_operator::WebhookDeliveriesResponse,
+            data::models::AnalyticsExportConfig,
+            data::models::AnalyticsExportRun,
+            data::models::AnalyticsExportEventType,
+            data::models::AnalyticsExportFormat,
+            data::models::AnalyticsExportRunStatus,
+            data::models::AnalyticsForwardSink,
+            handlers::analytics_export_handler::UpsertAnalyticsExportReqPayload,
+            handlers::analytics_export_handler::GetAnalyticsExportRunsReqPayload,
+            operators::analytics_export_operator::AnalyticsExportRunWithUrls,
+            operators::analytics_export_operator::AnalyticsExportRunsResponse,
+            data::models::AnalyticsReportSchedule,
+            data::models::AnalyticsReportSubscription,
+            handlers::analytics_report_handler::UpsertAnalyticsReportSubscriptionReqPayload,
+            operators::analytics_report_operator::AnalyticsReport,
+            operators::analytics_report_operator::IngestionErrorSummary,
+            operators::analytics_operator::SearchCTRTrendPoint,
+            data::models::QuerySuggestion,
+            data::models::QuerySuggestionType,
+            data::models::QuerySuggestionStatus,
+            handlers::query_suggestion_handler::GetQuerySuggestionsReqPayload,
+            operators::query_suggestion_operator::QuerySuggestionsResponse,
+            data::models::AuditAction,
+            data::models::AuditTargetType,
+            data::models::AuditLog,
+            data::models::AuditLogFilter,
+            handlers::audit_log_handler::GetAuditLogsReqPayload,
+            operators::audit_log_operator::AuditLogsResponse,
+            operators::usage_operator::UsageFilter,
+            operators::usage_operator::UsageResponse,
+            data::models::UsageCounts,
+            data::models::DatasetUsage,
+            handlers::search_token_handler::CreateSearchTokenReqPayload,
+            handlers::search_token_handler::CreateSearchTokenResponse,
             data::models::Message,
             data::models::ChunkMetadata,
             data::models::ChatMessageProxy,
@@ -555,6 +667,7 @@ impl Modify for SecurityAddon {
             data::models::GeoTypes,
             data::models::ChunkMetadataWithPosition,
             data::models::ScoreChunkDTO,
+            data::models::ScoreExplanation,
             data::models::ChunkMetadataTypes,
             data::models::ContentChunkMetadata,
             data::models::ChunkMetadataStringTagSet,
@@ -563,6 +676,16 @@ impl Modify for SecurityAddon {
             data::models::HasChunkIDCondition,
             data::models::DistanceMetric,
             data::models::PublicDatasetOptions,
+            data::models::FallbackEndpoint,
+            data::models::RateLimits,
+            data::models::AccessControlConfig,
+            data::models::QueryRewrites,
+            data::models::QueryRedirect,
+            data::models::QuerySynonym,
+            data::models::EngagementRankingConfig,
+            data::models::AclDefaultPolicy,
+            data::models::RateLimit,
+            data::models::ApiOperation,
             data::models::Invitation,
             data::models::CrawlYoutubeOptions,
             data::models::RagQueryRatingsResponse,
@@ -582,11 +705,18 @@ impl Modify for SecurityAddon {
         (name = "File", description = "File endpoint. When files are uploaded, they are stored in S3 and broken up into chunks with text extraction from Apache Tika. You can upload files of pretty much any type up to 1GB in size. See chunking algorithm details at `docs.trieve.ai` for more information on how chunking works. Improved default chunking is on our roadmap."),
         (name = "Events", description = "Notifications endpoint. Files are uploaded asynchronously and events are sent to the user when the upload is complete."),
         (name = "Topic", description = "Topic chat endpoint. Think of topics as the storage system for gen-ai chat memory. Gen AI messages belong to topics."),
+        (name = "RAG Preset", description = "RAG preset endpoint. Presets are named bundles of prompts, model settings and retrieval options stored on a dataset which topics and messages can reference by name."),
+        (name = "Ingestion Job", description = "Ingestion job endpoint. Ingestion jobs track the chunks queued by a single create chunk, file upload or CSV/JSONL import request through the ingestion workers."),
+        (name = "Dead Letter", description = "Dead letter endpoint. Dead letters are worker messages which failed on every retry attempt. They can be inspected, edited and replayed or discarded."),
+        (name = "Trash", description = "Trash endpoint. Deleted datasets, groups and files stay in the trash with their chunks until the organization's retention window passes, and can be restored or purged until then."),
+        (name = "Dataset Webhook", description = "Dataset webhook endpoint. Webhooks receive signed POST requests when chunks, files, crawls and searches of a dataset produce events. Failed deliveries are retried with backoff and kept in a delivery log which can be replayed."),
+        (name = "Search Token", description = "Search token endpoint. Search tokens are short-lived signed tokens which browser clients can use in place of an api key."),
         (name = "Message", description = "Message chat endpoint. Messages are units belonging to a topic in the context of a chat with a LLM. There are system, user, and assistant messages."),
         (name = "Stripe", description = "Stripe endpoint. Used for the managed SaaS version of this app. Eventually this will become a micro-service. Reach out to the team using contact info found at `docs.trieve.ai` for more information."),
         (name = "Health", description = "Health check endpoint. Used to check if the server is up and running."),
         (name = "Metrics", description = "Metrics endpoint. Used to get information for monitoring"),
         (name = "Analytics", description = "Analytics endpoint. Used to get information for search and RAG analytics"),
+        (name = "Experiment", description = "Experiment endpoint. Experiments A/B test search and RAG configurations of a dataset by bucketing users into variants and comparing their analytics."),
     ),
 )]
 pub struct ApiDoc;
@@ -690,7 +820,19 @@ pub fn main() -> std::io::Result<()> {
         }
 
 
-        let (clickhouse_client, event_queue) = if std::env::var("USE_ANALYTICS").unwrap_or("false".to_string()).parse().unwrap_or(false) {
+        let (clickhouse_client, event_queue, analytics_backend) = if std::env::var("USE_ANALYTICS").unwrap_or("false".to_string()).parse().unwrap_or(false) && AnalyticsBackend::is_postgres_configured() {
+            log::info!("Analytics enabled with the postgres backend");
+
+            let postgres_analytics_store = PostgresAnalyticsStore {
+                pool: web::Data::new(pool.clone()),
+            };
+            postgres_analytics_store.start_retention_service();
+            let analytics_backend = AnalyticsBackend::Postgres(postgres_analytics_store);
+
+            let mut event_queue = EventQueue::from_analytics_backend(analytics_backend.clone());
+            event_queue.start_service();
+            (clickhouse::Client::default(), event_queue, analytics_backend)
+        } else if std::env::var("USE_ANALYTICS").unwrap_or("false".to_string()).parse().unwrap_or(false) {
             log::info!("Analytics enabled");
 
             let args  = SetupArgs {
@@ -715,11 +857,17 @@ pub fn main() -> std::io::Result<()> {
 
             let mut event_queue = EventQueue::new(clickhouse_client.clone());
             event_queue.start_service();
-            (clickhouse_client, event_queue)
+            let analytics_backend = AnalyticsBackend::ClickHouse(ClickHouseAnalyticsStore {
+                clickhouse_client: clickhouse_client.clone(),
+            });
+            (clickhouse_client, event_queue, analytics_backend)
         } else {
             log::info!("Analytics disabled");
-            (clickhouse::Client::default(), EventQueue::default())
+            (clickhouse::Client::default(), EventQueue::default(), AnalyticsBackend::Disabled)
         };
+        let event_queue = event_queue
+            .with_webhooks(redis_pool.clone())
+            .with_analytics_forwarding(redis_pool.clone());
 
         BKTreeCache::enforce_cache_ttl();
 
@@ -753,6 +901,8 @@ pub fn main() -> std::io::Result<()> {
             }
         };
 
+        let shutdown_pool = web::Data::new(pool.clone());
+
         HttpServer::new(move || {
             let mut env = Environment::new();
             minijinja_embed::load_templates!(&mut env);
@@ -774,6 +924,7 @@ pub fn main() -> std::io::Result<()> {
                 .app_data(web::Data::new(redis_pool.clone()))
                 .app_data(web::Data::new(event_queue.clone()))
                 .app_data(web::Data::new(clickhouse_client.clone()))
+                .app_data(web::Data::new(analytics_backend.clone()))
                 .app_data(web::Data::new(metrics.clone()))
                 .app_data(detector.clone())
                 .app_data(web::Data::new(broccoli_queue.clone()))
@@ -988,6 +1139,82 @@ pub fn main() -> std::io::Result<()> {
                             web::resource("/topic/owner/{user_id}")
                                 .route(web::get().to(handlers::topic_handler::get_all_topics_for_owner_id)),
                         )
+                        .service(
+                            web::resource("/rag_preset")
+                                .route(web::post().to(handlers::rag_preset_handler::create_rag_preset))
+                                .route(web::get().to(handlers::rag_preset_handler::get_rag_presets_for_dataset)),
+                        )
+                        .service(
+                            web::resource("/rag_preset/{preset_name}")
+                                .route(web::get().to(handlers::rag_preset_handler::get_rag_preset))
+                                .route(web::put().to(handlers::rag_preset_handler::update_rag_preset))
+                                .route(web::delete().to(handlers::rag_preset_handler::delete_rag_preset)),
+                        )
+                        .service(
+                            web::resource("/experiments")
+                                .route(web::post().to(handlers::experiment_handler::create_experiment))
+                                .route(web::get().to(handlers::experiment_handler::get_experiments)),
+                        )
+                        .service(
+                            web::resource("/experiments/{experiment_id}")
+                                .route(web::get().to(handlers::experiment_handler::get_experiment))
+                                .route(web::put().to(handlers::experiment_handler::update_experiment))
+                                .route(web::delete().to(handlers::experiment_handler::delete_experiment)),
+                        )
+                        .service(
+                            web::resource("/experiments/{experiment_id}/results")
+                                .route(web::get().to(handlers::experiment_handler::get_experiment_results)),
+                        )
+                        .service(
+                            web::resource("/ingestion_job/{ingestion_job_id}")
+                                .route(web::get().to(handlers::ingestion_job_handler::get_ingestion_job)),
+                        )
+                        .service(
+                            web::resource("/dead_letter")
+                                .route(web::get().to(handlers::dead_letter_handler::get_dead_letters)),
+                        )
+                        .service(
+                            web::resource("/dead_letter/{dead_letter_id}")
+                                .route(web::delete().to(handlers::dead_letter_handler::delete_dead_letter)),
+                        )
+                        .service(
+                            web::resource("/dead_letter/{dead_letter_id}/replay")
+                                .route(web::post().to(handlers::dead_letter_handler::replay_dead_letter)),
+                        )
+                        .service(
+                            web::resource("/trash")
+                                .route(web::get().to(handlers::trash_handler::get_trash)),
+                        )
+                        .service(
+                            web::resource("/trash/{trash_id}")
+                                .route(web::delete().to(handlers::trash_handler::purge_trash_item)),
+                        )
+                        .service(
+                            web::resource("/trash/{trash_id}/restore")
+                                .route(web::post().to(handlers::trash_handler::restore_trash_item)),
+                        )
+                        .service(
+                            web::resource("/dataset_webhook")
+                                .route(web::post().to(handlers::dataset_webhook_handler::create_dataset_webhook))
+                                .route(web::get().to(handlers::dataset_webhook_handler::get_dataset_webhooks))
+                                .route(web::put().to(handlers::dataset_webhook_handler::update_dataset_webhook)),
+                        )
+                        .service(
+                            web::resource("/dataset_webhook/delivery/{delivery_id}/redeliver")
+                                .route(web::post().to(handlers::dataset_webhook_handler::redeliver_webhook_delivery)),
+                        )
+                        .service(
+                            web::resource("/dataset_webhook/{webhook_id}")
+                                .route(web::delete().to(handlers::dataset_webhook_handler::delete_dataset_webhook)),
+                        )
+                        .service(
+                            web::resource("/dataset_webhook/{webhook_id}/deliveries")
+                                .route(web::get().to(handlers::dataset_webhook_handler::get_webhook_deliveries)),
+                        )
+                        .service(
+                            web::resource("/search_token")
+                                .route(web::post().to(handlers::search_token_handler::create_search_token)),
+                        )
                         .service(
                             web::resource("/message")
                                 .route(
@@ -1197,6 +1424,10 @@ pub fn main() -> std::io::Result<()> {
                                     web::resource("/csv_or_jsonl")
                                         .route(web::post().to(handlers::file_handler::create_presigned_url_for_csv_jsonl)),
                                 )
+                                .service(
+                                    web::resource("/csv_or_jsonl/{file_id}/errors")
+                                        .route(web::get().to(handlers::file_handler::get_csv_jsonl_error_file)),
+                                )
                                 .service(
                                     web::resource("/{file_id}")
                                         .route(web::get().to(handlers::file_handler::get_file_handler))
@@ -1249,6 +1480,18 @@ pub fn main() -> std::io::Result<()> {
                                             web::delete().to(handlers::organization_handler::delete_organization_api_key),
                                         ),
                                 )
+                                .service(
+                                    web::resource("/audit_logs")
+                                        .route(web::post().to(handlers::audit_log_handler::get_audit_logs)),
+                                )
+                                .service(
+                                    web::resource("/metered_usage")
+                                        .route(web::get().to(handlers::usage_handler::get_metered_usage)),
+                                )
+                                .service(
+                                    web::resource("/metered_usage/csv")
+                                        .route(web::get().to(handlers::usage_handler::get_metered_usage_csv)),
+                                )
                                 .service(
                                     web::resource("/{organization_id}/user/{user_id}")
                                         .route(web::delete().to(handlers::organization_handler::remove_user_from_org)),
@@ -1377,14 +1620,50 @@ pub fn main() -> std::io::Result<()> {
                                 web::resource("/ctr")
                                     .route(web::put().to(handlers::analytics_handler::send_ctr_data))
                             )
+                            .service(
+                                web::resource("/export")
+                                    .route(web::put().to(handlers::analytics_export_handler::upsert_analytics_export))
+                                    .route(web::get().to(handlers::analytics_export_handler::get_analytics_export))
+                                    .route(web::delete().to(handlers::analytics_export_handler::delete_analytics_export))
+                            )
+                            .service(
+                                web::resource("/export/runs")
+                                    .route(web::get().to(handlers::analytics_export_handler::get_analytics_export_runs))
+                            )
+                            .service(
+                                web::resource("/reports")
+                                    .route(web::put().to(handlers::analytics_report_handler::upsert_analytics_report_subscription))
+                                    .route(web::get().to(handlers::analytics_report_handler::get_analytics_report_subscription))
+                                    .route(web::delete().to(handlers::analytics_report_handler::delete_analytics_report_subscription))
+                            )
+                            .service(
+                                web::resource("/reports/send")
+                                    .route(web::post().to(handlers::analytics_report_handler::send_analytics_report_now))
+                            )
+                            .service(
+                                web::resource("/suggestions")
+                                    .route(web::get().to(handlers::query_suggestion_handler::get_query_suggestions))
+                            )
+                            .service(
+                                web::resource("/suggestions/{suggestion_id}/accept")
+                                    .route(web::post().to(handlers::query_suggestion_handler::accept_query_suggestion))
+                            )
+                            .service(
+                                web::resource("/suggestions/{suggestion_id}/dismiss")
+                                    .route(web::post().to(handlers::query_suggestion_handler::dismiss_query_suggestion))
+                            )
                         ),
                 )
         })
         .workers(num_workers)
         .bind(("0.0.0.0", 8090))?
         .run()
-        .await
+        .await?;
+
+        // Write out usage buffered since the last periodic flush before exiting
+        operators::usage_operator::flush_usage_buffer(&shutdown_pool).await;
 
+        Ok(())
     })?;
 
     Ok(())
@@ -1,27 +1,34 @@
 use crate::{
     data::models::{
-        ApiKeyRequestParams, Pool, RedisPool, SlimUser, UnifiedId, User, UserApiKey, UserRole,
+        with_required_filters, AccessControlConfig, AclPrincipals, ApiKeyRequestParams,
+        ApiOperation, Pool, RateLimits, RedisPool, SlimUser, UnifiedId, User, UserApiKey, UserRole,
     },
     errors::ServiceError,
     handlers::{
         auth_handler::{AdminOnly, LoggedUser, OrganizationRole, OwnerOnly},
-        chunk_handler::{AutocompleteReqPayload, ScrollChunksReqPayload, SearchChunksReqPayload},
+        chunk_handler::{
+            AutocompleteReqPayload, ChunkFilter, ScrollChunksReqPayload, SearchChunksReqPayload,
+        },
         group_handler::{SearchOverGroupsReqPayload, SearchWithinGroupReqPayload},
         message_handler::CreateMessageReqPayload,
     },
     operators::{
+        acl_operator::get_acl_principals,
         dataset_operator::get_dataset_and_organization_from_dataset_id_query,
         organization_operator::{
             get_arbitrary_org_owner_from_dataset_id, get_arbitrary_org_owner_from_org_id,
             get_assumed_user_by_organization_api_key, get_org_from_id_query,
         },
+        rate_limit_operator::{check_rate_limits, get_organization_rate_limits, RateLimitScope},
+        search_token_operator::{get_assumed_user_by_search_token, SEARCH_TOKEN_PREFIX},
+        usage_operator::{meter_usage, with_usage_meter},
         user_operator::{get_user_by_id_query, get_user_from_api_key_query},
     },
 };
 use actix_identity::Identity;
 use actix_web::{
     dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
-    http::header::HeaderMap,
+    http::{header::HeaderMap, Method},
     web::{self, Json},
     Error, FromRequest, HttpMessage,
 };
@@ -63,6 +70,14 @@ where
             if let Some(user) = user.clone() {
                 req.extensions_mut().insert(user);
             }
+            if let Some(user_api_key) = api_key.clone() {
+                req.extensions_mut().insert(user_api_key);
+            }
+            let api_key_id = api_key.as_ref().map(|user_api_key| user_api_key.id);
+            let operation = ApiOperation::from_request(req.method(), req.path());
+            let mut metered_dataset_id = None;
+            // Buckets are only charged once the caller is authorized for the dataset, so that nobody can drain another organization's limits
+            let mut pending_rate_limits = None;
 
             let org_id = match get_dataset_id_from_headers(req.headers()) {
                 Some(dataset_id) => {
@@ -97,6 +112,63 @@ where
                         }
                     };
 
+                    if let Some(operation) = operation {
+                        let mut limits = vec![];
+                        if let Some(ref user_api_key) = api_key {
+                            if let Some(limit) = user_api_key
+                                .params
+                                .clone()
+                                .and_then(|params| {
+                                    serde_json::from_value::<ApiKeyRequestParams>(params).ok()
+                                })
+                                .and_then(|params| params.rate_limits)
+                                .and_then(|rate_limits| rate_limits.get_limit(operation))
+                            {
+                                limits.push((RateLimitScope::ApiKey(user_api_key.id), limit));
+                            }
+                        }
+                        if let Some(limit) = RateLimits::from_json(
+                            dataset_org_plan_sub
+                                .dataset
+                                .server_configuration
+                                .get("RATE_LIMITS"),
+                        )
+                        .get_limit(operation)
+                        {
+                            limits.push((
+                                RateLimitScope::Dataset(dataset_org_plan_sub.dataset.id),
+                                limit,
+                            ));
+                        }
+                        if let Some(limit) = get_organization_rate_limits().get_limit(operation) {
+                            limits.push((
+                                RateLimitScope::Organization(
+                                    dataset_org_plan_sub.organization.organization.id,
+                                ),
+                                limit,
+                            ));
+                        }
+
+                        pending_rate_limits = Some((operation, limits));
+                    }
+
+                    let access_control = AccessControlConfig::from_json(
+                        dataset_org_plan_sub
+                            .dataset
+                            .server_configuration
+                            .get("ACCESS_CONTROL"),
+                    );
+                    let api_key_params = api_key
+                        .as_ref()
+                        .and_then(|user_api_key| user_api_key.params.clone())
+                        .and_then(|params| {
+                            serde_json::from_value::<ApiKeyRequestParams>(params).ok()
+                        });
+                    // Taken before a scoped api key is elevated to admin below, so a scope grants access to a route without letting the key pick which end user's chunks it sees
+                    let acl_role = user.as_ref().and_then(|user| {
+                        get_role_for_org(user, &dataset_org_plan_sub.organization.organization.id)
+                    });
+
                     if let Some(user_api_key) = api_key {
                         if let Some(api_key_org_ids) = user_api_key.organization_ids {
                             if !api_key_org_ids.is_empty()
@@ -157,9 +229,25 @@ where
                         }
                     }
 
+                    let acl_principals = if access_control.enabled {
+                        get_request_acl_principals(
+                            req.headers(),
+                            api_key_params,
+                            acl_role,
+                            access_control,
+                        )
+                    } else {
+                        AclPrincipals::default()
+                    };
+                    if let Some(acl_filters) = acl_principals.required_filters() {
+                        insert_acl_filters_payload(&mut req, acl_filters).await?;
+                    }
+
+                    req.extensions_mut().insert(acl_principals);
                     req.extensions_mut().insert(dataset_org_plan_sub.clone());
                     req.extensions_mut()
                         .insert(dataset_org_plan_sub.organization.clone());
+                    metered_dataset_id = Some(dataset_org_plan_sub.dataset.id);
 
                     dataset_org_plan_sub.organization.organization.id
                 }
@@ -197,9 +285,31 @@ where
                 }?;
 
                 req.extensions_mut().insert(org_role);
+
+                if let Some((operation, limits)) = pending_rate_limits {
+                    let redis_pool = req.app_data::<web::Data<RedisPool>>().unwrap().to_owned();
+                    check_rate_limits(operation, limits, &redis_pool).await?;
+                }
             }
 
-            let res = srv.call(req).await?;
+            let res = match metered_dataset_id {
+                Some(dataset_id) => {
+                    with_usage_meter(dataset_id, api_key_id, &pool, async move {
+                        let res = srv.call(req).await?;
+                        if res.status().is_success() {
+                            meter_usage(|usage| match operation {
+                                Some(ApiOperation::Search) => usage.search_calls += 1,
+                                Some(ApiOperation::Rag) => usage.rag_calls += 1,
+                                _ => {}
+                            });
+                        }
+
+                        Ok::<_, Error>(res)
+                    })
+                    .await?
+                }
+                None => srv.call(req).await?,
+            };
 
             Ok(res)
         })
@@ -331,11 +441,27 @@ async fn auth_with_api_key(
             }
         }
 
+        if authen_header.starts_with(SEARCH_TOKEN_PREFIX) {
+            let (user, api_key) =
+                get_assumed_user_by_search_token(authen_header.as_str(), pool.clone()).await?;
+            if let Some(ref api_key_params) = api_key.params {
+                let params: ApiKeyRequestParams =
+                    serde_json::from_value(api_key_params.clone()).unwrap();
+                params.check_route(req.method(), req.path())?;
+                insert_api_key_payload(req, params).await.map_err(|_| {
+                    ServiceError::BadRequest("Could not insert api key payload".to_string())
+                })?;
+            }
+            return Ok((Some(user), Some(api_key)));
+        }
+
         if let Ok((user, api_key)) =
             get_assumed_user_by_organization_api_key(authen_header.as_str(), pool.clone()).await
         {
             if let Some(ref api_key_params) = api_key.params {
-                let params = serde_json::from_value(api_key_params.clone()).unwrap();
+                let params: ApiKeyRequestParams =
+                    serde_json::from_value(api_key_params.clone()).unwrap();
+                params.check_route(req.method(), req.path())?;
                 insert_api_key_payload(req, params).await.map_err(|_| {
                     ServiceError::BadRequest("Could not insert api key payload".to_string())
                 })?;
@@ -348,7 +474,9 @@ async fn auth_with_api_key(
             get_user_from_api_key_query(authen_header.as_str(), pool.clone()).await
         {
             if let Some(ref api_key_params) = api_key.params {
-                let params = serde_json::from_value(api_key_params.clone()).unwrap();
+                let params: ApiKeyRequestParams =
+                    serde_json::from_value(api_key_params.clone()).unwrap();
+                params.check_route(req.method(), req.path())?;
                 insert_api_key_payload(req, params).await.map_err(|_| {
                     ServiceError::BadRequest("Could not insert api key payload".to_string())
                 })?;
@@ -415,6 +543,111 @@ pub async fn insert_api_key_payload(
     Ok(())
 }
 
+/// Paths of the routes whose filters are combined with the end user's access control filter in `insert_acl_filters_payload`.
+const ACL_ENFORCED_ROUTES: [&str; 10] = [
+    "/api/chunk/search",
+    "/api/chunk/autocomplete",
+    "/api/chunk/recommend",
+    "/api/chunk/count",
+    "/api/chunk/suggestions",
+    "/api/chunks/scroll",
+    "/api/chunk_group/search",
+    "/api/chunk_group/group_oriented_search",
+    "/api/chunk_group/recommend",
+    "/api/message",
+];
+
+fn get_user_groups_from_headers(headers: &HeaderMap) -> Option<Vec<String>> {
+    headers
+        .get("TR-User-Groups")
+        .and_then(|user_groups| user_groups.to_str().ok())
+        .map(|user_groups| {
+            user_groups
+                .split(',')
+                .map(|group| group.trim().to_string())
+                .filter(|group| !group.is_empty())
+                .collect()
+        })
+}
+
+/// Principals of the end user a request to a dataset with access control enabled is made for. Api keys and search tokens with a `user_id` or `user_groups` always use those. Otherwise the `TR-User-Id` and `TR-User-Groups` headers are only trusted from admins, who are not restricted when they send neither, and everyone else only sees chunks without an access control list.
+fn get_request_acl_principals(
+    headers: &HeaderMap,
+    api_key_params: Option<ApiKeyRequestParams>,
+    role: Option<UserRole>,
+    access_control: AccessControlConfig,
+) -> AclPrincipals {
+    if let Some(params) =
+        api_key_params.filter(|params| params.user_id.is_some() || params.user_groups.is_some())
+    {
+        return get_acl_principals(params.user_id, params.user_groups, access_control);
+    }
+
+    if !role.is_some_and(|role| role >= UserRole::Admin) {
+        return get_acl_principals(None, None, access_control);
+    }
+
+    let user_id = headers
+        .get("TR-User-Id")
+        .and_then(|user_id| user_id.to_str().ok())
+        .map(|user_id| user_id.to_string());
+    let user_groups = get_user_groups_from_headers(headers);
+    if user_id.is_none() && user_groups.is_none() {
+        return AclPrincipals::default();
+    }
+
+    get_acl_principals(user_id, user_groups, access_control)
+}
+
+/// ANDs the access control filter with the filters in the body of requests to `ACL_ENFORCED_ROUTES`.
+async fn insert_acl_filters_payload(
+    req: &mut ServiceRequest,
+    acl_filters: ChunkFilter,
+) -> Result<(), ServiceError> {
+    if req.method() == Method::GET
+        || !ACL_ENFORCED_ROUTES.contains(&req.path().trim_end_matches('/'))
+    {
+        return Ok(());
+    }
+
+    let body = req
+        .extract::<web::Bytes>()
+        .await
+        .map_err(|_| ServiceError::BadRequest("Could not read request body".to_string()))?;
+
+    let mut body_json = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(body_json) => body_json,
+        Err(_) => {
+            // Leave invalid bodies for the handler to reject
+            req.set_payload(bytes_to_payload(body));
+            return Ok(());
+        }
+    };
+
+    if let Some(body_object) = body_json.as_object_mut() {
+        let filters = body_object
+            .remove("filters")
+            .filter(|filters| !filters.is_null())
+            .map(serde_json::from_value::<ChunkFilter>)
+            .transpose()
+            .map_err(|err| ServiceError::BadRequest(format!("Invalid filters: {}", err)))?;
+
+        body_object.insert(
+            "filters".to_string(),
+            serde_json::to_value(with_required_filters(filters, Some(acl_filters))).map_err(
+                |_| ServiceError::InternalServerError("Failed to serialize filters".to_string()),
+            )?,
+        );
+    }
+
+    let body_bytes = serde_json::to_vec(&body_json).map_err(|_| {
+        ServiceError::InternalServerError("Failed to serialize request body".to_string())
+    })?;
+    req.set_payload(bytes_to_payload(body_bytes.into()));
+
+    Ok(())
+}
+
 pub struct AuthMiddlewareFactory;
 
 impl<S, B> Transform<S, ServiceRequest> for AuthMiddlewareFactory
@@ -5,6 +5,7 @@ use actix_web::{
     middleware::Next,
     web,
 };
+use simple_server_timing_header::Timer;
 
 pub async fn error_logging_middleware(
     metrics: web::Data<Metrics>,
@@ -12,6 +13,7 @@ pub async fn error_logging_middleware(
     next: Next<impl MessageBody>,
 ) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
     let path = req.path().to_string();
+    let route = req.match_pattern().unwrap_or_else(|| path.clone());
     let method = req.method().to_string();
     let base_server_url =
         std::env::var("BASE_SERVER_URL").unwrap_or_else(|_| "https://api.trieve.ai".to_string());
@@ -23,6 +25,12 @@ pub async fn error_logging_middleware(
             let status = response.status();
             if !status.is_success() {
                 metrics.register_error(status.as_u16(), method, path, base_server_url);
+            } else if let Some(server_timing) = response
+                .headers()
+                .get(Timer::header_key())
+                .and_then(|server_timing| server_timing.to_str().ok())
+            {
+                metrics.observe_search_stages(server_timing, &route);
             }
 
             Ok(response)
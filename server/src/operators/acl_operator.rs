@@ -0,0 +1,180 @@
+use crate::{
+    data::models::{AccessControlConfig, AclDefaultPolicy, AclPrincipals},
+    errors::ServiceError,
+};
+use qdrant_client::qdrant::{Condition, Filter};
+
+/// Principal written to the Qdrant payload of chunks without an access control list so the dataset's default policy can be applied with the same keyword match as every other principal.
+pub const NO_ACL_PRINCIPAL: &str = "trieve:no_acl";
+
+/// Checks that every entry of an access control list is either a `user:<user_id>` or a `group:<group_name>` principal.
+pub fn validate_acl(acl: &[String]) -> Result<(), ServiceError> {
+    for principal in acl {
+        let is_valid = match principal.split_once(':') {
+            Some(("user", name)) | Some(("group", name)) => !name.trim().is_empty(),
+            _ => false,
+        };
+
+        if !is_valid {
+            return Err(ServiceError::BadRequest(format!(
+                "Invalid acl principal {}. Principals must be formatted as user:<user_id> or group:<group_name>",
+                principal
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Principals an access control list is stored with in Qdrant. Chunks without one get `NO_ACL_PRINCIPAL`.
+pub fn get_qdrant_acl(acl: Option<Vec<Option<String>>>) -> Vec<String> {
+    let acl = acl
+        .unwrap_or_default()
+        .into_iter()
+        .flatten()
+        .collect::<Vec<String>>();
+
+    if acl.is_empty() {
+        vec![NO_ACL_PRINCIPAL.to_string()]
+    } else {
+        acl
+    }
+}
+
+/// Condition matching the points whose `acl` contains one of the principals. Points indexed before access control lists were added have no `acl` in their payload and are treated as having none.
+pub fn get_acl_filter_condition(principals: Vec<String>) -> Condition {
+    if principals.is_empty() {
+        // An empty match any is dropped by qdrant, so use a contradiction which matches no points
+        return Filter {
+            must: vec![Condition::is_empty("acl")],
+            must_not: vec![Condition::is_empty("acl")],
+            ..Default::default()
+        }
+        .into();
+    }
+
+    let mut conditions = vec![];
+    if principals
+        .iter()
+        .any(|principal| principal == NO_ACL_PRINCIPAL)
+    {
+        conditions.push(Condition::is_empty("acl"));
+    }
+    conditions.push(Condition::matches("acl", principals));
+
+    Filter::should(conditions).into()
+}
+
+/// Principals of an end user with the given id and groups, including `NO_ACL_PRINCIPAL` when the dataset's default policy allows chunks without an access control list.
+pub fn get_acl_principals(
+    user_id: Option<String>,
+    user_groups: Option<Vec<String>>,
+    access_control: AccessControlConfig,
+) -> AclPrincipals {
+    let mut principals = vec![];
+
+    if let Some(user_id) = user_id.filter(|user_id| !user_id.trim().is_empty()) {
+        principals.push(format!("user:{}", user_id.trim()));
+    }
+
+    principals.extend(
+        user_groups
+            .unwrap_or_default()
+            .iter()
+            .filter(|group| !group.trim().is_empty())
+            .map(|group| format!("group:{}", group.trim())),
+    );
+
+    if access_control.default_policy == AclDefaultPolicy::Allow {
+        principals.push(NO_ACL_PRINCIPAL.to_string());
+    }
+
+    AclPrincipals(Some(principals))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn acl(principals: &[&str]) -> Option<Vec<Option<String>>> {
+        Some(
+            principals
+                .iter()
+                .map(|principal| Some(principal.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    pub fn test_validate_acl() {
+        assert!(validate_acl(&["user:alice".to_string(), "group:sales".to_string()]).is_ok());
+        assert!(validate_acl(&["alice".to_string()]).is_err());
+        assert!(validate_acl(&["group: ".to_string()]).is_err());
+        assert!(validate_acl(&["role:admin".to_string()]).is_err());
+    }
+
+    #[test]
+    pub fn test_can_access() {
+        let allow = get_acl_principals(
+            Some("alice".to_string()),
+            Some(vec!["sales".to_string()]),
+            AccessControlConfig {
+                enabled: true,
+                default_policy: AclDefaultPolicy::Allow,
+            },
+        );
+        assert!(allow.can_access(&acl(&["user:alice"])));
+        assert!(allow.can_access(&acl(&["user:bob", "group:sales"])));
+        assert!(!allow.can_access(&acl(&["user:bob"])));
+        assert!(allow.can_access(&None));
+        assert!(allow.can_access(&Some(vec![])));
+
+        let deny = get_acl_principals(
+            Some("alice".to_string()),
+            None,
+            AccessControlConfig {
+                enabled: true,
+                default_policy: AclDefaultPolicy::Deny,
+            },
+        );
+        assert!(deny.can_access(&acl(&["user:alice"])));
+        assert!(!deny.can_access(&None));
+        assert!(!deny.can_access(&acl(&["group:sales"])));
+
+        assert!(AclPrincipals(None).can_access(&acl(&["user:bob"])));
+    }
+
+    #[test]
+    pub fn test_get_acl_filter_condition() {
+        assert_eq!(
+            get_acl_filter_condition(vec!["user:alice".to_string()]),
+            Filter::should(vec![Condition::matches(
+                "acl",
+                vec!["user:alice".to_string()]
+            )])
+            .into()
+        );
+
+        assert_eq!(
+            get_acl_filter_condition(vec!["user:alice".to_string(), NO_ACL_PRINCIPAL.to_string()]),
+            Filter::should(vec![
+                Condition::is_empty("acl"),
+                Condition::matches(
+                    "acl",
+                    vec!["user:alice".to_string(), NO_ACL_PRINCIPAL.to_string()]
+                ),
+            ])
+            .into()
+        );
+
+        assert_eq!(
+            get_acl_filter_condition(vec![]),
+            Filter {
+                must: vec![Condition::is_empty("acl")],
+                must_not: vec![Condition::is_empty("acl")],
+                ..Default::default()
+            }
+            .into()
+        );
+    }
+}
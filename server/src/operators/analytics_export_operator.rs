@@ -0,0 +1,538 @@
+use crate::{
+    data::models::{
+        AnalyticsExportConfig, AnalyticsExportEventType, AnalyticsExportFormat, AnalyticsExportRun,
+        AnalyticsExportRunStatus, AnalyticsForwardMessage, AnalyticsForwardSink,
+        EventDataClickhouse, EventDataPostgres, Pool, RagQueryEventPostgres, RecommendationEvent,
+        SearchQueryEventPostgres,
+    },
+    errors::ServiceError,
+    operators::{
+        analytics_store_operator::{AnalyticsBackend, AnalyticsStore},
+        clickhouse_operator::ClickHouseEvent,
+        dataset_webhook_operator::validate_webhook_url,
+        file_operator::get_analytics_export_aws_bucket,
+    },
+};
+use actix_web::web;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Redis list the analytics export worker consumes forwarded events from.
+pub const ANALYTICS_FORWARD_QUEUE: &str = "analytics_forward_queue";
+/// Events which are being forwarded. They are pushed back onto the forward queue if the worker dies mid request.
+pub const ANALYTICS_FORWARD_PROCESSING_QUEUE: &str = "analytics_forward_processing";
+/// Sorted set of events waiting to be retried, scored by the unix timestamp of their next attempt.
+pub const ANALYTICS_FORWARD_RETRY_SET: &str = "analytics_forward_retry";
+/// Forwarded events which failed this many times become dead letters.
+pub const MAX_ANALYTICS_FORWARD_ATTEMPTS: usize = 8;
+/// How long a failed scheduled export waits before its window is exported again.
+pub const ANALYTICS_EXPORT_RETRY_MINUTES: i64 = 5;
+
+pub async fn validate_analytics_export_config(
+    config: &AnalyticsExportConfig,
+) -> Result<(), ServiceError> {
+    if !(5..=7 * 24 * 60).contains(&config.interval_minutes) {
+        return Err(ServiceError::BadRequest(
+            "interval_minutes must be between 5 and 10080".to_string(),
+        ));
+    }
+
+    // Forward urls are fetched by the analytics export worker, so they are held to the same rules as webhook urls
+    if let Some(forward_url) = &config.forward_url {
+        validate_webhook_url(forward_url).await?;
+    }
+
+    Ok(())
+}
+
+/// Creates the export config of the dataset or replaces the existing one. Replacing a config keeps its watermark so no events are skipped or exported twice.
+pub async fn upsert_analytics_export_config_query(
+    config: AnalyticsExportConfig,
+    pool: &web::Data<Pool>,
+) -> Result<AnalyticsExportConfig, ServiceError> {
+    use crate::data::schema::analytics_export_configs::dsl as export_configs_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::insert_into(export_configs_columns::analytics_export_configs)
+        .values(&config)
+        .on_conflict(export_configs_columns::dataset_id)
+        .do_update()
+        .set((
+            export_configs_columns::format.eq(excluded(export_configs_columns::format)),
+            export_configs_columns::event_types.eq(excluded(export_configs_columns::event_types)),
+            export_configs_columns::interval_minutes
+                .eq(excluded(export_configs_columns::interval_minutes)),
+            export_configs_columns::forward_url.eq(excluded(export_configs_columns::forward_url)),
+            export_configs_columns::forward_sink.eq(excluded(export_configs_columns::forward_sink)),
+            export_configs_columns::forward_auth_header
+                .eq(excluded(export_configs_columns::forward_auth_header)),
+            export_configs_columns::active.eq(excluded(export_configs_columns::active)),
+            export_configs_columns::updated_at.eq(excluded(export_configs_columns::updated_at)),
+        ))
+        .get_result::<AnalyticsExportConfig>(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Error saving analytics export config {:?}", err);
+            ServiceError::BadRequest("Error saving analytics export config".to_string())
+        })
+}
+
+pub async fn get_analytics_export_config_query(
+    dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<AnalyticsExportConfig, ServiceError> {
+    use crate::data::schema::analytics_export_configs::dsl as export_configs_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    export_configs_columns::analytics_export_configs
+        .filter(export_configs_columns::dataset_id.eq(dataset_id))
+        .select(AnalyticsExportConfig::as_select())
+        .first::<AnalyticsExportConfig>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::NotFound("Analytics export config not found".to_string()))
+}
+
+/// Deletes the export config of the dataset along with its run history. Files already exported are left in object storage.
+pub async fn delete_analytics_export_config_query(
+    dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::analytics_export_configs::dsl as export_configs_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let deleted = diesel::delete(
+        export_configs_columns::analytics_export_configs
+            .filter(export_configs_columns::dataset_id.eq(dataset_id)),
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(|_| ServiceError::BadRequest("Error deleting analytics export config".to_string()))?;
+
+    if deleted == 0 {
+        return Err(ServiceError::NotFound(
+            "Analytics export config not found".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct AnalyticsExportRunWithUrls {
+    pub run: AnalyticsExportRun,
+    /// Presigned urls to download the files of the run, valid for one hour.
+    pub download_urls: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct AnalyticsExportRunsResponse {
+    pub runs: Vec<AnalyticsExportRunWithUrls>,
+    pub total_pages: i64,
+}
+
+pub async fn get_analytics_export_runs_query(
+    dataset_id: uuid::Uuid,
+    page: u64,
+    pool: &web::Data<Pool>,
+) -> Result<AnalyticsExportRunsResponse, ServiceError> {
+    use crate::data::schema::analytics_export_runs::dsl as export_runs_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let page = page.max(1);
+
+    let total_runs = export_runs_columns::analytics_export_runs
+        .filter(export_runs_columns::dataset_id.eq(dataset_id))
+        .count()
+        .get_result::<i64>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting analytics export runs".to_string()))?;
+
+    let runs = export_runs_columns::analytics_export_runs
+        .filter(export_runs_columns::dataset_id.eq(dataset_id))
+        .order_by(export_runs_columns::created_at.desc())
+        .offset(((page - 1) * 10) as i64)
+        .limit(10)
+        .select(AnalyticsExportRun::as_select())
+        .load::<AnalyticsExportRun>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting analytics export runs".to_string()))?;
+
+    let bucket = get_analytics_export_aws_bucket()?;
+    let mut runs_with_urls = vec![];
+    for run in runs {
+        let mut download_urls = vec![];
+        for file in run.files.iter() {
+            download_urls.push(bucket.presign_get(file, 3600, None).await.map_err(|e| {
+                log::error!("Error getting signed url: {}", e);
+                ServiceError::BadRequest(format!("Error getting signed url: {}", e))
+            })?);
+        }
+        runs_with_urls.push(AnalyticsExportRunWithUrls { run, download_urls });
+    }
+
+    Ok(AnalyticsExportRunsResponse {
+        runs: runs_with_urls,
+        total_pages: (total_runs as f64 / 10.0).ceil() as i64,
+    })
+}
+
+/// Claims active configs whose next export is due by pushing their `next_export_at` out by `lease`, so concurrent workers do not export the same window. An export which crashes mid run is picked up again once the lease expires.
+pub async fn claim_due_analytics_exports_query(
+    limit: i64,
+    lease: chrono::Duration,
+    pool: &web::Data<Pool>,
+) -> Result<Vec<AnalyticsExportConfig>, ServiceError> {
+    use crate::data::schema::analytics_export_configs::dsl as export_configs_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let now = chrono::Utc::now().naive_utc();
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        async move {
+            let due_ids = export_configs_columns::analytics_export_configs
+                .filter(export_configs_columns::active.eq(true))
+                .filter(export_configs_columns::next_export_at.le(now))
+                .order_by(export_configs_columns::next_export_at.asc())
+                .limit(limit)
+                .select(export_configs_columns::id)
+                .for_update()
+                .skip_locked()
+                .load::<uuid::Uuid>(conn)
+                .await?;
+
+            diesel::update(
+                export_configs_columns::analytics_export_configs
+                    .filter(export_configs_columns::id.eq_any(&due_ids)),
+            )
+            .set(export_configs_columns::next_export_at.eq(now + lease))
+            .get_results::<AnalyticsExportConfig>(conn)
+            .await
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(|err| {
+        log::error!("Error claiming analytics exports {:?}", err);
+        ServiceError::BadRequest("Error claiming analytics exports".to_string())
+    })
+}
+
+/// Parquet columns need one type per column, so nested JSON values are written as JSON strings.
+fn flatten_export_row(row: serde_json::Value) -> serde_json::Value {
+    match row {
+        serde_json::Value::Object(fields) => serde_json::Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        serde_json::Value::Object(_) => {
+                            serde_json::Value::String(value.to_string())
+                        }
+                        serde_json::Value::Array(ref values)
+                            if values
+                                .iter()
+                                .any(|value| value.is_object() || value.is_array()) =>
+                        {
+                            serde_json::Value::String(value.to_string())
+                        }
+                        value => value,
+                    };
+                    (key, value)
+                })
+                .collect(),
+        ),
+        row => row,
+    }
+}
+
+fn parquet_error(err: impl std::fmt::Debug) -> ServiceError {
+    log::error!("Error writing parquet file {:?}", err);
+    ServiceError::InternalServerError("Error writing parquet file".to_string())
+}
+
+fn write_parquet_file(rows: Vec<serde_json::Value>) -> Result<Vec<u8>, ServiceError> {
+    let rows: Vec<serde_json::Value> = rows.into_iter().map(flatten_export_row).collect();
+
+    let schema = arrow_json::reader::infer_json_schema_from_iterator(rows.iter().map(Ok)).map_err(
+        |err| {
+            log::error!("Error inferring parquet schema {:?}", err);
+            ServiceError::InternalServerError("Error inferring parquet schema".to_string())
+        },
+    )?;
+    let schema = std::sync::Arc::new(schema);
+
+    let mut decoder = arrow_json::ReaderBuilder::new(schema.clone())
+        .build_decoder()
+        .map_err(parquet_error)?;
+    let mut buffer = vec![];
+    let mut writer = ArrowWriter::try_new(&mut buffer, schema, None).map_err(parquet_error)?;
+
+    for batch_rows in rows.chunks(1024) {
+        decoder.serialize(batch_rows).map_err(parquet_error)?;
+        if let Some(batch) = decoder.flush().map_err(parquet_error)? {
+            writer.write(&batch).map_err(parquet_error)?;
+        }
+    }
+
+    writer.close().map_err(parquet_error)?;
+
+    Ok(buffer)
+}
+
+fn write_export_file(
+    rows: Vec<serde_json::Value>,
+    format: AnalyticsExportFormat,
+) -> Result<Vec<u8>, ServiceError> {
+    match format {
+        AnalyticsExportFormat::Jsonl => {
+            let mut buffer = vec![];
+            for row in rows {
+                serde_json::to_writer(&mut buffer, &row).map_err(|_| {
+                    ServiceError::InternalServerError("Error writing jsonl file".to_string())
+                })?;
+                buffer.push(b'\n');
+            }
+            Ok(buffer)
+        }
+        AnalyticsExportFormat::Parquet => write_parquet_file(rows),
+    }
+}
+
+/// Exports every configured table from the config's watermark up to now, one file per table. The watermark only advances once every file is uploaded, so a failed run is exported again in full and delivery is at least once.
+pub async fn run_analytics_export_query(
+    config: AnalyticsExportConfig,
+    analytics_backend: &AnalyticsBackend,
+    pool: &web::Data<Pool>,
+) -> Result<AnalyticsExportRun, ServiceError> {
+    use crate::data::schema::analytics_export_configs::dsl as export_configs_columns;
+    use crate::data::schema::analytics_export_runs::dsl as export_runs_columns;
+
+    let window_start = config.last_exported_at;
+    let window_end = chrono::Utc::now().naive_utc();
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let run = diesel::insert_into(export_runs_columns::analytics_export_runs)
+        .values(AnalyticsExportRun::from_details(
+            config.id,
+            config.dataset_id,
+            window_start,
+            window_end,
+        ))
+        .get_result::<AnalyticsExportRun>(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Error creating analytics export run {:?}", err);
+            ServiceError::BadRequest("Error creating analytics export run".to_string())
+        })?;
+
+    let export_result = export_window(&config, window_start, window_end, analytics_backend).await;
+
+    let now = chrono::Utc::now().naive_utc();
+    let (status, files, row_count, error, next_export_at) = match export_result {
+        Ok((files, row_count)) => (
+            AnalyticsExportRunStatus::Succeeded,
+            files,
+            row_count,
+            None,
+            window_end + chrono::Duration::minutes(config.interval_minutes as i64),
+        ),
+        Err(err) => (
+            AnalyticsExportRunStatus::Failed,
+            vec![],
+            0,
+            Some(err.to_string()),
+            now + chrono::Duration::minutes(ANALYTICS_EXPORT_RETRY_MINUTES),
+        ),
+    };
+
+    let succeeded = status == AnalyticsExportRunStatus::Succeeded;
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        async move {
+            let run = diesel::update(
+                export_runs_columns::analytics_export_runs
+                    .filter(export_runs_columns::id.eq(run.id)),
+            )
+            .set((
+                export_runs_columns::status.eq(status.to_string()),
+                export_runs_columns::files.eq(files),
+                export_runs_columns::row_count.eq(row_count),
+                export_runs_columns::error.eq(error),
+                export_runs_columns::updated_at.eq(now),
+            ))
+            .get_result::<AnalyticsExportRun>(conn)
+            .await?;
+
+            let config_update = diesel::update(
+                export_configs_columns::analytics_export_configs
+                    .filter(export_configs_columns::id.eq(config.id)),
+            );
+            if succeeded {
+                config_update
+                    .set((
+                        export_configs_columns::last_exported_at.eq(window_end),
+                        export_configs_columns::next_export_at.eq(next_export_at),
+                    ))
+                    .execute(conn)
+                    .await?;
+            } else {
+                config_update
+                    .set(export_configs_columns::next_export_at.eq(next_export_at))
+                    .execute(conn)
+                    .await?;
+            }
+
+            Ok(run)
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(|err| {
+        log::error!("Error finishing analytics export run {:?}", err);
+        ServiceError::BadRequest("Error finishing analytics export run".to_string())
+    })
+}
+
+async fn export_window(
+    config: &AnalyticsExportConfig,
+    window_start: chrono::NaiveDateTime,
+    window_end: chrono::NaiveDateTime,
+    analytics_backend: &AnalyticsBackend,
+) -> Result<(Vec<String>, i64), ServiceError> {
+    let format = AnalyticsExportFormat::from_name(&config.format);
+    let bucket = get_analytics_export_aws_bucket()?;
+
+    let mut files = vec![];
+    let mut row_count = 0;
+
+    for event_type in config
+        .event_types
+        .iter()
+        .filter_map(|event_type| AnalyticsExportEventType::from_name(event_type))
+    {
+        let rows = analytics_backend
+            .get_events_for_export(config.dataset_id, event_type, window_start, window_end)
+            .await?;
+
+        if rows.is_empty() {
+            continue;
+        }
+
+        row_count += rows.len() as i64;
+
+        let key = format!(
+            "analytics-exports/{}/{}/{}_{}.{}",
+            config.dataset_id,
+            event_type,
+            window_start.and_utc().timestamp(),
+            window_end.and_utc().timestamp(),
+            format
+        );
+        let file = write_export_file(rows, format)?;
+
+        bucket
+            .put_object_with_content_type(&key, file.as_slice(), format.content_type())
+            .await
+            .map_err(|e| {
+                log::error!("Could not upload analytics export to S3 {:?}", e);
+                ServiceError::BadRequest("Could not upload analytics export to S3".to_string())
+            })?;
+
+        files.push(key);
+    }
+
+    Ok((files, row_count))
+}
+
+/// The message forwarded for an analytics event. Worker and audit log events are not analytics events and are not forwarded.
+pub fn get_analytics_forward_message(event: &ClickHouseEvent) -> Option<AnalyticsForwardMessage> {
+    let (id, dataset_id, event_type, event) = match event {
+        ClickHouseEvent::SearchQueryEvent(event) => (
+            event.id,
+            event.dataset_id,
+            AnalyticsExportEventType::SearchQueries,
+            serde_json::to_value(SearchQueryEventPostgres::from(event.clone())),
+        ),
+        ClickHouseEvent::RagQueryEvent(event) => (
+            event.id,
+            event.dataset_id,
+            AnalyticsExportEventType::RagQueries,
+            serde_json::to_value(RagQueryEventPostgres::from(event.clone())),
+        ),
+        ClickHouseEvent::RecommendationEvent(event) => (
+            event.id,
+            event.dataset_id,
+            AnalyticsExportEventType::Recommendations,
+            serde_json::to_value(RecommendationEvent::from(event.clone())),
+        ),
+        ClickHouseEvent::WorkerEvent(_) | ClickHouseEvent::AuditLogEvent(_) => return None,
+    };
+
+    Some(AnalyticsForwardMessage {
+        id,
+        dataset_id,
+        event_type,
+        event: event.ok()?,
+        attempt_number: 0,
+    })
+}
+
+pub fn get_event_data_forward_message(event: &EventDataClickhouse) -> AnalyticsForwardMessage {
+    AnalyticsForwardMessage {
+        id: event.id,
+        dataset_id: event.dataset_id,
+        event_type: AnalyticsExportEventType::Events,
+        event: serde_json::to_value(EventDataPostgres::from(event.clone()))
+            .unwrap_or(serde_json::Value::Null),
+        attempt_number: 0,
+    }
+}
+
+/// Body POSTed to the forward url. The event id doubles as an idempotency key since retried events are sent again with the same id.
+pub fn get_analytics_forward_body(
+    message: &AnalyticsForwardMessage,
+    sink: AnalyticsForwardSink,
+) -> serde_json::Value {
+    let event = serde_json::json!({
+        "id": message.id,
+        "dataset_id": message.dataset_id,
+        "event_type": message.event_type,
+        "event": message.event,
+    });
+
+    match sink {
+        AnalyticsForwardSink::Http => event,
+        AnalyticsForwardSink::KafkaRest => serde_json::json!({
+            "records": [{ "key": message.dataset_id, "value": event }]
+        }),
+    }
+}
+
+/// Exponential backoff starting at 10 seconds and capped at one hour.
+pub fn get_analytics_forward_retry_delay(attempt_number: usize) -> std::time::Duration {
+    let delay_secs = 10u64.saturating_mul(2u64.saturating_pow(attempt_number.max(1) as u32 - 1));
+    std::time::Duration::from_secs(delay_secs.min(60 * 60))
+}
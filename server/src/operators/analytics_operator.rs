@@ -20,6 +20,7 @@ use crate::{
     handlers::analytics_handler::{GetTopDatasetsRequestBody, RateQueryRequest},
 };
 use actix_web::web;
+use clickhouse::Row;
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use futures::future::join_all;
@@ -349,6 +350,51 @@ pub async fn get_no_result_queries_query(
     Ok(SearchQueryResponse { queries })
 }
 
+/// Like `get_head_queries_query`, but only counts searches which found nothing.
+pub async fn get_no_result_head_queries_query(
+    dataset_id: uuid::Uuid,
+    filter: Option<SearchAnalyticsFilter>,
+    page: Option<u32>,
+    clickhouse_client: &clickhouse::Client,
+) -> Result<HeadQueryResponse, ServiceError> {
+    let mut query_string = String::from(
+        "SELECT 
+            query, 
+            count(*) AS count
+        FROM 
+            search_queries
+        WHERE dataset_id = ? AND top_score = 0 AND search_queries.is_duplicate = 0",
+    );
+
+    if let Some(filter) = filter {
+        query_string = filter.add_to_query(query_string);
+    }
+
+    query_string.push_str(
+        " GROUP BY 
+            query
+        ORDER BY 
+            count DESC
+        LIMIT 10
+        OFFSET ?",
+    );
+
+    let no_result_queries = clickhouse_client
+        .query(query_string.as_str())
+        .bind(dataset_id)
+        .bind((page.unwrap_or(1) - 1) * 10)
+        .fetch_all::<HeadQueries>()
+        .await
+        .map_err(|e| {
+            log::error!("Error fetching query: {:?}", e);
+            ServiceError::InternalServerError("Error fetching query".to_string())
+        })?;
+
+    Ok(HeadQueryResponse {
+        queries: no_result_queries,
+    })
+}
+
 pub async fn get_all_queries_query(
     dataset_id: uuid::Uuid,
     filter: Option<SearchAnalyticsFilter>,
@@ -1123,6 +1169,98 @@ pub async fn get_search_ctr_metrics_query(
     Ok(clickhouse_query.into())
 }
 
+#[derive(Debug, Row, Serialize, Deserialize)]
+struct SearchCTRTrendPointClickhouse {
+    #[serde(with = "clickhouse::serde::time::datetime")]
+    time_stamp: time::OffsetDateTime,
+    searches: u64,
+    searches_with_clicks: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SearchCTRTrendPoint {
+    pub time_stamp: String,
+    pub searches: u64,
+    pub searches_with_clicks: u64,
+    pub percent_searches_with_clicks: f64,
+}
+
+impl From<SearchCTRTrendPointClickhouse> for SearchCTRTrendPoint {
+    fn from(point: SearchCTRTrendPointClickhouse) -> Self {
+        SearchCTRTrendPoint {
+            time_stamp: point.time_stamp.to_string(),
+            searches: point.searches,
+            searches_with_clicks: point.searches_with_clicks,
+            percent_searches_with_clicks: if point.searches > 0 {
+                100.0 * point.searches_with_clicks as f64 / point.searches as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Share of searches with at least one click per interval.
+pub async fn get_search_ctr_trend_query(
+    dataset_id: uuid::Uuid,
+    filter: Option<SearchAnalyticsFilter>,
+    granularity: Option<Granularity>,
+    clickhouse_client: &clickhouse::Client,
+) -> Result<Vec<SearchCTRTrendPoint>, ServiceError> {
+    let granularity = granularity.unwrap_or(Granularity::Day);
+    let interval = match granularity {
+        Granularity::Second => "1 SECOND",
+        Granularity::Minute => "1 MINUTE",
+        Granularity::Hour => "1 HOUR",
+        Granularity::Day => "1 DAY",
+        Granularity::Month => "1 MONTH",
+    };
+
+    let mut query_string = format!(
+        "SELECT 
+            CAST(toStartOfInterval(created_at, INTERVAL {}) AS DateTime) AS time_stamp,
+            count(*) AS searches,
+            countIf(id IN (
+                SELECT toUUIDOrZero(request_id)
+                FROM events
+                WHERE dataset_id = ? AND event_type = 'click'
+            )) AS searches_with_clicks
+        FROM 
+            search_queries
+        WHERE 
+            dataset_id = ? AND is_duplicate = 0
+        ",
+        interval
+    );
+
+    if let Some(filter) = filter {
+        query_string = filter.add_to_query(query_string);
+    }
+
+    query_string.push_str(
+        "
+        GROUP BY 
+            time_stamp
+        ORDER BY 
+            time_stamp
+        LIMIT
+            1000",
+    );
+
+    let clickhouse_query = clickhouse_client
+        .query(query_string.as_str())
+        .bind(dataset_id)
+        .bind(dataset_id)
+        .fetch_all::<SearchCTRTrendPointClickhouse>()
+        .await
+        .map_err(|e| {
+            log::error!("Error fetching query: {:?}", e);
+            ServiceError::InternalServerError("Error fetching query".to_string())
+        })?;
+
+    Ok(clickhouse_query.into_iter().map(|q| q.into()).collect())
+}
+
 pub async fn get_searches_with_clicks_query(
     dataset_id: uuid::Uuid,
     page: Option<u32>,
@@ -0,0 +1,489 @@
+use crate::{
+    data::models::{
+        AnalyticsReportSchedule, AnalyticsReportSubscription, Dataset, DateRange, HeadQueries,
+        IngestionBatchStatus, Pool, RAGAnalyticsFilter, SearchAnalyticsFilter,
+    },
+    errors::ServiceError,
+    operators::{
+        analytics_operator::{
+            get_head_queries_query, get_no_result_head_queries_query, get_rag_query_ratings_query,
+            get_rag_usage_query, get_search_ctr_trend_query, get_search_metrics_query,
+            SearchCTRTrendPoint,
+        },
+        email_operator::send_email,
+    },
+};
+use actix_web::web;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use minijinja::{context, Environment};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Number of distinct ingestion errors quoted in a report.
+const MAX_REPORTED_INGESTION_ERRORS: usize = 5;
+
+pub async fn upsert_analytics_report_subscription_query(
+    subscription: AnalyticsReportSubscription,
+    pool: &web::Data<Pool>,
+) -> Result<AnalyticsReportSubscription, ServiceError> {
+    use crate::data::schema::analytics_report_subscriptions::dsl as report_subscriptions_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::insert_into(report_subscriptions_columns::analytics_report_subscriptions)
+        .values(&subscription)
+        .on_conflict((
+            report_subscriptions_columns::user_id,
+            report_subscriptions_columns::dataset_id,
+        ))
+        .do_update()
+        .set((
+            report_subscriptions_columns::schedule
+                .eq(excluded(report_subscriptions_columns::schedule)),
+            report_subscriptions_columns::active.eq(excluded(report_subscriptions_columns::active)),
+            report_subscriptions_columns::next_send_at
+                .eq(excluded(report_subscriptions_columns::next_send_at)),
+            report_subscriptions_columns::updated_at
+                .eq(excluded(report_subscriptions_columns::updated_at)),
+        ))
+        .get_result::<AnalyticsReportSubscription>(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Error saving analytics report subscription {:?}", err);
+            ServiceError::BadRequest("Error saving analytics report subscription".to_string())
+        })
+}
+
+pub async fn get_analytics_report_subscription_query(
+    user_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<AnalyticsReportSubscription, ServiceError> {
+    use crate::data::schema::analytics_report_subscriptions::dsl as report_subscriptions_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    report_subscriptions_columns::analytics_report_subscriptions
+        .filter(report_subscriptions_columns::user_id.eq(user_id))
+        .filter(report_subscriptions_columns::dataset_id.eq(dataset_id))
+        .select(AnalyticsReportSubscription::as_select())
+        .first(&mut conn)
+        .await
+        .map_err(|_db_error| {
+            ServiceError::NotFound(
+                "You are not subscribed to analytics reports of this dataset".to_string(),
+            )
+        })
+}
+
+pub async fn delete_analytics_report_subscription_query(
+    user_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::analytics_report_subscriptions::dsl as report_subscriptions_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let deleted_count = diesel::delete(
+        report_subscriptions_columns::analytics_report_subscriptions
+            .filter(report_subscriptions_columns::user_id.eq(user_id))
+            .filter(report_subscriptions_columns::dataset_id.eq(dataset_id)),
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(|_db_error| {
+        ServiceError::BadRequest("Error deleting analytics report subscription".to_string())
+    })?;
+
+    if deleted_count == 0 {
+        return Err(ServiceError::NotFound(
+            "You are not subscribed to analytics reports of this dataset".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Claims up to `limit` subscriptions whose report is due by pushing their next send time out by `lease`, so concurrent cronjobs do not send the same report twice. A report which fails to send is retried once the lease expires.
+pub async fn claim_due_analytics_reports_query(
+    limit: i64,
+    lease: chrono::Duration,
+    pool: &web::Data<Pool>,
+) -> Result<Vec<AnalyticsReportSubscription>, ServiceError> {
+    use crate::data::schema::analytics_report_subscriptions::dsl as report_subscriptions_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let now = chrono::Utc::now().naive_utc();
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        async move {
+            let due_ids = report_subscriptions_columns::analytics_report_subscriptions
+                .filter(report_subscriptions_columns::active.eq(true))
+                .filter(report_subscriptions_columns::next_send_at.le(now))
+                .order_by(report_subscriptions_columns::next_send_at.asc())
+                .limit(limit)
+                .select(report_subscriptions_columns::id)
+                .for_update()
+                .skip_locked()
+                .load::<uuid::Uuid>(conn)
+                .await?;
+
+            diesel::update(
+                report_subscriptions_columns::analytics_report_subscriptions
+                    .filter(report_subscriptions_columns::id.eq_any(&due_ids)),
+            )
+            .set(report_subscriptions_columns::next_send_at.eq(now + lease))
+            .get_results::<AnalyticsReportSubscription>(conn)
+            .await
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(|err| {
+        log::error!("Error claiming analytics reports {:?}", err);
+        ServiceError::BadRequest("Error claiming analytics reports".to_string())
+    })
+}
+
+pub async fn set_analytics_report_sent_query(
+    subscription: &AnalyticsReportSubscription,
+    sent_at: chrono::NaiveDateTime,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::analytics_report_subscriptions::dsl as report_subscriptions_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::update(
+        report_subscriptions_columns::analytics_report_subscriptions
+            .filter(report_subscriptions_columns::id.eq(subscription.id)),
+    )
+    .set((
+        report_subscriptions_columns::last_sent_at.eq(sent_at),
+        report_subscriptions_columns::next_send_at.eq(sent_at + subscription.schedule().period()),
+    ))
+    .execute(&mut conn)
+    .await
+    .map_err(|err| {
+        log::error!("Error marking analytics report as sent {:?}", err);
+        ServiceError::BadRequest("Error marking analytics report as sent".to_string())
+    })?;
+
+    Ok(())
+}
+
+pub async fn deactivate_analytics_report_subscription_query(
+    subscription_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::analytics_report_subscriptions::dsl as report_subscriptions_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::update(
+        report_subscriptions_columns::analytics_report_subscriptions
+            .filter(report_subscriptions_columns::id.eq(subscription_id)),
+    )
+    .set(report_subscriptions_columns::active.eq(false))
+    .execute(&mut conn)
+    .await
+    .map_err(|_db_error| {
+        ServiceError::BadRequest("Error deactivating analytics report subscription".to_string())
+    })?;
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+/// Ingestion batches of the dataset which failed during the report window.
+pub struct IngestionErrorSummary {
+    pub failed_batches: i64,
+    pub failed_chunks: i64,
+    /// The most common distinct errors of the failed batches.
+    pub errors: Vec<String>,
+}
+
+async fn get_ingestion_error_summary_query(
+    dataset_id: uuid::Uuid,
+    window_start: chrono::NaiveDateTime,
+    window_end: chrono::NaiveDateTime,
+    pool: &web::Data<Pool>,
+) -> Result<IngestionErrorSummary, ServiceError> {
+    use crate::data::schema::ingestion_job_batches::dsl as ingestion_job_batches_columns;
+    use crate::data::schema::ingestion_jobs::dsl as ingestion_jobs_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let failed_batches: Vec<(i32, Option<String>)> =
+        ingestion_job_batches_columns::ingestion_job_batches
+            .inner_join(ingestion_jobs_columns::ingestion_jobs)
+            .filter(ingestion_jobs_columns::dataset_id.eq(dataset_id))
+            .filter(
+                ingestion_job_batches_columns::status.eq(IngestionBatchStatus::Failed.to_string()),
+            )
+            .filter(ingestion_job_batches_columns::updated_at.ge(window_start))
+            .filter(ingestion_job_batches_columns::updated_at.lt(window_end))
+            .select((
+                ingestion_job_batches_columns::chunk_count,
+                ingestion_job_batches_columns::error,
+            ))
+            .load(&mut conn)
+            .await
+            .map_err(|_db_error| {
+                ServiceError::BadRequest("Error getting failed ingestion batches".to_string())
+            })?;
+
+    let mut error_counts: Vec<(String, usize)> = vec![];
+    for error in failed_batches.iter().filter_map(|(_, error)| error.clone()) {
+        match error_counts.iter_mut().find(|(seen, _)| *seen == error) {
+            Some((_, count)) => *count += 1,
+            None => error_counts.push((error, 1)),
+        }
+    }
+    error_counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    Ok(IngestionErrorSummary {
+        failed_batches: failed_batches.len() as i64,
+        failed_chunks: failed_batches
+            .iter()
+            .map(|(chunk_count, _)| *chunk_count as i64)
+            .sum(),
+        errors: error_counts
+            .into_iter()
+            .take(MAX_REPORTED_INGESTION_ERRORS)
+            .map(|(error, _)| error)
+            .collect(),
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+/// Digest of a dataset's search, RAG and ingestion activity over a window, compared to the window before it.
+pub struct AnalyticsReport {
+    pub dataset_id: uuid::Uuid,
+    pub dataset_name: String,
+    pub window_start: chrono::NaiveDateTime,
+    pub window_end: chrono::NaiveDateTime,
+    pub total_searches: i64,
+    pub previous_total_searches: i64,
+    /// Percent change of the search volume compared to the previous window. Null if there were no searches in the previous window.
+    pub search_volume_change: Option<f64>,
+    pub avg_latency: f64,
+    pub top_queries: Vec<HeadQueries>,
+    pub no_result_queries: Vec<HeadQueries>,
+    /// Percent of the window's searches with at least one click.
+    pub percent_searches_with_clicks: f64,
+    pub previous_percent_searches_with_clicks: f64,
+    /// Click-through rate of each day of the window.
+    pub ctr_trend: Vec<SearchCTRTrendPoint>,
+    pub rag_queries: u32,
+    /// Null if no RAG completions of the window were rated.
+    pub rag_percent_thumbs_up: Option<f64>,
+    pub rag_percent_thumbs_down: Option<f64>,
+    pub ingestion_errors: IngestionErrorSummary,
+}
+
+fn date_range(window_start: chrono::NaiveDateTime, window_end: chrono::NaiveDateTime) -> DateRange {
+    DateRange {
+        gte: Some(window_start.format("%Y-%m-%d %H:%M:%S").to_string()),
+        lt: Some(window_end.format("%Y-%m-%d %H:%M:%S").to_string()),
+        gt: None,
+        lte: None,
+    }
+}
+
+fn search_filter(
+    window_start: chrono::NaiveDateTime,
+    window_end: chrono::NaiveDateTime,
+) -> Option<SearchAnalyticsFilter> {
+    Some(SearchAnalyticsFilter {
+        date_range: Some(date_range(window_start, window_end)),
+        search_method: None,
+        search_type: None,
+    })
+}
+
+fn percent_with_clicks(ctr_trend: &[SearchCTRTrendPoint]) -> f64 {
+    let searches: u64 = ctr_trend.iter().map(|point| point.searches).sum();
+    let searches_with_clicks: u64 = ctr_trend
+        .iter()
+        .map(|point| point.searches_with_clicks)
+        .sum();
+
+    if searches == 0 {
+        return 0.0;
+    }
+
+    100.0 * searches_with_clicks as f64 / searches as f64
+}
+
+pub async fn build_analytics_report(
+    dataset: &Dataset,
+    window_start: chrono::NaiveDateTime,
+    window_end: chrono::NaiveDateTime,
+    clickhouse_client: &clickhouse::Client,
+    pool: &web::Data<Pool>,
+) -> Result<AnalyticsReport, ServiceError> {
+    let previous_window_start = window_start - (window_end - window_start);
+
+    let search_metrics = get_search_metrics_query(
+        dataset.id,
+        search_filter(window_start, window_end),
+        clickhouse_client,
+    )
+    .await?;
+    let previous_search_metrics = get_search_metrics_query(
+        dataset.id,
+        search_filter(previous_window_start, window_start),
+        clickhouse_client,
+    )
+    .await?;
+
+    let top_queries = get_head_queries_query(
+        dataset.id,
+        search_filter(window_start, window_end),
+        None,
+        clickhouse_client,
+    )
+    .await?;
+    let no_result_queries = get_no_result_head_queries_query(
+        dataset.id,
+        search_filter(window_start, window_end),
+        None,
+        clickhouse_client,
+    )
+    .await?;
+
+    let ctr_trend = get_search_ctr_trend_query(
+        dataset.id,
+        search_filter(window_start, window_end),
+        None,
+        clickhouse_client,
+    )
+    .await?;
+    let previous_ctr_trend = get_search_ctr_trend_query(
+        dataset.id,
+        search_filter(previous_window_start, window_start),
+        None,
+        clickhouse_client,
+    )
+    .await?;
+
+    let rag_filter = || {
+        Some(RAGAnalyticsFilter {
+            date_range: Some(date_range(window_start, window_end)),
+            rag_type: None,
+        })
+    };
+    let rag_usage = get_rag_usage_query(dataset.id, rag_filter(), clickhouse_client).await?;
+    let rag_ratings =
+        get_rag_query_ratings_query(dataset.id, rag_filter(), clickhouse_client).await?;
+
+    let ingestion_errors =
+        get_ingestion_error_summary_query(dataset.id, window_start, window_end, pool).await?;
+
+    Ok(AnalyticsReport {
+        dataset_id: dataset.id,
+        dataset_name: dataset.name.clone(),
+        window_start,
+        window_end,
+        total_searches: search_metrics.total_queries,
+        previous_total_searches: previous_search_metrics.total_queries,
+        search_volume_change: if previous_search_metrics.total_queries > 0 {
+            Some(
+                100.0
+                    * (search_metrics.total_queries - previous_search_metrics.total_queries) as f64
+                    / previous_search_metrics.total_queries as f64,
+            )
+        } else {
+            None
+        },
+        avg_latency: if search_metrics.avg_latency.is_finite() {
+            search_metrics.avg_latency
+        } else {
+            0.0
+        },
+        top_queries: top_queries.queries,
+        no_result_queries: no_result_queries.queries,
+        percent_searches_with_clicks: percent_with_clicks(&ctr_trend),
+        previous_percent_searches_with_clicks: percent_with_clicks(&previous_ctr_trend),
+        ctr_trend,
+        rag_queries: rag_usage.total_queries,
+        rag_percent_thumbs_up: Some(rag_ratings.percent_thumbs_up)
+            .filter(|percent| percent.is_finite()),
+        rag_percent_thumbs_down: Some(rag_ratings.percent_thumbs_down)
+            .filter(|percent| percent.is_finite()),
+        ingestion_errors,
+    })
+}
+
+pub fn render_analytics_report(
+    report: &AnalyticsReport,
+    templates: &Environment<'_>,
+) -> Result<String, ServiceError> {
+    let dashboard_url =
+        std::env::var("ADMIN_DASHBOARD_URL").unwrap_or("https://dashboard.trieve.ai".to_string());
+
+    let templ = templates
+        .get_template("analytics_report.html")
+        .map_err(|e| ServiceError::InternalServerError(format!("Error getting template {}", e)))?;
+
+    templ
+        .render(context! {
+            report,
+            dashboard_url => format!("{}/dataset/{}/analytics", dashboard_url.trim_end_matches('/'), report.dataset_id),
+        })
+        .map_err(|e| ServiceError::InternalServerError(format!("Error rendering template {}", e)))
+}
+
+pub fn analytics_report_subject(schedule: AnalyticsReportSchedule, dataset: &Dataset) -> String {
+    format!(
+        "Your {} Trieve analytics report for {}",
+        schedule, dataset.name
+    )
+}
+
+/// Builds the report of the subscription's latest window and emails it to `email`. Returns the end of the window, which becomes the start of the next report's window.
+pub async fn send_analytics_report(
+    subscription: &AnalyticsReportSubscription,
+    dataset: &Dataset,
+    email: String,
+    templates: &Environment<'_>,
+    clickhouse_client: &clickhouse::Client,
+    pool: &web::Data<Pool>,
+) -> Result<chrono::NaiveDateTime, ServiceError> {
+    let window_end = chrono::Utc::now().naive_utc();
+    let window_start = subscription
+        .last_sent_at
+        .unwrap_or(window_end - subscription.schedule().period());
+
+    let report =
+        build_analytics_report(dataset, window_start, window_end, clickhouse_client, pool).await?;
+    let html_email_body = render_analytics_report(&report, templates)?;
+
+    send_email(
+        html_email_body,
+        email,
+        Some(analytics_report_subject(subscription.schedule(), dataset)),
+    )?;
+
+    Ok(window_end)
+}
@@ -0,0 +1,1445 @@
+use std::future::Future;
+
+use crate::{
+    data::{
+        models::{
+            AnalyticsExportEventType, AuditLog, AuditLogFilter, AuditLogPostgres, DateRange,
+            EventDataClickhouse, EventDataPostgres, EventTypeRequest, Granularity, HeadQueries,
+            Pool, RAGAnalyticsFilter, RAGUsageGraphResponse, RAGUsageResponse,
+            RagQueryEventClickhouse, RagQueryEventPostgres, RecommendationEvent,
+            RecommendationEventClickhouse, RecommendationEventPostgres, SearchAnalyticsFilter,
+            SearchCTRMetrics, SearchQueryEvent, SearchQueryEventClickhouse,
+            SearchQueryEventPostgres, UsageGraphPoint, WorkerEvent, WorkerEventPostgres,
+        },
+        schema::analytics_audit_logs,
+    },
+    errors::ServiceError,
+    operators::{
+        analytics_operator::{
+            get_head_queries_query, get_no_result_queries_query, get_rag_usage_graph_query,
+            get_rag_usage_query, get_search_ctr_metrics_query, get_search_usage_graph_query,
+            send_event_data_query, HeadQueryResponse, SearchQueryResponse,
+            SearchUsageGraphResponse,
+        },
+        audit_log_operator::{get_audit_logs_query, AuditLogsResponse},
+        clickhouse_operator::{send_to_clickhouse, ClickHouseEvent},
+        event_operator::{get_events_query, EventReturn},
+        ingestion_job_operator::get_ingestion_job_events_query,
+    },
+};
+use actix_web::web;
+use chrono::NaiveDateTime;
+use dateparser::DateTimeUtc;
+use diesel::{
+    pg::Pg,
+    prelude::*,
+    query_builder::{BoxedSqlQuery, SqlQuery},
+    sql_types::{BigInt, Double, Nullable, Text, Timestamp},
+};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+
+/// Where analytics events are written and where the core analytics views are read from.
+///
+/// ClickHouse remains the full featured backend. Other backends store every event type and
+/// implement the subset needed for search and RAG usage, head queries, no result queries, CTR,
+/// dataset events, ingestion job failures and audit logs so self-hosted deployments without
+/// ClickHouse still get a working analytics dashboard.
+pub trait AnalyticsStore {
+    fn write_events(
+        &self,
+        events: Vec<ClickHouseEvent>,
+    ) -> impl Future<Output = Result<(), ServiceError>> + Send;
+
+    fn write_event_data(
+        &self,
+        event_data: EventDataClickhouse,
+    ) -> impl Future<Output = Result<(), ServiceError>> + Send;
+
+    fn get_search_usage_graph(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        granularity: Option<Granularity>,
+    ) -> impl Future<Output = Result<SearchUsageGraphResponse, ServiceError>> + Send;
+
+    fn get_head_queries(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        page: Option<u32>,
+    ) -> impl Future<Output = Result<HeadQueryResponse, ServiceError>> + Send;
+
+    fn get_no_result_queries(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        page: Option<u32>,
+    ) -> impl Future<Output = Result<SearchQueryResponse, ServiceError>> + Send;
+
+    fn get_rag_usage(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<RAGAnalyticsFilter>,
+    ) -> impl Future<Output = Result<RAGUsageResponse, ServiceError>> + Send;
+
+    fn get_rag_usage_graph(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<RAGAnalyticsFilter>,
+        granularity: Option<Granularity>,
+    ) -> impl Future<Output = Result<RAGUsageGraphResponse, ServiceError>> + Send;
+
+    fn get_search_ctr_metrics(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+    ) -> impl Future<Output = Result<SearchCTRMetrics, ServiceError>> + Send;
+
+    fn get_worker_events(
+        &self,
+        dataset_id: uuid::Uuid,
+        page: i64,
+        page_size: i64,
+        event_types: Vec<EventTypeRequest>,
+    ) -> impl Future<Output = Result<EventReturn, ServiceError>> + Send;
+
+    /// The ingestion worker events of an ingestion job, oldest first.
+    fn get_ingestion_job_events(
+        &self,
+        dataset_id: uuid::Uuid,
+        ingestion_job_id: uuid::Uuid,
+    ) -> impl Future<Output = Result<Vec<WorkerEvent>, ServiceError>> + Send;
+
+    fn get_audit_logs(
+        &self,
+        organization_id: uuid::Uuid,
+        filter: Option<AuditLogFilter>,
+        page: u64,
+        page_size: u64,
+    ) -> impl Future<Output = Result<AuditLogsResponse, ServiceError>> + Send;
+
+    /// Events of one table created at or after `window_start` and before `window_end`, oldest
+    /// first, as flat JSON rows ready to be written to an export file.
+    fn get_events_for_export(
+        &self,
+        dataset_id: uuid::Uuid,
+        event_type: AnalyticsExportEventType,
+        window_start: NaiveDateTime,
+        window_end: NaiveDateTime,
+    ) -> impl Future<Output = Result<Vec<serde_json::Value>, ServiceError>> + Send;
+}
+
+fn to_export_rows<T: Serialize>(
+    rows: impl IntoIterator<Item = T>,
+) -> Result<Vec<serde_json::Value>, ServiceError> {
+    rows.into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            log::error!("Error serializing export rows: {:?}", e);
+            ServiceError::InternalServerError("Error serializing export rows".to_string())
+        })
+}
+
+fn export_fetch_error(e: impl std::fmt::Debug) -> ServiceError {
+    log::error!("Error fetching events for export: {:?}", e);
+    ServiceError::InternalServerError("Error fetching events for export".to_string())
+}
+
+#[derive(Clone)]
+pub struct ClickHouseAnalyticsStore {
+    pub clickhouse_client: clickhouse::Client,
+}
+
+impl AnalyticsStore for ClickHouseAnalyticsStore {
+    async fn write_events(&self, events: Vec<ClickHouseEvent>) -> Result<(), ServiceError> {
+        send_to_clickhouse(events, &self.clickhouse_client).await
+    }
+
+    async fn write_event_data(&self, event_data: EventDataClickhouse) -> Result<(), ServiceError> {
+        send_event_data_query(event_data, &self.clickhouse_client).await
+    }
+
+    async fn get_search_usage_graph(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        granularity: Option<Granularity>,
+    ) -> Result<SearchUsageGraphResponse, ServiceError> {
+        get_search_usage_graph_query(dataset_id, filter, granularity, &self.clickhouse_client).await
+    }
+
+    async fn get_head_queries(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        page: Option<u32>,
+    ) -> Result<HeadQueryResponse, ServiceError> {
+        get_head_queries_query(dataset_id, filter, page, &self.clickhouse_client).await
+    }
+
+    async fn get_no_result_queries(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        page: Option<u32>,
+    ) -> Result<SearchQueryResponse, ServiceError> {
+        get_no_result_queries_query(dataset_id, filter, page, &self.clickhouse_client).await
+    }
+
+    async fn get_rag_usage(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<RAGAnalyticsFilter>,
+    ) -> Result<RAGUsageResponse, ServiceError> {
+        get_rag_usage_query(dataset_id, filter, &self.clickhouse_client).await
+    }
+
+    async fn get_rag_usage_graph(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<RAGAnalyticsFilter>,
+        granularity: Option<Granularity>,
+    ) -> Result<RAGUsageGraphResponse, ServiceError> {
+        get_rag_usage_graph_query(dataset_id, filter, granularity, &self.clickhouse_client).await
+    }
+
+    async fn get_search_ctr_metrics(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+    ) -> Result<SearchCTRMetrics, ServiceError> {
+        get_search_ctr_metrics_query(dataset_id, filter, &self.clickhouse_client).await
+    }
+
+    async fn get_worker_events(
+        &self,
+        dataset_id: uuid::Uuid,
+        page: i64,
+        page_size: i64,
+        event_types: Vec<EventTypeRequest>,
+    ) -> Result<EventReturn, ServiceError> {
+        get_events_query(
+            dataset_id,
+            page,
+            page_size,
+            event_types,
+            &self.clickhouse_client,
+        )
+        .await
+    }
+
+    async fn get_ingestion_job_events(
+        &self,
+        dataset_id: uuid::Uuid,
+        ingestion_job_id: uuid::Uuid,
+    ) -> Result<Vec<WorkerEvent>, ServiceError> {
+        get_ingestion_job_events_query(ingestion_job_id, dataset_id, &self.clickhouse_client).await
+    }
+
+    async fn get_audit_logs(
+        &self,
+        organization_id: uuid::Uuid,
+        filter: Option<AuditLogFilter>,
+        page: u64,
+        page_size: u64,
+    ) -> Result<AuditLogsResponse, ServiceError> {
+        get_audit_logs_query(
+            organization_id,
+            filter,
+            page,
+            page_size,
+            &self.clickhouse_client,
+        )
+        .await
+    }
+
+    async fn get_events_for_export(
+        &self,
+        dataset_id: uuid::Uuid,
+        event_type: AnalyticsExportEventType,
+        window_start: NaiveDateTime,
+        window_end: NaiveDateTime,
+    ) -> Result<Vec<serde_json::Value>, ServiceError> {
+        let query_string = format!(
+            "SELECT ?fields
+            FROM {}
+            WHERE dataset_id = ?
+                AND created_at >= toDateTime(?)
+                AND created_at < toDateTime(?)
+            ORDER BY created_at",
+            event_type
+        );
+        let query = self
+            .clickhouse_client
+            .query(&query_string)
+            .bind(dataset_id)
+            .bind(window_start.and_utc().timestamp())
+            .bind(window_end.and_utc().timestamp());
+
+        match event_type {
+            AnalyticsExportEventType::SearchQueries => to_export_rows(
+                query
+                    .fetch_all::<SearchQueryEventClickhouse>()
+                    .await
+                    .map_err(export_fetch_error)?
+                    .into_iter()
+                    .map(SearchQueryEventPostgres::from),
+            ),
+            AnalyticsExportEventType::RagQueries => to_export_rows(
+                query
+                    .fetch_all::<RagQueryEventClickhouse>()
+                    .await
+                    .map_err(export_fetch_error)?
+                    .into_iter()
+                    .map(RagQueryEventPostgres::from),
+            ),
+            AnalyticsExportEventType::Recommendations => to_export_rows(
+                query
+                    .fetch_all::<RecommendationEventClickhouse>()
+                    .await
+                    .map_err(export_fetch_error)?
+                    .into_iter()
+                    .map(RecommendationEvent::from),
+            ),
+            AnalyticsExportEventType::Events => to_export_rows(
+                query
+                    .fetch_all::<EventDataClickhouse>()
+                    .await
+                    .map_err(export_fetch_error)?
+                    .into_iter()
+                    .map(EventDataPostgres::from),
+            ),
+        }
+    }
+}
+
+/// Stores every analytics event type in Postgres tables next to the rest of the dataset. Rows
+/// older than `POSTGRES_ANALYTICS_RETENTION_DAYS` (audit logs `POSTGRES_AUDIT_LOG_RETENTION_DAYS`)
+/// are deleted by `start_retention_service`.
+#[derive(Clone)]
+pub struct PostgresAnalyticsStore {
+    pub pool: web::Data<Pool>,
+}
+
+#[derive(Debug, QueryableByName)]
+struct UsageGraphPointPostgres {
+    #[diesel(sql_type = Timestamp)]
+    time_stamp: NaiveDateTime,
+    #[diesel(sql_type = BigInt)]
+    requests: i64,
+}
+
+impl From<UsageGraphPointPostgres> for UsageGraphPoint {
+    fn from(point: UsageGraphPointPostgres) -> Self {
+        UsageGraphPoint {
+            time_stamp: point.time_stamp.to_string(),
+            requests: point.requests,
+        }
+    }
+}
+
+#[derive(Debug, QueryableByName)]
+struct HeadQueriesPostgres {
+    #[diesel(sql_type = Text)]
+    query: String,
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+#[derive(Debug, QueryableByName)]
+struct RAGUsagePostgres {
+    #[diesel(sql_type = BigInt)]
+    total_queries: i64,
+}
+
+#[derive(Debug, QueryableByName)]
+struct SearchCTRMetricsPostgres {
+    #[diesel(sql_type = BigInt)]
+    searches_with_clicks: i64,
+    #[diesel(sql_type = Double)]
+    percent_searches_with_clicks: f64,
+    #[diesel(sql_type = Double)]
+    percent_searches_without_clicks: f64,
+    #[diesel(sql_type = Double)]
+    avg_position_of_click: f64,
+}
+
+/// Date range bounds take `$2` through `$5`, the dataset id is always `$1`.
+const DATE_RANGE_CLAUSE: &str = "
+    AND ($2::timestamp IS NULL OR created_at > $2)
+    AND ($3::timestamp IS NULL OR created_at < $3)
+    AND ($4::timestamp IS NULL OR created_at >= $4)
+    AND ($5::timestamp IS NULL OR created_at <= $5)";
+
+const SEARCH_FILTER_CLAUSE: &str = "
+    AND ($6::text IS NULL OR search_type = $6)
+    AND ($7::text IS NULL OR search_method = $7)";
+
+const RAG_FILTER_CLAUSE: &str = "
+    AND ($6::text IS NULL OR rag_type = $6)";
+
+fn parse_filter_date(date: &Option<String>) -> Result<Option<NaiveDateTime>, ServiceError> {
+    date.as_ref()
+        .map(|date| {
+            date.parse::<DateTimeUtc>()
+                .map(|date| date.0.naive_utc())
+                .map_err(|_| ServiceError::BadRequest(format!("Invalid date in range: {}", date)))
+        })
+        .transpose()
+}
+
+fn bind_date_range(
+    query: BoxedSqlQuery<'static, Pg, SqlQuery>,
+    date_range: Option<DateRange>,
+) -> Result<BoxedSqlQuery<'static, Pg, SqlQuery>, ServiceError> {
+    let date_range = date_range.unwrap_or(DateRange {
+        gt: None,
+        lt: None,
+        gte: None,
+        lte: None,
+    });
+
+    Ok(query
+        .bind::<Nullable<Timestamp>, _>(parse_filter_date(&date_range.gt)?)
+        .bind::<Nullable<Timestamp>, _>(parse_filter_date(&date_range.lt)?)
+        .bind::<Nullable<Timestamp>, _>(parse_filter_date(&date_range.gte)?)
+        .bind::<Nullable<Timestamp>, _>(parse_filter_date(&date_range.lte)?))
+}
+
+/// Binds `$1` through `$7` for a query built with `DATE_RANGE_CLAUSE` and `SEARCH_FILTER_CLAUSE`.
+fn bind_search_filter(
+    query_string: String,
+    dataset_id: uuid::Uuid,
+    filter: Option<SearchAnalyticsFilter>,
+) -> Result<BoxedSqlQuery<'static, Pg, SqlQuery>, ServiceError> {
+    let query = diesel::sql_query(query_string)
+        .into_boxed::<Pg>()
+        .bind::<diesel::sql_types::Uuid, _>(dataset_id);
+
+    let (date_range, search_type, search_method) = match filter {
+        Some(filter) => (
+            filter.date_range,
+            filter
+                .search_type
+                .map(|search_type| search_type.to_string()),
+            filter
+                .search_method
+                .map(|search_method| search_method.to_string()),
+        ),
+        None => (None, None, None),
+    };
+
+    Ok(bind_date_range(query, date_range)?
+        .bind::<Nullable<Text>, _>(search_type)
+        .bind::<Nullable<Text>, _>(search_method))
+}
+
+/// Binds `$1` through `$6` for a query built with `DATE_RANGE_CLAUSE` and `RAG_FILTER_CLAUSE`.
+fn bind_rag_filter(
+    query_string: String,
+    dataset_id: uuid::Uuid,
+    filter: Option<RAGAnalyticsFilter>,
+) -> Result<BoxedSqlQuery<'static, Pg, SqlQuery>, ServiceError> {
+    let query = diesel::sql_query(query_string)
+        .into_boxed::<Pg>()
+        .bind::<diesel::sql_types::Uuid, _>(dataset_id);
+
+    let (date_range, rag_type) = match filter {
+        Some(filter) => (
+            filter.date_range,
+            filter.rag_type.map(|rag_type| rag_type.to_string()),
+        ),
+        None => (None, None),
+    };
+
+    Ok(bind_date_range(query, date_range)?.bind::<Nullable<Text>, _>(rag_type))
+}
+
+fn get_audit_logs_filter_query(
+    organization_id: uuid::Uuid,
+    filter: &AuditLogFilter,
+) -> Result<analytics_audit_logs::BoxedQuery<'static, Pg>, ServiceError> {
+    use crate::data::schema::analytics_audit_logs::dsl as audit_logs_columns;
+
+    let mut query = audit_logs_columns::analytics_audit_logs
+        .filter(audit_logs_columns::organization_id.eq(organization_id))
+        .into_boxed();
+
+    if let Some(dataset_id) = filter.dataset_id {
+        query = query.filter(audit_logs_columns::dataset_id.eq(dataset_id));
+    }
+    if let Some(actor_id) = filter.actor_id {
+        query = query.filter(audit_logs_columns::actor_id.eq(actor_id));
+    }
+    if let Some(api_key_id) = filter.api_key_id {
+        query = query.filter(audit_logs_columns::api_key_id.eq(api_key_id));
+    }
+    if let Some(target_type) = filter.target_type {
+        query = query.filter(audit_logs_columns::target_type.eq(target_type.to_string()));
+    }
+    if let Some(target_id) = filter.target_id.clone() {
+        query = query.filter(audit_logs_columns::target_id.eq(target_id));
+    }
+    if let Some(date_range) = &filter.date_range {
+        if let Some(gt) = parse_filter_date(&date_range.gt)? {
+            query = query.filter(audit_logs_columns::created_at.gt(gt));
+        }
+        if let Some(lt) = parse_filter_date(&date_range.lt)? {
+            query = query.filter(audit_logs_columns::created_at.lt(lt));
+        }
+        if let Some(gte) = parse_filter_date(&date_range.gte)? {
+            query = query.filter(audit_logs_columns::created_at.ge(gte));
+        }
+        if let Some(lte) = parse_filter_date(&date_range.lte)? {
+            query = query.filter(audit_logs_columns::created_at.le(lte));
+        }
+    }
+
+    let actions = filter
+        .actions
+        .iter()
+        .flatten()
+        .map(|action| action.to_string())
+        .collect::<Vec<String>>();
+    if !actions.is_empty() {
+        query = query.filter(audit_logs_columns::action.eq_any(actions));
+    }
+
+    Ok(query)
+}
+
+fn get_page_offset(page: Option<u32>) -> i64 {
+    (page.unwrap_or(1).max(1) as i64 - 1) * 10
+}
+
+/// Days analytics rows are kept in Postgres unless `POSTGRES_ANALYTICS_RETENTION_DAYS` overrides it. 0 keeps them forever.
+const DEFAULT_POSTGRES_ANALYTICS_RETENTION_DAYS: i64 = 90;
+/// Days audit logs are kept in Postgres unless `POSTGRES_AUDIT_LOG_RETENTION_DAYS` overrides it. 0 keeps them forever.
+const DEFAULT_POSTGRES_AUDIT_LOG_RETENTION_DAYS: i64 = 365;
+const POSTGRES_ANALYTICS_RETENTION_INTERVAL: std::time::Duration =
+    std::time::Duration::from_secs(60 * 60);
+
+fn get_retention_cutoff(env_var: &str, default_days: i64) -> Option<NaiveDateTime> {
+    let retention_days = std::env::var(env_var)
+        .ok()
+        .and_then(|retention_days| retention_days.parse::<i64>().ok())
+        .unwrap_or(default_days);
+
+    (retention_days > 0)
+        .then(|| chrono::Utc::now().naive_utc() - chrono::Duration::days(retention_days))
+}
+
+fn retention_delete_error(e: diesel::result::Error) -> ServiceError {
+    log::error!("Error deleting expired analytics rows: {:?}", e);
+    ServiceError::InternalServerError("Error deleting expired analytics rows".to_string())
+}
+
+impl PostgresAnalyticsStore {
+    /// Deletes analytics rows and audit logs which are past their retention period.
+    pub async fn delete_expired_events(&self) -> Result<(), ServiceError> {
+        use crate::data::schema::analytics_audit_logs::dsl as audit_logs_columns;
+        use crate::data::schema::analytics_events::dsl as analytics_events_columns;
+        use crate::data::schema::analytics_rag_queries::dsl as rag_queries_columns;
+        use crate::data::schema::analytics_recommendations::dsl as recommendations_columns;
+        use crate::data::schema::analytics_search_queries::dsl as search_queries_columns;
+        use crate::data::schema::analytics_worker_events::dsl as worker_events_columns;
+
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        if let Some(cutoff) = get_retention_cutoff(
+            "POSTGRES_ANALYTICS_RETENTION_DAYS",
+            DEFAULT_POSTGRES_ANALYTICS_RETENTION_DAYS,
+        ) {
+            diesel::delete(
+                search_queries_columns::analytics_search_queries
+                    .filter(search_queries_columns::created_at.lt(cutoff)),
+            )
+            .execute(&mut conn)
+            .await
+            .map_err(retention_delete_error)?;
+
+            diesel::delete(
+                rag_queries_columns::analytics_rag_queries
+                    .filter(rag_queries_columns::created_at.lt(cutoff)),
+            )
+            .execute(&mut conn)
+            .await
+            .map_err(retention_delete_error)?;
+
+            diesel::delete(
+                recommendations_columns::analytics_recommendations
+                    .filter(recommendations_columns::created_at.lt(cutoff)),
+            )
+            .execute(&mut conn)
+            .await
+            .map_err(retention_delete_error)?;
+
+            diesel::delete(
+                analytics_events_columns::analytics_events
+                    .filter(analytics_events_columns::created_at.lt(cutoff)),
+            )
+            .execute(&mut conn)
+            .await
+            .map_err(retention_delete_error)?;
+
+            diesel::delete(
+                worker_events_columns::analytics_worker_events
+                    .filter(worker_events_columns::created_at.lt(cutoff)),
+            )
+            .execute(&mut conn)
+            .await
+            .map_err(retention_delete_error)?;
+        }
+
+        if let Some(cutoff) = get_retention_cutoff(
+            "POSTGRES_AUDIT_LOG_RETENTION_DAYS",
+            DEFAULT_POSTGRES_AUDIT_LOG_RETENTION_DAYS,
+        ) {
+            diesel::delete(
+                audit_logs_columns::analytics_audit_logs
+                    .filter(audit_logs_columns::created_at.lt(cutoff)),
+            )
+            .execute(&mut conn)
+            .await
+            .map_err(retention_delete_error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes expired rows every hour. Each server runs this, the deletes are safe to repeat.
+    pub fn start_retention_service(&self) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = store.delete_expired_events().await {
+                    log::error!("Error applying postgres analytics retention: {:?}", e);
+                }
+                tokio::time::sleep(POSTGRES_ANALYTICS_RETENTION_INTERVAL).await;
+            }
+        });
+    }
+
+    async fn get_usage_graph(
+        &self,
+        query: BoxedSqlQuery<'static, Pg, SqlQuery>,
+    ) -> Result<Vec<UsageGraphPoint>, ServiceError> {
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        let usage_points = query
+            .load::<UsageGraphPointPostgres>(&mut conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error fetching usage graph: {:?}", e);
+                ServiceError::InternalServerError("Error fetching usage graph".to_string())
+            })?;
+
+        Ok(usage_points.into_iter().map(|point| point.into()).collect())
+    }
+}
+
+impl AnalyticsStore for PostgresAnalyticsStore {
+    async fn write_events(&self, events: Vec<ClickHouseEvent>) -> Result<(), ServiceError> {
+        use crate::data::schema::analytics_audit_logs::dsl as audit_logs_columns;
+        use crate::data::schema::analytics_rag_queries::dsl as rag_queries_columns;
+        use crate::data::schema::analytics_recommendations::dsl as recommendations_columns;
+        use crate::data::schema::analytics_search_queries::dsl as search_queries_columns;
+        use crate::data::schema::analytics_worker_events::dsl as worker_events_columns;
+
+        let mut search_queries: Vec<SearchQueryEventPostgres> = vec![];
+        let mut rag_queries: Vec<RagQueryEventPostgres> = vec![];
+        let mut recommendations: Vec<RecommendationEventPostgres> = vec![];
+        let mut worker_events: Vec<WorkerEventPostgres> = vec![];
+        let mut audit_logs: Vec<AuditLogPostgres> = vec![];
+
+        for event in events {
+            match event {
+                ClickHouseEvent::SearchQueryEvent(event) => search_queries.push(event.into()),
+                ClickHouseEvent::RagQueryEvent(event) => rag_queries.push(event.into()),
+                ClickHouseEvent::RecommendationEvent(event) => recommendations.push(event.into()),
+                ClickHouseEvent::WorkerEvent(event) => worker_events.push(event.into()),
+                ClickHouseEvent::AuditLogEvent(event) => audit_logs.push(event.into()),
+            }
+        }
+
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        if !search_queries.is_empty() {
+            diesel::insert_into(search_queries_columns::analytics_search_queries)
+                .values(&search_queries)
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .await
+                .map_err(|e| {
+                    log::error!("Error writing search queries to postgres: {:?}", e);
+                    ServiceError::InternalServerError(
+                        "Error writing search queries to postgres".to_string(),
+                    )
+                })?;
+        }
+
+        if !rag_queries.is_empty() {
+            diesel::insert_into(rag_queries_columns::analytics_rag_queries)
+                .values(&rag_queries)
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .await
+                .map_err(|e| {
+                    log::error!("Error writing rag queries to postgres: {:?}", e);
+                    ServiceError::InternalServerError(
+                        "Error writing rag queries to postgres".to_string(),
+                    )
+                })?;
+        }
+
+        if !recommendations.is_empty() {
+            diesel::insert_into(recommendations_columns::analytics_recommendations)
+                .values(&recommendations)
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .await
+                .map_err(|e| {
+                    log::error!("Error writing recommendations to postgres: {:?}", e);
+                    ServiceError::InternalServerError(
+                        "Error writing recommendations to postgres".to_string(),
+                    )
+                })?;
+        }
+
+        if !worker_events.is_empty() {
+            diesel::insert_into(worker_events_columns::analytics_worker_events)
+                .values(&worker_events)
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .await
+                .map_err(|e| {
+                    log::error!("Error writing worker events to postgres: {:?}", e);
+                    ServiceError::InternalServerError(
+                        "Error writing worker events to postgres".to_string(),
+                    )
+                })?;
+        }
+
+        if !audit_logs.is_empty() {
+            diesel::insert_into(audit_logs_columns::analytics_audit_logs)
+                .values(&audit_logs)
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .await
+                .map_err(|e| {
+                    log::error!("Error writing audit logs to postgres: {:?}", e);
+                    ServiceError::InternalServerError(
+                        "Error writing audit logs to postgres".to_string(),
+                    )
+                })?;
+        }
+
+        Ok(())
+    }
+
+    async fn write_event_data(&self, event_data: EventDataClickhouse) -> Result<(), ServiceError> {
+        use crate::data::schema::analytics_events::dsl as analytics_events_columns;
+
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        diesel::insert_into(analytics_events_columns::analytics_events)
+            .values(EventDataPostgres::from(event_data))
+            .execute(&mut conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error sending event data: {:?}", e);
+                ServiceError::InternalServerError("Error sending event data".to_string())
+            })?;
+
+        Ok(())
+    }
+
+    async fn get_search_usage_graph(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        granularity: Option<Granularity>,
+    ) -> Result<SearchUsageGraphResponse, ServiceError> {
+        let query_string = format!(
+            "SELECT
+                date_trunc('{}', created_at) AS time_stamp,
+                count(*) AS requests
+            FROM analytics_search_queries
+            WHERE dataset_id = $1 {} {}
+            GROUP BY time_stamp
+            ORDER BY time_stamp
+            LIMIT 1000",
+            granularity.unwrap_or(Granularity::Hour),
+            DATE_RANGE_CLAUSE,
+            SEARCH_FILTER_CLAUSE
+        );
+
+        let usage_points = self
+            .get_usage_graph(bind_search_filter(query_string, dataset_id, filter)?)
+            .await?;
+
+        Ok(SearchUsageGraphResponse { usage_points })
+    }
+
+    async fn get_head_queries(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        page: Option<u32>,
+    ) -> Result<HeadQueryResponse, ServiceError> {
+        let query_string = format!(
+            "SELECT query, count(*) AS count
+            FROM analytics_search_queries
+            WHERE dataset_id = $1 {} {}
+            GROUP BY query
+            ORDER BY count DESC
+            LIMIT 10
+            OFFSET $8",
+            DATE_RANGE_CLAUSE, SEARCH_FILTER_CLAUSE
+        );
+
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        let head_queries = bind_search_filter(query_string, dataset_id, filter)?
+            .bind::<BigInt, _>(get_page_offset(page))
+            .load::<HeadQueriesPostgres>(&mut conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error fetching query: {:?}", e);
+                ServiceError::InternalServerError("Error fetching query".to_string())
+            })?;
+
+        Ok(HeadQueryResponse {
+            queries: head_queries
+                .into_iter()
+                .map(|head_query| HeadQueries {
+                    query: head_query.query,
+                    count: head_query.count,
+                })
+                .collect(),
+        })
+    }
+
+    async fn get_no_result_queries(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        page: Option<u32>,
+    ) -> Result<SearchQueryResponse, ServiceError> {
+        let query_string = format!(
+            "SELECT *
+            FROM analytics_search_queries
+            WHERE dataset_id = $1 AND top_score = 0 {} {}
+            ORDER BY created_at DESC
+            LIMIT 10
+            OFFSET $8",
+            DATE_RANGE_CLAUSE, SEARCH_FILTER_CLAUSE
+        );
+
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        let queries = bind_search_filter(query_string, dataset_id, filter)?
+            .bind::<BigInt, _>(get_page_offset(page))
+            .load::<SearchQueryEventPostgres>(&mut conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error fetching query: {:?}", e);
+                ServiceError::InternalServerError("Error fetching query".to_string())
+            })?;
+
+        Ok(SearchQueryResponse {
+            queries: queries.into_iter().map(SearchQueryEvent::from).collect(),
+        })
+    }
+
+    async fn get_rag_usage(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<RAGAnalyticsFilter>,
+    ) -> Result<RAGUsageResponse, ServiceError> {
+        let query_string = format!(
+            "SELECT count(*) AS total_queries
+            FROM analytics_rag_queries
+            WHERE dataset_id = $1 {} {}",
+            DATE_RANGE_CLAUSE, RAG_FILTER_CLAUSE
+        );
+
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        let rag_usage = bind_rag_filter(query_string, dataset_id, filter)?
+            .get_result::<RAGUsagePostgres>(&mut conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error fetching query: {:?}", e);
+                ServiceError::InternalServerError("Error fetching query".to_string())
+            })?;
+
+        Ok(RAGUsageResponse {
+            total_queries: rag_usage.total_queries as u32,
+        })
+    }
+
+    async fn get_rag_usage_graph(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<RAGAnalyticsFilter>,
+        granularity: Option<Granularity>,
+    ) -> Result<RAGUsageGraphResponse, ServiceError> {
+        let query_string = format!(
+            "SELECT
+                date_trunc('{}', created_at) AS time_stamp,
+                count(*) AS requests
+            FROM analytics_rag_queries
+            WHERE dataset_id = $1 {} {}
+            GROUP BY time_stamp
+            ORDER BY time_stamp
+            LIMIT 1000",
+            granularity.unwrap_or(Granularity::Hour),
+            DATE_RANGE_CLAUSE,
+            RAG_FILTER_CLAUSE
+        );
+
+        let usage_points = self
+            .get_usage_graph(bind_rag_filter(query_string, dataset_id, filter)?)
+            .await?;
+
+        Ok(RAGUsageGraphResponse { usage_points })
+    }
+
+    async fn get_search_ctr_metrics(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+    ) -> Result<SearchCTRMetrics, ServiceError> {
+        let query_string = format!(
+            "WITH searches AS (
+                SELECT id
+                FROM analytics_search_queries
+                WHERE dataset_id = $1 {} {}
+            ),
+            clicks AS (
+                SELECT searches.id, (analytics_events.metadata->>'position')::float8 AS position
+                FROM analytics_events
+                JOIN searches ON analytics_events.request_id = searches.id::text
+                WHERE analytics_events.dataset_id = $1 AND analytics_events.event_type = 'click'
+            ),
+            total_searches AS (
+                SELECT count(*) AS total FROM searches
+            )
+            SELECT
+                count(DISTINCT clicks.id) AS searches_with_clicks,
+                COALESCE(count(DISTINCT clicks.id) * 100.0 / NULLIF(total_searches.total, 0), 0)::float8 AS percent_searches_with_clicks,
+                COALESCE((total_searches.total - count(DISTINCT clicks.id)) * 100.0 / NULLIF(total_searches.total, 0), 0)::float8 AS percent_searches_without_clicks,
+                COALESCE(avg(clicks.position), 0)::float8 AS avg_position_of_click
+            FROM total_searches
+            LEFT JOIN clicks ON TRUE
+            GROUP BY total_searches.total",
+            DATE_RANGE_CLAUSE, SEARCH_FILTER_CLAUSE
+        );
+
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        let ctr_metrics = bind_search_filter(query_string, dataset_id, filter)?
+            .get_result::<SearchCTRMetricsPostgres>(&mut conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error fetching query: {:?}", e);
+                ServiceError::InternalServerError("Error fetching query".to_string())
+            })?;
+
+        Ok(SearchCTRMetrics {
+            searches_with_clicks: ctr_metrics.searches_with_clicks,
+            percent_searches_with_clicks: ctr_metrics.percent_searches_with_clicks,
+            percent_searches_without_clicks: ctr_metrics.percent_searches_without_clicks,
+            avg_position_of_click: ctr_metrics.avg_position_of_click,
+        })
+    }
+
+    async fn get_worker_events(
+        &self,
+        dataset_id: uuid::Uuid,
+        page: i64,
+        page_size: i64,
+        event_types: Vec<EventTypeRequest>,
+    ) -> Result<EventReturn, ServiceError> {
+        use crate::data::schema::analytics_worker_events::dsl as worker_events_columns;
+
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        let event_types = event_types
+            .iter()
+            .map(|event_type| event_type.to_string())
+            .collect::<Vec<String>>();
+
+        let events = worker_events_columns::analytics_worker_events
+            .filter(worker_events_columns::dataset_id.eq(dataset_id))
+            .filter(worker_events_columns::event_type.eq_any(&event_types))
+            .order(worker_events_columns::created_at.desc())
+            .limit(page_size)
+            .offset((page.max(1) - 1) * page_size)
+            .select(WorkerEventPostgres::as_select())
+            .load::<WorkerEventPostgres>(&mut conn)
+            .await
+            .map_err(|err| {
+                log::error!("Failed to get events {:?}", err);
+                ServiceError::BadRequest("Failed to get events".to_string())
+            })?;
+
+        let count = worker_events_columns::analytics_worker_events
+            .filter(worker_events_columns::dataset_id.eq(dataset_id))
+            .filter(worker_events_columns::event_type.eq_any(&event_types))
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await
+            .map_err(|err| {
+                log::error!("Failed to get events count {:?}", err);
+                ServiceError::BadRequest("Failed to get events count".to_string())
+            })?;
+
+        let dataset_event_types = worker_events_columns::analytics_worker_events
+            .filter(worker_events_columns::dataset_id.eq(dataset_id))
+            .select(worker_events_columns::event_type)
+            .distinct()
+            .order(worker_events_columns::event_type)
+            .load::<String>(&mut conn)
+            .await
+            .map_err(|err| {
+                log::error!("Failed to get event types {:?}", err);
+                ServiceError::BadRequest("Failed to get event types".to_string())
+            })?;
+
+        Ok(EventReturn {
+            events: events.into_iter().map(WorkerEvent::from).collect(),
+            event_types: dataset_event_types,
+            page_count: (count as f64 / page_size.max(1) as f64).ceil() as i32,
+        })
+    }
+
+    async fn get_ingestion_job_events(
+        &self,
+        dataset_id: uuid::Uuid,
+        ingestion_job_id: uuid::Uuid,
+    ) -> Result<Vec<WorkerEvent>, ServiceError> {
+        use crate::data::schema::analytics_worker_events::dsl as worker_events_columns;
+
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        let events = worker_events_columns::analytics_worker_events
+            .filter(worker_events_columns::dataset_id.eq(dataset_id))
+            .filter(worker_events_columns::ingestion_job_id.eq(ingestion_job_id))
+            .filter(
+                worker_events_columns::event_type
+                    .eq_any(["chunks_uploaded", "bulk_chunk_upload_failed"]),
+            )
+            .order(worker_events_columns::created_at.asc())
+            .select(WorkerEventPostgres::as_select())
+            .load::<WorkerEventPostgres>(&mut conn)
+            .await
+            .map_err(|err| {
+                log::error!("Failed to get ingestion job events {:?}", err);
+                ServiceError::BadRequest("Failed to get ingestion job events".to_string())
+            })?;
+
+        Ok(events.into_iter().map(WorkerEvent::from).collect())
+    }
+
+    async fn get_audit_logs(
+        &self,
+        organization_id: uuid::Uuid,
+        filter: Option<AuditLogFilter>,
+        page: u64,
+        page_size: u64,
+    ) -> Result<AuditLogsResponse, ServiceError> {
+        use crate::data::schema::analytics_audit_logs::dsl as audit_logs_columns;
+
+        let filter = filter.unwrap_or_default();
+
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        let audit_logs = get_audit_logs_filter_query(organization_id, &filter)?
+            .order(audit_logs_columns::created_at.desc())
+            .limit(page_size as i64)
+            .offset(((page.max(1) - 1) * page_size) as i64)
+            .select(AuditLogPostgres::as_select())
+            .load::<AuditLogPostgres>(&mut conn)
+            .await
+            .map_err(|err| {
+                log::error!("Failed to get audit logs {:?}", err);
+                ServiceError::BadRequest("Failed to get audit logs".to_string())
+            })?
+            .into_iter()
+            .map(AuditLog::from)
+            .collect();
+
+        let count = get_audit_logs_filter_query(organization_id, &filter)?
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await
+            .map_err(|err| {
+                log::error!("Failed to get audit logs count {:?}", err);
+                ServiceError::BadRequest("Failed to get audit logs count".to_string())
+            })?;
+
+        Ok(AuditLogsResponse {
+            audit_logs,
+            page_count: (count as f64 / page_size.max(1) as f64).ceil() as i32,
+        })
+    }
+
+    async fn get_events_for_export(
+        &self,
+        dataset_id: uuid::Uuid,
+        event_type: AnalyticsExportEventType,
+        window_start: NaiveDateTime,
+        window_end: NaiveDateTime,
+    ) -> Result<Vec<serde_json::Value>, ServiceError> {
+        use crate::data::schema::analytics_events::dsl as analytics_events_columns;
+        use crate::data::schema::analytics_rag_queries::dsl as rag_queries_columns;
+        use crate::data::schema::analytics_recommendations::dsl as recommendations_columns;
+        use crate::data::schema::analytics_search_queries::dsl as search_queries_columns;
+
+        let mut conn = self.pool.get().await.map_err(|_e| {
+            ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+        })?;
+
+        match event_type {
+            AnalyticsExportEventType::SearchQueries => to_export_rows(
+                search_queries_columns::analytics_search_queries
+                    .filter(search_queries_columns::dataset_id.eq(dataset_id))
+                    .filter(search_queries_columns::created_at.ge(window_start))
+                    .filter(search_queries_columns::created_at.lt(window_end))
+                    .order(search_queries_columns::created_at.asc())
+                    .select(SearchQueryEventPostgres::as_select())
+                    .load::<SearchQueryEventPostgres>(&mut conn)
+                    .await
+                    .map_err(export_fetch_error)?,
+            ),
+            AnalyticsExportEventType::RagQueries => to_export_rows(
+                rag_queries_columns::analytics_rag_queries
+                    .filter(rag_queries_columns::dataset_id.eq(dataset_id))
+                    .filter(rag_queries_columns::created_at.ge(window_start))
+                    .filter(rag_queries_columns::created_at.lt(window_end))
+                    .order(rag_queries_columns::created_at.asc())
+                    .select(RagQueryEventPostgres::as_select())
+                    .load::<RagQueryEventPostgres>(&mut conn)
+                    .await
+                    .map_err(export_fetch_error)?,
+            ),
+            AnalyticsExportEventType::Recommendations => to_export_rows(
+                recommendations_columns::analytics_recommendations
+                    .filter(recommendations_columns::dataset_id.eq(dataset_id))
+                    .filter(recommendations_columns::created_at.ge(window_start))
+                    .filter(recommendations_columns::created_at.lt(window_end))
+                    .order(recommendations_columns::created_at.asc())
+                    .select(RecommendationEventPostgres::as_select())
+                    .load::<RecommendationEventPostgres>(&mut conn)
+                    .await
+                    .map_err(export_fetch_error)?,
+            ),
+            AnalyticsExportEventType::Events => to_export_rows(
+                analytics_events_columns::analytics_events
+                    .filter(analytics_events_columns::dataset_id.eq(dataset_id))
+                    .filter(analytics_events_columns::created_at.ge(window_start))
+                    .filter(analytics_events_columns::created_at.lt(window_end))
+                    .order(analytics_events_columns::created_at.asc())
+                    .select(EventDataPostgres::as_select())
+                    .load::<EventDataPostgres>(&mut conn)
+                    .await
+                    .map_err(export_fetch_error)?,
+            ),
+        }
+    }
+}
+
+/// The analytics backend picked at startup. `USE_ANALYTICS` turns analytics on and
+/// `ANALYTICS_BACKEND` selects `clickhouse` (the default) or `postgres`.
+#[derive(Clone, Default)]
+pub enum AnalyticsBackend {
+    ClickHouse(ClickHouseAnalyticsStore),
+    Postgres(PostgresAnalyticsStore),
+    #[default]
+    Disabled,
+}
+
+impl AnalyticsBackend {
+    pub fn is_postgres_configured() -> bool {
+        std::env::var("ANALYTICS_BACKEND")
+            .unwrap_or("clickhouse".to_string())
+            .eq_ignore_ascii_case("postgres")
+    }
+
+    /// The backend workers write their events to. `ANALYTICS_BACKEND=postgres` stores them
+    /// in Postgres, otherwise they go to the given ClickHouse client.
+    pub fn from_env(clickhouse_client: clickhouse::Client, pool: web::Data<Pool>) -> Self {
+        if Self::is_postgres_configured() {
+            AnalyticsBackend::Postgres(PostgresAnalyticsStore { pool })
+        } else {
+            AnalyticsBackend::ClickHouse(ClickHouseAnalyticsStore { clickhouse_client })
+        }
+    }
+
+    /// Cronjobs of features which only the ClickHouse backend implements call this on startup
+    /// so they exit with a clear error instead of querying an unconfigured ClickHouse client.
+    pub fn require_clickhouse(feature: &str) -> Result<(), ServiceError> {
+        if Self::is_postgres_configured() {
+            return Err(ServiceError::BadRequest(format!(
+                "{} requires the ClickHouse analytics backend and can't run with ANALYTICS_BACKEND=postgres",
+                feature
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self, AnalyticsBackend::Disabled)
+    }
+
+    /// Analytics views which only ClickHouse implements go through this so other backends
+    /// return a clear error instead of querying an unconfigured ClickHouse client.
+    pub fn clickhouse_client(&self) -> Result<&clickhouse::Client, ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => Ok(&store.clickhouse_client),
+            AnalyticsBackend::Postgres(_) => Err(ServiceError::BadRequest(
+                "This analytics view requires the ClickHouse analytics backend".to_string(),
+            )),
+            AnalyticsBackend::Disabled => Err(analytics_disabled_error()),
+        }
+    }
+}
+
+fn analytics_disabled_error() -> ServiceError {
+    ServiceError::BadRequest("Analytics are not enabled on this server".to_string())
+}
+
+impl AnalyticsStore for AnalyticsBackend {
+    async fn write_events(&self, events: Vec<ClickHouseEvent>) -> Result<(), ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => store.write_events(events).await,
+            AnalyticsBackend::Postgres(store) => store.write_events(events).await,
+            AnalyticsBackend::Disabled => Ok(()),
+        }
+    }
+
+    async fn write_event_data(&self, event_data: EventDataClickhouse) -> Result<(), ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => store.write_event_data(event_data).await,
+            AnalyticsBackend::Postgres(store) => store.write_event_data(event_data).await,
+            AnalyticsBackend::Disabled => Ok(()),
+        }
+    }
+
+    async fn get_search_usage_graph(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        granularity: Option<Granularity>,
+    ) -> Result<SearchUsageGraphResponse, ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => {
+                store
+                    .get_search_usage_graph(dataset_id, filter, granularity)
+                    .await
+            }
+            AnalyticsBackend::Postgres(store) => {
+                store
+                    .get_search_usage_graph(dataset_id, filter, granularity)
+                    .await
+            }
+            AnalyticsBackend::Disabled => Err(analytics_disabled_error()),
+        }
+    }
+
+    async fn get_head_queries(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        page: Option<u32>,
+    ) -> Result<HeadQueryResponse, ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => {
+                store.get_head_queries(dataset_id, filter, page).await
+            }
+            AnalyticsBackend::Postgres(store) => {
+                store.get_head_queries(dataset_id, filter, page).await
+            }
+            AnalyticsBackend::Disabled => Err(analytics_disabled_error()),
+        }
+    }
+
+    async fn get_no_result_queries(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+        page: Option<u32>,
+    ) -> Result<SearchQueryResponse, ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => {
+                store.get_no_result_queries(dataset_id, filter, page).await
+            }
+            AnalyticsBackend::Postgres(store) => {
+                store.get_no_result_queries(dataset_id, filter, page).await
+            }
+            AnalyticsBackend::Disabled => Err(analytics_disabled_error()),
+        }
+    }
+
+    async fn get_rag_usage(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<RAGAnalyticsFilter>,
+    ) -> Result<RAGUsageResponse, ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => store.get_rag_usage(dataset_id, filter).await,
+            AnalyticsBackend::Postgres(store) => store.get_rag_usage(dataset_id, filter).await,
+            AnalyticsBackend::Disabled => Err(analytics_disabled_error()),
+        }
+    }
+
+    async fn get_rag_usage_graph(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<RAGAnalyticsFilter>,
+        granularity: Option<Granularity>,
+    ) -> Result<RAGUsageGraphResponse, ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => {
+                store
+                    .get_rag_usage_graph(dataset_id, filter, granularity)
+                    .await
+            }
+            AnalyticsBackend::Postgres(store) => {
+                store
+                    .get_rag_usage_graph(dataset_id, filter, granularity)
+                    .await
+            }
+            AnalyticsBackend::Disabled => Err(analytics_disabled_error()),
+        }
+    }
+
+    async fn get_search_ctr_metrics(
+        &self,
+        dataset_id: uuid::Uuid,
+        filter: Option<SearchAnalyticsFilter>,
+    ) -> Result<SearchCTRMetrics, ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => {
+                store.get_search_ctr_metrics(dataset_id, filter).await
+            }
+            AnalyticsBackend::Postgres(store) => {
+                store.get_search_ctr_metrics(dataset_id, filter).await
+            }
+            AnalyticsBackend::Disabled => Err(analytics_disabled_error()),
+        }
+    }
+
+    async fn get_worker_events(
+        &self,
+        dataset_id: uuid::Uuid,
+        page: i64,
+        page_size: i64,
+        event_types: Vec<EventTypeRequest>,
+    ) -> Result<EventReturn, ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => {
+                store
+                    .get_worker_events(dataset_id, page, page_size, event_types)
+                    .await
+            }
+            AnalyticsBackend::Postgres(store) => {
+                store
+                    .get_worker_events(dataset_id, page, page_size, event_types)
+                    .await
+            }
+            AnalyticsBackend::Disabled => Err(analytics_disabled_error()),
+        }
+    }
+
+    async fn get_ingestion_job_events(
+        &self,
+        dataset_id: uuid::Uuid,
+        ingestion_job_id: uuid::Uuid,
+    ) -> Result<Vec<WorkerEvent>, ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => {
+                store
+                    .get_ingestion_job_events(dataset_id, ingestion_job_id)
+                    .await
+            }
+            AnalyticsBackend::Postgres(store) => {
+                store
+                    .get_ingestion_job_events(dataset_id, ingestion_job_id)
+                    .await
+            }
+            AnalyticsBackend::Disabled => Ok(vec![]),
+        }
+    }
+
+    async fn get_audit_logs(
+        &self,
+        organization_id: uuid::Uuid,
+        filter: Option<AuditLogFilter>,
+        page: u64,
+        page_size: u64,
+    ) -> Result<AuditLogsResponse, ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => {
+                store
+                    .get_audit_logs(organization_id, filter, page, page_size)
+                    .await
+            }
+            AnalyticsBackend::Postgres(store) => {
+                store
+                    .get_audit_logs(organization_id, filter, page, page_size)
+                    .await
+            }
+            AnalyticsBackend::Disabled => Err(analytics_disabled_error()),
+        }
+    }
+
+    async fn get_events_for_export(
+        &self,
+        dataset_id: uuid::Uuid,
+        event_type: AnalyticsExportEventType,
+        window_start: NaiveDateTime,
+        window_end: NaiveDateTime,
+    ) -> Result<Vec<serde_json::Value>, ServiceError> {
+        match self {
+            AnalyticsBackend::ClickHouse(store) => {
+                store
+                    .get_events_for_export(dataset_id, event_type, window_start, window_end)
+                    .await
+            }
+            AnalyticsBackend::Postgres(store) => {
+                store
+                    .get_events_for_export(dataset_id, event_type, window_start, window_end)
+                    .await
+            }
+            AnalyticsBackend::Disabled => Err(analytics_disabled_error()),
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use crate::{
+    data::models::{
+        AuditAction, AuditActor, AuditLog, AuditLogClickhouse, AuditLogFilter, AuditTargetType,
+    },
+    errors::ServiceError,
+    operators::clickhouse_operator::{ClickHouseEvent, EventQueue},
+};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::OnceLock;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+static TRUSTED_PROXIES: OnceLock<Vec<TrustedProxy>> = OnceLock::new();
+
+/// A proxy address or CIDR range from `TRUSTED_PROXIES` whose forwarded headers are believed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrustedProxy {
+    network: IpAddr,
+    prefix_len: u32,
+}
+
+impl TrustedProxy {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (address, prefix_len) = match value.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len.parse::<u32>().ok()?)),
+            None => (value, None),
+        };
+
+        let network = address.parse::<IpAddr>().ok()?.to_canonical();
+        let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_len.unwrap_or(max_prefix_len);
+
+        (prefix_len <= max_prefix_len).then_some(TrustedProxy {
+            network,
+            prefix_len,
+        })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses the comma separated `TRUSTED_PROXIES` once. It is empty by default so forwarded headers are ignored unless the server is deployed behind known proxies.
+pub fn get_trusted_proxies() -> &'static [TrustedProxy] {
+    TRUSTED_PROXIES.get_or_init(|| {
+        std::env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|value| !value.trim().is_empty())
+            .filter_map(|value| {
+                let trusted_proxy = TrustedProxy::parse(value);
+                if trusted_proxy.is_none() {
+                    log::error!("Ignoring invalid TRUSTED_PROXIES entry {:?}", value);
+                }
+                trusted_proxy
+            })
+            .collect()
+    })
+}
+
+/// The client ip recorded in the audit log. This is the address of the connection's peer unless the peer is a trusted proxy, in which case `X-Forwarded-For` is walked from the right past trusted proxies. Addresses a client put in the header itself are never reached that way, so they can't spoof the recorded ip.
+pub fn get_source_ip(
+    peer_ip: Option<IpAddr>,
+    forwarded_for: &str,
+    trusted_proxies: &[TrustedProxy],
+) -> String {
+    let Some(mut source_ip) = peer_ip.map(|ip| ip.to_canonical()) else {
+        return String::new();
+    };
+
+    for hop in forwarded_for.rsplit(',') {
+        if !trusted_proxies
+            .iter()
+            .any(|proxy| proxy.contains(source_ip))
+        {
+            break;
+        }
+
+        let hop = hop.trim();
+        match hop
+            .parse::<IpAddr>()
+            .or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip()))
+        {
+            Ok(ip) => source_ip = ip.to_canonical(),
+            Err(_) => break,
+        }
+    }
+
+    source_ip.to_string()
+}
+
+/// Keeps only the keys of two json objects whose values differ, recursing into nested objects so a changed configuration key is recorded without the rest of the configuration.
+pub fn get_json_diff(
+    before: &serde_json::Value,
+    after: &serde_json::Value,
+) -> (serde_json::Value, serde_json::Value) {
+    match (before, after) {
+        (serde_json::Value::Object(before_map), serde_json::Value::Object(after_map)) => {
+            let mut before_diff = serde_json::Map::new();
+            let mut after_diff = serde_json::Map::new();
+
+            let keys = before_map.keys().chain(
+                after_map
+                    .keys()
+                    .filter(|key| !before_map.contains_key(*key)),
+            );
+
+            for key in keys {
+                let before_value = before_map.get(key).unwrap_or(&serde_json::Value::Null);
+                let after_value = after_map.get(key).unwrap_or(&serde_json::Value::Null);
+                if before_value == after_value {
+                    continue;
+                }
+
+                let (before_value, after_value) = get_json_diff(before_value, after_value);
+                before_diff.insert(key.clone(), before_value);
+                after_diff.insert(key.clone(), after_value);
+            }
+
+            (
+                serde_json::Value::Object(before_diff),
+                serde_json::Value::Object(after_diff),
+            )
+        }
+        _ => (before.clone(), after.clone()),
+    }
+}
+
+/// Replaces the values of api key fields such as `LLM_API_KEY` so secrets never end up in the audit log. A changed key still shows up in the diff as a redacted value.
+pub fn redact_api_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    if key.to_lowercase().ends_with("api_key") && !value.is_null() {
+                        (key, serde_json::Value::String("[redacted]".to_string()))
+                    } else {
+                        (key, redact_api_keys(value))
+                    }
+                })
+                .collect(),
+        ),
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(redact_api_keys).collect())
+        }
+        value => value,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn log_audit_event(
+    actor: &AuditActor,
+    organization_id: uuid::Uuid,
+    dataset_id: Option<uuid::Uuid>,
+    action: AuditAction,
+    target_type: AuditTargetType,
+    target_id: String,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+    event_queue: &EventQueue,
+) {
+    let (before, after) = get_json_diff(
+        &before.unwrap_or(serde_json::Value::Null),
+        &after.unwrap_or(serde_json::Value::Null),
+    );
+
+    event_queue
+        .send(ClickHouseEvent::AuditLogEvent(AuditLogClickhouse {
+            id: uuid::Uuid::new_v4(),
+            organization_id,
+            dataset_id: dataset_id.unwrap_or_default(),
+            actor_id: actor.user_id,
+            actor_email: actor.email.clone(),
+            api_key_id: actor.api_key_id.unwrap_or_default(),
+            action: action.to_string(),
+            target_type: target_type.to_string(),
+            target_id,
+            before: redact_api_keys(before).to_string(),
+            after: redact_api_keys(after).to_string(),
+            source_ip: actor.source_ip.clone(),
+            created_at: OffsetDateTime::now_utc(),
+        }))
+        .await;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct AuditLogsResponse {
+    pub audit_logs: Vec<AuditLog>,
+    pub page_count: i32,
+}
+
+pub async fn get_audit_logs_query(
+    organization_id: uuid::Uuid,
+    filter: Option<AuditLogFilter>,
+    page: u64,
+    page_size: u64,
+    clickhouse_client: &clickhouse::Client,
+) -> Result<AuditLogsResponse, ServiceError> {
+    let filter = filter.unwrap_or_default();
+    let mut where_clause = String::from("organization_id = ?");
+    let mut binds: Vec<String> = vec![];
+
+    if let Some(dataset_id) = filter.dataset_id {
+        where_clause.push_str(" AND dataset_id = ?");
+        binds.push(dataset_id.to_string());
+    }
+    if let Some(actor_id) = filter.actor_id {
+        where_clause.push_str(" AND actor_id = ?");
+        binds.push(actor_id.to_string());
+    }
+    if let Some(api_key_id) = filter.api_key_id {
+        where_clause.push_str(" AND api_key_id = ?");
+        binds.push(api_key_id.to_string());
+    }
+    if let Some(target_type) = filter.target_type {
+        where_clause.push_str(" AND target_type = ?");
+        binds.push(target_type.to_string());
+    }
+    if let Some(target_id) = filter.target_id {
+        where_clause.push_str(" AND target_id = ?");
+        binds.push(target_id);
+    }
+    if let Some(date_range) = filter.date_range {
+        if let Some(gt) = date_range.gt {
+            where_clause.push_str(" AND created_at > ?");
+            binds.push(gt);
+        }
+        if let Some(lt) = date_range.lt {
+            where_clause.push_str(" AND created_at < ?");
+            binds.push(lt);
+        }
+        if let Some(gte) = date_range.gte {
+            where_clause.push_str(" AND created_at >= ?");
+            binds.push(gte);
+        }
+        if let Some(lte) = date_range.lte {
+            where_clause.push_str(" AND created_at <= ?");
+            binds.push(lte);
+        }
+    }
+    let actions = filter
+        .actions
+        .unwrap_or_default()
+        .iter()
+        .map(|action| action.to_string())
+        .collect::<Vec<String>>();
+    if !actions.is_empty() {
+        where_clause.push_str(" AND has(?, action)");
+    }
+
+    let mut logs_query = clickhouse_client
+        .query(&format!(
+            "SELECT ?fields FROM audit_logs WHERE {} ORDER BY created_at DESC LIMIT ? OFFSET ?",
+            where_clause
+        ))
+        .bind(organization_id);
+    let mut count_query = clickhouse_client
+        .query(&format!(
+            "SELECT count(*) FROM audit_logs WHERE {}",
+            where_clause
+        ))
+        .bind(organization_id);
+    for bind in binds {
+        logs_query = logs_query.bind(bind.clone());
+        count_query = count_query.bind(bind);
+    }
+    if !actions.is_empty() {
+        logs_query = logs_query.bind(actions.clone());
+        count_query = count_query.bind(actions);
+    }
+
+    let audit_logs = logs_query
+        .bind(page_size)
+        .bind((page.max(1) - 1) * page_size)
+        .fetch_all::<AuditLogClickhouse>()
+        .await
+        .map_err(|err| {
+            log::error!("Failed to get audit logs {:?}", err);
+            ServiceError::BadRequest("Failed to get audit logs".to_string())
+        })?
+        .into_iter()
+        .map(AuditLog::from)
+        .collect();
+
+    let count = count_query.fetch_one::<u64>().await.map_err(|err| {
+        log::error!("Failed to get audit logs count {:?}", err);
+        ServiceError::BadRequest("Failed to get audit logs count".to_string())
+    })?;
+
+    Ok(AuditLogsResponse {
+        audit_logs,
+        page_count: (count as f64 / page_size.max(1) as f64).ceil() as i32,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn trusted_proxies() -> Vec<TrustedProxy> {
+        ["10.0.0.0/8", "192.168.1.1", "fd00::/8"]
+            .iter()
+            .filter_map(|value| TrustedProxy::parse(value))
+            .collect()
+    }
+
+    #[test]
+    pub fn test_trusted_proxy_parse() {
+        assert!(TrustedProxy::parse("10.0.0.0/8").is_some());
+        assert!(TrustedProxy::parse(" 192.168.1.1 ").is_some());
+        assert!(TrustedProxy::parse("fd00::/8").is_some());
+        assert!(TrustedProxy::parse("10.0.0.0/33").is_none());
+        assert!(TrustedProxy::parse("not an ip").is_none());
+
+        let proxy = TrustedProxy::parse("10.0.0.0/8").unwrap();
+        assert!(proxy.contains("10.1.2.3".parse().unwrap()));
+        assert!(proxy.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!proxy.contains("11.1.2.3".parse().unwrap()));
+
+        let any = TrustedProxy::parse("0.0.0.0/0").unwrap();
+        assert!(any.contains("8.8.8.8".parse().unwrap()));
+    }
+
+    #[test]
+    pub fn test_get_source_ip() {
+        let trusted_proxies = trusted_proxies();
+
+        // Direct connections ignore the header
+        assert_eq!(
+            get_source_ip(
+                Some("8.8.8.8".parse().unwrap()),
+                "1.1.1.1",
+                &trusted_proxies
+            ),
+            "8.8.8.8"
+        );
+        // No trusted proxies configured ignores the header
+        assert_eq!(
+            get_source_ip(Some("10.0.0.1".parse().unwrap()), "1.1.1.1", &[]),
+            "10.0.0.1"
+        );
+        // Behind a trusted proxy the address it appended is used
+        assert_eq!(
+            get_source_ip(
+                Some("10.0.0.1".parse().unwrap()),
+                "1.1.1.1",
+                &trusted_proxies
+            ),
+            "1.1.1.1"
+        );
+        // A spoofed address prepended by the client is not reached
+        assert_eq!(
+            get_source_ip(
+                Some("10.0.0.1".parse().unwrap()),
+                "6.6.6.6, 1.1.1.1, 192.168.1.1",
+                &trusted_proxies
+            ),
+            "1.1.1.1"
+        );
+        // Unparsable hops stop at the last trusted proxy
+        assert_eq!(
+            get_source_ip(
+                Some("10.0.0.1".parse().unwrap()),
+                "garbage",
+                &trusted_proxies
+            ),
+            "10.0.0.1"
+        );
+        assert_eq!(
+            get_source_ip(
+                Some("::ffff:8.8.8.8".parse().unwrap()),
+                "",
+                &trusted_proxies
+            ),
+            "8.8.8.8"
+        );
+        assert_eq!(get_source_ip(None, "1.1.1.1", &trusted_proxies), "");
+    }
+}
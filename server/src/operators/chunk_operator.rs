@@ -6,10 +6,12 @@ use crate::data::models::{
 };
 use crate::handlers::chunk_handler::{BulkUploadIngestionMessage, ChunkReqPayload};
 use crate::handlers::chunk_handler::{ChunkFilter, UploadIngestionMessage};
+use crate::operators::acl_operator::validate_acl;
 use crate::operators::parse_operator::convert_html_to_text;
 use crate::operators::qdrant_operator::{
     delete_points_from_qdrant, get_qdrant_collection_from_dataset_config, scroll_dataset_points,
 };
+use crate::operators::usage_operator::current_usage_api_key_id;
 use crate::{
     data::models::{ChunkMetadata, Pool},
     errors::ServiceError,
@@ -691,6 +693,7 @@ pub async fn bulk_insert_chunk_metadata_query(
                 chunk_metadata_columns::location.eq(excluded(chunk_metadata_columns::location)),
                 chunk_metadata_columns::image_urls.eq(excluded(chunk_metadata_columns::image_urls)),
                 chunk_metadata_columns::num_value.eq(excluded(chunk_metadata_columns::num_value)),
+                chunk_metadata_columns::acl.eq(excluded(chunk_metadata_columns::acl)),
             ))
             .returning(ChunkMetadataTable::as_select())
             .get_results::<ChunkMetadataTable>(&mut conn)
@@ -1254,6 +1257,7 @@ pub async fn update_chunk_metadata_query(
         chunk_metadata_columns::weight.eq(chunk_data.weight),
         chunk_metadata_columns::image_urls.eq(chunk_data.image_urls),
         chunk_metadata_columns::num_value.eq(chunk_data.num_value),
+        chunk_metadata_columns::acl.eq(chunk_data.acl),
     ))
     .get_result::<ChunkMetadataTable>(&mut conn)
     .await
@@ -2491,7 +2495,11 @@ pub async fn create_chunk_metadata(
                 .transpose()?
         };
 
-        let chunk_metadata = ChunkMetadata::from_details(
+        if let Some(acl) = chunk.acl.as_ref() {
+            validate_acl(acl)?;
+        }
+
+        let mut chunk_metadata = ChunkMetadata::from_details(
             &chunk.chunk_html.clone(),
             &chunk.link,
             &chunk_tag_set,
@@ -2505,6 +2513,10 @@ pub async fn create_chunk_metadata(
             chunk.weight.unwrap_or(0.0),
             chunk.num_value,
         );
+        chunk_metadata.acl = chunk
+            .acl
+            .clone()
+            .map(|acl| acl.into_iter().map(Some).collect());
         chunk_metadatas.push(chunk_metadata.clone());
 
         let upload_message = UploadIngestionMessage {
@@ -2526,6 +2538,9 @@ pub async fn create_chunk_metadata(
             attempt_number: 0,
             dataset_id: dataset_uuid,
             ingestion_messages,
+            ingestion_job_id: None,
+            ingestion_batch_id: None,
+            api_key_id: current_usage_api_key_id(),
         },
         chunk_metadatas,
     ))
@@ -4,10 +4,17 @@ use tokio::sync::mpsc;
 
 use crate::{
     data::models::{
-        RagQueryEventClickhouse, RecommendationEventClickhouse, SearchQueryEventClickhouse,
-        WorkerEventClickhouse,
+        AnalyticsForwardMessage, AuditLogClickhouse, EventDataClickhouse, RagQueryEventClickhouse,
+        RecommendationEventClickhouse, RedisPool, SearchQueryEventClickhouse, WebhookEventType,
+        WorkerEvent, WorkerEventClickhouse,
     },
     errors::ServiceError,
+    operators::{
+        analytics_export_operator::{
+            get_analytics_forward_message, get_event_data_forward_message, ANALYTICS_FORWARD_QUEUE,
+        },
+        analytics_store_operator::{AnalyticsBackend, AnalyticsStore, ClickHouseAnalyticsStore},
+    },
 };
 
 #[derive(Debug, Clone)]
@@ -16,6 +23,7 @@ pub enum ClickHouseEvent {
     RecommendationEvent(RecommendationEventClickhouse),
     RagQueryEvent(RagQueryEventClickhouse),
     WorkerEvent(WorkerEventClickhouse),
+    AuditLogEvent(AuditLogClickhouse),
 }
 
 pub fn get_latency_from_header(header: String) -> f32 {
@@ -30,6 +38,14 @@ pub fn get_latency_from_header(header: String) -> f32 {
         .sum()
 }
 
+/// Escapes a client supplied value for the hand built `search_queries` insert. Backslashes are escaped first so a trailing `\` cannot swallow the closing quote and break the whole batch.
+fn escape_search_query_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\'', "''")
+        .replace('?', "|q")
+}
+
 pub async fn send_to_clickhouse(
     events: Vec<ClickHouseEvent>,
     clickhouse_client: &clickhouse::Client,
@@ -38,7 +54,7 @@ pub async fn send_to_clickhouse(
         return Ok(());
     }
 
-    let mut search_queries_inserter = String::from("INSERT INTO search_queries (id, search_type, query, request_params, query_vector, latency, top_score, results, dataset_id, created_at, query_rating) VALUES");
+    let mut search_queries_inserter = String::from("INSERT INTO search_queries (id, search_type, query, request_params, query_vector, latency, top_score, results, dataset_id, created_at, query_rating, user_id, experiment_id, experiment_variant) VALUES");
 
     let mut rag_queries_inserter = clickhouse_client.insert("rag_queries").map_err(|e| {
         log::error!("Error inserting rag queries: {:?}", e);
@@ -56,6 +72,11 @@ pub async fn send_to_clickhouse(
         ServiceError::InternalServerError(format!("Error inserting recommendations: {:?}", e))
     })?;
 
+    let mut audit_logs_inserter = clickhouse_client.insert("audit_logs").map_err(|e| {
+        log::error!("Error inserting audit logs: {:?}", e);
+        ServiceError::InternalServerError(format!("Error inserting audit logs: {:?}", e))
+    })?;
+
     for event in events {
         match event {
             ClickHouseEvent::SearchQueryEvent(mut event) => {
@@ -67,7 +88,7 @@ pub async fn send_to_clickhouse(
                 });
 
                 search_queries_inserter.push_str(&format!(
-                    " ('{}', '{}', '{}', '{}', embed_p('{}'), '{}', '{}', ['{}'], '{}', now(), ''),",
+                    " ('{}', '{}', '{}', '{}', embed_p('{}'), '{}', '{}', ['{}'], '{}', now(), '', '{}', '{}', '{}'),",
                     event.id,
                     event.search_type,
                     event.query.replace('?', "|q"),
@@ -76,7 +97,10 @@ pub async fn send_to_clickhouse(
                     event.latency,
                     event.top_score,
                     event.results.join("','"),
-                    event.dataset_id
+                    event.dataset_id,
+                    escape_search_query_value(&event.user_id),
+                    event.experiment_id,
+                    escape_search_query_value(&event.experiment_variant),
                 ));
 
                 if search_queries_inserter.len() > 13000 {
@@ -90,7 +114,7 @@ pub async fn send_to_clickhouse(
                                 "Error writing to ClickHouse search_queries".to_string(),
                             )
                         })?;
-                    search_queries_inserter = String::from("INSERT INTO search_queries (id, search_type, query, request_params, query_vector, latency, top_score, results, dataset_id, created_at, query_rating) VALUES");
+                    search_queries_inserter = String::from("INSERT INTO search_queries (id, search_type, query, request_params, query_vector, latency, top_score, results, dataset_id, created_at, query_rating, user_id, experiment_id, experiment_variant) VALUES");
                 }
             }
             ClickHouseEvent::RecommendationEvent(event) => {
@@ -120,10 +144,19 @@ pub async fn send_to_clickhouse(
                     ))
                 })?;
             }
+            ClickHouseEvent::AuditLogEvent(event) => {
+                audit_logs_inserter.write(&event).await.map_err(|e| {
+                    log::error!("Error writing audit log event: {:?}", e);
+                    ServiceError::InternalServerError(format!(
+                        "Error writing audit log event: {:?}",
+                        e
+                    ))
+                })?;
+            }
         }
     }
 
-    if search_queries_inserter != *"INSERT INTO search_queries (id, search_type, query, request_params, query_vector, latency, top_score, results, dataset_id, created_at, query_rating) VALUES" {
+    if search_queries_inserter != *"INSERT INTO search_queries (id, search_type, query, request_params, query_vector, latency, top_score, results, dataset_id, created_at, query_rating, user_id, experiment_id, experiment_variant) VALUES" {
         clickhouse_client
             .query(&search_queries_inserter[..search_queries_inserter.len() - 1])
             .execute()
@@ -146,26 +179,154 @@ pub async fn send_to_clickhouse(
         log::error!("Error ending worker events inserter: {:?}", e);
         ServiceError::InternalServerError(format!("Error ending worker events inserter: {:?}", e))
     })?;
+    audit_logs_inserter.end().await.map_err(|e| {
+        log::error!("Error ending audit logs inserter: {:?}", e);
+        ServiceError::InternalServerError(format!("Error ending audit logs inserter: {:?}", e))
+    })?;
 
     Ok(())
 }
 
+/// Redis list the webhook worker consumes worker events from.
+pub const WEBHOOK_EVENT_QUEUE: &str = "webhook_event_queue";
+
+/// Events kept in the webhook event queue by default, overridable with `WEBHOOK_EVENT_QUEUE_MAX_LEN`. The oldest events are dropped past it so the list can't grow without bound while no webhook worker is consuming it.
+const DEFAULT_WEBHOOK_EVENT_QUEUE_MAX_LEN: i64 = 100_000;
+
 #[derive(Default, Clone)]
 pub struct EventQueue {
     sender: Option<mpsc::Sender<ClickHouseEvent>>,
-    clickhouse_client: clickhouse::Client,
+    analytics_backend: AnalyticsBackend,
+    webhook_redis_pool: Option<RedisPool>,
+    forward_redis_pool: Option<RedisPool>,
 }
 
 impl EventQueue {
     pub fn new(clickhouse_client: clickhouse::Client) -> Self {
+        Self::from_analytics_backend(AnalyticsBackend::ClickHouse(ClickHouseAnalyticsStore {
+            clickhouse_client,
+        }))
+    }
+
+    pub fn from_analytics_backend(analytics_backend: AnalyticsBackend) -> Self {
         Self {
             sender: None,
-            clickhouse_client,
+            analytics_backend,
+            webhook_redis_pool: None,
+            forward_redis_pool: None,
+        }
+    }
+
+    /// Forwards worker events to the webhook worker when `USE_WEBHOOKS` is set. This works whether or not analytics are enabled.
+    pub fn with_webhooks(mut self, redis_pool: RedisPool) -> Self {
+        if std::env::var("USE_WEBHOOKS")
+            .unwrap_or("false".to_string())
+            .parse()
+            .unwrap_or(false)
+        {
+            log::info!("Webhooks enabled");
+            self.webhook_redis_pool = Some(redis_pool);
+        }
+        self
+    }
+
+    /// Queues analytics events for the analytics export worker when `USE_ANALYTICS_FORWARDING` is set. The worker POSTs them to the forward url of datasets which configured one.
+    pub fn with_analytics_forwarding(mut self, redis_pool: RedisPool) -> Self {
+        if std::env::var("USE_ANALYTICS_FORWARDING")
+            .unwrap_or("false".to_string())
+            .parse()
+            .unwrap_or(false)
+        {
+            log::info!("Analytics forwarding enabled");
+            self.forward_redis_pool = Some(redis_pool);
+        }
+        self
+    }
+
+    async fn send_to_forwarder(&self, message: AnalyticsForwardMessage) {
+        let Some(redis_pool) = &self.forward_redis_pool else {
+            return;
+        };
+
+        let Ok(serialized_message) = serde_json::to_string(&message) else {
+            return;
+        };
+
+        let mut redis_conn = match redis_pool.get().await {
+            Ok(redis_conn) => redis_conn,
+            Err(e) => {
+                log::error!(
+                    "Error getting redis connection for analytics forwarding: {:?}",
+                    e
+                );
+                return;
+            }
+        };
+
+        let _ = redis::cmd("lpush")
+            .arg(ANALYTICS_FORWARD_QUEUE)
+            .arg(serialized_message)
+            .query_async::<_, ()>(&mut *redis_conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error sending event to analytics forwarder: {:?}", e);
+            });
+    }
+
+    /// User events are written outside of the batched queue, so their handlers forward them through this.
+    pub async fn forward_event_data(&self, event_data: &EventDataClickhouse) {
+        if self.forward_redis_pool.is_some() {
+            self.send_to_forwarder(get_event_data_forward_message(event_data))
+                .await;
         }
     }
 
+    async fn send_to_webhooks(&self, event: &WorkerEventClickhouse) {
+        let Some(redis_pool) = &self.webhook_redis_pool else {
+            return;
+        };
+
+        if WebhookEventType::from_worker_event_type(&event.event_type).is_none() {
+            return;
+        }
+
+        let Ok(serialized_event) = serde_json::to_string(&WorkerEvent::from(event.clone())) else {
+            return;
+        };
+
+        let mut redis_conn = match redis_pool.get().await {
+            Ok(redis_conn) => redis_conn,
+            Err(e) => {
+                log::error!("Error getting redis connection for webhooks: {:?}", e);
+                return;
+            }
+        };
+
+        let max_len = std::env::var("WEBHOOK_EVENT_QUEUE_MAX_LEN")
+            .ok()
+            .and_then(|max_len| max_len.parse::<i64>().ok())
+            .filter(|max_len| *max_len > 0)
+            .unwrap_or(DEFAULT_WEBHOOK_EVENT_QUEUE_MAX_LEN);
+
+        let _ = redis::pipe()
+            .cmd("lpush")
+            .arg(WEBHOOK_EVENT_QUEUE)
+            .arg(serialized_event)
+            .ignore()
+            .cmd("ltrim")
+            .arg(WEBHOOK_EVENT_QUEUE)
+            .arg(0)
+            .arg(max_len - 1)
+            .ignore()
+            .query_async::<_, ()>(&mut *redis_conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error sending event to webhooks: {:?}", e);
+            });
+    }
+
     pub fn start_service(&mut self) {
-        let clickhouse_client = self.clickhouse_client.clone();
+        let analytics_backend = self.analytics_backend.clone();
         let (sender, mut reciever) = mpsc::channel(1000);
         self.sender = Some(sender);
 
@@ -177,8 +338,8 @@ impl EventQueue {
                     Some(event) = reciever.recv() => {
                         events.push(event);
                         if Instant::now().0.duration_since(timer.0).as_secs() > 10 || events.len() > 1000 {
-                            if let Err(e) = send_to_clickhouse(events.clone(), &clickhouse_client).await {
-                                log::error!("Error sending events to clickhouse: {:?}", e);
+                            if let Err(e) = analytics_backend.write_events(events.clone()).await {
+                                log::error!("Error sending events to analytics backend: {:?}", e);
                             }
                             events.clear();
                             timer = Instant::now();
@@ -186,8 +347,8 @@ impl EventQueue {
                     }
                     _ = tokio::time::sleep(Duration::from_secs(10)) => {
                         if !events.is_empty() {
-                            if let Err(e) = send_to_clickhouse(events.clone(), &clickhouse_client).await {
-                                log::error!("Error sending events to clickhouse: {:?}", e);
+                            if let Err(e) = analytics_backend.write_events(events.clone()).await {
+                                log::error!("Error sending events to analytics backend: {:?}", e);
                             }
                             events.clear();
                             timer = Instant::now();
@@ -199,6 +360,16 @@ impl EventQueue {
     }
 
     pub async fn send(&self, event: ClickHouseEvent) {
+        if let ClickHouseEvent::WorkerEvent(worker_event) = &event {
+            self.send_to_webhooks(worker_event).await;
+        }
+
+        if self.forward_redis_pool.is_some() {
+            if let Some(message) = get_analytics_forward_message(&event) {
+                self.send_to_forwarder(message).await;
+            }
+        }
+
         if let Some(sender) = &self.sender {
             let _ = sender.send(event).await.map_err(|e| {
                 log::error!("Error sending event to clickhouse: {:?}", e);
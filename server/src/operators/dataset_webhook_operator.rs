@@ -0,0 +1,711 @@
+use crate::{
+    data::models::{
+        DatasetWebhook, DatasetWebhookDelivery, Pool, WebhookDeliveryStatus, WebhookEventType,
+    },
+    errors::ServiceError,
+    operators::organization_operator::generate_api_key,
+};
+use actix_web::web;
+use clickhouse::Row;
+use diesel::prelude::*;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
+use utoipa::ToSchema;
+
+/// Deliveries which failed this many times are marked failed and are only retried when redelivered by hand.
+pub const MAX_WEBHOOK_DELIVERY_ATTEMPTS: i32 = 8;
+/// Header carrying the `t=<timestamp>,v1=<signature>` signature of a delivery.
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Trieve-Signature";
+
+pub fn generate_webhook_secret() -> String {
+    format!("whsec_{}", generate_api_key().trim_start_matches("tr-"))
+}
+
+/// Hex encoded HMAC-SHA256 of `<timestamp>.<body>` keyed with the webhook's secret. Receivers recompute it to verify a delivery came from Trieve and reject stale timestamps to prevent replays.
+pub fn sign_webhook_payload(
+    secret: &str,
+    timestamp: i64,
+    body: &str,
+) -> Result<String, ServiceError> {
+    let sign = || -> Result<Vec<u8>, openssl::error::ErrorStack> {
+        let key = PKey::hmac(secret.as_bytes())?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        signer.update(timestamp.to_string().as_bytes())?;
+        signer.update(b".")?;
+        signer.update(body.as_bytes())?;
+        signer.sign_to_vec()
+    };
+
+    let signature = sign().map_err(|err| {
+        log::error!("Failed to sign webhook payload {:?}", err);
+        ServiceError::InternalServerError("Failed to sign webhook payload".to_string())
+    })?;
+
+    Ok(signature
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Exponential backoff starting at 30 seconds and capped at 6 hours.
+pub fn get_webhook_retry_delay(attempt_number: i32) -> chrono::Duration {
+    let delay_secs = 30i64.saturating_mul(2i64.saturating_pow(attempt_number.max(1) as u32 - 1));
+    chrono::Duration::seconds(delay_secs.min(6 * 60 * 60))
+}
+
+/// Whether the address is on the public internet. Webhooks may not target loopback, private, link-local or other reserved addresses of the server's own network.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || octets[0] == 0
+                // Shared address space of carrier-grade NAT, 100.64.0.0/10
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ipv4) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(ipv4));
+            }
+
+            let segments = ip.segments();
+            let octets = ip.octets();
+            // 6to4, 2002::/16, carries the IPv4 address in the 32 bits after the prefix
+            if segments[0] == 0x2002 {
+                return is_public_ip(IpAddr::V4(Ipv4Addr::new(
+                    octets[2], octets[3], octets[4], octets[5],
+                )));
+            }
+            // Well-known NAT64 prefix, 64:ff9b::/96, carries the IPv4 address in the last 32 bits
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                return is_public_ip(IpAddr::V4(Ipv4Addr::new(
+                    octets[12], octets[13], octets[14], octets[15],
+                )));
+            }
+
+            let first_segment = segments[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local, fc00::/7
+                || (first_segment & 0xfe00) == 0xfc00
+                // Link-local, fe80::/10
+                || (first_segment & 0xffc0) == 0xfe80
+                // Local-use NAT64, 64:ff9b:1::/48, which translates to networks of the operator's choosing
+                || segments[..3] == [0x64, 0xff9b, 0x1])
+        }
+    }
+}
+
+/// Private webhook targets are allowed when `WEBHOOK_ALLOW_PRIVATE_URLS` is set, e.g. to test against a local receiver.
+fn allow_private_webhook_urls() -> bool {
+    std::env::var("WEBHOOK_ALLOW_PRIVATE_URLS")
+        .unwrap_or("false".to_string())
+        .parse()
+        .unwrap_or(false)
+}
+
+/// Resolves the host and fails unless every address it resolves to is public.
+async fn resolve_public_addrs(host: String, port: u16) -> Result<Vec<SocketAddr>, ServiceError> {
+    let addrs = tokio::task::spawn_blocking(move || {
+        (host.as_str(), port)
+            .to_socket_addrs()
+            .map(|addrs| addrs.collect::<Vec<SocketAddr>>())
+    })
+    .await
+    .map_err(|_| ServiceError::InternalServerError("Failed to resolve webhook host".to_string()))?
+    .map_err(|_| ServiceError::BadRequest("Could not resolve webhook url host".to_string()))?;
+
+    if addrs.is_empty()
+        || (!allow_private_webhook_urls() && addrs.iter().any(|addr| !is_public_ip(addr.ip())))
+    {
+        return Err(ServiceError::BadRequest(
+            "Webhook url must resolve to a public address".to_string(),
+        ));
+    }
+
+    Ok(addrs)
+}
+
+pub async fn validate_webhook_url(url: &str) -> Result<(), ServiceError> {
+    let parsed_url = reqwest::Url::parse(url)
+        .map_err(|_| ServiceError::BadRequest("Invalid webhook url".to_string()))?;
+
+    if !matches!(parsed_url.scheme(), "http" | "https") {
+        return Err(ServiceError::BadRequest(
+            "Webhook url must use http or https".to_string(),
+        ));
+    }
+
+    let host = parsed_url
+        .host_str()
+        .ok_or(ServiceError::BadRequest(
+            "Webhook url must have a host".to_string(),
+        ))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+
+    match host.parse::<IpAddr>() {
+        Ok(ip) if !is_public_ip(ip) && !allow_private_webhook_urls() => Err(
+            ServiceError::BadRequest("Webhook url must resolve to a public address".to_string()),
+        ),
+        Ok(_) => Ok(()),
+        Err(_) => {
+            resolve_public_addrs(
+                host.to_string(),
+                parsed_url.port_or_known_default().unwrap_or(443),
+            )
+            .await?;
+
+            Ok(())
+        }
+    }
+}
+
+/// DNS resolver for the webhook client which refuses hosts resolving to a non-public address, so a url which passed validation can not be pointed at the internal network later by changing its DNS records.
+pub struct PublicDnsResolver;
+
+impl reqwest::dns::Resolve for PublicDnsResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = resolve_public_addrs(host, 0).await.map_err(
+                |err| -> Box<dyn std::error::Error + Send + Sync> { err.to_string().into() },
+            )?;
+
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// HTTP client for requests to user supplied urls. It does not follow redirects and only connects to public addresses.
+pub fn build_webhook_http_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(std::sync::Arc::new(PublicDnsResolver))
+        .build()
+}
+
+pub async fn create_dataset_webhook_query(
+    webhook: DatasetWebhook,
+    pool: &web::Data<Pool>,
+) -> Result<DatasetWebhook, ServiceError> {
+    use crate::data::schema::dataset_webhooks::dsl as dataset_webhooks_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::insert_into(dataset_webhooks_columns::dataset_webhooks)
+        .values(&webhook)
+        .get_result::<DatasetWebhook>(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Error creating webhook {:?}", err);
+            ServiceError::BadRequest("Error creating webhook".to_string())
+        })
+}
+
+pub async fn get_dataset_webhooks_query(
+    dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<Vec<DatasetWebhook>, ServiceError> {
+    use crate::data::schema::dataset_webhooks::dsl as dataset_webhooks_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    dataset_webhooks_columns::dataset_webhooks
+        .filter(dataset_webhooks_columns::dataset_id.eq(dataset_id))
+        .order_by(dataset_webhooks_columns::created_at.asc())
+        .select(DatasetWebhook::as_select())
+        .load::<DatasetWebhook>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting webhooks".to_string()))
+}
+
+pub async fn get_dataset_webhook_query(
+    webhook_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<DatasetWebhook, ServiceError> {
+    use crate::data::schema::dataset_webhooks::dsl as dataset_webhooks_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    dataset_webhooks_columns::dataset_webhooks
+        .filter(dataset_webhooks_columns::id.eq(webhook_id))
+        .filter(dataset_webhooks_columns::dataset_id.eq(dataset_id))
+        .select(DatasetWebhook::as_select())
+        .first::<DatasetWebhook>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::NotFound("Webhook not found".to_string()))
+}
+
+pub async fn update_dataset_webhook_query(
+    webhook: DatasetWebhook,
+    pool: &web::Data<Pool>,
+) -> Result<DatasetWebhook, ServiceError> {
+    use crate::data::schema::dataset_webhooks::dsl as dataset_webhooks_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::update(
+        dataset_webhooks_columns::dataset_webhooks
+            .filter(dataset_webhooks_columns::id.eq(webhook.id))
+            .filter(dataset_webhooks_columns::dataset_id.eq(webhook.dataset_id)),
+    )
+    .set((
+        dataset_webhooks_columns::url.eq(webhook.url),
+        dataset_webhooks_columns::event_types.eq(webhook.event_types),
+        dataset_webhooks_columns::active.eq(webhook.active),
+        dataset_webhooks_columns::updated_at.eq(chrono::Utc::now().naive_local()),
+    ))
+    .get_result::<DatasetWebhook>(&mut conn)
+    .await
+    .map_err(|_| ServiceError::BadRequest("Error updating webhook".to_string()))
+}
+
+pub async fn delete_dataset_webhook_query(
+    webhook_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::dataset_webhooks::dsl as dataset_webhooks_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let deleted = diesel::delete(
+        dataset_webhooks_columns::dataset_webhooks
+            .filter(dataset_webhooks_columns::id.eq(webhook_id))
+            .filter(dataset_webhooks_columns::dataset_id.eq(dataset_id)),
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(|_| ServiceError::BadRequest("Error deleting webhook".to_string()))?;
+
+    if deleted == 0 {
+        return Err(ServiceError::NotFound("Webhook not found".to_string()));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct WebhookDeliveriesResponse {
+    pub deliveries: Vec<DatasetWebhookDelivery>,
+    pub total_pages: i64,
+}
+
+pub async fn get_webhook_deliveries_query(
+    webhook_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    status: Option<WebhookDeliveryStatus>,
+    page: u64,
+    pool: &web::Data<Pool>,
+) -> Result<WebhookDeliveriesResponse, ServiceError> {
+    use crate::data::schema::dataset_webhook_deliveries::dsl as deliveries_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let mut query = deliveries_columns::dataset_webhook_deliveries
+        .filter(deliveries_columns::webhook_id.eq(webhook_id))
+        .filter(deliveries_columns::dataset_id.eq(dataset_id))
+        .into_boxed();
+    let mut count_query = deliveries_columns::dataset_webhook_deliveries
+        .filter(deliveries_columns::webhook_id.eq(webhook_id))
+        .filter(deliveries_columns::dataset_id.eq(dataset_id))
+        .into_boxed();
+
+    if let Some(status) = status {
+        query = query.filter(deliveries_columns::status.eq(status.to_string()));
+        count_query = count_query.filter(deliveries_columns::status.eq(status.to_string()));
+    }
+
+    let deliveries = query
+        .order_by(deliveries_columns::created_at.desc())
+        .offset(((page.max(1) - 1) * 10).try_into().unwrap_or(0))
+        .limit(10)
+        .select(DatasetWebhookDelivery::as_select())
+        .load::<DatasetWebhookDelivery>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting webhook deliveries".to_string()))?;
+
+    let count = count_query
+        .count()
+        .get_result::<i64>(&mut conn)
+        .await
+        .map_err(|_| {
+            ServiceError::BadRequest("Error getting webhook deliveries count".to_string())
+        })?;
+
+    Ok(WebhookDeliveriesResponse {
+        deliveries,
+        total_pages: (count as f64 / 10.0).ceil() as i64,
+    })
+}
+
+/// Queues a delivery for another round of attempts, whatever its current status.
+pub async fn redeliver_webhook_delivery_query(
+    delivery_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<DatasetWebhookDelivery, ServiceError> {
+    use crate::data::schema::dataset_webhook_deliveries::dsl as deliveries_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::update(
+        deliveries_columns::dataset_webhook_deliveries
+            .filter(deliveries_columns::id.eq(delivery_id))
+            .filter(deliveries_columns::dataset_id.eq(dataset_id)),
+    )
+    .set((
+        deliveries_columns::status.eq(WebhookDeliveryStatus::Pending.to_string()),
+        deliveries_columns::attempt_number.eq(0),
+        deliveries_columns::next_attempt_at.eq(chrono::Utc::now().naive_local()),
+        deliveries_columns::updated_at.eq(chrono::Utc::now().naive_local()),
+    ))
+    .get_result::<DatasetWebhookDelivery>(&mut conn)
+    .await
+    .map_err(|_| ServiceError::NotFound("Webhook delivery not found".to_string()))
+}
+
+/// Records a delivery for every active webhook of the dataset subscribed to the event type. The webhook worker picks them up on its next poll.
+pub async fn create_webhook_deliveries_query(
+    dataset_id: uuid::Uuid,
+    event_type: WebhookEventType,
+    data: serde_json::Value,
+    pool: &web::Data<Pool>,
+) -> Result<Vec<DatasetWebhookDelivery>, ServiceError> {
+    use crate::data::schema::dataset_webhook_deliveries::dsl as deliveries_columns;
+
+    let deliveries = get_dataset_webhooks_query(dataset_id, pool)
+        .await?
+        .into_iter()
+        .filter(|webhook| webhook.subscribes_to(event_type))
+        .map(|webhook| {
+            DatasetWebhookDelivery::from_details(webhook.id, dataset_id, event_type, data.clone())
+        })
+        .collect::<Vec<DatasetWebhookDelivery>>();
+
+    if deliveries.is_empty() {
+        return Ok(deliveries);
+    }
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::insert_into(deliveries_columns::dataset_webhook_deliveries)
+        .values(&deliveries)
+        .execute(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Error creating webhook deliveries {:?}", err);
+            ServiceError::BadRequest("Error creating webhook deliveries".to_string())
+        })?;
+
+    Ok(deliveries)
+}
+
+/// Claims up to `limit` pending deliveries which are due by pushing their next attempt back by `lease`, so several webhook workers never send the same delivery at once.
+pub async fn claim_due_webhook_deliveries_query(
+    limit: i64,
+    lease: chrono::Duration,
+    pool: &web::Data<Pool>,
+) -> Result<Vec<(DatasetWebhookDelivery, DatasetWebhook)>, ServiceError> {
+    use crate::data::schema::dataset_webhook_deliveries::dsl as deliveries_columns;
+    use crate::data::schema::dataset_webhooks::dsl as dataset_webhooks_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let now = chrono::Utc::now().naive_local();
+
+    let claimed_ids = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            async move {
+                let due_ids = deliveries_columns::dataset_webhook_deliveries
+                    .filter(
+                        deliveries_columns::status.eq(WebhookDeliveryStatus::Pending.to_string()),
+                    )
+                    .filter(deliveries_columns::next_attempt_at.le(now))
+                    .order_by(deliveries_columns::next_attempt_at.asc())
+                    .limit(limit)
+                    .select(deliveries_columns::id)
+                    .for_update()
+                    .skip_locked()
+                    .load::<uuid::Uuid>(conn)
+                    .await?;
+
+                diesel::update(
+                    deliveries_columns::dataset_webhook_deliveries
+                        .filter(deliveries_columns::id.eq_any(&due_ids)),
+                )
+                .set(deliveries_columns::next_attempt_at.eq(now + lease))
+                .execute(conn)
+                .await?;
+
+                Ok(due_ids)
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(|err| {
+            log::error!("Error claiming webhook deliveries {:?}", err);
+            ServiceError::BadRequest("Error claiming webhook deliveries".to_string())
+        })?;
+
+    if claimed_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    deliveries_columns::dataset_webhook_deliveries
+        .inner_join(dataset_webhooks_columns::dataset_webhooks)
+        .filter(deliveries_columns::id.eq_any(claimed_ids))
+        .select((
+            DatasetWebhookDelivery::as_select(),
+            DatasetWebhook::as_select(),
+        ))
+        .load::<(DatasetWebhookDelivery, DatasetWebhook)>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting webhook deliveries".to_string()))
+}
+
+/// Makes one signed attempt at a delivery and records the outcome. Failed attempts are scheduled again with backoff until `MAX_WEBHOOK_DELIVERY_ATTEMPTS` is reached.
+pub async fn attempt_webhook_delivery_query(
+    delivery: DatasetWebhookDelivery,
+    webhook: DatasetWebhook,
+    http_client: &reqwest::Client,
+    pool: &web::Data<Pool>,
+) -> Result<WebhookDeliveryStatus, ServiceError> {
+    use crate::data::schema::dataset_webhook_deliveries::dsl as deliveries_columns;
+
+    let attempt_number = delivery.attempt_number + 1;
+
+    let (response_status, error) = if !webhook.active {
+        (None, Some("Webhook is inactive".to_string()))
+    } else {
+        let body = delivery.payload.to_string();
+        let timestamp = chrono::Utc::now().timestamp();
+        let signature = sign_webhook_payload(&webhook.secret, timestamp, &body)?;
+
+        match http_client
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .header("X-Trieve-Webhook-Id", webhook.id.to_string())
+            .header("X-Trieve-Delivery-Id", delivery.id.to_string())
+            .header("X-Trieve-Event", delivery.event_type.clone())
+            .header(
+                WEBHOOK_SIGNATURE_HEADER,
+                format!("t={},v1={}", timestamp, signature),
+            )
+            .body(body)
+            .send()
+            .await
+        {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16() as i32), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16() as i32),
+                Some(format!("Webhook responded with {}", response.status())),
+            ),
+            Err(err) => (None, Some(err.to_string())),
+        }
+    };
+
+    let now = chrono::Utc::now().naive_local();
+    let status = match &error {
+        None => WebhookDeliveryStatus::Succeeded,
+        Some(_) if !webhook.active || attempt_number >= MAX_WEBHOOK_DELIVERY_ATTEMPTS => {
+            WebhookDeliveryStatus::Failed
+        }
+        Some(_) => WebhookDeliveryStatus::Pending,
+    };
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::update(
+        deliveries_columns::dataset_webhook_deliveries
+            .filter(deliveries_columns::id.eq(delivery.id)),
+    )
+    .set((
+        deliveries_columns::status.eq(status.to_string()),
+        deliveries_columns::attempt_number.eq(attempt_number),
+        deliveries_columns::response_status.eq(response_status),
+        deliveries_columns::error.eq(error),
+        deliveries_columns::next_attempt_at.eq(now + get_webhook_retry_delay(attempt_number)),
+        deliveries_columns::updated_at.eq(now),
+    ))
+    .execute(&mut conn)
+    .await
+    .map_err(|_| ServiceError::BadRequest("Error updating webhook delivery".to_string()))?;
+
+    Ok(status)
+}
+
+/// Datasets with an active webhook subscribed to `event_type`.
+pub async fn get_datasets_subscribed_to_query(
+    event_type: WebhookEventType,
+    pool: &web::Data<Pool>,
+) -> Result<Vec<uuid::Uuid>, ServiceError> {
+    use crate::data::schema::dataset_webhooks::dsl as dataset_webhooks_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    dataset_webhooks_columns::dataset_webhooks
+        .filter(dataset_webhooks_columns::active.eq(true))
+        .filter(dataset_webhooks_columns::event_types.contains(vec![event_type.to_string()]))
+        .select(dataset_webhooks_columns::dataset_id)
+        .distinct()
+        .load::<uuid::Uuid>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting subscribed datasets".to_string()))
+}
+
+#[derive(Debug, Row, Serialize, Deserialize, Clone)]
+pub struct SearchConfidenceClickhouse {
+    #[serde(with = "clickhouse::serde::uuid")]
+    pub dataset_id: uuid::Uuid,
+    pub total_searches: u64,
+    pub low_confidence_searches: u64,
+}
+
+/// Datasets where at least `min_low_confidence_ratio` of the searches in the last `window_minutes` had a top score below `score_threshold`. Datasets with fewer than `min_searches` searches in the window are skipped so a handful of bad queries do not count as a spike.
+pub async fn get_low_confidence_search_spikes_query(
+    dataset_ids: Vec<uuid::Uuid>,
+    window_minutes: u32,
+    score_threshold: f32,
+    min_searches: u64,
+    min_low_confidence_ratio: f64,
+    clickhouse_client: &clickhouse::Client,
+) -> Result<Vec<SearchConfidenceClickhouse>, ServiceError> {
+    if dataset_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let search_confidence = clickhouse_client
+        .query(
+            "SELECT ?fields FROM (
+                SELECT dataset_id, count(*) AS total_searches, countIf(top_score < ?) AS low_confidence_searches
+                FROM search_queries
+                WHERE dataset_id IN ? AND created_at >= now() - INTERVAL ? MINUTE
+                GROUP BY dataset_id
+            )",
+        )
+        .bind(score_threshold)
+        .bind(dataset_ids)
+        .bind(window_minutes)
+        .fetch_all::<SearchConfidenceClickhouse>()
+        .await
+        .map_err(|err| {
+            log::error!("Failed to get search confidence {:?}", err);
+            ServiceError::BadRequest("Failed to get search confidence".to_string())
+        })?;
+
+    Ok(search_confidence
+        .into_iter()
+        .filter(|confidence| {
+            confidence.total_searches >= min_searches
+                && confidence.low_confidence_searches as f64
+                    >= confidence.total_searches as f64 * min_low_confidence_ratio
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_sign_webhook_payload() {
+        let signature =
+            sign_webhook_payload("whsec_test", 1700000000, r#"{"event":"chunks_created"}"#)
+                .unwrap();
+
+        assert_eq!(
+            signature,
+            "733cae81929f53294353d5292f1a34e61b5e480ef5d11654ca353787ae0ec8a7"
+        );
+        assert_ne!(
+            signature,
+            sign_webhook_payload("whsec_test", 1700000001, r#"{"event":"chunks_created"}"#)
+                .unwrap()
+        );
+        assert_ne!(
+            signature,
+            sign_webhook_payload("whsec_other", 1700000000, r#"{"event":"chunks_created"}"#)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    pub fn test_is_public_ip() {
+        for ip in [
+            "93.184.216.34",
+            "2606:4700:4700::1111",
+            "2002:5db8:d822::1",
+            "64:ff9b::5db8:d822",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "2002:7f00:1::1",
+            "2002:a9fe:a9fe::1",
+            "2002:c0a8:101::",
+            "64:ff9b::7f00:1",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b::a01:203",
+            "64:ff9b:1::5db8:d822",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    pub fn test_get_webhook_retry_delay() {
+        assert_eq!(get_webhook_retry_delay(1), chrono::Duration::seconds(30));
+        assert_eq!(get_webhook_retry_delay(3), chrono::Duration::seconds(120));
+        assert_eq!(get_webhook_retry_delay(40), chrono::Duration::hours(6));
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+
+use crate::{
+    data::models::{AnalyticsForwardMessage, CrawlRequest, DeadLetter, DeadLetterQueue, RedisPool},
+    errors::ServiceError,
+    handlers::chunk_handler::BulkUploadIngestionMessage,
+    operators::{
+        dataset_operator::DeleteMessage,
+        typo_operator::{CreateBkTreeMessage, ProcessWordsFromDatasetMessage},
+    },
+};
+use broccoli_queue::queue::{BroccoliQueue, RetryStrategy};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Attempts broccoli makes for a message before it is dead lettered, configured with BROCCOLI_MAX_ATTEMPTS.
+pub fn broccoli_max_attempts() -> u8 {
+    std::env::var("BROCCOLI_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|attempts| attempts.parse().ok())
+        .filter(|attempts| *attempts > 0)
+        .unwrap_or(3)
+}
+
+/// Retry strategy for the queues of workers which dead letter messages, so their failure handlers agree with broccoli on the last attempt.
+pub fn broccoli_retry_strategy() -> RetryStrategy {
+    RetryStrategy::new()
+        .with_attempts(broccoli_max_attempts())
+        .retry_failed(true)
+}
+
+/// Whether a failed message has used up its attempts and will not be retried by broccoli.
+pub fn is_last_broccoli_attempt(attempts: u8) -> bool {
+    attempts as usize + 1 >= broccoli_max_attempts() as usize
+}
+
+/// Hash of queue name to the number of dead letters from that queue, used for the DLQ depth metric.
+pub const DEAD_LETTER_DEPTH_KEY: &str = "dead_letter_depth";
+
+fn dead_letters_key(dataset_id: uuid::Uuid) -> String {
+    format!("dead_letters:{}", dataset_id)
+}
+
+/// Sorted set of a dataset's dead letter ids scored by created_at, optionally only for one queue, used to page through them.
+fn dead_letters_index_key(dataset_id: uuid::Uuid, queue: Option<DeadLetterQueue>) -> String {
+    match queue {
+        Some(queue) => format!("dead_letters_index:{}:{}", dataset_id, queue),
+        None => format!("dead_letters_index:{}", dataset_id),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct DeadLettersResponse {
+    pub dead_letters: Vec<DeadLetter>,
+    /// Total number of dead letters matching the filter across all pages.
+    pub total: usize,
+}
+
+/// Stores a message which exhausted its retries so it can be inspected and replayed later.
+pub async fn add_dead_letter<T: Serialize>(
+    queue: DeadLetterQueue,
+    queue_name: &str,
+    dataset_id: uuid::Uuid,
+    payload: &T,
+    error: String,
+    redis_pool: &RedisPool,
+) -> Result<DeadLetter, ServiceError> {
+    let dead_letter = DeadLetter {
+        id: uuid::Uuid::new_v4(),
+        dataset_id,
+        queue,
+        queue_name: queue_name.to_string(),
+        payload: serde_json::to_value(payload).map_err(|_| {
+            ServiceError::InternalServerError("Failed to serialize dead letter".to_string())
+        })?,
+        error,
+        created_at: chrono::Utc::now().naive_utc(),
+    };
+
+    let serialized_dead_letter = serde_json::to_string(&dead_letter).map_err(|_| {
+        ServiceError::InternalServerError("Failed to serialize dead letter".to_string())
+    })?;
+
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    redis::pipe()
+        .atomic()
+        .cmd("HSET")
+        .arg(dead_letters_key(dataset_id))
+        .arg(dead_letter.id.to_string())
+        .arg(serialized_dead_letter)
+        .ignore()
+        .cmd("ZADD")
+        .arg(dead_letters_index_key(dataset_id, None))
+        .arg(dead_letter.created_at.and_utc().timestamp_millis())
+        .arg(dead_letter.id.to_string())
+        .ignore()
+        .cmd("ZADD")
+        .arg(dead_letters_index_key(dataset_id, Some(queue)))
+        .arg(dead_letter.created_at.and_utc().timestamp_millis())
+        .arg(dead_letter.id.to_string())
+        .ignore()
+        .cmd("HINCRBY")
+        .arg(DEAD_LETTER_DEPTH_KEY)
+        .arg(queue.to_string())
+        .arg(1)
+        .ignore()
+        .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    Ok(dead_letter)
+}
+
+pub async fn get_dead_letters_query(
+    dataset_id: uuid::Uuid,
+    queue: Option<DeadLetterQueue>,
+    page: u64,
+    page_size: u64,
+    redis_pool: &RedisPool,
+) -> Result<DeadLettersResponse, ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    let index_key = dead_letters_index_key(dataset_id, queue);
+    let start = page.saturating_sub(1) * page_size;
+
+    let (total, dead_letter_ids): (usize, Vec<String>) = redis::pipe()
+        .cmd("ZCARD")
+        .arg(&index_key)
+        .cmd("ZREVRANGE")
+        .arg(&index_key)
+        .arg(start)
+        .arg((start + page_size.max(1)) as i64 - 1)
+        .query_async(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    if dead_letter_ids.is_empty() {
+        return Ok(DeadLettersResponse {
+            dead_letters: vec![],
+            total,
+        });
+    }
+
+    let serialized_dead_letters: Vec<Option<String>> = redis::cmd("HMGET")
+        .arg(dead_letters_key(dataset_id))
+        .arg(&dead_letter_ids)
+        .query_async(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    let dead_letters = serialized_dead_letters
+        .iter()
+        .flatten()
+        .filter_map(|dead_letter| serde_json::from_str(dead_letter).ok())
+        .collect();
+
+    Ok(DeadLettersResponse {
+        dead_letters,
+        total,
+    })
+}
+
+pub async fn get_dead_letter_query(
+    dataset_id: uuid::Uuid,
+    dead_letter_id: uuid::Uuid,
+    redis_pool: &RedisPool,
+) -> Result<DeadLetter, ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    let serialized_dead_letter: Option<String> = redis::cmd("HGET")
+        .arg(dead_letters_key(dataset_id))
+        .arg(dead_letter_id.to_string())
+        .query_async(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    let serialized_dead_letter = serialized_dead_letter
+        .ok_or(ServiceError::NotFound("Dead letter not found".to_string()))?;
+
+    serde_json::from_str(&serialized_dead_letter).map_err(|_| {
+        ServiceError::InternalServerError("Failed to deserialize dead letter".to_string())
+    })
+}
+
+pub async fn delete_dead_letter_query(
+    dead_letter: &DeadLetter,
+    redis_pool: &RedisPool,
+) -> Result<(), ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    let removed: usize = redis::cmd("HDEL")
+        .arg(dead_letters_key(dead_letter.dataset_id))
+        .arg(dead_letter.id.to_string())
+        .query_async(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    if removed > 0 {
+        redis::pipe()
+            .cmd("ZREM")
+            .arg(dead_letters_index_key(dead_letter.dataset_id, None))
+            .arg(dead_letter.id.to_string())
+            .ignore()
+            .cmd("ZREM")
+            .arg(dead_letters_index_key(
+                dead_letter.dataset_id,
+                Some(dead_letter.queue),
+            ))
+            .arg(dead_letter.id.to_string())
+            .ignore()
+            .cmd("HINCRBY")
+            .arg(DEAD_LETTER_DEPTH_KEY)
+            .arg(dead_letter.queue.to_string())
+            .arg(-1)
+            .ignore()
+            .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+    }
+
+    Ok(())
+}
+
+fn parse_dead_letter_payload<T: serde::de::DeserializeOwned>(
+    payload: serde_json::Value,
+    queue: DeadLetterQueue,
+) -> Result<T, ServiceError> {
+    serde_json::from_value(payload).map_err(|err| {
+        ServiceError::BadRequest(format!("Payload is not a valid {} message: {}", queue, err))
+    })
+}
+
+fn check_dead_letter_dataset(
+    dead_letter: &DeadLetter,
+    dataset_id: uuid::Uuid,
+) -> Result<(), ServiceError> {
+    if dataset_id != dead_letter.dataset_id {
+        return Err(ServiceError::BadRequest(
+            "The payload must belong to the same dataset as the dead letter".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Pushes the dead letter's payload, or the edited payload if one is given, back onto its queue with the attempt number reset and removes the dead letter.
+pub async fn replay_dead_letter_query(
+    dead_letter: DeadLetter,
+    payload: Option<serde_json::Value>,
+    redis_pool: &RedisPool,
+    broccoli_queue: &BroccoliQueue,
+) -> Result<(), ServiceError> {
+    let payload = payload.unwrap_or(dead_letter.payload.clone());
+
+    let redis_message = match dead_letter.queue {
+        DeadLetterQueue::Ingestion => {
+            let mut message: BulkUploadIngestionMessage =
+                parse_dead_letter_payload(payload, dead_letter.queue)?;
+            check_dead_letter_dataset(&dead_letter, message.dataset_id)?;
+            message.attempt_number = 0;
+
+            broccoli_queue
+                .publish(
+                    &dead_letter.queue_name,
+                    Some(message.dataset_id.to_string()),
+                    &message,
+                    None,
+                )
+                .await
+                .map_err(|err| {
+                    log::error!("Error publishing dead letter to queue: {:?}", err);
+                    ServiceError::InternalServerError(
+                        "Error publishing dead letter to queue".to_string(),
+                    )
+                })?;
+
+            None
+        }
+        DeadLetterQueue::Crawl => {
+            let mut message: CrawlRequest = parse_dead_letter_payload(payload, dead_letter.queue)?;
+            check_dead_letter_dataset(&dead_letter, message.dataset_id)?;
+            message.attempt_number = 0;
+
+            broccoli_queue
+                .publish(&dead_letter.queue_name, None, &message, None)
+                .await
+                .map_err(|err| {
+                    log::error!("Error publishing dead letter to queue: {:?}", err);
+                    ServiceError::InternalServerError(
+                        "Error publishing dead letter to queue".to_string(),
+                    )
+                })?;
+
+            None
+        }
+        DeadLetterQueue::Delete => {
+            let mut message: DeleteMessage = parse_dead_letter_payload(payload, dead_letter.queue)?;
+            check_dead_letter_dataset(&dead_letter, message.dataset_id())?;
+            match message {
+                DeleteMessage::DatasetDelete(ref mut message) => message.attempt_number = 0,
+                DeleteMessage::ChunkDelete(ref mut message) => message.attempt_number = 0,
+            }
+
+            Some(("lpush", serde_json::to_string(&message)))
+        }
+        DeadLetterQueue::Dictionary => {
+            let mut message: ProcessWordsFromDatasetMessage =
+                parse_dead_letter_payload(payload, dead_letter.queue)?;
+            if message
+                .chunks_to_process
+                .iter()
+                .any(|(_, dataset_id)| *dataset_id != dead_letter.dataset_id)
+            {
+                return Err(ServiceError::BadRequest(
+                    "The payload must belong to the same dataset as the dead letter".to_string(),
+                ));
+            }
+            message.attempt_number = 0;
+
+            Some(("lpush", serde_json::to_string(&message)))
+        }
+        DeadLetterQueue::BkTree => {
+            let mut message: CreateBkTreeMessage =
+                parse_dead_letter_payload(payload, dead_letter.queue)?;
+            check_dead_letter_dataset(&dead_letter, message.dataset_id)?;
+            message.attempt_number = 0;
+
+            Some(("SADD", serde_json::to_string(&message)))
+        }
+        DeadLetterQueue::AnalyticsForward => {
+            let mut message: AnalyticsForwardMessage =
+                parse_dead_letter_payload(payload, dead_letter.queue)?;
+            check_dead_letter_dataset(&dead_letter, message.dataset_id)?;
+            message.attempt_number = 0;
+
+            Some(("lpush", serde_json::to_string(&message)))
+        }
+    };
+
+    if let Some((command, serialized_message)) = redis_message {
+        let serialized_message = serialized_message.map_err(|_| {
+            ServiceError::InternalServerError("Failed to serialize dead letter".to_string())
+        })?;
+
+        let mut redis_conn = redis_pool
+            .get()
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+        redis::cmd(command)
+            .arg(&dead_letter.queue_name)
+            .arg(serialized_message)
+            .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+    }
+
+    delete_dead_letter_query(&dead_letter, redis_pool).await
+}
+
+/// Returns the number of dead letters per queue.
+pub async fn get_dead_letter_depths_query(
+    redis_pool: &RedisPool,
+) -> Result<HashMap<String, i64>, ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    redis::cmd("HGETALL")
+        .arg(DEAD_LETTER_DEPTH_KEY)
+        .query_async(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))
+}
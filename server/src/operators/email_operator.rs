@@ -10,6 +10,31 @@ pub fn get_smtp_creds() -> Credentials {
     Credentials::new(smtp_username.to_owned(), smtp_password.to_owned())
 }
 
+/// Local SMTP stand-ins such as Mailpit or MailHog accept plain connections without credentials. Set SMTP_DISABLE_TLS=true and SMTP_PORT to send to one.
+fn get_mailer(smtp_relay: &str) -> SmtpTransport {
+    let disable_tls: bool = std::env::var("SMTP_DISABLE_TLS")
+        .unwrap_or("false".to_string())
+        .parse()
+        .unwrap_or(false);
+
+    let mut mailer_builder = if disable_tls {
+        SmtpTransport::builder_dangerous(smtp_relay)
+    } else {
+        SmtpTransport::relay(smtp_relay)
+            .expect("Failed to create mailer")
+            .credentials(get_smtp_creds())
+    };
+
+    if let Some(smtp_port) = std::env::var("SMTP_PORT")
+        .ok()
+        .and_then(|port| port.parse::<u16>().ok())
+    {
+        mailer_builder = mailer_builder.port(smtp_port);
+    }
+
+    mailer_builder.build()
+}
+
 pub fn send_email(
     html_email_body: String,
     to_address: String,
@@ -18,11 +43,7 @@ pub fn send_email(
     let smtp_relay = get_env!("SMTP_RELAY", "SMTP_RELAY should be set");
     let smtp_email_address = get_env!("SMTP_EMAIL_ADDRESS", "SMTP_EMAIL_ADDRESS should be set");
 
-    let smtp_creds = get_smtp_creds();
-    let mailer = SmtpTransport::relay(smtp_relay)
-        .expect("Failed to create mailer")
-        .credentials(smtp_creds)
-        .build();
+    let mailer = get_mailer(smtp_relay);
 
     let email = Message::builder()
         .from(smtp_email_address.parse().expect("Invalid email address"))
@@ -0,0 +1,353 @@
+use crate::{
+    data::models::{
+        DatasetConfiguration, EngagementRankingConfig, RedisPool, ScoreChunkDTO, ScoreExplanation,
+        SortOptions,
+    },
+    errors::ServiceError,
+};
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How much each kind of event counts towards a chunk's engagement relative to a click.
+const CLICK_EVENT_WEIGHT: f64 = 1.0;
+const ADD_TO_CART_EVENT_WEIGHT: f64 = 3.0;
+const PURCHASE_EVENT_WEIGHT: f64 = 5.0;
+/// Clicks are weighted by their position raised to this exponent, the inverse of the probability a user examines a result at that position.
+const POSITION_BIAS_EXPONENT: f64 = 1.0;
+/// Upper bound on the position correction so a handful of clicks deep in the results cannot dominate.
+const MAX_POSITION_CORRECTION: f64 = 10.0;
+/// Weighted events at which a chunk gets half of the maximum boost. Boosts saturate towards 1 as engagement grows.
+const ENGAGEMENT_PRIOR: f64 = 5.0;
+/// Share of the combined boost coming from engagement on the same query, the rest comes from engagement across all queries.
+const QUERY_BOOST_SHARE: f64 = 0.7;
+/// Maximum number of events read from ClickHouse per dataset.
+const MAX_ENGAGEMENT_EVENTS: u64 = 1_000_000;
+/// Maximum number of query and chunk pairs stored per dataset, the most engaged are kept.
+const MAX_QUERY_BOOSTS: usize = 100_000;
+/// Stored boosts expire if the job stops refreshing them.
+const ENGAGEMENT_BOOSTS_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+fn engagement_boosts_key(dataset_id: uuid::Uuid) -> String {
+    format!("engagement_boosts:{}", dataset_id)
+}
+
+fn chunk_boost_field(chunk_id: uuid::Uuid) -> String {
+    format!("chunk:{}", chunk_id)
+}
+
+fn query_boost_field(query: &str, chunk_id: uuid::Uuid) -> String {
+    format!("query:{}:{}", query, chunk_id)
+}
+
+fn normalize_engagement_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn saturate_engagement(weighted_events: f64) -> f64 {
+    weighted_events / (weighted_events + ENGAGEMENT_PRIOR)
+}
+
+#[derive(Debug, Row, Serialize, Deserialize, Clone)]
+struct EngagementEventRow {
+    query: String,
+    event_type: String,
+    items: Vec<String>,
+    clicked_chunk_id: String,
+    position: i64,
+    created_at_unix: u32,
+}
+
+impl EngagementEventRow {
+    /// Chunks the event engaged with and how much the event counts for each of them after position-bias correction.
+    fn weighted_chunks(&self) -> Vec<(uuid::Uuid, f64)> {
+        match self.event_type.as_str() {
+            "click" => {
+                let position = self.position.max(1) as f64;
+                let correction = position
+                    .powf(POSITION_BIAS_EXPONENT)
+                    .min(MAX_POSITION_CORRECTION);
+
+                uuid::Uuid::parse_str(&self.clicked_chunk_id)
+                    .map(|chunk_id| vec![(chunk_id, CLICK_EVENT_WEIGHT * correction)])
+                    .unwrap_or_default()
+            }
+            "add_to_cart" | "purchase" => {
+                let weight = if self.event_type == "purchase" {
+                    PURCHASE_EVENT_WEIGHT
+                } else {
+                    ADD_TO_CART_EVENT_WEIGHT
+                };
+
+                self.items
+                    .iter()
+                    .filter_map(|item| uuid::Uuid::parse_str(item).ok())
+                    .map(|chunk_id| (chunk_id, weight))
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// Computes the dataset's engagement boosts from its click, add to cart and purchase events over the configured lookback. Each event is weighted by its kind, corrected for the position the chunk was shown at and decayed by its age. Returns the boosts keyed by the redis hash field they are stored under.
+pub async fn compute_engagement_boosts(
+    dataset_id: uuid::Uuid,
+    config: &EngagementRankingConfig,
+    clickhouse_client: &clickhouse::Client,
+) -> Result<HashMap<String, f64>, ServiceError> {
+    let now = chrono::Utc::now().timestamp();
+    let since = now - config.lookback_days * 24 * 60 * 60;
+
+    let rows = clickhouse_client
+        .query(
+            "SELECT
+                sq.query AS query,
+                events.event_type AS event_type,
+                events.items AS items,
+                JSONExtractString(events.metadata, 'chunk_id') AS clicked_chunk_id,
+                JSONExtractInt(events.metadata, 'position') AS position,
+                toUnixTimestamp(events.created_at) AS created_at_unix
+            FROM events
+            LEFT JOIN (
+                SELECT id, query
+                FROM search_queries
+                WHERE dataset_id = ? AND created_at >= toDateTime(?)
+            ) AS sq ON toUUIDOrZero(events.request_id) = sq.id
+            WHERE events.dataset_id = ?
+                AND events.event_type IN ('click', 'add_to_cart', 'purchase')
+                AND events.created_at >= toDateTime(?)
+            LIMIT ?",
+        )
+        .bind(dataset_id)
+        .bind(since)
+        .bind(dataset_id)
+        .bind(since)
+        .bind(MAX_ENGAGEMENT_EVENTS)
+        .fetch_all::<EngagementEventRow>()
+        .await
+        .map_err(|e| {
+            log::error!("Error fetching engagement events: {:?}", e);
+            ServiceError::InternalServerError("Error fetching engagement events".to_string())
+        })?;
+
+    let half_life_secs = config.half_life_days as f64 * 24.0 * 60.0 * 60.0;
+    let mut chunk_engagement: HashMap<uuid::Uuid, f64> = HashMap::new();
+    let mut query_engagement: HashMap<(String, uuid::Uuid), f64> = HashMap::new();
+
+    for row in rows.iter() {
+        let age_secs = (now - row.created_at_unix as i64).max(0) as f64;
+        let decay = 0.5_f64.powf(age_secs / half_life_secs);
+        let query = normalize_engagement_query(&row.query);
+
+        for (chunk_id, weight) in row.weighted_chunks() {
+            *chunk_engagement.entry(chunk_id).or_insert(0.0) += weight * decay;
+            if !query.is_empty() {
+                *query_engagement
+                    .entry((query.clone(), chunk_id))
+                    .or_insert(0.0) += weight * decay;
+            }
+        }
+    }
+
+    let mut query_engagement = query_engagement.into_iter().collect::<Vec<_>>();
+    query_engagement.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    query_engagement.truncate(MAX_QUERY_BOOSTS);
+
+    let mut boosts: HashMap<String, f64> = chunk_engagement
+        .into_iter()
+        .map(|(chunk_id, engagement)| {
+            (chunk_boost_field(chunk_id), saturate_engagement(engagement))
+        })
+        .collect();
+    boosts.extend(
+        query_engagement
+            .into_iter()
+            .map(|((query, chunk_id), engagement)| {
+                (
+                    query_boost_field(&query, chunk_id),
+                    saturate_engagement(engagement),
+                )
+            }),
+    );
+
+    Ok(boosts)
+}
+
+/// Replaces the dataset's stored engagement boosts. The new boosts are written to a temporary key which is renamed over the old one so searches never see a partially written set.
+pub async fn store_engagement_boosts(
+    dataset_id: uuid::Uuid,
+    boosts: HashMap<String, f64>,
+    redis_pool: &RedisPool,
+) -> Result<(), ServiceError> {
+    let key = engagement_boosts_key(dataset_id);
+    let tmp_key = format!("{}:tmp", key);
+
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    if boosts.is_empty() {
+        redis::cmd("DEL")
+            .arg(&key)
+            .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+        return Ok(());
+    }
+
+    redis::cmd("DEL")
+        .arg(&tmp_key)
+        .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    let boosts = boosts.into_iter().collect::<Vec<(String, f64)>>();
+    for batch in boosts.chunks(1000) {
+        let mut cmd = redis::cmd("HSET");
+        cmd.arg(&tmp_key);
+        for (field, boost) in batch {
+            cmd.arg(field).arg(boost);
+        }
+
+        cmd.query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+    }
+
+    redis::pipe()
+        .atomic()
+        .cmd("RENAME")
+        .arg(&tmp_key)
+        .arg(&key)
+        .ignore()
+        .cmd("EXPIRE")
+        .arg(&key)
+        .arg(ENGAGEMENT_BOOSTS_TTL_SECS)
+        .ignore()
+        .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    Ok(())
+}
+
+/// Engagement boosts of the chunks of a single search along with the weight they are applied with.
+#[derive(Debug, Clone)]
+pub struct EngagementBoosts {
+    pub weight: f64,
+    pub query_boosts: HashMap<uuid::Uuid, f64>,
+    pub chunk_boosts: HashMap<uuid::Uuid, f64>,
+}
+
+impl EngagementBoosts {
+    /// Multiplies the score of each chunk by 1 + weight times its combined boost. Chunks are not resorted.
+    pub fn apply(&self, chunks: Vec<ScoreChunkDTO>, explain: bool) -> Vec<ScoreChunkDTO> {
+        chunks
+            .into_iter()
+            .map(|mut chunk| {
+                let chunk_id = chunk.metadata[0].metadata().id;
+                let query_boost = self.query_boosts.get(&chunk_id).copied().unwrap_or(0.0);
+                let chunk_boost = self.chunk_boosts.get(&chunk_id).copied().unwrap_or(0.0);
+                let boost =
+                    QUERY_BOOST_SHARE * query_boost + (1.0 - QUERY_BOOST_SHARE) * chunk_boost;
+
+                if explain {
+                    chunk.explain = Some(ScoreExplanation {
+                        score_before_engagement: chunk.score,
+                        query_engagement_boost: query_boost,
+                        chunk_engagement_boost: chunk_boost,
+                        engagement_boost: boost,
+                        engagement_weight: self.weight,
+                    });
+                }
+
+                chunk.score *= 1.0 + self.weight * boost;
+                chunk
+            })
+            .collect()
+    }
+}
+
+/// Looks up the stored engagement boosts for the chunks of a search. Returns None when engagement ranking is disabled for the dataset or the request, or when the boosts could not be read, so that searches never fail because of this stage.
+pub async fn get_engagement_boosts(
+    dataset_id: uuid::Uuid,
+    query: &str,
+    chunks: &[ScoreChunkDTO],
+    config: &DatasetConfiguration,
+    sort_options: Option<&SortOptions>,
+    redis_pool: &RedisPool,
+) -> Option<EngagementBoosts> {
+    if !config.ENGAGEMENT_RANKING.enabled || chunks.is_empty() {
+        return None;
+    }
+
+    let weight = sort_options
+        .and_then(|sort_options| sort_options.engagement_weight)
+        .unwrap_or(config.ENGAGEMENT_RANKING.weight)
+        .max(0.0) as f64;
+    if weight == 0.0 {
+        return None;
+    }
+
+    let query = normalize_engagement_query(query);
+    let chunk_ids = chunks
+        .iter()
+        .map(|chunk| chunk.metadata[0].metadata().id)
+        .collect::<Vec<uuid::Uuid>>();
+
+    let mut fields = chunk_ids
+        .iter()
+        .map(|chunk_id| chunk_boost_field(*chunk_id))
+        .collect::<Vec<String>>();
+    fields.extend(
+        chunk_ids
+            .iter()
+            .map(|chunk_id| query_boost_field(&query, *chunk_id)),
+    );
+
+    let mut redis_conn = match redis_pool.get().await {
+        Ok(redis_conn) => redis_conn,
+        Err(err) => {
+            log::error!(
+                "Failed to get redis connection for engagement boosts: {:?}",
+                err
+            );
+            return None;
+        }
+    };
+
+    let values = match redis::cmd("HMGET")
+        .arg(engagement_boosts_key(dataset_id))
+        .arg(fields)
+        .query_async::<redis::aio::MultiplexedConnection, Vec<Option<f64>>>(&mut *redis_conn)
+        .await
+    {
+        Ok(values) => values,
+        Err(err) => {
+            log::error!("Failed to get engagement boosts: {:?}", err);
+            return None;
+        }
+    };
+
+    let (chunk_values, query_values) = values.split_at(chunk_ids.len().min(values.len()));
+    let collect_boosts = |values: &[Option<f64>]| {
+        chunk_ids
+            .iter()
+            .zip(values.iter())
+            .filter_map(|(chunk_id, value)| value.map(|value| (*chunk_id, value)))
+            .collect::<HashMap<uuid::Uuid, f64>>()
+    };
+
+    Some(EngagementBoosts {
+        weight,
+        query_boosts: collect_boosts(query_values),
+        chunk_boosts: collect_boosts(chunk_values),
+    })
+}
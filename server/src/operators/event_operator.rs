@@ -2,7 +2,6 @@ use crate::{
     data::models::{EventTypeRequest, WorkerEvent, WorkerEventClickhouse},
     errors::ServiceError,
 };
-use actix_web::web;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -18,7 +17,7 @@ pub async fn get_events_query(
     page: i64,
     page_size: i64,
     event_types: Vec<EventTypeRequest>,
-    clickhouse_client: web::Data<clickhouse::Client>,
+    clickhouse_client: &clickhouse::Client,
 ) -> Result<EventReturn, ServiceError> {
     let query = format!(
         "
@@ -27,7 +26,8 @@ pub async fn get_events_query(
             dataset_id,
             event_type,
             event_data,
-            created_at
+            created_at,
+            ingestion_job_id
         FROM dataset_events
         WHERE dataset_id = '{dataset_id}' AND event_type IN ({event_types})
         ORDER BY created_at DESC
@@ -0,0 +1,642 @@
+use crate::{
+    data::models::{
+        DatasetConfiguration, Experiment, ExperimentAssignment, ExperimentVariant, Pool,
+    },
+    errors::ServiceError,
+    handlers::{chunk_handler::SearchChunksReqPayload, message_handler::CreateMessageReqPayload},
+};
+use actix_web::web;
+use clickhouse::Row;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+/// z-score of the two-sided 95% intervals reported on experiment results.
+const CONFIDENCE_Z: f64 = 1.96;
+
+pub async fn create_experiment_query(
+    experiment: Experiment,
+    pool: &web::Data<Pool>,
+) -> Result<Experiment, ServiceError> {
+    use crate::data::schema::experiments::dsl as experiments_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let created_experiment: Experiment = diesel::insert_into(experiments_columns::experiments)
+        .values(&experiment)
+        .get_result(&mut conn)
+        .await
+        .map_err(|err| match err {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => ServiceError::BadRequest(
+                "Dataset already has an active experiment, stop it before starting another one"
+                    .to_string(),
+            ),
+            _ => ServiceError::BadRequest("Error creating experiment, try again".to_string()),
+        })?;
+
+    Ok(created_experiment)
+}
+
+pub async fn get_experiments_for_dataset_query(
+    given_dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<Vec<Experiment>, ServiceError> {
+    use crate::data::schema::experiments::dsl as experiments_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    experiments_columns::experiments
+        .filter(experiments_columns::dataset_id.eq(given_dataset_id))
+        .order(experiments_columns::created_at.desc())
+        .select(Experiment::as_select())
+        .load(&mut conn)
+        .await
+        .map_err(|_db_error| {
+            ServiceError::BadRequest("Error getting experiments for the dataset".to_string())
+        })
+}
+
+pub async fn get_experiment_by_id_query(
+    experiment_id: uuid::Uuid,
+    given_dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<Experiment, ServiceError> {
+    use crate::data::schema::experiments::dsl as experiments_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    experiments_columns::experiments
+        .filter(experiments_columns::id.eq(experiment_id))
+        .filter(experiments_columns::dataset_id.eq(given_dataset_id))
+        .select(Experiment::as_select())
+        .first(&mut conn)
+        .await
+        .map_err(|_db_error| ServiceError::NotFound("Experiment not found".to_string()))
+}
+
+pub async fn get_active_experiment_query(
+    given_dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<Option<Experiment>, ServiceError> {
+    use crate::data::schema::experiments::dsl as experiments_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    experiments_columns::experiments
+        .filter(experiments_columns::dataset_id.eq(given_dataset_id))
+        .filter(experiments_columns::active.eq(true))
+        .select(Experiment::as_select())
+        .first(&mut conn)
+        .await
+        .optional()
+        .map_err(|_db_error| {
+            ServiceError::BadRequest("Error getting active experiment".to_string())
+        })
+}
+
+pub async fn update_experiment_query(
+    experiment: Experiment,
+    pool: &web::Data<Pool>,
+) -> Result<Experiment, ServiceError> {
+    use crate::data::schema::experiments::dsl as experiments_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::update(
+        experiments_columns::experiments
+            .filter(experiments_columns::id.eq(experiment.id))
+            .filter(experiments_columns::dataset_id.eq(experiment.dataset_id)),
+    )
+    .set((
+        experiments_columns::name.eq(&experiment.name),
+        experiments_columns::variants.eq(&experiment.variants),
+        experiments_columns::active.eq(experiment.active),
+        experiments_columns::updated_at.eq(diesel::dsl::now),
+    ))
+    .get_result(&mut conn)
+    .await
+    .map_err(|err| match err {
+        diesel::result::Error::NotFound => {
+            ServiceError::NotFound("Experiment not found".to_string())
+        }
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            _,
+        ) => ServiceError::BadRequest(
+            "Dataset already has an active experiment, stop it before starting another one"
+                .to_string(),
+        ),
+        _ => ServiceError::BadRequest("Error updating experiment, try again".to_string()),
+    })
+}
+
+pub async fn delete_experiment_query(
+    experiment_id: uuid::Uuid,
+    given_dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::experiments::dsl as experiments_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let deleted_count = diesel::delete(
+        experiments_columns::experiments
+            .filter(experiments_columns::id.eq(experiment_id))
+            .filter(experiments_columns::dataset_id.eq(given_dataset_id)),
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(|_db_error| {
+        ServiceError::BadRequest("Error deleting experiment, try again".to_string())
+    })?;
+
+    if deleted_count == 0 {
+        return Err(ServiceError::NotFound("Experiment not found".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Checks that the variants can be bucketed into and that their overrides apply cleanly, so a bad variant is rejected up front instead of failing its users' requests.
+pub fn validate_experiment_variants(variants: &[ExperimentVariant]) -> Result<(), ServiceError> {
+    if variants.len() < 2 {
+        return Err(ServiceError::BadRequest(
+            "An experiment must have at least two variants".to_string(),
+        ));
+    }
+
+    let mut names = HashSet::new();
+    for variant in variants {
+        if variant.name.trim().is_empty() {
+            return Err(ServiceError::BadRequest(
+                "Variant names cannot be empty".to_string(),
+            ));
+        }
+        if !names.insert(variant.name.as_str()) {
+            return Err(ServiceError::BadRequest(format!(
+                "Variant name {} is used more than once",
+                variant.name
+            )));
+        }
+        if !variant.weight.is_finite() || variant.weight < 0.0 {
+            return Err(ServiceError::BadRequest(format!(
+                "Weight of variant {} must be a non-negative number",
+                variant.name
+            )));
+        }
+
+        for overrides in [&variant.search_overrides, &variant.message_overrides]
+            .into_iter()
+            .flatten()
+        {
+            if !overrides.is_object() {
+                return Err(ServiceError::BadRequest(format!(
+                    "Overrides of variant {} must be JSON objects",
+                    variant.name
+                )));
+            }
+        }
+
+        apply_overrides(SearchChunksReqPayload::default(), &variant.search_overrides)?;
+    }
+
+    if variants.iter().all(|variant| variant.weight == 0.0) {
+        return Err(ServiceError::BadRequest(
+            "At least one variant must have a positive weight".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Replaces the top-level fields of the payload with the ones set on the overrides.
+pub fn apply_overrides<T: Serialize + DeserializeOwned>(
+    payload: T,
+    overrides: &Option<serde_json::Value>,
+) -> Result<T, ServiceError> {
+    let Some(serde_json::Value::Object(overrides)) = overrides else {
+        return Ok(payload);
+    };
+
+    let mut payload_json = serde_json::to_value(payload)
+        .map_err(|_| ServiceError::BadRequest("Failed to serialize request payload".to_string()))?;
+    if let serde_json::Value::Object(fields) = &mut payload_json {
+        for (key, value) in overrides {
+            fields.insert(key.clone(), value.clone());
+        }
+    }
+
+    serde_json::from_value(payload_json).map_err(|err| {
+        ServiceError::BadRequest(format!("Invalid experiment variant overrides: {}", err))
+    })
+}
+
+/// Buckets the user into a variant of the dataset's active experiment, if there is one. Requests without a user_id are not part of experiments.
+pub async fn get_experiment_assignment(
+    given_dataset_id: uuid::Uuid,
+    user_id: Option<&String>,
+    pool: &web::Data<Pool>,
+) -> Option<ExperimentAssignment> {
+    let user_id = user_id.filter(|user_id| !user_id.is_empty())?;
+
+    let experiment = match get_active_experiment_query(given_dataset_id, pool).await {
+        Ok(experiment) => experiment?,
+        Err(err) => {
+            log::error!("Error getting active experiment: {:?}", err);
+            return None;
+        }
+    };
+
+    experiment
+        .assign_variant(user_id)
+        .map(|variant| ExperimentAssignment {
+            experiment_id: experiment.id,
+            variant,
+        })
+}
+
+fn apply_config_overrides(variant: &ExperimentVariant, dataset_config: &mut DatasetConfiguration) {
+    if let Some(config_overrides) = &variant.config_overrides {
+        *dataset_config = config_overrides.from_curr_dataset(dataset_config.clone());
+    }
+}
+
+/// Applies the search and dataset configuration overrides of the user's variant to a search request.
+pub async fn apply_experiment_to_search(
+    search_data: &mut SearchChunksReqPayload,
+    dataset_config: &mut DatasetConfiguration,
+    given_dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<Option<ExperimentAssignment>, ServiceError> {
+    let Some(assignment) =
+        get_experiment_assignment(given_dataset_id, search_data.user_id.as_ref(), pool).await
+    else {
+        return Ok(None);
+    };
+
+    *search_data = apply_overrides(search_data.clone(), &assignment.variant.search_overrides)?;
+    apply_config_overrides(&assignment.variant, dataset_config);
+
+    Ok(Some(assignment))
+}
+
+/// Applies the message and dataset configuration overrides of the user's variant to a RAG completion. Variant overrides take precedence over RAG presets.
+pub async fn apply_experiment_to_message(
+    create_message_data: &mut CreateMessageReqPayload,
+    dataset_config: &mut DatasetConfiguration,
+    given_dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<Option<ExperimentAssignment>, ServiceError> {
+    let Some(assignment) =
+        get_experiment_assignment(given_dataset_id, create_message_data.user_id.as_ref(), pool)
+            .await
+    else {
+        return Ok(None);
+    };
+
+    *create_message_data = apply_overrides(
+        create_message_data.clone(),
+        &assignment.variant.message_overrides,
+    )?;
+    apply_config_overrides(&assignment.variant, dataset_config);
+
+    Ok(Some(assignment))
+}
+
+#[derive(Debug, Row, Serialize, Deserialize)]
+struct VariantSearchRow {
+    experiment_variant: String,
+    searches: u64,
+    searches_with_click: u64,
+    searches_with_conversion: u64,
+    ratings: u64,
+    rating_avg: f64,
+    rating_stddev: f64,
+}
+
+#[derive(Debug, Row, Serialize, Deserialize)]
+struct VariantRagRow {
+    experiment_variant: String,
+    rag_queries: u64,
+    ratings: u64,
+    rating_avg: f64,
+    rating_stddev: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+/// A metric of a variant with its 95% confidence interval. Variants whose intervals do not overlap differ significantly.
+pub struct MetricInterval {
+    pub value: f64,
+    pub lower: f64,
+    pub upper: f64,
+    /// Number of searches, completions or ratings the metric was computed over.
+    pub sample_size: u64,
+}
+
+impl MetricInterval {
+    /// Wilson score interval of a rate, which stays within [0, 1] and behaves with few samples.
+    fn from_rate(successes: u64, sample_size: u64) -> Option<Self> {
+        if sample_size == 0 {
+            return None;
+        }
+
+        let n = sample_size as f64;
+        let p = successes as f64 / n;
+        let z2 = CONFIDENCE_Z * CONFIDENCE_Z;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let margin =
+            CONFIDENCE_Z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+
+        Some(MetricInterval {
+            value: p,
+            lower: (center - margin).max(0.0),
+            upper: (center + margin).min(1.0),
+            sample_size,
+        })
+    }
+
+    /// Normal interval of a mean.
+    fn from_mean(mean: f64, stddev: f64, sample_size: u64) -> Option<Self> {
+        if sample_size == 0 || !mean.is_finite() {
+            return None;
+        }
+
+        let margin = if stddev.is_finite() {
+            CONFIDENCE_Z * stddev / (sample_size as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        Some(MetricInterval {
+            value: mean,
+            lower: mean - margin,
+            upper: mean + margin,
+            sample_size,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct ExperimentVariantResults {
+    pub variant: String,
+    pub searches: u64,
+    /// Share of the variant's searches with at least one click.
+    pub click_through_rate: Option<MetricInterval>,
+    /// Share of the variant's searches with at least one conversion event other than a click, e.g. add to cart or purchase.
+    pub conversion_rate: Option<MetricInterval>,
+    /// Average rating of the variant's rated searches.
+    pub search_rating: Option<MetricInterval>,
+    pub rag_queries: u64,
+    /// Average rating of the variant's rated RAG completions.
+    pub rag_rating: Option<MetricInterval>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct ExperimentResultsResponse {
+    pub experiment: Experiment,
+    pub variants: Vec<ExperimentVariantResults>,
+}
+
+/// Aggregates the search and RAG events recorded for each variant of the experiment.
+pub async fn get_experiment_results_query(
+    experiment: Experiment,
+    clickhouse_client: &clickhouse::Client,
+) -> Result<ExperimentResultsResponse, ServiceError> {
+    let experiment_id = experiment.id.to_string();
+
+    let search_rows = clickhouse_client
+        .query(
+            "SELECT
+                experiment_variant,
+                count() AS searches,
+                countIf(id IN (
+                    SELECT toUUIDOrZero(request_id)
+                    FROM events
+                    WHERE dataset_id = ? AND event_type = 'click'
+                )) AS searches_with_click,
+                countIf(id IN (
+                    SELECT toUUIDOrZero(request_id)
+                    FROM events
+                    WHERE dataset_id = ? AND is_conversion = true AND event_type != 'click'
+                )) AS searches_with_conversion,
+                countIf(JSONHas(query_rating, 'rating')) AS ratings,
+                avgIf(toFloat64(JSONExtractInt(query_rating, 'rating')), JSONHas(query_rating, 'rating')) AS rating_avg,
+                stddevSampIf(toFloat64(JSONExtractInt(query_rating, 'rating')), JSONHas(query_rating, 'rating')) AS rating_stddev
+            FROM search_queries
+            WHERE dataset_id = ? AND experiment_id = ? AND is_duplicate = 0
+            GROUP BY experiment_variant",
+        )
+        .bind(experiment.dataset_id)
+        .bind(experiment.dataset_id)
+        .bind(experiment.dataset_id)
+        .bind(&experiment_id)
+        .fetch_all::<VariantSearchRow>()
+        .await
+        .map_err(|e| {
+            log::error!("Error fetching experiment search results: {:?}", e);
+            ServiceError::InternalServerError(
+                "Error fetching experiment search results".to_string(),
+            )
+        })?;
+
+    let rag_rows = clickhouse_client
+        .query(
+            "SELECT
+                experiment_variant,
+                count() AS rag_queries,
+                countIf(JSONHas(query_rating, 'rating')) AS ratings,
+                avgIf(toFloat64(JSONExtractInt(query_rating, 'rating')), JSONHas(query_rating, 'rating')) AS rating_avg,
+                stddevSampIf(toFloat64(JSONExtractInt(query_rating, 'rating')), JSONHas(query_rating, 'rating')) AS rating_stddev
+            FROM rag_queries
+            WHERE dataset_id = ? AND experiment_id = ?
+            GROUP BY experiment_variant",
+        )
+        .bind(experiment.dataset_id)
+        .bind(&experiment_id)
+        .fetch_all::<VariantRagRow>()
+        .await
+        .map_err(|e| {
+            log::error!("Error fetching experiment RAG results: {:?}", e);
+            ServiceError::InternalServerError("Error fetching experiment RAG results".to_string())
+        })?;
+
+    let search_rows: HashMap<String, VariantSearchRow> = search_rows
+        .into_iter()
+        .map(|row| (row.experiment_variant.clone(), row))
+        .collect();
+    let rag_rows: HashMap<String, VariantRagRow> = rag_rows
+        .into_iter()
+        .map(|row| (row.experiment_variant.clone(), row))
+        .collect();
+
+    let variants = experiment
+        .variants()
+        .into_iter()
+        .map(|variant| {
+            let search_row = search_rows.get(&variant.name);
+            let rag_row = rag_rows.get(&variant.name);
+
+            ExperimentVariantResults {
+                searches: search_row.map(|row| row.searches).unwrap_or(0),
+                click_through_rate: search_row.and_then(|row| {
+                    MetricInterval::from_rate(row.searches_with_click, row.searches)
+                }),
+                conversion_rate: search_row.and_then(|row| {
+                    MetricInterval::from_rate(row.searches_with_conversion, row.searches)
+                }),
+                search_rating: search_row.and_then(|row| {
+                    MetricInterval::from_mean(row.rating_avg, row.rating_stddev, row.ratings)
+                }),
+                rag_queries: rag_row.map(|row| row.rag_queries).unwrap_or(0),
+                rag_rating: rag_row.and_then(|row| {
+                    MetricInterval::from_mean(row.rating_avg, row.rating_stddev, row.ratings)
+                }),
+                variant: variant.name,
+            }
+        })
+        .collect();
+
+    Ok(ExperimentResultsResponse {
+        experiment,
+        variants,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn variant(name: &str, weight: f32) -> ExperimentVariant {
+        ExperimentVariant {
+            name: name.to_string(),
+            weight,
+            search_overrides: None,
+            message_overrides: None,
+            config_overrides: None,
+        }
+    }
+
+    fn experiment(variants: Vec<ExperimentVariant>) -> Experiment {
+        Experiment::from_details(uuid::Uuid::new_v4(), "test".to_string(), variants)
+    }
+
+    #[test]
+    pub fn test_assign_variant_is_deterministic() {
+        let experiment = experiment(vec![variant("control", 1.0), variant("treatment", 1.0)]);
+
+        for user in 0..100 {
+            let user_id = format!("user-{}", user);
+            let first = experiment.assign_variant(&user_id).map(|v| v.name);
+            let second = experiment.assign_variant(&user_id).map(|v| v.name);
+            assert!(first.is_some());
+            assert_eq!(first, second);
+        }
+    }
+
+    #[test]
+    pub fn test_assign_variant_respects_weights() {
+        let experiment = experiment(vec![
+            variant("control", 3.0),
+            variant("treatment", 1.0),
+            variant("disabled", 0.0),
+        ]);
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for user in 0..10_000 {
+            let name = experiment
+                .assign_variant(&format!("user-{}", user))
+                .expect("variant should be assigned")
+                .name;
+            *counts.entry(name).or_default() += 1;
+        }
+
+        let control = counts.get("control").copied().unwrap_or_default();
+        let treatment = counts.get("treatment").copied().unwrap_or_default();
+        assert_eq!(counts.get("disabled"), None);
+        assert_eq!(control + treatment, 10_000);
+        assert!((7_000..8_000).contains(&control), "control got {}", control);
+    }
+
+    #[test]
+    pub fn test_assign_variant_without_positive_weight() {
+        let experiment = experiment(vec![variant("control", 0.0), variant("treatment", 0.0)]);
+
+        assert!(experiment.assign_variant("user").is_none());
+    }
+
+    #[test]
+    pub fn test_validate_experiment_variants() {
+        assert!(validate_experiment_variants(&[
+            variant("control", 1.0),
+            variant("treatment", 2.0)
+        ])
+        .is_ok());
+        assert!(validate_experiment_variants(&[variant("control", 1.0)]).is_err());
+        assert!(
+            validate_experiment_variants(&[variant("control", 1.0), variant("control", 1.0)])
+                .is_err()
+        );
+        assert!(
+            validate_experiment_variants(&[variant("control", 1.0), variant(" ", 1.0)]).is_err()
+        );
+        assert!(validate_experiment_variants(&[
+            variant("control", 1.0),
+            variant("treatment", -1.0)
+        ])
+        .is_err());
+        assert!(validate_experiment_variants(&[
+            variant("control", 1.0),
+            variant("treatment", f32::NAN)
+        ])
+        .is_err());
+        assert!(validate_experiment_variants(&[
+            variant("control", 0.0),
+            variant("treatment", 0.0)
+        ])
+        .is_err());
+
+        let mut bad_overrides = variant("treatment", 1.0);
+        bad_overrides.search_overrides = Some(serde_json::json!(["not", "an", "object"]));
+        assert!(validate_experiment_variants(&[variant("control", 1.0), bad_overrides]).is_err());
+    }
+
+    #[test]
+    pub fn test_apply_overrides() {
+        let overrides = Some(serde_json::json!({"query": "overridden", "page_size": 5}));
+        let payload = SearchChunksReqPayload {
+            query: crate::data::models::QueryTypes::Single(
+                crate::data::models::SearchModalities::Text("original".to_string()),
+            ),
+            ..Default::default()
+        };
+
+        let payload = apply_overrides(payload, &overrides).expect("overrides should apply");
+        assert_eq!(payload.page_size, Some(5));
+        assert_eq!(
+            payload.query,
+            crate::data::models::QueryTypes::Single(crate::data::models::SearchModalities::Text(
+                "overridden".to_string()
+            ))
+        );
+
+        let invalid = Some(serde_json::json!({"page_size": "five"}));
+        assert!(apply_overrides(SearchChunksReqPayload::default(), &invalid).is_err());
+    }
+}
@@ -0,0 +1,545 @@
+use crate::{
+    data::models::{DatasetConfiguration, FallbackEndpoint},
+    errors::ServiceError,
+    get_env,
+    operators::usage_operator::meter_chat_completion,
+};
+use derive_more::Display;
+use openai_dive::v1::{
+    api::Client,
+    error::APIError,
+    resources::chat::{ChatCompletionParameters, ChatCompletionResponse},
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+pub enum EndpointKind {
+    #[display(fmt = "llm")]
+    Llm,
+    #[display(fmt = "embedding")]
+    Embedding,
+    #[display(fmt = "reranker")]
+    Reranker,
+}
+
+/// A fully resolved endpoint with the base URL, key and model that requests should be sent with.
+#[derive(Debug, Clone)]
+pub struct ResolvedEndpoint {
+    pub base_url: String,
+    pub api_key: String,
+    /// When set, overrides the model requested by the caller.
+    pub model_name: Option<String>,
+}
+
+impl ResolvedEndpoint {
+    pub fn llm_client(&self) -> Client {
+        Client {
+            headers: None,
+            project: None,
+            api_key: self.api_key.clone(),
+            http_client: reqwest::Client::new(),
+            base_url: self.base_url.clone(),
+            organization: None,
+        }
+    }
+}
+
+/// A failed call to a single endpoint. Only transient failures are retried and counted towards the endpoint's circuit breaker, so a bad request or api key can not open the circuit for every dataset sharing the endpoint.
+#[derive(Debug, Display)]
+#[display(fmt = "{}", error)]
+pub struct EndpointError {
+    pub error: ServiceError,
+    /// Whether the failure was a connect error, timeout, 5xx or 429 response.
+    pub transient: bool,
+}
+
+impl EndpointError {
+    pub fn transient(error: ServiceError) -> Self {
+        EndpointError {
+            error,
+            transient: true,
+        }
+    }
+
+    pub fn from_status(status: u16, error: ServiceError) -> Self {
+        EndpointError {
+            error,
+            transient: status == 429 || status >= 500,
+        }
+    }
+
+    pub fn from_reqwest(err: &reqwest::Error, error: ServiceError) -> Self {
+        match err.status() {
+            Some(status) => Self::from_status(status.as_u16(), error),
+            None => EndpointError {
+                error,
+                transient: err.is_connect() || err.is_timeout(),
+            },
+        }
+    }
+
+    pub fn from_ureq(err: &ureq::Error, error: ServiceError) -> Self {
+        match err {
+            ureq::Error::Status(status, _) => Self::from_status(*status, error),
+            ureq::Error::Transport(_) => Self::transient(error),
+        }
+    }
+
+    pub fn from_llm(err: &APIError, error: ServiceError) -> Self {
+        let transient = match err {
+            APIError::AuthenticationError(_)
+            | APIError::BadRequestError(_)
+            | APIError::PermissionError(_)
+            | APIError::NotFoundError(_) => false,
+            APIError::UnknownError(status, _) => *status == 429 || *status >= 500,
+            _ => true,
+        };
+
+        EndpointError { error, transient }
+    }
+}
+
+impl From<ServiceError> for EndpointError {
+    fn from(error: ServiceError) -> Self {
+        EndpointError {
+            error,
+            transient: false,
+        }
+    }
+}
+
+impl From<EndpointError> for ServiceError {
+    fn from(err: EndpointError) -> Self {
+        err.error
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    HalfOpen,
+    Open,
+}
+
+impl CircuitState {
+    pub fn as_gauge_value(&self) -> f64 {
+        match self {
+            CircuitState::Closed => 0.0,
+            CircuitState::HalfOpen => 1.0,
+            CircuitState::Open => 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct CircuitBreaker {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn state(&self) -> CircuitState {
+        match self.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() < circuit_breaker_cooldown() => {
+                CircuitState::Open
+            }
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EndpointHealth {
+    pub kind: EndpointKind,
+    pub base_url: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+}
+
+// Circuit breakers are kept per process, so each server and worker tracks the endpoints it calls.
+static CIRCUIT_BREAKERS: once_cell::sync::Lazy<
+    Mutex<HashMap<(EndpointKind, String), CircuitBreaker>>,
+> = once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn endpoint_max_retries() -> u32 {
+    std::env::var("ENDPOINT_MAX_RETRIES")
+        .unwrap_or("2".to_string())
+        .parse()
+        .unwrap_or(2)
+}
+
+fn endpoint_retry_base_delay() -> Duration {
+    Duration::from_millis(
+        std::env::var("ENDPOINT_RETRY_BASE_DELAY_MS")
+            .unwrap_or("250".to_string())
+            .parse()
+            .unwrap_or(250),
+    )
+}
+
+fn circuit_breaker_failure_threshold() -> u32 {
+    std::env::var("CIRCUIT_BREAKER_FAILURE_THRESHOLD")
+        .unwrap_or("5".to_string())
+        .parse()
+        .unwrap_or(5)
+}
+
+fn circuit_breaker_cooldown() -> Duration {
+    Duration::from_secs(
+        std::env::var("CIRCUIT_BREAKER_COOLDOWN_SECS")
+            .unwrap_or("30".to_string())
+            .parse()
+            .unwrap_or(30),
+    )
+}
+
+fn get_circuit_state(kind: EndpointKind, base_url: &str) -> CircuitState {
+    CIRCUIT_BREAKERS
+        .lock()
+        .map(|breakers| {
+            breakers
+                .get(&(kind, base_url.to_string()))
+                .map(|breaker| breaker.state())
+                .unwrap_or(CircuitState::Closed)
+        })
+        .unwrap_or(CircuitState::Closed)
+}
+
+fn record_endpoint_success(kind: EndpointKind, base_url: &str) {
+    if let Ok(mut breakers) = CIRCUIT_BREAKERS.lock() {
+        breakers.insert((kind, base_url.to_string()), CircuitBreaker::default());
+    }
+}
+
+fn record_endpoint_failure(kind: EndpointKind, base_url: &str) {
+    if let Ok(mut breakers) = CIRCUIT_BREAKERS.lock() {
+        let breaker = breakers.entry((kind, base_url.to_string())).or_default();
+        let was_half_open = breaker.state() == CircuitState::HalfOpen;
+        breaker.consecutive_failures += 1;
+
+        if was_half_open || breaker.consecutive_failures >= circuit_breaker_failure_threshold() {
+            if breaker.opened_at.is_none() || was_half_open {
+                log::error!(
+                    "Opening circuit for {} endpoint {} after {} consecutive failures",
+                    kind,
+                    base_url,
+                    breaker.consecutive_failures
+                );
+            }
+            breaker.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+/// Returns the circuit state of every endpoint this process has called.
+pub fn get_endpoint_health() -> Vec<EndpointHealth> {
+    CIRCUIT_BREAKERS
+        .lock()
+        .map(|breakers| {
+            breakers
+                .iter()
+                .map(|((kind, base_url), breaker)| EndpointHealth {
+                    kind: *kind,
+                    base_url: base_url.clone(),
+                    state: breaker.state(),
+                    consecutive_failures: breaker.consecutive_failures,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Calls `call` against each endpoint in order until one succeeds. Transient failures are retried with exponential backoff and open the endpoint's circuit once they exceed the threshold, while other failures move straight on to the next endpoint. Endpoints with an open circuit are skipped.
+pub async fn call_with_fallbacks<T, F, Fut>(
+    kind: EndpointKind,
+    endpoints: &[ResolvedEndpoint],
+    call: F,
+) -> Result<T, ServiceError>
+where
+    F: Fn(ResolvedEndpoint) -> Fut,
+    Fut: Future<Output = Result<T, EndpointError>>,
+{
+    let max_retries = endpoint_max_retries();
+    let base_delay = endpoint_retry_base_delay();
+    let mut last_error = None;
+
+    for endpoint in endpoints {
+        let attempts = match get_circuit_state(kind, &endpoint.base_url) {
+            CircuitState::Open => {
+                log::warn!(
+                    "Skipping {} endpoint {} because its circuit is open",
+                    kind,
+                    endpoint.base_url
+                );
+                continue;
+            }
+            // Only a single trial request is let through while half open
+            CircuitState::HalfOpen => 1,
+            CircuitState::Closed => max_retries + 1,
+        };
+
+        let mut transient_failure = false;
+        for attempt in 0..attempts {
+            match call(endpoint.clone()).await {
+                Ok(result) => {
+                    record_endpoint_success(kind, &endpoint.base_url);
+                    return Ok(result);
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Request to {} endpoint {} failed on attempt {}: {}",
+                        kind,
+                        endpoint.base_url,
+                        attempt + 1,
+                        err
+                    );
+                    transient_failure = err.transient;
+                    last_error = Some(err.error);
+
+                    if !transient_failure {
+                        break;
+                    }
+
+                    if attempt + 1 < attempts {
+                        tokio::time::sleep(base_delay * 2u32.pow(attempt)).await;
+                    }
+                }
+            }
+        }
+
+        if transient_failure {
+            record_endpoint_failure(kind, &endpoint.base_url);
+        }
+    }
+
+    Err(
+        last_error.unwrap_or(ServiceError::InternalServerError(format!(
+            "All {} endpoints are currently unavailable",
+            kind
+        ))),
+    )
+}
+
+fn get_llm_api_key(base_url: &str, dataset_config: &DatasetConfiguration) -> String {
+    if !dataset_config.LLM_API_KEY.is_empty() {
+        dataset_config.LLM_API_KEY.clone()
+    } else if base_url.contains("openai.com") {
+        get_env!("OPENAI_API_KEY", "OPENAI_API_KEY for openai should be set").into()
+    } else {
+        get_env!(
+            "LLM_API_KEY",
+            "LLM_API_KEY for openrouter or self-hosted should be set"
+        )
+        .into()
+    }
+}
+
+/// Maps the configured embedding base URL to the origin the server should call.
+pub fn get_embedding_base_url(config_embedding_base_url: &str) -> String {
+    match config_embedding_base_url {
+        "" => get_env!("OPENAI_BASE_URL", "OPENAI_BASE_URL must be set").to_string(),
+        "https://api.openai.com/v1" => {
+            get_env!("OPENAI_BASE_URL", "OPENAI_BASE_URL must be set").to_string()
+        }
+        "https://embedding.trieve.ai" => std::env::var("EMBEDDING_SERVER_ORIGIN")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or("https://embedding.trieve.ai".to_string()),
+        "https://embedding.trieve.ai/bge-m3" => std::env::var("EMBEDDING_SERVER_ORIGIN_BGEM3")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or("https://embedding.trieve.ai/bge-m3".to_string()),
+        "https://embedding.trieve.ai/jina-code" => {
+            std::env::var("EMBEDDING_SERVER_ORIGIN_JINA_CODE")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or("https://embedding.trieve.ai/jina-code".to_string())
+        }
+        _ => config_embedding_base_url.to_string(),
+    }
+}
+
+pub fn get_embedding_api_key(config_embedding_base_url: &str) -> String {
+    let embedding_api_key = get_env!("OPENAI_API_KEY", "OPENAI_API_KEY should be set");
+
+    if config_embedding_base_url == "https://embedding.trieve.ai/jina-code" {
+        std::env::var("JINA_CODE_API_KEY")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or(embedding_api_key.to_string())
+    } else {
+        embedding_api_key.to_string()
+    }
+}
+
+/// LLM base URL used when the dataset does not set LLM_BASE_URL.
+pub const DEFAULT_LLM_BASE_URL: &str = "https://api.openai.com/api/v1";
+
+/// LLM base URL topic naming and image descriptions have always defaulted to.
+pub const OPENROUTER_BASE_URL: &str = "https://openrouter.ai/api/v1";
+
+/// The LLM_BASE_URL endpoint followed by the dataset's LLM fallbacks.
+pub fn get_llm_endpoints(dataset_config: &DatasetConfiguration) -> Vec<ResolvedEndpoint> {
+    get_llm_endpoints_with_default(dataset_config, DEFAULT_LLM_BASE_URL)
+}
+
+/// Same as `get_llm_endpoints`, with `default_base_url` in place of an empty LLM_BASE_URL.
+pub fn get_llm_endpoints_with_default(
+    dataset_config: &DatasetConfiguration,
+    default_base_url: &str,
+) -> Vec<ResolvedEndpoint> {
+    let base_url = if dataset_config.LLM_BASE_URL.is_empty() {
+        default_base_url.to_string()
+    } else {
+        dataset_config.LLM_BASE_URL.clone()
+    };
+
+    let primary = ResolvedEndpoint {
+        api_key: get_llm_api_key(&base_url, dataset_config),
+        base_url,
+        model_name: None,
+    };
+
+    std::iter::once(primary)
+        .chain(
+            dataset_config
+                .LLM_FALLBACK_ENDPOINTS
+                .iter()
+                .map(|fallback| ResolvedEndpoint {
+                    base_url: fallback.base_url.clone(),
+                    api_key: fallback
+                        .api_key
+                        .clone()
+                        .unwrap_or_else(|| get_llm_api_key(&fallback.base_url, dataset_config)),
+                    model_name: fallback.model_name.clone(),
+                }),
+        )
+        .collect()
+}
+
+fn resolve_embedding_fallback(
+    fallback: &FallbackEndpoint,
+    dataset_config: &DatasetConfiguration,
+) -> ResolvedEndpoint {
+    ResolvedEndpoint {
+        base_url: get_embedding_base_url(&fallback.base_url),
+        api_key: fallback
+            .api_key
+            .clone()
+            .unwrap_or_else(|| get_embedding_api_key(&fallback.base_url)),
+        model_name: Some(
+            fallback
+                .model_name
+                .clone()
+                .unwrap_or(dataset_config.EMBEDDING_MODEL_NAME.clone()),
+        ),
+    }
+}
+
+pub fn get_primary_embedding_endpoint(dataset_config: &DatasetConfiguration) -> ResolvedEndpoint {
+    ResolvedEndpoint {
+        base_url: get_embedding_base_url(&dataset_config.EMBEDDING_BASE_URL),
+        api_key: get_embedding_api_key(&dataset_config.EMBEDDING_BASE_URL),
+        model_name: Some(dataset_config.EMBEDDING_MODEL_NAME.clone()),
+    }
+}
+
+pub fn get_embedding_fallback_endpoints(
+    dataset_config: &DatasetConfiguration,
+) -> Vec<ResolvedEndpoint> {
+    dataset_config
+        .EMBEDDING_FALLBACK_ENDPOINTS
+        .iter()
+        .map(|fallback| resolve_embedding_fallback(fallback, dataset_config))
+        .collect()
+}
+
+/// The EMBEDDING_BASE_URL endpoint followed by the dataset's embedding fallbacks.
+pub fn get_embedding_endpoints(dataset_config: &DatasetConfiguration) -> Vec<ResolvedEndpoint> {
+    std::iter::once(get_primary_embedding_endpoint(dataset_config))
+        .chain(get_embedding_fallback_endpoints(dataset_config))
+        .collect()
+}
+
+/// The RERANKER_BASE_URL endpoint followed by the dataset's reranker fallbacks.
+pub fn get_reranker_endpoints(dataset_config: &DatasetConfiguration) -> Vec<ResolvedEndpoint> {
+    let primary = ResolvedEndpoint {
+        base_url: dataset_config.RERANKER_BASE_URL.clone(),
+        api_key: dataset_config.RERANKER_API_KEY.clone(),
+        model_name: Some(dataset_config.RERANKER_MODEL_NAME.clone()),
+    };
+
+    std::iter::once(primary)
+        .chain(
+            dataset_config
+                .RERANKER_FALLBACK_ENDPOINTS
+                .iter()
+                .map(|fallback| ResolvedEndpoint {
+                    base_url: fallback.base_url.clone(),
+                    api_key: fallback
+                        .api_key
+                        .clone()
+                        .unwrap_or(dataset_config.RERANKER_API_KEY.clone()),
+                    model_name: Some(
+                        fallback
+                            .model_name
+                            .clone()
+                            .unwrap_or(dataset_config.RERANKER_MODEL_NAME.clone()),
+                    ),
+                }),
+        )
+        .collect()
+}
+
+/// Creates a chat completion against the dataset's LLM endpoints, falling back in order when one is unavailable.
+pub async fn create_chat_completion_with_fallbacks(
+    parameters: ChatCompletionParameters,
+    dataset_config: &DatasetConfiguration,
+) -> Result<ChatCompletionResponse, ServiceError> {
+    create_chat_completion_with_default_base_url(parameters, dataset_config, DEFAULT_LLM_BASE_URL)
+        .await
+}
+
+/// Same as `create_chat_completion_with_fallbacks`, with `default_base_url` in place of an empty LLM_BASE_URL.
+pub async fn create_chat_completion_with_default_base_url(
+    parameters: ChatCompletionParameters,
+    dataset_config: &DatasetConfiguration,
+    default_base_url: &str,
+) -> Result<ChatCompletionResponse, ServiceError> {
+    let endpoints = get_llm_endpoints_with_default(dataset_config, default_base_url);
+
+    let completion = call_with_fallbacks(EndpointKind::Llm, &endpoints, |endpoint| {
+        let mut parameters = parameters.clone();
+        async move {
+            if let Some(model_name) = endpoint.model_name.clone() {
+                parameters.model = model_name;
+            }
+
+            endpoint
+                .llm_client()
+                .chat()
+                .create(parameters)
+                .await
+                .map_err(|err| {
+                    EndpointError::from_llm(
+                        &err,
+                        ServiceError::BadRequest(format!(
+                            "Bad response from LLM server provider: {}",
+                            err
+                        )),
+                    )
+                })
+        }
+    })
+    .await?;
+
+    meter_chat_completion(&completion);
+
+    Ok(completion)
+}
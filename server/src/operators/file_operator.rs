@@ -1,6 +1,7 @@
 use super::chunk_operator::{create_chunk_metadata, get_row_count_for_organization_id_query};
 use super::clickhouse_operator::{ClickHouseEvent, EventQueue};
 use super::group_operator::{create_group_from_file_query, create_groups_query};
+use super::ingestion_job_operator::create_tracked_ingestion_message;
 use super::parse_operator::{build_chunking_regex, coarse_doc_chunker};
 use crate::data::models::ChunkGroup;
 use crate::data::models::FileDTO;
@@ -96,6 +97,46 @@ pub fn get_pagefind_aws_bucket() -> Result<Bucket, ServiceError> {
     Ok(*aws_bucket)
 }
 
+pub fn get_analytics_export_aws_bucket() -> Result<Bucket, ServiceError> {
+    let aws_region_name: String =
+        std::env::var("AWS_REGION_ANALYTICS_EXPORT").unwrap_or("".to_string());
+    let s3_endpoint = std::env::var("S3_ENDPOINT_ANALYTICS_EXPORT")
+        .unwrap_or(get_env!("S3_ENDPOINT", "S3_ENDPOINT should be set").to_string());
+    let s3_bucket_name = std::env::var("S3_BUCKET_ANALYTICS_EXPORT")
+        .unwrap_or(get_env!("S3_BUCKET", "S3_BUCKET should be set").to_string());
+
+    let aws_region = Region::Custom {
+        region: aws_region_name,
+        endpoint: s3_endpoint,
+    };
+
+    let aws_credentials = if let Ok(creds) = Credentials::from_instance_metadata() {
+        creds
+    } else {
+        let s3_access_key = std::env::var("S3_ACCESS_KEY_ANALYTICS_EXPORT")
+            .unwrap_or(get_env!("S3_ACCESS_KEY", "S3_ACCESS_KEY should be set").to_string());
+        let s3_secret_key = std::env::var("S3_SECRET_KEY_ANALYTICS_EXPORT")
+            .unwrap_or(get_env!("S3_SECRET_KEY", "S3_SECRET_KEY should be set").to_string());
+
+        Credentials {
+            access_key: Some(s3_access_key),
+            secret_key: Some(s3_secret_key),
+            security_token: None,
+            session_token: None,
+            expiration: None,
+        }
+    };
+
+    let aws_bucket = Bucket::new(&s3_bucket_name, aws_region, aws_credentials)
+        .map_err(|e| {
+            log::error!("Could not create or get bucket {:?}", e);
+            ServiceError::BadRequest("Could not create or get bucket".to_string())
+        })?
+        .with_path_style();
+
+    Ok(*aws_bucket)
+}
+
 pub fn get_csvjsonl_aws_bucket() -> Result<Bucket, ServiceError> {
     let aws_region_name: String = std::env::var("AWS_REGION_CSVJSONL").unwrap_or("".to_string());
     let s3_endpoint = std::env::var("S3_ENDPOINT_CSVJSONL")
@@ -287,6 +328,7 @@ pub async fn create_file_chunks(
     pool: web::Data<Pool>,
     event_queue: web::Data<EventQueue>,
     broccoli_queue: BroccoliQueue,
+    ingestion_job_id: Option<uuid::Uuid>,
 ) -> Result<(), ServiceError> {
     let name = upload_file_data.file_name.clone();
 
@@ -410,6 +452,19 @@ pub async fn create_file_chunks(
             continue;
         }
 
+        let ingestion_message = match ingestion_job_id {
+            Some(ingestion_job_id) => {
+                create_tracked_ingestion_message(
+                    ingestion_job_id,
+                    dataset_org_plan_sub.dataset.id,
+                    ingestion_message.ingestion_messages,
+                    pool.clone(),
+                )
+                .await?
+            }
+            None => ingestion_message,
+        };
+
         broccoli_queue
             .publish(
                 "ingestion",
@@ -447,6 +502,7 @@ pub async fn get_file_query(
     pool: web::Data<Pool>,
 ) -> Result<FileDTO, actix_web::Error> {
     use crate::data::schema::files::dsl as files_columns;
+    use crate::data::schema::trash::dsl as trash_columns;
 
     let mut conn = pool
         .get()
@@ -456,6 +512,7 @@ pub async fn get_file_query(
     let file: File = files_columns::files
         .filter(files_columns::id.eq(file_uuid))
         .filter(files_columns::dataset_id.eq(dataset_id))
+        .filter(files_columns::id.ne_all(trash_columns::trash.select(trash_columns::item_id)))
         .get_result(&mut conn)
         .await
         .map_err(|e| {
@@ -505,6 +562,55 @@ pub async fn get_file_query(
     Ok(file_dto)
 }
 
+pub fn csv_jsonl_error_file_key(file_id: uuid::Uuid) -> String {
+    format!("{}-errors.jsonl", file_id)
+}
+
+pub async fn get_csv_jsonl_error_file_query(
+    file_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<String, ServiceError> {
+    use crate::data::schema::files::dsl as files_columns;
+
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|_| ServiceError::BadRequest("Could not get database connection".to_string()))?;
+
+    let file: File = files_columns::files
+        .filter(files_columns::id.eq(file_id))
+        .filter(files_columns::dataset_id.eq(dataset_id))
+        .get_result(&mut conn)
+        .await
+        .map_err(|_| ServiceError::NotFound("File with specified id not found".to_string()))?;
+
+    let bucket = get_csvjsonl_aws_bucket()?;
+    let error_file_key = csv_jsonl_error_file_key(file.id);
+    bucket.head_object(&error_file_key).await.map_err(|_| {
+        ServiceError::NotFound("No row errors were recorded for this file".to_string())
+    })?;
+
+    let mut custom_queries = HashMap::new();
+    custom_queries.insert(
+        "response-content-disposition".into(),
+        format!(
+            "attachment; filename=\"{}-errors.jsonl\"",
+            file.file_name
+                .trim_end_matches(".csv")
+                .trim_end_matches(".jsonl")
+        ),
+    );
+
+    bucket
+        .presign_get(error_file_key, 6000, Some(custom_queries))
+        .await
+        .map_err(|e| {
+            log::error!("Could not get presigned url for error file {:?}", e);
+            ServiceError::BadRequest("Could not get presigned url for error file".to_string())
+        })
+}
+
 pub async fn get_dataset_file_query(
     dataset_id: uuid::Uuid,
     page: u64,
@@ -512,6 +618,7 @@ pub async fn get_dataset_file_query(
 ) -> Result<Vec<(File, i64, Option<uuid::Uuid>)>, actix_web::Error> {
     use crate::data::schema::files::dsl as files_columns;
     use crate::data::schema::groups_from_files::dsl as groups_from_files_columns;
+    use crate::data::schema::trash::dsl as trash_columns;
 
     let mut conn = pool
         .get()
@@ -524,6 +631,7 @@ pub async fn get_dataset_file_query(
                 .on(groups_from_files_columns::file_id.eq(files_columns::id)),
         )
         .filter(files_columns::dataset_id.eq(dataset_id))
+        .filter(files_columns::id.ne_all(trash_columns::trash.select(trash_columns::item_id)))
         .select((
             File::as_select(),
             sql::<BigInt>("count(*) OVER()"),
@@ -546,6 +654,7 @@ pub async fn delete_file_query(
     dataset_config: DatasetConfiguration,
 ) -> Result<(), actix_web::Error> {
     use crate::data::schema::files::dsl as files_columns;
+    use crate::data::schema::trash::dsl as trash_columns;
 
     let mut conn = pool
         .get()
@@ -591,5 +700,13 @@ pub async fn delete_file_query(
         ServiceError::BadRequest("Could not delete file".to_string())
     })?;
 
+    diesel::delete(trash_columns::trash.filter(trash_columns::item_id.eq(file_uuid)))
+        .execute(&mut conn)
+        .await
+        .map_err(|e| {
+            log::error!("Error deleting trashed file {:?}", e);
+            ServiceError::BadRequest("Could not delete file".to_string())
+        })?;
+
     Ok(())
 }
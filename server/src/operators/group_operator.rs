@@ -9,8 +9,8 @@ use crate::operators::qdrant_operator::{
 };
 use crate::{
     data::models::{
-        ChunkGroup, ChunkGroupAndFileId, ChunkGroupBookmark, ChunkMetadataTable, Dataset,
-        DatasetConfiguration, FileGroup, Pool, RedisPool, UnifiedId,
+        AclPrincipals, ChunkGroup, ChunkGroupAndFileId, ChunkGroupBookmark, ChunkMetadataTable,
+        Dataset, DatasetConfiguration, FileGroup, Pool, RedisPool, UnifiedId,
     },
     handlers::group_handler::GroupsBookmarkQueryResult,
     operators::chunk_operator::{delete_chunk_metadata_query, get_chunk_metadatas_from_point_ids},
@@ -135,6 +135,7 @@ pub async fn create_groups_query(
                 chunk_group_columns::description.eq(excluded(chunk_group_columns::description)),
                 chunk_group_columns::metadata.eq(excluded(chunk_group_columns::metadata)),
                 chunk_group_columns::tag_set.eq(excluded(chunk_group_columns::tag_set)),
+                chunk_group_columns::acl.eq(excluded(chunk_group_columns::acl)),
             ))
             .returning(ChunkGroup::as_select())
             .get_results::<ChunkGroup>(&mut conn)
@@ -169,6 +170,7 @@ pub async fn get_groups_for_dataset_query(
     use crate::data::schema::chunk_group::dsl as chunk_group_columns;
     use crate::data::schema::dataset_group_counts::dsl as dataset_group_count_columns;
     use crate::data::schema::groups_from_files::dsl as groups_from_files_columns;
+    use crate::data::schema::trash::dsl as trash_columns;
 
     let page = if page == 0 { 1 } else { page };
     let mut conn = pool.get().await.map_err(|_e| {
@@ -188,6 +190,7 @@ pub async fn get_groups_for_dataset_query(
 
     let groups = chunk_group_columns::chunk_group
         .filter(chunk_group_columns::dataset_id.eq(dataset_uuid))
+        .filter(chunk_group_columns::id.ne_all(trash_columns::trash.select(trash_columns::item_id)))
         .order_by(chunk_group_columns::id.desc())
         .offset(((page - 1) * 10).try_into().unwrap_or(0))
         .limit(10)
@@ -227,6 +230,7 @@ pub async fn get_groups_for_dataset_query(
                 file_id,
                 created_at: group.created_at,
                 updated_at: group.updated_at,
+                acl: group.acl,
             }
         })
         .collect();
@@ -241,6 +245,7 @@ pub async fn get_group_by_id_query(
 ) -> Result<ChunkGroupAndFileId, ServiceError> {
     use crate::data::schema::chunk_group::dsl as chunk_group_columns;
     use crate::data::schema::groups_from_files::dsl as groups_from_files_columns;
+    use crate::data::schema::trash::dsl as trash_columns;
 
     let mut conn = pool.get().await.map_err(|_e| {
         ServiceError::InternalServerError("Failed to get postgres connection".to_string())
@@ -253,6 +258,7 @@ pub async fn get_group_by_id_query(
         )
         .filter(chunk_group_columns::dataset_id.eq(dataset_uuid))
         .filter(chunk_group_columns::id.eq(group_id))
+        .filter(chunk_group_columns::id.ne_all(trash_columns::trash.select(trash_columns::item_id)))
         .select((
             ChunkGroup::as_select(),
             groups_from_files_columns::file_id.nullable(),
@@ -276,6 +282,7 @@ pub async fn delete_group_by_id_query(
     use crate::data::schema::chunk_group_bookmarks::dsl as chunk_group_bookmarks_columns;
     use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
     use crate::data::schema::groups_from_files::dsl as groups_from_files_columns;
+    use crate::data::schema::trash::dsl as trash_columns;
 
     let mut conn = pool.get().await.map_err(|_e| {
         ServiceError::InternalServerError("Failed to get postgres connection".to_string())
@@ -318,6 +325,10 @@ pub async fn delete_group_by_id_query(
                 .execute(conn)
                 .await?;
 
+                diesel::delete(trash_columns::trash.filter(trash_columns::item_id.eq(group_id)))
+                    .execute(conn)
+                    .await?;
+
                 Ok(())
             }
             .scope_boxed()
@@ -407,6 +418,7 @@ pub async fn update_chunk_group_query(
         chunk_group_columns::tracking_id.eq(group.tracking_id),
         chunk_group_columns::metadata.eq(group.metadata),
         chunk_group_columns::tag_set.eq(group.tag_set),
+        chunk_group_columns::acl.eq(group.acl),
     ))
     .get_result(&mut conn)
     .await
@@ -455,6 +467,7 @@ pub async fn get_bookmarks_for_group_query(
     page: u64,
     limit: Option<u64>,
     dataset_uuid: uuid::Uuid,
+    acl_principals: &AclPrincipals,
     pool: web::Data<Pool>,
 ) -> Result<GroupsBookmarkQueryResult, ServiceError> {
     use crate::data::schema::chunk_group::dsl as chunk_group_columns;
@@ -494,11 +507,16 @@ pub async fn get_bookmarks_for_group_query(
         get_chunk_metadatas_from_point_ids(chunk_metadata_point_ids, pool.clone()).await?;
     let chunk_metadata_string_tag_sets = chunk_metadatas
         .iter()
-        .map(|chunk_metadata| chunk_metadata.metadata().into())
+        .map(|chunk_metadata| chunk_metadata.metadata())
+        .filter(|chunk_metadata| acl_principals.can_access(&chunk_metadata.acl))
+        .map(|chunk_metadata| chunk_metadata.into())
         .collect();
 
     let chunk_count = chunk_count_result?;
     let chunk_group = chunk_group_result?;
+    if !acl_principals.can_access(&chunk_group.acl) {
+        return Err(ServiceError::NotFound("Group not found".to_string()));
+    }
 
     Ok(GroupsBookmarkQueryResult {
         chunks: chunk_metadata_string_tag_sets,
@@ -0,0 +1,353 @@
+use crate::data::models::{DatasetConfiguration, GuardrailAction, GuardrailsConfig};
+use derive_more::Display;
+use once_cell::sync::Lazy;
+use openai_dive::v1::resources::chat::{ChatCompletionParameters, ChatMessage, ChatMessageContent};
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+use super::fallback_operator::create_chat_completion_with_fallbacks;
+
+static EMAIL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").expect("Valid email regex")
+});
+
+static PHONE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{3}\)|\d{3})[\s.-]?\d{3}[\s.-]?\d{4}\b")
+        .expect("Valid phone regex")
+});
+
+static CARD_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").expect("Valid card number regex"));
+
+const REDACTED: &str = "[REDACTED]";
+
+const DEFAULT_REFUSAL_MESSAGE: &str = "I'm sorry, but I can't help with that request.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum GuardrailStage {
+    #[display(fmt = "input")]
+    Input,
+    #[display(fmt = "output")]
+    Output,
+}
+
+#[derive(Debug, Clone)]
+pub struct GuardrailOutcome {
+    pub stage: GuardrailStage,
+    /// The action taken, or None when no check flagged the text.
+    pub action: Option<GuardrailAction>,
+    /// Names of the checks which flagged the text, e.g. `denylist` or `pii_email`.
+    pub violations: Vec<String>,
+    /// The text to use going forward: unchanged, redacted or the refusal message.
+    pub text: String,
+    /// The text with every located violation redacted, safe to record in analytics.
+    pub redacted_text: String,
+}
+
+impl GuardrailOutcome {
+    fn passed(stage: GuardrailStage, text: &str) -> Self {
+        GuardrailOutcome {
+            stage,
+            action: None,
+            violations: vec![],
+            text: text.to_string(),
+            redacted_text: text.to_string(),
+        }
+    }
+
+    pub fn is_refused(&self) -> bool {
+        self.action == Some(GuardrailAction::Refuse)
+    }
+
+    pub fn is_modified(&self) -> bool {
+        matches!(
+            self.action,
+            Some(GuardrailAction::Refuse) | Some(GuardrailAction::Redact)
+        )
+    }
+}
+
+/// Flattens guardrail outcomes into the `guardrail_actions` and `guardrail_violations` columns of a RAG query event.
+pub fn guardrail_event_fields(outcomes: &[&GuardrailOutcome]) -> (Vec<String>, Vec<String>) {
+    let actions = outcomes
+        .iter()
+        .filter_map(|outcome| {
+            outcome
+                .action
+                .map(|action| format!("{}:{}", outcome.stage, action))
+        })
+        .collect();
+
+    let violations = outcomes
+        .iter()
+        .flat_map(|outcome| {
+            outcome
+                .violations
+                .iter()
+                .map(move |violation| format!("{}:{}", outcome.stage, violation))
+        })
+        .collect();
+
+    (actions, violations)
+}
+
+fn passes_luhn(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() < 13 || digits.len() > 19 {
+        return false;
+    }
+
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(idx, digit)| {
+            if idx % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                *digit
+            }
+        })
+        .sum();
+
+    sum % 10 == 0
+}
+
+fn find_pattern_violations(text: &str, config: &GuardrailsConfig) -> Vec<(String, Range<usize>)> {
+    let mut found: Vec<(String, Range<usize>)> = vec![];
+
+    for phrase in config.denylist.iter().filter(|phrase| !phrase.is_empty()) {
+        if let Ok(phrase_regex) = RegexBuilder::new(&regex::escape(phrase))
+            .case_insensitive(true)
+            .build()
+        {
+            found.extend(
+                phrase_regex
+                    .find_iter(text)
+                    .map(|m| ("denylist".to_string(), m.range())),
+            );
+        }
+    }
+
+    for pattern in config.regex_patterns.iter() {
+        match Regex::new(pattern) {
+            Ok(pattern_regex) => found.extend(
+                pattern_regex
+                    .find_iter(text)
+                    .map(|m| ("regex".to_string(), m.range())),
+            ),
+            Err(err) => log::error!("Skipping invalid guardrail regex {}: {:?}", pattern, err),
+        }
+    }
+
+    if config.detect_pii {
+        found.extend(
+            EMAIL_REGEX
+                .find_iter(text)
+                .map(|m| ("pii_email".to_string(), m.range())),
+        );
+        found.extend(
+            CARD_REGEX
+                .find_iter(text)
+                .filter(|m| passes_luhn(m.as_str()))
+                .map(|m| ("pii_card_number".to_string(), m.range())),
+        );
+        found.extend(
+            PHONE_REGEX
+                .find_iter(text)
+                .map(|m| ("pii_phone".to_string(), m.range())),
+        );
+    }
+
+    found
+}
+
+fn redact_ranges(text: &str, ranges: &[Range<usize>]) -> String {
+    let mut sorted_ranges = ranges.to_vec();
+    sorted_ranges.sort_by_key(|range| range.start);
+
+    let mut redacted = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for range in sorted_ranges {
+        if range.end <= cursor {
+            continue;
+        }
+        let start = range.start.max(cursor);
+        redacted.push_str(&text[cursor..start]);
+        redacted.push_str(REDACTED);
+        cursor = range.end;
+    }
+    redacted.push_str(&text[cursor..]);
+
+    redacted
+}
+
+/// Asks the LLM whether the text violates the dataset's classifier policy. Classifier failures are logged and treated as a pass so an LLM outage does not block every message.
+async fn is_blocked_by_classifier(
+    text: &str,
+    stage: GuardrailStage,
+    classifier_prompt: &str,
+    dataset_config: &DatasetConfiguration,
+) -> bool {
+    let subject = match stage {
+        GuardrailStage::Input => "user message",
+        GuardrailStage::Output => "assistant response",
+    };
+
+    let parameters = ChatCompletionParameters {
+        model: dataset_config.LLM_DEFAULT_MODEL.clone(),
+        messages: vec![
+            ChatMessage::System {
+                content: ChatMessageContent::Text(format!(
+                    "You are a content policy classifier. Decide whether the {} below violates the following policy.\n\nPolicy: {}\n\nReply with exactly one word: ALLOW or BLOCK.",
+                    subject, classifier_prompt
+                )),
+                name: None,
+            },
+            ChatMessage::User {
+                content: ChatMessageContent::Text(text.to_string()),
+                name: None,
+            },
+        ],
+        stream: Some(false),
+        temperature: Some(0.0),
+        max_completion_tokens: Some(5),
+        ..Default::default()
+    };
+
+    match create_chat_completion_with_fallbacks(parameters, dataset_config).await {
+        Ok(response) => match response.choices.first().map(|choice| &choice.message) {
+            Some(ChatMessage::Assistant {
+                content: Some(ChatMessageContent::Text(verdict)),
+                ..
+            }) => verdict.trim().to_uppercase().starts_with("BLOCK"),
+            _ => false,
+        },
+        Err(err) => {
+            log::error!("Guardrail classifier failed: {:?}", err);
+            false
+        }
+    }
+}
+
+/// Runs the dataset's guardrail checks over a message and applies the configured action for the stage.
+pub async fn run_guardrails(
+    text: &str,
+    stage: GuardrailStage,
+    dataset_config: &DatasetConfiguration,
+) -> GuardrailOutcome {
+    let config = &dataset_config.GUARDRAILS;
+    if !config.enabled || text.is_empty() {
+        return GuardrailOutcome::passed(stage, text);
+    }
+
+    let pattern_violations = find_pattern_violations(text, config);
+
+    let blocked_by_classifier = match &config.classifier_prompt {
+        Some(classifier_prompt) if !classifier_prompt.is_empty() => {
+            is_blocked_by_classifier(text, stage, classifier_prompt, dataset_config).await
+        }
+        _ => false,
+    };
+
+    if pattern_violations.is_empty() && !blocked_by_classifier {
+        return GuardrailOutcome::passed(stage, text);
+    }
+
+    let mut violations: Vec<String> = pattern_violations
+        .iter()
+        .map(|(kind, _)| kind.clone())
+        .collect();
+    if blocked_by_classifier {
+        violations.push("classifier".to_string());
+    }
+    violations.sort();
+    violations.dedup();
+
+    let ranges: Vec<Range<usize>> = pattern_violations
+        .into_iter()
+        .map(|(_, range)| range)
+        .collect();
+    let redacted_text = redact_ranges(text, &ranges);
+
+    let configured_action = match stage {
+        GuardrailStage::Input => config.input_action.unwrap_or(GuardrailAction::Refuse),
+        GuardrailStage::Output => config.output_action.unwrap_or(GuardrailAction::Redact),
+    };
+
+    let action = match configured_action {
+        GuardrailAction::Redact if blocked_by_classifier => GuardrailAction::Refuse,
+        action => action,
+    };
+
+    let output_text = match action {
+        GuardrailAction::Refuse => config
+            .refusal_message
+            .clone()
+            .unwrap_or(DEFAULT_REFUSAL_MESSAGE.to_string()),
+        GuardrailAction::Redact => redacted_text.clone(),
+        GuardrailAction::Continue => text.to_string(),
+    };
+
+    GuardrailOutcome {
+        stage,
+        action: Some(action),
+        violations,
+        text: output_text,
+        redacted_text,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_passes_luhn() {
+        assert!(passes_luhn("4111 1111 1111 1111"));
+        assert!(passes_luhn("5500-0000-0000-0004"));
+        assert!(!passes_luhn("4111 1111 1111 1112"));
+        assert!(!passes_luhn("1234"));
+    }
+
+    #[test]
+    pub fn test_redact_ranges_merges_overlaps() {
+        let text = "call 555-123-4567 or mail a@b.co";
+        let redacted = redact_ranges(text, &[26..32, 5..17, 10..17]);
+
+        assert_eq!(redacted, "call [REDACTED] or mail [REDACTED]");
+    }
+
+    #[test]
+    pub fn test_find_pattern_violations() {
+        let config = GuardrailsConfig {
+            enabled: true,
+            denylist: vec!["Secret Plan".to_string()],
+            detect_pii: true,
+            ..Default::default()
+        };
+        let text = "the secret plan is on card 4111 1111 1111 1111, ask jo@example.com";
+
+        let mut kinds: Vec<String> = find_pattern_violations(text, &config)
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect();
+        kinds.sort();
+
+        assert_eq!(kinds, vec!["denylist", "pii_card_number", "pii_email"]);
+
+        let ranges: Vec<Range<usize>> = find_pattern_violations(text, &config)
+            .into_iter()
+            .map(|(_, range)| range)
+            .collect();
+        assert_eq!(
+            redact_ranges(text, &ranges),
+            "the [REDACTED] is on card [REDACTED], ask [REDACTED]"
+        );
+    }
+}
@@ -0,0 +1,571 @@
+use std::collections::HashMap;
+
+use crate::{
+    data::models::{
+        DatasetConfiguration, FailedIngestionChunk, IngestionBatchStatus, IngestionJob,
+        IngestionJobBatch, IngestionJobState, IngestionJobStatus, Pool, WorkerEvent,
+        WorkerEventClickhouse,
+    },
+    errors::ServiceError,
+    handlers::chunk_handler::{BulkUploadIngestionMessage, UploadIngestionMessage},
+    operators::{
+        analytics_store_operator::{AnalyticsBackend, AnalyticsStore},
+        dataset_operator::get_dataset_by_id_query,
+        dataset_webhook_operator::{
+            generate_webhook_secret, get_webhook_retry_delay, sign_webhook_payload,
+            validate_webhook_url, MAX_WEBHOOK_DELIVERY_ATTEMPTS, WEBHOOK_SIGNATURE_HEADER,
+        },
+        usage_operator::current_usage_api_key_id,
+    },
+};
+use actix_web::web;
+use diesel::prelude::*;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use serde::Deserialize;
+
+pub async fn create_ingestion_job_query(
+    ingestion_job: IngestionJob,
+    pool: web::Data<Pool>,
+) -> Result<IngestionJob, ServiceError> {
+    use crate::data::schema::ingestion_jobs::dsl as ingestion_jobs_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::insert_into(ingestion_jobs_columns::ingestion_jobs)
+        .values(&ingestion_job)
+        .execute(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Failed to create ingestion job: {:?}", err);
+            ServiceError::BadRequest("Failed to create ingestion job".to_string())
+        })?;
+
+    Ok(ingestion_job)
+}
+
+/// Records a new batch for the ingestion job and returns the message to publish to the ingestion queue.
+pub async fn create_tracked_ingestion_message(
+    ingestion_job_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    ingestion_messages: Vec<UploadIngestionMessage>,
+    pool: web::Data<Pool>,
+) -> Result<BulkUploadIngestionMessage, ServiceError> {
+    use crate::data::schema::ingestion_job_batches::dsl as ingestion_job_batches_columns;
+
+    let batch = IngestionJobBatch::from_details(ingestion_job_id, ingestion_messages.len());
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::insert_into(ingestion_job_batches_columns::ingestion_job_batches)
+        .values(&batch)
+        .execute(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Failed to create ingestion job batch: {:?}", err);
+            ServiceError::BadRequest("Failed to create ingestion job batch".to_string())
+        })?;
+
+    Ok(BulkUploadIngestionMessage {
+        attempt_number: 0,
+        dataset_id,
+        ingestion_messages,
+        ingestion_job_id: Some(ingestion_job_id),
+        ingestion_batch_id: Some(batch.id),
+        api_key_id: current_usage_api_key_id(),
+    })
+}
+
+/// Returns the message to publish to the ingestion queue, recording it as a batch of the ingestion job when one is given.
+pub async fn create_ingestion_message(
+    ingestion_job_id: Option<uuid::Uuid>,
+    dataset_id: uuid::Uuid,
+    ingestion_messages: Vec<UploadIngestionMessage>,
+    pool: web::Data<Pool>,
+) -> Result<BulkUploadIngestionMessage, ServiceError> {
+    match ingestion_job_id {
+        Some(ingestion_job_id) => {
+            create_tracked_ingestion_message(ingestion_job_id, dataset_id, ingestion_messages, pool)
+                .await
+        }
+        None => Ok(BulkUploadIngestionMessage {
+            attempt_number: 0,
+            dataset_id,
+            ingestion_messages,
+            ingestion_job_id: None,
+            ingestion_batch_id: None,
+            api_key_id: current_usage_api_key_id(),
+        }),
+    }
+}
+
+pub async fn set_ingestion_batch_status_query(
+    ingestion_batch_id: uuid::Uuid,
+    status: IngestionBatchStatus,
+    error: Option<String>,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::ingestion_job_batches::dsl as ingestion_job_batches_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::update(
+        ingestion_job_batches_columns::ingestion_job_batches
+            .filter(ingestion_job_batches_columns::id.eq(ingestion_batch_id)),
+    )
+    .set((
+        ingestion_job_batches_columns::status.eq(status.to_string()),
+        ingestion_job_batches_columns::error.eq(error),
+        ingestion_job_batches_columns::updated_at.eq(chrono::Utc::now().naive_local()),
+    ))
+    .execute(&mut conn)
+    .await
+    .map_err(|err| {
+        log::error!("Failed to update ingestion job batch status: {:?}", err);
+        ServiceError::BadRequest("Failed to update ingestion job batch status".to_string())
+    })?;
+
+    Ok(())
+}
+
+pub async fn mark_ingestion_job_queuing_complete_query(
+    ingestion_job_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::ingestion_jobs::dsl as ingestion_jobs_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::update(
+        ingestion_jobs_columns::ingestion_jobs
+            .filter(ingestion_jobs_columns::id.eq(ingestion_job_id)),
+    )
+    .set((
+        ingestion_jobs_columns::queuing_complete.eq(true),
+        ingestion_jobs_columns::updated_at.eq(chrono::Utc::now().naive_local()),
+    ))
+    .execute(&mut conn)
+    .await
+    .map_err(|err| {
+        log::error!("Failed to mark ingestion job as queued: {:?}", err);
+        ServiceError::BadRequest("Failed to mark ingestion job as queued".to_string())
+    })?;
+
+    Ok(())
+}
+
+/// Marks that every batch of the job has been queued and queues its webhook if the batches have already finished.
+pub async fn complete_ingestion_job_queuing(
+    ingestion_job_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    mark_ingestion_job_queuing_complete_query(ingestion_job_id, pool.clone()).await?;
+
+    if let Err(err) =
+        queue_ingestion_job_webhook_if_complete(ingestion_job_id, dataset_id, pool).await
+    {
+        log::error!("Failed to queue ingestion job webhook: {:?}", err);
+    }
+
+    Ok(())
+}
+
+/// Returns the chunk counts of the job grouped by status. `failed_chunks` is left empty, use `get_failed_chunks_for_ingestion_job_query` to fill it in.
+pub async fn get_ingestion_job_status_query(
+    ingestion_job_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<IngestionJobStatus, ServiceError> {
+    use crate::data::schema::ingestion_job_batches::dsl as ingestion_job_batches_columns;
+    use crate::data::schema::ingestion_jobs::dsl as ingestion_jobs_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let ingestion_job: IngestionJob = ingestion_jobs_columns::ingestion_jobs
+        .filter(ingestion_jobs_columns::id.eq(ingestion_job_id))
+        .filter(ingestion_jobs_columns::dataset_id.eq(dataset_id))
+        .select(IngestionJob::as_select())
+        .first(&mut conn)
+        .await
+        .optional()
+        .map_err(|err| {
+            log::error!("Failed to get ingestion job: {:?}", err);
+            ServiceError::BadRequest("Failed to get ingestion job".to_string())
+        })?
+        .ok_or(ServiceError::NotFound(
+            "Ingestion job not found for the specified dataset".to_string(),
+        ))?;
+
+    let counts: Vec<(String, Option<i64>)> = ingestion_job_batches_columns::ingestion_job_batches
+        .filter(ingestion_job_batches_columns::ingestion_job_id.eq(ingestion_job_id))
+        .group_by(ingestion_job_batches_columns::status)
+        .select((
+            ingestion_job_batches_columns::status,
+            diesel::dsl::sum(ingestion_job_batches_columns::chunk_count),
+        ))
+        .load(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Failed to get ingestion job batch counts: {:?}", err);
+            ServiceError::BadRequest("Failed to get ingestion job batch counts".to_string())
+        })?;
+
+    let count_for = |status: IngestionBatchStatus| {
+        counts
+            .iter()
+            .find(|(batch_status, _)| *batch_status == status.to_string())
+            .and_then(|(_, count)| *count)
+            .unwrap_or(0)
+    };
+
+    let queued = count_for(IngestionBatchStatus::Queued);
+    let processing = count_for(IngestionBatchStatus::Processing);
+    let done = count_for(IngestionBatchStatus::Done);
+    let failed = count_for(IngestionBatchStatus::Failed);
+
+    let state = if !ingestion_job.queuing_complete || queued > 0 || processing > 0 {
+        if processing > 0 || done > 0 || failed > 0 {
+            IngestionJobState::Processing
+        } else {
+            IngestionJobState::Queued
+        }
+    } else if failed > 0 {
+        IngestionJobState::CompletedWithFailures
+    } else {
+        IngestionJobState::Completed
+    };
+
+    Ok(IngestionJobStatus {
+        id: ingestion_job.id,
+        dataset_id: ingestion_job.dataset_id,
+        source: ingestion_job.source,
+        state,
+        queued,
+        processing,
+        done,
+        failed,
+        total: queued + processing + done + failed,
+        failed_chunks: vec![],
+        created_at: ingestion_job.created_at,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct IngestionEventChunks {
+    chunk_ids: Vec<uuid::Uuid>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Reads the ingestion worker events of the job from ClickHouse, oldest first.
+pub async fn get_ingestion_job_events_query(
+    ingestion_job_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    clickhouse_client: &clickhouse::Client,
+) -> Result<Vec<WorkerEvent>, ServiceError> {
+    let query = format!(
+        "
+        SELECT
+            id,
+            dataset_id,
+            event_type,
+            event_data,
+            created_at,
+            ingestion_job_id
+        FROM dataset_events
+        WHERE dataset_id = '{dataset_id}' AND ingestion_job_id = '{ingestion_job_id}' AND event_type IN ('chunks_uploaded', 'bulk_chunk_upload_failed')
+        ORDER BY created_at ASC
+        ",
+    );
+
+    let events: Vec<WorkerEventClickhouse> = clickhouse_client
+        .query(&query)
+        .fetch_all()
+        .await
+        .map_err(|err| {
+            log::error!("Failed to get ingestion job events {:?}", err);
+            ServiceError::BadRequest("Failed to get ingestion job events".to_string())
+        })?;
+
+    Ok(events.into_iter().map(WorkerEvent::from).collect())
+}
+
+/// Reads the worker events for the job and returns the chunks whose latest ingestion attempt failed.
+pub async fn get_failed_chunks_for_ingestion_job_query(
+    ingestion_job_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    analytics_backend: &AnalyticsBackend,
+) -> Result<Vec<FailedIngestionChunk>, ServiceError> {
+    if !analytics_backend.is_enabled() {
+        return Ok(vec![]);
+    }
+
+    let events = analytics_backend
+        .get_ingestion_job_events(dataset_id, ingestion_job_id)
+        .await?;
+
+    let mut failed_chunks: HashMap<uuid::Uuid, String> = HashMap::new();
+    for event in events {
+        let Ok(event_chunks) = serde_json::from_str::<IngestionEventChunks>(&event.event_data)
+        else {
+            continue;
+        };
+
+        for chunk_id in event_chunks.chunk_ids {
+            match event_chunks.error.clone() {
+                Some(error) => {
+                    failed_chunks.insert(chunk_id, error);
+                }
+                None => {
+                    failed_chunks.remove(&chunk_id);
+                }
+            }
+        }
+    }
+
+    Ok(failed_chunks
+        .into_iter()
+        .map(|(chunk_id, error)| FailedIngestionChunk { chunk_id, error })
+        .collect())
+}
+
+/// Sends the job's status to its webhook url once all of its chunks have been queued and none are still queued or processing.
+///
+/// The webhook is sent at most once per job. A batch which fails and is later retried successfully by the worker will not trigger a second webhook.
+/// Queues the job's webhook for the webhook worker once queuing is complete and none of its batches are still queued or processing.
+pub async fn queue_ingestion_job_webhook_if_complete(
+    ingestion_job_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::ingestion_job_batches::dsl as ingestion_job_batches_columns;
+    use crate::data::schema::ingestion_jobs::dsl as ingestion_jobs_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let pending_batches = ingestion_job_batches_columns::ingestion_job_batches
+        .filter(ingestion_job_batches_columns::ingestion_job_id.eq(ingestion_job_id))
+        .filter(ingestion_job_batches_columns::status.eq_any(vec![
+            IngestionBatchStatus::Queued.to_string(),
+            IngestionBatchStatus::Processing.to_string(),
+        ]));
+
+    diesel::update(
+        ingestion_jobs_columns::ingestion_jobs
+            .filter(ingestion_jobs_columns::id.eq(ingestion_job_id))
+            .filter(ingestion_jobs_columns::dataset_id.eq(dataset_id))
+            .filter(ingestion_jobs_columns::queuing_complete.eq(true))
+            .filter(ingestion_jobs_columns::webhook_url.is_not_null())
+            .filter(ingestion_jobs_columns::webhook_sent_at.is_null())
+            .filter(ingestion_jobs_columns::webhook_next_attempt_at.is_null())
+            .filter(diesel::dsl::not(diesel::dsl::exists(pending_batches))),
+    )
+    .set(ingestion_jobs_columns::webhook_next_attempt_at.eq(chrono::Utc::now().naive_local()))
+    .execute(&mut conn)
+    .await
+    .map_err(|err| {
+        log::error!("Failed to queue ingestion job webhook: {:?}", err);
+        ServiceError::BadRequest("Failed to queue ingestion job webhook".to_string())
+    })?;
+
+    Ok(())
+}
+
+/// Claims up to `limit` ingestion jobs whose webhook is due by pushing their next attempt back by `lease`, so several webhook workers never send the same webhook at once.
+pub async fn claim_due_ingestion_job_webhooks_query(
+    limit: i64,
+    lease: chrono::Duration,
+    pool: &web::Data<Pool>,
+) -> Result<Vec<IngestionJob>, ServiceError> {
+    use crate::data::schema::ingestion_jobs::dsl as ingestion_jobs_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let now = chrono::Utc::now().naive_local();
+
+    let claimed_ids = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            async move {
+                let due_ids = ingestion_jobs_columns::ingestion_jobs
+                    .filter(ingestion_jobs_columns::webhook_sent_at.is_null())
+                    .filter(ingestion_jobs_columns::webhook_next_attempt_at.le(now))
+                    .order_by(ingestion_jobs_columns::webhook_next_attempt_at.asc())
+                    .limit(limit)
+                    .select(ingestion_jobs_columns::id)
+                    .for_update()
+                    .skip_locked()
+                    .load::<uuid::Uuid>(conn)
+                    .await?;
+
+                diesel::update(
+                    ingestion_jobs_columns::ingestion_jobs
+                        .filter(ingestion_jobs_columns::id.eq_any(&due_ids)),
+                )
+                .set(ingestion_jobs_columns::webhook_next_attempt_at.eq(now + lease))
+                .execute(conn)
+                .await?;
+
+                Ok(due_ids)
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(|err| {
+            log::error!("Error claiming ingestion job webhooks {:?}", err);
+            ServiceError::BadRequest("Error claiming ingestion job webhooks".to_string())
+        })?;
+
+    if claimed_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    ingestion_jobs_columns::ingestion_jobs
+        .filter(ingestion_jobs_columns::id.eq_any(claimed_ids))
+        .select(IngestionJob::as_select())
+        .load::<IngestionJob>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting ingestion jobs".to_string()))
+}
+
+/// Makes one signed attempt at POSTing the job's status to its webhook url. The job is marked sent only once the receiver responds with a 2xx, otherwise the attempt is scheduled again with backoff until `MAX_WEBHOOK_DELIVERY_ATTEMPTS` is reached.
+pub async fn attempt_ingestion_job_webhook_query(
+    ingestion_job: IngestionJob,
+    http_client: &reqwest::Client,
+    pool: &web::Data<Pool>,
+) -> Result<bool, ServiceError> {
+    use crate::data::schema::ingestion_jobs::dsl as ingestion_jobs_columns;
+
+    let Some(webhook_url) = ingestion_job.webhook_url.clone() else {
+        return Ok(false);
+    };
+
+    let error =
+        match send_ingestion_job_webhook(&ingestion_job, &webhook_url, http_client, pool).await {
+            Ok(()) => None,
+            Err(err) => Some(err),
+        };
+
+    let now = chrono::Utc::now().naive_local();
+    let attempts = ingestion_job.webhook_attempts + 1;
+    let next_attempt_at = match &error {
+        None => None,
+        Some(err) if attempts >= MAX_WEBHOOK_DELIVERY_ATTEMPTS => {
+            log::error!(
+                "Giving up on the webhook of ingestion job {} after {} attempts: {}",
+                ingestion_job.id,
+                attempts,
+                err
+            );
+            None
+        }
+        Some(err) => {
+            log::warn!(
+                "Failed to send the webhook of ingestion job {}, retrying: {}",
+                ingestion_job.id,
+                err
+            );
+            Some(now + get_webhook_retry_delay(attempts))
+        }
+    };
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::update(
+        ingestion_jobs_columns::ingestion_jobs
+            .filter(ingestion_jobs_columns::id.eq(ingestion_job.id)),
+    )
+    .set((
+        ingestion_jobs_columns::webhook_sent_at.eq(error.is_none().then_some(now)),
+        ingestion_jobs_columns::webhook_attempts.eq(attempts),
+        ingestion_jobs_columns::webhook_next_attempt_at.eq(next_attempt_at),
+        ingestion_jobs_columns::updated_at.eq(now),
+    ))
+    .execute(&mut conn)
+    .await
+    .map_err(|_| ServiceError::BadRequest("Error updating ingestion job webhook".to_string()))?;
+
+    Ok(error.is_none())
+}
+
+async fn send_ingestion_job_webhook(
+    ingestion_job: &IngestionJob,
+    webhook_url: &str,
+    http_client: &reqwest::Client,
+    pool: &web::Data<Pool>,
+) -> Result<(), String> {
+    validate_webhook_url(webhook_url)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let dataset_config = DatasetConfiguration::from_json(
+        get_dataset_by_id_query(ingestion_job.dataset_id, pool.clone())
+            .await
+            .map_err(|err| err.to_string())?
+            .server_configuration,
+    );
+    let secret = dataset_config
+        .INGESTION_WEBHOOK_SECRET
+        .ok_or("Dataset has no INGESTION_WEBHOOK_SECRET, set its INGESTION_WEBHOOK_URL again to generate one".to_string())?;
+
+    let status =
+        get_ingestion_job_status_query(ingestion_job.id, ingestion_job.dataset_id, pool.clone())
+            .await
+            .map_err(|err| err.to_string())?;
+    let body = serde_json::to_string(&status).map_err(|err| err.to_string())?;
+    let timestamp = chrono::Utc::now().timestamp();
+    let signature =
+        sign_webhook_payload(&secret, timestamp, &body).map_err(|err| err.to_string())?;
+
+    let response = http_client
+        .post(webhook_url)
+        .header("Content-Type", "application/json")
+        .header("X-Trieve-Event", "ingestion_job_completed")
+        .header(
+            WEBHOOK_SIGNATURE_HEADER,
+            format!("t={},v1={}", timestamp, signature),
+        )
+        .body(body)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("Webhook responded with {}", response.status()));
+    }
+
+    Ok(())
+}
+
+/// Checks the ingestion webhook url of a dataset configuration and generates the secret its webhooks are signed with the first time one is set.
+pub async fn prepare_ingestion_webhook_config(
+    dataset_config: &mut DatasetConfiguration,
+) -> Result<(), ServiceError> {
+    let Some(webhook_url) = dataset_config.INGESTION_WEBHOOK_URL.as_ref() else {
+        return Ok(());
+    };
+
+    validate_webhook_url(webhook_url).await?;
+
+    if dataset_config.INGESTION_WEBHOOK_SECRET.is_none() {
+        dataset_config.INGESTION_WEBHOOK_SECRET = Some(generate_webhook_secret());
+    }
+
+    Ok(())
+}
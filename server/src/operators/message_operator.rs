@@ -1,3 +1,5 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 use std::sync::{Arc, Mutex};
 
@@ -5,9 +7,9 @@ use std::sync::{Arc, Mutex};
 use crate::data::models::DummyHallucinationScore;
 use crate::data::models::{
     self, escape_quotes, ChunkMetadata, ChunkMetadataStringTagSet,
-    ChunkMetadataStringTagSetWithHighlightsScore, Dataset, DatasetConfiguration, LLMOptions,
-    MultiQuery, QueryTypes, RagQueryEventClickhouse, RedisPool, ScoreChunk, SearchMethod,
-    SearchModalities,
+    ChunkMetadataStringTagSetWithHighlightsScore, Dataset, DatasetConfiguration,
+    ExperimentAssignment, LLMOptions, MultiQuery, QueryTypes, RagQueryEventClickhouse, RedisPool,
+    ScoreChunk, SearchMethod, SearchModalities, UsageCounts,
 };
 use crate::diesel::prelude::*;
 use crate::get_env;
@@ -39,7 +41,7 @@ use openai_dive::v1::resources::shared::{FileUpload, FileUploadBytes};
 use openai_dive::v1::{
     api::Client,
     resources::{
-        chat::{ChatCompletionParameters, ChatMessage, ChatMessageContent},
+        chat::{ChatCompletionParameters, ChatCompletionResponse, ChatMessage, ChatMessageContent},
         shared::StopToken,
     },
 };
@@ -49,11 +51,20 @@ use simple_server_timing_header::Timer;
 use ureq::json;
 
 use super::clickhouse_operator::{get_latency_from_header, EventQueue};
+use super::fallback_operator::{
+    call_with_fallbacks, create_chat_completion_with_default_base_url,
+    create_chat_completion_with_fallbacks, get_llm_endpoints, EndpointError, EndpointKind,
+    OPENROUTER_BASE_URL,
+};
+use super::guardrail_operator::{
+    guardrail_event_fields, run_guardrails, GuardrailOutcome, GuardrailStage,
+};
 use super::parse_operator::parse_streaming_completetion;
 use super::search_operator::{
     hybrid_search_over_groups, search_chunks_query, search_hybrid_chunks, search_over_groups_query,
     ParsedQuery, ParsedQueryTypes,
 };
+use super::usage_operator::{estimate_prompt_tokens, UsageMeter};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatCompletionDTO {
@@ -299,18 +310,51 @@ pub async fn delete_message_query(
     Ok(())
 }
 
+/// Replaces the stored content of a message, e.g. with the redacted text of a user message flagged by the input guardrail.
+pub async fn update_message_content_query(
+    given_message_id: uuid::Uuid,
+    given_dataset_id: uuid::Uuid,
+    new_content: String,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::messages::dsl::*;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::update(
+        messages
+            .filter(id.eq(given_message_id))
+            .filter(dataset_id.eq(given_dataset_id)),
+    )
+    .set(content.eq(new_content))
+    .execute(&mut conn)
+    .await
+    .map_err(|_| ServiceError::BadRequest("Error updating message".to_string()))?;
+
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn get_rag_chunks_query(
     create_message_req_payload: CreateMessageReqPayload,
     dataset_config: DatasetConfiguration,
     dataset: Dataset,
     user_message_query: String,
+    conversation_history: String,
     chosen_model: String,
-    client: &Client,
     pool: web::Data<Pool>,
     redis_pool: web::Data<RedisPool>,
     event_queue: web::Data<EventQueue>,
-) -> Result<(SearchQueryEventClickhouse, Vec<ScoreChunk>), actix_web::Error> {
+    experiment: Option<&ExperimentAssignment>,
+) -> Result<(SearchQueryEventClickhouse, Vec<ScoreChunk>, Vec<String>), actix_web::Error> {
+    let query_rewrite = create_message_req_payload
+        .query_rewrite
+        .clone()
+        .unwrap_or_default();
+    let mut rewritten_queries: Vec<String> = vec![];
+
     let mut query =
         if let Some(create_message_query) = create_message_req_payload.search_query.clone() {
             create_message_query
@@ -318,6 +362,28 @@ pub async fn get_rag_chunks_query(
             user_message_query
         };
 
+    if create_message_req_payload.search_query.is_none()
+        && query_rewrite.standalone_question.unwrap_or(false)
+        && !conversation_history.is_empty()
+    {
+        match rewrite_standalone_query(
+            &query,
+            &conversation_history,
+            query_rewrite.rewrite_prompt.as_deref(),
+            &dataset_config,
+        )
+        .await
+        {
+            Ok(standalone_query) => {
+                rewritten_queries.push(standalone_query.clone());
+                query = standalone_query;
+            }
+            Err(err) => {
+                log::error!("Error rewriting query into a standalone question {:?}", err);
+            }
+        }
+    }
+
     let use_message_to_query_prompt = dataset_config.USE_MESSAGE_TO_QUERY_PROMPT;
     if create_message_req_payload.search_query.is_none() && use_message_to_query_prompt {
         let message_to_query_prompt = dataset_config.MESSAGE_TO_QUERY_PROMPT.clone();
@@ -377,7 +443,9 @@ pub async fn get_rag_chunks_query(
         };
 
         let search_query_from_message_to_query_prompt =
-            match client.chat().create(gen_inference_parameters).await {
+            match create_chat_completion_with_fallbacks(gen_inference_parameters, &dataset_config)
+                .await
+            {
                 Ok(query) => query,
                 Err(err) => {
                     log::error!(
@@ -412,39 +480,126 @@ pub async fn get_rag_chunks_query(
         };
     }
 
+    let mut queries = vec![query.clone()];
+    let multi_query_count = query_rewrite
+        .multi_query_count
+        .unwrap_or(0)
+        .min(MAX_MULTI_QUERY_COUNT);
+    if create_message_req_payload.search_query.is_none() && multi_query_count > 0 {
+        match expand_query(&query, multi_query_count, &dataset_config).await {
+            Ok(expanded_queries) => {
+                rewritten_queries.extend(expanded_queries.iter().cloned());
+                queries.extend(expanded_queries);
+            }
+            Err(err) => {
+                log::error!("Error expanding query for multi-query retrieval {:?}", err);
+            }
+        }
+    }
+
+    let mut search_results = futures::future::try_join_all(queries.into_iter().map(|query| {
+        search_for_rag_chunks(
+            query,
+            &create_message_req_payload,
+            &dataset_config,
+            dataset.clone(),
+            pool.clone(),
+            redis_pool.clone(),
+            experiment,
+        )
+    }))
+    .await?;
+
+    // The expanded queries are one retrieval, so only a single search event is recorded for them
+    let (search_event, score_chunks) = if search_results.len() == 1 {
+        search_results.remove(0)
+    } else {
+        let page_size = create_message_req_payload.page_size.unwrap_or(
+            dataset_config
+                .N_RETRIEVALS_TO_INCLUDE
+                .try_into()
+                .unwrap_or(8),
+        ) as usize;
+        let mut search_event = search_results[0].0.clone();
+        search_event.latency = search_results
+            .iter()
+            .map(|(search_event, _)| search_event.latency)
+            .fold(0.0, f32::max);
+
+        let fused_chunks = reciprocal_rank_fusion(
+            search_results
+                .into_iter()
+                .map(|(_, score_chunks)| score_chunks)
+                .collect(),
+            page_size,
+        );
+        search_event.top_score = fused_chunks
+            .first()
+            .map(|score_chunk| score_chunk.score)
+            .unwrap_or(0.0);
+        search_event.results = fused_chunks
+            .iter()
+            .map(|score_chunk| {
+                let mut json = serde_json::to_value(score_chunk).unwrap_or_default();
+                escape_quotes(&mut json);
+                json.to_string()
+            })
+            .collect();
+
+        (search_event, fused_chunks)
+    };
+
+    if !dataset_config.DISABLE_ANALYTICS {
+        event_queue
+            .send(ClickHouseEvent::SearchQueryEvent(search_event.clone()))
+            .await;
+    }
+
+    Ok((search_event, score_chunks, rewritten_queries))
+}
+
+/// Runs a single retrieval query for RAG with the search parameters of the message request and builds its search event.
+async fn search_for_rag_chunks(
+    query: String,
+    create_message_req_payload: &CreateMessageReqPayload,
+    dataset_config: &DatasetConfiguration,
+    dataset: Dataset,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+    experiment: Option<&ExperimentAssignment>,
+) -> Result<(SearchQueryEventClickhouse, Vec<ScoreChunk>), actix_web::Error> {
+    let (experiment_id, experiment_variant) = ExperimentAssignment::event_fields(experiment);
     let n_retrievals_to_include = dataset_config.N_RETRIEVALS_TO_INCLUDE;
     let search_type = create_message_req_payload
         .search_type
+        .clone()
         .unwrap_or(SearchMethod::Hybrid);
 
-    let query_type =
-        if let Some(ref image_urls) = create_message_req_payload.image_urls.and_then(|x| {
-            if x.is_empty() {
-                None
-            } else {
-                Some(x)
-            }
-        }) {
-            let image_queries = image_urls.iter().map(|url| MultiQuery {
-                query: SearchModalities::Image {
-                    image_url: url.clone(),
-                    llm_prompt: None,
-                },
-                weight: 0.5 / image_urls.len() as f32,
-            });
-
-            QueryTypes::Multi(
-                vec![MultiQuery {
-                    query: SearchModalities::Text(query.clone()),
-                    weight: 0.5,
-                }]
-                .into_iter()
-                .chain(image_queries)
-                .collect(),
-            )
-        } else {
-            QueryTypes::Single(SearchModalities::Text(query.clone()))
-        };
+    let query_type = if let Some(ref image_urls) = create_message_req_payload
+        .image_urls
+        .clone()
+        .and_then(|x| if x.is_empty() { None } else { Some(x) })
+    {
+        let image_queries = image_urls.iter().map(|url| MultiQuery {
+            query: SearchModalities::Image {
+                image_url: url.clone(),
+                llm_prompt: None,
+            },
+            weight: 0.5 / image_urls.len() as f32,
+        });
+
+        QueryTypes::Multi(
+            vec![MultiQuery {
+                query: SearchModalities::Text(query.clone()),
+                weight: 0.5,
+            }]
+            .into_iter()
+            .chain(image_queries)
+            .collect(),
+        )
+    } else {
+        QueryTypes::Single(SearchModalities::Text(query.clone()))
+    };
 
     if create_message_req_payload
         .use_group_search
@@ -459,9 +614,9 @@ pub async fn get_rag_chunks_query(
                     .page_size
                     .unwrap_or(n_retrievals_to_include.try_into().unwrap_or(8)),
             ),
-            sort_options: create_message_req_payload.sort_options,
-            highlight_options: create_message_req_payload.highlight_options,
-            filters: create_message_req_payload.filters,
+            sort_options: create_message_req_payload.sort_options.clone(),
+            highlight_options: create_message_req_payload.highlight_options.clone(),
+            filters: create_message_req_payload.filters.clone(),
             group_size: Some(1),
             ..Default::default()
         };
@@ -482,7 +637,7 @@ pub async fn get_rag_chunks_query(
                     pool.clone(),
                     redis_pool,
                     dataset.clone(),
-                    &dataset_config,
+                    dataset_config,
                     &mut search_timer,
                 )
                 .await?
@@ -494,7 +649,7 @@ pub async fn get_rag_chunks_query(
                     pool.clone(),
                     redis_pool,
                     dataset.clone(),
-                    &dataset_config,
+                    dataset_config,
                     &mut search_timer,
                 )
                 .await?
@@ -529,14 +684,9 @@ pub async fn get_rag_chunks_query(
                 .user_id
                 .clone()
                 .unwrap_or_default(),
+            experiment_id: experiment_id.clone(),
+            experiment_variant: experiment_variant.clone(),
         };
-        if !dataset_config.DISABLE_ANALYTICS {
-            event_queue
-                .send(ClickHouseEvent::SearchQueryEvent(
-                    clickhouse_search_event.clone(),
-                ))
-                .await;
-        }
         Ok((
             clickhouse_search_event,
             result_groups
@@ -556,14 +706,14 @@ pub async fn get_rag_chunks_query(
             search_type: search_type.clone(),
             query: query_type.clone(),
             score_threshold: create_message_req_payload.score_threshold,
-            sort_options: create_message_req_payload.sort_options,
+            sort_options: create_message_req_payload.sort_options.clone(),
             page_size: Some(
                 create_message_req_payload
                     .page_size
                     .unwrap_or(n_retrievals_to_include.try_into().unwrap_or(8)),
             ),
-            highlight_options: create_message_req_payload.highlight_options,
-            filters: create_message_req_payload.filters,
+            highlight_options: create_message_req_payload.highlight_options.clone(),
+            filters: create_message_req_payload.filters.clone(),
             ..Default::default()
         };
         let parsed_query = ParsedQuery {
@@ -581,7 +731,7 @@ pub async fn get_rag_chunks_query(
                     pool.clone(),
                     redis_pool,
                     dataset.clone(),
-                    &dataset_config,
+                    dataset_config,
                     &mut search_timer,
                 )
                 .await?
@@ -593,7 +743,7 @@ pub async fn get_rag_chunks_query(
                     pool.clone(),
                     redis_pool,
                     dataset.clone(),
-                    &dataset_config,
+                    dataset_config,
                     &mut search_timer,
                 )
                 .await?
@@ -628,14 +778,9 @@ pub async fn get_rag_chunks_query(
                 .user_id
                 .clone()
                 .unwrap_or_default(),
+            experiment_id: experiment_id.clone(),
+            experiment_variant: experiment_variant.clone(),
         };
-        if !dataset_config.DISABLE_ANALYTICS {
-            event_queue
-                .send(ClickHouseEvent::SearchQueryEvent(
-                    clickhouse_search_event.clone(),
-                ))
-                .await;
-        }
         Ok((
             clickhouse_search_event,
             result_chunks
@@ -647,6 +792,125 @@ pub async fn get_rag_chunks_query(
     }
 }
 
+const MAX_MULTI_QUERY_COUNT: u32 = 5;
+
+const RRF_K: f32 = 60.0;
+
+/// Merges the results of several retrieval queries with reciprocal rank fusion. Each chunk keeps the best score it received from any single query.
+fn reciprocal_rank_fusion(result_lists: Vec<Vec<ScoreChunk>>, limit: usize) -> Vec<ScoreChunk> {
+    let mut fused: HashMap<uuid::Uuid, (f32, ScoreChunk)> = HashMap::new();
+
+    for score_chunks in result_lists {
+        for (rank, score_chunk) in score_chunks.into_iter().enumerate() {
+            let rrf_score = 1.0 / (RRF_K + rank as f32 + 1.0);
+            match fused.entry(score_chunk.chunk.id()) {
+                Entry::Occupied(mut entry) => {
+                    let (fused_score, best_chunk) = entry.get_mut();
+                    *fused_score += rrf_score;
+                    if score_chunk.score > best_chunk.score {
+                        *best_chunk = score_chunk;
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert((rrf_score, score_chunk));
+                }
+            }
+        }
+    }
+
+    let mut fused = fused.into_values().collect::<Vec<(f32, ScoreChunk)>>();
+    fused.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    fused
+        .into_iter()
+        .take(limit)
+        .map(|(_, score_chunk)| score_chunk)
+        .collect()
+}
+
+fn get_completion_text(completion: &ChatCompletionResponse) -> Option<String> {
+    match completion.choices.first().map(|choice| &choice.message) {
+        Some(ChatMessage::Assistant {
+            content: Some(ChatMessageContent::Text(content)),
+            ..
+        }) => Some(content.trim().to_string()),
+        _ => None,
+    }
+}
+
+/// Rewrites the latest user message into a question which can be understood without the rest of the conversation.
+async fn rewrite_standalone_query(
+    query: &str,
+    conversation_history: &str,
+    rewrite_prompt: Option<&str>,
+    dataset_config: &DatasetConfiguration,
+) -> Result<String, ServiceError> {
+    let instructions = rewrite_prompt.unwrap_or(
+        "Given the conversation below and a follow-up message from the user, rewrite the follow-up message into a standalone question which can be understood without the conversation. Keep any names, products, or other specifics the follow-up refers to. Your only response should be the rewritten question.",
+    );
+
+    let parameters = ChatCompletionParameters {
+        model: dataset_config.LLM_DEFAULT_MODEL.clone(),
+        messages: vec![ChatMessage::User {
+            content: ChatMessageContent::Text(format!(
+                "{}\n\nConversation:\n{}\n\nFollow-up message: {}",
+                instructions, conversation_history, query
+            )),
+            name: None,
+        }],
+        stream: Some(false),
+        temperature: Some(0.0),
+        ..Default::default()
+    };
+
+    let completion = create_chat_completion_with_fallbacks(parameters, dataset_config).await?;
+
+    match get_completion_text(&completion) {
+        Some(standalone_query) if !standalone_query.is_empty() => Ok(standalone_query),
+        _ => Err(ServiceError::InternalServerError(
+            "LLM returned an empty standalone question".to_string(),
+        )),
+    }
+}
+
+/// Generates alternative phrasings of a retrieval query for multi-query search.
+async fn expand_query(
+    query: &str,
+    count: u32,
+    dataset_config: &DatasetConfiguration,
+) -> Result<Vec<String>, ServiceError> {
+    let parameters = ChatCompletionParameters {
+        model: dataset_config.LLM_DEFAULT_MODEL.clone(),
+        messages: vec![ChatMessage::User {
+            content: ChatMessageContent::Text(format!(
+                "Write {} different search queries which could be used to find documents answering the question below. Vary the wording and focus of each query. Your only response should be the queries separated by new lines. Do not number them or add any other context or information.\n\nQuestion: {}",
+                count, query
+            )),
+            name: None,
+        }],
+        stream: Some(false),
+        ..Default::default()
+    };
+
+    let completion = create_chat_completion_with_fallbacks(parameters, dataset_config).await?;
+
+    let expanded_queries = get_completion_text(&completion)
+        .unwrap_or_default()
+        .split('\n')
+        .map(|expanded_query| {
+            expanded_query
+                .trim()
+                .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == '-')
+                .trim()
+                .to_string()
+        })
+        .filter(|expanded_query| !expanded_query.is_empty() && expanded_query != query)
+        .take(count as usize)
+        .collect();
+
+    Ok(expanded_queries)
+}
+
 pub fn clean_markdown(markdown_text: &str) -> String {
     let mut text = markdown_text.to_string();
 
@@ -694,7 +958,7 @@ pub fn clean_markdown(markdown_text: &str) -> String {
 #[allow(clippy::too_many_arguments)]
 
 pub async fn stream_response(
-    messages: Vec<models::Message>,
+    mut messages: Vec<models::Message>,
     topic_id: uuid::Uuid,
     dataset: Dataset,
     pool: web::Data<Pool>,
@@ -702,10 +966,86 @@ pub async fn stream_response(
     redis_pool: web::Data<RedisPool>,
     dataset_config: DatasetConfiguration,
     create_message_req_payload: CreateMessageReqPayload,
+    experiment: Option<ExperimentAssignment>,
     #[cfg(feature = "hallucination-detection")] hallucination_detector: web::Data<
         HallucinationDetector,
     >,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let (experiment_id, experiment_variant) =
+        ExperimentAssignment::event_fields(experiment.as_ref());
+    let last_message_content = messages
+        .last()
+        .map(|message| message.content.clone())
+        .unwrap_or_default();
+    let input_guardrail = run_guardrails(
+        &last_message_content,
+        GuardrailStage::Input,
+        &dataset_config,
+    )
+    .await;
+
+    // The user message is stored before the guardrail runs, so replace it with the redacted text
+    if input_guardrail.is_modified() && input_guardrail.redacted_text != last_message_content {
+        if let Some(last_message) = messages.last() {
+            update_message_content_query(
+                last_message.id,
+                dataset.id,
+                input_guardrail.redacted_text.clone(),
+                &pool,
+            )
+            .await?;
+        }
+    }
+
+    if input_guardrail.is_refused() {
+        let query_id = uuid::Uuid::new_v4();
+
+        if !dataset_config.DISABLE_ANALYTICS {
+            let (guardrail_actions, guardrail_violations) =
+                guardrail_event_fields(&[&input_guardrail]);
+
+            event_queue
+                .send(ClickHouseEvent::RagQueryEvent(RagQueryEventClickhouse {
+                    id: query_id,
+                    created_at: time::OffsetDateTime::now_utc(),
+                    dataset_id: dataset.id,
+                    search_id: uuid::Uuid::nil(),
+                    top_score: 0.0,
+                    results: vec![],
+                    json_results: vec![],
+                    user_message: input_guardrail.redacted_text.clone(),
+                    query_rating: String::new(),
+                    rag_type: "all_chunks".to_string(),
+                    llm_response: input_guardrail.text.clone(),
+                    user_id: create_message_req_payload
+                        .user_id
+                        .clone()
+                        .unwrap_or_default(),
+                    hallucination_score: 0.0,
+                    detected_hallucinations: vec![],
+                    guardrail_actions,
+                    guardrail_violations,
+                    rewritten_queries: vec![],
+                    experiment_id: experiment_id.clone(),
+                    experiment_variant: experiment_variant.clone(),
+                }))
+                .await;
+        }
+
+        let response_stream = stream::iter(vec![Ok::<actix_web::web::Bytes, actix_web::Error>(
+            Bytes::from(format!("[]||{}", input_guardrail.text)),
+        )]);
+        return Ok(HttpResponse::Ok()
+            .insert_header(("TR-QueryID", query_id.to_string()))
+            .streaming(response_stream));
+    }
+
+    if input_guardrail.is_modified() {
+        if let Some(last_message) = messages.last_mut() {
+            last_message.content.clone_from(&input_guardrail.text);
+        }
+    }
+
     let user_message_query = match create_message_req_payload.concat_user_messages_query {
         Some(true) => messages
             .iter()
@@ -729,28 +1069,12 @@ pub async fn stream_response(
         .map(|message| ChatMessage::from(message.clone()))
         .collect();
 
-    let base_url = dataset_config.LLM_BASE_URL.clone();
-
-    let llm_api_key = if !dataset_config.LLM_API_KEY.is_empty() {
-        dataset_config.LLM_API_KEY.clone()
-    } else if base_url.contains("openai.com") {
-        get_env!("OPENAI_API_KEY", "OPENAI_API_KEY for openai should be set").into()
-    } else {
-        get_env!(
-            "LLM_API_KEY",
-            "LLM_API_KEY for openrouter or self-hosted should be set"
-        )
-        .into()
-    };
-
-    let client = Client {
-        headers: None,
-        project: None,
-        api_key: llm_api_key,
-        http_client: reqwest::Client::new(),
-        base_url,
-        organization: None,
-    };
+    let conversation_history = messages[..messages.len().saturating_sub(1)]
+        .iter()
+        .filter(|message| message.role == "user" || message.role == "assistant")
+        .map(|message| format!("{}: {}", message.role, message.content))
+        .collect::<Vec<String>>()
+        .join("\n\n");
 
     let next_message_order = move || {
         let messages_len = messages.len();
@@ -763,16 +1087,17 @@ pub async fn stream_response(
     let rag_prompt = dataset_config.RAG_PROMPT.clone();
     let chosen_model = dataset_config.LLM_DEFAULT_MODEL.clone();
 
-    let (search_event, score_chunks) = get_rag_chunks_query(
+    let (search_event, score_chunks, rewritten_queries) = get_rag_chunks_query(
         create_message_req_payload.clone(),
         dataset_config.clone(),
         dataset.clone(),
         user_message_query.clone(),
+        conversation_history,
         chosen_model.clone(),
-        &client,
         pool.clone(),
         redis_pool.clone(),
         event_queue.clone(),
+        experiment.as_ref(),
     )
     .await?;
 
@@ -974,16 +1299,7 @@ pub async fn stream_response(
         .is_some_and(|llm_options| !llm_options.stream_response.unwrap_or(true))
     {
         let assistant_completion =
-            client
-                .chat()
-                .create(parameters.clone())
-                .await
-                .map_err(|err| {
-                    ServiceError::BadRequest(format!(
-                        "Bad response from LLM server provider: {}",
-                        err
-                    ))
-                })?;
+            create_chat_completion_with_fallbacks(parameters.clone(), &dataset_config).await?;
 
         let completion_content = match &assistant_completion
             .choices
@@ -1025,6 +1341,15 @@ pub async fn stream_response(
 
         let (response_text, filtered_chunks) = completion_content;
 
+        let output_guardrail =
+            run_guardrails(&response_text, GuardrailStage::Output, &dataset_config).await;
+        let response_text = output_guardrail.text.clone();
+        let filtered_chunks = if output_guardrail.is_refused() {
+            vec![]
+        } else {
+            filtered_chunks
+        };
+
         let filtered_chunks_stringified = serde_json::to_string(&filtered_chunks)
             .expect("Failed to serialize filtered citation chunks");
 
@@ -1048,7 +1373,22 @@ pub async fn stream_response(
             )
         };
 
-        let new_message = models::Message::from_details(
+        let follow_up_questions = if create_message_req_payload
+            .suggest_follow_up_questions
+            .unwrap_or(false)
+            && !output_guardrail.is_refused()
+        {
+            get_follow_up_questions(&user_message, &response_text, &rag_content, &dataset_config)
+                .await
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to generate follow-up questions: {:?}", err);
+                    vec![]
+                })
+        } else {
+            vec![]
+        };
+
+        let mut new_message = models::Message::from_details(
             final_response.clone(),
             topic_id,
             next_message_order()
@@ -1100,6 +1440,9 @@ pub async fn stream_response(
             })
             .collect::<Vec<String>>();
 
+        let (guardrail_actions, guardrail_violations) =
+            guardrail_event_fields(&[&input_guardrail, &output_guardrail]);
+
         let clickhouse_rag_event = RagQueryEventClickhouse {
             id: query_id,
             created_at: time::OffsetDateTime::now_utc(),
@@ -1118,6 +1461,11 @@ pub async fn stream_response(
                 .unwrap_or_default(),
             hallucination_score: score.total_score,
             detected_hallucinations: score.detected_hallucinations,
+            guardrail_actions,
+            guardrail_violations,
+            rewritten_queries: rewritten_queries.clone(),
+            experiment_id: experiment_id.clone(),
+            experiment_variant: experiment_variant.clone(),
         };
 
         if !dataset_config.DISABLE_ANALYTICS {
@@ -1125,6 +1473,11 @@ pub async fn stream_response(
                 .send(ClickHouseEvent::RagQueryEvent(clickhouse_rag_event.clone()))
                 .await;
         }
+        if !follow_up_questions.is_empty() {
+            new_message.follow_up_questions =
+                Some(follow_up_questions.into_iter().map(Some).collect());
+        }
+
         create_messages_query(vec![new_message], &pool).await?;
         if create_message_req_payload.audio_input.is_some() {
             return Ok(HttpResponse::Ok()
@@ -1144,7 +1497,33 @@ pub async fn stream_response(
     }
 
     let (s, r) = unbounded::<String>();
-    let stream = client.chat().create_stream(parameters).await.unwrap();
+    let usage_meter = UsageMeter::current();
+    let estimated_prompt_tokens = estimate_prompt_tokens(&parameters.messages);
+    let llm_endpoints = get_llm_endpoints(&dataset_config);
+    let stream = call_with_fallbacks(EndpointKind::Llm, &llm_endpoints, |endpoint| {
+        let mut parameters = parameters.clone();
+        async move {
+            if let Some(model_name) = endpoint.model_name.clone() {
+                parameters.model = model_name;
+            }
+
+            endpoint
+                .llm_client()
+                .chat()
+                .create_stream(parameters)
+                .await
+                .map_err(|err| {
+                    EndpointError::from_llm(
+                        &err,
+                        ServiceError::BadRequest(format!(
+                            "Bad response from LLM server provider: {}",
+                            err
+                        )),
+                    )
+                })
+        }
+    })
+    .await?;
 
     let completion_first = create_message_req_payload
         .llm_options
@@ -1155,28 +1534,62 @@ pub async fn stream_response(
 
     let query_id_arb = query_id;
 
+    let suggest_follow_up_questions = create_message_req_payload
+        .suggest_follow_up_questions
+        .unwrap_or(false);
+    let stream_follow_up_questions = suggest_follow_up_questions
+        && create_message_req_payload
+            .stream_follow_up_questions
+            .unwrap_or(false);
+    let streamed_follow_up_questions: Arc<Mutex<Option<Vec<String>>>> = Arc::new(Mutex::new(None));
+    let streamed_follow_up_questions_arb = streamed_follow_up_questions.clone();
+    // The follow-up stage holds a sender so the message is only stored once its streamed questions are known
+    let follow_up_sender = s.clone();
+    let guardrails_enabled = dataset_config.GUARDRAILS.enabled;
+    let buffered_output_guardrail: Arc<Mutex<Option<GuardrailOutcome>>> =
+        Arc::new(Mutex::new(None));
+    let buffered_output_guardrail_arb = buffered_output_guardrail.clone();
+    let guardrail_dataset_config = dataset_config.clone();
+    let follow_up_user_message = user_message.clone();
+    let follow_up_rag_content = rag_content.clone();
+    let follow_up_stage_user_message = user_message.clone();
+    let follow_up_stage_rag_content = rag_content.clone();
+    let follow_up_stage_dataset_config = dataset_config.clone();
+    let buffered_output_guardrail_stage = buffered_output_guardrail.clone();
+
     Arbiter::new().spawn(async move {
         let chunk_v: Vec<String> = r.iter().collect();
         let completion = chunk_v.join("");
 
-        let mut split_completion = completion.split("||");
+        if let Some(usage_meter) = usage_meter {
+            usage_meter
+                .record(
+                    UsageCounts {
+                        prompt_tokens: estimated_prompt_tokens,
+                        completion_tokens: chunk_v.len() as i64,
+                        ..Default::default()
+                    },
+                    &pool,
+                )
+                .await;
+        }
 
+        let (response, chunk_json) = split_streamed_completion(&completion, completion_first);
+        let response = response.to_string();
         #[allow(unused_variables)]
-        let (response, chunks) = if completion_first {
-            let response = split_completion.next().unwrap_or_default().to_string();
-            let chunk_data: Vec<ChunkMetadataStringTagSet> =
-                serde_json::from_str(split_completion.next().unwrap_or_default())
-                    .unwrap_or_default();
+        let chunks: Vec<ChunkMetadataStringTagSet> =
+            serde_json::from_str(chunk_json).unwrap_or_default();
 
-            (response, chunk_data)
+        let buffered_output_guardrail = buffered_output_guardrail_arb.lock().unwrap().take();
+        let output_guardrail = match buffered_output_guardrail {
+            Some(output_guardrail) => output_guardrail,
+            None => run_guardrails(&response, GuardrailStage::Output, &dataset_config).await,
+        };
+        let completion = apply_output_guardrail(completion, &output_guardrail, completion_first);
+        let chunks = if output_guardrail.is_refused() {
+            vec![]
         } else {
-            let chunk_data: Vec<ChunkMetadataStringTagSet> =
-                serde_json::from_str(split_completion.next().unwrap_or_default())
-                    .unwrap_or_default();
-
-            let response = split_completion.next().unwrap_or_default().to_string();
-
-            (response, chunk_data)
+            chunks
         };
 
         let chunk_data: Vec<String> = chunks
@@ -1188,7 +1601,7 @@ pub async fn stream_response(
             })
             .collect();
 
-        let new_message = models::Message::from_details(
+        let mut new_message = models::Message::from_details(
             completion.clone(),
             topic_id,
             next_message_order().try_into().unwrap(),
@@ -1199,6 +1612,31 @@ pub async fn stream_response(
             query_id_arb,
         );
 
+        if stream_follow_up_questions && !output_guardrail.is_refused() {
+            new_message.follow_up_questions = streamed_follow_up_questions_arb
+                .lock()
+                .unwrap()
+                .take()
+                .filter(|questions| !questions.is_empty())
+                .map(|questions| questions.into_iter().map(Some).collect());
+        } else if suggest_follow_up_questions && !output_guardrail.is_refused() {
+            let questions = get_follow_up_questions(
+                &follow_up_user_message,
+                completion_response(&completion, completion_first),
+                &follow_up_rag_content,
+                &dataset_config,
+            )
+            .await
+            .unwrap_or_else(|err| {
+                log::error!("Failed to generate follow-up questions: {:?}", err);
+                vec![]
+            });
+
+            if !questions.is_empty() {
+                new_message.follow_up_questions = Some(questions.into_iter().map(Some).collect());
+            }
+        }
+
         if !dataset_config.DISABLE_ANALYTICS {
             #[cfg(feature = "hallucination-detection")]
             let score = {
@@ -1225,6 +1663,9 @@ pub async fn stream_response(
                 detected_hallucinations: vec![],
             };
 
+            let (guardrail_actions, guardrail_violations) =
+                guardrail_event_fields(&[&input_guardrail, &output_guardrail]);
+
             let clickhouse_rag_event = RagQueryEventClickhouse {
                 id: query_id_arb,
                 created_at: time::OffsetDateTime::now_utc(),
@@ -1243,6 +1684,11 @@ pub async fn stream_response(
                     .unwrap_or_default(),
                 hallucination_score: score.total_score,
                 detected_hallucinations: score.detected_hallucinations,
+                guardrail_actions,
+                guardrail_violations,
+                rewritten_queries,
+                experiment_id: experiment_id.clone(),
+                experiment_variant: experiment_variant.clone(),
             };
 
             event_queue
@@ -1369,6 +1815,89 @@ pub async fn stream_response(
         .into())
     });
 
+    // The output guardrail needs the whole completion, so when guardrails are enabled the stream is buffered and sent as one checked message
+    let completion_stream = if guardrails_enabled {
+        let dataset_config = guardrail_dataset_config;
+
+        stream::once(async move {
+            let mut completion_stream = Box::pin(completion_stream);
+            let mut completion = String::new();
+            while let Some(chunk) = completion_stream.next().await {
+                completion.push_str(&String::from_utf8_lossy(&chunk?));
+            }
+
+            let response = completion_response(&completion, completion_first).to_string();
+            let output_guardrail =
+                run_guardrails(&response, GuardrailStage::Output, &dataset_config).await;
+            let completion =
+                apply_output_guardrail(completion, &output_guardrail, completion_first);
+
+            *buffered_output_guardrail.lock().unwrap() = Some(output_guardrail);
+
+            Ok::<Bytes, actix_web::Error>(Bytes::from(completion))
+        })
+        .boxed_local()
+    } else {
+        completion_stream.boxed_local()
+    };
+
+    // Follow-up questions are only appended to the stream when asked for, as they change the `chunks||completion` shape of the body
+    let completion_stream = if stream_follow_up_questions {
+        let streamed_completion = Arc::new(Mutex::new(String::new()));
+        let follow_up_completion = streamed_completion.clone();
+
+        let follow_up_stream = stream::once(async move {
+            let _follow_up_sender = follow_up_sender;
+            let refused = buffered_output_guardrail_stage
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_some_and(|output_guardrail| output_guardrail.is_refused());
+            if refused {
+                return None;
+            }
+
+            let completion = follow_up_completion.lock().unwrap().clone();
+            let questions = get_follow_up_questions(
+                &follow_up_stage_user_message,
+                completion_response(&completion, completion_first),
+                &follow_up_stage_rag_content,
+                &follow_up_stage_dataset_config,
+            )
+            .await
+            .unwrap_or_else(|err| {
+                log::error!("Failed to generate follow-up questions: {:?}", err);
+                vec![]
+            });
+
+            *streamed_follow_up_questions.lock().unwrap() = Some(questions.clone());
+
+            if questions.is_empty() {
+                None
+            } else {
+                Some(Ok::<Bytes, actix_web::Error>(Bytes::from(
+                    follow_up_questions_event(&questions),
+                )))
+            }
+        })
+        .filter_map(|event| async move { event });
+
+        completion_stream
+            .inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    streamed_completion
+                        .lock()
+                        .unwrap()
+                        .push_str(&String::from_utf8_lossy(chunk));
+                }
+            })
+            .chain(follow_up_stream)
+            .boxed_local()
+    } else {
+        drop(follow_up_sender);
+        completion_stream
+    };
+
     if create_message_req_payload.audio_input.is_some() {
         return Ok(HttpResponse::Ok()
             .insert_header((
@@ -1420,40 +1949,14 @@ pub async fn get_topic_string(
     };
 
     let dataset_config = DatasetConfiguration::from_json(dataset.server_configuration.clone());
-    let base_url = dataset_config.LLM_BASE_URL;
-
-    let base_url = if base_url.is_empty() {
-        "https://openrouter.ai/api/v1".into()
-    } else {
-        base_url
-    };
-
-    let llm_api_key = if !dataset_config.LLM_API_KEY.is_empty() {
-        dataset_config.LLM_API_KEY.clone()
-    } else if base_url.contains("openai.com") {
-        get_env!("OPENAI_API_KEY", "OPENAI_API_KEY for openai should be set").into()
-    } else {
-        get_env!(
-            "LLM_API_KEY",
-            "LLM_API_KEY for openrouter or self-hosted should be set"
-        )
-        .into()
-    };
-
-    let client = Client {
-        headers: None,
-        api_key: llm_api_key,
-        project: None,
-        http_client: reqwest::Client::new(),
-        base_url,
-        organization: None,
-    };
 
-    let query = client
-        .chat()
-        .create(parameters)
-        .await
-        .map_err(|_| ServiceError::BadRequest("No LLM Completion for topic".to_string()))?;
+    let query = create_chat_completion_with_default_base_url(
+        parameters,
+        &dataset_config,
+        OPENROUTER_BASE_URL,
+    )
+    .await
+    .map_err(|_| ServiceError::BadRequest("No LLM Completion for topic".to_string()))?;
 
     let topic = match &query
         .choices
@@ -1481,41 +1984,109 @@ pub async fn get_topic_string(
     Ok(topic)
 }
 
-pub async fn get_text_from_image(
-    image_url: String,
-    prompt: Option<String>,
-    dataset: &Dataset,
-) -> Result<String, ServiceError> {
-    let dataset_config = DatasetConfiguration::from_json(dataset.server_configuration.clone());
-    let base_url = dataset_config.LLM_BASE_URL;
+/// Splits a streamed `chunks||completion` or `completion||chunks` response into its completion and chunk JSON. The chunk JSON has `||` stripped but the completion may contain it, so everything on the completion's side of the separator belongs to the completion.
+fn split_streamed_completion(completion: &str, completion_first: bool) -> (&str, &str) {
+    if completion_first {
+        completion.rsplit_once("||").unwrap_or((completion, ""))
+    } else {
+        completion
+            .split_once("||")
+            .map(|(chunks, response)| (response, chunks))
+            .unwrap_or(("", completion))
+    }
+}
 
-    let base_url = if base_url.is_empty() {
-        "https://openrouter.ai/api/v1".into()
+/// The completion text of a streamed `chunks||completion` or `completion||chunks` response.
+fn completion_response(completion: &str, completion_first: bool) -> &str {
+    split_streamed_completion(completion, completion_first).0
+}
+
+/// Applies the output guardrail to a streamed response. Refusals drop the citations along with the completion.
+fn apply_output_guardrail(
+    completion: String,
+    output_guardrail: &GuardrailOutcome,
+    completion_first: bool,
+) -> String {
+    let (response, chunks) = split_streamed_completion(&completion, completion_first);
+
+    if output_guardrail.is_refused() {
+        if completion_first {
+            format!("{}||[]", output_guardrail.text)
+        } else {
+            format!("[]||{}", output_guardrail.text)
+        }
+    } else if output_guardrail.is_modified() && !response.is_empty() {
+        if completion_first {
+            format!("{}||{}", output_guardrail.text, chunks)
+        } else {
+            format!("{}||{}", chunks, output_guardrail.text)
+        }
     } else {
-        base_url
+        completion
+    }
+}
+
+fn follow_up_questions_event(questions: &[String]) -> String {
+    format!(
+        "||{}",
+        json!({ "follow_up_questions": questions })
+            .to_string()
+            .replace("||", "")
+    )
+}
+
+pub async fn get_follow_up_questions(
+    user_message: &str,
+    answer: &str,
+    rag_content: &str,
+    dataset_config: &DatasetConfiguration,
+) -> Result<Vec<String>, ServiceError> {
+    let prompt = ChatMessage::User {
+        content: ChatMessageContent::Text(format!(
+            "Here are the documents which were retrieved to answer a user's question:\n\n{}\n\nThe user asked: {}\n\nThe assistant answered: {}\n\nWrite between 3 and 5 short follow-up questions the user is likely to ask next. Each question must be answerable from the documents above. Your only response should be the questions separated by new lines. Do not number them or add any other context or information.",
+            rag_content, user_message, answer
+        )),
+        name: None,
     };
 
-    let llm_api_key = if !dataset_config.LLM_API_KEY.is_empty() {
-        dataset_config.LLM_API_KEY.clone()
-    } else if base_url.contains("openai.com") {
-        get_env!("OPENAI_API_KEY", "OPENAI_API_KEY for openai should be set").into()
-    } else {
-        get_env!(
-            "LLM_API_KEY",
-            "LLM_API_KEY for openrouter or self-hosted should be set"
-        )
-        .into()
+    let parameters = ChatCompletionParameters {
+        model: dataset_config.LLM_DEFAULT_MODEL.clone(),
+        messages: vec![prompt],
+        stream: Some(false),
+        ..Default::default()
     };
 
-    let client = Client {
-        headers: None,
-        api_key: llm_api_key,
-        project: None,
-        http_client: reqwest::Client::new(),
-        base_url,
-        organization: None,
+    let completion = create_chat_completion_with_fallbacks(parameters, dataset_config).await?;
+
+    let questions = match completion.choices.first().map(|choice| &choice.message) {
+        Some(ChatMessage::Assistant {
+            content: Some(ChatMessageContent::Text(content)),
+            ..
+        }) => content
+            .split('\n')
+            .map(|question| {
+                question
+                    .trim()
+                    .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == '-')
+                    .trim()
+                    .to_string()
+            })
+            .filter(|question| !question.is_empty())
+            .take(5)
+            .collect(),
+        _ => vec![],
     };
 
+    Ok(questions)
+}
+
+pub async fn get_text_from_image(
+    image_url: String,
+    prompt: Option<String>,
+    dataset: &Dataset,
+) -> Result<String, ServiceError> {
+    let dataset_config = DatasetConfiguration::from_json(dataset.server_configuration.clone());
+
     let default_system_prompt = "Please describe the image and turn the description into a search query. DO NOT INCLUDE ANY OTHER CONTEXT OR INFORMATION. JUST OUTPUT THE SEARCH QUERY AND NOTHING ELSE".to_string();
 
     let messages = vec![
@@ -1558,11 +2129,13 @@ pub async fn get_text_from_image(
         ..Default::default()
     };
 
-    let query = client
-        .chat()
-        .create(parameters)
-        .await
-        .map_err(|err| ServiceError::BadRequest(format!("Error: {:?}", err)))?;
+    let query = create_chat_completion_with_default_base_url(
+        parameters,
+        &dataset_config,
+        OPENROUTER_BASE_URL,
+    )
+    .await
+    .map_err(|err| ServiceError::BadRequest(format!("Error: {:?}", err)))?;
 
     let text = match &query
         .choices
@@ -1629,3 +2202,117 @@ pub async fn get_text_from_audio(audio_base64: &str) -> Result<String, ServiceEr
 
     Ok(text.replace("\n", ""))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::models::{NewChunkMetadataTypes, SlimChunkMetadataWithArrayTagSet};
+
+    fn score_chunk(id: uuid::Uuid, score: f32) -> ScoreChunk {
+        ScoreChunk {
+            chunk: NewChunkMetadataTypes::ID(SlimChunkMetadataWithArrayTagSet {
+                id,
+                link: None,
+                qdrant_point_id: id,
+                created_at: chrono::Utc::now().naive_utc(),
+                updated_at: chrono::Utc::now().naive_utc(),
+                tag_set: None,
+                metadata: None,
+                tracking_id: None,
+                time_stamp: None,
+                location: None,
+                dataset_id: uuid::Uuid::nil(),
+                weight: 1.0,
+                image_urls: None,
+                num_value: None,
+            }),
+            highlights: None,
+            score,
+            explain: None,
+        }
+    }
+
+    #[test]
+    pub fn test_reciprocal_rank_fusion() {
+        let (a, b, c, d) = (
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+        );
+
+        let fused = reciprocal_rank_fusion(
+            vec![
+                vec![
+                    score_chunk(a, 0.9),
+                    score_chunk(b, 0.8),
+                    score_chunk(c, 0.7),
+                ],
+                vec![
+                    score_chunk(b, 0.95),
+                    score_chunk(d, 0.6),
+                    score_chunk(a, 0.5),
+                ],
+            ],
+            3,
+        );
+
+        let ids: Vec<uuid::Uuid> = fused
+            .iter()
+            .map(|score_chunk| score_chunk.chunk.id())
+            .collect();
+        // b is ranked 2nd and 1st, a is ranked 1st and 3rd
+        assert_eq!(ids, vec![b, a, d]);
+        // Each chunk keeps its best score from any single query
+        assert_eq!(fused[0].score, 0.95);
+        assert_eq!(fused[1].score, 0.9);
+    }
+
+    #[test]
+    pub fn test_reciprocal_rank_fusion_limit() {
+        let fused = reciprocal_rank_fusion(
+            vec![vec![
+                score_chunk(uuid::Uuid::new_v4(), 0.9),
+                score_chunk(uuid::Uuid::new_v4(), 0.8),
+            ]],
+            1,
+        );
+
+        assert_eq!(fused.len(), 1);
+        assert_eq!(fused[0].score, 0.9);
+    }
+
+    #[test]
+    pub fn test_split_streamed_completion_keeps_separators_in_completion() {
+        assert_eq!(
+            split_streamed_completion("[{\"id\":1}]||use a || b here", false),
+            ("use a || b here", "[{\"id\":1}]")
+        );
+        assert_eq!(
+            split_streamed_completion("use a || b here||[{\"id\":1}]", true),
+            ("use a || b here", "[{\"id\":1}]")
+        );
+        assert_eq!(split_streamed_completion("[]", false), ("", "[]"));
+        assert_eq!(split_streamed_completion("answer", true), ("answer", ""));
+    }
+
+    #[test]
+    pub fn test_apply_output_guardrail_redacts_whole_completion() {
+        let redacted = GuardrailOutcome {
+            stage: GuardrailStage::Output,
+            action: Some(crate::data::models::GuardrailAction::Redact),
+            violations: vec!["pii_email".to_string()],
+            text: "a || [REDACTED]".to_string(),
+            redacted_text: "a || [REDACTED]".to_string(),
+        };
+
+        assert_eq!(
+            apply_output_guardrail("[]||a || me@example.com".to_string(), &redacted, false),
+            "[]||a || [REDACTED]"
+        );
+        assert_eq!(
+            apply_output_guardrail("a || me@example.com||[]".to_string(), &redacted, true),
+            "a || [REDACTED]||[]"
+        );
+    }
+}
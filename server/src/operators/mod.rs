@@ -1,14 +1,26 @@
+pub mod acl_operator;
+pub mod analytics_export_operator;
 pub mod analytics_operator;
+pub mod analytics_report_operator;
+pub mod analytics_store_operator;
+pub mod audit_log_operator;
 pub mod chunk_operator;
 pub mod clickhouse_operator;
 pub mod crawl_operator;
 pub mod dataset_operator;
+pub mod dataset_webhook_operator;
+pub mod dead_letter_operator;
 pub mod dittofeed_operator;
 pub mod email_operator;
+pub mod engagement_operator;
 pub mod etl_operator;
 pub mod event_operator;
+pub mod experiment_operator;
+pub mod fallback_operator;
 pub mod file_operator;
 pub mod group_operator;
+pub mod guardrail_operator;
+pub mod ingestion_job_operator;
 pub mod invitation_operator;
 pub mod message_operator;
 pub mod model_operator;
@@ -16,10 +28,18 @@ pub mod organization_operator;
 pub mod pagefind_operator;
 pub mod parse_operator;
 pub mod qdrant_operator;
+pub mod query_clustering_operator;
+pub mod query_suggestion_operator;
+pub mod rag_preset_operator;
+pub mod rate_limit_operator;
 pub mod search_operator;
+pub mod search_token_operator;
 pub mod stripe_operator;
 pub mod topic_operator;
+pub mod trash_operator;
 pub mod typo_operator;
+pub mod usage_operator;
 pub mod user_operator;
 pub mod video_operator;
 pub mod webhook_operator;
+pub mod worker_metrics_operator;
@@ -11,9 +11,15 @@ use openai_dive::v1::resources::embedding::EmbeddingInput;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, io::Cursor, ops::IndexMut, sync::Arc};
 
+use super::fallback_operator::{
+    call_with_fallbacks, get_embedding_endpoints, get_embedding_fallback_endpoints,
+    get_primary_embedding_endpoint, get_reranker_endpoints, EndpointError, EndpointKind,
+    ResolvedEndpoint,
+};
 use super::parse_operator::convert_html_to_text;
+use super::usage_operator::{estimate_tokens, meter_usage};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EmbeddingParameters {
     /// Input text to embed, encoded as a string or array of tokens.
     /// To embed multiple inputs in a single request, pass an array of strings or array of token arrays.
@@ -49,41 +55,6 @@ pub async fn get_dense_vector(
     _embed_type: &str,
     dataset_config: DatasetConfiguration,
 ) -> Result<Vec<f32>, ServiceError> {
-    let embedding_api_key = get_env!("OPENAI_API_KEY", "OPENAI_API_KEY should be set");
-    let config_embedding_base_url = dataset_config.EMBEDDING_BASE_URL;
-
-    let embedding_base_url = match config_embedding_base_url.as_str() {
-        "" => get_env!("OPENAI_BASE_URL", "OPENAI_BASE_URL must be set").to_string(),
-        "https://api.openai.com/v1" => {
-            get_env!("OPENAI_BASE_URL", "OPENAI_BASE_URL must be set").to_string()
-        }
-        "https://embedding.trieve.ai" => std::env::var("EMBEDDING_SERVER_ORIGIN")
-            .ok()
-            .filter(|s| !s.is_empty())
-            .unwrap_or("https://embedding.trieve.ai".to_string()),
-        "https://embedding.trieve.ai/bge-m3" => std::env::var("EMBEDDING_SERVER_ORIGIN_BGEM3")
-            .ok()
-            .filter(|s| !s.is_empty())
-            .unwrap_or("https://embedding.trieve.ai/bge-m3".to_string()),
-        "https://embedding.trieve.ai/jina-code" => {
-            std::env::var("EMBEDDING_SERVER_ORIGIN_JINA_CODE")
-                .ok()
-                .filter(|s| !s.is_empty())
-                .unwrap_or("https://embedding.trieve.ai/jina-code".to_string())
-        }
-        _ => config_embedding_base_url.clone(),
-    };
-
-    let embedding_api_key =
-        if config_embedding_base_url.as_str() == "https://embedding.trieve.ai/jina-code" {
-            std::env::var("JINA_CODE_API_KEY")
-                .ok()
-                .filter(|s| !s.is_empty())
-                .unwrap_or(embedding_api_key.to_string())
-        } else {
-            embedding_api_key.to_string()
-        };
-
     let clipped_message: String = message.chars().take(20000).collect();
     let mut messages = vec![format!(
         "{}{}",
@@ -101,14 +72,47 @@ pub async fn get_dense_vector(
         messages.push(clipped_boost);
     }
 
+    let endpoints = get_embedding_endpoints(&dataset_config);
+
+    let vector = call_with_fallbacks(EndpointKind::Embedding, &endpoints, |endpoint| {
+        let messages = messages.clone();
+        let semantic_boost = semantic_boost.clone();
+        let dataset_config = dataset_config.clone();
+        async move {
+            get_dense_vector_from_endpoint(endpoint, messages, semantic_boost, dataset_config).await
+        }
+    })
+    .await?;
+
+    meter_usage(|usage| {
+        usage.embedding_tokens += messages
+            .iter()
+            .map(|message| estimate_tokens(message))
+            .sum::<i64>()
+    });
+
+    Ok(vector)
+}
+
+async fn get_dense_vector_from_endpoint(
+    endpoint: ResolvedEndpoint,
+    messages: Vec<String>,
+    semantic_boost: Option<SemanticBoost>,
+    dataset_config: DatasetConfiguration,
+) -> Result<Vec<f32>, EndpointError> {
     let input = EmbeddingInput::StringArray(messages);
     let parameters = EmbeddingParameters {
-        model: dataset_config.EMBEDDING_MODEL_NAME.to_string(),
+        model: endpoint
+            .model_name
+            .clone()
+            .unwrap_or(dataset_config.EMBEDDING_MODEL_NAME.to_string()),
         input,
         truncate: true,
     };
+    let embedding_base_url = endpoint.base_url;
+    let embedding_api_key = endpoint.api_key;
 
-    web::block(move || {
+    web::block(move || -> Result<Vec<f32>, EndpointError> {
         let embeddings_resp_a = ureq::AgentBuilder::new()
             .tls_connector(Arc::new(native_tls::TlsConnector::new().map_err(|_| {
                 ServiceError::InternalServerError("Failed to acquire tls connection".to_string())
@@ -124,11 +128,14 @@ pub async fn get_dense_vector(
             .set("Content-Type", "application/json")
             .send_json(serde_json::to_value(parameters).unwrap())
             .map_err(|e| {
-                ServiceError::InternalServerError(format!(
-                    "Could not get embeddings from server: {:?}, {:?}",
-                    e,
-                    e.to_string()
-                ))
+                EndpointError::from_ureq(
+                    &e,
+                    ServiceError::InternalServerError(format!(
+                        "Could not get embeddings from server: {:?}, {:?}",
+                        e,
+                        e.to_string()
+                    )),
+                )
             })?;
 
         let embeddings_resp = embeddings_resp_a
@@ -148,7 +155,8 @@ pub async fn get_dense_vector(
                 None => {
                     return Err(ServiceError::InternalServerError(
                         "No dense embedding returned from server for boost_vector".to_owned(),
-                    ))
+                    )
+                    .into())
                 }
             };
             let embedding_vector = match vectors.pop() {
@@ -156,7 +164,8 @@ pub async fn get_dense_vector(
                 None => {
                     return Err(ServiceError::InternalServerError(
                         "No dense embedding returned from server for embedding_vector".to_owned(),
-                    ))
+                    )
+                    .into())
                 }
             };
 
@@ -171,7 +180,8 @@ pub async fn get_dense_vector(
             Some(v) => Ok(v.clone()),
             None => Err(ServiceError::InternalServerError(
                 "No dense embeddings returned from server".to_owned(),
-            )),
+            )
+            .into()),
         }
     })
     .await
@@ -315,41 +325,7 @@ pub async fn get_dense_vectors(
     dataset_config: DatasetConfiguration,
     reqwest_client: reqwest::Client,
 ) -> Result<Vec<Vec<f32>>, ServiceError> {
-    let embedding_api_key = get_env!("OPENAI_API_KEY", "OPENAI_API_KEY should be set");
-    let config_embedding_base_url = dataset_config.EMBEDDING_BASE_URL;
-    let embedding_base_url = match config_embedding_base_url.as_str() {
-        "" => get_env!("OPENAI_BASE_URL", "OPENAI_BASE_URL must be set").to_string(),
-        "https://api.openai.com/v1" => {
-            get_env!("OPENAI_BASE_URL", "OPENAI_BASE_URL must be set").to_string()
-        }
-        "https://embedding.trieve.ai" => std::env::var("EMBEDDING_SERVER_ORIGIN")
-            .ok()
-            .filter(|s| !s.is_empty())
-            .unwrap_or("https://embedding.trieve.ai".to_string()),
-        "https://embedding.trieve.ai/bge-m3" => std::env::var("EMBEDDING_SERVER_ORIGIN_BGEM3")
-            .ok()
-            .filter(|s| !s.is_empty())
-            .unwrap_or("https://embedding.trieve.ai/bge-m3".to_string())
-            .to_string(),
-        "https://embedding.trieve.ai/jina-code" => {
-            std::env::var("EMBEDDING_SERVER_ORIGIN_JINA_CODE")
-                .ok()
-                .filter(|s| !s.is_empty())
-                .unwrap_or("https://embedding.trieve.ai/jina-code".to_string())
-                .to_string()
-        }
-        _ => config_embedding_base_url.clone(),
-    };
-
-    let embedding_api_key =
-        if config_embedding_base_url.as_str() == "https://embedding.trieve.ai/jina-code" {
-            std::env::var("JINA_CODE_API_KEY")
-                .ok()
-                .filter(|s| !s.is_empty())
-                .unwrap_or(embedding_api_key.to_string())
-        } else {
-            embedding_api_key.to_string()
-        };
+    let endpoints = get_embedding_endpoints(&dataset_config);
 
     let (contents, distance_phrases): (Vec<_>, Vec<_>) =
         content_and_distances.clone().into_iter().unzip();
@@ -400,28 +376,16 @@ pub async fn get_dense_vectors(
             };
 
             let cur_client = reqwest_client.clone();
-            let url = embedding_base_url.clone();
-
-            let embedding_api_key = embedding_api_key.clone();
+            let endpoints = endpoints.clone();
 
             async move {
-                let embeddings_resp = cur_client
-                    .post(format!("{}/embeddings?api-version=2023-05-15", url))
-                    .header("Authorization", &format!("Bearer {}", &embedding_api_key.clone()))
-                    .header("api-key", &embedding_api_key.clone())
-                    .header("Content-Type", "application/json")
-                    .timeout(std::time::Duration::from_secs(90))
-                    .json(&parameters)
-                    .send()
-                    .await
-                    .map_err(|_| {
-                        ServiceError::BadRequest("Failed to send message to embedding server".to_string())
-                    })?
-                    .json::<DenseEmbedData>()
-                    .await
-                    .map_err(|err| {
-                        ServiceError::BadRequest(format!("Failed to format text from embeddings {}", err))
-                    })?;
+                let embeddings_resp = send_embedding_request_with_fallbacks(
+                    cur_client,
+                    &endpoints,
+                    parameters,
+                    Some(std::time::Duration::from_secs(90)),
+                )
+                .await?;
 
                 let vectors_and_boosts: Vec<(Vec<f32>, &(usize, SemanticBoost))> = embeddings_resp
                     .to_vec()
@@ -467,35 +431,12 @@ pub async fn get_dense_vectors(
             };
 
             let cur_client = reqwest_client.clone();
-            let url = embedding_base_url.clone();
-
-            let embedding_api_key = embedding_api_key.clone();
+            let endpoints = endpoints.clone();
 
             async move {
-                let embeddings_resp = cur_client
-                    .post(format!("{}/embeddings?api-version=2023-05-15", url))
-                    .header(
-                        "Authorization",
-                        &format!("Bearer {}", &embedding_api_key.clone()),
-                    )
-                    .header("api-key", &embedding_api_key.clone())
-                    .header("Content-Type", "application/json")
-                    .json(&parameters)
-                    .send()
-                    .await
-                    .map_err(|_| {
-                        ServiceError::BadRequest(
-                            "Failed to send message to embedding server".to_string(),
-                        )
-                    })?
-                    .json::<DenseEmbedData>()
-                    .await
-                    .map_err(|err| {
-                        ServiceError::BadRequest(format!(
-                            "Failed to get text from embeddings {:?}",
-                            err
-                        ))
-                    })?;
+                let embeddings_resp =
+                    send_embedding_request_with_fallbacks(cur_client, &endpoints, parameters, None)
+                        .await?;
 
                 let vectors: Vec<Vec<f32>> = embeddings_resp.to_vec();
 
@@ -545,9 +486,168 @@ pub async fn get_dense_vectors(
             .collect();
     }
 
+    meter_usage(|usage| {
+        usage.embedding_tokens += content_and_distances
+            .iter()
+            .flat_map(|(content, semantic_boost)| {
+                std::iter::once(content).chain(semantic_boost.as_ref().map(|boost| &boost.phrase))
+            })
+            .map(|message| estimate_tokens(&message.chars().take(12000).collect::<String>()))
+            .sum::<i64>()
+    });
+
     Ok(content_vectors)
 }
 
+async fn send_embedding_request(
+    client: reqwest::Client,
+    endpoint: &ResolvedEndpoint,
+    mut parameters: EmbeddingParameters,
+    timeout: Option<std::time::Duration>,
+) -> Result<DenseEmbedData, EndpointError> {
+    if let Some(model_name) = endpoint.model_name.clone() {
+        parameters.model = model_name;
+    }
+
+    let mut request = client
+        .post(format!(
+            "{}/embeddings?api-version=2023-05-15",
+            endpoint.base_url
+        ))
+        .header("Authorization", &format!("Bearer {}", &endpoint.api_key))
+        .header("api-key", &endpoint.api_key)
+        .header("Content-Type", "application/json")
+        .json(&parameters);
+
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+
+    request
+        .send()
+        .await
+        .map_err(|err| {
+            EndpointError::from_reqwest(
+                &err,
+                ServiceError::BadRequest("Failed to send message to embedding server".to_string()),
+            )
+        })?
+        .error_for_status()
+        .map_err(|err| {
+            EndpointError::from_reqwest(
+                &err,
+                ServiceError::BadRequest(format!("Embedding server returned an error {}", err)),
+            )
+        })?
+        .json::<DenseEmbedData>()
+        .await
+        .map_err(|err| {
+            ServiceError::BadRequest(format!("Failed to format text from embeddings {}", err))
+                .into()
+        })
+}
+
+async fn send_embedding_request_with_fallbacks(
+    client: reqwest::Client,
+    endpoints: &[ResolvedEndpoint],
+    parameters: EmbeddingParameters,
+    timeout: Option<std::time::Duration>,
+) -> Result<DenseEmbedData, ServiceError> {
+    call_with_fallbacks(EndpointKind::Embedding, endpoints, |endpoint| {
+        let client = client.clone();
+        let parameters = parameters.clone();
+        async move { send_embedding_request(client, &endpoint, parameters, timeout).await }
+    })
+    .await
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    dot / (norm_a * norm_b)
+}
+
+/// Embeds a probe phrase with the primary endpoint and every embedding fallback to make sure each fallback returns vectors of EMBEDDING_SIZE which land next to the primary model's vector.
+pub async fn validate_embedding_fallback_endpoints(
+    dataset_config: &DatasetConfiguration,
+) -> Result<(), ServiceError> {
+    let fallback_endpoints = get_embedding_fallback_endpoints(dataset_config);
+    if fallback_endpoints.is_empty() {
+        return Ok(());
+    }
+
+    let min_similarity: f32 = std::env::var("EMBEDDING_FALLBACK_MIN_SIMILARITY")
+        .unwrap_or("0.98".to_string())
+        .parse()
+        .unwrap_or(0.98);
+
+    let client = reqwest::Client::new();
+    let parameters = EmbeddingParameters {
+        model: dataset_config.EMBEDDING_MODEL_NAME.clone(),
+        input: EmbeddingInput::StringArray(vec![
+            "Trieve embedding fallback compatibility check".to_string()
+        ]),
+        truncate: true,
+    };
+    let timeout = Some(std::time::Duration::from_secs(10));
+
+    let primary_vector = send_embedding_request(
+        client.clone(),
+        &get_primary_embedding_endpoint(dataset_config),
+        parameters.clone(),
+        timeout,
+    )
+    .await
+    .map_err(|err| {
+        ServiceError::BadRequest(format!(
+            "Could not reach EMBEDDING_BASE_URL to validate the embedding fallbacks: {}",
+            err
+        ))
+    })?
+    .to_vec()
+    .pop()
+    .unwrap_or_default();
+
+    for endpoint in fallback_endpoints {
+        let fallback_vector =
+            send_embedding_request(client.clone(), &endpoint, parameters.clone(), timeout)
+                .await
+                .map_err(|err| {
+                    ServiceError::BadRequest(format!(
+                        "Could not reach embedding fallback {}: {}",
+                        endpoint.base_url, err
+                    ))
+                })?
+                .to_vec()
+                .pop()
+                .unwrap_or_default();
+
+        if fallback_vector.len() != dataset_config.EMBEDDING_SIZE {
+            return Err(ServiceError::BadRequest(format!(
+                "Embedding fallback {} returned vectors of size {} but EMBEDDING_SIZE is {}",
+                endpoint.base_url,
+                fallback_vector.len(),
+                dataset_config.EMBEDDING_SIZE
+            )));
+        }
+
+        if cosine_similarity(&primary_vector, &fallback_vector) < min_similarity {
+            return Err(ServiceError::BadRequest(format!(
+                "Embedding fallback {} is not vector-compatible with {}. Fallbacks must serve the same embedding model as the primary endpoint",
+                endpoint.base_url, dataset_config.EMBEDDING_MODEL_NAME
+            )));
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SpladeEmbedding {
     pub embeddings: Vec<(u32, f32)>,
@@ -862,6 +962,87 @@ pub struct CohereScorePair {
     pub relevance_score: f32,
 }
 
+async fn rerank_documents(
+    endpoint: ResolvedEndpoint,
+    query: String,
+    documents: Vec<String>,
+    default_server_origin: String,
+    timeout: Option<std::time::Duration>,
+) -> Result<Vec<(usize, f64)>, EndpointError> {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/rerank", endpoint.base_url))
+        .header("Authorization", &format!("Bearer {}", endpoint.api_key))
+        .header("api-key", endpoint.api_key.to_string())
+        .header("Content-Type", "application/json");
+
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+
+    // Any reranker other than the default server origin is assumed to be cohere compatible
+    let is_cohere = endpoint.base_url != default_server_origin;
+    let request = if is_cohere {
+        request.json(&CohereRerankCall {
+            model: endpoint.model_name.clone().unwrap_or_default(),
+            query,
+            documents,
+        })
+    } else {
+        request.json(&CrossEncoderData {
+            query,
+            texts: documents,
+            truncate: true,
+        })
+    };
+
+    let rerank_resp = request
+        .send()
+        .await
+        .map_err(|err| {
+            EndpointError::from_reqwest(
+                &err,
+                ServiceError::BadRequest(format!("Failed making call to server {:?}", err)),
+            )
+        })?
+        .error_for_status()
+        .map_err(|err| {
+            EndpointError::from_reqwest(
+                &err,
+                ServiceError::BadRequest(format!("Reranker returned an error {:?}", err)),
+            )
+        })?
+        .text()
+        .await
+        .map_err(|_| ServiceError::BadRequest("Failed to get text from reranker".to_string()))?;
+
+    if is_cohere {
+        let rankings: CohereRerankResponse = serde_json::from_str(&rerank_resp).map_err(|e| {
+            log::error!("Failed to format response from reranker server {:?}", e);
+            ServiceError::InternalServerError(
+                "Failed to format response from reranker server".to_owned(),
+            )
+        })?;
+
+        Ok(rankings
+            .results
+            .into_iter()
+            .map(|pair| (pair.index, pair.relevance_score as f64))
+            .collect())
+    } else {
+        let rankings: Vec<ScorePair> = serde_json::from_str(&rerank_resp).map_err(|e| {
+            log::error!("Failed to format response from reranker server {:?}", e);
+            ServiceError::InternalServerError(
+                "Failed to format response from reranker server".to_owned(),
+            )
+        })?;
+
+        Ok(rankings
+            .into_iter()
+            .map(|pair| (pair.index, pair.score as f64))
+            .collect())
+    }
+}
+
 pub async fn cross_encoder(
     query: String,
     page_size: u64,
@@ -871,10 +1052,9 @@ pub async fn cross_encoder(
     let default_server_origin = get_env!(
         "RERANKER_SERVER_ORIGIN",
         "RERANKER_SERVER_ORIGIN must be set"
-    );
-    let server_origin: String = dataset_config.RERANKER_BASE_URL.clone();
-
-    let embedding_server_call = format!("{}/rerank", server_origin);
+    )
+    .to_string();
+    let endpoints = get_reranker_endpoints(dataset_config);
 
     if results.is_empty() {
         return Ok(vec![]);
@@ -908,95 +1088,29 @@ pub async fn cross_encoder(
             })
             .collect::<Result<Vec<String>, ServiceError>>()?;
 
-        let reranker_api_key = dataset_config.RERANKER_API_KEY.clone();
-        if server_origin != default_server_origin {
-            // Assume cohere
-            let reranker_model_name = dataset_config.RERANKER_MODEL_NAME.clone();
-            let resp = ureq::AgentBuilder::new()
-                .tls_connector(Arc::new(native_tls::TlsConnector::new().map_err(|_| {
-                    ServiceError::InternalServerError(
-                        "Failed to acquire tls connection".to_string(),
-                    )
-                })?))
-                .timeout(std::time::Duration::from_secs(5))
-                .build()
-                .post(&embedding_server_call)
-                .set("Content-Type", "application/json")
-                .set(
-                    "Authorization",
-                    &format!("Bearer {}", reranker_api_key.clone()),
-                )
-                .send_json(CohereRerankCall {
-                    model: reranker_model_name.clone(),
-                    query: query.clone(),
-                    documents: request_docs,
-                })
-                .map_err(|err| {
-                    ServiceError::BadRequest(format!("Failed making call to server {:?}", err))
-                })?
-                .into_json::<CohereRerankResponse>()
-                .map_err(|_e| {
-                    log::error!(
-                        "Failed parsing response from custom embedding server {:?}",
-                        _e
-                    );
-                    ServiceError::BadRequest(
-                        "Failed parsing response from custom embedding server".to_string(),
-                    )
-                })?;
-
-            resp.results.into_iter().for_each(|pair| {
-                results.index_mut(pair.index).score = pair.relevance_score as f64;
-            });
-        } else {
-            let resp = ureq::AgentBuilder::new()
-                .tls_connector(Arc::new(native_tls::TlsConnector::new().map_err(|_| {
-                    ServiceError::InternalServerError(
-                        "Failed to acquire tls connection".to_string(),
-                    )
-                })?))
-                .timeout(std::time::Duration::from_secs(5))
-                .build()
-                .post(&embedding_server_call)
-                .set("Content-Type", "application/json")
-                .set(
-                    "Authorization",
-                    &format!("Bearer {}", reranker_api_key.clone()),
-                )
-                .send_json(CrossEncoderData {
-                    query: query.clone(),
-                    texts: request_docs,
-                    truncate: true,
-                })
-                .map_err(|err| {
-                    ServiceError::BadRequest(format!("Failed making call to server {:?}", err))
-                })?
-                .into_json::<Vec<ScorePair>>()
-                .map_err(|_e| {
-                    log::error!(
-                        "Failed parsing response from custom embedding server {:?}",
-                        _e
-                    );
-                    ServiceError::BadRequest(
-                        "Failed parsing response from custom embedding server".to_string(),
-                    )
-                })?;
+        let scores = call_with_fallbacks(EndpointKind::Reranker, &endpoints, |endpoint| {
+            rerank_documents(
+                endpoint,
+                query.clone(),
+                request_docs.clone(),
+                default_server_origin.clone(),
+                Some(std::time::Duration::from_secs(5)),
+            )
+        })
+        .await?;
 
-            resp.into_iter().for_each(|pair| {
-                results.index_mut(pair.index).score = pair.score as f64;
-            });
-        }
+        scores.into_iter().for_each(|(index, score)| {
+            results.index_mut(index).score = score;
+        });
     } else {
         let vec_futures: Vec<_> = results
             .chunks_mut(20)
             .map(|docs_chunk| {
-                let cur_client = reqwest::Client::new();
                 let query = query.clone();
-                let reranker_api_key = dataset_config.RERANKER_API_KEY.clone();
-                let url = embedding_server_call.clone();
-                let server_origin = server_origin.clone();
+                let endpoints = endpoints.clone();
+                let default_server_origin = default_server_origin.clone();
 
-                let vectors_resp = async move {
+                async move {
                     let request_docs = docs_chunk
                         .iter_mut()
                         .map(|x| {
@@ -1014,97 +1128,24 @@ pub async fn cross_encoder(
                         })
                         .collect::<Result<Vec<String>, ServiceError>>()?;
 
-                    if server_origin != default_server_origin {
-                        let reranker_model_name = dataset_config.RERANKER_MODEL_NAME.clone();
-                        let parameters = CohereRerankCall {
-                            model: reranker_model_name.clone(),
-                            query: query.clone(),
-                            documents: request_docs.clone(),
-                        };
-
-                        let embeddings_resp = cur_client
-                            .post(&url)
-                            .header("Authorization", &format!("Bearer {}", reranker_api_key))
-                            .header("api-key", reranker_api_key.to_string())
-                            .header("Content-Type", "application/json")
-                            .json(&parameters)
-                            .send()
-                            .await
-                            .map_err(|_| {
-                                ServiceError::BadRequest(
-                                    "Failed to send message to embedding server".to_string(),
-                                )
-                            })?
-                            .text()
-                            .await
-                            .map_err(|_| {
-                                ServiceError::BadRequest(
-                                    "Failed to get text from embeddings".to_string(),
-                                )
-                            })?;
-
-                        let rankings: CohereRerankResponse = serde_json::from_str(&embeddings_resp)
-                            .map_err(|e| {
-                                log::error!(
-                                    "Failed to format response from embeddings server {:?}",
-                                    e
-                                );
-                                ServiceError::InternalServerError(
-                                    "Failed to format response from embeddings server".to_owned(),
-                                )
-                            })?;
-
-                        rankings.results.into_iter().for_each(|pair| {
-                            docs_chunk.index_mut(pair.index).score = pair.relevance_score as f64;
-                        });
-                    } else {
-                        let parameters = CrossEncoderData {
-                            query: query.clone(),
-                            texts: request_docs.clone(),
-                            truncate: true,
-                        };
-
-                        let embeddings_resp = cur_client
-                            .post(&url)
-                            .header("Authorization", &format!("Bearer {}", reranker_api_key))
-                            .header("api-key", reranker_api_key.to_string())
-                            .header("Content-Type", "application/json")
-                            .json(&parameters)
-                            .send()
-                            .await
-                            .map_err(|_| {
-                                ServiceError::BadRequest(
-                                    "Failed to send message to embedding server".to_string(),
-                                )
-                            })?
-                            .text()
-                            .await
-                            .map_err(|_| {
-                                ServiceError::BadRequest(
-                                    "Failed to get text from embeddings".to_string(),
-                                )
-                            })?;
-
-                        let embeddings: Vec<ScorePair> = serde_json::from_str(&embeddings_resp)
-                            .map_err(|e| {
-                                log::error!(
-                                    "Failed to format response from embeddings server {:?}",
-                                    e
-                                );
-                                ServiceError::InternalServerError(
-                                    "Failed to format response from embeddings server".to_owned(),
-                                )
-                            })?;
-
-                        embeddings.into_iter().for_each(|pair| {
-                            docs_chunk.index_mut(pair.index).score = pair.score as f64;
-                        });
-                    }
+                    let scores =
+                        call_with_fallbacks(EndpointKind::Reranker, &endpoints, |endpoint| {
+                            rerank_documents(
+                                endpoint,
+                                query.clone(),
+                                request_docs.clone(),
+                                default_server_origin.clone(),
+                                None,
+                            )
+                        })
+                        .await?;
 
-                    Ok(())
-                };
+                    scores.into_iter().for_each(|(index, score)| {
+                        docs_chunk.index_mut(index).score = score;
+                    });
 
-                vectors_resp
+                    Ok(())
+                }
             })
             .collect();
 
@@ -65,6 +65,7 @@ pub async fn update_organization_query(
     id: uuid::Uuid,
     name: &str,
     partner_configuration: serde_json::Value,
+    trash_retention_days: i32,
     pool: web::Data<Pool>,
     redis_pool: web::Data<RedisPool>,
 ) -> Result<Organization, ServiceError> {
@@ -81,6 +82,7 @@ pub async fn update_organization_query(
         .set((
             organizations_columns::name.eq(name),
             organizations_columns::partner_configuration.eq(partner_configuration),
+            organizations_columns::trash_retention_days.eq(trash_retention_days),
             organizations_columns::updated_at.eq(chrono::Utc::now().naive_local()),
         ))
         .get_result(&mut conn)
@@ -682,7 +684,7 @@ pub async fn create_organization_api_key_query(
     organization_id: uuid::Uuid,
     data: CreateApiKeyReqPayload,
     pool: web::Data<Pool>,
-) -> Result<String, ServiceError> {
+) -> Result<(uuid::Uuid, String), ServiceError> {
     let raw_api_key = generate_api_key();
     let hashed_api_key = hash_function(&raw_api_key);
 
@@ -717,7 +719,7 @@ pub async fn create_organization_api_key_query(
         .await
         .map_err(|err| ServiceError::BadRequest(format!("Error setting api key {}", err)))?;
 
-    Ok(raw_api_key)
+    Ok((api_key_struct.id, raw_api_key))
 }
 
 pub async fn get_organization_api_keys_query(
@@ -295,6 +295,15 @@ pub async fn create_new_qdrant_collection_query(
             .await
             .map_err(|_| ServiceError::BadRequest("Failed to create index".into()))?;
 
+        qdrant_client
+            .create_field_index(CreateFieldIndexCollectionBuilder::new(
+                collection_name.clone(),
+                "acl",
+                FieldType::Keyword,
+            ))
+            .await
+            .map_err(|_| ServiceError::BadRequest("Failed to create index".into()))?;
+
         qdrant_client
             .create_field_index(
                 CreateFieldIndexCollectionBuilder::new(
@@ -384,6 +393,15 @@ pub async fn create_new_qdrant_collection_query(
             ))
             .await
             .map_err(|_| ServiceError::BadRequest("Failed to create index".into()))?;
+
+        qdrant_client
+            .create_field_index(CreateFieldIndexCollectionBuilder::new(
+                collection_name.clone(),
+                "hidden",
+                FieldType::Bool,
+            ))
+            .await
+            .map_err(|_| ServiceError::BadRequest("Failed to create index".into()))?;
     }
 
     Ok(())
@@ -543,12 +561,18 @@ pub async fn update_qdrant_point_query(
                 .flatten()
                 .collect();
 
-        QdrantPayload::new(
-            metadata.clone(),
-            group_ids.into(),
-            Some(dataset_id),
-            Some(chunk_tags),
-        )
+        QdrantPayload {
+            hidden: current_point
+                .and_then(|point| point.payload.get("hidden"))
+                .and_then(|hidden| hidden.as_bool())
+                .unwrap_or_default(),
+            ..QdrantPayload::new(
+                metadata.clone(),
+                group_ids.into(),
+                Some(dataset_id),
+                Some(chunk_tags),
+            )
+        }
     };
 
     if let Some(updated_vector) = updated_vector {
@@ -1443,6 +1467,7 @@ pub async fn recommend_qdrant_groups_query(
     Ok(group_recommendation_results)
 }
 
+/// Whether all of the points exist in the collection. Points hidden by an item in the trash count as missing.
 pub async fn point_ids_exists_in_qdrant(
     point_ids: Vec<uuid::Uuid>,
     dataset_config: DatasetConfiguration,
@@ -1460,7 +1485,7 @@ pub async fn point_ids_exists_in_qdrant(
     let data = qdrant_client
         .get_points(
             GetPointsBuilder::new(qdrant_collection.clone(), points.clone())
-                .with_payload(false)
+                .with_payload(true)
                 .with_vectors(false)
                 .build(),
         )
@@ -1470,7 +1495,58 @@ pub async fn point_ids_exists_in_qdrant(
             ServiceError::BadRequest("Failed to fetch points from qdrant".to_string())
         })?;
 
-    Ok(data.result.len() == point_ids.len())
+    let visible_points = data
+        .result
+        .iter()
+        .filter(|point| {
+            !point
+                .payload
+                .get("hidden")
+                .and_then(|hidden| hidden.as_bool())
+                .unwrap_or_default()
+        })
+        .count();
+
+    Ok(visible_points == point_ids.len())
+}
+
+/// Sets the group_ids and hidden fields of the points' payloads, leaving the rest of the payload as is.
+pub async fn set_trash_payload_in_qdrant_query(
+    point_ids: Vec<uuid::Uuid>,
+    group_ids: Vec<uuid::Uuid>,
+    hidden: bool,
+    qdrant_collection: String,
+) -> Result<(), ServiceError> {
+    if point_ids.is_empty() {
+        return Ok(());
+    }
+
+    let qdrant_client = get_qdrant_connection(
+        Some(get_env!("QDRANT_URL", "QDRANT_URL should be set")),
+        Some(get_env!("QDRANT_API_KEY", "QDRANT_API_KEY should be set")),
+    )
+    .await?;
+
+    let points: Vec<PointId> = point_ids.iter().map(|x| x.to_string().into()).collect();
+
+    let payload: Payload = serde_json::json!({
+        "group_ids": group_ids,
+        "hidden": hidden,
+    })
+    .try_into()
+    .map_err(|_| ServiceError::BadRequest("Failed to build qdrant payload".to_string()))?;
+
+    qdrant_client
+        .set_payload(
+            SetPayloadPointsBuilder::new(qdrant_collection, payload).points_selector(points),
+        )
+        .await
+        .map_err(|err| {
+            log::info!("Failed to set trash payload in qdrant {:?}", err);
+            ServiceError::BadRequest("Failed updating chunk payload in qdrant".to_string())
+        })?;
+
+    Ok(())
 }
 
 pub fn get_collection_name_from_config(config: &DatasetConfiguration) -> String {
@@ -0,0 +1,394 @@
+use crate::{
+    data::models::{
+        ClusterTopicsClickhouse, Dataset, DatasetConfiguration, SearchClusterMembershipClickhouse,
+    },
+    errors::ServiceError,
+    operators::fallback_operator::{
+        call_with_fallbacks, get_llm_endpoints, EndpointError, EndpointKind,
+    },
+};
+use clickhouse::Row;
+use openai_dive::v1::resources::chat::{ChatCompletionParameters, ChatMessage, ChatMessageContent};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use time::OffsetDateTime;
+
+/// Maximum number of searches read from ClickHouse per clustering run.
+const MAX_CLUSTERED_SEARCHES: u64 = 10_000;
+/// Maximum number of clusters created per dataset, which is also how many the clusters analytics return.
+const MAX_CLUSTERS: usize = 10;
+/// Clusters with fewer searches than this are dropped as noise.
+const MIN_CLUSTER_SIZE: usize = 5;
+const KMEANS_MAX_ITERATIONS: usize = 50;
+/// Number of distinct queries closest to the centroid which the LLM is shown to label a cluster.
+const LABEL_SAMPLE_SIZE: usize = 10;
+
+#[derive(Debug, Row, Serialize, Deserialize, Clone)]
+struct ClusteringSearchRow {
+    #[serde(with = "clickhouse::serde::uuid")]
+    id: uuid::Uuid,
+    query: String,
+    top_score: f32,
+    query_vector: Vec<f32>,
+}
+
+#[derive(Debug, Row, Serialize, Deserialize)]
+struct ClusterIdRow {
+    #[serde(with = "clickhouse::serde::uuid")]
+    id: uuid::Uuid,
+}
+
+#[derive(Debug, Clone)]
+struct QueryCluster {
+    /// Indices of the member searches and their cosine distance to the centroid.
+    members: Vec<(usize, f32)>,
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    1.0 - a.iter().zip(b.iter()).map(|(x, y)| x * y).sum::<f32>()
+}
+
+fn nearest_centroid(vector: &[f32], centroids: &[Vec<f32>]) -> (usize, f32) {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(index, centroid)| (index, cosine_distance(vector, centroid)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((0, 0.0))
+}
+
+/// Spherical k-means over normalized vectors with k-means++ seeding. The rng is seeded by the caller so reruns over the same searches produce the same clusters.
+fn kmeans(vectors: &[Vec<f32>], k: usize, rng: &mut StdRng) -> Vec<QueryCluster> {
+    let mut centroids: Vec<Vec<f32>> = vec![vectors[rng.gen_range(0..vectors.len())].clone()];
+
+    while centroids.len() < k {
+        let distances: Vec<f32> = vectors
+            .iter()
+            .map(|vector| nearest_centroid(vector, &centroids).1.max(0.0).powi(2))
+            .collect();
+        let total: f32 = distances.iter().sum();
+        if total <= 0.0 {
+            break;
+        }
+
+        let mut target = rng.gen::<f32>() * total;
+        let next = distances
+            .iter()
+            .position(|distance| {
+                target -= distance;
+                target <= 0.0
+            })
+            .unwrap_or(vectors.len() - 1);
+        centroids.push(vectors[next].clone());
+    }
+
+    let mut assignments = vec![usize::MAX; vectors.len()];
+    for _ in 0..KMEANS_MAX_ITERATIONS {
+        let mut changed = false;
+        for (index, vector) in vectors.iter().enumerate() {
+            let (cluster, _) = nearest_centroid(vector, &centroids);
+            if assignments[index] != cluster {
+                assignments[index] = cluster;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+
+        for (cluster, centroid) in centroids.iter_mut().enumerate() {
+            let mut sum = vec![0.0; centroid.len()];
+            for (vector, _) in vectors
+                .iter()
+                .zip(assignments.iter())
+                .filter(|(_, assignment)| **assignment == cluster)
+            {
+                sum.iter_mut().zip(vector).for_each(|(s, x)| *s += x);
+            }
+            normalize(&mut sum);
+            // An emptied cluster keeps its previous centroid
+            if sum.iter().any(|x| *x != 0.0) {
+                *centroid = sum;
+            }
+        }
+    }
+
+    let mut clusters = vec![QueryCluster { members: vec![] }; centroids.len()];
+    for (index, vector) in vectors.iter().enumerate() {
+        let (cluster, distance) = nearest_centroid(vector, &centroids);
+        clusters[cluster].members.push((index, distance));
+    }
+
+    clusters
+}
+
+async fn label_query_cluster(
+    queries: &[String],
+    dataset_config: &DatasetConfiguration,
+) -> Result<String, ServiceError> {
+    let parameters = ChatCompletionParameters {
+        model: dataset_config.LLM_DEFAULT_MODEL.clone(),
+        messages: vec![ChatMessage::User {
+            content: ChatMessageContent::Text(format!(
+                "Here are search queries users made which are similar to each other:\n\n{}\n\nRespond with a short topic name of 2 to 5 words which describes what these users were looking for. Your only response should be the topic name without quotes, punctuation or any other context.",
+                queries.join("\n")
+            )),
+            name: None,
+        }],
+        stream: Some(false),
+        temperature: Some(0.0),
+        ..Default::default()
+    };
+
+    let llm_endpoints = get_llm_endpoints(dataset_config);
+    let response = call_with_fallbacks(EndpointKind::Llm, &llm_endpoints, |endpoint| {
+        let mut parameters = parameters.clone();
+        async move {
+            if let Some(model_name) = endpoint.model_name.clone() {
+                parameters.model = model_name;
+            }
+
+            endpoint
+                .llm_client()
+                .chat()
+                .create(parameters)
+                .await
+                .map_err(|err| {
+                    EndpointError::from_llm(
+                        &err,
+                        ServiceError::BadRequest(format!(
+                            "Bad response from LLM server provider: {}",
+                            err
+                        )),
+                    )
+                })
+        }
+    })
+    .await?;
+
+    let topic = match response.choices.first().map(|choice| &choice.message) {
+        Some(ChatMessage::Assistant {
+            content: Some(ChatMessageContent::Text(content)),
+            ..
+        }) => content
+            .trim()
+            .trim_matches(|c: char| c == '"' || c == '\'' || c == '.')
+            .to_string(),
+        _ => String::new(),
+    };
+
+    if topic.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "No topic in the response of the LLM".to_string(),
+        ));
+    }
+
+    Ok(topic)
+}
+
+/// Clusters the dataset's searches since `since` by their stored query vectors, labels each cluster with the dataset's LLM, and replaces the dataset's cluster topics and memberships in ClickHouse. Returns the number of clusters written.
+pub async fn cluster_dataset_queries(
+    dataset: &Dataset,
+    since: chrono::NaiveDateTime,
+    clickhouse_client: &clickhouse::Client,
+) -> Result<usize, ServiceError> {
+    let rows = clickhouse_client
+        .query(
+            "SELECT ?fields
+            FROM search_queries
+            WHERE dataset_id = ? AND is_duplicate = 0 AND query != '' AND length(query_vector) > 0 AND created_at >= toDateTime(?)
+            ORDER BY created_at DESC
+            LIMIT ?",
+        )
+        .bind(dataset.id)
+        .bind(since.and_utc().timestamp())
+        .bind(MAX_CLUSTERED_SEARCHES)
+        .fetch_all::<ClusteringSearchRow>()
+        .await
+        .map_err(|e| {
+            log::error!("Error fetching searches to cluster: {:?}", e);
+            ServiceError::InternalServerError("Error fetching searches to cluster".to_string())
+        })?;
+
+    // Searches embedded before a change of the dataset's embedding model have a different size and can not be compared
+    let dimensions = match rows.first() {
+        Some(row) => row.query_vector.len(),
+        None => return Ok(0),
+    };
+    let rows: Vec<ClusteringSearchRow> = rows
+        .into_iter()
+        .filter(|row| row.query_vector.len() == dimensions)
+        .collect();
+
+    if rows.len() < MIN_CLUSTER_SIZE * 2 {
+        return Ok(0);
+    }
+
+    let vectors: Vec<Vec<f32>> = rows
+        .iter()
+        .map(|row| {
+            let mut vector = row.query_vector.clone();
+            normalize(&mut vector);
+            vector
+        })
+        .collect();
+
+    let k = ((rows.len() as f64 / 2.0).sqrt().round() as usize).clamp(2, MAX_CLUSTERS);
+    let mut rng = StdRng::seed_from_u64(dataset.id.as_u128() as u64);
+    let clusters: Vec<QueryCluster> = kmeans(&vectors, k, &mut rng)
+        .into_iter()
+        .filter(|cluster| cluster.members.len() >= MIN_CLUSTER_SIZE)
+        .collect();
+
+    let dataset_config = DatasetConfiguration::from_json(dataset.server_configuration.clone());
+    let created_at = OffsetDateTime::now_utc();
+    let mut topics = vec![];
+    let mut memberships = vec![];
+
+    for mut cluster in clusters {
+        cluster.members.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut seen_queries = HashSet::new();
+        let sample_queries: Vec<String> = cluster
+            .members
+            .iter()
+            .map(|(index, _)| rows[*index].query.trim().to_string())
+            .filter(|query| seen_queries.insert(query.to_lowercase()))
+            .take(LABEL_SAMPLE_SIZE)
+            .collect();
+
+        let topic = match label_query_cluster(&sample_queries, &dataset_config).await {
+            Ok(topic) => topic,
+            Err(err) => {
+                log::error!(
+                    "Failed to label query cluster of dataset {}, falling back to its most central query: {:?}",
+                    dataset.id,
+                    err
+                );
+                sample_queries.first().cloned().unwrap_or_default()
+            }
+        };
+
+        let cluster_id = uuid::Uuid::new_v4();
+        topics.push(ClusterTopicsClickhouse {
+            id: cluster_id,
+            dataset_id: dataset.id,
+            topic,
+            density: cluster.members.len() as i32,
+            avg_score: cluster
+                .members
+                .iter()
+                .map(|(index, _)| rows[*index].top_score)
+                .sum::<f32>()
+                / cluster.members.len() as f32,
+            created_at,
+        });
+        memberships.extend(cluster.members.iter().map(|(index, distance)| {
+            SearchClusterMembershipClickhouse {
+                id: uuid::Uuid::new_v4(),
+                search_id: rows[*index].id,
+                cluster_id,
+                distance_to_centroid: *distance,
+            }
+        }));
+    }
+
+    replace_query_clusters_query(
+        dataset.id,
+        topics.as_slice(),
+        memberships,
+        clickhouse_client,
+    )
+    .await?;
+
+    Ok(topics.len())
+}
+
+async fn replace_query_clusters_query(
+    dataset_id: uuid::Uuid,
+    topics: &[ClusterTopicsClickhouse],
+    memberships: Vec<SearchClusterMembershipClickhouse>,
+    clickhouse_client: &clickhouse::Client,
+) -> Result<(), ServiceError> {
+    // Mutations run asynchronously, so the old cluster ids are read before their topics get deleted
+    let old_cluster_ids = clickhouse_client
+        .query("SELECT ?fields FROM cluster_topics WHERE dataset_id = ?")
+        .bind(dataset_id)
+        .fetch_all::<ClusterIdRow>()
+        .await
+        .map_err(|e| {
+            log::error!("Error fetching cluster topics: {:?}", e);
+            ServiceError::InternalServerError("Error fetching cluster topics".to_string())
+        })?;
+
+    if !old_cluster_ids.is_empty() {
+        clickhouse_client
+            .query(&format!(
+                "ALTER TABLE search_cluster_memberships DELETE WHERE cluster_id IN ({})",
+                old_cluster_ids
+                    .iter()
+                    .map(|cluster| format!("'{}'", cluster.id))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ))
+            .execute()
+            .await
+            .map_err(|e| {
+                log::error!("Error deleting cluster memberships: {:?}", e);
+                ServiceError::InternalServerError("Error deleting cluster memberships".to_string())
+            })?;
+    }
+
+    // Mutations only apply to the parts which exist when they are issued, so the rows inserted below are kept
+    clickhouse_client
+        .query("ALTER TABLE cluster_topics DELETE WHERE dataset_id = ?")
+        .bind(dataset_id)
+        .execute()
+        .await
+        .map_err(|e| {
+            log::error!("Error deleting cluster topics: {:?}", e);
+            ServiceError::InternalServerError("Error deleting cluster topics".to_string())
+        })?;
+
+    let mut topics_inserter = clickhouse_client.insert("cluster_topics").map_err(|e| {
+        log::error!("Error inserting cluster topics: {:?}", e);
+        ServiceError::InternalServerError("Error inserting cluster topics".to_string())
+    })?;
+    for topic in topics {
+        topics_inserter.write(topic).await.map_err(|e| {
+            log::error!("Error writing cluster topic: {:?}", e);
+            ServiceError::InternalServerError("Error writing cluster topic".to_string())
+        })?;
+    }
+    topics_inserter.end().await.map_err(|e| {
+        log::error!("Error ending cluster topics inserter: {:?}", e);
+        ServiceError::InternalServerError("Error ending cluster topics inserter".to_string())
+    })?;
+
+    let mut memberships_inserter = clickhouse_client
+        .insert("search_cluster_memberships")
+        .map_err(|e| {
+            log::error!("Error inserting cluster memberships: {:?}", e);
+            ServiceError::InternalServerError("Error inserting cluster memberships".to_string())
+        })?;
+    for membership in memberships {
+        memberships_inserter.write(&membership).await.map_err(|e| {
+            log::error!("Error writing cluster membership: {:?}", e);
+            ServiceError::InternalServerError("Error writing cluster membership".to_string())
+        })?;
+    }
+    memberships_inserter.end().await.map_err(|e| {
+        log::error!("Error ending cluster memberships inserter: {:?}", e);
+        ServiceError::InternalServerError("Error ending cluster memberships inserter".to_string())
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,436 @@
+use crate::{
+    data::models::{
+        Dataset, DatasetConfiguration, Pool, QueryRedirect, QuerySuggestion, QuerySuggestionStatus,
+        QuerySuggestionType, QuerySynonym, RedisPool,
+    },
+    errors::ServiceError,
+    operators::{
+        dataset_operator::{add_words_to_dataset, update_dataset_query},
+        typo_operator::{levenshtein_distance, CreateBkTreeMessage},
+    },
+};
+use actix_web::web;
+use clickhouse::Row;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+/// Searches by the same user this many seconds apart are treated as a reformulation of one another.
+const REFORMULATION_WINDOW_SECS: u32 = 60;
+/// Reformulated words within this edit distance of each other are suggested as typos rather than synonyms.
+const MAX_TYPO_DISTANCE: usize = 2;
+/// Maximum number of searches read from ClickHouse per mining run.
+const MAX_MINED_SEARCHES: u64 = 200_000;
+/// Count a word accepted from a typo suggestion is added to the typo dictionary with at minimum, so that corrections prefer it over rare words.
+const MIN_TYPO_WORD_COUNT: i32 = 100;
+
+#[derive(Debug, Row, Serialize, Deserialize, Clone)]
+struct MinedSearchRow {
+    user_id: String,
+    query: String,
+    top_score: f32,
+    created_at_unix: u32,
+    clicked: u8,
+}
+
+impl MinedSearchRow {
+    fn normalized_query(&self) -> String {
+        self.query
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .to_lowercase()
+    }
+
+    fn succeeded(&self) -> bool {
+        self.clicked > 0
+    }
+}
+
+/// Classifies the reformulation of a search which found nothing useful into one which got a click.
+fn classify_reformulation(
+    from_query: &str,
+    to_query: &str,
+) -> (QuerySuggestionType, String, String) {
+    let from_words: Vec<&str> = from_query.split_whitespace().collect();
+    let to_words: Vec<&str> = to_query.split_whitespace().collect();
+
+    if from_words.len() == to_words.len() {
+        let changed_words: Vec<(&str, &str)> = from_words
+            .iter()
+            .zip(to_words.iter())
+            .filter(|(from_word, to_word)| from_word != to_word)
+            .map(|(from_word, to_word)| (*from_word, *to_word))
+            .collect();
+
+        if let [(from_word, to_word)] = changed_words.as_slice() {
+            let suggestion_type = if levenshtein_distance(from_word, to_word) <= MAX_TYPO_DISTANCE {
+                QuerySuggestionType::Typo
+            } else {
+                QuerySuggestionType::Synonym
+            };
+
+            return (suggestion_type, from_word.to_string(), to_word.to_string());
+        }
+    }
+
+    (
+        QuerySuggestionType::Redirect,
+        from_query.to_string(),
+        to_query.to_string(),
+    )
+}
+
+/// Mines the dataset's searches and clicks since `since` for queries users reformulated into ones which got a click, and for frequent queries without results which were never successfully reformulated. Returns the suggestions seen at least `min_occurrences` times.
+pub async fn mine_query_suggestions(
+    dataset_id: uuid::Uuid,
+    since: chrono::NaiveDateTime,
+    min_occurrences: i64,
+    clickhouse_client: &clickhouse::Client,
+) -> Result<Vec<QuerySuggestion>, ServiceError> {
+    let since = since.and_utc().timestamp();
+
+    let rows = clickhouse_client
+        .query(
+            "SELECT
+                user_id,
+                query,
+                top_score,
+                toUnixTimestamp(created_at) AS created_at_unix,
+                id IN (
+                    SELECT toUUIDOrZero(request_id)
+                    FROM events
+                    WHERE dataset_id = ? AND event_type = 'click' AND created_at >= toDateTime(?)
+                ) AS clicked
+            FROM search_queries
+            WHERE dataset_id = ? AND user_id != '' AND is_duplicate = 0 AND created_at >= toDateTime(?)
+            ORDER BY user_id, created_at_unix
+            LIMIT ?",
+        )
+        .bind(dataset_id)
+        .bind(since)
+        .bind(dataset_id)
+        .bind(since)
+        .bind(MAX_MINED_SEARCHES)
+        .fetch_all::<MinedSearchRow>()
+        .await
+        .map_err(|e| {
+            log::error!("Error fetching searches to mine: {:?}", e);
+            ServiceError::InternalServerError("Error fetching searches to mine".to_string())
+        })?;
+
+    let mut counts: HashMap<(QuerySuggestionType, String, String), i64> = HashMap::new();
+    let mut reformulated_queries: HashSet<String> = HashSet::new();
+    let mut no_result_counts: HashMap<String, i64> = HashMap::new();
+
+    for (index, row) in rows.iter().enumerate() {
+        let query = row.normalized_query();
+        if query.is_empty() {
+            continue;
+        }
+
+        if row.top_score == 0.0 {
+            *no_result_counts.entry(query.clone()).or_insert(0) += 1;
+        }
+
+        if row.succeeded() {
+            continue;
+        }
+
+        let Some(next_row) = rows.get(index + 1) else {
+            continue;
+        };
+        if next_row.user_id != row.user_id
+            || next_row.created_at_unix.saturating_sub(row.created_at_unix)
+                > REFORMULATION_WINDOW_SECS
+            || !next_row.succeeded()
+        {
+            continue;
+        }
+
+        let next_query = next_row.normalized_query();
+        if next_query.is_empty() || next_query == query {
+            continue;
+        }
+
+        reformulated_queries.insert(query.clone());
+        *counts
+            .entry(classify_reformulation(&query, &next_query))
+            .or_insert(0) += 1;
+    }
+
+    for (query, count) in no_result_counts {
+        if !reformulated_queries.contains(&query) {
+            counts.insert(
+                (QuerySuggestionType::ContentGap, query, String::new()),
+                count,
+            );
+        }
+    }
+
+    Ok(counts
+        .into_iter()
+        .filter(|(_, occurrences)| *occurrences >= min_occurrences)
+        .map(|((suggestion_type, query, suggestion), occurrences)| {
+            QuerySuggestion::from_details(
+                dataset_id,
+                suggestion_type,
+                query,
+                suggestion,
+                occurrences,
+            )
+        })
+        .collect())
+}
+
+/// Adds newly mined suggestions to the review queue. Suggestions already in the queue have their occurrences increased and keep their status, so dismissed suggestions are not proposed again.
+pub async fn upsert_query_suggestions_query(
+    suggestions: Vec<QuerySuggestion>,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::query_suggestions::dsl as query_suggestions_columns;
+
+    if suggestions.is_empty() {
+        return Ok(());
+    }
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::insert_into(query_suggestions_columns::query_suggestions)
+        .values(&suggestions)
+        .on_conflict((
+            query_suggestions_columns::dataset_id,
+            query_suggestions_columns::suggestion_type,
+            query_suggestions_columns::query,
+            query_suggestions_columns::suggestion,
+        ))
+        .do_update()
+        .set((
+            query_suggestions_columns::occurrences.eq(query_suggestions_columns::occurrences
+                + excluded(query_suggestions_columns::occurrences)),
+            query_suggestions_columns::updated_at.eq(diesel::dsl::now),
+        ))
+        .execute(&mut conn)
+        .await
+        .map_err(|e| {
+            log::error!("Error upserting query suggestions: {:?}", e);
+            ServiceError::BadRequest("Error upserting query suggestions".to_string())
+        })?;
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct QuerySuggestionsResponse {
+    pub suggestions: Vec<QuerySuggestion>,
+    pub total_pages: i64,
+}
+
+/// Gets the dataset's suggestions with the most occurrences first.
+pub async fn get_query_suggestions_query(
+    dataset_id: uuid::Uuid,
+    status: QuerySuggestionStatus,
+    suggestion_type: Option<QuerySuggestionType>,
+    page: u64,
+    pool: &web::Data<Pool>,
+) -> Result<QuerySuggestionsResponse, ServiceError> {
+    use crate::data::schema::query_suggestions::dsl as query_suggestions_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let page = page.max(1);
+
+    let mut count_query = query_suggestions_columns::query_suggestions
+        .filter(query_suggestions_columns::dataset_id.eq(dataset_id))
+        .filter(query_suggestions_columns::status.eq(status.to_string()))
+        .into_boxed();
+    let mut suggestions_query = query_suggestions_columns::query_suggestions
+        .filter(query_suggestions_columns::dataset_id.eq(dataset_id))
+        .filter(query_suggestions_columns::status.eq(status.to_string()))
+        .into_boxed();
+
+    if let Some(suggestion_type) = suggestion_type {
+        count_query = count_query
+            .filter(query_suggestions_columns::suggestion_type.eq(suggestion_type.to_string()));
+        suggestions_query = suggestions_query
+            .filter(query_suggestions_columns::suggestion_type.eq(suggestion_type.to_string()));
+    }
+
+    let total_suggestions = count_query
+        .count()
+        .get_result::<i64>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting query suggestions".to_string()))?;
+
+    let suggestions = suggestions_query
+        .order_by((
+            query_suggestions_columns::occurrences.desc(),
+            query_suggestions_columns::id,
+        ))
+        .offset(((page - 1) * 10) as i64)
+        .limit(10)
+        .select(QuerySuggestion::as_select())
+        .load::<QuerySuggestion>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting query suggestions".to_string()))?;
+
+    Ok(QuerySuggestionsResponse {
+        suggestions,
+        total_pages: (total_suggestions as f64 / 10.0).ceil() as i64,
+    })
+}
+
+pub async fn get_query_suggestion_by_id_query(
+    suggestion_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<QuerySuggestion, ServiceError> {
+    use crate::data::schema::query_suggestions::dsl as query_suggestions_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    query_suggestions_columns::query_suggestions
+        .filter(query_suggestions_columns::id.eq(suggestion_id))
+        .filter(query_suggestions_columns::dataset_id.eq(dataset_id))
+        .select(QuerySuggestion::as_select())
+        .first::<QuerySuggestion>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::NotFound("Query suggestion not found".to_string()))
+}
+
+pub async fn set_query_suggestion_status_query(
+    suggestion_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    status: QuerySuggestionStatus,
+    pool: &web::Data<Pool>,
+) -> Result<QuerySuggestion, ServiceError> {
+    use crate::data::schema::query_suggestions::dsl as query_suggestions_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::update(
+        query_suggestions_columns::query_suggestions
+            .filter(query_suggestions_columns::id.eq(suggestion_id))
+            .filter(query_suggestions_columns::dataset_id.eq(dataset_id)),
+    )
+    .set((
+        query_suggestions_columns::status.eq(status.to_string()),
+        query_suggestions_columns::updated_at.eq(diesel::dsl::now),
+    ))
+    .get_result::<QuerySuggestion>(&mut conn)
+    .await
+    .map_err(|_| ServiceError::NotFound("Query suggestion not found".to_string()))
+}
+
+/// Applies a pending suggestion and marks it accepted. Synonyms and redirects are added to the dataset's QUERY_REWRITES, in which case the updated dataset is returned. Typos add the suggested word to the dataset's typo dictionary and queue a rebuild of it. Content gaps are only marked accepted.
+pub async fn accept_query_suggestion_query(
+    suggestion_id: uuid::Uuid,
+    dataset: Dataset,
+    pool: &web::Data<Pool>,
+    redis_pool: &web::Data<RedisPool>,
+    clickhouse_client: &clickhouse::Client,
+) -> Result<(QuerySuggestion, Option<Dataset>), ServiceError> {
+    let suggestion = get_query_suggestion_by_id_query(suggestion_id, dataset.id, pool).await?;
+
+    if suggestion.status != QuerySuggestionStatus::Pending.to_string() {
+        return Err(ServiceError::BadRequest(format!(
+            "Query suggestion is already {}",
+            suggestion.status
+        )));
+    }
+
+    let suggestion_type = QuerySuggestionType::from_name(&suggestion.suggestion_type).ok_or(
+        ServiceError::BadRequest("Unknown query suggestion type".to_string()),
+    )?;
+
+    let updated_dataset = match suggestion_type {
+        QuerySuggestionType::Synonym | QuerySuggestionType::Redirect => {
+            let mut config = DatasetConfiguration::from_json(dataset.server_configuration.clone());
+
+            if suggestion_type == QuerySuggestionType::Synonym {
+                match config
+                    .QUERY_REWRITES
+                    .synonyms
+                    .iter_mut()
+                    .find(|synonym| synonym.term.to_lowercase() == suggestion.query)
+                {
+                    Some(synonym) => {
+                        if !synonym.synonyms.contains(&suggestion.suggestion) {
+                            synonym.synonyms.push(suggestion.suggestion.clone());
+                        }
+                    }
+                    None => config.QUERY_REWRITES.synonyms.push(QuerySynonym {
+                        term: suggestion.query.clone(),
+                        synonyms: vec![suggestion.suggestion.clone()],
+                    }),
+                }
+            } else {
+                config
+                    .QUERY_REWRITES
+                    .redirects
+                    .retain(|redirect| redirect.query.to_lowercase() != suggestion.query);
+                config.QUERY_REWRITES.redirects.push(QueryRedirect {
+                    query: suggestion.query.clone(),
+                    redirect_to: suggestion.suggestion.clone(),
+                });
+            }
+
+            Some(update_dataset_query(dataset.id, dataset.name, config, None, pool.clone()).await?)
+        }
+        QuerySuggestionType::Typo => {
+            add_words_to_dataset(
+                vec![suggestion.suggestion.clone()],
+                vec![(suggestion.occurrences as i32).max(MIN_TYPO_WORD_COUNT)],
+                vec![dataset.id],
+                clickhouse_client,
+            )
+            .await?;
+
+            let create_tree_msg = serde_json::to_string(&CreateBkTreeMessage {
+                dataset_id: dataset.id,
+                attempt_number: 0,
+            })
+            .map_err(|_| {
+                ServiceError::InternalServerError("Failed to serialize message".to_string())
+            })?;
+
+            let mut redis_conn = redis_pool
+                .get()
+                .await
+                .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+            redis::cmd("SADD")
+                .arg("bktree_creation")
+                .arg(create_tree_msg)
+                .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
+                .await
+                .map_err(|_| {
+                    ServiceError::InternalServerError("Failed to send message to redis".to_string())
+                })?;
+
+            None
+        }
+        QuerySuggestionType::ContentGap => None,
+    };
+
+    let suggestion = set_query_suggestion_status_query(
+        suggestion.id,
+        suggestion.dataset_id,
+        QuerySuggestionStatus::Accepted,
+        pool,
+    )
+    .await?;
+
+    Ok((suggestion, updated_dataset))
+}
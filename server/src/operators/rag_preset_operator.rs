@@ -0,0 +1,190 @@
+use crate::data::models::{DatasetConfiguration, Pool, RagPreset, RagPresetOptions, RagPresetPG};
+use crate::handlers::message_handler::CreateMessageReqPayload;
+use crate::operators::topic_operator::get_topic_query;
+use crate::{diesel::prelude::*, errors::ServiceError};
+use actix_web::web;
+use diesel_async::RunQueryDsl;
+
+pub async fn create_rag_preset_query(
+    preset: RagPreset,
+    pool: &web::Data<Pool>,
+) -> Result<RagPreset, ServiceError> {
+    use crate::data::schema::rag_presets::dsl as rag_presets_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let created_preset: RagPresetPG = diesel::insert_into(rag_presets_columns::rag_presets)
+        .values(RagPresetPG::from(preset))
+        .get_result(&mut conn)
+        .await
+        .map_err(|err| match err {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => ServiceError::BadRequest(
+                "A RAG preset with the same name already exists in the dataset".to_string(),
+            ),
+            _ => ServiceError::BadRequest("Error creating RAG preset, try again".to_string()),
+        })?;
+
+    Ok(created_preset.into())
+}
+
+pub async fn get_rag_preset_by_name_query(
+    preset_name: String,
+    given_dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<RagPreset, ServiceError> {
+    use crate::data::schema::rag_presets::dsl as rag_presets_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let preset: RagPresetPG = rag_presets_columns::rag_presets
+        .filter(rag_presets_columns::dataset_id.eq(given_dataset_id))
+        .filter(rag_presets_columns::name.eq(&preset_name))
+        .select(RagPresetPG::as_select())
+        .first(&mut conn)
+        .await
+        .map_err(|_db_error| {
+            ServiceError::NotFound(format!(
+                "RAG preset {} does not exist in the dataset",
+                preset_name
+            ))
+        })?;
+
+    Ok(preset.into())
+}
+
+pub async fn get_rag_presets_for_dataset_query(
+    given_dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<Vec<RagPreset>, ServiceError> {
+    use crate::data::schema::rag_presets::dsl as rag_presets_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let presets: Vec<RagPresetPG> = rag_presets_columns::rag_presets
+        .filter(rag_presets_columns::dataset_id.eq(given_dataset_id))
+        .order(rag_presets_columns::name.asc())
+        .select(RagPresetPG::as_select())
+        .load(&mut conn)
+        .await
+        .map_err(|_db_error| {
+            ServiceError::BadRequest("Error getting RAG presets for the dataset".to_string())
+        })?;
+
+    Ok(presets.into_iter().map(RagPreset::from).collect())
+}
+
+pub async fn update_rag_preset_query(
+    preset_name: String,
+    new_options: RagPresetOptions,
+    given_dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<RagPreset, ServiceError> {
+    use crate::data::schema::rag_presets::dsl as rag_presets_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let options_json = serde_json::to_value(new_options).map_err(|_| {
+        ServiceError::BadRequest("Failed to serialize RAG preset options".to_string())
+    })?;
+
+    let updated_preset: RagPresetPG = diesel::update(
+        rag_presets_columns::rag_presets
+            .filter(rag_presets_columns::dataset_id.eq(given_dataset_id))
+            .filter(rag_presets_columns::name.eq(&preset_name)),
+    )
+    .set((
+        rag_presets_columns::options.eq(options_json),
+        rag_presets_columns::updated_at.eq(diesel::dsl::now),
+    ))
+    .get_result(&mut conn)
+    .await
+    .map_err(|err| match err {
+        diesel::result::Error::NotFound => ServiceError::NotFound(format!(
+            "RAG preset {} does not exist in the dataset",
+            preset_name
+        )),
+        _ => ServiceError::BadRequest("Error updating RAG preset, try again".to_string()),
+    })?;
+
+    Ok(updated_preset.into())
+}
+
+pub async fn delete_rag_preset_query(
+    preset_name: String,
+    given_dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::rag_presets::dsl as rag_presets_columns;
+    use crate::data::schema::topics::dsl as topics_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let deleted_count = diesel::delete(
+        rag_presets_columns::rag_presets
+            .filter(rag_presets_columns::dataset_id.eq(given_dataset_id))
+            .filter(rag_presets_columns::name.eq(&preset_name)),
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(|_db_error| {
+        ServiceError::BadRequest("Error deleting RAG preset, try again".to_string())
+    })?;
+
+    if deleted_count == 0 {
+        return Err(ServiceError::NotFound(format!(
+            "RAG preset {} does not exist in the dataset",
+            preset_name
+        )));
+    }
+
+    diesel::update(
+        topics_columns::topics
+            .filter(topics_columns::dataset_id.eq(given_dataset_id))
+            .filter(topics_columns::rag_preset.eq(&preset_name)),
+    )
+    .set(topics_columns::rag_preset.eq(None::<String>))
+    .execute(&mut conn)
+    .await
+    .map_err(|_db_error| {
+        ServiceError::BadRequest("Error detaching RAG preset from topics".to_string())
+    })?;
+
+    Ok(())
+}
+
+/// Resolves the preset named on the request, falling back to the one attached to the topic, and layers it onto the dataset configuration and message payload.
+pub async fn apply_rag_preset_to_message(
+    create_message_data: &mut CreateMessageReqPayload,
+    dataset_config: &mut DatasetConfiguration,
+    given_dataset_id: uuid::Uuid,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    let preset_name = match create_message_data.rag_preset.clone() {
+        Some(preset_name) => Some(preset_name),
+        None => {
+            get_topic_query(create_message_data.topic_id, given_dataset_id, pool)
+                .await?
+                .rag_preset
+        }
+    };
+
+    if let Some(preset_name) = preset_name {
+        let preset = get_rag_preset_by_name_query(preset_name, given_dataset_id, pool).await?;
+        preset.options.apply(dataset_config, create_message_data);
+    }
+
+    Ok(())
+}
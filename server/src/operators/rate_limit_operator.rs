@@ -0,0 +1,180 @@
+use crate::{
+    data::models::{ApiOperation, RateLimit, RateLimits, RedisPool},
+    errors::ServiceError,
+};
+use derive_more::Display;
+use once_cell::sync::Lazy;
+
+/// Refills every bucket and only takes a token from each of them if all of them have one left, so a request rejected by one scope does not use up the others. Returns 0 when the request is allowed, otherwise the number of seconds until it would be.
+///
+/// KEYS are the bucket keys and ARGV is the current time in milliseconds followed by the capacity and full refill time in milliseconds of each bucket.
+static TOKEN_BUCKET_SCRIPT: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r#"
+local now = tonumber(ARGV[1])
+local retry_after = 0
+local remaining = {}
+for i, key in ipairs(KEYS) do
+    local capacity = tonumber(ARGV[i * 2])
+    local refill_ms = tonumber(ARGV[i * 2 + 1])
+    local bucket = redis.call('HMGET', key, 'tokens', 'ts')
+    local tokens = tonumber(bucket[1]) or capacity
+    local ts = tonumber(bucket[2]) or now
+    tokens = math.min(capacity, tokens + math.max(0, now - ts) * capacity / refill_ms)
+    if tokens < 1 then
+        retry_after = math.max(retry_after, math.ceil((1 - tokens) * refill_ms / capacity / 1000))
+    end
+    remaining[i] = tokens
+end
+if retry_after > 0 then
+    return retry_after
+end
+for i, key in ipairs(KEYS) do
+    redis.call('HSET', key, 'tokens', tostring(remaining[i] - 1), 'ts', now)
+    redis.call('PEXPIRE', key, tonumber(ARGV[i * 2 + 1]))
+end
+return 0
+"#,
+    )
+});
+
+static ORGANIZATION_RATE_LIMITS: Lazy<RateLimits> = Lazy::new(|| RateLimits {
+    search: RateLimit::from_env("ORGANIZATION_SEARCH_RATE_LIMIT"),
+    rag: RateLimit::from_env("ORGANIZATION_RAG_RATE_LIMIT"),
+    ingest: RateLimit::from_env("ORGANIZATION_INGEST_RATE_LIMIT"),
+});
+
+/// Scope a token bucket is kept for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum RateLimitScope {
+    #[display(fmt = "api_key:{}", _0)]
+    ApiKey(uuid::Uuid),
+    #[display(fmt = "dataset:{}", _0)]
+    Dataset(uuid::Uuid),
+    #[display(fmt = "organization:{}", _0)]
+    Organization(uuid::Uuid),
+}
+
+/// Limits every organization gets, configured with the `ORGANIZATION_{SEARCH,RAG,INGEST}_RATE_LIMIT` environment variables in the `requests/per_seconds` format.
+pub fn get_organization_rate_limits() -> RateLimits {
+    *ORGANIZATION_RATE_LIMITS
+}
+
+/// Builds the KEYS and ARGV of the token bucket script for the operation's buckets of each scope.
+fn get_token_bucket_keys_and_args(
+    operation: ApiOperation,
+    limits: &[(RateLimitScope, RateLimit)],
+    now_ms: i64,
+) -> (Vec<String>, Vec<i64>) {
+    let mut keys = Vec::with_capacity(limits.len());
+    let mut args = Vec::with_capacity(limits.len() * 2 + 1);
+    args.push(now_ms);
+    for (scope, limit) in limits.iter() {
+        keys.push(format!("rate_limit:{}:{}", operation, scope));
+        args.push(i64::from(limit.requests.max(1)));
+        args.push(i64::from(limit.per_seconds.max(1)) * 1000);
+    }
+
+    (keys, args)
+}
+
+/// Takes a token from the operation's bucket for each scope which has a limit and returns a `TooManyRequests` error with the seconds to wait when any of them is empty.
+pub async fn check_rate_limits(
+    operation: ApiOperation,
+    limits: Vec<(RateLimitScope, RateLimit)>,
+    redis_pool: &RedisPool,
+) -> Result<(), ServiceError> {
+    if limits.is_empty() {
+        return Ok(());
+    }
+
+    let (keys, args) =
+        get_token_bucket_keys_and_args(operation, &limits, chrono::Utc::now().timestamp_millis());
+    let mut invocation = TOKEN_BUCKET_SCRIPT.prepare_invoke();
+    for key in keys {
+        invocation.key(key);
+    }
+    for arg in args {
+        invocation.arg(arg);
+    }
+
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    let retry_after = invocation
+        .invoke_async::<redis::aio::MultiplexedConnection, u64>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    if retry_after > 0 {
+        return Err(ServiceError::TooManyRequests(
+            format!(
+                "Rate limit exceeded for {} requests, retry after {} seconds",
+                operation, retry_after
+            ),
+            retry_after,
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_token_bucket_keys_and_args() {
+        let api_key_id = uuid::Uuid::new_v4();
+        let dataset_id = uuid::Uuid::new_v4();
+        let limits = vec![
+            (
+                RateLimitScope::ApiKey(api_key_id),
+                RateLimit {
+                    requests: 600,
+                    per_seconds: 60,
+                },
+            ),
+            (
+                RateLimitScope::Dataset(dataset_id),
+                RateLimit {
+                    requests: 10,
+                    per_seconds: 1,
+                },
+            ),
+        ];
+
+        let (keys, args) = get_token_bucket_keys_and_args(ApiOperation::Search, &limits, 1_000);
+
+        assert_eq!(
+            keys,
+            vec![
+                format!("rate_limit:search:api_key:{}", api_key_id),
+                format!("rate_limit:search:dataset:{}", dataset_id),
+            ]
+        );
+        assert_eq!(args, vec![1_000, 600, 60_000, 10, 1_000]);
+    }
+
+    #[test]
+    pub fn test_token_bucket_args_clamp_zero_limits() {
+        let organization_id = uuid::Uuid::new_v4();
+        let limits = vec![(
+            RateLimitScope::Organization(organization_id),
+            RateLimit {
+                requests: 0,
+                per_seconds: 0,
+            },
+        )];
+
+        let (keys, args) = get_token_bucket_keys_and_args(ApiOperation::Rag, &limits, 5);
+
+        assert_eq!(
+            keys,
+            vec![format!("rate_limit:rag:organization:{}", organization_id)]
+        );
+        assert_eq!(args, vec![5, 1, 1_000]);
+    }
+}
@@ -18,9 +18,9 @@ use super::typo_operator::correct_query;
 use crate::data::models::{
     convert_to_date_time, ChunkGroup, ChunkGroupAndFileId, ChunkMetadataStringTagSet,
     ChunkMetadataTypes, ConditionType, Dataset, DatasetConfiguration, HasChunkIDCondition,
-    MmrOptions, QdrantChunkMetadata, QdrantSortBy, QueryTypes, ReRankOptions, RedisPool,
-    ScoreChunk, ScoreChunkDTO, SearchMethod, SearchModalities, SlimChunkMetadata, SortByField,
-    SortBySearchType, SortOptions, UnifiedId,
+    MmrOptions, QdrantChunkMetadata, QdrantSortBy, QueryRewrites, QueryTypes, ReRankOptions,
+    RedisPool, ScoreChunk, ScoreChunkDTO, SearchMethod, SearchModalities, SlimChunkMetadata,
+    SortByField, SortBySearchType, SortOptions, UnifiedId,
 };
 use crate::handlers::chunk_handler::{
     AutocompleteReqPayload, ChunkFilter, CountChunkQueryResponseBody, CountChunksReqPayload,
@@ -29,6 +29,7 @@ use crate::handlers::chunk_handler::{
 use crate::handlers::group_handler::{
     SearchOverGroupsReqPayload, SearchWithinGroupReqPayload, SearchWithinGroupResults,
 };
+use crate::operators::engagement_operator::{get_engagement_boosts, EngagementBoosts};
 use crate::operators::qdrant_operator::search_qdrant_query;
 use crate::{
     data::models::{get_range, FieldCondition, MatchCondition, Pool},
@@ -229,6 +230,8 @@ pub async fn assemble_qdrant_filter(
         .must
         .push(Condition::matches("dataset_id", dataset_id.to_string()));
 
+    filter.must_not.push(Condition::matches("hidden", true));
+
     if let Some(filters) = filters {
         if let Some(should_filters) = filters.should {
             for should_condition in should_filters {
@@ -1232,6 +1235,8 @@ pub struct GroupScoreChunk {
     pub group_metadata: Option<serde_json::Value>,
     pub group_tag_set: Option<Vec<Option<String>>>,
     pub group_dataset_id: uuid::Uuid,
+    #[serde(skip)]
+    pub group_acl: Option<Vec<Option<String>>>,
     pub metadata: Vec<ScoreChunkDTO>,
     pub file_id: Option<uuid::Uuid>,
 }
@@ -1257,6 +1262,7 @@ impl From<GroupScoreChunk> for SearchOverGroupsResults {
                 tracking_id: val.group_tracking_id,
                 metadata: val.group_metadata,
                 tag_set: val.group_tag_set,
+                acl: val.group_acl,
             },
             chunks: val
                 .metadata
@@ -1419,6 +1425,7 @@ pub async fn retrieve_chunks_for_groups(
                         metadata: vec![chunk],
                         highlights,
                         score: search_result.score.into(),
+                        explain: None,
                     })
                 })
                 .sorted_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal))
@@ -1436,6 +1443,7 @@ pub async fn retrieve_chunks_for_groups(
                 group_metadata: group_data.and_then(|group| group.metadata.clone()),
                 group_tag_set: group_data.and_then(|group| group.tag_set.clone()),
                 group_dataset_id: group_data.map(|group| group.dataset_id).unwrap_or_default(),
+                group_acl: group_data.and_then(|group| group.acl.clone()),
                 metadata: score_chunks,
                 file_id: group_data.and_then(|group| group.file_id),
             }
@@ -1501,6 +1509,7 @@ pub async fn get_metadata_from_groups(
                         metadata: vec![chunk],
                         highlights: None,
                         score: search_result.score.into(),
+                        explain: None,
                     })
                 })
                 .collect_vec();
@@ -1517,6 +1526,7 @@ pub async fn get_metadata_from_groups(
                 group_metadata: group_data.and_then(|group| group.metadata.clone()),
                 group_tag_set: group_data.and_then(|group| group.tag_set.clone()),
                 group_dataset_id: group_data.map(|group| group.dataset_id).unwrap_or_default(),
+                group_acl: group_data.and_then(|group| group.acl.clone()),
                 metadata: score_chunk,
                 file_id: group_data.and_then(|group| group.file_id),
             }
@@ -1675,6 +1685,7 @@ pub async fn retrieve_chunks_from_point_ids(
             metadata: vec![chunk],
             highlights,
             score: search_result.score.into(),
+            explain: None,
         })
     }
 
@@ -1729,6 +1740,13 @@ pub async fn parse_query(
         SearchModalities::Audio { audio_base64 } => get_text_from_audio(&audio_base64).await?,
     };
 
+    let query_rewrites =
+        QueryRewrites::from_json(dataset.server_configuration.get("QUERY_REWRITES"));
+    let query = match query_rewrites.is_empty() {
+        true => query,
+        false => query_rewrites.apply(&query),
+    };
+
     let query = match remove_stop_words {
         Some(true) => {
             let mut query_parts_split_by_stop_words: Vec<String> = Vec::new();
@@ -1798,12 +1816,26 @@ pub fn rerank_chunks(
     chunks: Vec<ScoreChunkDTO>,
     search_results: Vec<SearchResult>,
     sort_options: Option<SortOptions>,
+    engagement_boosts: Option<&EngagementBoosts>,
 ) -> Vec<ScoreChunkDTO> {
     let mut reranked_chunks = Vec::new();
 
     let sort_options = match sort_options {
         Some(options) => options,
-        None => return chunks,
+        None => {
+            return match engagement_boosts {
+                Some(engagement_boosts) => {
+                    let mut boosted_chunks = engagement_boosts.apply(chunks, false);
+                    boosted_chunks.sort_by(|a, b| {
+                        b.score
+                            .partial_cmp(&a.score)
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    });
+                    boosted_chunks
+                }
+                None => chunks,
+            }
+        }
     };
 
     if sort_options.use_weights.unwrap_or(true) {
@@ -1958,6 +1990,11 @@ pub fn rerank_chunks(
             .collect::<Vec<ScoreChunkDTO>>();
     }
 
+    if let Some(engagement_boosts) = engagement_boosts {
+        reranked_chunks =
+            engagement_boosts.apply(reranked_chunks, sort_options.explain.unwrap_or(false));
+    }
+
     reranked_chunks.sort_by(|a, b| {
         b.score
             .partial_cmp(&a.score)
@@ -2284,7 +2321,7 @@ pub async fn search_chunks_query(
         match parsed_query {
             ParsedQueryTypes::Single(ref mut query) => {
                 let typo_corrected_query =
-                    correct_query(query.clone(), dataset.id, redis_pool, options).await?;
+                    correct_query(query.clone(), dataset.id, redis_pool.clone(), options).await?;
                 if typo_corrected_query.corrected {
                     corrected_query.clone_from(&typo_corrected_query.query);
                 }
@@ -2388,10 +2425,24 @@ pub async fn search_chunks_query(
         result_chunks.score_chunks
     };
 
+    let engagement_boosts = get_engagement_boosts(
+        dataset.id,
+        &parsed_query
+            .to_parsed_query()
+            .map(|parsed_query| parsed_query.query)
+            .unwrap_or_default(),
+        &rerank_chunks_input,
+        config,
+        data.sort_options.as_ref(),
+        &redis_pool,
+    )
+    .await;
+
     result_chunks.score_chunks = rerank_chunks(
         rerank_chunks_input,
         search_chunk_query_results.search_results,
         data.sort_options,
+        engagement_boosts.as_ref(),
     );
     result_chunks
         .score_chunks
@@ -2420,8 +2471,13 @@ pub async fn search_hybrid_chunks(
 
     if let Some(options) = &data.typo_options {
         timer.add("start correcting query");
-        let typo_corrected_query =
-            correct_query(parsed_query.clone(), dataset.id, redis_pool, options).await?;
+        let typo_corrected_query = correct_query(
+            parsed_query.clone(),
+            dataset.id,
+            redis_pool.clone(),
+            options,
+        )
+        .await?;
         if typo_corrected_query.corrected {
             corrected_query.clone_from(&typo_corrected_query.query);
         }
@@ -2542,10 +2598,21 @@ pub async fn search_hybrid_chunks(
                 cross_encoder_results.retain(|chunk| chunk.score >= score_threshold.into());
             }
 
+            let engagement_boosts = get_engagement_boosts(
+                dataset.id,
+                &parsed_query.query,
+                &cross_encoder_results,
+                config,
+                data.sort_options.as_ref(),
+                &redis_pool,
+            )
+            .await;
+
             rerank_chunks(
                 cross_encoder_results,
                 search_chunk_query_results.search_results,
                 data.sort_options,
+                engagement_boosts.as_ref(),
             )
         };
 
@@ -2580,6 +2647,7 @@ pub async fn search_hybrid_chunks(
                     .collect(),
                 highlights: score_chunk.highlights,
                 score: score_chunk.score,
+                explain: score_chunk.explain,
             })
             .collect();
     }
@@ -2699,10 +2767,24 @@ pub async fn search_groups_query(
         result_chunks.score_chunks
     };
 
+    let engagement_boosts = get_engagement_boosts(
+        dataset.id,
+        &parsed_query
+            .to_parsed_query()
+            .map(|parsed_query| parsed_query.query)
+            .unwrap_or_default(),
+        &rerank_chunks_input,
+        config,
+        data.sort_options.as_ref(),
+        &redis_pool,
+    )
+    .await;
+
     result_chunks.score_chunks = rerank_chunks(
         rerank_chunks_input,
         search_semantic_chunk_query_results.search_results,
         data.sort_options,
+        engagement_boosts.as_ref(),
     );
     result_chunks
         .score_chunks
@@ -2735,8 +2817,13 @@ pub async fn search_hybrid_groups(
 
     if let Some(options) = &data.typo_options {
         timer.add("start correcting query");
-        let typo_corrected_query =
-            correct_query(parsed_query.clone(), dataset.id, redis_pool, options).await?;
+        let typo_corrected_query = correct_query(
+            parsed_query.clone(),
+            dataset.id,
+            redis_pool.clone(),
+            options,
+        )
+        .await?;
         if typo_corrected_query.corrected {
             corrected_query.clone_from(&typo_corrected_query.query);
         }
@@ -2848,10 +2935,20 @@ pub async fn search_hybrid_groups(
                 config,
             )
             .await?;
+            let engagement_boosts = get_engagement_boosts(
+                dataset.id,
+                &parsed_query.query,
+                &cross_encoder_results,
+                config,
+                data.sort_options.as_ref(),
+                &redis_pool,
+            )
+            .await;
             let mut score_chunks: Vec<ScoreChunkDTO> = rerank_chunks(
                 cross_encoder_results,
                 qdrant_results.search_results,
                 data.sort_options,
+                engagement_boosts.as_ref(),
             );
             score_chunks.truncate(data.page_size.unwrap_or(10) as usize);
 
@@ -2869,10 +2966,21 @@ pub async fn search_hybrid_groups(
             )
             .await?;
 
+            let engagement_boosts = get_engagement_boosts(
+                dataset.id,
+                &parsed_query.query,
+                &cross_encoder_results,
+                config,
+                data.sort_options.as_ref(),
+                &redis_pool,
+            )
+            .await;
+
             let mut score_chunks: Vec<ScoreChunkDTO> = rerank_chunks(
                 cross_encoder_results,
                 qdrant_results.search_results,
                 data.sort_options,
+                engagement_boosts.as_ref(),
             );
             score_chunks.truncate(data.page_size.unwrap_or(10) as usize);
             score_chunks
@@ -3245,8 +3353,13 @@ pub async fn autocomplete_chunks_query(
 
     if let Some(options) = &data.typo_options {
         timer.add("start correcting query");
-        let typo_corrected_query =
-            correct_query(parsed_query.clone(), dataset.id, redis_pool, options).await?;
+        let typo_corrected_query = correct_query(
+            parsed_query.clone(),
+            dataset.id,
+            redis_pool.clone(),
+            options,
+        )
+        .await?;
         if typo_corrected_query.corrected {
             corrected_query.clone_from(&typo_corrected_query.query);
         }
@@ -3360,15 +3473,27 @@ pub async fn autocomplete_chunks_query(
         (result_chunks.score_chunks.as_slice(), empty_vec)
     };
 
+    let engagement_boosts = get_engagement_boosts(
+        dataset.id,
+        &parsed_query.query,
+        &result_chunks.score_chunks,
+        config,
+        data.sort_options.as_ref(),
+        &redis_pool,
+    )
+    .await;
+
     let mut reranked_chunks = rerank_chunks(
         before_increase.to_vec(),
         search_chunk_query_results.search_results.clone(),
         data.sort_options.clone(),
+        engagement_boosts.as_ref(),
     );
     reranked_chunks.extend(rerank_chunks(
         after_increase.to_vec(),
         search_chunk_query_results.search_results,
         data.sort_options,
+        engagement_boosts.as_ref(),
     ));
     reranked_chunks.truncate(data.page_size.unwrap_or(10) as usize);
 
@@ -0,0 +1,199 @@
+use crate::{
+    data::models::{ApiKeyRequestParams, Pool, SlimUser, UserApiKey, UserOrganization, UserRole},
+    errors::ServiceError,
+    operators::organization_operator::get_org_from_id_query,
+};
+use actix_web::web;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+/// Prefix which tells search tokens apart from api keys in the Authorization header.
+pub const SEARCH_TOKEN_PREFIX: &str = "tr-st.";
+
+/// Key search tokens are signed with. Search tokens are disabled when `SEARCH_TOKEN_SECRET` is missing or empty so they can never be signed with a guessable key.
+static SEARCH_TOKEN_KEY: Lazy<Option<[u8; 32]>> = Lazy::new(|| {
+    std::env::var("SEARCH_TOKEN_SECRET")
+        .ok()
+        .filter(|secret| !secret.trim().is_empty())
+        .map(|secret| blake3::derive_key("trieve search token v1", secret.as_bytes()))
+});
+
+/// Contents of a search token. The token is the base64 encoded claims followed by their keyed blake3 hash, so it can be verified without a database lookup.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchTokenClaims {
+    /// Unique id of the token, used as the key for its rate limits.
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub organization_id: uuid::Uuid,
+    /// Unix timestamp in seconds after which the token is rejected.
+    pub exp: i64,
+    pub params: ApiKeyRequestParams,
+}
+
+fn sign_search_token_payload(key: &[u8; 32], payload: &str) -> blake3::Hash {
+    blake3::keyed_hash(key, payload.as_bytes())
+}
+
+pub fn encode_search_token(claims: &SearchTokenClaims) -> Result<String, ServiceError> {
+    let key = SEARCH_TOKEN_KEY.as_ref().ok_or(ServiceError::BadRequest(
+        "Search tokens are disabled because SEARCH_TOKEN_SECRET is not set on the server"
+            .to_string(),
+    ))?;
+
+    encode_search_token_with_key(key, claims)
+}
+
+fn encode_search_token_with_key(
+    key: &[u8; 32],
+    claims: &SearchTokenClaims,
+) -> Result<String, ServiceError> {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).map_err(|_| {
+        ServiceError::InternalServerError("Failed to serialize search token".to_string())
+    })?);
+    let signature = sign_search_token_payload(key, &payload);
+
+    Ok(format!(
+        "{}{}.{}",
+        SEARCH_TOKEN_PREFIX,
+        payload,
+        signature.to_hex()
+    ))
+}
+
+pub fn verify_search_token(token: &str) -> Result<SearchTokenClaims, ServiceError> {
+    let key = SEARCH_TOKEN_KEY
+        .as_ref()
+        .ok_or(ServiceError::Unauthorized)?;
+
+    verify_search_token_with_key(key, token)
+}
+
+fn verify_search_token_with_key(
+    key: &[u8; 32],
+    token: &str,
+) -> Result<SearchTokenClaims, ServiceError> {
+    let (payload, signature) = token
+        .strip_prefix(SEARCH_TOKEN_PREFIX)
+        .and_then(|token| token.split_once('.'))
+        .ok_or(ServiceError::Unauthorized)?;
+
+    let signature = blake3::Hash::from_hex(signature).map_err(|_| ServiceError::Unauthorized)?;
+    // blake3::Hash compares in constant time
+    if signature != sign_search_token_payload(key, payload) {
+        return Err(ServiceError::Unauthorized);
+    }
+
+    let claims: SearchTokenClaims = URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()
+        .and_then(|payload| serde_json::from_slice(&payload).ok())
+        .ok_or(ServiceError::Unauthorized)?;
+
+    if claims.exp < chrono::Utc::now().timestamp() {
+        return Err(ServiceError::Unauthorized);
+    }
+
+    Ok(claims)
+}
+
+/// Verifies the search token and returns a read only user for its organization along with an api key restricted to its dataset and params.
+pub async fn get_assumed_user_by_search_token(
+    token: &str,
+    pool: web::Data<Pool>,
+) -> Result<(SlimUser, UserApiKey), ServiceError> {
+    let claims = verify_search_token(token)?;
+
+    let org_sub_plan = get_org_from_id_query(claims.organization_id, pool).await?;
+
+    let fake_user_id = uuid::Uuid::new_v4();
+
+    let user = SlimUser {
+        id: fake_user_id,
+        email: "".to_string(),
+        name: Some("".to_string()),
+        created_at: chrono::Utc::now().naive_utc(),
+        user_orgs: vec![UserOrganization {
+            id: uuid::Uuid::new_v4(),
+            user_id: fake_user_id,
+            organization_id: org_sub_plan.organization.id,
+            role: UserRole::User.into(),
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        }],
+        orgs: vec![org_sub_plan.organization],
+    };
+
+    let api_key = UserApiKey {
+        id: claims.id,
+        user_id: fake_user_id,
+        api_key_hash: None,
+        name: "search token".to_string(),
+        created_at: chrono::Utc::now().naive_utc(),
+        updated_at: chrono::Utc::now().naive_utc(),
+        role: UserRole::User.into(),
+        blake3_hash: None,
+        dataset_ids: Some(vec![Some(claims.dataset_id.to_string())]),
+        organization_ids: Some(vec![Some(claims.organization_id.to_string())]),
+        scopes: None,
+        params: Some(serde_json::to_value(&claims.params).map_err(|_| {
+            ServiceError::InternalServerError("Failed to serialize search token params".to_string())
+        })?),
+        expires_at: chrono::DateTime::from_timestamp(claims.exp, 0)
+            .map(|expires_at| expires_at.naive_utc()),
+    };
+
+    Ok((user, api_key))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    fn claims(exp: i64) -> SearchTokenClaims {
+        SearchTokenClaims {
+            id: uuid::Uuid::new_v4(),
+            dataset_id: uuid::Uuid::new_v4(),
+            organization_id: uuid::Uuid::new_v4(),
+            exp,
+            params: serde_json::from_value(serde_json::json!({})).unwrap(),
+        }
+    }
+
+    #[test]
+    pub fn test_verify_search_token_roundtrip() {
+        let claims = claims(chrono::Utc::now().timestamp() + 60);
+        let token = encode_search_token_with_key(&KEY, &claims).unwrap();
+
+        let verified = verify_search_token_with_key(&KEY, &token).unwrap();
+        assert_eq!(verified.id, claims.id);
+        assert_eq!(verified.dataset_id, claims.dataset_id);
+    }
+
+    #[test]
+    pub fn test_verify_search_token_rejects_tampered_payload() {
+        let token =
+            encode_search_token_with_key(&KEY, &claims(chrono::Utc::now().timestamp() + 60))
+                .unwrap();
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        let forged_payload = URL_SAFE_NO_PAD
+            .encode(serde_json::to_vec(&claims(chrono::Utc::now().timestamp() + 60)).unwrap());
+        let forged = format!("{}{}.{}", SEARCH_TOKEN_PREFIX, forged_payload, signature);
+
+        assert!(verify_search_token_with_key(&KEY, &forged).is_err());
+        assert!(verify_search_token_with_key(&[8; 32], &token).is_err());
+        assert!(
+            verify_search_token_with_key(&KEY, &token.replace(SEARCH_TOKEN_PREFIX, "")).is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_verify_search_token_rejects_expired() {
+        let token = encode_search_token_with_key(&KEY, &claims(chrono::Utc::now().timestamp() - 1))
+            .unwrap();
+
+        assert!(verify_search_token_with_key(&KEY, &token).is_err());
+    }
+}
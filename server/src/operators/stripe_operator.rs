@@ -166,6 +166,44 @@ pub async fn create_stripe_payment_link(
     Ok(payment_link.to_string())
 }
 
+/// Reports usage to a Stripe billing meter. Stripe dedupes meter events by `identifier`, so resending an event after a failure does not double bill.
+pub async fn create_stripe_meter_event(
+    event_name: &str,
+    stripe_customer_id: &str,
+    value: i64,
+    identifier: &str,
+    timestamp: chrono::NaiveDateTime,
+) -> Result<(), ServiceError> {
+    let stripe_secret = get_env!("STRIPE_SECRET", "STRIPE_SECRET must be set");
+
+    let meter_event_form_url_encoded = json!({
+        "event_name": event_name,
+        "payload[stripe_customer_id]": stripe_customer_id,
+        "payload[value]": value.to_string(),
+        "identifier": identifier,
+        "timestamp": timestamp.and_utc().timestamp(),
+    });
+
+    reqwest::Client::new()
+        .post("https://api.stripe.com/v1/billing/meter_events")
+        .header("Authorization", format!("Bearer {}", stripe_secret))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .form(&meter_event_form_url_encoded)
+        .send()
+        .await
+        .map_err(|e| {
+            log::error!("Failed to create stripe meter event: {}", e);
+            ServiceError::BadRequest("Failed to create stripe meter event".to_string())
+        })?
+        .error_for_status()
+        .map_err(|e| {
+            log::error!("Stripe rejected meter event: {}", e);
+            ServiceError::BadRequest(format!("Stripe rejected meter event: {}", e))
+        })?;
+
+    Ok(())
+}
+
 pub async fn get_subscription_by_id_query(
     subscription_id: uuid::Uuid,
     pool: web::Data<Pool>,
@@ -48,6 +48,7 @@ pub async fn delete_topic_query(
 pub async fn update_topic_query(
     topic_id: uuid::Uuid,
     topic_name: String,
+    topic_rag_preset: Option<Option<String>>,
     given_dataset_id: uuid::Uuid,
     pool: &web::Data<Pool>,
 ) -> Result<(), ServiceError> {
@@ -57,14 +58,28 @@ pub async fn update_topic_query(
         ServiceError::InternalServerError("Failed to get postgres connection".to_string())
     })?;
 
-    diesel::update(
-        topics
-            .filter(id.eq(topic_id))
-            .filter(dataset_id.eq(given_dataset_id)),
-    )
-    .set((name.eq(topic_name), updated_at.eq(diesel::dsl::now)))
-    .execute(&mut conn)
-    .await
+    let target_topic = topics
+        .filter(id.eq(topic_id))
+        .filter(dataset_id.eq(given_dataset_id));
+
+    match topic_rag_preset {
+        Some(new_rag_preset) => {
+            diesel::update(target_topic)
+                .set((
+                    name.eq(topic_name),
+                    rag_preset.eq(new_rag_preset),
+                    updated_at.eq(diesel::dsl::now),
+                ))
+                .execute(&mut conn)
+                .await
+        }
+        None => {
+            diesel::update(target_topic)
+                .set((name.eq(topic_name), updated_at.eq(diesel::dsl::now)))
+                .execute(&mut conn)
+                .await
+        }
+    }
     .map_err(|_db_error| ServiceError::BadRequest("Error updating topic, try again".to_string()))?;
 
     Ok(())
@@ -0,0 +1,650 @@
+use crate::{
+    data::models::{
+        ChunkGroupAndFileId, Dataset, DatasetConfiguration, Pool, RedisPool, TrashItem,
+        TrashItemType,
+    },
+    errors::ServiceError,
+    operators::{
+        dataset_operator::{
+            get_dataset_by_id_query, soft_delete_dataset_by_id_query, DatasetDeleteMessage,
+            DeleteMessage,
+        },
+        file_operator::delete_file_query,
+        group_operator::delete_group_by_id_query,
+        qdrant_operator::{
+            get_qdrant_collection_from_dataset_config, set_trash_payload_in_qdrant_query,
+        },
+    },
+};
+use actix_web::web;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DBError};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+pub const MAX_TRASH_RETENTION_DAYS: i32 = 90;
+
+fn map_trash_insert_error(err: DBError) -> ServiceError {
+    match err {
+        DBError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            ServiceError::BadRequest("Item is already in the trash".to_string())
+        }
+        _ => {
+            log::error!("Error moving item to the trash: {:?}", err);
+            ServiceError::BadRequest("Error moving item to the trash".to_string())
+        }
+    }
+}
+
+/// Moves a dataset to the trash by soft deleting it without queueing its deletion. Datasets of organizations with a retention window of 0 days are deleted right away.
+pub async fn trash_dataset_query(
+    dataset: Dataset,
+    retention_days: i32,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::datasets::dsl as datasets_columns;
+    use crate::data::schema::trash::dsl as trash_columns;
+
+    let dataset_config = DatasetConfiguration::from_json(dataset.server_configuration.clone());
+
+    if retention_days <= 0 {
+        return soft_delete_dataset_by_id_query(dataset.id, dataset_config, pool, redis_pool).await;
+    }
+
+    if dataset_config.LOCKED {
+        return Err(ServiceError::BadRequest(
+            "Cannot delete a locked dataset".to_string(),
+        ));
+    }
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let trash_item = TrashItem::from_details(
+        dataset.organization_id,
+        dataset.id,
+        TrashItemType::Dataset,
+        dataset.id,
+        dataset.name.clone(),
+        dataset.tracking_id.clone(),
+        retention_days,
+    );
+
+    conn.transaction::<_, DBError, _>(|conn| {
+        async move {
+            diesel::insert_into(trash_columns::trash)
+                .values(&trash_item)
+                .execute(conn)
+                .await?;
+
+            diesel::update(datasets_columns::datasets.filter(datasets_columns::id.eq(dataset.id)))
+                .set((
+                    datasets_columns::deleted.eq(1),
+                    datasets_columns::tracking_id.eq(None::<String>),
+                ))
+                .execute(conn)
+                .await?;
+
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(map_trash_insert_error)?;
+
+    Ok(())
+}
+
+/// Moves a group to the trash. The group keeps its chunks and bookmarks so it can be restored as it was, but is taken out of its chunks' Qdrant payloads so it is left out of reads and group searches until then. With delete_chunks its chunks are hidden from searches and reads as well, unless they are also in a live group.
+pub async fn trash_group_query(
+    group: ChunkGroupAndFileId,
+    organization_id: uuid::Uuid,
+    delete_chunks: bool,
+    retention_days: i32,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::chunk_group::dsl as chunk_group_columns;
+    use crate::data::schema::trash::dsl as trash_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let trash_item = TrashItem::from_details(
+        organization_id,
+        group.dataset_id,
+        TrashItemType::Group,
+        group.id,
+        group.name.clone(),
+        group.tracking_id.clone(),
+        retention_days,
+    )
+    .with_delete_chunks(delete_chunks);
+
+    conn.transaction::<_, DBError, _>(|conn| {
+        async move {
+            diesel::insert_into(trash_columns::trash)
+                .values(&trash_item)
+                .execute(conn)
+                .await?;
+
+            diesel::update(
+                chunk_group_columns::chunk_group.filter(chunk_group_columns::id.eq(group.id)),
+            )
+            .set(chunk_group_columns::tracking_id.eq(None::<String>))
+            .execute(conn)
+            .await?;
+
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(map_trash_insert_error)?;
+
+    sync_trashed_chunk_payloads_query(vec![group.id], group.dataset_id, pool).await
+}
+
+/// Moves a file to the trash. The file is kept in S3 until it is purged. With delete_chunks the chunks of the file's group are hidden from searches and reads until then, unless they are also in a live group.
+pub async fn trash_file_query(
+    file_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    organization_id: uuid::Uuid,
+    delete_chunks: bool,
+    retention_days: i32,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::files::dsl as files_columns;
+    use crate::data::schema::trash::dsl as trash_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let file_name = files_columns::files
+        .filter(files_columns::id.eq(file_id))
+        .filter(files_columns::dataset_id.eq(dataset_id))
+        .select(files_columns::file_name)
+        .first::<String>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::NotFound("File with specified id not found".to_string()))?;
+
+    let trash_item = TrashItem::from_details(
+        organization_id,
+        dataset_id,
+        TrashItemType::File,
+        file_id,
+        file_name,
+        None,
+        retention_days,
+    )
+    .with_delete_chunks(delete_chunks);
+
+    diesel::insert_into(trash_columns::trash)
+        .values(&trash_item)
+        .execute(&mut conn)
+        .await
+        .map_err(map_trash_insert_error)?;
+
+    if delete_chunks {
+        let group_ids = get_file_group_ids_query(file_id, pool.clone()).await?;
+        sync_trashed_chunk_payloads_query(group_ids, dataset_id, pool).await?;
+    }
+
+    Ok(())
+}
+
+/// Recomputes the group_ids and hidden fields of the Qdrant payloads of the chunks in the groups. Trashed groups are left out of group_ids, and a chunk is hidden when one of its groups is hidden by a group or file trashed with delete_chunks and none of its other groups are live.
+pub async fn sync_trashed_chunk_payloads_query(
+    group_ids: Vec<uuid::Uuid>,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::chunk_group_bookmarks::dsl as chunk_group_bookmarks_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+    use crate::data::schema::groups_from_files::dsl as groups_from_files_columns;
+    use crate::data::schema::trash::dsl as trash_columns;
+
+    if group_ids.is_empty() {
+        return Ok(());
+    }
+
+    let dataset = get_dataset_by_id_query(dataset_id, pool.clone()).await?;
+    let qdrant_collection = get_qdrant_collection_from_dataset_config(
+        &DatasetConfiguration::from_json(dataset.server_configuration),
+    );
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let chunk_ids = chunk_group_bookmarks_columns::chunk_group_bookmarks
+        .filter(chunk_group_bookmarks_columns::group_id.eq_any(&group_ids))
+        .select(chunk_group_bookmarks_columns::chunk_metadata_id)
+        .distinct()
+        .load::<uuid::Uuid>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting chunks of groups".to_string()))?;
+
+    let point_groups = chunk_group_bookmarks_columns::chunk_group_bookmarks
+        .inner_join(chunk_metadata_columns::chunk_metadata)
+        .filter(chunk_group_bookmarks_columns::chunk_metadata_id.eq_any(&chunk_ids))
+        .select((
+            chunk_metadata_columns::qdrant_point_id,
+            chunk_group_bookmarks_columns::group_id,
+        ))
+        .load::<(uuid::Uuid, uuid::Uuid)>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting groups of chunks".to_string()))?
+        .into_iter()
+        .into_group_map();
+
+    let chunk_group_ids = point_groups
+        .values()
+        .flatten()
+        .copied()
+        .unique()
+        .collect::<Vec<uuid::Uuid>>();
+
+    let trashed_groups = trash_columns::trash
+        .filter(trash_columns::dataset_id.eq(dataset_id))
+        .filter(trash_columns::item_type.eq(TrashItemType::Group.to_string()))
+        .filter(trash_columns::item_id.eq_any(&chunk_group_ids))
+        .select((trash_columns::item_id, trash_columns::delete_chunks))
+        .load::<(uuid::Uuid, bool)>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting trashed groups".to_string()))?;
+
+    let trashed_file_group_ids = groups_from_files_columns::groups_from_files
+        .filter(groups_from_files_columns::group_id.eq_any(&chunk_group_ids))
+        .filter(
+            groups_from_files_columns::file_id.eq_any(
+                trash_columns::trash
+                    .filter(trash_columns::dataset_id.eq(dataset_id))
+                    .filter(trash_columns::delete_chunks.eq(true))
+                    .filter(trash_columns::item_type.eq(TrashItemType::File.to_string()))
+                    .select(trash_columns::item_id),
+            ),
+        )
+        .select(groups_from_files_columns::group_id)
+        .load::<uuid::Uuid>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting trashed files".to_string()))?;
+
+    let trashed_group_ids = trashed_groups
+        .iter()
+        .map(|(group_id, _)| *group_id)
+        .collect::<HashSet<uuid::Uuid>>();
+    let hiding_group_ids = trashed_groups
+        .iter()
+        .filter(|(_, delete_chunks)| *delete_chunks)
+        .map(|(group_id, _)| *group_id)
+        .chain(trashed_file_group_ids)
+        .collect::<HashSet<uuid::Uuid>>();
+
+    let mut payload_points: HashMap<(Vec<uuid::Uuid>, bool), Vec<uuid::Uuid>> = HashMap::new();
+    for (point_id, groups) in point_groups {
+        let hidden = groups
+            .iter()
+            .any(|group_id| hiding_group_ids.contains(group_id))
+            && groups.iter().all(|group_id| {
+                trashed_group_ids.contains(group_id) || hiding_group_ids.contains(group_id)
+            });
+        let visible_group_ids = groups
+            .into_iter()
+            .filter(|group_id| !trashed_group_ids.contains(group_id))
+            .sorted()
+            .collect::<Vec<uuid::Uuid>>();
+
+        payload_points
+            .entry((visible_group_ids, hidden))
+            .or_default()
+            .push(point_id);
+    }
+
+    for ((visible_group_ids, hidden), point_ids) in payload_points {
+        for point_ids in point_ids.chunks(1000) {
+            set_trash_payload_in_qdrant_query(
+                point_ids.to_vec(),
+                visible_group_ids.clone(),
+                hidden,
+                qdrant_collection.clone(),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Ids of the groups created from the file.
+async fn get_file_group_ids_query(
+    file_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<uuid::Uuid>, ServiceError> {
+    use crate::data::schema::groups_from_files::dsl as groups_from_files_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    groups_from_files_columns::groups_from_files
+        .filter(groups_from_files_columns::file_id.eq(file_id))
+        .select(groups_from_files_columns::group_id)
+        .load::<uuid::Uuid>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting groups of file".to_string()))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct TrashResponse {
+    pub items: Vec<TrashItem>,
+    pub total_pages: i64,
+}
+
+pub async fn get_trash_query(
+    organization_id: uuid::Uuid,
+    dataset_id: Option<uuid::Uuid>,
+    item_type: Option<TrashItemType>,
+    page: u64,
+    pool: web::Data<Pool>,
+) -> Result<TrashResponse, ServiceError> {
+    use crate::data::schema::trash::dsl as trash_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let mut query = trash_columns::trash
+        .filter(trash_columns::organization_id.eq(organization_id))
+        .into_boxed();
+    let mut count_query = trash_columns::trash
+        .filter(trash_columns::organization_id.eq(organization_id))
+        .into_boxed();
+
+    if let Some(dataset_id) = dataset_id {
+        query = query.filter(trash_columns::dataset_id.eq(dataset_id));
+        count_query = count_query.filter(trash_columns::dataset_id.eq(dataset_id));
+    }
+    if let Some(item_type) = item_type {
+        query = query.filter(trash_columns::item_type.eq(item_type.to_string()));
+        count_query = count_query.filter(trash_columns::item_type.eq(item_type.to_string()));
+    }
+
+    let items = query
+        .order_by(trash_columns::deleted_at.desc())
+        .offset(((page.max(1) - 1) * 10).try_into().unwrap_or(0))
+        .limit(10)
+        .select(TrashItem::as_select())
+        .load::<TrashItem>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting trash".to_string()))?;
+
+    let count = count_query
+        .count()
+        .get_result::<i64>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting trash count".to_string()))?;
+
+    Ok(TrashResponse {
+        items,
+        total_pages: (count as f64 / 10.0).ceil() as i64,
+    })
+}
+
+pub async fn get_trash_item_query(
+    trash_id: uuid::Uuid,
+    organization_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<TrashItem, ServiceError> {
+    use crate::data::schema::trash::dsl as trash_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    trash_columns::trash
+        .filter(trash_columns::id.eq(trash_id))
+        .filter(trash_columns::organization_id.eq(organization_id))
+        .select(TrashItem::as_select())
+        .first::<TrashItem>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::NotFound("Trash item not found".to_string()))
+}
+
+/// Takes an item out of the trash. Chunks hidden along with it show up in searches again and its tracking id is given back unless another dataset or group has claimed it in the meantime.
+pub async fn restore_trash_item_query(
+    trash_item: TrashItem,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::chunk_group::dsl as chunk_group_columns;
+    use crate::data::schema::datasets::dsl as datasets_columns;
+    use crate::data::schema::trash::dsl as trash_columns;
+
+    let item_type = TrashItemType::from_db(&trash_item.item_type).ok_or(
+        ServiceError::InternalServerError("Unknown trash item type".to_string()),
+    )?;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let restored_item = trash_item.clone();
+    conn.transaction::<_, DBError, _>(|conn| {
+        async move {
+            match item_type {
+                TrashItemType::Dataset => {
+                    let tracking_id_taken = match &trash_item.tracking_id {
+                        Some(tracking_id) => {
+                            datasets_columns::datasets
+                                .filter(
+                                    datasets_columns::organization_id
+                                        .eq(trash_item.organization_id),
+                                )
+                                .filter(datasets_columns::tracking_id.eq(tracking_id))
+                                .count()
+                                .get_result::<i64>(conn)
+                                .await?
+                                > 0
+                        }
+                        None => false,
+                    };
+
+                    diesel::update(
+                        datasets_columns::datasets
+                            .filter(datasets_columns::id.eq(trash_item.item_id)),
+                    )
+                    .set((
+                        datasets_columns::deleted.eq(0),
+                        datasets_columns::tracking_id.eq(trash_item
+                            .tracking_id
+                            .clone()
+                            .filter(|_| !tracking_id_taken)),
+                    ))
+                    .execute(conn)
+                    .await?;
+                }
+                TrashItemType::Group => {
+                    let tracking_id_taken = match &trash_item.tracking_id {
+                        Some(tracking_id) => {
+                            chunk_group_columns::chunk_group
+                                .filter(chunk_group_columns::dataset_id.eq(trash_item.dataset_id))
+                                .filter(chunk_group_columns::tracking_id.eq(tracking_id))
+                                .count()
+                                .get_result::<i64>(conn)
+                                .await?
+                                > 0
+                        }
+                        None => false,
+                    };
+
+                    diesel::update(
+                        chunk_group_columns::chunk_group
+                            .filter(chunk_group_columns::id.eq(trash_item.item_id)),
+                    )
+                    .set(
+                        chunk_group_columns::tracking_id.eq(trash_item
+                            .tracking_id
+                            .clone()
+                            .filter(|_| !tracking_id_taken)),
+                    )
+                    .execute(conn)
+                    .await?;
+                }
+                TrashItemType::File => {}
+            }
+
+            diesel::delete(trash_columns::trash.filter(trash_columns::id.eq(trash_item.id)))
+                .execute(conn)
+                .await?;
+
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(|err| {
+        log::error!("Error restoring trash item: {:?}", err);
+        ServiceError::BadRequest("Error restoring trash item".to_string())
+    })?;
+
+    match item_type {
+        TrashItemType::Dataset => {}
+        TrashItemType::Group => {
+            sync_trashed_chunk_payloads_query(
+                vec![restored_item.item_id],
+                restored_item.dataset_id,
+                pool,
+            )
+            .await?;
+        }
+        TrashItemType::File => {
+            if restored_item.delete_chunks {
+                let group_ids =
+                    get_file_group_ids_query(restored_item.item_id, pool.clone()).await?;
+                sync_trashed_chunk_payloads_query(group_ids, restored_item.dataset_id, pool)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Permanently deletes an item in the trash. Datasets are handed to the delete worker along with their chunks, groups and files are deleted in place along with their chunks if they were trashed with delete_chunks.
+pub async fn purge_trash_item_query(
+    trash_item: TrashItem,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::trash::dsl as trash_columns;
+
+    let item_type = TrashItemType::from_db(&trash_item.item_type).ok_or(
+        ServiceError::InternalServerError("Unknown trash item type".to_string()),
+    )?;
+
+    match item_type {
+        TrashItemType::Dataset => {
+            let message = DatasetDeleteMessage {
+                dataset_id: trash_item.item_id,
+                attempt_number: 0,
+                deleted_at: chrono::Utc::now().naive_utc(),
+                empty_dataset: false,
+            };
+
+            let serialized_message = serde_json::to_string(&DeleteMessage::DatasetDelete(message))
+                .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+            let mut redis_conn = redis_pool
+                .get()
+                .await
+                .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+            redis::cmd("lpush")
+                .arg("delete_dataset_queue")
+                .arg(&serialized_message)
+                .query_async::<_, ()>(&mut *redis_conn)
+                .await
+                .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+        }
+        TrashItemType::Group | TrashItemType::File => {
+            // Items of a dataset which was deleted since are removed along with it by the delete worker
+            if let Ok(dataset) = get_dataset_by_id_query(trash_item.dataset_id, pool.clone()).await
+            {
+                let dataset_config =
+                    DatasetConfiguration::from_json(dataset.server_configuration.clone());
+
+                if item_type == TrashItemType::Group {
+                    delete_group_by_id_query(
+                        trash_item.item_id,
+                        dataset,
+                        chrono::Utc::now().naive_utc(),
+                        Some(trash_item.delete_chunks),
+                        pool.clone(),
+                        dataset_config,
+                    )
+                    .await?;
+                } else {
+                    delete_file_query(
+                        trash_item.item_id,
+                        Some(trash_item.delete_chunks),
+                        dataset,
+                        pool.clone(),
+                        dataset_config,
+                    )
+                    .await
+                    .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+                }
+            }
+        }
+    }
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::delete(trash_columns::trash.filter(trash_columns::id.eq(trash_item.id)))
+        .execute(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error deleting trash item".to_string()))?;
+
+    Ok(())
+}
+
+/// Purges the items whose retention window has passed. Returns the number of items purged.
+pub async fn purge_expired_trash_query(
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<usize, ServiceError> {
+    use crate::data::schema::trash::dsl as trash_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let expired_items = trash_columns::trash
+        .filter(trash_columns::purge_at.le(chrono::Utc::now().naive_local()))
+        .order_by(trash_columns::purge_at.asc())
+        .limit(100)
+        .select(TrashItem::as_select())
+        .load::<TrashItem>(&mut conn)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Error getting expired trash".to_string()))?;
+
+    let mut purged = 0;
+    for trash_item in expired_items {
+        let trash_id = trash_item.id;
+        match purge_trash_item_query(trash_item, pool.clone(), redis_pool.clone()).await {
+            Ok(()) => purged += 1,
+            Err(err) => log::error!("Failed to purge trash item {}: {:?}", trash_id, err),
+        }
+    }
+
+    Ok(purged)
+}
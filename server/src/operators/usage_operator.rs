@@ -0,0 +1,567 @@
+use crate::{
+    data::models::{DatasetUsage, Pool, UsageCounts},
+    errors::ServiceError,
+    operators::stripe_operator::create_stripe_meter_event,
+};
+use actix_web::web;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use diesel_async::RunQueryDsl;
+use openai_dive::v1::resources::chat::{ChatCompletionResponse, ChatMessage, ChatMessageContent};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+    time::Duration,
+};
+use utoipa::{IntoParams, ToSchema};
+
+/// Days after which usage is no longer pushed to Stripe, which rejects meter events older than 35 days.
+const STRIPE_EXPORT_LOOKBACK_DAYS: i64 = 30;
+
+/// Seconds between writes of the buffered usage to `dataset_usage` when `USAGE_FLUSH_INTERVAL_SECS` is not set.
+const DEFAULT_USAGE_FLUSH_INTERVAL_SECS: u64 = 10;
+
+tokio::task_local! {
+    static USAGE_METER: UsageMeter;
+}
+
+/// Usage recorded since the last flush, keyed like the unique index of `dataset_usage` so that every entry is one row of the flush upsert.
+type UsageBuffer = HashMap<(uuid::Uuid, uuid::Uuid, chrono::NaiveDate), UsageCounts>;
+
+static USAGE_BUFFER: OnceLock<Mutex<UsageBuffer>> = OnceLock::new();
+static USAGE_FLUSHER: OnceLock<()> = OnceLock::new();
+
+fn usage_buffer() -> &'static Mutex<UsageBuffer> {
+    USAGE_BUFFER.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn add_to_usage_buffer(
+    buffer: &mut UsageBuffer,
+    dataset_id: uuid::Uuid,
+    api_key_id: Option<uuid::Uuid>,
+    usage_date: chrono::NaiveDate,
+    counts: &UsageCounts,
+) {
+    buffer
+        .entry((dataset_id, api_key_id.unwrap_or_default(), usage_date))
+        .or_default()
+        .add(counts);
+}
+
+/// Writes the buffered usage to `dataset_usage` in one upsert. Usage which fails to be written is put back into the buffer and retried on the next flush.
+pub async fn flush_usage_buffer(pool: &web::Data<Pool>) {
+    let buffered_usage = std::mem::take(
+        &mut *usage_buffer()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner),
+    );
+    if buffered_usage.is_empty() {
+        return;
+    }
+
+    let usage = buffered_usage
+        .iter()
+        .map(|((dataset_id, api_key_id, usage_date), counts)| {
+            DatasetUsage::from_counts(*dataset_id, Some(*api_key_id), *usage_date, *counts)
+        })
+        .collect();
+
+    if let Err(err) = record_dataset_usage_query(usage, pool).await {
+        log::error!(
+            "Failed to flush usage, retrying on the next flush: {:?}",
+            err
+        );
+        let mut buffer = usage_buffer()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for ((dataset_id, api_key_id, usage_date), counts) in buffered_usage {
+            add_to_usage_buffer(
+                &mut buffer,
+                dataset_id,
+                Some(api_key_id),
+                usage_date,
+                &counts,
+            );
+        }
+    }
+}
+
+/// Starts the task which periodically flushes the usage buffer, once per process.
+fn start_usage_flusher(pool: &web::Data<Pool>) {
+    USAGE_FLUSHER.get_or_init(|| {
+        let pool = pool.clone();
+        let flush_interval = std::env::var("USAGE_FLUSH_INTERVAL_SECS")
+            .ok()
+            .and_then(|interval| interval.parse().ok())
+            .unwrap_or(DEFAULT_USAGE_FLUSH_INTERVAL_SECS);
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(flush_interval)).await;
+                flush_usage_buffer(&pool).await;
+            }
+        });
+    });
+}
+
+/// Accumulates the usage of one request or worker job, which is added to the usage buffer once it finishes.
+#[derive(Debug, Clone)]
+pub struct UsageMeter {
+    pub dataset_id: uuid::Uuid,
+    pub api_key_id: Option<uuid::Uuid>,
+    counts: Arc<Mutex<UsageCounts>>,
+}
+
+impl UsageMeter {
+    /// The meter of the request or job being run, if any. Work which outlives it, such as streamed completions, can hold on to the meter and `record` its usage separately.
+    pub fn current() -> Option<UsageMeter> {
+        USAGE_METER.try_with(|meter| meter.clone()).ok()
+    }
+
+    /// Buffers the usage, which is written to `dataset_usage` on the next flush rather than on the request path.
+    pub async fn record(&self, counts: UsageCounts, pool: &web::Data<Pool>) {
+        if counts.is_empty() {
+            return;
+        }
+
+        start_usage_flusher(pool);
+        add_to_usage_buffer(
+            &mut usage_buffer()
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+            self.dataset_id,
+            self.api_key_id,
+            chrono::Utc::now().date_naive(),
+            &counts,
+        );
+    }
+}
+
+/// Runs `future` with a usage meter for the dataset and buffers everything metered while it ran.
+pub async fn with_usage_meter<F, T>(
+    dataset_id: uuid::Uuid,
+    api_key_id: Option<uuid::Uuid>,
+    pool: &web::Data<Pool>,
+    future: F,
+) -> T
+where
+    F: Future<Output = T>,
+{
+    let meter = UsageMeter {
+        dataset_id,
+        api_key_id,
+        counts: Arc::new(Mutex::new(UsageCounts::default())),
+    };
+
+    let output = USAGE_METER.scope(meter.clone(), future).await;
+
+    // A panic while metering should not lose the usage recorded before it
+    let counts = *meter.counts.lock().unwrap_or_else(PoisonError::into_inner);
+    meter.record(counts, pool).await;
+
+    output
+}
+
+/// Adds to the usage of the current request or job. Does nothing outside of one.
+pub fn meter_usage(add: impl FnOnce(&mut UsageCounts)) {
+    let _ = USAGE_METER.try_with(|meter| {
+        add(&mut meter.counts.lock().unwrap_or_else(PoisonError::into_inner));
+    });
+}
+
+/// The api key of the current request or job, so that queued work is metered against the key which queued it.
+pub fn current_usage_api_key_id() -> Option<uuid::Uuid> {
+    USAGE_METER
+        .try_with(|meter| meter.api_key_id)
+        .ok()
+        .flatten()
+}
+
+/// Rough token count of text for providers which do not report usage.
+pub fn estimate_tokens(text: &str) -> i64 {
+    (text.chars().count() as i64 + 3) / 4
+}
+
+/// Rough token count of the text parts of a chat prompt, used for streamed completions which do not report usage.
+pub fn estimate_prompt_tokens(messages: &[ChatMessage]) -> i64 {
+    messages
+        .iter()
+        .map(|message| match message {
+            ChatMessage::User {
+                content: ChatMessageContent::Text(text),
+                ..
+            }
+            | ChatMessage::System {
+                content: ChatMessageContent::Text(text),
+                ..
+            }
+            | ChatMessage::Assistant {
+                content: Some(ChatMessageContent::Text(text)),
+                ..
+            } => estimate_tokens(text),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Meters the tokens a chat completion provider reported for a request.
+pub fn meter_chat_completion(completion: &ChatCompletionResponse) {
+    if let Some(usage) = completion.usage.as_ref() {
+        meter_usage(|counts| {
+            counts.prompt_tokens += usage.prompt_tokens as i64;
+            counts.completion_tokens += usage.completion_tokens.unwrap_or(0) as i64;
+        });
+    }
+}
+
+pub async fn record_dataset_usage_query(
+    usage: Vec<DatasetUsage>,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::dataset_usage::dsl as dataset_usage_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    diesel::insert_into(dataset_usage_columns::dataset_usage)
+        .values(usage)
+        .on_conflict((
+            dataset_usage_columns::dataset_id,
+            dataset_usage_columns::api_key_id,
+            dataset_usage_columns::usage_date,
+        ))
+        .do_update()
+        .set(
+            (
+                dataset_usage_columns::search_calls.eq(dataset_usage_columns::search_calls
+                    + excluded(dataset_usage_columns::search_calls)),
+                dataset_usage_columns::rag_calls
+                    .eq(dataset_usage_columns::rag_calls
+                        + excluded(dataset_usage_columns::rag_calls)),
+                dataset_usage_columns::prompt_tokens.eq(dataset_usage_columns::prompt_tokens
+                    + excluded(dataset_usage_columns::prompt_tokens)),
+                dataset_usage_columns::completion_tokens
+                    .eq(dataset_usage_columns::completion_tokens
+                        + excluded(dataset_usage_columns::completion_tokens)),
+                dataset_usage_columns::embedding_tokens.eq(dataset_usage_columns::embedding_tokens
+                    + excluded(dataset_usage_columns::embedding_tokens)),
+                dataset_usage_columns::file_pages
+                    .eq(dataset_usage_columns::file_pages
+                        + excluded(dataset_usage_columns::file_pages)),
+                dataset_usage_columns::storage_bytes.eq(dataset_usage_columns::storage_bytes
+                    + excluded(dataset_usage_columns::storage_bytes)),
+                dataset_usage_columns::updated_at.eq(excluded(dataset_usage_columns::updated_at)),
+            ),
+        )
+        .execute(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Failed to record dataset usage: {:?}", err);
+            ServiceError::BadRequest("Failed to record dataset usage".to_string())
+        })?;
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct UsageFilter {
+    /// First day to get the usage of, inclusive. Default is 30 days ago.
+    pub start_date: Option<chrono::NaiveDate>,
+    /// Last day to get the usage of, inclusive. Default is today.
+    pub end_date: Option<chrono::NaiveDate>,
+    /// Only get the usage of this dataset.
+    pub dataset_id: Option<uuid::Uuid>,
+    /// Only get the usage made with this api key. Use the nil uuid for usage not made with an api key.
+    pub api_key_id: Option<uuid::Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct UsageResponse {
+    /// Usage per dataset, api key and day, newest first.
+    pub usage: Vec<DatasetUsage>,
+    /// Sum of the usage.
+    pub total: UsageCounts,
+}
+
+pub async fn get_organization_usage_query(
+    organization_id: uuid::Uuid,
+    filter: UsageFilter,
+    pool: &web::Data<Pool>,
+) -> Result<UsageResponse, ServiceError> {
+    use crate::data::schema::dataset_usage::dsl as dataset_usage_columns;
+    use crate::data::schema::datasets::dsl as datasets_columns;
+
+    let today = chrono::Utc::now().date_naive();
+    let start_date = filter
+        .start_date
+        .unwrap_or(today - chrono::Duration::days(30));
+    let end_date = filter.end_date.unwrap_or(today);
+    if start_date > end_date {
+        return Err(ServiceError::BadRequest(
+            "start_date must not be after end_date".to_string(),
+        ));
+    }
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let mut query = dataset_usage_columns::dataset_usage
+        .inner_join(datasets_columns::datasets)
+        .filter(datasets_columns::organization_id.eq(organization_id))
+        .filter(dataset_usage_columns::usage_date.ge(start_date))
+        .filter(dataset_usage_columns::usage_date.le(end_date))
+        .select(DatasetUsage::as_select())
+        .into_boxed();
+
+    if let Some(dataset_id) = filter.dataset_id {
+        query = query.filter(dataset_usage_columns::dataset_id.eq(dataset_id));
+    }
+    if let Some(api_key_id) = filter.api_key_id {
+        query = query.filter(dataset_usage_columns::api_key_id.eq(api_key_id));
+    }
+
+    let usage = query
+        .order_by((
+            dataset_usage_columns::usage_date.desc(),
+            dataset_usage_columns::dataset_id,
+            dataset_usage_columns::api_key_id,
+        ))
+        .load::<DatasetUsage>(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Failed to get usage: {:?}", err);
+            ServiceError::BadRequest("Failed to get usage".to_string())
+        })?;
+
+    let mut total = UsageCounts::default();
+    usage.iter().for_each(|record| total.add(&record.counts()));
+
+    Ok(UsageResponse { usage, total })
+}
+
+pub fn usage_to_csv(usage: &[DatasetUsage]) -> String {
+    let mut csv = format!(
+        "usage_date,dataset_id,api_key_id,{}\n",
+        UsageCounts::default()
+            .metrics()
+            .iter()
+            .map(|(metric, _)| *metric)
+            .collect::<Vec<&str>>()
+            .join(",")
+    );
+
+    for record in usage {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            record.usage_date,
+            record.dataset_id,
+            record.api_key_id,
+            record
+                .counts()
+                .metrics()
+                .iter()
+                .map(|(_, value)| value.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        ));
+    }
+
+    csv
+}
+
+/// Stripe customers set in the `stripe_customer_id` params of user and organization api keys.
+async fn get_api_key_stripe_customer_ids_query(
+    api_key_ids: Vec<uuid::Uuid>,
+    pool: &web::Data<Pool>,
+) -> Result<HashMap<uuid::Uuid, String>, ServiceError> {
+    use crate::data::schema::organization_api_key::dsl as organization_api_key_columns;
+    use crate::data::schema::user_api_key::dsl as user_api_key_columns;
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    let mut api_key_params = user_api_key_columns::user_api_key
+        .filter(user_api_key_columns::id.eq_any(api_key_ids.clone()))
+        .select((user_api_key_columns::id, user_api_key_columns::params))
+        .load::<(uuid::Uuid, Option<serde_json::Value>)>(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Failed to get api keys: {:?}", err);
+            ServiceError::BadRequest("Failed to get api keys".to_string())
+        })?;
+
+    api_key_params.extend(
+        organization_api_key_columns::organization_api_key
+            .filter(organization_api_key_columns::id.eq_any(api_key_ids.clone()))
+            .select((
+                organization_api_key_columns::id,
+                organization_api_key_columns::params,
+            ))
+            .load::<(uuid::Uuid, Option<serde_json::Value>)>(&mut conn)
+            .await
+            .map_err(|err| {
+                log::error!("Failed to get organization api keys: {:?}", err);
+                ServiceError::BadRequest("Failed to get organization api keys".to_string())
+            })?,
+    );
+
+    Ok(api_key_params
+        .into_iter()
+        .filter_map(|(api_key_id, params)| {
+            params?
+                .get("stripe_customer_id")?
+                .as_str()
+                .filter(|customer_id| !customer_id.is_empty())
+                .map(|customer_id| (api_key_id, customer_id.to_string()))
+        })
+        .collect())
+}
+
+/// Pushes the usage of finished days made with api keys that have a `stripe_customer_id` to Stripe as meter events named `{event_prefix}{metric}`, e.g. `trieve_search_calls`. Returns the number of usage records reported.
+pub async fn export_usage_to_stripe(
+    event_prefix: &str,
+    pool: &web::Data<Pool>,
+) -> Result<usize, ServiceError> {
+    use crate::data::schema::dataset_usage::dsl as dataset_usage_columns;
+
+    let today = chrono::Utc::now().date_naive();
+
+    let mut conn = pool.get().await.map_err(|_e| {
+        ServiceError::InternalServerError("Failed to get postgres connection".to_string())
+    })?;
+
+    // Today's usage is still being written to, so only finished days are reported
+    let unreported_usage = dataset_usage_columns::dataset_usage
+        .filter(dataset_usage_columns::stripe_reported_at.is_null())
+        .filter(dataset_usage_columns::usage_date.lt(today))
+        .filter(
+            dataset_usage_columns::usage_date
+                .ge(today - chrono::Duration::days(STRIPE_EXPORT_LOOKBACK_DAYS)),
+        )
+        .filter(dataset_usage_columns::api_key_id.ne(uuid::Uuid::nil()))
+        .select(DatasetUsage::as_select())
+        .load::<DatasetUsage>(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Failed to get unreported usage: {:?}", err);
+            ServiceError::BadRequest("Failed to get unreported usage".to_string())
+        })?;
+
+    let stripe_customer_ids = get_api_key_stripe_customer_ids_query(
+        unreported_usage
+            .iter()
+            .map(|record| record.api_key_id)
+            .collect(),
+        pool,
+    )
+    .await?;
+
+    let mut reported = 0;
+    for record in unreported_usage {
+        let Some(stripe_customer_id) = stripe_customer_ids.get(&record.api_key_id) else {
+            continue;
+        };
+
+        let timestamp = record.usage_date.and_hms_opt(0, 0, 0).unwrap_or_default();
+        let mut failed = false;
+        for (metric, value) in record.counts().metrics() {
+            if value == 0 {
+                continue;
+            }
+
+            if let Err(err) = create_stripe_meter_event(
+                &format!("{}{}", event_prefix, metric),
+                stripe_customer_id,
+                value,
+                &format!("{}-{}", record.id, metric),
+                timestamp,
+            )
+            .await
+            {
+                log::error!(
+                    "Failed to report {} of usage {} to Stripe: {:?}",
+                    metric,
+                    record.id,
+                    err
+                );
+                failed = true;
+            }
+        }
+
+        // Failed records are retried on the next run, which is safe since Stripe dedupes by identifier
+        if failed {
+            continue;
+        }
+
+        diesel::update(
+            dataset_usage_columns::dataset_usage.filter(dataset_usage_columns::id.eq(record.id)),
+        )
+        .set(dataset_usage_columns::stripe_reported_at.eq(chrono::Utc::now().naive_utc()))
+        .execute(&mut conn)
+        .await
+        .map_err(|err| {
+            log::error!("Failed to mark usage as reported: {:?}", err);
+            ServiceError::BadRequest("Failed to mark usage as reported".to_string())
+        })?;
+        reported += 1;
+    }
+
+    Ok(reported)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_add_to_usage_buffer() {
+        let dataset_id = uuid::Uuid::new_v4();
+        let api_key_id = Some(uuid::Uuid::new_v4());
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let yesterday = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let counts = UsageCounts {
+            search_calls: 1,
+            embedding_tokens: 10,
+            ..Default::default()
+        };
+
+        let mut buffer = UsageBuffer::new();
+        add_to_usage_buffer(&mut buffer, dataset_id, api_key_id, today, &counts);
+        add_to_usage_buffer(&mut buffer, dataset_id, api_key_id, today, &counts);
+        add_to_usage_buffer(&mut buffer, dataset_id, None, today, &counts);
+        add_to_usage_buffer(
+            &mut buffer,
+            dataset_id,
+            Some(uuid::Uuid::nil()),
+            today,
+            &counts,
+        );
+        add_to_usage_buffer(&mut buffer, dataset_id, api_key_id, yesterday, &counts);
+
+        assert_eq!(buffer.len(), 3);
+        let api_key_id = api_key_id.unwrap();
+        assert_eq!(
+            buffer[&(dataset_id, api_key_id, today)],
+            UsageCounts {
+                search_calls: 2,
+                embedding_tokens: 20,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            buffer[&(dataset_id, uuid::Uuid::nil(), today)],
+            UsageCounts {
+                search_calls: 2,
+                embedding_tokens: 20,
+                ..Default::default()
+            }
+        );
+        assert_eq!(buffer[&(dataset_id, api_key_id, yesterday)], counts);
+    }
+}
@@ -0,0 +1,277 @@
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use crate::{
+    data::models::RedisPool,
+    errors::ServiceError,
+    operators::{
+        analytics_export_operator::ANALYTICS_FORWARD_QUEUE,
+        clickhouse_operator::WEBHOOK_EVENT_QUEUE,
+    },
+};
+
+/// Hash of job counters and duration buckets written by every worker and cronjob, read by the `/metrics` route.
+pub const WORKER_METRICS_KEY: &str = "worker_metrics";
+
+/// Upper bounds in seconds of the worker job duration histogram.
+pub const WORKER_DURATION_BUCKETS: [f64; 12] = [
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0,
+];
+
+/// Lists workers pull messages from, reported as the queue depth metric.
+const WORKER_LIST_QUEUES: [&str; 13] = [
+    "ingestion",
+    "file_ingestion",
+    "update_chunk_queue",
+    "etl_queue",
+    "crawl_queue",
+    "csv_jsonl_ingestion",
+    "delete_dataset_queue",
+    "group_update_queue",
+    "create_dictionary",
+    "pagefind-index-ingestion",
+    "collection_migration",
+    ANALYTICS_FORWARD_QUEUE,
+    WEBHOOK_EVENT_QUEUE,
+];
+
+/// Sets workers pull messages from, reported as the queue depth metric.
+const WORKER_SET_QUEUES: [&str; 1] = ["bktree_creation"];
+
+/// Dataset label used once a worker has reported `WORKER_METRICS_MAX_DATASETS` distinct datasets, or for jobs without a dataset.
+const OTHER_DATASET_LABEL: &str = "other";
+const NO_DATASET_LABEL: &str = "none";
+
+/// Counts a job under its dataset label, checking and adding the dataset to the worker's labeled datasets in one step so concurrent jobs cannot push the worker past `WORKER_METRICS_MAX_DATASETS`.
+///
+/// KEYS are the worker's labeled datasets set and `WORKER_METRICS_KEY`. ARGV is the dataset id or an empty string, the maximum number of labeled datasets, the worker, the outcome and the labels used for jobs without a dataset and for datasets over the maximum.
+const JOB_COUNT_SCRIPT: &str = r#"
+local label = ARGV[1]
+if label == '' then
+    label = ARGV[5]
+elseif redis.call('SISMEMBER', KEYS[1], label) == 0 then
+    if redis.call('SCARD', KEYS[1]) < tonumber(ARGV[2]) then
+        redis.call('SADD', KEYS[1], label)
+    else
+        label = ARGV[6]
+    end
+end
+redis.call('HINCRBY', KEYS[2], 'jobs:' .. ARGV[3] .. ':' .. label .. ':' .. ARGV[4], 1)
+return label
+"#;
+
+fn worker_metric_datasets_key(worker: &str) -> String {
+    format!("worker_metric_datasets:{}", worker)
+}
+
+fn max_dataset_labels() -> usize {
+    std::env::var("WORKER_METRICS_MAX_DATASETS")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(100)
+}
+
+/// Counts and duration buckets of one worker's jobs.
+#[derive(Debug, Clone, Default)]
+pub struct WorkerDurations {
+    /// Cumulative count of jobs per bound of `WORKER_DURATION_BUCKETS`.
+    pub buckets: Vec<u64>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WorkerMetrics {
+    /// Jobs per worker, dataset label and outcome.
+    pub jobs: Vec<(String, String, String, u64)>,
+    pub durations: HashMap<String, WorkerDurations>,
+}
+
+/// Runs a worker job and records its duration and outcome. Recording failures are only logged so they never fail the job.
+pub async fn observe_worker_job<T, E, F>(
+    worker: &str,
+    dataset_id: Option<uuid::Uuid>,
+    redis_pool: &RedisPool,
+    job: F,
+) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let started_at = std::time::Instant::now();
+    let result = job.await;
+
+    record_worker_job(
+        worker,
+        dataset_id,
+        started_at.elapsed(),
+        result.is_ok(),
+        redis_pool,
+    )
+    .await;
+
+    result
+}
+
+/// Records the duration and outcome of a job for workers which cannot wrap it in `observe_worker_job`, such as ones that bail out of their loop midway through a job.
+pub async fn record_worker_job(
+    worker: &str,
+    dataset_id: Option<uuid::Uuid>,
+    duration: Duration,
+    succeeded: bool,
+    redis_pool: &RedisPool,
+) {
+    if let Err(err) =
+        record_worker_job_query(worker, dataset_id, duration, succeeded, redis_pool).await
+    {
+        log::error!("Failed to record {} job metrics: {:?}", worker, err);
+    }
+}
+
+pub async fn record_worker_job_query(
+    worker: &str,
+    dataset_id: Option<uuid::Uuid>,
+    duration: Duration,
+    succeeded: bool,
+    redis_pool: &RedisPool,
+) -> Result<(), ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    let outcome = if succeeded { "success" } else { "error" };
+    let seconds = duration.as_secs_f64();
+
+    let mut pipe = redis::pipe();
+    pipe.atomic()
+        .cmd("EVAL")
+        .arg(JOB_COUNT_SCRIPT)
+        .arg(2)
+        .arg(worker_metric_datasets_key(worker))
+        .arg(WORKER_METRICS_KEY)
+        .arg(
+            dataset_id
+                .map(|dataset_id| dataset_id.to_string())
+                .unwrap_or_default(),
+        )
+        .arg(max_dataset_labels())
+        .arg(worker)
+        .arg(outcome)
+        .arg(NO_DATASET_LABEL)
+        .arg(OTHER_DATASET_LABEL)
+        .ignore()
+        .cmd("HINCRBY")
+        .arg(WORKER_METRICS_KEY)
+        .arg(format!("duration_count:{}", worker))
+        .arg(1)
+        .ignore()
+        .cmd("HINCRBYFLOAT")
+        .arg(WORKER_METRICS_KEY)
+        .arg(format!("duration_sum:{}", worker))
+        .arg(seconds)
+        .ignore();
+
+    for bound in WORKER_DURATION_BUCKETS
+        .iter()
+        .filter(|bound| seconds <= **bound)
+    {
+        pipe.cmd("HINCRBY")
+            .arg(WORKER_METRICS_KEY)
+            .arg(format!("duration_bucket:{}:{}", worker, bound))
+            .arg(1)
+            .ignore();
+    }
+
+    pipe.query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    Ok(())
+}
+
+pub async fn get_worker_metrics_query(
+    redis_pool: &RedisPool,
+) -> Result<WorkerMetrics, ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    let fields: HashMap<String, String> = redis::cmd("HGETALL")
+        .arg(WORKER_METRICS_KEY)
+        .query_async(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    let mut metrics = WorkerMetrics::default();
+
+    for (field, value) in fields {
+        let parts = field.split(':').collect::<Vec<&str>>();
+        match parts.as_slice() {
+            ["jobs", worker, dataset, outcome] => metrics.jobs.push((
+                worker.to_string(),
+                dataset.to_string(),
+                outcome.to_string(),
+                value.parse().unwrap_or_default(),
+            )),
+            ["duration_count", worker] => {
+                metrics
+                    .durations
+                    .entry(worker.to_string())
+                    .or_default()
+                    .count = value.parse().unwrap_or_default();
+            }
+            ["duration_sum", worker] => {
+                metrics.durations.entry(worker.to_string()).or_default().sum =
+                    value.parse().unwrap_or_default();
+            }
+            ["duration_bucket", worker, bound] => {
+                let Some(index) = WORKER_DURATION_BUCKETS
+                    .iter()
+                    .position(|known_bound| known_bound.to_string() == *bound)
+                else {
+                    continue;
+                };
+
+                let durations = metrics.durations.entry(worker.to_string()).or_default();
+                durations.buckets.resize(WORKER_DURATION_BUCKETS.len(), 0);
+                durations.buckets[index] = value.parse().unwrap_or_default();
+            }
+            _ => {}
+        }
+    }
+
+    for durations in metrics.durations.values_mut() {
+        durations.buckets.resize(WORKER_DURATION_BUCKETS.len(), 0);
+    }
+
+    Ok(metrics)
+}
+
+pub async fn get_worker_queue_depths_query(
+    redis_pool: &RedisPool,
+) -> Result<Vec<(String, i64)>, ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    let mut pipe = redis::pipe();
+    for queue in WORKER_LIST_QUEUES {
+        pipe.cmd("LLEN").arg(queue);
+    }
+    for queue in WORKER_SET_QUEUES {
+        pipe.cmd("SCARD").arg(queue);
+    }
+
+    let depths: Vec<i64> = pipe
+        .query_async(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    Ok(WORKER_LIST_QUEUES
+        .iter()
+        .chain(WORKER_SET_QUEUES.iter())
+        .map(|queue| queue.to_string())
+        .zip(depths)
+        .collect())
+}